@@ -0,0 +1,11 @@
+#[derive(thiserror::Error, Debug)]
+pub enum AchievementError {
+    #[error("Got an io error while reading a ruleset:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("Couldn't parse the ruleset:\n{_0}")]
+    ParseError(#[from] toml::de::Error),
+    #[error("There is more than one achievement with the id {_0:?}!")]
+    DuplicateIdError(String),
+    #[error("The achievement {_0:?} has no conditions so it would unlock right away!")]
+    NoConditionsError(String),
+}
@@ -0,0 +1,249 @@
+//! # Achievements
+//!
+//! A small condition engine in the spirit of RetroAchievements. Every
+//! frame the conditions of every achievement are checked against memory
+//! and once they all hold the achievement unlocks. Needs the
+//! `achievements` feature.
+//!
+//! Rulesets are toml files:
+//!
+//! ```toml
+//! [[achievement]]
+//! id = "coins"
+//! title = "Rich"
+//! description = "Collect 50 coins without dying"
+//!
+//! # all the conditions have to hold on the same frame
+//! [[achievement.conditions]]
+//! address = 0x075E
+//! compare = ">="
+//! value = 50
+//!
+//! # has to have held on 60 frames (not necessarily in a row)
+//! [[achievement.conditions]]
+//! address = 0x000E
+//! compare = "=="
+//! value = 8
+//! hits = 60
+//!
+//! # any reset condition holding clears the hit counts
+//! [[achievement.resets]]
+//! address = 0x075A
+//! compare = "<"
+//! value = 2
+//! ```
+//!
+//! `size` can be `"u8"` (the default) or `"u16"` for little endian words.
+
+pub mod error;
+
+use std::{collections::HashSet, path::Path};
+
+use serde::Deserialize;
+
+use crate::{achievements::error::AchievementError, devices::nes::Nes, trace::targets};
+
+pub type Result<T> = std::result::Result<T, AchievementError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Comparison {
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub fn compare(self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Size {
+    #[default]
+    U8,
+    U16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Condition {
+    pub address: u16,
+    pub compare: Comparison,
+    pub value: u16,
+    #[serde(default)]
+    pub size: Size,
+    /// How many frames the condition has to have held, 0 means it only
+    /// has to hold right now
+    #[serde(default)]
+    pub hits: u32,
+}
+
+impl Condition {
+    pub fn holds(&self, peek: &impl Fn(u16) -> u8) -> bool {
+        let value = match self.size {
+            Size::U8 => peek(self.address) as u16,
+            Size::U16 => {
+                u16::from_le_bytes([peek(self.address), peek(self.address.wrapping_add(1))])
+            }
+        };
+        self.compare.compare(value, self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Achievement {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub resets: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub struct Ruleset {
+    #[serde(rename = "achievement", default)]
+    pub achievements: Vec<Achievement>,
+}
+
+impl Ruleset {
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let ruleset: Ruleset = toml::from_str(toml)?;
+        let mut ids = HashSet::new();
+        for achievement in ruleset.achievements.iter() {
+            if !ids.insert(achievement.id.as_str()) {
+                return Err(AchievementError::DuplicateIdError(achievement.id.clone()));
+            }
+            if achievement.conditions.is_empty() {
+                return Err(AchievementError::NoConditionsError(achievement.id.clone()));
+            }
+        }
+        Ok(ruleset)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+}
+
+struct Progress {
+    achievement: Achievement,
+    hits: Vec<u32>,
+    unlocked: bool,
+}
+
+pub type UnlockCallback = Box<dyn FnMut(&Achievement)>;
+
+/// Runs a [Ruleset], call [AchievementEngine::do_frame] once per frame.
+/// The unlock callback is the place to push an
+/// [osd notification](crate::osd::notifications::Notifications).
+pub struct AchievementEngine {
+    progress: Vec<Progress>,
+    unlock_callback: Option<UnlockCallback>,
+}
+
+impl AchievementEngine {
+    pub fn new(ruleset: Ruleset) -> Self {
+        let progress = ruleset
+            .achievements
+            .into_iter()
+            .map(|achievement| Progress {
+                hits: vec![0; achievement.conditions.len()],
+                achievement,
+                unlocked: false,
+            })
+            .collect();
+        Self {
+            progress,
+            unlock_callback: None,
+        }
+    }
+
+    pub fn set_unlock_callback(&mut self, callback: impl FnMut(&Achievement) + 'static) {
+        self.unlock_callback = Some(Box::new(callback));
+    }
+
+    /// Checks the conditions against the memory of `nes`, reads don't
+    /// have side effects, see [crate::CpuBus::peek]
+    pub fn do_frame(&mut self, nes: &Nes) -> Vec<String> {
+        self.do_frame_with(|address| nes.bus.peek(address))
+    }
+
+    /// Same as [AchievementEngine::do_frame] with memory read through
+    /// `peek`. Returns the ids of the achievements that unlocked.
+    pub fn do_frame_with(&mut self, peek: impl Fn(u16) -> u8) -> Vec<String> {
+        let mut unlocked = Vec::new();
+        for progress in self
+            .progress
+            .iter_mut()
+            .filter(|progress| !progress.unlocked)
+        {
+            let achievement = &progress.achievement;
+            if achievement.resets.iter().any(|reset| reset.holds(&peek)) {
+                progress.hits.fill(0);
+                continue;
+            }
+
+            let mut all_hold = true;
+            for (condition, hits) in achievement.conditions.iter().zip(progress.hits.iter_mut()) {
+                let holds = condition.holds(&peek);
+                if holds && *hits < condition.hits {
+                    *hits += 1;
+                }
+                all_hold &= if condition.hits == 0 {
+                    holds
+                } else {
+                    *hits >= condition.hits
+                };
+            }
+
+            if all_hold {
+                progress.unlocked = true;
+                tracing::info!(target: targets::EMULATOR, id = achievement.id, "achievement unlocked");
+                if let Some(callback) = self.unlock_callback.as_mut() {
+                    callback(achievement);
+                }
+                unlocked.push(achievement.id.clone());
+            }
+        }
+        unlocked
+    }
+
+    pub fn is_unlocked(&self, id: &str) -> bool {
+        self.progress
+            .iter()
+            .any(|progress| progress.unlocked && progress.achievement.id == id)
+    }
+
+    pub fn achievements(&self) -> impl Iterator<Item = &Achievement> {
+        self.progress.iter().map(|progress| &progress.achievement)
+    }
+
+    /// Locks everything again and clears the hit counts, for when the
+    /// game gets reset or another game is loaded
+    pub fn reset(&mut self) {
+        for progress in self.progress.iter_mut() {
+            progress.hits.fill(0);
+            progress.unlocked = false;
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! A shadow call stack built from the JSR, BRK, interrupts, RTS and RTI
+//! the cpu runs, since the real stack is just bytes. Returns that don't
+//! match what was called are reported, they catch stack bugs in homebrew
+//! early (but also show up for the "RTS trick" jump tables some games use).
+
+use std::fmt::Display;
+
+use crate::{devices::nes::Nes, hardware::constants::cpu::STACK_OFFSET, trace::targets};
+
+const BRK: u8 = 0x00;
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+const TXS: u8 = 0x9A;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Subroutine,
+    Nmi,
+    Irq,
+    Brk,
+}
+
+impl FrameKind {
+    fn is_interrupt(&self) -> bool {
+        *self != FrameKind::Subroutine
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: FrameKind,
+    /// The JSR or BRK, for nmis and irqs the instruction they interrupted
+    pub call_site: u16,
+    /// Where the subroutine or handler starts
+    pub target: u16,
+    /// Where RTS or RTI should go back to
+    pub return_address: u16,
+    /// The stack pointer before anything got pushed
+    pub stack_pointer: u8,
+}
+
+impl Display for CallFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            FrameKind::Subroutine => "JSR",
+            FrameKind::Nmi => "NMI",
+            FrameKind::Irq => "IRQ",
+            FrameKind::Brk => "BRK",
+        };
+        write!(
+            f,
+            "${:04X} from {kind} at ${:04X}",
+            self.target, self.call_site
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallStackWarning {
+    /// An RTS or RTI at `address` went to `return_address`, which nothing
+    /// on the call stack returns to
+    UnmatchedReturn { address: u16, return_address: u16 },
+    /// An RTS returning from an interrupt or an RTI from a subroutine
+    WrongReturn { address: u16, frame: CallFrame },
+    /// The instruction at `address` skipped frames, either by returning
+    /// past them or by moving the stack pointer with TXS
+    DroppedFrames { address: u16, dropped: usize },
+}
+
+impl Display for CallStackWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallStackWarning::UnmatchedReturn {
+                address,
+                return_address,
+            } => write!(
+                f,
+                "return at ${address:04X} to ${return_address:04X} doesn't match any call"
+            ),
+            CallStackWarning::WrongReturn { address, frame } => {
+                let instruction = if frame.kind.is_interrupt() {
+                    "RTS"
+                } else {
+                    "RTI"
+                };
+                write!(f, "{instruction} at ${address:04X} returns from {frame}")
+            }
+            CallStackWarning::DroppedFrames { address, dropped } => write!(
+                f,
+                "the instruction at ${address:04X} dropped {dropped} frames of the call stack"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    /// the outermost call first
+    frames: Vec<CallFrame>,
+    warnings: Vec<CallStackWarning>,
+    /// the cpu cycle of the last instruction looked at, so running again
+    /// from a break doesn't count it twice
+    last_cycle: Option<u64>,
+    interrupts_seen: Option<u64>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The outermost call first
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<CallStackWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Should be done after resetting the nes or loading a state
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Looks at the instruction the cpu is about to run, does nothing if
+    /// it's in the middle of one
+    pub fn on_instruction(&mut self, nes: &Nes) {
+        if !nes.is_at_instruction_start() || self.last_cycle == Some(nes.total_cpu_cycles()) {
+            return;
+        }
+        self.last_cycle = Some(nes.total_cpu_cycles());
+
+        let registers = nes.cpu.lock().unwrap().get_registers();
+        if let Some(interrupt) = super::new_interrupt(nes, &mut self.interrupts_seen) {
+            self.frames.push(CallFrame {
+                kind: if interrupt.is_nmi {
+                    FrameKind::Nmi
+                } else {
+                    FrameKind::Irq
+                },
+                call_site: interrupt.return_address,
+                target: interrupt.handler,
+                return_address: interrupt.return_address,
+                stack_pointer: interrupt.stack_pointer,
+            });
+            let stack_pointer = interrupt.stack_pointer.wrapping_sub(3);
+            self.on_opcode(nes, interrupt.handler, stack_pointer, registers.x);
+            if !interrupt.ran_first_instruction {
+                return;
+            }
+        }
+        self.on_opcode(
+            nes,
+            registers.program_counter,
+            registers.stack_pointer,
+            registers.x,
+        );
+    }
+
+    fn on_opcode(&mut self, nes: &Nes, address: u16, stack_pointer: u8, x: u8) {
+        let stack = |offset: u8| {
+            nes.bus
+                .peek(STACK_OFFSET | stack_pointer.wrapping_add(offset) as u16)
+        };
+        match nes.bus.peek(address) {
+            JSR => self.frames.push(CallFrame {
+                kind: FrameKind::Subroutine,
+                call_site: address,
+                target: nes.bus.peek_u16(address.wrapping_add(1)),
+                return_address: address.wrapping_add(3),
+                stack_pointer,
+            }),
+            BRK => self.frames.push(CallFrame {
+                kind: FrameKind::Brk,
+                call_site: address,
+                target: nes.bus.peek_u16(0xFFFE),
+                return_address: address.wrapping_add(2),
+                stack_pointer,
+            }),
+            // RTS adds one to the address it pulls
+            RTS => {
+                let return_address = u16::from_le_bytes([stack(1), stack(2)]).wrapping_add(1);
+                self.on_return(address, return_address, false);
+            }
+            // the status is pulled first
+            RTI => {
+                let return_address = u16::from_le_bytes([stack(2), stack(3)]);
+                self.on_return(address, return_address, true);
+            }
+            TXS => {
+                let live = self
+                    .frames
+                    .iter()
+                    .position(|frame| x >= frame.stack_pointer)
+                    .unwrap_or(self.frames.len());
+                self.drop_frames(address, live);
+            }
+            _ => (),
+        }
+    }
+
+    fn on_return(&mut self, address: u16, return_address: u16, is_rti: bool) {
+        let Some(index) = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == return_address)
+        else {
+            self.warn(CallStackWarning::UnmatchedReturn {
+                address,
+                return_address,
+            });
+            return;
+        };
+        let frame = self.frames[index];
+        self.drop_frames(address, index + 1);
+        self.frames.pop();
+        if frame.kind.is_interrupt() != is_rti {
+            self.warn(CallStackWarning::WrongReturn { address, frame });
+        }
+    }
+
+    /// Keeps the first `len` frames
+    fn drop_frames(&mut self, address: u16, len: usize) {
+        if len < self.frames.len() {
+            let dropped = self.frames.len() - len;
+            self.frames.truncate(len);
+            self.warn(CallStackWarning::DroppedFrames { address, dropped });
+        }
+    }
+
+    fn warn(&mut self, warning: CallStackWarning) {
+        tracing::warn!(target: targets::CPU, "{warning}");
+        self.warnings.push(warning);
+    }
+}
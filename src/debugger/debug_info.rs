@@ -0,0 +1,202 @@
+//! Reads the debug files ld65 writes with `--dbgfile`, so the debugger
+//! can show which source line the cpu is on and put breakpoints on lines.
+//! https://cc65.github.io/doc/ld65.html#s5
+//!
+//! Every line of the file is a record type and a tab followed by
+//! `key=value` pairs:
+//! ```text
+//! file id=0,name="main.s",size=1200,mtime=0x65F0A1B2,mod=0
+//! seg id=0,name="CODE",start=0x008000,size=0x0120,addrsize=absolute,type=ro
+//! span id=3,seg=0,start=16,size=3
+//! line id=7,file=0,line=42,span=3
+//! ```
+//! Only files, segments, spans and lines are needed, the rest is skipped.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::debugger::error::DebugInfoError;
+
+pub type Result<T> = std::result::Result<T, DebugInfoError>;
+
+/// `type` of a line record that comes from a macro expansion
+const MACRO_LINE: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// The file name as the assembler got it
+    pub file: String,
+    pub line: u32,
+}
+
+/// The code of one source line
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineSpan {
+    start: u16,
+    size: u16,
+    source: SourceLine,
+    is_macro: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    /// sorted by start address
+    spans: Vec<LineSpan>,
+}
+
+/// The `key=value` pairs of one record
+struct Record<'a> {
+    line_number: usize,
+    values: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Record<'a> {
+    fn parse(line_number: usize, pairs: &'a str) -> Self {
+        let mut values = HashMap::new();
+        let mut rest = pairs;
+        while let Some((key, after)) = rest.split_once('=') {
+            // names are quoted and can have commas in them
+            let (value, after) = if let Some(quoted) = after.strip_prefix('"') {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let after = quoted[end..].trim_start_matches('"');
+                (&quoted[..end], after)
+            } else {
+                after
+                    .split_once(',')
+                    .map_or((after, ""), |(value, after)| (value, after))
+            };
+            values.insert(key.trim(), value);
+            rest = after.trim_start_matches(',');
+        }
+        Self {
+            line_number,
+            values,
+        }
+    }
+
+    fn get(&self, key: &'static str) -> Result<&'a str> {
+        self.values
+            .get(key)
+            .copied()
+            .ok_or(DebugInfoError::MissingKeyError(self.line_number, key))
+    }
+
+    fn number(&self, key: &'static str) -> Result<u32> {
+        let value = self.get(key)?;
+        let parsed = match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => value.parse(),
+        };
+        parsed.map_err(|_| DebugInfoError::InvalidValueError(self.line_number, key))
+    }
+
+    /// A list like `span=3+4+5`
+    fn numbers(&self, key: &'static str) -> Result<Vec<u32>> {
+        self.get(key)?
+            .split('+')
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| DebugInfoError::InvalidValueError(self.line_number, key))
+            })
+            .collect()
+    }
+}
+
+impl DebugInfo {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut files = HashMap::new();
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut lines = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let Some((kind, pairs)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let record = Record::parse(index + 1, pairs.trim());
+            match kind {
+                "file" => {
+                    files.insert(record.number("id")?, record.get("name")?.to_string());
+                }
+                "seg" => {
+                    segments.insert(record.number("id")?, record.number("start")?);
+                }
+                "span" => {
+                    let span = (
+                        record.number("seg")?,
+                        record.number("start")?,
+                        record.number("size")?,
+                    );
+                    spans.insert(record.number("id")?, span);
+                }
+                // lines without code (comments, labels) have no span
+                "line" if record.values.contains_key("span") => lines.push(record),
+                _ => (),
+            }
+        }
+
+        let mut out = Vec::new();
+        for record in lines {
+            let file_id = record.number("file")?;
+            let file = files
+                .get(&file_id)
+                .ok_or(DebugInfoError::MissingIdError("line", "file", file_id))?;
+            let is_macro = record.number("type").ok() == Some(MACRO_LINE);
+            for span_id in record.numbers("span")? {
+                let (segment_id, start, size) = *spans
+                    .get(&span_id)
+                    .ok_or(DebugInfoError::MissingIdError("line", "span", span_id))?;
+                let segment = segments
+                    .get(&segment_id)
+                    .ok_or(DebugInfoError::MissingIdError("span", "seg", segment_id))?;
+                out.push(LineSpan {
+                    start: (segment + start) as u16,
+                    size: size as u16,
+                    source: SourceLine {
+                        file: file.clone(),
+                        line: record.number("line")?,
+                    },
+                    is_macro,
+                });
+            }
+        }
+        out.sort_by_key(|span| span.start);
+        Ok(Self { spans: out })
+    }
+
+    /// The source line the code at `address` came from. A line inside a
+    /// macro loses to the line that used the macro, and the smallest span
+    /// wins when a line covers other lines (like `.proc`).
+    ///
+    /// Banks mapped to the same address can't be told apart, the first
+    /// one in the file wins.
+    pub fn source_line(&self, address: u16) -> Option<&SourceLine> {
+        self.spans
+            .iter()
+            .take_while(|span| span.start <= address)
+            .filter(|span| address < span.start.saturating_add(span.size))
+            .min_by_key(|span| (span.is_macro, span.size))
+            .map(|span| &span.source)
+    }
+
+    /// The first address of every piece of code on `line` of `file`.
+    /// `file` matches the end of the file name so `main.s` also finds
+    /// `src/main.s`.
+    pub fn addresses(&self, file: &str, line: u32) -> Vec<u16> {
+        let mut addresses: Vec<u16> = self
+            .spans
+            .iter()
+            .filter(|span| {
+                span.source.line == line
+                    && !span.is_macro
+                    && Path::new(&span.source.file).ends_with(file)
+            })
+            .map(|span| span.start)
+            .collect();
+        addresses.dedup();
+        addresses
+    }
+}
@@ -0,0 +1,367 @@
+//! Disassembles a block of memory or a whole cartrige without a running
+//! nes, for dumping code to a file. [Debugger::disassemble](super::Debugger::disassemble) is the
+//! one to use for live views since it also shows the values the operands
+//! point to.
+
+use std::{collections::BTreeSet, fmt::Write, ops::Range};
+
+use serde::Serialize;
+
+use crate::hardware::{
+    cartrige::{Cartrige, PrgBank},
+    cpu::{AddressingModeKind, OpcodeInfo},
+};
+
+/// How many bytes go on one `.byte` line
+const BYTES_PER_DATA_LINE: usize = 8;
+/// The nmi, reset and irq vectors at the end of the bank mapped to $E000
+const VECTORS_SIZE: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisassemblyFormat {
+    /// Address, bytes and instruction like the nestest log:
+    /// `C000  4C F5 C5  JMP $C5F5`
+    #[default]
+    Listing,
+    /// Source that ca65 assembles back into the same bytes, with labels
+    /// on jump and branch targets. Illegal opcodes are written as
+    /// `.byte` since ca65 doesn't have names for all of them.
+    Ca65,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DisassemblyOptions {
+    pub format: DisassemblyFormat,
+    /// The address of the first byte
+    pub origin: u16,
+    /// Addresses that hold data instead of code, they get written as
+    /// `.byte`. When disassembling a cartrige they count for every bank
+    /// mapped there.
+    pub data_ranges: Vec<Range<u16>>,
+}
+
+/// One instruction, data byte or vector, made to be exported with serde (for
+/// example as json) for other tools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DisassemblyRecord {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// `None` for data, vectors are data with a `target`
+    pub mnemonic: Option<&'static str>,
+    pub mode: Option<AddressingModeKind>,
+    /// The operand bytes as one number
+    pub operand: Option<u16>,
+    /// Where a branch or jump goes, already worked out for relative
+    /// branches. Indirect jumps have the pointer address here.
+    pub target: Option<u16>,
+    pub is_illegal: bool,
+}
+
+impl DisassemblyRecord {
+    pub fn is_data(&self) -> bool {
+        self.mnemonic.is_none()
+    }
+
+    /// The instruction in the syntax the
+    /// [assembler](crate::hardware::cpu::assembler) reads, like
+    /// `LDA ($10),Y`. Data is written as `.byte` and vectors as `.word`.
+    pub fn text(&self) -> String {
+        let Some(mnemonic) = self.mnemonic else {
+            return match self.target {
+                Some(target) => format!(".word ${target:04X}"),
+                None => data_text(&self.bytes),
+            };
+        };
+        let operand = self.operand.unwrap_or_default();
+        let operand = match self.mode.unwrap_or(AddressingModeKind::Implicit) {
+            AddressingModeKind::Implicit => String::new(),
+            AddressingModeKind::Accumulator => "A".to_string(),
+            AddressingModeKind::Immediate => format!("#${operand:02X}"),
+            AddressingModeKind::ZeroPage => format!("${operand:02X}"),
+            AddressingModeKind::ZeroPageX => format!("${operand:02X},X"),
+            AddressingModeKind::ZeroPageY => format!("${operand:02X},Y"),
+            AddressingModeKind::Absolute => format!("${operand:04X}"),
+            AddressingModeKind::AbsoluteX => format!("${operand:04X},X"),
+            AddressingModeKind::AbsoluteY => format!("${operand:04X},Y"),
+            AddressingModeKind::Indirect => format!("(${operand:04X})"),
+            AddressingModeKind::IndirectX => format!("(${operand:02X},X)"),
+            AddressingModeKind::IndirectY => format!("(${operand:02X}),Y"),
+            AddressingModeKind::Relative => format!("${:04X}", self.target.unwrap_or_default()),
+            AddressingModeKind::ZeroPageIndirect => format!("(${operand:02X})"),
+            AddressingModeKind::AbsoluteIndirectX => format!("(${operand:04X},X)"),
+        };
+        format!("{mnemonic} {operand}").trim_end().to_string()
+    }
+}
+
+/// The records of one prg bank of a cartrige
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BankDisassembly {
+    pub bank: usize,
+    /// Where the bank is mapped, see [PrgBank::address]
+    pub address: u16,
+    pub records: Vec<DisassemblyRecord>,
+}
+
+impl BankDisassembly {
+    /// The last address of the bank
+    pub fn end_address(&self) -> u16 {
+        self.records
+            .last()
+            .map(|record| record.address + (record.bytes.len() as u16 - 1))
+            .unwrap_or(self.address)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Disassembler {
+    options: DisassemblyOptions,
+}
+
+impl Disassembler {
+    pub fn new(options: DisassemblyOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn get_options(&self) -> &DisassemblyOptions {
+        &self.options
+    }
+
+    /// Decodes `memory` from start to end. Data ranges and instructions
+    /// that would run past the end come out as single data bytes.
+    pub fn records(&self, memory: &[u8]) -> Vec<DisassemblyRecord> {
+        self.records_at(self.options.origin, memory)
+    }
+
+    /// Decodes every prg bank of `cartrige` at the address it is usually
+    /// mapped at, the origin of the options is ignored. The vectors at the
+    /// end of the bank mapped to $E000 become `.word`s and the code they
+    /// point to in that bank gets labels.
+    pub fn cartrige_records(&self, cartrige: &Cartrige) -> Vec<BankDisassembly> {
+        cartrige
+            .prg_banks()
+            .into_iter()
+            .map(|bank| BankDisassembly {
+                bank: bank.index,
+                address: bank.address,
+                records: self.bank_records(&bank),
+            })
+            .collect()
+    }
+
+    /// The whole prg rom of `cartrige` as text, every bank starts with a
+    /// comment saying which bank it is and where it is mapped. In the ca65
+    /// format every bank is its own `.scope` so labels don't clash between
+    /// banks mapped to the same address.
+    pub fn disassemble_cartrige(&self, cartrige: &Cartrige) -> String {
+        let mut out = String::new();
+        for bank in self.cartrige_records(cartrige) {
+            let _ = writeln!(
+                out,
+                "; bank {} ${:04X}-${:04X}",
+                bank.bank,
+                bank.address,
+                bank.end_address()
+            );
+            match self.options.format {
+                DisassemblyFormat::Listing => out += &listing(&bank.records),
+                DisassemblyFormat::Ca65 => {
+                    let _ = writeln!(out, ".scope Bank{}", bank.bank);
+                    out += &ca65(bank.address, &bank.records);
+                    out += ".endscope\n";
+                }
+            }
+        }
+        out
+    }
+
+    fn bank_records(&self, bank: &PrgBank) -> Vec<DisassemblyRecord> {
+        if !bank.has_vectors() || bank.data.len() < VECTORS_SIZE {
+            return self.records_at(bank.address, bank.data);
+        }
+        let code_size = bank.data.len() - VECTORS_SIZE;
+        let mut records = self.records_at(bank.address, &bank.data[..code_size]);
+        for (i, vector) in bank.data[code_size..].chunks(2).enumerate() {
+            records.push(DisassemblyRecord {
+                address: bank.address + (code_size + i * 2) as u16,
+                bytes: vector.to_vec(),
+                mnemonic: None,
+                mode: None,
+                operand: None,
+                target: Some(u16::from_le_bytes([vector[0], vector[1]])),
+                is_illegal: false,
+            });
+        }
+        records
+    }
+
+    fn records_at(&self, origin: u16, memory: &[u8]) -> Vec<DisassemblyRecord> {
+        let mut out = Vec::new();
+        let mut offset = 0;
+        while offset < memory.len() {
+            let address = origin.wrapping_add(offset as u16);
+            let info = OpcodeInfo::get(memory[offset]);
+            let size = 1 + info.mode.operand_size() as usize;
+            let is_data = (0..size as u16).any(|i| self.is_data(address.wrapping_add(i)));
+            let Some(bytes) = memory.get(offset..offset + size).filter(|_| !is_data) else {
+                out.push(DisassemblyRecord {
+                    address,
+                    bytes: vec![memory[offset]],
+                    mnemonic: None,
+                    mode: None,
+                    operand: None,
+                    target: None,
+                    is_illegal: false,
+                });
+                offset += 1;
+                continue;
+            };
+
+            let operand = match bytes.len() {
+                2 => Some(bytes[1] as u16),
+                3 => Some(u16::from_le_bytes([bytes[1], bytes[2]])),
+                _ => None,
+            };
+            let target = match info.mode {
+                AddressingModeKind::Relative => operand.map(|offset| {
+                    address
+                        .wrapping_add(2)
+                        .wrapping_add(offset as u8 as i8 as u16)
+                }),
+                AddressingModeKind::Absolute if matches!(info.mnemonic, "JMP" | "JSR") => operand,
+                AddressingModeKind::Indirect => operand,
+                _ => None,
+            };
+            out.push(DisassemblyRecord {
+                address,
+                bytes: bytes.to_vec(),
+                mnemonic: Some(info.mnemonic),
+                mode: Some(info.mode),
+                operand,
+                target,
+                is_illegal: info.is_illegal,
+            });
+            offset += size;
+        }
+        out
+    }
+
+    /// The whole of `memory` as text in the [DisassemblyFormat] of the
+    /// options
+    pub fn disassemble(&self, memory: &[u8]) -> String {
+        let records = self.records(memory);
+        match self.options.format {
+            DisassemblyFormat::Listing => listing(&records),
+            DisassemblyFormat::Ca65 => ca65(self.options.origin, &records),
+        }
+    }
+
+    fn is_data(&self, address: u16) -> bool {
+        self.options
+            .data_ranges
+            .iter()
+            .any(|range| range.contains(&address))
+    }
+}
+
+fn data_text(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("${byte:02X}")).collect();
+    format!(".byte {}", bytes.join(", "))
+}
+
+fn listing(records: &[DisassemblyRecord]) -> String {
+    let mut out = String::new();
+    for record in records {
+        let bytes: Vec<String> = record
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        let marker = if record.is_illegal { '*' } else { ' ' };
+        let _ = writeln!(
+            out,
+            "{:04X}  {:<8} {marker}{}",
+            record.address,
+            bytes.join(" "),
+            record.text()
+        );
+    }
+    out
+}
+
+fn ca65(origin: u16, records: &[DisassemblyRecord]) -> String {
+    // only instructions can have labels, a target in the middle of one
+    // stays a number
+    let starts: BTreeSet<u16> = records
+        .iter()
+        .filter(|record| !record.is_data())
+        .map(|record| record.address)
+        .collect();
+    let labels: BTreeSet<u16> = records
+        .iter()
+        .filter(|record| !record.is_illegal && record.mode != Some(AddressingModeKind::Indirect))
+        .filter_map(|record| record.target)
+        .filter(|target| starts.contains(target))
+        .collect();
+    let label = |address: u16| format!("L{address:04X}");
+
+    let mut out = format!(".org ${origin:04X}\n");
+    let mut data = Vec::new();
+    let flush = |out: &mut String, data: &mut Vec<u8>| {
+        for chunk in data.chunks(BYTES_PER_DATA_LINE) {
+            let _ = writeln!(out, "        {}", data_text(chunk));
+        }
+        data.clear();
+    };
+    for record in records {
+        if labels.contains(&record.address) {
+            flush(&mut out, &mut data);
+            let _ = writeln!(out, "{}:", label(record.address));
+        }
+        if record.is_data() && record.target.is_none() {
+            data.extend_from_slice(&record.bytes);
+            continue;
+        }
+        flush(&mut out, &mut data);
+        if let Some(target) = record.target.filter(|_| record.is_data()) {
+            let target = if labels.contains(&target) {
+                label(target)
+            } else {
+                format!("${target:04X}")
+            };
+            let _ = writeln!(out, "        .word {target}");
+            continue;
+        }
+        if record.is_illegal {
+            let _ = writeln!(
+                out,
+                "        {} ; {}",
+                data_text(&record.bytes),
+                record.text()
+            );
+            continue;
+        }
+
+        let mnemonic = record.mnemonic.unwrap_or_default().to_ascii_lowercase();
+        let mut operand = record.text()[3..].trim().to_string();
+        if let Some(target) = record.target.filter(|target| labels.contains(target))
+            && record.mode != Some(AddressingModeKind::Indirect)
+        {
+            operand = operand.replace(&format!("${target:04X}"), &label(target));
+        } else if let Some(
+            AddressingModeKind::Absolute
+            | AddressingModeKind::AbsoluteX
+            | AddressingModeKind::AbsoluteY,
+        ) = record.mode
+            && record.operand.is_some_and(|operand| operand < 0x100)
+        {
+            // ca65 would pick the zero page version
+            operand.insert_str(0, "a:");
+        }
+        let _ = writeln!(out, "        {mnemonic} {operand}");
+    }
+    flush(&mut out, &mut data);
+    out.lines()
+        .map(str::trim_end)
+        .fold(String::new(), |out, line| out + line + "\n")
+}
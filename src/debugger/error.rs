@@ -0,0 +1,17 @@
+#[derive(thiserror::Error, Debug)]
+pub enum DebugInfoError {
+    #[error("Got an io error while reading a debug file:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("Line {_0} of the debug file has no {_1:?}!")]
+    MissingKeyError(usize, &'static str),
+    #[error("Line {_0} of the debug file has an invalid {_1:?}!")]
+    InvalidValueError(usize, &'static str),
+    #[error("A {_0} in the debug file points to the {_1} with id {_2} which doesn't exist!")]
+    MissingIdError(&'static str, &'static str, u32),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RegisterLogError {
+    #[error("{_0:?} isn't a register address!")]
+    InvalidAddressError(String),
+}
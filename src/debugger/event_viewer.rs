@@ -0,0 +1,112 @@
+use crate::hardware::{
+    constants::ppu::{DOTS_PER_SCANLINE, SCANLINES_PER_FRAME},
+    cpu_bus::{BusObserver, BusWrite},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// $2000-$3FFF and oam dma at $4014
+    Ppu = 0,
+    /// $4000-$4017 except for $4014 and $4016
+    Apu = 1,
+    /// $4016, controller strobe
+    Input = 2,
+    /// $4020-$FFFF, mapper registers and cartrige ram
+    Mapper = 3,
+}
+
+impl EventKind {
+    /// `None` for writes that aren't to registers (cpu ram)
+    pub fn from_address(address: u16) -> Option<Self> {
+        match address {
+            0x2000..0x4000 | 0x4014 => Some(EventKind::Ppu),
+            0x4016 => Some(EventKind::Input),
+            0x4000..0x4018 => Some(EventKind::Apu),
+            0x4020.. => Some(EventKind::Mapper),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub write: BusWrite,
+}
+
+/// Records the register writes of every frame with the scanline and dot
+/// they happened on, like the event viewer of Mesen. Handy for debugging
+/// raster effects. Attach it with
+/// [Nes::add_bus_observer](crate::Nes::add_bus_observer).
+#[derive(Debug, Clone)]
+pub struct EventViewer {
+    current: Vec<Event>,
+    last_frame: Vec<Event>,
+    /// which kinds get recorded, indexed like [EventViewer::set_enabled]
+    enabled: [bool; 4],
+}
+
+impl EventViewer {
+    pub fn new() -> Self {
+        Self {
+            current: Vec::new(),
+            last_frame: Vec::new(),
+            enabled: [true; 4],
+        }
+    }
+
+    /// Every kind is recorded by default
+    pub fn set_enabled(&mut self, kind: EventKind, enabled: bool) {
+        self.enabled[kind as usize] = enabled;
+    }
+
+    pub fn is_enabled(&self, kind: EventKind) -> bool {
+        self.enabled[kind as usize]
+    }
+
+    /// The writes of the last finished frame, in order
+    pub fn get_events(&self) -> &[Event] {
+        &self.last_frame
+    }
+
+    /// The writes of the frame the ppu is drawing right now
+    pub fn get_current_events(&self) -> &[Event] {
+        &self.current
+    }
+
+    /// How many writes of the last frame happened on every dot, row by
+    /// row with [DOTS_PER_SCANLINE] dots and [SCANLINES_PER_FRAME] rows
+    pub fn heatmap(&self) -> Vec<u16> {
+        let mut heatmap = vec![0u16; DOTS_PER_SCANLINE * SCANLINES_PER_FRAME];
+        for event in self.last_frame.iter() {
+            let scanline = (event.write.scanline as usize).min(SCANLINES_PER_FRAME - 1);
+            let dot = (event.write.dot as usize).min(DOTS_PER_SCANLINE - 1);
+            let count = &mut heatmap[scanline * DOTS_PER_SCANLINE + dot];
+            *count = count.saturating_add(1);
+        }
+        heatmap
+    }
+}
+
+impl Default for EventViewer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusObserver for EventViewer {
+    fn on_write(&mut self, write: &BusWrite) {
+        if let Some(kind) = EventKind::from_address(write.address)
+            && self.is_enabled(kind)
+        {
+            self.current.push(Event {
+                kind,
+                write: *write,
+            });
+        }
+    }
+
+    fn on_frame_end(&mut self) {
+        self.last_frame = std::mem::take(&mut self.current);
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::{self, Write};
+
+use crate::{
+    frontend::screenshots,
+    hardware::{
+        cpu::{CpuConfig, OpcodeInfo},
+        cpu_bus::{BusObserver, BusRead, BusWrite},
+    },
+};
+
+/// Bytes every bucket of an [AccessHeatmap] counts by default
+pub const DEFAULT_BUCKET_SIZE: usize = 16;
+
+/// How often the cpu touched a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AccessCounts {
+    pub reads: u64,
+    pub writes: u64,
+    /// Opcode and operand bytes of the instructions run
+    pub executes: u64,
+}
+
+impl AccessCounts {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Counts the reads, writes and executes of every address the cpu
+/// touches, for telling code from data in a rom or finding the hot code
+/// of a game. Addresses are counted in buckets of a few bytes, which is
+/// plenty for both and keeps the table small. Attach it with
+/// [Nes::add_bus_observer](crate::Nes::add_bus_observer), it can be turned
+/// off while attached.
+#[derive(Debug, Clone)]
+pub struct AccessHeatmap {
+    bucket_shift: u32,
+    buckets: Vec<AccessCounts>,
+    is_enabled: bool,
+}
+
+impl AccessHeatmap {
+    /// `bucket_size` gets rounded up to a power of 2, up to 4kb
+    pub fn new(bucket_size: usize) -> Self {
+        let bucket_shift = bucket_size
+            .clamp(1, 0x1000)
+            .next_power_of_two()
+            .trailing_zeros();
+        Self {
+            bucket_shift,
+            buckets: vec![AccessCounts::default(); 0x10000 >> bucket_shift],
+            is_enabled: true,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.is_enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.fill(AccessCounts::default());
+    }
+
+    pub fn get_bucket_size(&self) -> usize {
+        1 << self.bucket_shift
+    }
+
+    /// The counts of the bucket `address` is in
+    pub fn get_counts(&self, address: u16) -> AccessCounts {
+        self.buckets[self.bucket(address)]
+    }
+
+    /// Every bucket that got touched with the first address in it
+    pub fn touched(&self) -> impl Iterator<Item = (u16, AccessCounts)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, counts)| !counts.is_empty())
+            .map(|(index, counts)| ((index << self.bucket_shift) as u16, *counts))
+    }
+
+    /// One line for every touched bucket, like `$8000,12,0,3410`
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "address,reads,writes,executes")?;
+        for (address, counts) in self.touched() {
+            writeln!(
+                writer,
+                "${address:04X},{},{},{}",
+                counts.reads, counts.writes, counts.executes
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Width and height of [AccessHeatmap::to_rgb], as square as it gets.
+    /// With 1 byte buckets every row is a page.
+    pub fn image_size(&self) -> (usize, usize) {
+        let buckets = self.buckets.len();
+        let width = 1 << buckets.trailing_zeros().div_ceil(2);
+        (width, buckets / width)
+    }
+
+    /// A pixel per bucket, writes in red, reads in green and executes in
+    /// blue. The counts are on a log scale so code that runs every frame
+    /// doesn't drown out everything else.
+    pub fn to_rgb(&self) -> Vec<u8> {
+        let max = self
+            .buckets
+            .iter()
+            .fold(AccessCounts::default(), |max, counts| AccessCounts {
+                reads: max.reads.max(counts.reads),
+                writes: max.writes.max(counts.writes),
+                executes: max.executes.max(counts.executes),
+            });
+        let scale = |count: u64, max: u64| {
+            if count == 0 {
+                return 0;
+            }
+            (255.0 * ((count + 1) as f64).ln() / ((max + 1) as f64).ln()) as u8
+        };
+        self.buckets
+            .iter()
+            .flat_map(|counts| {
+                [
+                    scale(counts.writes, max.writes),
+                    scale(counts.reads, max.reads),
+                    scale(counts.executes, max.executes),
+                ]
+            })
+            .collect()
+    }
+
+    /// Saves [AccessHeatmap::to_rgb] as a png
+    pub fn write_png(&self, writer: impl Write) -> io::Result<()> {
+        let (width, height) = self.image_size();
+        screenshots::write_rgb_png(width, height, &self.to_rgb(), writer)
+    }
+
+    fn bucket(&self, address: u16) -> usize {
+        address as usize >> self.bucket_shift
+    }
+}
+
+impl Default for AccessHeatmap {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKET_SIZE)
+    }
+}
+
+impl BusObserver for AccessHeatmap {
+    fn on_write(&mut self, write: &BusWrite) {
+        if self.is_enabled {
+            let bucket = self.bucket(write.address);
+            self.buckets[bucket].writes += 1;
+        }
+    }
+
+    fn on_read(&mut self, read: &BusRead) {
+        if self.is_enabled {
+            let bucket = self.bucket(read.address);
+            self.buckets[bucket].reads += 1;
+        }
+    }
+
+    fn on_execute(&mut self, address: u16, opcode: u8, config: &CpuConfig) {
+        if !self.is_enabled {
+            return;
+        }
+        let operand_size = OpcodeInfo::get_for(config.variant, opcode)
+            .mode
+            .operand_size();
+        for offset in 0..=operand_size {
+            let bucket = self.bucket(address.wrapping_add(offset));
+            self.buckets[bucket].executes += 1;
+        }
+    }
+}
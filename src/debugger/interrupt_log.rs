@@ -0,0 +1,172 @@
+//! Records every interrupt the cpu takes with where in the frame it
+//! happened and how long its handler ran, for finding nmi handlers that
+//! spill out of vblank or irqs that fire on the wrong scanline.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::{devices::nes::Nes, trace::targets};
+
+/// How many interrupts [InterruptLog] keeps, the oldest get dropped
+pub const INTERRUPT_LOG_SIZE: usize = 4096;
+
+const BRK: u8 = 0x00;
+const RTI: u8 = 0x40;
+/// RTI takes 6 cycles, the handler is over once it's done
+const RTI_CYCLES: u64 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum InterruptKind {
+    Nmi,
+    Irq,
+    Brk,
+}
+
+impl InterruptKind {
+    pub fn vector(self) -> u16 {
+        match self {
+            InterruptKind::Nmi => 0xFFFA,
+            InterruptKind::Irq | InterruptKind::Brk => 0xFFFE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Interrupt {
+    pub kind: InterruptKind,
+    pub frame: u64,
+    pub scanline: u32,
+    pub dot: u32,
+    /// The BRK or the instruction that got interrupted
+    pub address: u16,
+    /// Where the vector pointed, the start of the handler
+    pub handler: u16,
+    /// Cpu cycles from the start of the handler until its RTI finished,
+    /// `None` while the handler is still running
+    pub cycles: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InterruptLog {
+    interrupts: VecDeque<Interrupt>,
+    /// how many interrupts were dropped off the front, so the running
+    /// handlers can be found by their number
+    dropped: u64,
+    /// the numbers of the handlers that haven't returned yet, the
+    /// innermost last, with the cycle they started on
+    running: Vec<(u64, u64)>,
+    last_cycle: Option<u64>,
+    interrupts_seen: Option<u64>,
+}
+
+impl InterruptLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The oldest first
+    pub fn interrupts(&self) -> impl Iterator<Item = &Interrupt> {
+        self.interrupts.iter()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Writes every interrupt to the trace as its own event, with the
+    /// json format they end up as structured records
+    pub fn dump(&self) {
+        for interrupt in self.interrupts.iter() {
+            tracing::info!(
+                target: targets::CPU,
+                kind = ?interrupt.kind,
+                frame = interrupt.frame,
+                scanline = interrupt.scanline,
+                dot = interrupt.dot,
+                address = interrupt.address,
+                vector = interrupt.kind.vector(),
+                handler = interrupt.handler,
+                cycles = interrupt.cycles,
+                "interrupt"
+            );
+        }
+    }
+
+    /// Looks at the instruction the cpu is about to run, does nothing if
+    /// it's in the middle of one
+    pub fn on_instruction(&mut self, nes: &Nes) {
+        if !nes.is_at_instruction_start() || self.last_cycle == Some(nes.total_cpu_cycles()) {
+            return;
+        }
+        self.last_cycle = Some(nes.total_cpu_cycles());
+
+        let (address, cycle) = {
+            let cpu = nes.cpu.lock().unwrap();
+            (cpu.get_program_counter(), cpu.get_total_cycles())
+        };
+        if let Some(interrupt) = super::new_interrupt(nes, &mut self.interrupts_seen) {
+            self.start(
+                Interrupt {
+                    kind: if interrupt.is_nmi {
+                        InterruptKind::Nmi
+                    } else {
+                        InterruptKind::Irq
+                    },
+                    frame: nes.frame_count(),
+                    scanline: interrupt.scanline,
+                    dot: interrupt.dot,
+                    address: interrupt.return_address,
+                    handler: interrupt.handler,
+                    cycles: None,
+                },
+                interrupt.cycle,
+            );
+            // RTI can be all there is to a handler
+            self.on_opcode(nes, interrupt.handler, interrupt.cycle);
+            if !interrupt.ran_first_instruction {
+                return;
+            }
+        }
+        self.on_opcode(nes, address, cycle);
+    }
+
+    fn on_opcode(&mut self, nes: &Nes, address: u16, cycle: u64) {
+        match nes.bus.peek(address) {
+            BRK => {
+                let (scanline, dot) = nes.ppu_dot_position();
+                self.start(
+                    Interrupt {
+                        kind: InterruptKind::Brk,
+                        frame: nes.frame_count(),
+                        scanline,
+                        dot,
+                        address,
+                        handler: nes.bus.peek_u16(InterruptKind::Brk.vector()),
+                        cycles: None,
+                    },
+                    cycle,
+                );
+            }
+            RTI => {
+                if let Some((number, start)) = self.running.pop()
+                    && let Some(index) = number.checked_sub(self.dropped)
+                    && let Some(interrupt) = self.interrupts.get_mut(index as usize)
+                {
+                    interrupt.cycles = Some(cycle + RTI_CYCLES - start);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn start(&mut self, interrupt: Interrupt, cycle: u64) {
+        if self.interrupts.len() == INTERRUPT_LOG_SIZE {
+            self.interrupts.pop_front();
+            self.dropped += 1;
+        }
+        self.running
+            .push((self.dropped + self.interrupts.len() as u64, cycle));
+        self.interrupts.push_back(interrupt);
+    }
+}
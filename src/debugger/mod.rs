@@ -0,0 +1,467 @@
+//! # Debugger
+//!
+//! Everything a debugger frontend needs to show what the nes is doing:
+//! breakpoints, disassembly, memory, the pattern tables and the source
+//! lines from ld65 debug files (see [debug_info]). Nothing in
+//! here changes the state of the nes except for running it, so the panels
+//! can be drawn while the game keeps going.
+
+pub mod call_stack;
+pub mod debug_info;
+pub mod disassembler;
+pub mod error;
+pub mod event_viewer;
+pub mod heatmap;
+pub mod interrupt_log;
+pub mod register_log;
+pub mod watchpoints;
+
+use std::{
+    cell::RefCell,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    debugger::{
+        call_stack::{CallFrame, CallStack, CallStackWarning},
+        debug_info::{DebugInfo, SourceLine},
+        interrupt_log::{Interrupt, InterruptLog},
+        watchpoints::{WatchHit, Watchpoint, Watchpoints},
+    },
+    devices::{
+        nes::Nes,
+        run::{BreakReason, RunSummary},
+    },
+    hardware::{
+        constants::{
+            clock_rates::CPU_CLOCK,
+            cpu::{STACK_OFFSET, flags::INTERRUPT_DISABLE},
+            ppu::{PATTERN_TABLE_VIEW_HEIGHT, PATTERN_TABLE_VIEW_WIDTH},
+        },
+        cpu::{DisassembledInstruction, TakenInterrupt, assembler},
+        ppu::frame::Frame,
+    },
+};
+
+/// How many bytes are in a row of [Debugger::memory_rows]
+pub const MEMORY_ROW_SIZE: usize = 16;
+/// [Debugger::step_over] gives up on a subroutine after a second
+pub const STEP_OVER_MAX_CYCLES: u64 = CPU_CLOCK;
+
+const JSR: u8 = 0x20;
+
+/// The nmi or irq taken since the last instruction or about to be taken,
+/// for the trackers that look at every instruction. `seen` is how many
+/// interrupts the tracker already knows about. Check
+/// [TakenInterrupt::ran_first_instruction] to know if the handler already
+/// started.
+fn new_interrupt(nes: &Nes, seen: &mut Option<u64>) -> Option<TakenInterrupt> {
+    let cpu = nes.cpu.lock().unwrap();
+    let taken = cpu.get_interrupts_taken();
+    let last = match *seen {
+        Some(seen) if seen < taken => cpu.get_last_interrupt(),
+        _ => None,
+    };
+    *seen = Some(taken);
+    if last.is_some() {
+        return last;
+    }
+
+    let registers = cpu.get_registers();
+    let is_nmi = cpu.is_triggered_nmi;
+    let is_irq = cpu.is_triggered_irq && registers.status & INTERRUPT_DISABLE == 0;
+    if !is_nmi && !is_irq {
+        return None;
+    }
+    let cycle = cpu.get_total_cycles();
+    drop(cpu);
+    *seen = Some(taken + 1);
+    let (scanline, dot) = nes.ppu_dot_position();
+    Some(TakenInterrupt {
+        is_nmi,
+        return_address: registers.program_counter,
+        stack_pointer: registers.stack_pointer,
+        handler: nes.bus.peek_u16(if is_nmi { 0xFFFA } else { 0xFFFE }),
+        cycle,
+        scanline,
+        dot,
+        ran_first_instruction: false,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub is_enabled: bool,
+}
+
+/// The stack page at $0100-$01FF, see [Debugger::stack]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackView {
+    /// Points at the next free byte, the stack grows down
+    pub stack_pointer: u8,
+    pub bytes: [u8; 256],
+}
+
+impl StackView {
+    /// The bytes pushed and not pulled yet, the most recent first
+    pub fn used(&self) -> &[u8] {
+        &self.bytes[self.stack_pointer as usize + 1..]
+    }
+
+    /// The address the stack pointer points at
+    pub fn top_address(&self) -> u16 {
+        STACK_OFFSET | self.stack_pointer as u16
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// shared with the nes it's attached to, see [Debugger::attach]
+    watchpoints: Arc<Mutex<Watchpoints>>,
+    /// only follows what runs through the debugger
+    call_stack: RefCell<CallStack>,
+    interrupt_log: RefCell<InterruptLog>,
+    debug_info: Option<DebugInfo>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Adds an enabled breakpoint, does nothing if there already is one
+    /// at `address`
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if self.find_breakpoint(address).is_none() {
+            self.breakpoints.push(Breakpoint {
+                address,
+                is_enabled: true,
+            });
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints
+            .retain(|breakpoint| breakpoint.address != address);
+    }
+
+    pub fn set_breakpoint_enabled(&mut self, address: u16, enabled: bool) {
+        if let Some(breakpoint) = self.find_breakpoint(address) {
+            breakpoint.is_enabled = enabled;
+        }
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Lets the debugger see the writes of `nes`, needed for watchpoints.
+    /// Only attach it once.
+    pub fn attach(&self, nes: &mut Nes) {
+        nes.add_bus_observer(self.watchpoints.clone());
+    }
+
+    pub fn get_watchpoints(&self) -> Vec<Watchpoint> {
+        self.watchpoints.lock().unwrap().watchpoints.clone()
+    }
+
+    /// Breaks when `address` changes to `value`, or on any change with
+    /// `None`. Replaces the watchpoint that was on `address`.
+    pub fn add_watchpoint(&mut self, address: u16, value: Option<u8>) {
+        self.remove_watchpoint(address);
+        self.watchpoints
+            .lock()
+            .unwrap()
+            .watchpoints
+            .push(Watchpoint {
+                address,
+                value,
+                is_enabled: true,
+            });
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) {
+        self.watchpoints
+            .lock()
+            .unwrap()
+            .watchpoints
+            .retain(|watchpoint| watchpoint.address != address);
+    }
+
+    pub fn set_watchpoint_enabled(&mut self, address: u16, enabled: bool) {
+        let mut watchpoints = self.watchpoints.lock().unwrap();
+        if let Some(watchpoint) = watchpoints
+            .watchpoints
+            .iter_mut()
+            .find(|watchpoint| watchpoint.address == address)
+        {
+            watchpoint.is_enabled = enabled;
+        }
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.lock().unwrap().watchpoints.clear();
+    }
+
+    /// The write that stopped the nes with [BreakReason::Watchpoint],
+    /// running again without taking it breaks right away
+    pub fn take_watch_hit(&self) -> Option<WatchHit> {
+        self.watchpoints.lock().unwrap().hit.take()
+    }
+
+    fn watch_hit_address(&self) -> Option<u16> {
+        self.watchpoints
+            .lock()
+            .unwrap()
+            .hit
+            .map(|hit| hit.write.address)
+    }
+
+    /// The calls the cpu is in right now, the outermost first. Only what
+    /// ran through the debugger is seen.
+    pub fn call_stack(&self) -> Vec<CallFrame> {
+        self.call_stack.borrow().frames().to_vec()
+    }
+
+    /// The mismatched returns seen since the last call, they also get
+    /// logged as warnings
+    pub fn take_call_stack_warnings(&self) -> Vec<CallStackWarning> {
+        self.call_stack.borrow_mut().take_warnings()
+    }
+
+    /// Forgets the call stack, for after resetting or loading a state
+    pub fn clear_call_stack(&self) {
+        self.call_stack.borrow_mut().clear();
+    }
+
+    /// The call stack to show on a break, innermost call first with the
+    /// source lines of the calls when there is debug info
+    pub fn call_stack_report(&self) -> String {
+        let mut report = String::new();
+        for (depth, frame) in self.call_stack.borrow().frames().iter().rev().enumerate() {
+            let _ = write!(report, "#{depth} {frame}");
+            if let Some(line) = self
+                .debug_info
+                .as_ref()
+                .and_then(|debug_info| debug_info.source_line(frame.call_site))
+            {
+                let _ = write!(report, " ({}:{})", line.file, line.line);
+            }
+            report.push('\n');
+        }
+        report
+    }
+
+    /// The interrupts taken while running through the debugger, the
+    /// oldest first
+    pub fn interrupt_log(&self) -> Vec<Interrupt> {
+        self.interrupt_log.borrow().interrupts().copied().collect()
+    }
+
+    pub fn clear_interrupt_log(&self) {
+        self.interrupt_log.borrow_mut().clear();
+    }
+
+    /// Writes the interrupt log to the trace, see [InterruptLog::dump]
+    pub fn dump_interrupt_log(&self) {
+        self.interrupt_log.borrow().dump();
+    }
+
+    fn track(&self, nes: &Nes) {
+        self.call_stack.borrow_mut().on_instruction(nes);
+        self.interrupt_log.borrow_mut().on_instruction(nes);
+    }
+
+    /// Adds a breakpoint on every piece of code `line` of `file` turned
+    /// into and returns their addresses, see [DebugInfo::addresses]. Does
+    /// nothing without debug info.
+    pub fn add_source_breakpoint(&mut self, file: &str, line: u32) -> Vec<u16> {
+        let addresses = self
+            .debug_info
+            .as_ref()
+            .map(|debug_info| debug_info.addresses(file, line))
+            .unwrap_or_default();
+        for address in addresses.iter() {
+            self.add_breakpoint(*address);
+        }
+        addresses
+    }
+
+    pub fn set_debug_info(&mut self, debug_info: Option<DebugInfo>) {
+        self.debug_info = debug_info;
+    }
+
+    pub fn get_debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// The source line of the instruction the cpu is about to run
+    pub fn current_source_line(&self, nes: &Nes) -> Option<&SourceLine> {
+        let program_counter = nes.cpu.lock().unwrap().get_program_counter();
+        self.debug_info.as_ref()?.source_line(program_counter)
+    }
+
+    fn find_breakpoint(&mut self, address: u16) -> Option<&mut Breakpoint> {
+        self.breakpoints
+            .iter_mut()
+            .find(|breakpoint| breakpoint.address == address)
+    }
+
+    fn is_at_breakpoint(&self, nes: &Nes) -> bool {
+        let program_counter = nes.cpu.lock().unwrap().get_program_counter();
+        nes.is_at_instruction_start()
+            && self
+                .breakpoints
+                .iter()
+                .any(|breakpoint| breakpoint.is_enabled && breakpoint.address == program_counter)
+    }
+
+    /// Same as [Nes::run_frame] but stops early with
+    /// [BreakReason::ReachedProgramCounter] when the cpu is about to run
+    /// an instruction with a breakpoint, or [BreakReason::Watchpoint]
+    /// right after a watched address changed. Running again continues
+    /// from there, `frame` is only written when the frame gets finished.
+    pub fn run_frame(&self, nes: &mut Nes, frame: &mut Frame) -> RunSummary {
+        self.track(nes);
+        let mut summary = nes.run_until(u64::MAX, |nes| {
+            self.track(nes);
+            // scanline 240 is the first one after the visible ones
+            nes.ppu.lock().unwrap().get_position() == (240, 0)
+                || self.is_at_breakpoint(nes)
+                || self.watch_hit_address().is_some()
+        });
+        if summary.break_reason != BreakReason::ConditionMet {
+            return summary;
+        }
+
+        if let Some(address) = self.watch_hit_address() {
+            summary.break_reason = BreakReason::Watchpoint(address);
+        } else if self.is_at_breakpoint(nes) {
+            summary.break_reason =
+                BreakReason::ReachedProgramCounter(nes.cpu.lock().unwrap().get_program_counter());
+        } else {
+            summary.break_reason = BreakReason::FrameDone;
+            frame.clone_from(&nes.get_last_frame());
+        }
+        summary
+    }
+
+    /// Runs one instruction, but a JSR runs until its subroutine returns.
+    /// Stops early at breakpoints and after [STEP_OVER_MAX_CYCLES].
+    pub fn step_over(&self, nes: &mut Nes) -> RunSummary {
+        let (program_counter, stack_pointer) = {
+            let registers = nes.cpu.lock().unwrap().get_registers();
+            (registers.program_counter, registers.stack_pointer)
+        };
+        self.track(nes);
+        if nes.bus.peek(program_counter) != JSR {
+            return nes.run_instructions(1);
+        }
+
+        let return_address = program_counter.wrapping_add(3);
+        let mut summary = nes.run_until(STEP_OVER_MAX_CYCLES, |nes| {
+            self.track(nes);
+            if !nes.is_at_instruction_start() {
+                return false;
+            }
+            let registers = nes.cpu.lock().unwrap().get_registers();
+            // recursive calls come back to the same address deeper down the stack
+            let returned = registers.program_counter == return_address
+                && registers.stack_pointer >= stack_pointer;
+            returned || self.is_at_breakpoint(nes) || self.watch_hit_address().is_some()
+        });
+        if summary.break_reason == BreakReason::ConditionMet {
+            summary.break_reason = match self.watch_hit_address() {
+                Some(address) => BreakReason::Watchpoint(address),
+                None => BreakReason::ReachedProgramCounter(
+                    nes.cpu.lock().unwrap().get_program_counter(),
+                ),
+            };
+        }
+        summary
+    }
+
+    /// Disassembles `count` instructions starting at `address`, for a
+    /// view following the cpu start at its program counter
+    pub fn disassemble(nes: &Nes, address: u16, count: usize) -> Vec<DisassembledInstruction> {
+        let cpu = nes.cpu.lock().unwrap();
+        let mut address = address;
+        (0..count)
+            .map(|_| {
+                let instruction = cpu.disassemble(&nes.bus, address);
+                address = address.wrapping_add(instruction.bytes.len() as u16);
+                instruction
+            })
+            .collect()
+    }
+
+    /// Assembles `source` at `address` and writes it over what's there,
+    /// rom included (see [Nes::patch_memory]). Returns how many bytes
+    /// were written.
+    pub fn assemble_in_place(
+        nes: &mut Nes,
+        address: u16,
+        source: &str,
+    ) -> assembler::Result<usize> {
+        let bytes = assembler::assemble(address, source)?;
+        nes.patch_memory(address, &bytes);
+        Ok(bytes.len())
+    }
+
+    /// `rows` rows of the cpu address space for a hex view, starting at
+    /// the row `address` is in. Reads are peeks so registers with side
+    /// effects don't get triggered.
+    pub fn memory_rows(nes: &Nes, address: u16, rows: usize) -> Vec<(u16, [u8; MEMORY_ROW_SIZE])> {
+        let start = address as usize / MEMORY_ROW_SIZE * MEMORY_ROW_SIZE;
+        (0..rows)
+            .map(|row| {
+                let row_address = ((start + row * MEMORY_ROW_SIZE) & 0xFFFF) as u16;
+                let bytes =
+                    std::array::from_fn(|i| nes.bus.peek(row_address.wrapping_add(i as u16)));
+                (row_address, bytes)
+            })
+            .collect()
+    }
+
+    /// The whole stack page with the stack pointer, for a stack view.
+    /// Wrapping it can be caught with
+    /// [StackWrapPolicy](crate::hardware::cpu::StackWrapPolicy).
+    pub fn stack(nes: &Nes) -> StackView {
+        StackView {
+            stack_pointer: nes.cpu.lock().unwrap().get_registers().stack_pointer,
+            bytes: std::array::from_fn(|i| nes.bus.peek(STACK_OFFSET + i as u16)),
+        }
+    }
+
+    /// Both pattern tables next to each other, drawn with the colors of
+    /// `pallet` (0-3 are the background pallets and 4-7 the sprite ones).
+    /// The pixels are [PATTERN_TABLE_VIEW_WIDTH] by
+    /// [PATTERN_TABLE_VIEW_HEIGHT], row by row.
+    pub fn pattern_tables(nes: &Nes, pallet: u8) -> Vec<u32> {
+        let ppu = nes.ppu.lock().unwrap();
+        let tiles = ppu.process_pattern_table();
+        let mut pixels = vec![0; PATTERN_TABLE_VIEW_WIDTH * PATTERN_TABLE_VIEW_HEIGHT];
+        // every row of `tiles` is half a row of tiles of one table
+        for (tile_row, row) in tiles.iter().enumerate() {
+            let table = tile_row / 16;
+            for (tile_column, tile) in row.iter().enumerate() {
+                let x = table * 128 + tile_column * 8;
+                let y = (tile_row % 16) * 8;
+                for (i, line) in tile.iter().enumerate() {
+                    for (j, pattern) in line.iter().enumerate() {
+                        pixels[(y + i) * PATTERN_TABLE_VIEW_WIDTH + x + j] =
+                            ppu.get_output_color(*pattern, pallet & 7);
+                    }
+                }
+            }
+        }
+        pixels
+    }
+}
@@ -0,0 +1,169 @@
+//! Logs the reads and writes of a few picked registers instead of tracing
+//! every instruction, like only the $2005 and $2006 writes of a game with
+//! broken scrolling. Every access goes to the trace under
+//! [targets::REGISTERS] and the latest ones are kept for a debugger panel.
+
+use std::{collections::VecDeque, fmt::Display, str::FromStr};
+
+use crate::{
+    debugger::error::RegisterLogError,
+    hardware::cpu_bus::{BusObserver, BusRead, BusWrite},
+    trace::targets,
+};
+
+/// How many accesses [RegisterLog] keeps, the oldest get dropped
+pub const REGISTER_LOG_SIZE: usize = 4096;
+
+/// The ppu registers repeat every 8 bytes up to $3FFF, a filter on $2005
+/// also sees $2805
+fn mirror(address: u16) -> u16 {
+    match address {
+        0x2000..0x4000 => address & 0x2007,
+        address => address,
+    }
+}
+
+/// Which registers [RegisterLog] looks at. Parses from a list like
+/// `$2000,2005,$4016`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterFilter {
+    pub registers: Vec<u16>,
+    pub reads: bool,
+    pub writes: bool,
+}
+
+impl RegisterFilter {
+    /// Both reads and writes of `registers`
+    pub fn new(registers: impl IntoIterator<Item = u16>) -> Self {
+        Self {
+            registers: registers.into_iter().map(mirror).collect(),
+            reads: true,
+            writes: true,
+        }
+    }
+
+    pub fn matches(&self, address: u16, is_write: bool) -> bool {
+        (if is_write { self.writes } else { self.reads })
+            && self.registers.contains(&mirror(address))
+    }
+}
+
+impl FromStr for RegisterFilter {
+    type Err = RegisterLogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let registers = s
+            .split(',')
+            .map(str::trim)
+            .filter(|register| !register.is_empty())
+            .map(|register| {
+                u16::from_str_radix(register.trim_start_matches('$'), 16)
+                    .map_err(|_| RegisterLogError::InvalidAddressError(register.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(registers))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterAccess {
+    pub is_write: bool,
+    pub address: u16,
+    pub value: u8,
+    pub program_counter: u16,
+    pub frame: u64,
+    pub scanline: u32,
+    pub dot: u32,
+}
+
+impl Display for RegisterAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let access = if self.is_write { "write" } else { "read" };
+        write!(
+            f,
+            "{access} ${:04X} = {:02X} at ${:04X} (frame {}, scanline {}, dot {})",
+            self.address, self.value, self.program_counter, self.frame, self.scanline, self.dot
+        )
+    }
+}
+
+/// Attach it with [Nes::add_bus_observer](crate::Nes::add_bus_observer),
+/// the accesses show up in the trace with `SCAMU_LOG=scamu::registers=info`
+#[derive(Debug, Clone, Default)]
+pub struct RegisterLog {
+    filter: RegisterFilter,
+    accesses: VecDeque<RegisterAccess>,
+}
+
+impl RegisterLog {
+    pub fn new(filter: RegisterFilter) -> Self {
+        Self {
+            filter,
+            accesses: VecDeque::new(),
+        }
+    }
+
+    pub fn get_filter(&self) -> &RegisterFilter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: RegisterFilter) {
+        self.filter = filter;
+    }
+
+    /// The latest accesses, the oldest first
+    pub fn get_accesses(&self) -> impl Iterator<Item = &RegisterAccess> {
+        self.accesses.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.accesses.clear();
+    }
+
+    fn log(&mut self, access: RegisterAccess) {
+        if !self.filter.matches(access.address, access.is_write) {
+            return;
+        }
+        tracing::info!(
+            target: targets::REGISTERS,
+            write = access.is_write,
+            address = access.address,
+            value = access.value,
+            program_counter = access.program_counter,
+            frame = access.frame,
+            scanline = access.scanline,
+            dot = access.dot,
+            "{access}"
+        );
+        if self.accesses.len() == REGISTER_LOG_SIZE {
+            self.accesses.pop_front();
+        }
+        self.accesses.push_back(access);
+    }
+}
+
+impl BusObserver for RegisterLog {
+    fn on_write(&mut self, write: &BusWrite) {
+        self.log(RegisterAccess {
+            is_write: true,
+            address: write.address,
+            value: write.value,
+            program_counter: write.program_counter,
+            frame: write.frame,
+            scanline: write.scanline,
+            dot: write.dot,
+        });
+    }
+
+    fn on_read(&mut self, read: &BusRead) {
+        self.log(RegisterAccess {
+            is_write: false,
+            address: read.address,
+            value: read.value,
+            program_counter: read.program_counter,
+            frame: read.frame,
+            scanline: read.scanline,
+            dot: read.dot,
+        });
+    }
+}
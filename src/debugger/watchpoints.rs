@@ -0,0 +1,80 @@
+use std::fmt::Display;
+
+use crate::hardware::{
+    constants,
+    cpu_bus::{BusObserver, BusWrite},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u16,
+    /// Only break when the address changes to this, `None` breaks on
+    /// every change
+    pub value: Option<u8>,
+    pub is_enabled: bool,
+}
+
+impl Watchpoint {
+    /// Writes that leave the value as it was don't count
+    fn is_hit_by(&self, write: &BusWrite) -> bool {
+        self.is_enabled
+            && mirror(self.address) == mirror(write.address)
+            && write.value != write.previous_value
+            && self.value.is_none_or(|value| value == write.value)
+    }
+}
+
+/// Cpu ram is mirrored up to $1FFF, a watch on $0030 also sees $0830
+fn mirror(address: u16) -> u16 {
+    match address {
+        0x0..0x2000 => address & (constants::cpu::RAM_SIZE as u16 - 1),
+        address => address,
+    }
+}
+
+/// The write that triggered a watchpoint, [BusWrite::program_counter] is
+/// the instruction that did it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub write: BusWrite,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "${:04X} changed from {:02X} to {:02X} by the instruction at ${:04X} (scanline {}, dot {})",
+            self.write.address,
+            self.write.previous_value,
+            self.write.value,
+            self.write.program_counter,
+            self.write.scanline,
+            self.write.dot
+        )
+    }
+}
+
+/// Watches the writes on the bus for [super::Debugger], keeps the first
+/// hit until the debugger takes it
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    pub(super) watchpoints: Vec<Watchpoint>,
+    pub(super) hit: Option<WatchHit>,
+}
+
+impl BusObserver for Watchpoints {
+    fn on_write(&mut self, write: &BusWrite) {
+        if self.hit.is_some() {
+            return;
+        }
+        self.hit = self
+            .watchpoints
+            .iter()
+            .find(|watchpoint| watchpoint.is_hit_by(write))
+            .map(|watchpoint| WatchHit {
+                watchpoint: *watchpoint,
+                write: *write,
+            });
+    }
+}
@@ -0,0 +1,61 @@
+//! Raw dumps of the memories of the nes, so scripts can diff them between
+//! runs without a debugger, see [Nes::dump_region](crate::Nes::dump_region).
+//! A dump is just the bytes of the region, no header.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::devices::error::DumpError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Region {
+    /// The 2kb of ram inside the console
+    CpuRam,
+    /// The nametables, all four are dumped even if the cartrige only
+    /// uses two
+    PpuVram,
+    Oam,
+    PaletteRam,
+    /// The ram on the cartrige at $6000-$7FFF, battery backed or not
+    PrgRam,
+}
+
+impl Region {
+    pub const ALL: [Region; 5] = [
+        Region::CpuRam,
+        Region::PpuVram,
+        Region::Oam,
+        Region::PaletteRam,
+        Region::PrgRam,
+    ];
+
+    /// How the region is written on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::CpuRam => "cpu-ram",
+            Region::PpuVram => "ppu-vram",
+            Region::Oam => "oam",
+            Region::PaletteRam => "palette-ram",
+            Region::PrgRam => "prg-ram",
+        }
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Region {
+    type Err = DumpError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Region::ALL
+            .into_iter()
+            .find(|region| region.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| DumpError::UnknownRegionError(name.to_string()))
+    }
+}
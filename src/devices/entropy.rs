@@ -0,0 +1,132 @@
+//! Everything that could make two runs of the same movie differ goes
+//! through here instead of the host: random numbers and the time of day.
+//! The [Nes](super::nes::Nes) owns both and saves them in save states, so
+//! with the same seed and clock start a run always comes out the same and
+//! so do the state hashes of netplay and
+//! [lockstep](crate::frontend::lockstep).
+//!
+//! Right now only the power on ram (see [RamInit]) uses them, mappers with
+//! a real time clock should read [VirtualClock] and never the host time.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hardware::constants::clock_rates::CPU_CLOCK,
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+pub const DEFAULT_SEED: u64 = 0x5CA3_0000_0000_0001;
+
+/// A small xorshift64* rng, good enough for emulating noise and garbage
+/// and nothing else
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmuRng {
+    state: u64,
+}
+
+impl EmuRng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 so close seeds don't give close sequences, xorshift
+        // gets stuck on a zero state
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        Self {
+            state: state.max(1),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        for byte in bytes.iter_mut() {
+            *byte = self.next_u8();
+        }
+    }
+}
+
+impl Default for EmuRng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+/// The time of day as the emulated nes sees it, it starts at a fixed time
+/// and moves with the emulated cpu cycles instead of the host clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VirtualClock {
+    /// Seconds since the unix epoch at power on
+    start: u64,
+}
+
+impl VirtualClock {
+    pub fn new(start: u64) -> Self {
+        Self { start }
+    }
+
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    /// Seconds since the unix epoch after `cpu_cycles` cycles since power on
+    pub fn now(&self, cpu_cycles: u64) -> u64 {
+        self.start + cpu_cycles / CPU_CLOCK
+    }
+
+    /// Moves the clock so it says `unix_seconds` after `cpu_cycles`
+    pub fn set_now(&mut self, unix_seconds: u64, cpu_cycles: u64) {
+        self.start = unix_seconds.saturating_sub(cpu_cycles / CPU_CLOCK);
+    }
+}
+
+/// What the cpu ram holds after a power cycle. Real consoles come up with
+/// a mostly random pattern, a few games (and many homebrew bugs) depend on
+/// it. https://www.nesdev.org/wiki/CPU_power_up_state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RamInit {
+    #[default]
+    Zeros,
+    Ones,
+    /// From the [EmuRng], the same for the same seed
+    Random,
+}
+
+impl RamInit {
+    pub fn fill(self, ram: &mut [u8], rng: &mut EmuRng) {
+        match self {
+            RamInit::Zeros => ram.fill(0),
+            RamInit::Ones => ram.fill(0xFF),
+            RamInit::Random => rng.fill(ram),
+        }
+    }
+}
+
+/// Everything the [Nes](super::nes::Nes) saves in the `RNG ` chunk
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Entropy {
+    pub rng: EmuRng,
+    pub clock: VirtualClock,
+}
+
+impl SaveState for Entropy {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.rng.state);
+        writer.write_u64(self.clock.start);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.rng.state = reader.read_u64()?.max(1);
+        self.clock.start = reader.read_u64()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{devices::dump::Region, hardware::cartrige::error::CartrigeParseError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum MachineError {
+    #[error("Couldn't load the cartrige:\n{_0}")]
+    CartrigeError(#[from] CartrigeParseError),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    #[error("Unknown profile {_0:?}, the profiles are fast, balanced and accurate!")]
+    UnknownProfileError(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DumpError {
+    #[error("Got an io error while dumping or restoring memory:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("There is no cartrige inserted!")]
+    NoCartrigeError,
+    #[error("The {_0} dump is {_1} bytes but the region is {_2} bytes!")]
+    SizeMismatchError(Region, usize, usize),
+    #[error("Unknown memory region {_0:?}!")]
+    UnknownRegionError(String),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum RegionError {
+    #[error("Unknown region {_0:?}, the regions are auto, ntsc, pal and dendy!")]
+    UnknownRegionError(String),
+}
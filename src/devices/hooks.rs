@@ -0,0 +1,133 @@
+//! Callbacks on points of the ppu's frame, for code that needs to look at
+//! or poke the nes in the middle of a frame: rl environments sampling
+//! ram on a certain line, or tests of raster tricks that write the ppu
+//! registers on a line like a game's irq handler would. Unlike
+//! [Nes::set_scanline_callback](crate::Nes::set_scanline_callback) there
+//! can be any number of them, and they run on every line and in vblank.
+
+use crate::{
+    devices::region::Region,
+    hardware::{cpu_bus::CpuBus, ppu::PpuRegisters},
+};
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PpuEvent {
+    /// Right before the ppu starts on the line, so the lines above it are
+    /// drawn. Goes up to the pre-render line (261 on ntsc).
+    Scanline(u32),
+    /// Right after the vblank flag gets set, before the cpu gets to the
+    /// nmi handler
+    VblankStart,
+    /// Right after the vblank flag gets cleared at the start of the
+    /// pre-render line
+    VblankEnd,
+}
+
+impl PpuEvent {
+    /// The event at the (scanline, dot) the ppu is about to draw, if any
+    pub(crate) fn at(position: (u32, u32), region: Region) -> Option<Self> {
+        match position {
+            (line, 0) => Some(PpuEvent::Scanline(line)),
+            // the flags change on dot 1
+            (line, 2) if line == region.vblank_line() => Some(PpuEvent::VblankStart),
+            (line, 2) if line == region.pre_render_line() => Some(PpuEvent::VblankEnd),
+            _ => None,
+        }
+    }
+}
+
+/// What a hook gets to look at
+pub struct PpuHookContext<'a> {
+    pub event: PpuEvent,
+    /// See [Nes::frame_count](crate::Nes::frame_count)
+    pub frame: u64,
+    pub ppu: PpuRegisters,
+    /// Reads and writes go through like the cpu made them on this dot, so
+    /// writing PPUSCROLL on a line splits the screen there. Use
+    /// [CpuBus::peek] to look without side effects.
+    pub bus: &'a mut CpuBus,
+}
+
+pub type PpuHook = Box<dyn FnMut(&mut PpuHookContext) + Send>;
+
+/// Returned when adding a hook, for removing it again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+/// The hooks of a nes, see [Nes::hooks_mut](crate::Nes::hooks_mut). They
+/// run in the order they were added and stay through power cycles and
+/// save state loads.
+#[derive(Default)]
+pub struct PpuHooks {
+    hooks: Vec<(HookId, PpuEvent, PpuHook)>,
+    next_id: u64,
+}
+
+impl PpuHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `hook` when `event` happens, see [PpuEvent]
+    pub fn add(
+        &mut self,
+        event: PpuEvent,
+        hook: impl FnMut(&mut PpuHookContext) + Send + 'static,
+    ) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push((id, event, Box::new(hook)));
+        id
+    }
+
+    /// Runs `hook` before every frame's line `line`
+    pub fn on_scanline(
+        &mut self,
+        line: u32,
+        hook: impl FnMut(&mut PpuHookContext) + Send + 'static,
+    ) -> HookId {
+        self.add(PpuEvent::Scanline(line), hook)
+    }
+
+    pub fn on_vblank_start(
+        &mut self,
+        hook: impl FnMut(&mut PpuHookContext) + Send + 'static,
+    ) -> HookId {
+        self.add(PpuEvent::VblankStart, hook)
+    }
+
+    pub fn on_vblank_end(
+        &mut self,
+        hook: impl FnMut(&mut PpuHookContext) + Send + 'static,
+    ) -> HookId {
+        self.add(PpuEvent::VblankEnd, hook)
+    }
+
+    /// Returns false if there was no hook with `id`
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let len = self.hooks.len();
+        self.hooks.retain(|(hook_id, _, _)| *hook_id != id);
+        self.hooks.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.hooks.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub(crate) fn run(&mut self, context: &mut PpuHookContext) {
+        for (_, event, hook) in self.hooks.iter_mut() {
+            if *event == context.event {
+                hook(context);
+            }
+        }
+    }
+}
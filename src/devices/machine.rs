@@ -0,0 +1,114 @@
+use crate::{
+    devices::{error::MachineError, nes::Nes, region::Region, run::RunSummary},
+    hardware::{
+        cartrige::Cartrige, constants::clock_rates::FRAME_RATE, input::controller::Button,
+        ppu::frame::Frame,
+    },
+    save_state,
+};
+
+pub type Result<T> = std::result::Result<T, MachineError>;
+
+/// What every emulated system has to do so the frontend, save state and
+/// remote code can work with it without knowing which one it is. Only
+/// the [Nes] implements it for now, but famicom variants or the VS System
+/// would go next to it in [devices](crate::devices).
+pub trait Machine {
+    /// Presses the reset button
+    fn reset(&mut self);
+
+    /// Advances by the smallest step the machine has (a ppu dot for the
+    /// [Nes])
+    fn tick(&mut self);
+
+    /// Runs until the next frame is finished and copies it into `frame`
+    fn run_frame(&mut self, frame: &mut Frame) -> RunSummary;
+
+    /// Inserts a rom (or disk, tape...) from its bytes and resets
+    fn load_media(&mut self, media: &[u8]) -> Result<()>;
+
+    fn set_button(&mut self, controller_index: usize, button: Button, pressed: bool);
+
+    /// For machines with a paddle plugged in, like
+    /// [Nes::set_paddle]
+    fn set_paddle(&mut self, _position: u8, _fire: bool) {}
+
+    /// For machines with a keyboard plugged in, like [Nes::set_key]
+    fn set_key(&mut self, _row: usize, _column: usize, _key: usize, _pressed: bool) {}
+
+    fn save_state(&self) -> Vec<u8>;
+
+    fn load_state(&mut self, state: &[u8]) -> save_state::Result<()>;
+
+    /// Takes the audio samples made since the last call
+    fn take_audio_samples(&mut self) -> Vec<f32>;
+
+    /// See [Apu::get_queue_fill](crate::hardware::apu::Apu::get_queue_fill)
+    fn get_audio_queue_fill(&self) -> f32;
+
+    /// For machines sold in several regions, see [Nes::get_region]
+    fn get_region(&self) -> Option<Region> {
+        None
+    }
+
+    /// Frames a second, for pacing the frontend
+    fn get_frame_rate(&self) -> u32 {
+        FRAME_RATE
+    }
+}
+
+impl Machine for Nes {
+    fn reset(&mut self) {
+        Nes::reset(self);
+    }
+
+    fn tick(&mut self) {
+        Nes::tick(self);
+    }
+
+    fn run_frame(&mut self, frame: &mut Frame) -> RunSummary {
+        Nes::run_frame(self, frame)
+    }
+
+    fn load_media(&mut self, media: &[u8]) -> Result<()> {
+        self.insert_cartrige_now(Cartrige::from_bytes(media)?);
+        Nes::reset(self);
+        Ok(())
+    }
+
+    fn set_button(&mut self, controller_index: usize, button: Button, pressed: bool) {
+        Nes::set_button(self, controller_index, button, pressed);
+    }
+
+    fn set_paddle(&mut self, position: u8, fire: bool) {
+        Nes::set_paddle(self, position, fire);
+    }
+
+    fn set_key(&mut self, row: usize, column: usize, key: usize, pressed: bool) {
+        Nes::set_key(self, row, column, key, pressed);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        Nes::save_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> save_state::Result<()> {
+        Nes::load_state(self, state)
+    }
+
+    fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.apu.lock().unwrap().by_ref().collect()
+    }
+
+    fn get_audio_queue_fill(&self) -> f32 {
+        self.apu.lock().unwrap().get_queue_fill()
+    }
+
+    fn get_region(&self) -> Option<Region> {
+        Some(Nes::get_region(self))
+    }
+
+    fn get_frame_rate(&self) -> u32 {
+        Nes::get_region(self).frame_rate()
+    }
+}
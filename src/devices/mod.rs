@@ -1 +1,10 @@
+pub mod dump;
+pub mod entropy;
+pub mod error;
+pub mod hooks;
+pub mod machine;
 pub mod nes;
+pub mod profile;
+pub mod region;
+pub mod run;
+pub mod speed_hacks;
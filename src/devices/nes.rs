@@ -1,38 +1,177 @@
 use std::{
-    cell::RefCell,
-    rc::Rc,
-    sync::{Arc, Mutex},
+    io::{Read, Write},
+    ops::Deref,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
 };
 
-use crate::hardware::{
-    apu::Apu,
-    cartrige::Cartrige,
-    cpu::{Cpu, DmaState},
-    cpu_bus::CpuBus,
-    ppu::Ppu,
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    devices::{
+        dump::Region,
+        entropy::{DEFAULT_SEED, EmuRng, Entropy, RamInit},
+        error::DumpError,
+        hooks::{PpuEvent, PpuHookContext, PpuHooks},
+        profile::ProfileSettings,
+        region::Region as TvRegion,
+        run::{BreakReason, RunSummary},
+        speed_hacks::{IdleLoopDetector, SpeedHacks},
+    },
+    hardware::{
+        apu::{Apu, dmc_channel::DmcDmaCycle},
+        cartrige::{Cartrige, error::SramError, game_genie::GameGenie, sram},
+        constants::{
+            cartrige::CARTRIGE_START,
+            ppu::{DOTS_PER_SCANLINE, SCREEN_HEIGHT, SCREEN_WIDTH, STATUS_REGISTER},
+        },
+        counters::{Counter, Counters},
+        cpu::{Cpu, CpuConfig, DmaState, IllegalOpcodePolicy, OpcodeInfo, StackWrap},
+        cpu_bus::{BusObserver, CpuBus},
+        input::{
+            Device, Port,
+            controller::{Button, ControllerState},
+            data_recorder::DataRecorder,
+            macros::{FastBoot, InputMacro, MacroPriority},
+            sanitizer::OppositeDirections,
+        },
+        ppu::{
+            Layer, Ppu, PpuRegisters, frame::Frame, renderer::RenderMode, scanline::PpuAccuracy,
+        },
+    },
+    osd::{input_display::InputDisplay, overlay::Overlay},
+    save_state::{
+        self, ParsedState, StateBuilder, StateReader, StateWriter,
+        error::SaveStateError,
+        metadata::{METADATA_TAG, StateMetadata, Thumbnail},
+    },
+    trace::targets,
 };
 
+mod chunk_tags {
+    use crate::save_state::ChunkTag;
+
+    pub const NES: ChunkTag = *b"NES ";
+    pub const ROM: ChunkTag = *b"ROM ";
+    pub const CPU: ChunkTag = *b"CPU ";
+    pub const BUS: ChunkTag = *b"BUS ";
+    pub const INPUT: ChunkTag = *b"INPT";
+    pub const PPU: ChunkTag = *b"PPU ";
+    pub const APU: ChunkTag = *b"APU ";
+    pub const CARTRIGE: ChunkTag = *b"CART";
+    pub const ENTROPY: ChunkTag = *b"RNG ";
+}
+
+pub type FrameCallback = Box<dyn FnMut(&Frame) + Send>;
+pub type ScanlineCallback = Box<dyn FnMut(&mut Scanline) + Send>;
+
+/// A visible line the ppu just finished drawing, see
+/// [Nes::set_scanline_callback]
+pub struct Scanline<'a> {
+    /// 0 is the top of the screen
+    pub line: u32,
+    /// The raw pixels of the line (palette color and emphasis, see
+    /// [RawFrame](crate::hardware::ppu::frame::RawFrame)), changing them
+    /// draws over the line before its colors get looked up
+    pub pixels: &'a mut [u16],
+    pub ppu: PpuRegisters,
+    /// For reading game ram with [CpuBus::peek]
+    pub bus: &'a CpuBus,
+}
+
+/// The last frame borrowed straight from the ppu, see [Nes::get_last_frame].
+/// The ppu stays locked until this is dropped.
+pub struct LastFrame<'a>(MutexGuard<'a, Ppu>);
+
+impl Deref for LastFrame<'_> {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        self.0.get_last_frame()
+    }
+}
+
+/// When [Nes::insert_cartrige] and [Nes::eject_cartrige] take effect, see
+/// [Nes::set_cartrige_swap]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CartrigeSwap {
+    /// Right away, even in the middle of a frame. Fine before the first
+    /// power cycle or when the caller power cycles right after.
+    #[default]
+    Immediate,
+    /// At the start of the next vblank, then the nes power cycles so the
+    /// new game boots. The frame being drawn is finished with the old
+    /// cartrige, so a drag and drop never tears a frame.
+    NextVblank,
+    /// On the next [Nes::power_cycle], like a real console where the
+    /// cartrige only gets swapped with the power off
+    PowerCycle,
+}
+
+/// The parts of the nes share each other through `Arc<Mutex<_>>` so a
+/// whole nes is `Send`, many of them can run on a thread pool. Only the
+/// thread running the nes ever locks them.
 pub struct Nes {
     total_cycles: u64,
+    /// Cpu cycles since power on, the dots they fall on depend on the
+    /// region
+    cpu_cycles: u64,
+    frame_callback: Option<FrameCallback>,
+    scanline_callback: Option<ScanlineCallback>,
+    hooks: PpuHooks,
+    input_display: InputDisplay,
+    overlay: Overlay,
+    speed_hacks: SpeedHacks,
+    deterministic: bool,
+    idle_loop: IdleLoopDetector,
+    /// ppu dots left in the current overclock pause
+    overclock_dots: u32,
+    seed: u64,
+    entropy: Entropy,
+    ram_init: RamInit,
+    /// the region the next power cycle switches to, see [Nes::set_region]
+    next_region: TvRegion,
+    cartrige_swap: CartrigeSwap,
+    /// waiting for [Nes::cartrige_swap], `Some(None)` is an eject
+    pending_cartrige: Option<Option<Cartrige>>,
+    fast_boot: Option<FastBoot>,
     pub bus: CpuBus,
-    pub cpu: Rc<RefCell<Cpu>>,
-    pub ppu: Rc<RefCell<Ppu>>,
+    pub cpu: Arc<Mutex<Cpu>>,
+    pub ppu: Arc<Mutex<Ppu>>,
     pub apu: Arc<Mutex<Apu>>,
-    cartrige: Option<Rc<RefCell<Cartrige>>>,
+    cartrige: Option<Arc<Mutex<Cartrige>>>,
 }
 
 impl Nes {
     pub fn new() -> Self {
         let mut bus = CpuBus::new();
-        let cpu = Rc::new(RefCell::new(Cpu::new()));
-        let ppu = Rc::new(RefCell::new(Ppu::new()));
+        let cpu = Arc::new(Mutex::new(Cpu::new()));
+        let ppu = Arc::new(Mutex::new(Ppu::new()));
         let apu = Arc::new(Mutex::new(Apu::new()));
         bus.connect_ppu(ppu.clone());
         bus.connect_apu(apu.clone());
         apu.lock().unwrap().connect_cpu(cpu.clone());
-        ppu.borrow_mut().connect_cpu(cpu.clone());
+        ppu.lock().unwrap().connect_cpu(cpu.clone());
         Self {
             total_cycles: 0,
+            cpu_cycles: 0,
+            frame_callback: None,
+            scanline_callback: None,
+            hooks: PpuHooks::new(),
+            input_display: InputDisplay::new(),
+            overlay: Overlay::new(),
+            speed_hacks: SpeedHacks::default(),
+            deterministic: false,
+            idle_loop: IdleLoopDetector::default(),
+            overclock_dots: 0,
+            seed: DEFAULT_SEED,
+            entropy: Entropy::default(),
+            ram_init: RamInit::default(),
+            next_region: TvRegion::default(),
+            cartrige_swap: CartrigeSwap::default(),
+            pending_cartrige: None,
+            fast_boot: None,
             bus,
             cpu,
             ppu,
@@ -42,95 +181,1219 @@ impl Nes {
     }
 
     pub fn new_with_cartrige(cartrige: Cartrige) -> Self {
-        let cartrige_rc = Rc::new(RefCell::new(cartrige));
+        let cartrige_arc = Arc::new(Mutex::new(cartrige));
         let mut out = Self {
             total_cycles: 0,
+            cpu_cycles: 0,
+            frame_callback: None,
+            scanline_callback: None,
+            hooks: PpuHooks::new(),
+            input_display: InputDisplay::new(),
+            overlay: Overlay::new(),
+            speed_hacks: SpeedHacks::default(),
+            deterministic: false,
+            idle_loop: IdleLoopDetector::default(),
+            overclock_dots: 0,
+            seed: DEFAULT_SEED,
+            entropy: Entropy::default(),
+            ram_init: RamInit::default(),
+            next_region: TvRegion::default(),
+            cartrige_swap: CartrigeSwap::default(),
+            pending_cartrige: None,
+            fast_boot: None,
             bus: CpuBus::new(),
-            cpu: Rc::new(RefCell::new(Cpu::new())),
-            ppu: Rc::new(RefCell::new(Ppu::new())),
+            cpu: Arc::new(Mutex::new(Cpu::new())),
+            ppu: Arc::new(Mutex::new(Ppu::new())),
             apu: Arc::new(Mutex::new(Apu::new())),
-            cartrige: Some(cartrige_rc.clone()),
+            cartrige: Some(cartrige_arc.clone()),
         };
-        out.bus.insert_cartrige(cartrige_rc.clone());
+        out.bus.insert_cartrige(cartrige_arc.clone());
         out.bus.connect_ppu(out.ppu.clone());
         out.bus.connect_apu(out.apu.clone());
         out.apu.lock().unwrap().connect_cpu(out.cpu.clone());
-        out.ppu.borrow_mut().insert_cartrige(cartrige_rc);
-        out.ppu.borrow_mut().connect_cpu(out.cpu.clone());
+        out.ppu.lock().unwrap().insert_cartrige(cartrige_arc);
+        out.ppu.lock().unwrap().connect_cpu(out.cpu.clone());
+        out.attach_expansion_audio();
         out
     }
 
+    /// Puts `cartrige` in, replacing the one that was there. When that
+    /// happens depends on [Nes::set_cartrige_swap].
     pub fn insert_cartrige(&mut self, cartrige: Cartrige) {
-        let cartrige = Rc::new(RefCell::new(cartrige));
-        self.bus.insert_cartrige(cartrige.clone());
-        self.ppu.borrow_mut().insert_cartrige(cartrige.clone());
-        self.cartrige = Some(cartrige);
+        self.queue_cartrige(Some(cartrige));
+    }
+
+    /// Takes the cartrige out, timed like [Nes::insert_cartrige]. Without
+    /// a cartrige the cpu reads open bus, like a console with an empty
+    /// slot.
+    pub fn eject_cartrige(&mut self) {
+        self.queue_cartrige(None);
+    }
+
+    /// When inserting and ejecting cartriges takes effect
+    pub fn set_cartrige_swap(&mut self, cartrige_swap: CartrigeSwap) {
+        self.cartrige_swap = cartrige_swap;
+    }
+
+    pub fn get_cartrige_swap(&self) -> CartrigeSwap {
+        self.cartrige_swap
+    }
+
+    /// Whether an insert or eject is still waiting for the vblank or power
+    /// cycle, a newer one replaces it
+    pub fn has_pending_cartrige(&self) -> bool {
+        self.pending_cartrige.is_some()
+    }
+
+    /// Inserts right away whatever [Nes::set_cartrige_swap] says, for
+    /// callers that reset right after like loading a rom from the frontend
+    pub(crate) fn insert_cartrige_now(&mut self, cartrige: Cartrige) {
+        self.pending_cartrige = None;
+        self.swap_cartrige(Some(cartrige));
+    }
+
+    fn queue_cartrige(&mut self, cartrige: Option<Cartrige>) {
+        match self.cartrige_swap {
+            CartrigeSwap::Immediate => {
+                self.pending_cartrige = None;
+                self.swap_cartrige(cartrige);
+            }
+            CartrigeSwap::NextVblank | CartrigeSwap::PowerCycle => {
+                self.pending_cartrige = Some(cartrige);
+            }
+        }
+    }
+
+    fn swap_cartrige(&mut self, cartrige: Option<Cartrige>) {
+        match cartrige {
+            Some(cartrige) => {
+                let cartrige = Arc::new(Mutex::new(cartrige));
+                self.bus.insert_cartrige(cartrige.clone());
+                self.ppu.lock().unwrap().insert_cartrige(cartrige.clone());
+                self.cartrige = Some(cartrige);
+                self.bus.get_input_mut().start_fast_boot(self.fast_boot);
+            }
+            None => {
+                self.bus.eject_cartrige();
+                self.ppu.lock().unwrap().eject_cartrige();
+                self.cartrige = None;
+            }
+        }
+        self.attach_expansion_audio();
+    }
+
+    /// Plugs the inserted cartrige into a Game Genie with `rom` as its rom
+    /// and power cycles, so the Game Genie's menu comes up. Does nothing
+    /// without a cartrige to plug it into.
+    pub fn attach_game_genie(&mut self, rom: Cartrige) {
+        let Some(cartrige) = self.cartrige.as_ref() else {
+            return;
+        };
+        cartrige
+            .lock()
+            .unwrap()
+            .attach_game_genie(GameGenie::new(rom));
+        self.power_cycle();
+    }
+
+    /// Takes the Game Genie out and power cycles
+    pub fn detach_game_genie(&mut self) {
+        let Some(cartrige) = self.cartrige.as_ref() else {
+            return;
+        };
+        if cartrige.lock().unwrap().detach_game_genie().is_some() {
+            self.power_cycle();
+        }
+    }
+
+    /// Swaps the apu's expansion chip for a fresh one from the cartrige
+    fn attach_expansion_audio(&mut self) {
+        let audio = self
+            .cartrige
+            .as_ref()
+            .and_then(|cartrige| cartrige.lock().unwrap().expansion_audio());
+        self.bus.set_expansion_audio(audio.is_some());
+        let mut apu = self.apu.lock().unwrap();
+        apu.detach_expansion_audio();
+        if let Some(audio) = audio {
+            apu.attach_expansion_audio(audio);
+        }
+    }
+
+    /// The crc32 of the inserted rom, see
+    /// [Checksums::rom](crate::hardware::cartrige::Checksums)
+    pub fn get_rom_crc32(&self) -> Option<u32> {
+        self.cartrige
+            .as_ref()
+            .map(|cartrige| cartrige.lock().unwrap().get_checksums().rom.crc32)
+    }
+
+    /// Swaps in a new cartrige and power cycles, for picking up a rom that
+    /// was just rebuilt, see [crate::frontend::watch::RomWatcher]
+    pub fn reload_cartrige(&mut self, cartrige: Cartrige) {
+        self.insert_cartrige(cartrige);
+        self.power_cycle();
+    }
+
+    /// Like turning the console off and on again. Everything the frontend
+    /// set up stays: attached devices, bus observers, the frame and
+    /// scanline callbacks and the settings.
+    pub fn power_cycle(&mut self) {
+        if let Some(cartrige) = self.pending_cartrige.take() {
+            self.swap_cartrige(cartrige);
+        }
+        self.bus.power_cycle();
+        self.entropy.rng = EmuRng::new(self.seed);
+        self.ram_init
+            .fill(self.bus.get_cpu_ram_mut(), &mut self.entropy.rng);
+        self.cpu.lock().unwrap().power_cycle();
+        self.ppu.lock().unwrap().power_cycle();
+        self.apu.lock().unwrap().power_cycle();
+        self.apply_region(self.next_region);
+        if let Some(cartrige) = self.cartrige.as_ref() {
+            cartrige.lock().unwrap().power_cycle();
+        }
+        self.attach_expansion_audio();
+        self.total_cycles = 0;
+        self.cpu_cycles = 0;
+        self.idle_loop = IdleLoopDetector::default();
+        self.overclock_dots = 0;
+        self.bus.get_input_mut().start_fast_boot(self.fast_boot);
+        self.reset();
+    }
+
+    /// Switches to `region` on the next [Nes::power_cycle], a console
+    /// can't change its clocks while running. See
+    /// [RegionSetting::resolve](crate::devices::region::RegionSetting::resolve)
+    /// for picking the region of a game.
+    pub fn set_region(&mut self, region: TvRegion) {
+        self.next_region = region;
+    }
+
+    /// The region the nes is running as, for showing on the hud
+    pub fn get_region(&self) -> TvRegion {
+        self.ppu.lock().unwrap().get_region()
+    }
+
+    /// What [Nes::set_region] was last called with
+    pub fn get_next_region(&self) -> TvRegion {
+        self.next_region
+    }
+
+    fn apply_region(&mut self, region: TvRegion) {
+        self.ppu.lock().unwrap().set_region(region);
+        self.apu.lock().unwrap().set_region(region);
+    }
+
+    /// Whether the cpu and apu get clocked on the current dot, every 3rd
+    /// one on ntsc and 5 out of 16 on pal
+    fn is_cpu_dot(&self, region: TvRegion) -> bool {
+        let (dots, cycles) = region.dots_per_cpu_cycle();
+        self.total_cycles.wrapping_mul(cycles) % dots < cycles
+    }
+
+    /// How many of the first `total_cycles` dots were cpu dots
+    fn count_cpu_dots(total_cycles: u64, region: TvRegion) -> u64 {
+        let (dots, cycles) = region.dots_per_cpu_cycle();
+        let partial = (0..total_cycles % dots)
+            .filter(|dot| dot * cycles % dots < cycles)
+            .count() as u64;
+        total_cycles / dots * cycles + partial
+    }
+
+    /// Returns the battery backed ram of the inserted cartrige in the
+    /// `.sav` format used by FCEUX and Mesen. Returns `None` if there is
+    /// no cartrige or it has no battery.
+    pub fn export_sram(&self) -> Option<Vec<u8>> {
+        self.cartrige.as_ref()?.lock().unwrap().export_sram()
+    }
+
+    /// Loads a `.sav` file into the battery backed ram of the inserted
+    /// cartrige. This should be done before resetting the nes, since
+    /// games usually only check their saves when booting.
+    pub fn import_sram(&mut self, save: &[u8]) -> sram::Result<()> {
+        self.cartrige
+            .as_ref()
+            .ok_or(SramError::NoCartrigeError)?
+            .lock()
+            .unwrap()
+            .import_sram(save)
+    }
+
+    /// Writes the `.sav` file to `path`, see [Nes::export_sram]. Does
+    /// nothing if the cartrige doesn't have a battery.
+    pub fn save_sram_to_file(&self, path: &Path) -> sram::Result<()> {
+        if let Some(save) = self.export_sram() {
+            std::fs::write(path, save)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a `.sav` file from `path`, see [Nes::import_sram]
+    pub fn load_sram_from_file(&mut self, path: &Path) -> sram::Result<()> {
+        let save = std::fs::read(path)?;
+        self.import_sram(&save)
+    }
+
+    /// Writes the raw bytes of `region`, see [crate::devices::dump]
+    pub fn dump_region(&self, region: Region, writer: &mut impl Write) -> Result<(), DumpError> {
+        match region {
+            Region::CpuRam => writer.write_all(self.bus.get_cpu_ram())?,
+            Region::PpuVram => writer.write_all(self.ppu.lock().unwrap().get_nametable_memory())?,
+            Region::Oam => writer.write_all(&self.ppu.lock().unwrap().oam)?,
+            Region::PaletteRam => {
+                writer.write_all(self.ppu.lock().unwrap().pallet_memory.get_bytes())?
+            }
+            Region::PrgRam => writer.write_all(
+                self.cartrige
+                    .as_ref()
+                    .ok_or(DumpError::NoCartrigeError)?
+                    .lock()
+                    .unwrap()
+                    .get_prg_ram(),
+            )?,
+        }
+        Ok(())
+    }
+
+    /// Loads a dump made by [Nes::dump_region] back, it has to be
+    /// exactly as big as the region. Nothing else gets changed, so the
+    /// game may not notice until it reads the memory again.
+    pub fn restore_region(
+        &mut self,
+        region: Region,
+        reader: &mut impl Read,
+    ) -> Result<(), DumpError> {
+        let mut dump = Vec::new();
+        reader.read_to_end(&mut dump)?;
+        let restore = |memory: &mut [u8]| {
+            if memory.len() != dump.len() {
+                return Err(DumpError::SizeMismatchError(
+                    region,
+                    dump.len(),
+                    memory.len(),
+                ));
+            }
+            memory.copy_from_slice(&dump);
+            Ok(())
+        };
+        match region {
+            Region::CpuRam => restore(self.bus.get_cpu_ram_mut()),
+            Region::PpuVram => restore(self.ppu.lock().unwrap().get_nametable_memory_mut()),
+            Region::Oam => restore(&mut self.ppu.lock().unwrap().oam),
+            Region::PaletteRam => restore(self.ppu.lock().unwrap().pallet_memory.get_bytes_mut()),
+            Region::PrgRam => restore(
+                self.cartrige
+                    .as_ref()
+                    .ok_or(DumpError::NoCartrigeError)?
+                    .lock()
+                    .unwrap()
+                    .get_prg_ram_mut(),
+            ),
+        }
+    }
+
+    /// Saves the whole state of the nes, see [save_state] for the format.
+    /// If a cartrige is inserted its rom isn't saved, only a checksum of
+    /// it so the state can't be loaded with a different game.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.state_builder().finish()
+    }
+
+    /// Same as [Nes::save_state] but with a thumbnail of the last frame
+    /// and `timestamp` (seconds since the unix epoch) saved in it, see
+    /// [StateMetadata::from_state]
+    pub fn save_state_with_metadata(&self, timestamp: u64) -> Vec<u8> {
+        let metadata = StateMetadata {
+            timestamp,
+            frame_count: self.frame_count(),
+            thumbnail: Thumbnail::from_frame(&self.get_last_frame()),
+        };
+        let mut builder = self.state_builder();
+        builder.add_chunk(METADATA_TAG, &metadata);
+        builder.finish()
+    }
+
+    fn state_builder(&self) -> StateBuilder {
+        let mut builder = StateBuilder::new();
+
+        let mut nes = StateWriter::new();
+        nes.write_u64(self.total_cycles);
+        nes.write_u8(self.get_region() as u8);
+        builder.add_raw_chunk(chunk_tags::NES, &nes.into_bytes());
+
+        builder.add_chunk(chunk_tags::CPU, &*self.cpu.lock().unwrap());
+        builder.add_chunk(chunk_tags::BUS, &self.bus);
+        builder.add_chunk(chunk_tags::INPUT, &*self.bus.get_input());
+        builder.add_chunk(chunk_tags::PPU, &*self.ppu.lock().unwrap());
+        builder.add_chunk(chunk_tags::APU, &*self.apu.lock().unwrap());
+        builder.add_chunk(chunk_tags::ENTROPY, &self.entropy);
+
+        if let Some(cartrige) = self.cartrige.as_ref() {
+            let cartrige = cartrige.lock().unwrap();
+            let mut rom = StateWriter::new();
+            rom.write_u32(cartrige.get_checksums().rom.crc32);
+            builder.add_raw_chunk(chunk_tags::ROM, &rom.into_bytes());
+            builder.add_chunk(chunk_tags::CARTRIGE, &*cartrige);
+        }
+
+        builder
+    }
+
+    /// Loads a state made by [Nes::save_state], also accepts states made
+    /// by older versions of scamu. If loading fails the nes is left
+    /// exactly how it was before.
+    pub fn load_state(&mut self, data: &[u8]) -> save_state::Result<()> {
+        let state = ParsedState::parse(data)?;
+        let backup = self.save_state();
+
+        let result = self.load_parsed_state(&state);
+        if let Err(err) = result.as_ref() {
+            tracing::warn!(target: targets::EMULATOR, "failed to load save state: {err}");
+            let backup = ParsedState::parse(&backup).expect("backup state should be valid");
+            self.load_parsed_state(&backup)
+                .expect("backup state should load");
+        }
+        result
+    }
+
+    fn load_parsed_state(&mut self, state: &ParsedState) -> save_state::Result<()> {
+        let saved_rom = match state.chunk(chunk_tags::ROM) {
+            Ok(rom) => Some(StateReader::new(rom).read_u32()?),
+            Err(SaveStateError::MissingChunkError(_)) => None,
+            Err(err) => return Err(err),
+        };
+        let current_rom = self
+            .cartrige
+            .as_ref()
+            .map(|cartrige| cartrige.lock().unwrap().get_checksums().rom.crc32);
+        match (current_rom, saved_rom) {
+            (None, Some(_)) => return Err(SaveStateError::NoCartrigeError),
+            (current_rom, saved_rom) if current_rom != saved_rom => {
+                return Err(SaveStateError::RomMismatchError);
+            }
+            _ => {}
+        }
+
+        self.idle_loop.wake();
+        self.overclock_dots = 0;
+        let mut nes = StateReader::new(state.chunk(chunk_tags::NES)?);
+        self.total_cycles = nes.read_u64()?;
+        let region = nes.read_u8()?;
+        let region = *TvRegion::ALL
+            .get(region as usize)
+            .ok_or(SaveStateError::InvalidValueError("region", region as u64))?;
+        // the state decides, like a state made with a different game would
+        self.apply_region(region);
+        self.next_region = region;
+        self.cpu_cycles = Self::count_cpu_dots(self.total_cycles, region);
+
+        state.load_chunk(chunk_tags::CPU, &mut *self.cpu.lock().unwrap())?;
+        state.load_chunk(chunk_tags::BUS, &mut self.bus)?;
+        state.load_chunk(chunk_tags::INPUT, self.bus.get_input_mut())?;
+        state.load_chunk(chunk_tags::PPU, &mut *self.ppu.lock().unwrap())?;
+        state.load_chunk(chunk_tags::APU, &mut *self.apu.lock().unwrap())?;
+        state.load_chunk(chunk_tags::ENTROPY, &mut self.entropy)?;
+        if let Some(cartrige) = self.cartrige.as_ref() {
+            state.load_cartrige_chunk(chunk_tags::CARTRIGE, &mut cartrige.lock().unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Plugs `device` into `port`, replacing whatever was there. By
+    /// default both controller ports have a standard controller and the
+    /// expansion port is empty.
+    pub fn attach_device(&mut self, port: Port, device: Device) {
+        self.bus.get_input_mut().attach_device(port, device);
+    }
+
+    pub fn get_device(&self, port: Port) -> Device {
+        self.bus.get_input().get_device(port)
+    }
+
+    /// `controller_index` counts the controllers of all the attached
+    /// devices in port order, so with the default devices 0 is the first
+    /// controller and 1 the second one, and with a four score 0 to 3 are
+    /// its controllers. Anything else is ignored.
+    pub fn set_button(&mut self, controller_index: usize, button: Button, pressed: bool) {
+        self.bus
+            .get_input_mut()
+            .set_button(controller_index, button, pressed);
+    }
+
+    /// Presses the buttons in `buttons` and releases the others, like
+    /// [Nes::set_button] for every button
+    pub fn set_buttons(&mut self, controller_index: usize, buttons: ControllerState) {
+        for button in Button::ALL {
+            self.set_button(controller_index, button, buttons.is_pressed(button));
+        }
+    }
+
+    /// Makes `button` auto fire `rate` times a second while it is held,
+    /// `None` turns it back into a normal button
+    pub fn set_turbo(&mut self, controller_index: usize, button: Button, rate: Option<u32>) {
+        self.bus
+            .get_input_mut()
+            .set_turbo(controller_index, button, rate);
+    }
+
+    /// See [InputDevices::start_macro_recording](crate::hardware::input::InputDevices::start_macro_recording)
+    pub fn start_macro_recording(&mut self, controller_index: usize) {
+        self.bus
+            .get_input_mut()
+            .start_macro_recording(controller_index);
+    }
+
+    pub fn stop_macro_recording(&mut self) -> Option<InputMacro> {
+        self.bus.get_input_mut().stop_macro_recording()
+    }
+
+    /// Plays `input_macro` over what the player presses, see
+    /// [InputDevices::play_macro](crate::hardware::input::InputDevices::play_macro)
+    pub fn play_macro(
+        &mut self,
+        controller_index: usize,
+        input_macro: &InputMacro,
+        priority: MacroPriority,
+    ) {
+        self.bus
+            .get_input_mut()
+            .play_macro(controller_index, input_macro, priority);
+    }
+
+    pub fn stop_macro(&mut self) {
+        self.bus.get_input_mut().stop_macro();
+    }
+
+    /// Presses start for the player after every power cycle and cartrige
+    /// insert, to get past the title screen. Off with `None`, which is the
+    /// default. A game's
+    /// [CompatEntry::fast_boot](crate::frontend::compat::CompatEntry::fast_boot)
+    /// is where per-game timings go.
+    pub fn set_fast_boot(&mut self, fast_boot: Option<FastBoot>) {
+        self.fast_boot = fast_boot;
+    }
+
+    pub fn get_fast_boot(&self) -> Option<FastBoot> {
+        self.fast_boot
+    }
+
+    /// See [InputDevices::set_opposite_directions](crate::hardware::input::InputDevices::set_opposite_directions)
+    pub fn set_opposite_directions(&mut self, mode: OppositeDirections) {
+        self.bus.get_input_mut().set_opposite_directions(mode);
+    }
+
+    pub fn get_opposite_directions(&self) -> OppositeDirections {
+        self.bus.get_input().get_opposite_directions()
+    }
+
+    /// See [InputDevices::set_dma_conflicts](crate::hardware::input::InputDevices::set_dma_conflicts),
+    /// turning this off is less accurate but games never drop a button
+    pub fn set_dma_conflicts(&mut self, enabled: bool) {
+        self.bus.get_input_mut().set_dma_conflicts(enabled);
+    }
+
+    pub fn has_dma_conflicts(&self) -> bool {
+        self.bus.get_input().has_dma_conflicts()
+    }
+
+    /// The buttons the game sees as pressed, with turbo already applied.
+    /// This is what should be recorded in movies.
+    pub fn get_buttons(&self, controller_index: usize) -> ControllerState {
+        self.bus.get_input().get_buttons(controller_index)
+    }
+
+    /// `observer` gets told about every write the cpu does, for example
+    /// an [EventViewer](crate::debugger::event_viewer::EventViewer)
+    pub fn add_bus_observer(&mut self, observer: Arc<Mutex<dyn BusObserver>>) {
+        self.bus.add_observer(observer);
+    }
+
+    pub fn clear_bus_observers(&mut self) {
+        self.bus.clear_observers();
+    }
+
+    /// Draws the pressed buttons of a controller over every frame, see
+    /// [InputDisplay]
+    pub fn set_input_display(&mut self, controller_index: usize, enabled: bool) {
+        self.input_display.set_enabled(controller_index, enabled);
+    }
+
+    /// Shapes pushed here get drawn over the next frame, see [Overlay]
+    pub fn get_overlay_mut(&mut self) -> &mut Overlay {
+        &mut self.overlay
+    }
+
+    /// Only does something with a [Device::MicrophoneController] attached
+    pub fn set_microphone(&mut self, active: bool) {
+        self.bus.get_input_mut().set_microphone(active);
+    }
+
+    /// Only does something with a [Device::FamilyBasicKeyboard] attached
+    pub fn set_key(&mut self, row: usize, column: usize, key: usize, pressed: bool) {
+        self.bus.get_input_mut().set_key(row, column, key, pressed);
+    }
+
+    /// Only does something with a [Device::ArkanoidPaddle] attached, see
+    /// [InputDevices::set_paddle](crate::hardware::input::InputDevices::set_paddle)
+    pub fn set_paddle(&mut self, position: u8, fire: bool) {
+        self.bus.get_input_mut().set_paddle(position, fire);
+    }
+
+    /// The cassette recorder of the Family BASIC keyboard, for loading and
+    /// saving tapes
+    pub fn get_data_recorder_mut(&mut self) -> &mut DataRecorder {
+        self.bus.get_input_mut().get_data_recorder_mut()
     }
 
     pub fn is_resetting(&self) -> bool {
-        self.cpu.borrow().is_resetting()
+        self.cpu.lock().unwrap().is_resetting()
     }
 
     pub fn reset(&mut self) {
-        self.cpu.borrow_mut().reset(&self.bus);
+        self.cpu.lock().unwrap().reset(&self.bus);
     }
 
     pub fn reset_with_program_counter(&mut self, program_counter: u16) {
         self.cpu
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .reset_with_program_counter(program_counter);
     }
 
     /// ticks 4 times faster than the real nes would
     /// This means it should be clocked at a frequency of: [MASTER_CLOCK](crate::hardware::constants::clock_rates::MASTER_CLOCK)
     pub fn tick(&mut self) -> Option<(u32, u32, u8, u8)> {
-        let out = self.ppu.borrow_mut().tick();
-        if self.total_cycles % 3 == 0 {
+        if self.overclock_dots > 0 {
+            self.overclock_dots -= 1;
+            if self.is_cpu_dot(self.get_region()) {
+                self.tick_cpu_or_dma();
+                self.cpu_cycles += 1;
+            }
+            self.total_cycles += 1;
+            return None;
+        }
+
+        let (out, position, is_frame_ready, is_video_enabled, region) = {
+            let mut ppu = self.ppu.lock().unwrap();
+            (
+                ppu.tick(),
+                ppu.get_position(),
+                ppu.take_frame_ready(),
+                ppu.is_video_enabled(),
+                ppu.get_region(),
+            )
+        };
+        // the last pixel of a line is drawn on dot 256
+        if position.1 == SCREEN_WIDTH as u32 + 1
+            && position.0 < SCREEN_HEIGHT as u32
+            && is_video_enabled
+            && self.scanline_callback.is_some()
+        {
+            self.run_scanline_callback(position.0);
+        }
+        if !self.hooks.is_empty()
+            && let Some(event) = PpuEvent::at(position, region)
+        {
+            self.run_hooks(event);
+        }
+        if self.pending_cartrige.is_some()
+            && self.cartrige_swap == CartrigeSwap::NextVblank
+            && PpuEvent::at(position, region) == Some(PpuEvent::VblankStart)
+        {
+            // the swap happens with the power off, so nothing else runs
+            // on this dot
+            self.power_cycle();
+            return out;
+        }
+        // overclocking pauses the ppu right before the pre-render line
+        if position == (region.pre_render_line(), 0) {
+            self.overclock_dots = self.overclock_scanlines() * DOTS_PER_SCANLINE as u32;
+        }
+        if is_frame_ready {
+            self.bus.notify_frame_end();
+            if self.input_display.is_any_enabled() {
+                let input = self.bus.get_input();
+                self.input_display.draw(
+                    self.ppu.lock().unwrap().get_last_frame_mut(),
+                    |controller_index| input.get_buttons(controller_index),
+                );
+            }
+            if !self.overlay.is_empty() {
+                self.overlay
+                    .composite(self.ppu.lock().unwrap().get_last_frame_mut());
+            }
+            self.bus.get_input_mut().next_frame();
+            if let Some(callback) = self.frame_callback.as_mut() {
+                callback(self.ppu.lock().unwrap().get_last_frame());
+            }
+        }
+        if self.is_cpu_dot(region) {
             self.apu.lock().unwrap().tick();
-            let mut dma_status = self.cpu.borrow().dma_status.clone();
-            match &mut dma_status {
-                DmaState::None => self.cpu.borrow_mut().tick(&mut self.bus),
-                DmaState::Initializing { page } => {
-                    if self.total_cycles % 2 == 1 {
-                        self.cpu.borrow_mut().dma_status = DmaState::Transfering {
-                            page: *page,
-                            index: 0,
-                            fetched_value: 0,
-                        };
-                    }
-                }
-                DmaState::Transfering {
-                    page,
-                    index,
-                    fetched_value,
-                } => {
-                    if self.total_cycles % 2 == 0 {
-                        *fetched_value = self.bus.read(*index as u16 + *page as u16 * 0x100);
-                        self.cpu.borrow_mut().dma_status = dma_status;
-                    } else {
-                        self.ppu.borrow_mut().oam[*index as usize] = *fetched_value;
-
-                        if *index == 0xFF {
-                            self.cpu.borrow_mut().dma_status = DmaState::None;
-                        } else {
-                            *index += 1;
-                            self.cpu.borrow_mut().dma_status = dma_status;
-                        }
-                    }
-                }
+            self.bus.get_input_mut().tick();
+            self.tick_cartrige();
+            if !self.tick_dmc_dma() {
+                self.tick_cpu_or_dma();
             }
+            self.cpu_cycles += 1;
         }
 
         // if self.total_cycles % 4 == 0 {
-        //     self.ppu.borrow_mut().tick();
+        //     self.ppu.lock().unwrap().tick();
         // }
         // if self.total_cycles % 12 == 0 {
-        //     self.cpu.borrow_mut().tick(&mut self.bus);
+        //     self.cpu.lock().unwrap().tick(&mut self.bus);
         // }
         self.total_cycles += 1;
         out
     }
 
+    fn run_hooks(&mut self, event: PpuEvent) {
+        let (frame, ppu) = {
+            let ppu = self.ppu.lock().unwrap();
+            (ppu.get_frame_count(), ppu.get_registers())
+        };
+        self.hooks.run(&mut PpuHookContext {
+            event,
+            frame,
+            ppu,
+            bus: &mut self.bus,
+        });
+    }
+
+    /// The line is copied out so the callback can peek ppu registers
+    /// without the ppu being locked
+    fn run_scanline_callback(&mut self, line: u32) {
+        let Some(callback) = self.scanline_callback.as_mut() else {
+            return;
+        };
+        let mut pixels = [0; SCREEN_WIDTH];
+        let ppu_registers = {
+            let mut ppu = self.ppu.lock().unwrap();
+            pixels.copy_from_slice(ppu.get_drawing_line_mut(line as usize));
+            ppu.get_registers()
+        };
+        callback(&mut Scanline {
+            line,
+            pixels: &mut pixels,
+            ppu: ppu_registers,
+            bus: &self.bus,
+        });
+        self.ppu
+            .lock()
+            .unwrap()
+            .get_drawing_line_mut(line as usize)
+            .copy_from_slice(&pixels);
+    }
+
+    /// The dmc halts the cpu (and an oam dma) while it fetches a sample
+    /// byte. Returns whether it did on this cycle.
+    fn tick_dmc_dma(&mut self) -> bool {
+        let cycle = self.apu.lock().unwrap().next_dmc_dma_cycle();
+        match cycle {
+            DmcDmaCycle::None => return false,
+            DmcDmaCycle::Halt => {
+                // the halted read gets repeated. The cpu here runs a whole
+                // instruction on its first cycle, so on the last cycle of
+                // one that's its last read, before that it's still reading
+                // its own bytes and otherwise it's the next opcode.
+                let address = {
+                    let cpu = self.cpu.lock().unwrap();
+                    match cpu.get_cycles_left() {
+                        0 => cpu.get_program_counter(),
+                        1 => self.bus.get_last_read(),
+                        _ => self.bus.get_instruction_address(),
+                    }
+                };
+                self.bus.dmc_dma_halt(address);
+            }
+            DmcDmaCycle::Stall => self.bus.get_counters().increment(Counter::DmaStalls),
+            DmcDmaCycle::Fetch(address) => {
+                self.bus.get_counters().increment(Counter::DmaStalls);
+                let sample = self.bus.read(address);
+                self.apu.lock().unwrap().fill_dmc_buffer(sample);
+            }
+        }
+        true
+    }
+
+    fn tick_cpu_or_dma(&mut self) {
+        self.start_oam_dma();
+        let mut dma_status = self.cpu.lock().unwrap().dma_status.clone();
+        if !matches!(dma_status, DmaState::None) {
+            self.bus.get_counters().increment(Counter::DmaStalls);
+        }
+        match &mut dma_status {
+            DmaState::None => self.tick_cpu(),
+            DmaState::Initializing { page } => {
+                if self.cpu_cycles % 2 == 1 {
+                    self.cpu.lock().unwrap().dma_status = DmaState::Transfering {
+                        page: *page,
+                        index: 0,
+                        fetched_value: 0,
+                    };
+                }
+            }
+            DmaState::Transfering {
+                page,
+                index,
+                fetched_value,
+            } => {
+                if self.cpu_cycles.is_multiple_of(2) {
+                    *fetched_value = self.bus.read(*index as u16 + *page as u16 * 0x100);
+                    self.cpu.lock().unwrap().dma_status = dma_status;
+                } else {
+                    self.ppu.lock().unwrap().oam[*index as usize] = *fetched_value;
+
+                    if *index == 0xFF {
+                        self.cpu.lock().unwrap().dma_status = DmaState::None;
+                    } else {
+                        *index += 1;
+                        self.cpu.lock().unwrap().dma_status = dma_status;
+                    }
+                }
+            }
+        }
+        self.start_oam_dma();
+    }
+
+    fn start_oam_dma(&mut self) {
+        if let Some(page) = self.bus.take_oam_dma() {
+            self.cpu.lock().unwrap().dma_status = DmaState::Initializing { page };
+        }
+    }
+
+    /// Mapper irqs are level triggered, the cpu's pending irq follows
+    /// the line
+    fn tick_cartrige(&mut self) {
+        let Some(cartrige) = self.cartrige.as_ref() else {
+            return;
+        };
+        if let Some(line) = cartrige.lock().unwrap().tick() {
+            self.cpu.lock().unwrap().is_triggered_irq = line;
+        }
+    }
+
+    fn tick_cpu(&mut self) {
+        if !self.speed_hacks.skip_idle_loops || self.deterministic {
+            self.cpu.lock().unwrap().tick(&mut self.bus);
+            return;
+        }
+
+        if self.idle_loop.is_idle() {
+            let interrupted = {
+                let cpu = self.cpu.lock().unwrap();
+                cpu.is_triggered_nmi || cpu.is_triggered_irq
+            };
+            if self
+                .idle_loop
+                .should_skip(self.bus.peek(STATUS_REGISTER), interrupted)
+            {
+                return;
+            }
+        }
+
+        let (address, at_instruction_start) = {
+            let cpu = self.cpu.lock().unwrap();
+            (cpu.get_program_counter(), cpu.get_cycles_left() == 0)
+        };
+        let status_reads = self.bus.get_status_reads();
+        self.cpu.lock().unwrap().tick(&mut self.bus);
+        if at_instruction_start && self.bus.get_status_reads() != status_reads {
+            self.idle_loop.on_status_poll(
+                address,
+                self.cpu_cycles,
+                self.bus.get_writes(),
+                self.bus.get_open_bus(),
+                self.bus.peek(STATUS_REGISTER),
+            );
+        }
+    }
+
+    /// The extra cpu only scanlines to run before the pre-render line,
+    /// none while the nes is deterministic
+    fn overclock_scanlines(&self) -> u32 {
+        if self.deterministic {
+            0
+        } else {
+            self.speed_hacks.overclock_scanlines
+        }
+    }
+
+    /// Turns the [speed hacks](crate::devices::speed_hacks) on or off,
+    /// they do nothing while the nes is deterministic
+    pub fn set_speed_hacks(&mut self, speed_hacks: SpeedHacks) {
+        self.speed_hacks = speed_hacks;
+        self.idle_loop.wake();
+    }
+
+    pub fn get_speed_hacks(&self) -> SpeedHacks {
+        self.speed_hacks
+    }
+
+    /// Movies and netplay need every run to see the exact same timing,
+    /// while this is on the speed hacks are ignored
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+        self.idle_loop.wake();
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Seeds everything random in the emulated nes (see
+    /// [entropy](crate::devices::entropy)), takes effect at the next
+    /// [power cycle](Nes::power_cycle). Movies and netplay should store it
+    /// so every run starts from the same ram.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// What the cpu ram gets filled with at the next power cycle
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.ram_init = ram_init;
+    }
+
+    pub fn get_ram_init(&self) -> RamInit {
+        self.ram_init
+    }
+
+    /// Sets the time of day the emulated nes sees, in seconds since the
+    /// unix epoch. It's saved in save states and never read from the host.
+    pub fn set_clock(&mut self, unix_seconds: u64) {
+        let cpu_cycles = self.total_cpu_cycles();
+        self.entropy.clock.set_now(unix_seconds, cpu_cycles);
+    }
+
+    /// The time of day the emulated nes sees, see [Nes::set_clock]
+    pub fn get_clock(&self) -> u64 {
+        self.entropy.clock.now(self.total_cpu_cycles())
+    }
+
+    /// Counts of what the core did since power on, like
+    /// `nes.counters().get(Counter::Nmis)`. The [Arc] can be kept around
+    /// and read from another thread while the nes runs.
+    pub fn counters(&self) -> Arc<Counters> {
+        self.bus.get_counters().clone()
+    }
+
+    /// How many cpu cycles the idle loop skipping saved so far
+    pub fn skipped_idle_cycles(&self) -> u64 {
+        self.idle_loop.get_skipped_cycles()
+    }
+
+    /// Calls `callback` every time the ppu finishes a frame, replacing
+    /// the previous callback. The frame is borrowed straight from the
+    /// ppu so nothing gets copied.
+    pub fn set_frame_callback(&mut self, callback: impl FnMut(&Frame) + Send + 'static) {
+        self.frame_callback = Some(Box::new(callback));
+    }
+
+    pub fn clear_frame_callback(&mut self) {
+        self.frame_callback = None;
+    }
+
+    /// Calls `callback` after every visible scanline is drawn, replacing
+    /// the previous callback. It can draw over the line, e.g. hitboxes
+    /// read from game ram. Nothing gets called while video is disabled.
+    pub fn set_scanline_callback(&mut self, callback: impl FnMut(&mut Scanline) + Send + 'static) {
+        self.scanline_callback = Some(Box::new(callback));
+    }
+
+    pub fn clear_scanline_callback(&mut self) {
+        self.scanline_callback = None;
+    }
+
+    /// Callbacks on any line and on the start and end of vblank, see
+    /// [hooks](crate::devices::hooks)
+    pub fn hooks_mut(&mut self) -> &mut PpuHooks {
+        &mut self.hooks
+    }
+
+    pub fn hooks(&self) -> &PpuHooks {
+        &self.hooks
+    }
+
+    pub fn set_cpu_config(&mut self, config: CpuConfig) {
+        self.cpu.lock().unwrap().set_config(config);
+    }
+
+    pub fn get_cpu_config(&self) -> CpuConfig {
+        self.cpu.lock().unwrap().get_config()
+    }
+
+    /// See [Ppu::set_render_mode]
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.ppu.lock().unwrap().set_render_mode(mode);
+    }
+
+    pub fn get_render_mode(&self) -> RenderMode {
+        self.ppu.lock().unwrap().get_render_mode()
+    }
+
+    /// See [Ppu::set_accuracy]
+    pub fn set_ppu_accuracy(&mut self, accuracy: PpuAccuracy) {
+        self.ppu.lock().unwrap().set_accuracy(accuracy);
+    }
+
+    pub fn get_ppu_accuracy(&self) -> PpuAccuracy {
+        self.ppu.lock().unwrap().get_accuracy()
+    }
+
+    /// Sets every knob of a [profile](crate::devices::profile), like
+    /// `nes.apply_profile(Profile::Fast.into())`
+    pub fn apply_profile(&mut self, settings: ProfileSettings) {
+        self.set_ppu_accuracy(settings.ppu_accuracy);
+        self.apu.lock().unwrap().set_quality(settings.apu_quality);
+        self.set_dma_conflicts(settings.dma_conflicts);
+        self.set_speed_hacks(settings.speed_hacks);
+    }
+
+    /// What the knobs a profile sets are set to right now, see
+    /// [Profile::from_settings](crate::devices::profile::Profile::from_settings)
+    pub fn get_profile_settings(&self) -> ProfileSettings {
+        ProfileSettings {
+            ppu_accuracy: self.get_ppu_accuracy(),
+            apu_quality: self.apu.lock().unwrap().get_quality(),
+            dma_conflicts: self.has_dma_conflicts(),
+            speed_hacks: self.get_speed_hacks(),
+        }
+    }
+
+    /// See [Ppu::set_video_enabled], [Nes::run_frame] leaves its frame
+    /// alone while video is off
+    pub fn set_video_enabled(&mut self, enabled: bool) {
+        self.ppu.lock().unwrap().set_video_enabled(enabled);
+    }
+
+    pub fn is_video_enabled(&self) -> bool {
+        self.ppu.lock().unwrap().is_video_enabled()
+    }
+
+    /// See [Ppu::set_layer_shown]
+    pub fn set_layer_shown(&mut self, layer: Layer, shown: bool) {
+        self.ppu.lock().unwrap().set_layer_shown(layer, shown);
+    }
+
+    pub fn is_layer_shown(&self, layer: Layer) -> bool {
+        self.ppu.lock().unwrap().is_layer_shown(layer)
+    }
+
+    /// See [Ppu::set_sprite_limit_removed]
+    pub fn set_sprite_limit_removed(&mut self, removed: bool) {
+        self.ppu.lock().unwrap().set_sprite_limit_removed(removed);
+    }
+
+    pub fn is_sprite_limit_removed(&self) -> bool {
+        self.ppu.lock().unwrap().is_sprite_limit_removed()
+    }
+
+    /// For a hotkey, returns whether the layer is shown now
+    pub fn toggle_layer(&mut self, layer: Layer) -> bool {
+        let mut ppu = self.ppu.lock().unwrap();
+        let shown = !ppu.is_layer_shown(layer);
+        ppu.set_layer_shown(layer, shown);
+        shown
+    }
+
+    /// The last frame the ppu finished drawing, without copying it
+    pub fn get_last_frame(&self) -> LastFrame<'_> {
+        LastFrame(self.ppu.lock().unwrap())
+    }
+
+    /// Runs until the ppu finishes the current frame and returns it, see
+    /// [Nes::get_last_frame]
+    pub fn next_frame(&mut self) -> LastFrame<'_> {
+        let _span = tracing::trace_span!(target: targets::EMULATOR, "frame").entered();
+        self.run_with(|_, summary| (summary.frames == 1).then_some(BreakReason::FrameDone));
+        self.get_last_frame()
+    }
+
+    /// Same as [Nes::next_frame] but copies the frame into `frame` and
+    /// returns what happened while running
+    pub fn run_frame(&mut self, frame: &mut Frame) -> RunSummary {
+        let _span = tracing::trace_span!(target: targets::EMULATOR, "frame").entered();
+        let summary =
+            self.run_with(|_, summary| (summary.frames == 1).then_some(BreakReason::FrameDone));
+        if summary.break_reason == BreakReason::FrameDone && self.is_video_enabled() {
+            frame.clone_from(&self.get_last_frame());
+        }
+        summary
+    }
+
+    /// Runs `cycles` cpu cycles
+    pub fn run_cycles(&mut self, cycles: u64) -> RunSummary {
+        self.run_with(|_, summary| (summary.cycles >= cycles).then_some(BreakReason::CyclesDone))
+    }
+
+    /// Runs until the cpu starts executing its `instructions`th
+    /// instruction from now, so the summary has exactly `instructions`
+    /// instructions unless the cpu jams
+    pub fn run_instructions(&mut self, instructions: u64) -> RunSummary {
+        self.run_with(|nes, summary| {
+            (summary.instructions >= instructions && nes.is_at_instruction_start())
+                .then_some(BreakReason::InstructionsDone)
+        })
+    }
+
+    /// Runs until the cpu is about to execute the instruction at
+    /// `program_counter`. If it is already there, that instruction gets
+    /// executed first so this can be used to continue from a breakpoint.
+    /// Stops after `max_cycles` cpu cycles so it can't run forever.
+    pub fn run_until_pc(&mut self, program_counter: u16, max_cycles: u64) -> RunSummary {
+        self.run_with(|nes, summary| {
+            if nes.is_at_instruction_start()
+                && nes.cpu.lock().unwrap().get_program_counter() == program_counter
+            {
+                Some(BreakReason::ReachedProgramCounter(program_counter))
+            } else {
+                (summary.cycles >= max_cycles).then_some(BreakReason::CycleLimit)
+            }
+        })
+    }
+
+    /// Runs until `condition` returns true, it gets checked after every
+    /// ppu dot. Stops after `max_cycles` cpu cycles so it can't run forever.
+    pub fn run_until(
+        &mut self,
+        max_cycles: u64,
+        mut condition: impl FnMut(&Nes) -> bool,
+    ) -> RunSummary {
+        self.run_with(|nes, summary| {
+            if condition(nes) {
+                Some(BreakReason::ConditionMet)
+            } else {
+                (summary.cycles >= max_cycles).then_some(BreakReason::CycleLimit)
+            }
+        })
+    }
+
+    /// Cpu cycles since power on, including the ones the cpu spent
+    /// halted for dma. Goes up on every cpu dot so it is exact even in
+    /// the middle of an instruction.
+    pub fn total_cpu_cycles(&self) -> u64 {
+        self.cpu_cycles
+    }
+
+    /// Frames the ppu finished since power on
+    pub fn frame_count(&self) -> u64 {
+        self.ppu.lock().unwrap().get_frame_count()
+    }
+
+    /// The (scanline, dot) the ppu is about to draw, the last scanline
+    /// (261 on ntsc) is the pre-render one
+    pub fn ppu_dot_position(&self) -> (u32, u32) {
+        self.ppu.lock().unwrap().get_position()
+    }
+
+    /// True if the next cpu cycle starts a new instruction
+    pub fn is_at_instruction_start(&self) -> bool {
+        let is_cpu_dot = self.is_cpu_dot(self.get_region());
+        let cpu = self.cpu.lock().unwrap();
+        is_cpu_dot && cpu.get_cycles_left() == 0 && matches!(cpu.dma_status, DmaState::None)
+    }
+
+    /// Ticks the nes until `should_break` returns a reason to stop. It
+    /// gets checked after every tick.
+    fn run_with(
+        &mut self,
+        mut should_break: impl FnMut(&Nes, &RunSummary) -> Option<BreakReason>,
+    ) -> RunSummary {
+        let mut summary = RunSummary {
+            cycles: 0,
+            instructions: 0,
+            frames: 0,
+            break_reason: BreakReason::CyclesDone,
+        };
+
+        let region = self.get_region();
+        loop {
+            let is_cpu_cycle = self.is_cpu_dot(region);
+            let (is_jammed, is_at_instruction_start) = {
+                let cpu = self.cpu.lock().unwrap();
+                (
+                    cpu.is_jammed(),
+                    is_cpu_cycle
+                        && cpu.get_cycles_left() == 0
+                        && matches!(cpu.dma_status, DmaState::None),
+                )
+            };
+            if is_jammed {
+                summary.break_reason = BreakReason::CpuJammed;
+                return summary;
+            }
+
+            if is_at_instruction_start {
+                // the first instruction always runs so running again
+                // continues past the illegal opcode
+                if summary.instructions > 0
+                    && let Some(address) = self.illegal_opcode_break()
+                {
+                    summary.break_reason = BreakReason::IllegalOpcode(address);
+                    return summary;
+                }
+                summary.instructions += 1;
+            }
+
+            self.tick();
+
+            if is_cpu_cycle {
+                summary.cycles += 1;
+            }
+            // scanline 240 is the first one after the visible ones
+            // https://www.nesdev.org/wiki/PPU_rendering#Post-render_scanline_(240)
+            if self.ppu.lock().unwrap().get_position() == (240, 0) {
+                summary.frames += 1;
+            }
+
+            if let Some((wrap, address)) = self.cpu.lock().unwrap().take_stack_wrap() {
+                summary.break_reason = match wrap {
+                    StackWrap::Overflow => BreakReason::StackOverflow(address),
+                    StackWrap::Underflow => BreakReason::StackUnderflow(address),
+                };
+                return summary;
+            }
+
+            if let Some(break_reason) = should_break(self, &summary) {
+                summary.break_reason = break_reason;
+                return summary;
+            }
+        }
+    }
+
+    /// The address of the illegal opcode the cpu is about to run when
+    /// [IllegalOpcodePolicy::Break] is set
+    fn illegal_opcode_break(&self) -> Option<u16> {
+        let cpu = self.cpu.lock().unwrap();
+        let address = cpu.get_program_counter();
+        let config = cpu.get_config();
+        (config.illegal_opcodes == IllegalOpcodePolicy::Break
+            && OpcodeInfo::get_for(config.variant, self.bus.peek(address)).is_illegal)
+            .then_some(address)
+    }
+
+    /// Like [Nes::write_memory] but cartrige addresses get their rom or
+    /// ram changed directly instead of going to the mapper registers, see
+    /// [Cartrige::patch]
+    pub fn patch_memory(&mut self, start: u16, memory: &[u8]) {
+        for (i, value) in memory.iter().enumerate() {
+            let address = start.wrapping_add(i as u16);
+            let patched = address >= CARTRIGE_START
+                && self
+                    .cartrige
+                    .as_ref()
+                    .is_some_and(|cartrige| cartrige.lock().unwrap().patch(address, *value));
+            if !patched {
+                self.bus.write(address, *value);
+            }
+        }
+    }
+
     pub fn write_memory(&mut self, start: u16, memory: &[u8]) {
         for i in 0..memory.len() {
             self.bus.write(start + i as u16, memory[i]);
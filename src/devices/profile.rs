@@ -0,0 +1,118 @@
+//! Named bundles of the knobs that trade accuracy for speed, so a config
+//! can say `profile = "fast"` instead of setting each one. A profile is
+//! only a starting point, the knobs can still be changed one by one after
+//! applying it, [Profile::from_settings] tells if they still match one.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    devices::{error::ProfileError, speed_hacks::SpeedHacks},
+    hardware::{apu::ApuQuality, ppu::scanline::PpuAccuracy},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    /// For weak machines and batch jobs, games with mid line effects and
+    /// the test roms break
+    Fast,
+    /// Every game looks and sounds right, only the speed hacks are on
+    #[default]
+    Balanced,
+    /// As close to the hardware as it gets, for test roms and tas work
+    Accurate,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 3] = [Profile::Fast, Profile::Balanced, Profile::Accurate];
+
+    /// The name used in config files
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Fast => "fast",
+            Profile::Balanced => "balanced",
+            Profile::Accurate => "accurate",
+        }
+    }
+
+    pub fn settings(self) -> ProfileSettings {
+        match self {
+            Profile::Fast => ProfileSettings {
+                ppu_accuracy: PpuAccuracy::Scanline,
+                apu_quality: ApuQuality::Low,
+                dma_conflicts: false,
+                speed_hacks: SpeedHacks {
+                    skip_idle_loops: true,
+                    overclock_scanlines: 0,
+                },
+            },
+            Profile::Balanced => ProfileSettings {
+                ppu_accuracy: PpuAccuracy::Cycle,
+                apu_quality: ApuQuality::High,
+                dma_conflicts: false,
+                speed_hacks: SpeedHacks {
+                    skip_idle_loops: true,
+                    overclock_scanlines: 0,
+                },
+            },
+            Profile::Accurate => ProfileSettings {
+                ppu_accuracy: PpuAccuracy::Cycle,
+                apu_quality: ApuQuality::High,
+                dma_conflicts: true,
+                speed_hacks: SpeedHacks::default(),
+            },
+        }
+    }
+
+    /// The profile `settings` came from, `None` if some knob got changed
+    /// after applying it (a "custom" profile)
+    pub fn from_settings(settings: &ProfileSettings) -> Option<Profile> {
+        Profile::ALL
+            .into_iter()
+            .find(|profile| profile.settings() == *settings)
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = ProfileError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Profile::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| ProfileError::UnknownProfileError(name.to_string()))
+    }
+}
+
+/// Everything a [Profile] sets, see [Nes::apply_profile](crate::Nes::apply_profile)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileSettings {
+    /// See [Ppu::set_accuracy](crate::hardware::ppu::Ppu::set_accuracy)
+    pub ppu_accuracy: PpuAccuracy,
+    /// See [Apu::set_quality](crate::hardware::apu::Apu::set_quality)
+    pub apu_quality: ApuQuality,
+    /// The dummy reads of the dmc dma clocking the controllers, see
+    /// [Nes::set_dma_conflicts](crate::Nes::set_dma_conflicts)
+    pub dma_conflicts: bool,
+    pub speed_hacks: SpeedHacks,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        Profile::default().settings()
+    }
+}
+
+impl From<Profile> for ProfileSettings {
+    fn from(profile: Profile) -> Self {
+        profile.settings()
+    }
+}
@@ -0,0 +1,194 @@
+//! The tv systems the nes was sold for. They run the same cpu and ppu
+//! with different clocks and frame layouts, so a game made for one runs
+//! too fast, too slow or glitches on the others.
+//!
+//! | region | cpu clock    | dots per cpu cycle | lines | vblank starts |
+//! |--------|--------------|--------------------|-------|---------------|
+//! | ntsc   | 1.789773 mhz | 3                  | 262   | line 241      |
+//! | pal    | 1.662607 mhz | 3.2                | 312   | line 241      |
+//! | dendy  | 1.773448 mhz | 3                  | 312   | line 291      |
+//!
+//! https://www.nesdev.org/wiki/Cycle_reference_chart
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    devices::error::RegionError,
+    frontend::compat::CompatDatabase,
+    hardware::{
+        cartrige::{Cartrige, TvSystem},
+        constants::clock_rates::{CPU_CLOCK, FRAME_RATE},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Region {
+    /// North America and Japan
+    #[default]
+    Ntsc,
+    /// Europe and Australia
+    Pal,
+    /// The famiclone sold in Russia, pal timing made to run ntsc games
+    Dendy,
+}
+
+impl Region {
+    pub const ALL: [Region; 3] = [Region::Ntsc, Region::Pal, Region::Dendy];
+
+    /// The name used in config files
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ntsc => "ntsc",
+            Region::Pal => "pal",
+            Region::Dendy => "dendy",
+        }
+    }
+
+    /// `None` for headers that don't say or say the game runs on both
+    pub fn from_tv_system(tv_system: TvSystem) -> Option<Region> {
+        match tv_system {
+            TvSystem::Ntsc => Some(Region::Ntsc),
+            TvSystem::Pal => Some(Region::Pal),
+            TvSystem::Dendy => Some(Region::Dendy),
+            TvSystem::DualCompatible | TvSystem::Unknown(_) => None,
+        }
+    }
+
+    /// Lines in a frame, counting vblank and the pre-render line
+    pub fn scanlines_per_frame(self) -> u32 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The last line of the frame, the one that fetches for line 0
+    pub fn pre_render_line(self) -> u32 {
+        self.scanlines_per_frame() - 1
+    }
+
+    /// The line vblank and the nmi start on. The dendy draws 51 lines of
+    /// nothing first so games timed for the ntsc vblank fit in it.
+    pub fn vblank_line(self) -> u32 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Only the ntsc ppu skips a dot on odd frames
+    pub fn has_odd_frame_skip(self) -> bool {
+        self == Region::Ntsc
+    }
+
+    /// Ppu dots per cpu cycle as `(dots, cycles)`
+    pub fn dots_per_cpu_cycle(self) -> (u64, u64) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+
+    /// The cpu clock in hz, [CPU_CLOCK] for ntsc
+    pub fn cpu_clock(self) -> u64 {
+        match self {
+            Region::Ntsc => CPU_CLOCK,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    /// Rounded like [FRAME_RATE]
+    pub fn frame_rate(self) -> u32 {
+        match self {
+            Region::Ntsc => FRAME_RATE,
+            Region::Pal | Region::Dendy => 50,
+        }
+    }
+
+    /// The apu cycles the frame sequencer steps on, the last one is the
+    /// extra step of the 5 step mode. The dendy uses the ntsc ones.
+    pub fn frame_sequencer_steps(self) -> [usize; 5] {
+        match self {
+            Region::Ntsc | Region::Dendy => [3728, 7456, 11185, 14914, 18640],
+            Region::Pal => [4156, 8313, 12469, 16626, 20782],
+        }
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Region {
+    type Err = RegionError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Region::ALL
+            .into_iter()
+            .find(|region| region.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| RegionError::UnknownRegionError(name.to_string()))
+    }
+}
+
+/// What a config says about the region, like `region = "auto"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionSetting {
+    /// Whatever the game was made for, see [RegionSetting::resolve]
+    #[default]
+    Auto,
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl RegionSetting {
+    /// The region to run `cartrige` in. With [RegionSetting::Auto] the
+    /// game's entry in `database` wins (a user list loaded on top of the
+    /// built in one is where per-game overrides go), then the header,
+    /// and ntsc if neither says.
+    pub fn resolve(self, cartrige: &Cartrige, database: &CompatDatabase) -> Region {
+        match self {
+            RegionSetting::Auto => database
+                .lookup(cartrige)
+                .and_then(|entry| entry.region)
+                .or_else(|| Region::from_tv_system(cartrige.get_header().tv_system()))
+                .unwrap_or_default(),
+            RegionSetting::Ntsc => Region::Ntsc,
+            RegionSetting::Pal => Region::Pal,
+            RegionSetting::Dendy => Region::Dendy,
+        }
+    }
+}
+
+impl Display for RegionSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RegionSetting::Auto => "auto",
+            RegionSetting::Ntsc => "ntsc",
+            RegionSetting::Pal => "pal",
+            RegionSetting::Dendy => "dendy",
+        })
+    }
+}
+
+impl FromStr for RegionSetting {
+    type Err = RegionError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if name.eq_ignore_ascii_case("auto") {
+            return Ok(RegionSetting::Auto);
+        }
+        Ok(match name.parse()? {
+            Region::Ntsc => RegionSetting::Ntsc,
+            Region::Pal => RegionSetting::Pal,
+            Region::Dendy => RegionSetting::Dendy,
+        })
+    }
+}
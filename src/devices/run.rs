@@ -0,0 +1,43 @@
+use serde::Serialize;
+
+/// Why one of the `Nes::run_*` methods stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BreakReason {
+    /// Ran the requested amount of cpu cycles
+    CyclesDone,
+    /// Ran the requested amount of instructions
+    InstructionsDone,
+    /// The ppu finished drawing a frame
+    FrameDone,
+    /// The cpu is about to execute the instruction at this address
+    ReachedProgramCounter(u16),
+    /// The condition passed to `Nes::run_until` was met
+    ConditionMet,
+    /// Hit the cycle limit before anything else happened
+    CycleLimit,
+    /// The cpu executed a JAM instruction and won't run anymore
+    CpuJammed,
+    /// The cpu is about to run the illegal opcode at this address, see
+    /// [IllegalOpcodePolicy::Break](crate::hardware::cpu::IllegalOpcodePolicy::Break)
+    IllegalOpcode(u16),
+    /// A watched address was changed by the last instruction, see
+    /// [Debugger::take_watch_hit](crate::debugger::Debugger::take_watch_hit)
+    Watchpoint(u16),
+    /// The instruction at this address pushed past the bottom of the
+    /// stack, see [StackWrapPolicy::Break](crate::hardware::cpu::StackWrapPolicy::Break)
+    StackOverflow(u16),
+    /// The instruction at this address pulled past the top of the stack
+    StackUnderflow(u16),
+}
+
+/// What happened while running the nes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RunSummary {
+    /// Cpu cycles, there are 3 ppu dots in every one of them
+    pub cycles: u64,
+    /// Instructions the cpu started executing
+    pub instructions: u64,
+    /// Frames the ppu finished drawing
+    pub frames: u64,
+    pub break_reason: BreakReason,
+}
@@ -0,0 +1,95 @@
+//! Speed hacks for slow hosts. They change the timing the game sees so
+//! they are all off by default and get ignored while the nes is
+//! deterministic, see [Nes::set_deterministic](crate::Nes::set_deterministic).
+
+use serde::{Deserialize, Serialize};
+
+/// How many times in a row a loop has to poll $2002 before it counts as idle
+pub const IDLE_LOOP_POLLS: u32 = 4;
+/// Loops longer than this (in cpu cycles) are doing real work
+pub const IDLE_LOOP_MAX_CYCLES: u64 = 12;
+
+/// The bits of $2002 a waiting loop can be looking at
+const STATUS_FLAGS: u8 = 0xE0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SpeedHacks {
+    /// Stops running the cpu while it spins in a tight loop polling $2002
+    /// (waiting for vblank or a sprite 0 hit), the rest of the nes keeps
+    /// going until the status changes or an interrupt comes in
+    pub skip_idle_loops: bool,
+    /// Extra scanlines at the end of vblank where only the cpu runs, the
+    /// ppu and apu wait. Games that lag because their nmi handler runs
+    /// out of time get more of it, like Mesen's overclocking.
+    pub overclock_scanlines: u32,
+}
+
+/// Spots loops like `wait: BIT $2002; BPL wait`: the same instruction
+/// reading $2002 again and again only a few cycles apart with nothing
+/// written in between
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IdleLoopDetector {
+    poll_address: Option<u16>,
+    polls: u32,
+    last_poll_cycle: u64,
+    last_writes: u64,
+    /// the value of $2002 when the cpu went idle
+    idle_status: Option<u8>,
+    skipped_cycles: u64,
+}
+
+impl IdleLoopDetector {
+    /// Called after the instruction at `address` read `value` from $2002,
+    /// `status` is what $2002 holds now
+    pub(crate) fn on_status_poll(
+        &mut self,
+        address: u16,
+        cycle: u64,
+        writes: u64,
+        value: u8,
+        status: u8,
+    ) {
+        let is_same_loop = self.poll_address == Some(address)
+            && cycle - self.last_poll_cycle <= IDLE_LOOP_MAX_CYCLES
+            && writes == self.last_writes;
+        self.polls = if is_same_loop { self.polls + 1 } else { 1 };
+        self.poll_address = Some(address);
+        self.last_poll_cycle = cycle;
+        self.last_writes = writes;
+
+        // if the read cleared the vblank flag the loop is about to exit
+        if self.polls >= IDLE_LOOP_POLLS && value & STATUS_FLAGS == status & STATUS_FLAGS {
+            self.idle_status = Some(status & STATUS_FLAGS);
+        }
+    }
+
+    /// Whether the cpu can skip this cycle, wakes up once `status`
+    /// changes or `interrupted`
+    pub(crate) fn should_skip(&mut self, status: u8, interrupted: bool) -> bool {
+        match self.idle_status {
+            Some(idle_status) if !interrupted && status & STATUS_FLAGS == idle_status => {
+                self.skipped_cycles += 1;
+                true
+            }
+            Some(_) => {
+                self.wake();
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.idle_status.is_some()
+    }
+
+    pub(crate) fn wake(&mut self) {
+        self.idle_status = None;
+        self.poll_address = None;
+        self.polls = 0;
+    }
+
+    pub(crate) fn get_skipped_cycles(&self) -> u64 {
+        self.skipped_cycles
+    }
+}
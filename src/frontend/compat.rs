@@ -0,0 +1,183 @@
+//! How well games run, keyed by the crc32 of the rom (prg and chr, no
+//! header, see [Checksums::rom](crate::hardware::cartrige::Checksums)).
+//! The built in list only has roms that were checked against this
+//! emulator, users can add their own on top. The whole list
+//! (de)serializes with serde so it can be kept in a file and shared as a
+//! `scam compat export` report.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    devices::region::Region,
+    hardware::{cartrige::Cartrige, input::macros::FastBoot},
+    osd::notifications::Notifications,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompatStatus {
+    /// No known differences from real hardware
+    Perfect,
+    /// Can be finished, but something is off
+    Playable,
+    /// Doesn't boot or can't be finished
+    Broken,
+}
+
+impl Display for CompatStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CompatStatus::Perfect => "perfect",
+            CompatStatus::Playable => "playable",
+            CompatStatus::Broken => "broken",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompatEntry {
+    /// Written as a hex string, the way game databases show it
+    #[serde(
+        serialize_with = "serialize_crc32",
+        deserialize_with = "deserialize_crc32"
+    )]
+    pub crc32: u32,
+    pub title: String,
+    pub status: CompatStatus,
+    #[serde(default)]
+    pub issues: Vec<String>,
+    /// What [RegionSetting::Auto](crate::devices::region::RegionSetting)
+    /// runs the game in, for roms with a wrong or missing tv system
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+    /// When start has to be pressed to skip the intro, for
+    /// [Nes::set_fast_boot](crate::Nes::set_fast_boot)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fast_boot: Option<FastBoot>,
+}
+
+impl CompatEntry {
+    /// What to show when the game gets loaded, nothing for games that
+    /// run perfectly
+    pub fn load_message(&self) -> Option<String> {
+        if self.status == CompatStatus::Perfect {
+            return None;
+        }
+        Some(match self.issues.first() {
+            Some(issue) => format!("{}: {issue}", self.status),
+            None => format!("{} in this emulator", self.status),
+        })
+    }
+}
+
+/// For `scam info`
+impl Display for CompatEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} ({:08X}): {}", self.title, self.crc32, self.status)?;
+        for issue in self.issues.iter() {
+            writeln!(f, "  - {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+fn serialize_crc32<S: Serializer>(crc32: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{crc32:08X}"))
+}
+
+fn deserialize_crc32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    u32::from_str_radix(&text, 16).map_err(serde::de::Error::custom)
+}
+
+/// (crc32, title, status, issues)
+const BUILTIN: &[(u32, &str, CompatStatus, &[&str])] =
+    &[(0x158B0388, "nestest", CompatStatus::Perfect, &[])];
+
+/// Serializes as a plain list of entries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<CompatEntry>", into = "Vec<CompatEntry>")]
+pub struct CompatDatabase {
+    entries: BTreeMap<u32, CompatEntry>,
+}
+
+impl From<Vec<CompatEntry>> for CompatDatabase {
+    fn from(entries: Vec<CompatEntry>) -> Self {
+        let mut database = Self::new();
+        database.extend(entries);
+        database
+    }
+}
+
+impl From<CompatDatabase> for Vec<CompatEntry> {
+    fn from(database: CompatDatabase) -> Self {
+        database.entries.into_values().collect()
+    }
+}
+
+impl CompatDatabase {
+    /// An empty database, see [CompatDatabase::builtin]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The entries that ship with the emulator
+    pub fn builtin() -> Self {
+        let mut database = Self::new();
+        database.extend(
+            BUILTIN
+                .iter()
+                .map(|(crc32, title, status, issues)| CompatEntry {
+                    crc32: *crc32,
+                    title: title.to_string(),
+                    status: *status,
+                    issues: issues.iter().map(|issue| issue.to_string()).collect(),
+                    region: None,
+                    fast_boot: None,
+                }),
+        );
+        database
+    }
+
+    /// Replaces the entry with the same crc32, so a user list loaded on
+    /// top of the built in one wins
+    pub fn insert(&mut self, entry: CompatEntry) {
+        self.entries.insert(entry.crc32, entry);
+    }
+
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = CompatEntry>) {
+        for entry in entries {
+            self.insert(entry);
+        }
+    }
+
+    pub fn get(&self, crc32: u32) -> Option<&CompatEntry> {
+        self.entries.get(&crc32)
+    }
+
+    pub fn lookup(&self, cartrige: &Cartrige) -> Option<&CompatEntry> {
+        self.get(cartrige.get_checksums().rom.crc32)
+    }
+
+    /// Sorted by crc32
+    pub fn entries(&self) -> impl Iterator<Item = &CompatEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Tells the player about known issues when `cartrige` gets loaded
+    pub fn notify_on_load(&self, cartrige: &Cartrige, notifications: &mut Notifications) {
+        if let Some(message) = self.lookup(cartrige).and_then(CompatEntry::load_message) {
+            notifications.push(message);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Finds where a replayed movie stops doing what it did when it was
+//! recorded. While recording a [VerificationLog] keeps the hash of the
+//! state after every frame and a whole state every few frames. On replay
+//! [find_desync] binary searches those states for the first one the
+//! replay doesn't reach, then runs that stretch frame by frame to find the
+//! exact frame and traces it.
+//!
+//! Unlike [Lockstep](super::lockstep::Lockstep) the build the movie was
+//! recorded on doesn't have to be around, but there's only one trace to
+//! look at: what the replay ran in the frame where its state first
+//! differed.
+
+use std::fmt::Display;
+
+use crate::{
+    devices::nes::Nes,
+    frontend::lockstep::{self, TraceLine, state_hash},
+    hardware::{input::controller::ControllerState, ppu::frame::Frame},
+    save_state::{self, ParsedState},
+};
+
+/// Frames between the whole states of a [VerificationLog] by default, a
+/// bit over a second
+pub const DEFAULT_INTERVAL: u64 = 64;
+
+/// What a movie recording keeps to check replays against
+#[derive(Debug, Clone)]
+pub struct VerificationLog {
+    interval: u64,
+    /// the state hash after every frame
+    hashes: Vec<u32>,
+    /// the state before frame `index * interval`
+    states: Vec<Vec<u8>>,
+}
+
+impl VerificationLog {
+    /// Starts a log at the current state of `nes`, the first frame run
+    /// after this is frame 0
+    pub fn new(nes: &Nes, interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            hashes: Vec::new(),
+            states: vec![nes.save_state()],
+        }
+    }
+
+    /// Call after every frame of the recording
+    pub fn record_frame(&mut self, nes: &Nes) {
+        let state = nes.save_state();
+        self.hashes.push(state_hash(&state));
+        if (self.hashes.len() as u64).is_multiple_of(self.interval) {
+            self.states.push(state);
+        }
+    }
+
+    pub fn get_interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Frames recorded so far
+    pub fn get_frame_count(&self) -> u64 {
+        self.hashes.len() as u64
+    }
+
+    /// The state hash after `frame`, see [state_hash]
+    pub fn get_hash(&self, frame: u64) -> Option<u32> {
+        self.hashes.get(frame as usize).copied()
+    }
+}
+
+/// The first frame after which the replay didn't match the recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: u64,
+    pub expected_hash: u32,
+    pub actual_hash: u32,
+    /// The save state chunks that differ at the first whole state of the
+    /// recording after the desync, like `CPU` or `PPU`. Empty if the
+    /// desync is after the last one.
+    pub chunks: Vec<String>,
+    /// The last instructions the replay ran in the frame
+    pub trace: Vec<TraceLine>,
+}
+
+impl Display for Desync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "desynced in frame {} (expected {:08X} but got {:08X})",
+            self.frame, self.expected_hash, self.actual_hash
+        )?;
+        if !self.chunks.is_empty() {
+            writeln!(f, "the {} chunks differ", self.chunks.join(", "))?;
+        }
+        for line in &self.trace {
+            writeln!(f, "  {line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays `log` on `nes` with `input` giving the buttons of every frame
+/// (like [Lockstep::run](super::lockstep::Lockstep::run)) and returns the
+/// first frame that doesn't match, or `None` if all of them do. `nes`
+/// should have the same rom inserted, it's left at the end of the desynced
+/// frame. Shows up to `trace_window` instructions.
+///
+/// Like git bisect this expects a desync to stay desynced. Stretches that
+/// matched aren't run again: a state whose hash matches is as good as the
+/// recorded one, so every search step starts from the last recorded state
+/// known to match.
+pub fn find_desync(
+    nes: &mut Nes,
+    log: &VerificationLog,
+    mut input: impl FnMut(u64) -> Vec<ControllerState>,
+    trace_window: usize,
+) -> save_state::Result<Option<Desync>> {
+    let mut frame_buffer = Frame::new();
+    let mut run_frames = |nes: &mut Nes, frames: std::ops::Range<u64>, trace: bool| {
+        let mut last_trace = Vec::new();
+        for frame in frames {
+            lockstep::set_input(nes, &input(frame));
+            if trace {
+                last_trace = lockstep::trace_frame(nes);
+            } else {
+                nes.run_frame(&mut frame_buffer);
+            }
+        }
+        last_trace
+    };
+    let matches =
+        |nes: &Nes, frame: u64| Some(state_hash(&nes.save_state())) == log.get_hash(frame);
+
+    // the index of the last recorded state the replay reaches, and the
+    // first one it doesn't
+    let frame_count = log.get_frame_count();
+    let last_state = (frame_count / log.interval) as usize;
+    let (mut good, mut bad) = (0, last_state + 1);
+    while bad - good > 1 {
+        let middle = (good + bad) / 2;
+        let from = good as u64 * log.interval;
+        let to = middle as u64 * log.interval;
+        nes.load_state(&log.states[good])?;
+        run_frames(nes, from..to, false);
+        if matches(nes, to - 1) {
+            good = middle;
+        } else {
+            bad = middle;
+        }
+    }
+
+    // then frame by frame between them
+    let from = good as u64 * log.interval;
+    let to = (bad as u64 * log.interval).min(frame_count);
+    nes.load_state(&log.states[good])?;
+    let mut before = nes.save_state();
+    for frame in from..to {
+        run_frames(nes, frame..frame + 1, false);
+        if matches(nes, frame) {
+            before = nes.save_state();
+            continue;
+        }
+        let after = nes.save_state();
+        let actual_hash = state_hash(&after);
+
+        let chunks = match log.states.get(bad) {
+            Some(recorded) => {
+                run_frames(nes, frame + 1..bad as u64 * log.interval, false);
+                lockstep::differing_chunks(
+                    &ParsedState::parse(recorded)?,
+                    &ParsedState::parse(&nes.save_state())?,
+                )
+            }
+            None => Vec::new(),
+        };
+
+        nes.load_state(&before)?;
+        let trace = run_frames(nes, frame..frame + 1, true);
+        nes.load_state(&after)?;
+        let start = trace.len().saturating_sub(trace_window);
+        return Ok(Some(Desync {
+            frame,
+            expected_hash: log.hashes[frame as usize],
+            actual_hash,
+            chunks,
+            trace: trace[start..].to_vec(),
+        }));
+    }
+    Ok(None)
+}
@@ -0,0 +1,25 @@
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum KeymapError {
+    #[error("{_0:?} has no key, only modifiers!")]
+    MissingKeyError(String),
+    #[error("{_0:?} has more than one key, only modifiers can be combined!")]
+    TooManyKeysError(String),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum GoldenRunError {
+    #[error("The run was recorded with the rom {expected:08X} but {actual:08X} is inserted!")]
+    RomMismatchError { expected: u32, actual: u32 },
+    #[error("The run needs a cartrige inserted!")]
+    NoCartrigeError,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum InputScriptError {
+    #[error("Couldn't read the input script:\n{_0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Line {_0} of the input script doesn't make sense: {_1:?}!")]
+    InvalidLineError(usize, String),
+    #[error("Line {_0} of the input script has an unknown button {_1:?}!")]
+    UnknownButtonError(usize, String),
+}
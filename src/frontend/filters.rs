@@ -0,0 +1,138 @@
+//! Shader filters for gpu frontends. The frame gets uploaded as a texture
+//! (see [Frame::to_rgba_bytes](crate::hardware::ppu::frame::Frame::to_rgba_bytes)) and [CRT_SHADER] draws it onto the
+//! surface, so the scaling from [ScalingConfig] happens on the gpu
+//! instead of in [ScalingConfig::blit].
+//!
+//! The shader is plain WGSL so it works with wgpu without this crate
+//! depending on it. Its bind group is:
+//! - 0: a uniform buffer filled with [FilterUniforms::to_bytes]
+//! - 1: the current frame texture
+//! - 2: the previous frame texture, for [FilterParams::persistence]
+//! - 3: a nearest neighbour sampler
+//!
+//! The vertex shader makes a triangle covering the whole surface from
+//! the vertex index, draw it with `draw(0..3, 0..1)`.
+
+use crate::{
+    frontend::scaling::ScalingConfig,
+    hardware::constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+
+pub const CRT_SHADER: &str = include_str!("shaders/crt.wgsl");
+pub const VERTEX_ENTRY_POINT: &str = "vs_main";
+pub const FRAGMENT_ENTRY_POINT: &str = "fs_main";
+
+/// The frame texture is the whole frame, [FilterUniforms] picks the part
+/// of it that is shown
+pub const FRAME_TEXTURE_SIZE: (u32, u32) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShaderFilter {
+    /// Sharp pixels, like the software blit
+    #[default]
+    None,
+    /// Dark lines between the lines of the frame
+    Scanlines,
+    /// Scanlines, a curved screen, an aperture grille and phosphor
+    /// persistence
+    Crt,
+}
+
+/// How strong every effect is, 0 turns it off
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterParams {
+    pub scanline_strength: f32,
+    pub curvature: f32,
+    /// How much the aperture grille dims the other two colors of a column
+    pub mask_strength: f32,
+    /// How much of the previous frame still glows
+    pub persistence: f32,
+}
+
+impl FilterParams {
+    pub const NONE: FilterParams = FilterParams {
+        scanline_strength: 0.0,
+        curvature: 0.0,
+        mask_strength: 0.0,
+        persistence: 0.0,
+    };
+}
+
+impl Default for FilterParams {
+    fn default() -> Self {
+        ShaderFilter::default().params()
+    }
+}
+
+impl ShaderFilter {
+    pub fn params(self) -> FilterParams {
+        match self {
+            ShaderFilter::None => FilterParams::NONE,
+            ShaderFilter::Scanlines => FilterParams {
+                scanline_strength: 0.5,
+                ..FilterParams::NONE
+            },
+            ShaderFilter::Crt => FilterParams {
+                scanline_strength: 0.4,
+                curvature: 0.04,
+                mask_strength: 0.25,
+                persistence: 0.35,
+            },
+        }
+    }
+}
+
+/// Everything the shader needs for one surface size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterUniforms {
+    pub source_origin: [f32; 2],
+    pub source_size: [f32; 2],
+    pub viewport_origin: [f32; 2],
+    pub viewport_size: [f32; 2],
+    pub background: [f32; 4],
+    pub params: FilterParams,
+}
+
+impl FilterUniforms {
+    /// Size of the uniform buffer
+    pub const SIZE: usize = 64;
+
+    /// Uses the same cropping and viewport as [ScalingConfig::blit] does
+    /// on a `width`x`height` surface
+    pub fn new(config: &ScalingConfig, params: FilterParams, width: usize, height: usize) -> Self {
+        let (source_x, source_y, source_width, source_height) = config.source_rect();
+        let viewport = config.viewport(width, height);
+        let [_, r, g, b] = config.background.to_be_bytes();
+        Self {
+            source_origin: [source_x as f32, source_y as f32],
+            source_size: [source_width as f32, source_height as f32],
+            viewport_origin: [viewport.x as f32, viewport.y as f32],
+            viewport_size: [viewport.width as f32, viewport.height as f32],
+            background: [r, g, b, 0xFF].map(|channel| channel as f32 / 255.0),
+            params,
+        }
+    }
+
+    /// The uniforms laid out like the `Uniforms` struct of [CRT_SHADER]
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let params = [
+            self.params.scanline_strength,
+            self.params.curvature,
+            self.params.mask_strength,
+            self.params.persistence,
+        ];
+        let mut out = [0; Self::SIZE];
+        let values = self
+            .source_origin
+            .iter()
+            .chain(self.source_size.iter())
+            .chain(self.viewport_origin.iter())
+            .chain(self.viewport_size.iter())
+            .chain(self.background.iter())
+            .chain(params.iter());
+        for (bytes, value) in out.chunks_exact_mut(4).zip(values) {
+            bytes.copy_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+}
@@ -0,0 +1,111 @@
+//! Golden runs for bug reports: the input of every frame and a hash of
+//! the state after it, from power on. It's small enough to attach to an
+//! issue (a few bytes a frame as json) and replaying it on another
+//! machine points at the first frame that came out different, which can
+//! then be looked at with a [Lockstep](super::lockstep::Lockstep) or the
+//! debugger. What `scam record-run game.nes --frames 3000 -o run.json`
+//! would write.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    devices::{entropy::RamInit, nes::Nes},
+    frontend::{
+        error::GoldenRunError,
+        lockstep::{self, state_hash},
+    },
+    hardware::{input::controller::ControllerState, ppu::frame::Frame},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoldenFrame {
+    /// The buttons of every controller, a byte each in the json
+    pub input: Vec<ControllerState>,
+    /// See [state_hash]
+    pub hash: u32,
+}
+
+/// Where a replay stopped matching the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GoldenMismatch {
+    /// Counted from power on, 0 is the first frame
+    pub frame: u64,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoldenRun {
+    /// See [Nes::get_rom_crc32]
+    pub rom_crc32: u32,
+    /// Everything random at power on comes from these, see
+    /// [Nes::set_seed] and [Nes::set_ram_init]
+    pub seed: u64,
+    pub ram_init: RamInit,
+    pub frames: Vec<GoldenFrame>,
+}
+
+impl GoldenRun {
+    /// Power cycles `nes` and runs `frames` frames with the buttons
+    /// `input` gives for every frame, keeping the seed and ram init it
+    /// was set up with. Both this and [GoldenRun::replay] make `nes`
+    /// [deterministic](Nes::set_deterministic).
+    pub fn record(
+        nes: &mut Nes,
+        frames: u64,
+        mut input: impl FnMut(u64) -> Vec<ControllerState>,
+    ) -> Result<Self, GoldenRunError> {
+        let rom_crc32 = nes.get_rom_crc32().ok_or(GoldenRunError::NoCartrigeError)?;
+        nes.set_deterministic(true);
+        nes.power_cycle();
+        let mut frame_buffer = Frame::new();
+        let frames = (0..frames)
+            .map(|frame| {
+                let input = input(frame);
+                lockstep::set_input(nes, &input);
+                nes.run_frame(&mut frame_buffer);
+                GoldenFrame {
+                    input,
+                    hash: state_hash(&nes.save_state()),
+                }
+            })
+            .collect();
+        Ok(Self {
+            rom_crc32,
+            seed: nes.get_seed(),
+            ram_init: nes.get_ram_init(),
+            frames,
+        })
+    }
+
+    /// Power cycles `nes` with the recorded seed and runs the recorded
+    /// input. Stops at the first frame that doesn't match, `nes` is left
+    /// right after it.
+    pub fn replay(&self, nes: &mut Nes) -> Result<Option<GoldenMismatch>, GoldenRunError> {
+        let actual = nes.get_rom_crc32().ok_or(GoldenRunError::NoCartrigeError)?;
+        if actual != self.rom_crc32 {
+            return Err(GoldenRunError::RomMismatchError {
+                expected: self.rom_crc32,
+                actual,
+            });
+        }
+        nes.set_seed(self.seed);
+        nes.set_ram_init(self.ram_init);
+        nes.set_deterministic(true);
+        nes.power_cycle();
+        let mut frame_buffer = Frame::new();
+        for (frame, golden) in self.frames.iter().enumerate() {
+            lockstep::set_input(nes, &golden.input);
+            nes.run_frame(&mut frame_buffer);
+            let actual = state_hash(&nes.save_state());
+            if actual != golden.hash {
+                return Ok(Some(GoldenMismatch {
+                    frame: frame as u64,
+                    expected: golden.hash,
+                    actual,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
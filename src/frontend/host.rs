@@ -0,0 +1,221 @@
+//! The emulation loop written once for every frontend. A frontend only
+//! implements [Frontend] for its window, input and audio and hands it to
+//! [run_frontend] with a [Runner]. [NullFrontend] is the headless one.
+
+use std::io;
+
+use crate::{
+    devices::{machine::Machine, run::BreakReason},
+    frontend::{keymap::Hotkey, runner::Runner},
+    hardware::{
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        input::{controller::Button, paddle},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Button {
+        /// See [Nes::set_button](crate::devices::nes::Nes::set_button)
+        controller_index: usize,
+        button: Button,
+        pressed: bool,
+    },
+    /// Where the mouse is on the surface, it moves the paddle of
+    /// machines that have one and the left button is its fire button
+    Mouse {
+        x: usize,
+        y: usize,
+        left_button: bool,
+    },
+    /// A key of the emulated keyboard, see
+    /// [key_position](crate::hardware::input::keyboard::key_position)
+    Key {
+        row: usize,
+        column: usize,
+        key: usize,
+        pressed: bool,
+    },
+    /// A key of the [Keymap](super::keymap::Keymap) got pressed
+    Hotkey(Hotkey),
+    /// The window got closed or the user asked to quit
+    Quit,
+}
+
+/// Why [run_frontend] returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontendExit {
+    /// The frontend sent [InputEvent::Quit]
+    Quit,
+    /// The machine stopped in the middle of a frame, like when the cpu
+    /// jams or hits a breakpoint
+    Stopped(BreakReason),
+}
+
+/// What a frontend has to do, everything else is in [run_frontend].
+///
+/// Pacing is up to the frontend too: either [Frontend::present_frame]
+/// waits for vsync or [Frontend::push_audio] blocks while its audio
+/// buffer is full. Frontends that wait for vsync should tell how full
+/// their audio buffer is with [Frontend::audio_fill], or it drifts.
+pub trait Frontend {
+    /// Called once before the first frame, for opening the window and
+    /// the audio device
+    fn init(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The size of the surface [Frontend::present_frame] gets, usually
+    /// the size of the window
+    fn surface_size(&self) -> (usize, usize);
+
+    /// Shows a `width`x`height` surface of 0x00RRGGBB pixels
+    fn present_frame(&mut self, surface: &[u32], width: usize, height: usize) -> io::Result<()>;
+
+    /// The input that came in since the last call
+    fn poll_input(&mut self) -> Vec<InputEvent>;
+
+    /// Queues the samples of the last frame, from -1 to 1 at the sample
+    /// rate of the machine
+    fn push_audio(&mut self, samples: &[f32]);
+
+    /// How full the audio buffer is, from 0 to 1, see
+    /// [Runner::set_audio_fill]. `None` for frontends without audio.
+    fn audio_fill(&self) -> Option<f32> {
+        None
+    }
+
+    /// A message for the user, like why the emulation stopped
+    fn show_message(&mut self, message: &str);
+}
+
+/// Runs `runner` with `frontend` until the frontend quits or the machine
+/// stops
+pub fn run_frontend<M: Machine>(
+    runner: &mut Runner<M>,
+    frontend: &mut impl Frontend,
+) -> io::Result<FrontendExit> {
+    frontend.init()?;
+    let mut surface = Vec::new();
+    loop {
+        for event in frontend.poll_input() {
+            match event {
+                InputEvent::Button {
+                    controller_index,
+                    button,
+                    pressed,
+                } => runner
+                    .machine_mut()
+                    .set_button(controller_index, button, pressed),
+                InputEvent::Mouse { x, y, left_button } => {
+                    let (width, height) = frontend.surface_size();
+                    // off the frame the paddle stays where it was
+                    if let Some((x, _)) = runner.scaling.frame_position(x, y, width, height) {
+                        runner
+                            .machine_mut()
+                            .set_paddle(paddle::position_from_x(x), left_button);
+                    }
+                }
+                InputEvent::Key {
+                    row,
+                    column,
+                    key,
+                    pressed,
+                } => runner.machine_mut().set_key(row, column, key, pressed),
+                InputEvent::Hotkey(hotkey) => match hotkey {
+                    Hotkey::Quit => return Ok(FrontendExit::Quit),
+                    Hotkey::Pause => runner.set_paused(!runner.is_paused()),
+                    Hotkey::Reset => runner.machine_mut().reset(),
+                    // the rest need state slots, a recorder or a window,
+                    // which belong to the frontend
+                    _ => {}
+                },
+                InputEvent::Quit => return Ok(FrontendExit::Quit),
+            }
+        }
+
+        let (width, height) = frontend.surface_size();
+        surface.resize(width * height, 0);
+        let summary = runner.step(&mut surface, width, height);
+        frontend.present_frame(&surface, width, height)?;
+        runner.presented()?;
+        if let Some(fill) = frontend.audio_fill() {
+            runner.set_audio_fill(fill);
+        }
+        frontend.push_audio(&runner.take_audio_samples());
+
+        if summary.break_reason != BreakReason::FrameDone {
+            frontend.show_message(&format!("emulation stopped: {:?}", summary.break_reason));
+            return Ok(FrontendExit::Stopped(summary.break_reason));
+        }
+    }
+}
+
+/// A frontend without a window or audio that quits after a set amount of
+/// frames, for headless runs and tests. The last presented surface and
+/// the messages are kept.
+#[derive(Debug, Clone, Default)]
+pub struct NullFrontend {
+    frames_left: u64,
+    frames: u64,
+    surface: Vec<u32>,
+    samples: usize,
+    messages: Vec<String>,
+}
+
+impl NullFrontend {
+    pub fn new(frames: u64) -> Self {
+        Self {
+            frames_left: frames,
+            ..Self::default()
+        }
+    }
+
+    /// Frames presented so far
+    pub fn get_frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn get_surface(&self) -> &[u32] {
+        &self.surface
+    }
+
+    /// Audio samples pushed so far
+    pub fn get_sample_count(&self) -> usize {
+        self.samples
+    }
+
+    pub fn get_messages(&self) -> &[String] {
+        &self.messages
+    }
+}
+
+impl Frontend for NullFrontend {
+    fn surface_size(&self) -> (usize, usize) {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    fn present_frame(&mut self, surface: &[u32], _width: usize, _height: usize) -> io::Result<()> {
+        self.surface.clear();
+        self.surface.extend_from_slice(surface);
+        self.frames += 1;
+        self.frames_left = self.frames_left.saturating_sub(1);
+        Ok(())
+    }
+
+    fn poll_input(&mut self) -> Vec<InputEvent> {
+        if self.frames_left == 0 {
+            vec![InputEvent::Quit]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn push_audio(&mut self, samples: &[f32]) {
+        self.samples += samples.len();
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.messages.push(message.to_string());
+    }
+}
@@ -0,0 +1,246 @@
+//! Input scripts for testing games in ci: which buttons are held on which
+//! frames, without the rest of a movie. Something like "boot, press
+//! start, run 600 frames and hash the screen" is a few lines, and the
+//! script can carry the hash it expects, so a ci job would only have to
+//! pipe it into `scam script game.nes < boot.txt` and check the exit code.
+//!
+//! The text format has one range of frames per line, frames are counted
+//! from power on and ranges include both ends:
+//!
+//! ```text
+//! # press start once the title screen is up
+//! 60-64 start
+//! # p2 picks the controller, p1 is the default
+//! 120-179 p2 right b
+//! frames 600
+//! expect 1A2B3C4D
+//! ```
+//!
+//! [InputScript] also (de)serializes with serde, the same script as json
+//! with the buttons written like in the text:
+//!
+//! ```json
+//! {
+//!   "frames": 600,
+//!   "expect": "1A2B3C4D",
+//!   "input": [
+//!     { "from": 60, "to": 64, "buttons": "start" },
+//!     { "from": 120, "to": 179, "controller": 1, "buttons": "right b" }
+//!   ]
+//! }
+//! ```
+
+use std::io::Read;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    devices::{nes::Nes, run::BreakReason},
+    frontend::{
+        error::InputScriptError,
+        lockstep::{self, state_hash},
+    },
+    hardware::{input::controller::ControllerState, ppu::frame::Frame},
+};
+
+/// Buttons held on every frame from `from` to `to`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptEntry {
+    pub from: u64,
+    pub to: u64,
+    /// 0 is the first controller
+    #[serde(default)]
+    pub controller: usize,
+    /// Written as names like `"right b"`
+    #[serde(
+        serialize_with = "serialize_buttons",
+        deserialize_with = "deserialize_buttons"
+    )]
+    pub buttons: ControllerState,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputScript {
+    /// How many frames to run, up to the last input if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frames: Option<u64>,
+    /// The [screen_hash] of the last frame, written as a hex string
+    #[serde(
+        default,
+        rename = "expect",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_hash",
+        deserialize_with = "deserialize_hash"
+    )]
+    pub expected_hash: Option<u32>,
+    #[serde(default)]
+    pub input: Vec<ScriptEntry>,
+}
+
+/// What running a script ended with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptResult {
+    /// Less than the script's frames if the cpu jammed
+    pub frames: u64,
+    /// See [screen_hash]
+    pub screen_hash: u32,
+    /// See [state_hash]
+    pub state_hash: u32,
+    /// `None` if the script doesn't expect a hash
+    pub passed: Option<bool>,
+}
+
+impl InputScript {
+    /// Reads a whole script in the text format, like one piped into stdin
+    pub fn read(mut reader: impl Read) -> Result<Self, InputScriptError> {
+        let mut script = String::new();
+        reader.read_to_string(&mut script)?;
+        Self::parse(&script)
+    }
+
+    /// Parses the text format, see the [module docs](self)
+    pub fn parse(text: &str) -> Result<Self, InputScriptError> {
+        let mut script = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let Some(first) = words.next() else {
+                continue;
+            };
+            let invalid =
+                || InputScriptError::InvalidLineError(line_number, line.trim().to_string());
+            match first {
+                "frames" => {
+                    let frames = words.next().and_then(|frames| frames.parse().ok());
+                    script.frames = Some(frames.ok_or_else(invalid)?);
+                }
+                "expect" => {
+                    let hash = words
+                        .next()
+                        .and_then(|hash| u32::from_str_radix(hash, 16).ok());
+                    script.expected_hash = Some(hash.ok_or_else(invalid)?);
+                }
+                range => {
+                    let (from, to) = range.split_once('-').unwrap_or((range, range));
+                    let (Ok(from), Ok(to)) = (from.parse(), to.parse()) else {
+                        return Err(invalid());
+                    };
+                    if from > to {
+                        return Err(invalid());
+                    }
+                    let mut words = words.peekable();
+                    let controller = match words.peek().and_then(|word| word.strip_prefix('p')) {
+                        Some(number) => {
+                            let number: usize = number.parse().map_err(|_| invalid())?;
+                            words.next();
+                            number.checked_sub(1).ok_or_else(invalid)?
+                        }
+                        None => 0,
+                    };
+                    let buttons = words
+                        .map(|name| {
+                            name.parse().map_err(|_| {
+                                InputScriptError::UnknownButtonError(line_number, name.to_string())
+                            })
+                        })
+                        .collect::<Result<_, _>>()?;
+                    script.input.push(ScriptEntry {
+                        from,
+                        to,
+                        controller,
+                        buttons,
+                    });
+                }
+            }
+        }
+        Ok(script)
+    }
+
+    /// The buttons of every controller on `frame`, for
+    /// [GoldenRun::record](super::golden::GoldenRun::record) and friends
+    pub fn input(&self, frame: u64) -> Vec<ControllerState> {
+        let mut input = Vec::new();
+        for entry in &self.input {
+            if !(entry.from..=entry.to).contains(&frame) {
+                continue;
+            }
+            if input.len() <= entry.controller {
+                input.resize(entry.controller + 1, ControllerState::empty());
+            }
+            input[entry.controller] |= entry.buttons;
+        }
+        // controllers without input in the script get released
+        input.resize(input.len().max(2), ControllerState::empty());
+        input
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.frames.unwrap_or_else(|| {
+            self.input
+                .iter()
+                .map(|entry| entry.to + 1)
+                .max()
+                .unwrap_or(0)
+        })
+    }
+
+    /// Power cycles `nes` and runs the script on it. Like a
+    /// [GoldenRun](super::golden::GoldenRun) the nes is made
+    /// [deterministic](Nes::set_deterministic) so the hashes come out the
+    /// same on every machine.
+    pub fn run(&self, nes: &mut Nes) -> ScriptResult {
+        nes.set_deterministic(true);
+        nes.power_cycle();
+        let mut frame = Frame::new();
+        let mut frames = 0;
+        while frames < self.get_frame_count() {
+            lockstep::set_input(nes, &self.input(frames));
+            if nes.run_frame(&mut frame).break_reason != BreakReason::FrameDone {
+                break;
+            }
+            frames += 1;
+        }
+        let screen_hash = screen_hash(&frame);
+        ScriptResult {
+            frames,
+            screen_hash,
+            state_hash: state_hash(&nes.save_state()),
+            passed: self.expected_hash.map(|expected| expected == screen_hash),
+        }
+    }
+}
+
+/// A crc32 of the frame as rgb, the same on every machine
+pub fn screen_hash(frame: &Frame) -> u32 {
+    crc32fast::hash(&frame.to_rgb_bytes())
+}
+
+fn serialize_buttons<S: Serializer>(
+    buttons: &ControllerState,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(buttons)
+}
+
+fn deserialize_buttons<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ControllerState, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+fn serialize_hash<S: Serializer>(hash: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+    match hash {
+        Some(hash) => serializer.serialize_str(&format!("{hash:08X}")),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_hash<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    u32::from_str_radix(&text, 16)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
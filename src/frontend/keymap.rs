@@ -0,0 +1,291 @@
+//! Which keys do what outside of the game, shared by every frontend so
+//! they all read the same config. Keys are written like `Ctrl+Shift+F5`,
+//! the frontend turns its own key events into a [KeyCombo] with the same
+//! names (the ones winit and minifb use: `A`, `F5`, `Space`, `Escape`...)
+//! and looks them up with [Keymap::hotkey].
+
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::frontend::error::KeymapError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hotkey {
+    SaveState,
+    LoadState,
+    NextSlot,
+    PreviousSlot,
+    Rewind,
+    FastForward,
+    Pause,
+    FrameAdvance,
+    Reset,
+    Screenshot,
+    ToggleRecording,
+    QuickMenu,
+    Fullscreen,
+    Quit,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 14] = [
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::NextSlot,
+        Hotkey::PreviousSlot,
+        Hotkey::Rewind,
+        Hotkey::FastForward,
+        Hotkey::Pause,
+        Hotkey::FrameAdvance,
+        Hotkey::Reset,
+        Hotkey::Screenshot,
+        Hotkey::ToggleRecording,
+        Hotkey::QuickMenu,
+        Hotkey::Fullscreen,
+        Hotkey::Quit,
+    ];
+
+    /// The name used in config files
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::SaveState => "save_state",
+            Hotkey::LoadState => "load_state",
+            Hotkey::NextSlot => "next_slot",
+            Hotkey::PreviousSlot => "previous_slot",
+            Hotkey::Rewind => "rewind",
+            Hotkey::FastForward => "fast_forward",
+            Hotkey::Pause => "pause",
+            Hotkey::FrameAdvance => "frame_advance",
+            Hotkey::Reset => "reset",
+            Hotkey::Screenshot => "screenshot",
+            Hotkey::ToggleRecording => "toggle_recording",
+            Hotkey::QuickMenu => "quick_menu",
+            Hotkey::Fullscreen => "fullscreen",
+            Hotkey::Quit => "quit",
+        }
+    }
+}
+
+/// A key with the modifiers held with it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyCombo {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// Uppercase so `f5` and `F5` are the same key
+    pub key: String,
+}
+
+impl KeyCombo {
+    /// A key without modifiers
+    pub fn key(key: &str) -> Self {
+        Self {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            key: key.to_uppercase(),
+        }
+    }
+
+    pub fn with_ctrl(mut self) -> Self {
+        self.ctrl = true;
+        self
+    }
+
+    pub fn with_alt(mut self) -> Self {
+        self.alt = true;
+        self
+    }
+
+    pub fn with_shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut combo = KeyCombo::key("");
+        for part in s.split('+').map(str::trim) {
+            match part.to_uppercase().as_str() {
+                "CTRL" | "CONTROL" => combo.ctrl = true,
+                "ALT" => combo.alt = true,
+                "SHIFT" => combo.shift = true,
+                key if combo.key.is_empty() => combo.key = key.to_string(),
+                _ => return Err(KeymapError::TooManyKeysError(s.to_string())),
+            }
+        }
+        if combo.key.is_empty() {
+            return Err(KeymapError::MissingKeyError(s.to_string()));
+        }
+        Ok(combo)
+    }
+}
+
+impl TryFrom<String> for KeyCombo {
+    type Error = KeymapError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<KeyCombo> for String {
+    fn from(combo: KeyCombo) -> Self {
+        combo.to_string()
+    }
+}
+
+impl Display for KeyCombo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.alt, "Alt+"),
+            (self.shift, "Shift+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+/// A key bound to more than one hotkey, only the first one in
+/// [Hotkey::ALL] order gets it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyConflict {
+    pub combo: KeyCombo,
+    pub hotkeys: Vec<Hotkey>,
+}
+
+impl Display for KeyConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.hotkeys.iter().map(|hotkey| hotkey.name()).collect();
+        write!(f, "{} is bound to {}", self.combo, names.join(", "))
+    }
+}
+
+/// Every hotkey with its keys. In a config file it's a table of hotkey
+/// names to lists of keys, hotkeys that aren't in it keep their default
+/// keys and an empty list unbinds one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    from = "BTreeMap<Hotkey, Vec<KeyCombo>>",
+    into = "BTreeMap<Hotkey, Vec<KeyCombo>>"
+)]
+pub struct Keymap {
+    bindings: BTreeMap<Hotkey, Vec<KeyCombo>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let key = KeyCombo::key;
+        let bindings = [
+            (Hotkey::SaveState, vec![key("F5")]),
+            (Hotkey::LoadState, vec![key("F7")]),
+            (Hotkey::NextSlot, vec![key("F6")]),
+            (Hotkey::PreviousSlot, vec![key("F6").with_shift()]),
+            (Hotkey::Rewind, vec![key("Backspace")]),
+            (Hotkey::FastForward, vec![key("Tab")]),
+            (Hotkey::Pause, vec![key("P")]),
+            (Hotkey::FrameAdvance, vec![key("Backslash")]),
+            (Hotkey::Reset, vec![key("R").with_ctrl()]),
+            (Hotkey::Screenshot, vec![key("F12")]),
+            (Hotkey::ToggleRecording, vec![key("F9")]),
+            (Hotkey::QuickMenu, vec![key("Escape"), key("F1")]),
+            (
+                Hotkey::Fullscreen,
+                vec![key("Enter").with_alt(), key("F11")],
+            ),
+            (Hotkey::Quit, vec![key("Q").with_ctrl()]),
+        ];
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl From<BTreeMap<Hotkey, Vec<KeyCombo>>> for Keymap {
+    /// On top of the default keys
+    fn from(overrides: BTreeMap<Hotkey, Vec<KeyCombo>>) -> Self {
+        let mut keymap = Self::default();
+        keymap.bindings.extend(overrides);
+        keymap
+    }
+}
+
+impl From<Keymap> for BTreeMap<Hotkey, Vec<KeyCombo>> {
+    fn from(keymap: Keymap) -> Self {
+        keymap.bindings
+    }
+}
+
+impl Keymap {
+    /// Replaces the keys of `hotkey`
+    pub fn bind(&mut self, hotkey: Hotkey, combos: Vec<KeyCombo>) {
+        self.bindings.insert(hotkey, combos);
+    }
+
+    pub fn get_keys(&self, hotkey: Hotkey) -> &[KeyCombo] {
+        self.bindings.get(&hotkey).map_or(&[], Vec::as_slice)
+    }
+
+    /// The hotkey `combo` does, if a key has more than one the first one
+    /// in [Hotkey::ALL] wins
+    pub fn hotkey(&self, combo: &KeyCombo) -> Option<Hotkey> {
+        Hotkey::ALL
+            .into_iter()
+            .find(|hotkey| self.get_keys(*hotkey).contains(combo))
+    }
+
+    /// Keys bound to more than one hotkey, frontends should warn about
+    /// them after loading the config
+    pub fn conflicts(&self) -> Vec<KeyConflict> {
+        let mut hotkeys_by_combo: BTreeMap<&KeyCombo, Vec<Hotkey>> = BTreeMap::new();
+        for hotkey in Hotkey::ALL {
+            for combo in self.get_keys(hotkey) {
+                let hotkeys = hotkeys_by_combo.entry(combo).or_default();
+                if !hotkeys.contains(&hotkey) {
+                    hotkeys.push(hotkey);
+                }
+            }
+        }
+        hotkeys_by_combo
+            .into_iter()
+            .filter(|(_, hotkeys)| hotkeys.len() > 1)
+            .map(|(combo, hotkeys)| KeyConflict {
+                combo: combo.clone(),
+                hotkeys,
+            })
+            .collect()
+    }
+}
+
+/// The effective mapping as a table, for `scam keys`
+impl Display for Keymap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for hotkey in Hotkey::ALL {
+            let keys: Vec<String> = self
+                .get_keys(hotkey)
+                .iter()
+                .map(|k| k.to_string())
+                .collect();
+            let keys = if keys.is_empty() {
+                "-".to_string()
+            } else {
+                keys.join(", ")
+            };
+            writeln!(f, "{:<18}{keys}", hotkey.name())?;
+        }
+        for conflict in self.conflicts() {
+            writeln!(f, "conflict: {conflict}")?;
+        }
+        Ok(())
+    }
+}
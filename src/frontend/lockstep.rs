@@ -0,0 +1,253 @@
+//! Runs two nes side by side on the same rom and input and compares their
+//! states after every frame, for bisecting accuracy regressions: set one
+//! up with the change and one without (a different [CpuConfig], speed
+//! hacks...) and see the first frame where they stop agreeing.
+//!
+//! Comparing every instruction would be too slow, so the frames are
+//! compared by a hash of the save state. Once a frame differs it gets run
+//! again from the states before it one instruction at a time, which gives
+//! the instructions leading up to the first one that differs.
+//!
+//! [CpuConfig]: crate::hardware::cpu::CpuConfig
+
+use std::fmt::Display;
+
+use crate::{
+    devices::nes::Nes,
+    hardware::{cpu::CpuRegisters, input::controller::ControllerState, ppu::frame::Frame},
+    save_state::{self, ParsedState},
+};
+
+/// How many instructions a [Divergence] shows by default
+pub const DEFAULT_TRACE_WINDOW: usize = 16;
+
+/// One instruction as it was about to run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceLine {
+    pub registers: CpuRegisters,
+    /// See [Nes::total_cpu_cycles]
+    pub cycle: u64,
+    /// Like `LDA $0200 = 00`
+    pub text: String,
+}
+
+impl Display for TraceLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let registers = &self.registers;
+        write!(
+            f,
+            "{:04X}  {:<32} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            registers.program_counter,
+            self.text,
+            registers.accumulator,
+            registers.x,
+            registers.y,
+            registers.status,
+            registers.stack_pointer,
+            self.cycle
+        )
+    }
+}
+
+/// The first frame after which the two nes didn't match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Counted from when the [Lockstep] was made
+    pub frame: u64,
+    /// See [state_hash]
+    pub left_hash: u32,
+    pub right_hash: u32,
+    /// The save state chunks that differ, like `CPU` or `PPU`
+    pub chunks: Vec<String>,
+    /// The instructions before the first one that differs and that one,
+    /// or the last ones of the frame if only the ppu or apu differ
+    pub left_trace: Vec<TraceLine>,
+    pub right_trace: Vec<TraceLine>,
+}
+
+impl Divergence {
+    /// True if the cpus ran something different, not just the other
+    /// parts of the nes
+    pub fn is_in_trace(&self) -> bool {
+        self.left_trace.last() != self.right_trace.last()
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "diverged in frame {} ({:08X} and {:08X}), the {} chunks differ",
+            self.frame,
+            self.left_hash,
+            self.right_hash,
+            self.chunks.join(", ")
+        )?;
+        for (name, trace) in [("left", &self.left_trace), ("right", &self.right_trace)] {
+            writeln!(f, "{name}:")?;
+            for line in trace {
+                writeln!(f, "  {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Lockstep {
+    left: Nes,
+    right: Nes,
+    frame: u64,
+    trace_window: usize,
+    frame_buffer: Frame,
+}
+
+impl Lockstep {
+    /// Both should have the same rom inserted and be in the same state,
+    /// only their configuration should differ
+    pub fn new(left: Nes, right: Nes) -> Self {
+        Self {
+            left,
+            right,
+            frame: 0,
+            trace_window: DEFAULT_TRACE_WINDOW,
+            frame_buffer: Frame::new(),
+        }
+    }
+
+    pub fn set_trace_window(&mut self, instructions: usize) {
+        self.trace_window = instructions.max(1);
+    }
+
+    pub fn left(&self) -> &Nes {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Nes {
+        &self.right
+    }
+
+    pub fn left_mut(&mut self) -> &mut Nes {
+        &mut self.left
+    }
+
+    pub fn right_mut(&mut self) -> &mut Nes {
+        &mut self.right
+    }
+
+    /// Frames run so far
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Runs a frame on both with the buttons of every controller in
+    /// `input`. Returns where they diverged if
+    /// they don't match after it, they are left at the end of the frame.
+    pub fn step(&mut self, input: &[ControllerState]) -> save_state::Result<Option<Divergence>> {
+        let left_before = self.left.save_state();
+        let right_before = self.right.save_state();
+        for nes in [&mut self.left, &mut self.right] {
+            set_input(nes, input);
+            nes.run_frame(&mut self.frame_buffer);
+        }
+        let frame = self.frame;
+        self.frame += 1;
+
+        let left_after = self.left.save_state();
+        let right_after = self.right.save_state();
+        let (left_hash, right_hash) = (state_hash(&left_after), state_hash(&right_after));
+        if left_hash == right_hash {
+            return Ok(None);
+        }
+
+        let chunks = differing_chunks(
+            &ParsedState::parse(&left_after)?,
+            &ParsedState::parse(&right_after)?,
+        );
+
+        self.left.load_state(&left_before)?;
+        self.right.load_state(&right_before)?;
+        let left_trace = trace_frame(&mut self.left);
+        let right_trace = trace_frame(&mut self.right);
+        self.left.load_state(&left_after)?;
+        self.right.load_state(&right_after)?;
+
+        let end = left_trace
+            .iter()
+            .zip(right_trace.iter())
+            .position(|(left, right)| left != right)
+            .map_or(left_trace.len().min(right_trace.len()), |index| index + 1);
+        let start = end.saturating_sub(self.trace_window);
+        Ok(Some(Divergence {
+            frame,
+            left_hash,
+            right_hash,
+            chunks,
+            left_trace: left_trace[start..end.min(left_trace.len())].to_vec(),
+            right_trace: right_trace[start..end.min(right_trace.len())].to_vec(),
+        }))
+    }
+
+    /// Steps up to `frames` frames, `input` gives the buttons for every
+    /// frame. Stops at the first divergence.
+    pub fn run(
+        &mut self,
+        frames: u64,
+        mut input: impl FnMut(u64) -> Vec<ControllerState>,
+    ) -> save_state::Result<Option<Divergence>> {
+        for _ in 0..frames {
+            if let Some(divergence) = self.step(&input(self.frame))? {
+                return Ok(Some(divergence));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Save states end with a crc32 of everything before it, which is a
+/// good enough hash of the whole state
+pub fn state_hash(state: &[u8]) -> u32 {
+    let checksum = &state[state.len().saturating_sub(4)..];
+    u32::from_le_bytes(checksum.try_into().unwrap_or_default())
+}
+
+pub(crate) fn set_input(nes: &mut Nes, input: &[ControllerState]) {
+    for (controller_index, buttons) in input.iter().enumerate() {
+        nes.set_buttons(controller_index, *buttons);
+    }
+}
+
+/// The tags of the chunks in `left` that `right` has different
+pub(crate) fn differing_chunks(left: &ParsedState, right: &ParsedState) -> Vec<String> {
+    left.chunks()
+        .iter()
+        .filter(|chunk| right.chunk(chunk.tag).ok() != Some(chunk.payload.as_slice()))
+        .map(|chunk| String::from_utf8_lossy(&chunk.tag).trim_end().to_string())
+        .collect()
+}
+
+/// Runs a frame one instruction at a time
+pub(crate) fn trace_frame(nes: &mut Nes) -> Vec<TraceLine> {
+    let frame_count = nes.frame_count();
+    // frames end in the middle of instructions
+    if !nes.is_at_instruction_start() {
+        nes.run_until(u64::MAX, |nes| nes.is_at_instruction_start());
+    }
+    let mut trace = Vec::new();
+    while nes.frame_count() == frame_count && !nes.cpu.lock().unwrap().is_jammed() {
+        let (registers, text) = {
+            let cpu = nes.cpu.lock().unwrap();
+            let registers = cpu.get_registers();
+            (
+                registers,
+                cpu.disassemble(&nes.bus, registers.program_counter).text,
+            )
+        };
+        trace.push(TraceLine {
+            registers,
+            cycle: nes.total_cpu_cycles(),
+            text,
+        });
+        nes.run_instructions(1);
+    }
+    trace
+}
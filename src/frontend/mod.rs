@@ -0,0 +1,28 @@
+//! # Frontend helpers
+//!
+//! Everything a frontend needs that isn't tied to a windowing or audio
+//! crate, so every frontend doesn't have to write it again. Frontends
+//! should only have to open a window and implement [host::Frontend].
+
+pub mod compat;
+pub mod desync;
+pub mod error;
+pub mod filters;
+pub mod golden;
+pub mod host;
+pub mod input_script;
+pub mod keymap;
+pub mod lockstep;
+pub mod pipe;
+pub mod practice;
+pub mod runner;
+pub mod scaling;
+pub mod scan;
+pub mod screenshots;
+pub mod slots;
+pub mod speed;
+pub mod terminal;
+pub mod testsuite;
+pub mod watch;
+pub mod wav;
+pub mod window;
@@ -0,0 +1,127 @@
+//! A [Frontend] that streams the raw frames and samples into pipes, for
+//! feeding ffmpeg or anything else that can read raw video and audio
+//! without scamu having an encoder. Both streams have no header:
+//!
+//! - video: every frame as `SCREEN_WIDTH`x`SCREEN_HEIGHT` (256x240) rgb24
+//!   pixels, row by row, at [VIDEO_FRAME_RATE] frames a second
+//! - audio: mono signed 16 bit little endian samples at the sample rate of
+//!   the machine, 44100 by default
+//!
+//! With both going into named pipes:
+//!
+//! ```text
+//! ffmpeg -f rawvideo -pixel_format rgb24 -video_size 256x240 -framerate 60.0988 -i video.pipe \
+//!        -f s16le -ar 44100 -ac 1 -i audio.pipe game.mp4
+//! ```
+
+use std::io::{self, ErrorKind, Write};
+
+use crate::{
+    frontend::host::{Frontend, InputEvent},
+    hardware::constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    trace::targets,
+};
+
+/// The exact ntsc frame rate, for telling the reading end
+pub const VIDEO_FRAME_RATE: f64 = 60.0988;
+
+/// Either stream can be left out. Quits after the frame limit or once a
+/// reader closes its end of the pipe.
+pub struct PipeFrontend<V: Write, A: Write> {
+    video: Option<V>,
+    audio: Option<A>,
+    frames_left: Option<u64>,
+    closed: bool,
+    /// reused so a frame is one write
+    buffer: Vec<u8>,
+}
+
+impl<V: Write, A: Write> PipeFrontend<V, A> {
+    /// `frames` limits how many frames get streamed, `None` streams until
+    /// a pipe closes
+    pub fn new(video: Option<V>, audio: Option<A>, frames: Option<u64>) -> Self {
+        Self {
+            video,
+            audio,
+            frames_left: frames,
+            closed: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// True once a reader closed its pipe
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Flushes both streams and hands them back
+    pub fn finish(mut self) -> io::Result<(Option<V>, Option<A>)> {
+        if let Some(video) = self.video.as_mut() {
+            video.flush()?;
+        }
+        if let Some(audio) = self.audio.as_mut() {
+            audio.flush()?;
+        }
+        Ok((self.video, self.audio))
+    }
+
+    /// A closed pipe just ends the stream, anything else is an error
+    fn check(&mut self, result: io::Result<()>) -> io::Result<()> {
+        match result {
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => {
+                self.closed = true;
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+impl<V: Write, A: Write> Frontend for PipeFrontend<V, A> {
+    fn surface_size(&self) -> (usize, usize) {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    fn present_frame(&mut self, surface: &[u32], _width: usize, _height: usize) -> io::Result<()> {
+        if let Some(frames_left) = self.frames_left.as_mut() {
+            *frames_left = frames_left.saturating_sub(1);
+        }
+        let Some(video) = self.video.as_mut().filter(|_| !self.closed) else {
+            return Ok(());
+        };
+        self.buffer.clear();
+        for pixel in surface {
+            let [_, r, g, b] = pixel.to_be_bytes();
+            self.buffer.extend_from_slice(&[r, g, b]);
+        }
+        let result = video.write_all(&self.buffer);
+        self.check(result)
+    }
+
+    fn poll_input(&mut self) -> Vec<InputEvent> {
+        if self.closed || self.frames_left == Some(0) {
+            vec![InputEvent::Quit]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn push_audio(&mut self, samples: &[f32]) {
+        let Some(audio) = self.audio.as_mut().filter(|_| !self.closed) else {
+            return;
+        };
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect();
+        // the trait has nowhere to return it, any error ends the stream
+        if audio.write_all(&bytes).is_err() {
+            self.closed = true;
+        }
+    }
+
+    /// There's no one to show them to, they go to the log
+    fn show_message(&mut self, message: &str) {
+        tracing::info!(target: targets::EMULATOR, "{message}");
+    }
+}
@@ -0,0 +1,216 @@
+//! Practice points are named save states a speedrunner drops before the
+//! tricks they want to practice, then cycles through with hotkeys instead
+//! of remembering slot numbers. Every point can carry the ram addresses
+//! worth watching while practicing it. All points of a game are kept in
+//! one `<rom name>.practice` file in the save directory.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    devices::nes::Nes,
+    save_state::{
+        self, ChunkTag, ParsedState, SaveState, StateBuilder, StateReader, StateWriter,
+        error::SaveStateError,
+    },
+};
+
+pub const PRACTICE_EXTENSION: &str = "practice";
+const PRACTICE_TAG: ChunkTag = *b"PRAC";
+
+/// A ram address shown while practicing, e.g. a subpixel or a timer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RamWatch {
+    pub label: String,
+    pub address: u16,
+}
+
+impl RamWatch {
+    pub fn new(label: impl Into<String>, address: u16) -> Self {
+        Self {
+            label: label.into(),
+            address,
+        }
+    }
+
+    /// Reads without side effects, see [crate::hardware::cpu_bus::CpuBus::peek]
+    pub fn read(&self, nes: &Nes) -> u8 {
+        nes.bus.peek(self.address)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PracticePoint {
+    pub name: String,
+    pub watches: Vec<RamWatch>,
+    /// Made by [Nes::save_state]
+    pub state: Vec<u8>,
+}
+
+/// The practice points of one game, in the order they were added
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PracticePoints {
+    path: PathBuf,
+    points: Vec<PracticePoint>,
+    current: Option<usize>,
+}
+
+impl PracticePoints {
+    /// Reads the points saved for the rom at `rom_path`, a game without
+    /// a practice file starts with none
+    pub fn open(directory: &Path, rom_path: &Path) -> save_state::Result<Self> {
+        let game = rom_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut practice = Self {
+            path: directory.join(format!("{game}.{PRACTICE_EXTENSION}")),
+            points: Vec::new(),
+            current: None,
+        };
+        match std::fs::read(&practice.path) {
+            Ok(data) => ParsedState::parse(&data)?.load_chunk(PRACTICE_TAG, &mut practice)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+        Ok(practice)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes every point to [PracticePoints::path]
+    pub fn save(&self) -> save_state::Result<()> {
+        if let Some(directory) = self.path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let mut builder = StateBuilder::new();
+        builder.add_chunk(PRACTICE_TAG, self);
+        std::fs::write(&self.path, builder.finish())?;
+        Ok(())
+    }
+
+    /// Drops a point at the current state of `nes`, it becomes the
+    /// current point
+    pub fn add(&mut self, name: impl Into<String>, watches: Vec<RamWatch>, nes: &Nes) -> usize {
+        self.points.push(PracticePoint {
+            name: name.into(),
+            watches,
+            state: nes.save_state(),
+        });
+        self.current = Some(self.points.len() - 1);
+        self.points.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<PracticePoint> {
+        if index >= self.points.len() {
+            return None;
+        }
+        let point = self.points.remove(index);
+        self.current = match self.current {
+            _ if self.points.is_empty() => None,
+            Some(current) if current > index || current == self.points.len() => Some(current - 1),
+            current => current,
+        };
+        Some(point)
+    }
+
+    pub fn points(&self) -> &[PracticePoint] {
+        &self.points
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&PracticePoint> {
+        self.points.get(self.current?)
+    }
+
+    /// Makes `index` the current point and loads it
+    pub fn select(&mut self, index: usize, nes: &mut Nes) -> save_state::Result<&PracticePoint> {
+        let point = self
+            .points
+            .get(index)
+            .ok_or(SaveStateError::InvalidValueError(
+                "practice point",
+                index as u64,
+            ))?;
+        nes.load_state(&point.state)?;
+        self.current = Some(index);
+        Ok(point)
+    }
+
+    /// Loads the current point again, for retrying a trick
+    pub fn reload(&mut self, nes: &mut Nes) -> save_state::Result<Option<&PracticePoint>> {
+        match self.current {
+            Some(index) => self.select(index, nes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Moves to the next point and loads it, wrapping around at the end
+    pub fn next(&mut self, nes: &mut Nes) -> save_state::Result<Option<&PracticePoint>> {
+        if self.points.is_empty() {
+            return Ok(None);
+        }
+        let index = self
+            .current
+            .map_or(0, |current| (current + 1) % self.points.len());
+        self.select(index, nes).map(Some)
+    }
+
+    /// Moves to the previous point and loads it, wrapping around at the
+    /// start
+    pub fn previous(&mut self, nes: &mut Nes) -> save_state::Result<Option<&PracticePoint>> {
+        if self.points.is_empty() {
+            return Ok(None);
+        }
+        let index = self.current.map_or(self.points.len() - 1, |current| {
+            (current + self.points.len() - 1) % self.points.len()
+        });
+        self.select(index, nes).map(Some)
+    }
+}
+
+impl SaveState for PracticePoints {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.points.len() as u32);
+        for point in self.points.iter() {
+            writer.write_bytes(point.name.as_bytes());
+            writer.write_u16(point.watches.len() as u16);
+            for watch in point.watches.iter() {
+                writer.write_bytes(watch.label.as_bytes());
+                writer.write_u16(watch.address);
+            }
+            writer.write_bytes(&point.state);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        let count = reader.read_u32()?;
+        self.points.clear();
+        for _ in 0..count {
+            let name = read_string(reader)?;
+            let mut watches = Vec::new();
+            for _ in 0..reader.read_u16()? {
+                let label = read_string(reader)?;
+                watches.push(RamWatch {
+                    label,
+                    address: reader.read_u16()?,
+                });
+            }
+            self.points.push(PracticePoint {
+                name,
+                watches,
+                state: reader.read_bytes()?.to_vec(),
+            });
+        }
+        self.current = None;
+        Ok(())
+    }
+}
+
+fn read_string(reader: &mut StateReader) -> save_state::Result<String> {
+    Ok(String::from_utf8_lossy(reader.read_bytes()?).into_owned())
+}
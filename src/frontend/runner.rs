@@ -0,0 +1,210 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    devices::{
+        machine::Machine,
+        nes::Nes,
+        run::{BreakReason, RunSummary},
+    },
+    frontend::{
+        scaling::ScalingConfig,
+        speed::{AudioStretch, MAX_SPEED, MIN_SPEED, RateControl, Resampler, Wsola},
+    },
+    hardware::ppu::frame::Frame,
+    osd::performance::{FrameTiming, PerformanceHud, PerformanceStats},
+};
+
+/// Drives a [Machine] (usually a [Nes]) for a frontend, so the windowing code only has to
+/// hand over a surface, present it and pass on the input. One frame goes
+/// like this:
+///
+/// 1. [Runner::step] runs the machine for a frame and draws it (with the osd)
+///    into the surface
+/// 2. the frontend presents the surface
+/// 3. [Runner::presented] records how long all of that took
+pub struct Runner<M: Machine = Nes> {
+    machine: M,
+    frame: Frame,
+    pub scaling: ScalingConfig,
+    pub hud: PerformanceHud,
+    stats: PerformanceStats,
+    /// when the last step started presenting and how long emulating took
+    presenting: Option<(Instant, Duration)>,
+    frame_skip: u32,
+    paused: bool,
+    speed: f32,
+    /// frames owed to the speed, below 1 in slow motion
+    speed_progress: f32,
+    audio_stretch: AudioStretch,
+    resampler: Resampler,
+    wsola: Wsola,
+    rate_control: RateControl,
+}
+
+impl<M: Machine> Runner<M> {
+    pub fn new(machine: M) -> Self {
+        Self {
+            machine,
+            frame: Frame::new(),
+            scaling: ScalingConfig::default(),
+            hud: PerformanceHud::new(),
+            stats: PerformanceStats::new(),
+            presenting: None,
+            frame_skip: 0,
+            paused: false,
+            speed: 1.0,
+            speed_progress: 0.0,
+            audio_stretch: AudioStretch::default(),
+            resampler: Resampler::new(),
+            wsola: Wsola::new(),
+            rate_control: RateControl::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &M {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut M {
+        &mut self.machine
+    }
+
+    pub fn stats(&self) -> &PerformanceStats {
+        &self.stats
+    }
+
+    pub fn stats_mut(&mut self) -> &mut PerformanceStats {
+        &mut self.stats
+    }
+
+    /// How long a frame should take, for frontends that pace themselves
+    /// instead of following the audio. It's a little shorter or longer
+    /// to keep the audio buffer half full, see [Runner::set_audio_fill].
+    pub fn frame_duration(&self) -> Duration {
+        (Duration::from_secs(1) / self.machine.get_frame_rate())
+            .div_f32(1.0 + self.rate_control.get_adjustment())
+    }
+
+    /// How full the frontend's audio buffer is, from 0 to 1, every frame.
+    /// Frontends that don't tell never get their rate changed.
+    pub fn set_audio_fill(&mut self, fill: f32) {
+        self.rate_control.update(fill);
+    }
+
+    pub fn rate_control_mut(&mut self) -> &mut RateControl {
+        &mut self.rate_control
+    }
+
+    /// Runs `frame_skip` extra frames every step without drawing them, for
+    /// hosts too slow to present every frame. It doesn't change what the
+    /// game sees so it's fine for movies and netplay.
+    pub fn set_frame_skip(&mut self, frame_skip: u32) {
+        self.frame_skip = frame_skip;
+    }
+
+    pub fn get_frame_skip(&self) -> u32 {
+        self.frame_skip
+    }
+
+    /// While paused every step shows the last frame again without
+    /// running the machine
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// How fast the game runs compared to a real console, from
+    /// [MIN_SPEED] to [MAX_SPEED]. Every step still presents one frame,
+    /// sped up steps run more than one and in slow motion some steps run
+    /// none. Take the audio with [Runner::take_audio_samples] so it keeps
+    /// up with the speed.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// How the audio gets fit into real time when the speed isn't 1
+    pub fn set_audio_stretch(&mut self, audio_stretch: AudioStretch) {
+        self.audio_stretch = audio_stretch;
+    }
+
+    pub fn get_audio_stretch(&self) -> AudioStretch {
+        self.audio_stretch
+    }
+
+    /// The samples of the machine made to last as long as the frames
+    /// presented since the last call, see [Runner::set_speed]. They also
+    /// get stretched a tiny bit to keep the audio buffer from running dry
+    /// or overflowing, see [Runner::set_audio_fill].
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        let samples = self.machine.take_audio_samples();
+        let rate = 1.0 - self.rate_control.get_adjustment();
+        if self.speed == 1.0 && rate == 1.0 {
+            // so switching back to a different speed starts fresh
+            self.resampler = Resampler::new();
+            self.wsola = Wsola::new();
+            return samples;
+        }
+        match self.audio_stretch {
+            AudioStretch::PreservePitch if self.speed != 1.0 => {
+                self.wsola.process(&samples, self.speed * rate)
+            }
+            // the rate control alone is too small to hear the pitch change
+            _ => self.resampler.process(&samples, self.speed * rate),
+        }
+    }
+
+    /// Runs the machine for a frame (plus the skipped ones, see
+    /// [Runner::set_frame_skip], and the ones [Runner::set_speed] adds or
+    /// takes away) and draws it into a `width`x`height` surface, see
+    /// [ScalingConfig::blit]
+    pub fn step(&mut self, surface: &mut [u32], width: usize, height: usize) -> RunSummary {
+        let start = Instant::now();
+        if !self.paused {
+            self.speed_progress += self.speed;
+        }
+        let frames = self.speed_progress.floor();
+        self.speed_progress -= frames;
+        let frames = frames as u32 * (1 + self.frame_skip);
+
+        // pausing and slow motion show the last frame again
+        let mut summary = RunSummary {
+            cycles: 0,
+            instructions: 0,
+            frames: 0,
+            break_reason: BreakReason::FrameDone,
+        };
+        for _ in 0..frames {
+            summary = self.machine.run_frame(&mut self.frame);
+            if summary.break_reason != BreakReason::FrameDone {
+                break;
+            }
+        }
+        let emulation = start.elapsed();
+
+        let present_start = Instant::now();
+        self.hud.draw(&mut self.frame, &self.stats);
+        self.scaling.blit(&self.frame, surface, width, height);
+        self.presenting = Some((present_start, emulation));
+        summary
+    }
+
+    /// Has to be called after the surface from [Runner::step] got
+    /// presented, does nothing if there wasn't a step before
+    pub fn presented(&mut self) -> std::io::Result<()> {
+        let Some((present_start, emulation)) = self.presenting.take() else {
+            return Ok(());
+        };
+        self.stats.record(FrameTiming {
+            emulation,
+            present: present_start.elapsed(),
+            audio_fill: self.machine.get_audio_queue_fill(),
+        })
+    }
+}
@@ -0,0 +1,159 @@
+use crate::hardware::{
+    constants::ppu::{NTSC_PIXEL_ASPECT_RATIO, SCREEN_HEIGHT, SCREEN_WIDTH},
+    ppu::frame::Frame,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalingMode {
+    /// Only scales by whole numbers so every pixel has the same size,
+    /// the rest of the surface is black
+    #[default]
+    Integer,
+    /// As big as possible while keeping the aspect ratio
+    Fit,
+    /// Fills the whole surface
+    Stretch,
+}
+
+/// How many pixels to cut off every side of the frame. Most tvs didn't
+/// show the edges so games often have garbage there.
+/// https://www.nesdev.org/wiki/Overscan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    /// What most ntsc tvs cut off
+    pub const NTSC: Overscan = Overscan {
+        top: 8,
+        bottom: 8,
+        left: 0,
+        right: 0,
+    };
+}
+
+/// Where on the surface the frame ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScalingConfig {
+    pub mode: ScalingMode,
+    /// Draws the pixels 8:7 wide like an ntsc tv does
+    pub correct_aspect_ratio: bool,
+    pub overscan: Overscan,
+    /// Color of the bars around the frame
+    pub background: u32,
+}
+
+impl ScalingConfig {
+    /// The part of the frame that is left after cropping, as
+    /// (x, y, width, height). Crops that are too big are clamped so at
+    /// least one pixel is left.
+    pub fn source_rect(&self) -> (usize, usize, usize, usize) {
+        let left = self.overscan.left.min(SCREEN_WIDTH - 1);
+        let top = self.overscan.top.min(SCREEN_HEIGHT - 1);
+        let width = (SCREEN_WIDTH - left)
+            .saturating_sub(self.overscan.right)
+            .max(1);
+        let height = (SCREEN_HEIGHT - top)
+            .saturating_sub(self.overscan.bottom)
+            .max(1);
+        (left, top, width, height)
+    }
+
+    /// Where the frame goes on a `width`x`height` surface, centered
+    pub fn viewport(&self, width: usize, height: usize) -> Viewport {
+        let (_, _, source_width, source_height) = self.source_rect();
+        let pixel_aspect = if self.correct_aspect_ratio {
+            NTSC_PIXEL_ASPECT_RATIO
+        } else {
+            1.0
+        };
+        let display_width = source_width as f64 * pixel_aspect;
+        let fit_scale = (width as f64 / display_width).min(height as f64 / source_height as f64);
+
+        let (out_width, out_height) = match self.mode {
+            ScalingMode::Stretch => (width, height),
+            // falls back to fit when the surface is smaller than the frame
+            ScalingMode::Integer if fit_scale >= 1.0 => {
+                let scale = fit_scale.floor();
+                (
+                    ((display_width * scale).round() as usize).min(width),
+                    source_height * scale as usize,
+                )
+            }
+            ScalingMode::Integer | ScalingMode::Fit => (
+                (display_width * fit_scale).round() as usize,
+                (source_height as f64 * fit_scale).round() as usize,
+            ),
+        };
+
+        Viewport {
+            x: (width - out_width) / 2,
+            y: (height - out_height) / 2,
+            width: out_width,
+            height: out_height,
+        }
+    }
+
+    /// The frame pixel under (`x`, `y`) of a `width`x`height` surface, for
+    /// mouse input. `None` on the bars around the frame.
+    pub fn frame_position(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        let viewport = self.viewport(width, height);
+        let x = x.checked_sub(viewport.x).filter(|x| *x < viewport.width)?;
+        let y = y.checked_sub(viewport.y).filter(|y| *y < viewport.height)?;
+        let (source_x, source_y, source_width, source_height) = self.source_rect();
+        Some((
+            source_x + x * source_width / viewport.width,
+            source_y + y * source_height / viewport.height,
+        ))
+    }
+
+    /// Draws `frame` onto a `width`x`height` surface of 0x00RRGGBB pixels
+    /// (like a softbuffer buffer) with nearest neighbour scaling
+    pub fn blit(&self, frame: &Frame, surface: &mut [u32], width: usize, height: usize) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let surface = &mut surface[..width * height];
+        let viewport = self.viewport(width, height);
+        let (source_x, source_y, source_width, source_height) = self.source_rect();
+
+        let columns: Vec<usize> = (0..viewport.width)
+            .map(|x| source_x + x * source_width / viewport.width)
+            .collect();
+
+        for (y, row) in surface.chunks_exact_mut(width).enumerate() {
+            if y < viewport.y || y >= viewport.y + viewport.height {
+                row.fill(self.background);
+                continue;
+            }
+            let frame_y = source_y + (y - viewport.y) * source_height / viewport.height;
+
+            row[..viewport.x].fill(self.background);
+            row[viewport.x + viewport.width..].fill(self.background);
+            for (pixel, frame_x) in row[viewport.x..viewport.x + viewport.width]
+                .iter_mut()
+                .zip(columns.iter())
+            {
+                *pixel = frame.get_pixel(*frame_x, frame_y);
+            }
+        }
+    }
+}
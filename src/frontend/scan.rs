@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::hardware::{
+    cartrige::{Header, RomInfo},
+    constants::cartrige::HEADER_SIZE,
+};
+
+pub const ROM_EXTENSION: &str = "nes";
+
+/// The roms of one mapper
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MapperGroup {
+    pub mapper_id: u16,
+    pub mapper_name: Option<&'static str>,
+    pub is_supported: bool,
+    pub roms: Vec<PathBuf>,
+}
+
+/// A file that looked like a rom but whose header couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanFailure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// How much of a rom collection the emulator can run, made for a
+/// `scam scan <dir>` command. Only the headers are read so big
+/// collections get scanned quickly. Printing the report with
+/// [Display] gives a table grouped by mapper.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ScanReport {
+    /// sorted by mapper id
+    pub groups: Vec<MapperGroup>,
+    pub failures: Vec<ScanFailure>,
+}
+
+impl ScanReport {
+    /// Walks `directory` and everything under it for `.nes` files
+    pub fn scan(directory: &Path) -> std::io::Result<Self> {
+        let mut paths = Vec::new();
+        Self::find_roms(directory, &mut paths)?;
+        paths.sort();
+
+        let mut groups = BTreeMap::<u16, MapperGroup>::new();
+        let mut failures = Vec::new();
+        for path in paths {
+            let info = Self::read_header(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| Header::from_bytes(&bytes).map_err(|err| err.to_string()))
+                .map(|header| RomInfo::from_header(&header));
+            match info {
+                Ok(info) => groups
+                    .entry(info.mapper_id)
+                    .or_insert_with(|| MapperGroup {
+                        mapper_id: info.mapper_id,
+                        mapper_name: info.mapper_name,
+                        is_supported: info.is_mapper_supported,
+                        roms: Vec::new(),
+                    })
+                    .roms
+                    .push(path),
+                Err(error) => failures.push(ScanFailure { path, error }),
+            }
+        }
+
+        Ok(Self {
+            groups: groups.into_values().collect(),
+            failures,
+        })
+    }
+
+    fn read_header(path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        File::open(path)?
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn find_roms(directory: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::find_roms(&path, out)?;
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case(ROM_EXTENSION))
+            {
+                out.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Roms with a readable header
+    pub fn rom_count(&self) -> usize {
+        self.groups.iter().map(|group| group.roms.len()).sum()
+    }
+
+    pub fn supported_count(&self) -> usize {
+        self.groups
+            .iter()
+            .filter(|group| group.is_supported)
+            .map(|group| group.roms.len())
+            .sum()
+    }
+}
+
+impl Display for ScanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let percent = match self.rom_count() {
+            0 => 0,
+            count => self.supported_count() * 100 / count,
+        };
+        writeln!(
+            f,
+            "{} roms, {} supported ({percent}%)",
+            self.rom_count(),
+            self.supported_count()
+        )?;
+        for group in self.groups.iter() {
+            writeln!(
+                f,
+                "mapper {:>3} {:<14} {:>5} roms  {}",
+                group.mapper_id,
+                group.mapper_name.unwrap_or("?"),
+                group.roms.len(),
+                if group.is_supported {
+                    "supported"
+                } else {
+                    "missing"
+                }
+            )?;
+        }
+        if !self.failures.is_empty() {
+            writeln!(f, "{} files couldn't be read:", self.failures.len())?;
+            for failure in self.failures.iter() {
+                writeln!(f, "  {}: {}", failure.path.display(), failure.error)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! Saves frames as png files, without pulling in an image crate: the
+//! image data is stored uncompressed, which makes bigger files but they
+//! open everywhere. Meant for screenshots and for dumping every so many
+//! frames of a headless run, with a deterministic nes two runs of the
+//! same build give the exact same files so visual regressions can be
+//! bisected by comparing them.
+//! https://www.w3.org/TR/png/
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    devices::{machine::Machine, run::BreakReason},
+    hardware::{
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        ppu::frame::Frame,
+    },
+};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// The most a stored deflate block can hold
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+/// Writes `frame` as an 8 bit rgb png
+pub fn write_png(frame: &Frame, writer: impl Write) -> io::Result<()> {
+    write_rgb_png(SCREEN_WIDTH, SCREEN_HEIGHT, &frame.to_rgb_bytes(), writer)
+}
+
+/// Writes any picture as an 8 bit rgb png, `rgb` is `[r, g, b, ...]` row
+/// by row
+pub fn write_rgb_png(
+    width: usize,
+    height: usize,
+    rgb: &[u8],
+    mut writer: impl Write,
+) -> io::Result<()> {
+    writer.write_all(&PNG_SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, rgb, deflate, no filters, not interlaced
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut writer, b"IHDR", &header)?;
+
+    let mut scanlines = Vec::with_capacity(rgb.len() + height);
+    for row in rgb.chunks(width * 3) {
+        // every row starts with its filter type, 0 is none
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+    write_chunk(&mut writer, b"IDAT", &zlib_stored(&scanlines))?;
+    write_chunk(&mut writer, b"IEND", &[])?;
+    writer.flush()
+}
+
+/// Saves `frame` as a png at `path`
+pub fn save_png(frame: &Frame, path: impl AsRef<Path>) -> io::Result<()> {
+    write_png(frame, BufWriter::new(File::create(path)?))
+}
+
+fn write_chunk(writer: &mut impl Write, tag: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(tag)?;
+    writer.write_all(data)?;
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(tag);
+    hasher.update(data);
+    writer.write_all(&hasher.finalize().to_be_bytes())
+}
+
+/// A zlib stream with every block stored as is
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / STORED_BLOCK_SIZE * 5 + 11);
+    // deflate with a 32k window, no dictionary, the lowest compression
+    out.extend_from_slice(&[0x78, 0x01]);
+    let mut blocks = data.chunks(STORED_BLOCK_SIZE).peekable();
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Runs `machine` without any frontend for `frames` frames and saves
+/// every `every`th one into `out_dir` as `frame_00060.png` and so on,
+/// counting frames from 1. Returns the paths of the saved files. Stops
+/// early if the machine stops running (like when the cpu jams).
+pub fn render_frames(
+    machine: &mut impl Machine,
+    frames: u64,
+    every: u64,
+    out_dir: impl AsRef<Path>,
+) -> io::Result<Vec<PathBuf>> {
+    let out_dir = out_dir.as_ref();
+    fs::create_dir_all(out_dir)?;
+    let every = every.max(1);
+    let mut frame = Frame::new();
+    let mut saved = Vec::new();
+    for number in 1..=frames {
+        let summary = machine.run_frame(&mut frame);
+        // nothing listens for the audio
+        machine.take_audio_samples();
+        if summary.break_reason != BreakReason::FrameDone {
+            break;
+        }
+        if number % every == 0 {
+            let path = out_dir.join(format!("frame_{number:05}.png"));
+            save_png(&frame, &path)?;
+            saved.push(path);
+        }
+    }
+    Ok(saved)
+}
@@ -0,0 +1,72 @@
+// Draws the nes frame onto the whole surface with an optional crt look.
+// The uniforms are written by `FilterUniforms::to_bytes` in filters.rs,
+// keep the two in sync.
+
+struct Uniforms {
+    // the part of the frame texture that is shown, in texels
+    source_origin: vec2<f32>,
+    source_size: vec2<f32>,
+    // where the frame goes on the surface, in pixels
+    viewport_origin: vec2<f32>,
+    viewport_size: vec2<f32>,
+    background: vec4<f32>,
+    // 0 turns the effect off
+    scanline_strength: f32,
+    curvature: f32,
+    mask_strength: f32,
+    // the previous frame mixed in to fake phosphor decay
+    persistence: f32,
+};
+
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+@group(0) @binding(1) var frame_texture: texture_2d<f32>;
+@group(0) @binding(2) var previous_texture: texture_2d<f32>;
+@group(0) @binding(3) var frame_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// one triangle covering the whole surface, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// bends the picture like the glass of a crt, `uv` goes from 0 to 1
+fn curve(uv: vec2<f32>) -> vec2<f32> {
+    let centered = uv * 2.0 - 1.0;
+    let offset = centered.yx * centered.yx * uniforms.curvature;
+    return (centered + centered * offset) * 0.5 + 0.5;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var uv = (in.position.xy - uniforms.viewport_origin) / uniforms.viewport_size;
+    uv = curve(uv);
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return uniforms.background;
+    }
+
+    let texture_size = vec2<f32>(textureDimensions(frame_texture));
+    let texel = uniforms.source_origin + uv * uniforms.source_size;
+    let texture_uv = texel / texture_size;
+    var color = textureSample(frame_texture, frame_sampler, texture_uv).rgb;
+    let previous = textureSample(previous_texture, frame_sampler, texture_uv).rgb;
+    color = max(color, previous * uniforms.persistence);
+
+    // darkest between two lines of the frame
+    let line = fract(texel.y) - 0.5;
+    color *= 1.0 - uniforms.scanline_strength * 4.0 * line * line;
+
+    // every column of the surface only lets one color through fully
+    let column = u32(in.position.x) % 3u;
+    var mask = vec3<f32>(1.0 - uniforms.mask_strength);
+    mask[column] = 1.0;
+    color *= mask;
+
+    return vec4<f32>(color, 1.0);
+}
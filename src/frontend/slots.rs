@@ -0,0 +1,76 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    devices::nes::Nes,
+    save_state::{self, error::SaveStateError, metadata::StateMetadata},
+};
+
+pub const SLOT_COUNT: usize = 10;
+pub const SLOT_EXTENSION: &str = "ss";
+
+/// The save state slots of one game. Slot `n` is stored as `<rom name>.ss<n>`
+/// in the save directory, every state has a timestamp and a thumbnail so
+/// [crate::osd::slot_picker::SlotPicker] can show what's in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveSlots {
+    directory: PathBuf,
+    game: String,
+}
+
+impl SaveSlots {
+    pub fn new(directory: &Path, rom_path: &Path) -> Self {
+        let game = rom_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            directory: directory.to_path_buf(),
+            game,
+        }
+    }
+
+    pub fn slot_path(&self, slot: usize) -> PathBuf {
+        self.directory
+            .join(format!("{}.{SLOT_EXTENSION}{slot}", self.game))
+    }
+
+    pub fn save(&self, slot: usize, nes: &Nes) -> save_state::Result<()> {
+        Self::check_slot(slot)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        std::fs::create_dir_all(&self.directory)?;
+        std::fs::write(
+            self.slot_path(slot),
+            nes.save_state_with_metadata(timestamp),
+        )?;
+        Ok(())
+    }
+
+    pub fn load(&self, slot: usize, nes: &mut Nes) -> save_state::Result<()> {
+        Self::check_slot(slot)?;
+        nes.load_state(&std::fs::read(self.slot_path(slot))?)
+    }
+
+    /// `None` for empty slots and slots that can't be read
+    pub fn metadata(&self, slot: usize) -> Option<StateMetadata> {
+        let data = std::fs::read(self.slot_path(slot)).ok()?;
+        StateMetadata::from_state(&data).ok().flatten()
+    }
+
+    /// The metadata of every slot in order, see [SaveSlots::metadata]
+    pub fn list(&self) -> Vec<Option<StateMetadata>> {
+        (0..SLOT_COUNT).map(|slot| self.metadata(slot)).collect()
+    }
+
+    fn check_slot(slot: usize) -> save_state::Result<()> {
+        if slot >= SLOT_COUNT {
+            return Err(SaveStateError::InvalidValueError("slot", slot as u64));
+        }
+        Ok(())
+    }
+}
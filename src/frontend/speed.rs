@@ -0,0 +1,220 @@
+//! Fits the audio of a sped up or slowed down [Runner](super::runner::Runner)
+//! into real time. Resampling is cheap but changes the pitch with the
+//! speed, WSOLA (waveform similarity overlap-add) keeps the pitch by
+//! repeating or dropping whole pieces of the waveform where they line up,
+//! which is what makes slow motion practice bearable to listen to.
+//! https://www.surina.net/article/time-and-pitch-scaling.html
+//!
+//! At normal speed the audio still drifts: the monitor doesn't refresh at
+//! exactly the nes frame rate and the sound card doesn't play at exactly
+//! its sample rate, so the audio buffer slowly fills up or runs dry.
+//! [RateControl] nudges the rate by a fraction of a percent to keep it
+//! half full.
+//! https://docs.libretro.com/development/cores/dynamic-rate-control/
+
+use std::f32::consts::PI;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 4.0;
+
+/// The most [RateControl] changes the rate by, half a percent is too
+/// little to hear the pitch change
+pub const MAX_RATE_DEVIATION: f32 = 0.005;
+/// How much of a new fill level goes into the average, single frames
+/// jump around with the audio callbacks
+const FILL_SMOOTHING: f32 = 0.1;
+
+/// Samples in a WSOLA piece, about 12ms at 44100Hz
+const WINDOW: usize = 512;
+/// Pieces overlap by half
+const HOP: usize = WINDOW / 2;
+/// How far a piece can move from where it should be to line up better
+const SEARCH: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioStretch {
+    /// Plays the samples faster or slower, the pitch goes with the speed
+    #[default]
+    Resample,
+    /// Keeps the pitch, costs a bit more and smears short sounds a little
+    PreservePitch,
+}
+
+/// Linear interpolation that carries its position over from one batch of
+/// samples to the next
+#[derive(Debug, Clone, Default)]
+pub struct Resampler {
+    position: f64,
+    last: f32,
+}
+
+impl Resampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turns `samples` into about `samples.len() / speed` samples
+    pub fn process(&mut self, samples: &[f32], speed: f32) -> Vec<f32> {
+        let mut out = Vec::with_capacity((samples.len() as f32 / speed) as usize + 1);
+        // position 0 is the last sample of the previous batch
+        let at = |index: usize| {
+            if index == 0 {
+                self.last
+            } else {
+                samples[index - 1]
+            }
+        };
+        while self.position < samples.len() as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let (a, b) = (at(index), at(index + 1));
+            out.push(a + (b - a) * fraction);
+            self.position += speed as f64;
+        }
+        self.position -= samples.len() as f64;
+        if let Some(last) = samples.last() {
+            self.last = *last;
+        }
+        out
+    }
+}
+
+/// Streaming WSOLA, it holds on to a few pieces worth of samples until
+/// it has enough to pick the next piece
+#[derive(Debug, Clone)]
+pub struct Wsola {
+    input: Vec<f32>,
+    /// where the next piece should start in `input`, if the speed were
+    /// followed exactly
+    position: f64,
+    /// what came after the last piece, the next one should look like it
+    continuation: Option<Vec<f32>>,
+    /// the second half of the last piece, waiting for the next one to be
+    /// added on top of it
+    overlap: Vec<f32>,
+    window: Vec<f32>,
+}
+
+impl Default for Wsola {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wsola {
+    pub fn new() -> Self {
+        Self {
+            input: Vec::new(),
+            position: SEARCH as f64,
+            continuation: None,
+            overlap: vec![0.0; HOP],
+            // a periodic hann window, two of them half overlapped add up to 1
+            window: (0..WINDOW)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / WINDOW as f32).cos())
+                .collect(),
+        }
+    }
+
+    /// Turns `samples` into about `samples.len() / speed` samples with the
+    /// same pitch, a few milliseconds later
+    pub fn process(&mut self, samples: &[f32], speed: f32) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        let mut out = Vec::new();
+        loop {
+            let nominal = self.position.round() as usize;
+            // enough to search around the piece and to keep what follows it
+            if nominal + SEARCH + HOP + WINDOW > self.input.len() {
+                break;
+            }
+            let start = match self.continuation.as_ref() {
+                Some(continuation) => self.best_match(nominal, continuation),
+                None => nominal,
+            };
+            let piece = &self.input[start..start + WINDOW];
+            for (i, (sample, weight)) in piece.iter().zip(&self.window).enumerate() {
+                if i < HOP {
+                    out.push(self.overlap[i] + sample * weight);
+                } else {
+                    self.overlap[i - HOP] = sample * weight;
+                }
+            }
+            self.continuation = Some(self.input[start + HOP..start + HOP + WINDOW].to_vec());
+            self.position += HOP as f64 * speed as f64;
+        }
+
+        // drop what no piece can start in anymore
+        let consumed = (self.position as usize).saturating_sub(SEARCH);
+        if consumed > 0 {
+            let consumed = consumed.min(self.input.len());
+            self.input.drain(..consumed);
+            self.position -= consumed as f64;
+        }
+        out
+    }
+
+    /// The start near `nominal` that lines up best with `continuation`
+    fn best_match(&self, nominal: usize, continuation: &[f32]) -> usize {
+        (nominal - SEARCH..=nominal + SEARCH)
+            .map(|start| {
+                let similarity: f32 = self.input[start..start + WINDOW]
+                    .iter()
+                    .zip(continuation)
+                    .map(|(a, b)| a * b)
+                    .sum();
+                (start, similarity)
+            })
+            .fold((nominal, f32::MIN), |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .0
+    }
+}
+
+/// Dynamic rate control, follows how full the frontend's audio buffer is
+#[derive(Debug, Clone)]
+pub struct RateControl {
+    /// the average fill level, from 0 to 1
+    fill: f32,
+    max_deviation: f32,
+}
+
+impl Default for RateControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateControl {
+    /// Starts out half full, which doesn't change anything
+    pub fn new() -> Self {
+        Self {
+            fill: 0.5,
+            max_deviation: MAX_RATE_DEVIATION,
+        }
+    }
+
+    /// From 0 to [MAX_RATE_DEVIATION], 0 turns it off
+    pub fn set_max_deviation(&mut self, max_deviation: f32) {
+        self.max_deviation = max_deviation.clamp(0.0, MAX_RATE_DEVIATION);
+    }
+
+    pub fn get_max_deviation(&self) -> f32 {
+        self.max_deviation
+    }
+
+    /// Call once a frame with how full the audio buffer is, from 0 to 1
+    pub fn update(&mut self, fill: f32) {
+        self.fill += (fill.clamp(0.0, 1.0) - self.fill) * FILL_SMOOTHING;
+    }
+
+    /// How much faster the emulation should go, between
+    /// `-max_deviation` and `max_deviation`. Positive while the buffer is
+    /// running dry.
+    pub fn get_adjustment(&self) -> f32 {
+        self.max_deviation * (1.0 - 2.0 * self.fill)
+    }
+}
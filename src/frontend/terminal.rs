@@ -0,0 +1,268 @@
+//! A [Frontend] that draws into a terminal, for quick checks over ssh and
+//! demos. Every character is two pixels on top of each other: the upper
+//! half block gets the top pixel as its foreground color and the bottom
+//! pixel as its background. Only ansi escape codes are used so it works
+//! with any `Write`, the caller puts the terminal in raw mode and passes
+//! on the keys it reads with [TerminalFrontend::press_key].
+//!
+//! Terminals only send key presses, not releases, so a key holds its
+//! button down for [KEY_HOLD_FRAMES] frames. Keys that aren't buttons
+//! go through [TerminalFrontend::keymap], only the ones without
+//! modifiers can be typed though. With [TerminalFrontend::raw_keyboard]
+//! every key goes to the Family BASIC keyboard instead and only ctrl+c
+//! quits.
+
+use std::io::{self, Write};
+
+use crate::{
+    frontend::{
+        host::{Frontend, InputEvent},
+        keymap::{KeyCombo, Keymap},
+    },
+    hardware::input::{controller::Button, keyboard},
+};
+
+/// How long a key press holds its button down, long enough for key
+/// repeat to keep it held
+pub const KEY_HOLD_FRAMES: u32 = 8;
+
+const UPPER_HALF_BLOCK: char = '▀';
+const CTRL_C: char = '\x03';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// 24 bit colors, most terminals have them
+    #[default]
+    TrueColor,
+    /// The xterm 256 color palette, for older terminals and tmux
+    Ansi256,
+}
+
+/// The button a key is mapped to: wasd for the d-pad, k for A, j for B,
+/// enter for start and space for select
+pub fn key_button(key: char) -> Option<Button> {
+    match key.to_ascii_lowercase() {
+        'w' => Some(Button::Up),
+        'a' => Some(Button::Left),
+        's' => Some(Button::Down),
+        'd' => Some(Button::Right),
+        'k' => Some(Button::A),
+        'j' => Some(Button::B),
+        '\r' | '\n' => Some(Button::Start),
+        ' ' => Some(Button::Select),
+        _ => None,
+    }
+}
+
+/// The Family BASIC key a typed character is on, see
+/// [keyboard::key_position]
+pub fn keyboard_key(key: char) -> Option<(usize, usize, usize)> {
+    let name = match key {
+        '\r' | '\n' => "ENTER".to_string(),
+        ' ' => "SPACE".to_string(),
+        '\x1b' => "ESCAPE".to_string(),
+        '\x08' | '\x7f' => "BACKSPACE".to_string(),
+        '\\' => "BACKSLASH".to_string(),
+        _ => key.to_string(),
+    };
+    keyboard::key_position(&name)
+}
+
+/// The closest color of the xterm 256 color cube
+fn ansi256(color: u32) -> u8 {
+    let [_, r, g, b] = color.to_be_bytes();
+    let level = |channel: u8| (channel as u16 * 5 + 127) / 255;
+    (16 + 36 * level(r) + 6 * level(g) + level(b)) as u8
+}
+
+pub struct TerminalFrontend<W: Write> {
+    writer: W,
+    columns: usize,
+    rows: usize,
+    pub color_mode: ColorMode,
+    pub keymap: Keymap,
+    /// Sends every key to the emulated keyboard, for Family BASIC
+    pub raw_keyboard: bool,
+    /// frames left for every button of controller 0, see [Button::ALL]
+    held: [u32; 8],
+    /// frames left for the held keys of the emulated keyboard
+    held_keys: Vec<((usize, usize, usize), u32)>,
+    events: Vec<InputEvent>,
+    /// the frame as text, reused so drawing doesn't allocate
+    text: String,
+}
+
+impl<W: Write> TerminalFrontend<W> {
+    /// `columns` and `rows` are the size of the terminal, the last row is
+    /// left for messages
+    pub fn new(writer: W, columns: usize, rows: usize) -> Self {
+        Self {
+            writer,
+            columns: columns.max(1),
+            rows: rows.max(2),
+            color_mode: ColorMode::default(),
+            keymap: Keymap::default(),
+            raw_keyboard: false,
+            held: [0; 8],
+            held_keys: Vec::new(),
+            events: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    /// Has to be called when the terminal changes size
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        self.columns = columns.max(1);
+        self.rows = rows.max(2);
+        self.text.clear();
+        self.text.push_str("\x1b[2J");
+    }
+
+    /// A key the terminal sent, q and ctrl+c quit
+    pub fn press_key(&mut self, key: char) {
+        if key == CTRL_C || (key == 'q' && !self.raw_keyboard) {
+            self.events.push(InputEvent::Quit);
+            return;
+        }
+        if self.raw_keyboard {
+            self.press_keyboard_key(key);
+            return;
+        }
+        let Some(button) = key_button(key) else {
+            if let Some(hotkey) = self.keymap.hotkey(&KeyCombo::key(&key.to_string())) {
+                self.events.push(InputEvent::Hotkey(hotkey));
+            }
+            return;
+        };
+        let index = Button::ALL
+            .iter()
+            .position(|other| *other == button)
+            .unwrap();
+        if self.held[index] == 0 {
+            self.events.push(InputEvent::Button {
+                controller_index: 0,
+                button,
+                pressed: true,
+            });
+        }
+        self.held[index] = KEY_HOLD_FRAMES;
+    }
+
+    fn press_keyboard_key(&mut self, key: char) {
+        let Some(position) = keyboard_key(key) else {
+            return;
+        };
+        if let Some((_, frames)) = self
+            .held_keys
+            .iter_mut()
+            .find(|(held, _)| *held == position)
+        {
+            *frames = KEY_HOLD_FRAMES;
+            return;
+        }
+        let (row, column, key) = position;
+        self.events.push(InputEvent::Key {
+            row,
+            column,
+            key,
+            pressed: true,
+        });
+        self.held_keys.push((position, KEY_HOLD_FRAMES));
+    }
+
+    /// Puts the cursor and colors back, should be done before leaving raw
+    /// mode
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(b"\x1b[0m\x1b[?25h\r\n")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn push_color(&mut self, color: u32, is_background: bool) {
+        use std::fmt::Write;
+        let layer = if is_background { 48 } else { 38 };
+        let _ = match self.color_mode {
+            ColorMode::TrueColor => {
+                let [_, r, g, b] = color.to_be_bytes();
+                write!(self.text, "\x1b[{layer};2;{r};{g};{b}m")
+            }
+            ColorMode::Ansi256 => write!(self.text, "\x1b[{layer};5;{}m", ansi256(color)),
+        };
+    }
+}
+
+impl<W: Write> Frontend for TerminalFrontend<W> {
+    fn init(&mut self) -> io::Result<()> {
+        // hide the cursor and clear the screen
+        self.writer.write_all(b"\x1b[?25l\x1b[2J")?;
+        self.writer.flush()
+    }
+
+    fn surface_size(&self) -> (usize, usize) {
+        (self.columns, (self.rows - 1) * 2)
+    }
+
+    fn present_frame(&mut self, surface: &[u32], width: usize, height: usize) -> io::Result<()> {
+        self.text.push_str("\x1b[H");
+        for y in (0..height).step_by(2) {
+            let mut last = None;
+            for x in 0..width {
+                let top = surface[y * width + x];
+                let bottom = surface.get((y + 1) * width + x).copied().unwrap_or(0);
+                // only change the colors when they change
+                if last != Some((top, bottom)) {
+                    self.push_color(top, false);
+                    self.push_color(bottom, true);
+                    last = Some((top, bottom));
+                }
+                self.text.push(UPPER_HALF_BLOCK);
+            }
+            self.text.push_str("\x1b[0m\r\n");
+        }
+
+        self.writer.write_all(self.text.as_bytes())?;
+        self.text.clear();
+        self.writer.flush()
+    }
+
+    fn poll_input(&mut self) -> Vec<InputEvent> {
+        let mut events = std::mem::take(&mut self.events);
+        for (held, button) in self.held.iter_mut().zip(Button::ALL) {
+            if *held == 0 {
+                continue;
+            }
+            *held -= 1;
+            if *held == 0 {
+                events.push(InputEvent::Button {
+                    controller_index: 0,
+                    button,
+                    pressed: false,
+                });
+            }
+        }
+        self.held_keys.retain_mut(|((row, column, key), frames)| {
+            *frames -= 1;
+            if *frames == 0 {
+                events.push(InputEvent::Key {
+                    row: *row,
+                    column: *column,
+                    key: *key,
+                    pressed: false,
+                });
+            }
+            *frames > 0
+        });
+        events
+    }
+
+    /// Terminals can't play sound
+    fn push_audio(&mut self, _samples: &[f32]) {}
+
+    /// Shows up right away on the last row, until the next message
+    fn show_message(&mut self, message: &str) {
+        let message: String = message.chars().take(self.columns).collect();
+        // nowhere to report a broken terminal to
+        let _ = write!(self.writer, "\x1b[{};1H{message}\x1b[K", self.rows)
+            .and_then(|_| self.writer.flush());
+    }
+}
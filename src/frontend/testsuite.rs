@@ -0,0 +1,332 @@
+//! Runs a set of test roms headless and reports which ones pass, for a
+//! `scam-testsuite` command that tracks compatibility over time. The
+//! roms run in parallel, one [Nes] per worker thread.
+//!
+//! Most test roms (blargg's and everything built on his shell) report
+//! through $6000, see [PassCondition::Blargg]. The ones that only draw
+//! the result get compared against a hash of a known good screen.
+//! https://www.nesdev.org/wiki/Emulator_tests
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    devices::{nes::Nes, run::BreakReason},
+    frontend::input_script::screen_hash,
+    hardware::{cartrige::Cartrige, ppu::frame::Frame},
+};
+
+/// Frames a rom gets before it counts as hanging, about a minute
+pub const DEFAULT_MAX_FRAMES: u32 = 3600;
+
+/// The blargg shell writes these to $6001-$6003 once $6000 is valid
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_STATUS: u16 = 0x6000;
+const BLARGG_TEXT: u16 = 0x6004;
+const BLARGG_RUNNING: u8 = 0x80;
+const BLARGG_NEEDS_RESET: u8 = 0x81;
+/// The rom asks for the reset button to be held at least 100ms
+const RESET_DELAY_FRAMES: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PassCondition {
+    /// $6000 holds 0x80 while running, 0x81 when the rom wants a reset
+    /// and the result code once done (0 is a pass), with a text
+    /// explaining it from $6004
+    Blargg,
+    /// The crc32 of the screen as rgb bytes (see [Frame::to_rgb_bytes])
+    /// has to match at some point
+    ScreenHash { crc32: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestRom {
+    pub path: PathBuf,
+    pub condition: PassCondition,
+    #[serde(default = "default_max_frames")]
+    pub max_frames: u32,
+}
+
+fn default_max_frames() -> u32 {
+    DEFAULT_MAX_FRAMES
+}
+
+impl TestRom {
+    pub fn new(path: impl Into<PathBuf>, condition: PassCondition) -> Self {
+        Self {
+            path: path.into(),
+            condition,
+            max_frames: DEFAULT_MAX_FRAMES,
+        }
+    }
+}
+
+/// The roms to run, can be loaded from any format serde reads
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestSuite {
+    pub roms: Vec<TestRom>,
+    /// worker threads, 0 uses one per cpu core
+    #[serde(default)]
+    pub threads: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    TimedOut,
+    /// The rom couldn't be loaded or the cpu jammed
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TestResult {
+    pub path: PathBuf,
+    pub status: TestStatus,
+    /// The text the rom printed, the screen hash or the error
+    pub message: String,
+    pub frames: u32,
+    pub duration: Duration,
+}
+
+/// The roms of blargg's mmc3_test_2 set, relative to its folder. They
+/// check the scanline counter and how it gets clocked by A12, see
+/// [TestSuite::mmc3_irq]. The set's 6-MMC3_alt is left out, it's for the
+/// older MMC3A and only passes with a NES 2.0 header saying submapper 4.
+pub const MMC3_IRQ_ROMS: [&str; 5] = [
+    "rom_singles/1-clocking.nes",
+    "rom_singles/2-details.nes",
+    "rom_singles/3-A12_clocking.nes",
+    "rom_singles/4-scanline_timing.nes",
+    "rom_singles/5-MMC3.nes",
+];
+
+/// The results in the same order as [TestSuite::roms]. Serializes to the
+/// json report, [TestReport::to_junit] gives the junit one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestSuite {
+    /// The mmc3_test_2 roms in `directory`, see [MMC3_IRQ_ROMS]
+    pub fn mmc3_irq(directory: &Path) -> Self {
+        Self {
+            roms: MMC3_IRQ_ROMS
+                .iter()
+                .map(|rom| TestRom::new(directory.join(rom), PassCondition::Blargg))
+                .collect(),
+            threads: 0,
+        }
+    }
+
+    pub fn run(&self) -> TestReport {
+        let threads = match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        }
+        .min(self.roms.len().max(1));
+
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(vec![None; self.roms.len()]);
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(rom) = self.roms.get(index) else {
+                            return;
+                        };
+                        let result = run_test(rom);
+                        results.lock().unwrap()[index] = Some(result);
+                    }
+                });
+            }
+        });
+
+        TestReport {
+            results: results
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .collect(),
+        }
+    }
+}
+
+/// Runs one rom on the current thread
+pub fn run_test(rom: &TestRom) -> TestResult {
+    let start = Instant::now();
+    let mut result = TestResult {
+        path: rom.path.clone(),
+        status: TestStatus::TimedOut,
+        message: String::new(),
+        frames: 0,
+        duration: Duration::ZERO,
+    };
+
+    match Cartrige::from_file(&rom.path.to_string_lossy()) {
+        Ok(cartrige) => {
+            let mut nes = Nes::new_with_cartrige(cartrige);
+            nes.reset();
+            (result.status, result.message, result.frames) = match rom.condition {
+                PassCondition::Blargg => run_blargg(&mut nes, rom.max_frames),
+                PassCondition::ScreenHash { crc32 } => {
+                    run_screen_hash(&mut nes, crc32, rom.max_frames)
+                }
+            };
+        }
+        Err(err) => {
+            result.status = TestStatus::Error;
+            result.message = err.to_string();
+        }
+    }
+    result.duration = start.elapsed();
+    result
+}
+
+fn run_blargg(nes: &mut Nes, max_frames: u32) -> (TestStatus, String, u32) {
+    // video is only needed by screen hashes
+    nes.set_video_enabled(false);
+    let mut screen = Frame::new();
+    let mut reset_at = None;
+    for frame in 1..=max_frames {
+        let summary = nes.run_frame(&mut screen);
+        if summary.break_reason != BreakReason::FrameDone {
+            return (
+                TestStatus::Error,
+                format!("{:?}", summary.break_reason),
+                frame,
+            );
+        }
+
+        let signature = [0x6001, 0x6002, 0x6003].map(|address| nes.bus.peek(address));
+        if signature != BLARGG_SIGNATURE {
+            continue;
+        }
+        match nes.bus.peek(BLARGG_STATUS) {
+            BLARGG_RUNNING => (),
+            BLARGG_NEEDS_RESET => match reset_at {
+                Some(at) if frame >= at => {
+                    nes.reset();
+                    reset_at = None;
+                }
+                Some(_) => (),
+                None => reset_at = Some(frame + RESET_DELAY_FRAMES),
+            },
+            code => {
+                let status = if code == 0 {
+                    TestStatus::Passed
+                } else {
+                    TestStatus::Failed
+                };
+                return (status, blargg_text(nes), frame);
+            }
+        }
+    }
+    (TestStatus::TimedOut, blargg_text(nes), max_frames)
+}
+
+/// The null terminated text the rom printed
+fn blargg_text(nes: &Nes) -> String {
+    let bytes: Vec<u8> = (BLARGG_TEXT..BLARGG_STATUS + 0x1000)
+        .map(|address| nes.bus.peek(address))
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+fn run_screen_hash(nes: &mut Nes, crc32: u32, max_frames: u32) -> (TestStatus, String, u32) {
+    let mut frame = Frame::new();
+    let mut hash = 0;
+    for frames in 1..=max_frames {
+        let summary = nes.run_frame(&mut frame);
+        if summary.break_reason != BreakReason::FrameDone {
+            return (
+                TestStatus::Error,
+                format!("{:?}", summary.break_reason),
+                frames,
+            );
+        }
+        hash = screen_hash(&frame);
+        if hash == crc32 {
+            return (TestStatus::Passed, format!("{hash:08X}"), frames);
+        }
+    }
+    (TestStatus::Failed, format!("{hash:08X}"), max_frames)
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.count(TestStatus::Passed)
+    }
+
+    pub fn count(&self, status: TestStatus) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    }
+
+    pub fn is_all_passed(&self) -> bool {
+        self.passed() == self.results.len()
+    }
+
+    /// The report in the junit xml format ci servers understand, every
+    /// rom is a test case
+    pub fn to_junit(&self) -> String {
+        let total: Duration = self.results.iter().map(|result| result.duration).sum();
+        let mut out = String::new();
+        let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            out,
+            r#"<testsuite name="scamu" tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+            self.results.len(),
+            self.count(TestStatus::Failed) + self.count(TestStatus::TimedOut),
+            self.count(TestStatus::Error),
+            total.as_secs_f64()
+        );
+        for result in self.results.iter() {
+            let _ = write!(
+                out,
+                r#"  <testcase name="{}" time="{:.3}""#,
+                escape_xml(&result.path.to_string_lossy()),
+                result.duration.as_secs_f64()
+            );
+            let message = escape_xml(&result.message);
+            let _ = match result.status {
+                TestStatus::Passed => writeln!(out, "/>"),
+                TestStatus::Failed => {
+                    writeln!(out, r#"><failure message="{message}"/></testcase>"#)
+                }
+                TestStatus::TimedOut => writeln!(
+                    out,
+                    r#"><failure message="timed out after {} frames">{message}</failure></testcase>"#,
+                    result.frames
+                ),
+                TestStatus::Error => writeln!(out, r#"><error message="{message}"/></testcase>"#),
+            };
+        }
+        let _ = writeln!(out, "</testsuite>");
+        out
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
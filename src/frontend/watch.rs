@@ -0,0 +1,69 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    devices::nes::Nes,
+    hardware::cartrige::{Cartrige, LoadOptions, Result},
+    trace::targets,
+};
+
+/// Watches a rom for changes so a homebrew game can be rebuilt and picked
+/// up without restarting the emulator. Call [RomWatcher::poll] every now
+/// and then (like once a frame) and [RomWatcher::reload] when it returns
+/// true. Breakpoints live in the [crate::debugger::Debugger] and aren't
+/// touched by reloading.
+///
+/// The file is polled instead of using file system events, checking the
+/// modification time of one file is cheap and works the same everywhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomWatcher {
+    path: PathBuf,
+    /// the version that is loaded right now
+    loaded: Option<(SystemTime, u64)>,
+    /// what the last poll saw, the linker might still be writing it
+    pending: Option<(SystemTime, u64)>,
+}
+
+impl RomWatcher {
+    /// The rom at `path` as it is now counts as loaded
+    pub fn new(path: &Path) -> Self {
+        let version = Self::version(path);
+        Self {
+            path: path.to_path_buf(),
+            loaded: version,
+            pending: version,
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true when the rom changed since it was last loaded. A
+    /// change is only reported once two polls in a row see the same
+    /// version, so a rom that is still being written isn't picked up.
+    pub fn poll(&mut self) -> bool {
+        let version = Self::version(&self.path);
+        let settled = version == self.pending;
+        self.pending = version;
+        settled && version.is_some() && version != self.loaded
+    }
+
+    /// Loads the rom again and power cycles `nes` with it. If it can't be
+    /// loaded the old game keeps running and the error is returned, the
+    /// next change gets picked up as usual.
+    pub fn reload(&mut self, nes: &mut Nes, options: &LoadOptions) -> Result<()> {
+        self.loaded = self.pending;
+        let cartrige = Cartrige::from_file_with_options(&self.path.to_string_lossy(), options)?;
+        nes.reload_cartrige(cartrige);
+        tracing::info!(target: targets::EMULATOR, path = %self.path.display(), "reloaded rom");
+        Ok(())
+    }
+
+    fn version(path: &Path) -> Option<(SystemTime, u64)> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::{
+    devices::{machine::Machine, run::BreakReason},
+    hardware::ppu::frame::Frame,
+};
+
+const HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Writes mono 16 bit pcm wav files
+/// http://soundfile.sapp.org/doc/WaveFormat/
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = BITS_PER_SAMPLE / 8;
+        writer.write_all(b"RIFF")?;
+        // the sizes get filled in by finish
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // pcm, mono
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { writer, samples: 0 })
+    }
+
+    /// Samples go from -1 to 1, anything outside of that gets clipped
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        self.samples
+    }
+
+    /// Fills in the sizes in the header, without this the file is broken
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples * (BITS_PER_SAMPLE / 8) as u32;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Records the audio a frontend plays into a wav file, until it gets
+/// stopped or for a set amount of samples. The frontend passes it every
+/// sample it takes out of the apu.
+pub struct AudioCapture<W: Write + Seek> {
+    wav: WavWriter<W>,
+    max_samples: Option<u32>,
+}
+
+impl<W: Write + Seek> AudioCapture<W> {
+    /// `seconds` limits how long the capture is, `None` records until
+    /// [AudioCapture::finish]
+    pub fn new(writer: W, sample_rate: u32, seconds: Option<f64>) -> io::Result<Self> {
+        Ok(Self {
+            wav: WavWriter::new(writer, sample_rate)?,
+            max_samples: seconds.map(|seconds| (seconds * sample_rate as f64) as u32),
+        })
+    }
+
+    /// Returns false once the capture has all the samples it wants, the
+    /// extra ones are ignored
+    pub fn push(&mut self, samples: &[f32]) -> io::Result<bool> {
+        let samples = match self.max_samples {
+            Some(max) => {
+                let left = max.saturating_sub(self.wav.get_sample_count()) as usize;
+                &samples[..samples.len().min(left)]
+            }
+            None => samples,
+        };
+        self.wav.write_samples(samples)?;
+        Ok(!self.is_done())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.max_samples
+            .is_some_and(|max| self.wav.get_sample_count() >= max)
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.wav.finish()
+    }
+}
+
+/// Runs `machine` without any frontend until `seconds` of audio at
+/// `sample_rate` got written into `writer`, for rendering music from the
+/// command line. Stops early if the machine stops running (like when the
+/// cpu jams).
+pub fn render_audio<W: Write + Seek>(
+    machine: &mut impl Machine,
+    sample_rate: u32,
+    seconds: f64,
+    writer: W,
+) -> io::Result<W> {
+    let mut capture = AudioCapture::new(writer, sample_rate, Some(seconds))?;
+    let mut frame = Frame::new();
+    while !capture.is_done() {
+        let summary = machine.run_frame(&mut frame);
+        capture.push(&machine.take_audio_samples())?;
+        if summary.break_reason != BreakReason::FrameDone {
+            break;
+        }
+    }
+    capture.finish()
+}
@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use crate::{
+    devices::{machine::Machine, region::Region},
+    frontend::runner::Runner,
+    hardware::cartrige::header::{Header, TvSystem},
+};
+
+/// The name every window title starts with
+pub const TITLE: &str = "SCAM";
+
+/// How the window covers the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// A window without decorations the size of the monitor, switching
+    /// to it is instant and other windows can go on top
+    Borderless,
+    /// Takes over the monitor, can change its video mode
+    Exclusive,
+}
+
+/// Keeps track of the fullscreen state of the window so going back to
+/// windowed mode restores the size it had before. The frontend applies
+/// the mode and size to its actual window (like winit's `set_fullscreen`
+/// and `request_inner_size`) and calls [WindowState::resize] every time
+/// the window changes size, including after going fullscreen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowState {
+    mode: WindowMode,
+    /// the mode Alt+Enter switches to
+    fullscreen_mode: WindowMode,
+    size: (u32, u32),
+    windowed_size: (u32, u32),
+}
+
+impl WindowState {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            mode: WindowMode::Windowed,
+            fullscreen_mode: WindowMode::Borderless,
+            size: (width, height),
+            windowed_size: (width, height),
+        }
+    }
+
+    pub fn get_mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    /// The current size of the window, which is the size the surface
+    /// buffer should have
+    pub fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// The size to go back to when leaving fullscreen
+    pub fn get_windowed_size(&self) -> (u32, u32) {
+        self.windowed_size
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.mode != WindowMode::Windowed
+    }
+
+    /// Which fullscreen mode [WindowState::toggle_fullscreen] uses,
+    /// [WindowMode::Windowed] is ignored
+    pub fn set_fullscreen_mode(&mut self, mode: WindowMode) {
+        if mode != WindowMode::Windowed {
+            self.fullscreen_mode = mode;
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: WindowMode) {
+        if mode == self.mode {
+            return;
+        }
+        if self.mode == WindowMode::Windowed {
+            self.windowed_size = self.size;
+        } else if mode == WindowMode::Windowed {
+            self.size = self.windowed_size;
+        }
+        self.mode = mode;
+    }
+
+    /// What Alt+Enter does, returns the new mode
+    pub fn toggle_fullscreen(&mut self) -> WindowMode {
+        if self.is_fullscreen() {
+            self.set_mode(WindowMode::Windowed);
+        } else {
+            self.set_mode(self.fullscreen_mode);
+        }
+        self.mode
+    }
+
+    /// Has to be called when the window gets resized. Sizes of 0 (when
+    /// minimized) are ignored since there is nothing to draw into.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.size = (width, height);
+        if self.mode == WindowMode::Windowed {
+            self.windowed_size = self.size;
+        }
+    }
+}
+
+/// What the window title shows about the runner, see [window_title]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TitleStatus {
+    pub paused: bool,
+    /// See [Runner::set_speed]
+    pub speed: f32,
+    /// Audio or a movie is being recorded
+    pub recording: bool,
+    /// What the machine runs as, shown instead of the header's tv system
+    pub region: Option<Region>,
+}
+
+impl TitleStatus {
+    pub fn from_runner<M: Machine>(runner: &Runner<M>, recording: bool) -> Self {
+        Self {
+            paused: runner.is_paused(),
+            speed: runner.get_speed(),
+            recording,
+            region: runner.machine().get_region(),
+        }
+    }
+}
+
+impl Default for TitleStatus {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            speed: 1.0,
+            recording: false,
+            region: None,
+        }
+    }
+}
+
+/// Like `SCAM — game.nes [Mapper 4, NTSC] (paused, 200%)`, the frontend
+/// should set it again every time the status changes. Without a rom it's
+/// just [TITLE].
+pub fn window_title(
+    rom_path: Option<&Path>,
+    header: Option<&Header>,
+    status: TitleStatus,
+) -> String {
+    let mut title = TITLE.to_string();
+    if let Some(name) = rom_path.and_then(Path::file_name) {
+        title.push_str(" — ");
+        title.push_str(&name.to_string_lossy());
+    }
+    if let Some(header) = header {
+        title.push_str(&format!(" [Mapper {}", header.get_full_mapper_id()));
+        match (status.region, header.tv_system()) {
+            (Some(Region::Ntsc), _) | (None, TvSystem::Ntsc) => title.push_str(", NTSC"),
+            (Some(Region::Pal), _) | (None, TvSystem::Pal) => title.push_str(", PAL"),
+            (Some(Region::Dendy), _) | (None, TvSystem::Dendy) => title.push_str(", Dendy"),
+            (None, TvSystem::DualCompatible) => title.push_str(", NTSC/PAL"),
+            (None, TvSystem::Unknown(_)) => (),
+        }
+        title.push(']');
+    }
+
+    let mut indicators = Vec::new();
+    if status.paused {
+        indicators.push("paused".to_string());
+    }
+    if status.speed != 1.0 {
+        indicators.push(format!("{:.0}%", status.speed * 100.0));
+    }
+    if status.recording {
+        indicators.push("recording".to_string());
+    }
+    if !indicators.is_empty() {
+        title.push_str(&format!(" ({})", indicators.join(", ")));
+    }
+    title
+}
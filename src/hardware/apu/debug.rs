@@ -0,0 +1,115 @@
+//! A snapshot of what every apu channel is doing, for a debugger panel
+//! next to the music driver being written. See [Apu::debug_state].
+//!
+//! Noise and dmc aren't emulated yet, so they aren't in here either.
+//!
+//! [Apu::debug_state]: super::Apu::debug_state
+
+use serde::Serialize;
+
+use crate::hardware::constants::clock_rates::CPU_CLOCK;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct EnvelopeState {
+    pub constant_volume: bool,
+    pub looping: bool,
+    /// The constant volume, or how many quarter frames each decay step
+    /// takes (minus one)
+    pub volume: u8,
+    pub decay_level: u8,
+}
+
+impl EnvelopeState {
+    /// The volume the channel is at, 0 to 15
+    pub fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay_level
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SweepState {
+    pub enabled: bool,
+    pub negate: bool,
+    pub shift: u8,
+    /// Half frames between period changes (minus one)
+    pub period: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LengthCounterState {
+    pub enabled: bool,
+    pub halted: bool,
+    pub value: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PulseState {
+    /// 0 to 3 for 12.5%, 25%, 50% and 75%
+    pub duty: u8,
+    /// The 11 bit timer period, after the sweep changed it
+    pub period: u16,
+    pub timer: u16,
+    /// 0 to 7, where the duty cycle sequence is
+    pub sequence_step: u8,
+    pub envelope: EnvelopeState,
+    pub sweep: SweepState,
+    pub length_counter: LengthCounterState,
+    /// Silenced because the period is too low or the sweep would
+    /// overflow it
+    pub sweep_muted: bool,
+}
+
+impl PulseState {
+    /// The note the channel plays in Hz
+    pub fn frequency(&self) -> f32 {
+        CPU_CLOCK as f32 / (16.0 * (self.period as f32 + 1.0))
+    }
+
+    /// True if the channel is making sound right now
+    pub fn is_audible(&self) -> bool {
+        !self.sweep_muted && self.length_counter.value != 0 && self.envelope.output() != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TriangleState {
+    /// The 11 bit timer period
+    pub period: u16,
+    pub timer: u16,
+    /// 0 to 31, where the triangle sequence is
+    pub sequence_step: u8,
+    pub linear_counter: u8,
+    pub linear_reload: u8,
+    pub linear_reload_flag: bool,
+    /// Also halts the length counter
+    pub control: bool,
+    pub length_counter: LengthCounterState,
+}
+
+impl TriangleState {
+    /// The note the channel plays in Hz, an octave below a pulse with the
+    /// same period
+    pub fn frequency(&self) -> f32 {
+        CPU_CLOCK as f32 / (32.0 * (self.period as f32 + 1.0))
+    }
+
+    /// True if the sequence is moving
+    pub fn is_audible(&self) -> bool {
+        self.length_counter.value != 0 && self.linear_counter != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ApuDebugState {
+    pub pulse1: PulseState,
+    pub pulse2: PulseState,
+    pub triangle: TriangleState,
+    /// The 5 step sequence, false for the 4 step one
+    pub five_step_mode: bool,
+    pub interrupt_inhibit: bool,
+    pub frame_interrupt: bool,
+}
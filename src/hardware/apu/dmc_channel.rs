@@ -0,0 +1,245 @@
+use crate::{
+    devices::region::Region,
+    hardware::{
+        bit_ops::BitOps,
+        constants::apu::{DMC_RATES_NTSC, DMC_RATES_PAL, dmc_register0},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+/// What the dmc's memory reader does with the cpu on a cycle, see
+/// [DmcChannel::next_dma_cycle]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmcDmaCycle {
+    /// The cpu runs
+    None,
+    /// The cpu gets halted, it reads the address it was reading again
+    Halt,
+    /// The cpu stays halted, a dummy cycle or waiting for a get cycle
+    Stall,
+    /// The sample byte at the address gets read, see
+    /// [DmcChannel::fill_buffer]
+    Fetch(u16),
+}
+
+/// implementation of: https://www.nesdev.org/wiki/APU_DMC
+#[derive(Debug, Clone)]
+pub struct DmcChannel {
+    irq_enabled: bool,
+    loop_flag: bool,
+    timer_period: u16,
+    timer: u16,
+
+    output_level: u8,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    irq_flag: bool,
+
+    /// cycles the cpu has been halted for the running dma, `None` while
+    /// there is none
+    dma_cycle: Option<u8>,
+}
+
+impl Default for DmcChannel {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            loop_flag: false,
+            timer_period: DMC_RATES_NTSC[0],
+            timer: DMC_RATES_NTSC[0],
+            output_level: 0,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            irq_flag: false,
+            dma_cycle: None,
+        }
+    }
+}
+
+impl DmcChannel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8, region: Region) {
+        match address % 4 {
+            0 => {
+                self.irq_enabled = value.get_flag_enabled(dmc_register0::IRQ_ENABLED);
+                if !self.irq_enabled {
+                    self.irq_flag = false;
+                }
+                self.loop_flag = value.get_flag_enabled(dmc_register0::LOOP);
+                let rates = match region {
+                    Region::Pal => DMC_RATES_PAL,
+                    Region::Ntsc | Region::Dendy => DMC_RATES_NTSC,
+                };
+                self.timer_period = rates[value.get_bitfield(dmc_register0::RATE) as usize];
+            }
+            1 => self.output_level = value & 0x7F,
+            2 => self.sample_address = 0xC000 + value as u16 * 64,
+            3 => self.sample_length = value as u16 * 16 + 1,
+            _ => (),
+        }
+    }
+
+    /// Bit 4 of $4015, starts the sample over if it was done
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    /// Whether the sample is still playing, bit 4 of $4015
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn is_irq_flag_set(&self) -> bool {
+        self.irq_flag
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Ticks every cpu cycle
+    pub fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+
+        if !self.silence {
+            if self.shift_register & 1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// Steps the memory reader's dma, called every cpu cycle after
+    /// [DmcChannel::tick]. It takes 3 or 4 cycles, the fetch has to land
+    /// on a get cycle (`is_get_cycle`).
+    pub fn next_dma_cycle(&mut self, is_get_cycle: bool) -> DmcDmaCycle {
+        match self.dma_cycle {
+            None if self.sample_buffer.is_none() && self.bytes_remaining > 0 => {
+                self.dma_cycle = Some(0);
+                DmcDmaCycle::Halt
+            }
+            None => DmcDmaCycle::None,
+            Some(cycle) if cycle >= 1 && is_get_cycle => {
+                self.dma_cycle = None;
+                DmcDmaCycle::Fetch(self.current_address)
+            }
+            Some(cycle) => {
+                self.dma_cycle = Some(cycle + 1);
+                DmcDmaCycle::Stall
+            }
+        }
+    }
+
+    /// Hands the byte of a [DmcDmaCycle::Fetch] to the channel
+    pub fn fill_buffer(&mut self, sample: u8) {
+        self.sample_buffer = Some(sample);
+        // $4015 stopped the sample while the dma was running
+        if self.bytes_remaining == 0 {
+            return;
+        }
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+}
+
+impl Iterator for DmcChannel {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.output_level)
+    }
+}
+
+impl SaveState for DmcChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.loop_flag);
+        writer.write_u16(self.timer_period);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.output_level);
+        writer.write_u8(self.shift_register);
+        writer.write_u8(self.bits_remaining);
+        writer.write_bool(self.silence);
+        writer.write_u16(self.sample_address);
+        writer.write_u16(self.sample_length);
+        writer.write_u16(self.current_address);
+        writer.write_u16(self.bytes_remaining);
+        writer.write_bool(self.sample_buffer.is_some());
+        writer.write_u8(self.sample_buffer.unwrap_or(0));
+        writer.write_bool(self.irq_flag);
+        writer.write_bool(self.dma_cycle.is_some());
+        writer.write_u8(self.dma_cycle.unwrap_or(0));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.irq_enabled = reader.read_bool()?;
+        self.loop_flag = reader.read_bool()?;
+        self.timer_period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.output_level = reader.read_u8()? & 0x7F;
+        self.shift_register = reader.read_u8()?;
+        // 0 would underflow on the next output clock
+        self.bits_remaining = reader.read_u8()?.clamp(1, 8);
+        self.silence = reader.read_bool()?;
+        self.sample_address = reader.read_u16()?;
+        self.sample_length = reader.read_u16()?;
+        self.current_address = reader.read_u16()?;
+        self.bytes_remaining = reader.read_u16()?;
+        let has_sample = reader.read_bool()?;
+        let sample = reader.read_u8()?;
+        self.sample_buffer = has_sample.then_some(sample);
+        self.irq_flag = reader.read_bool()?;
+        let is_dma_running = reader.read_bool()?;
+        let dma_cycle = reader.read_u8()?;
+        self.dma_cycle = is_dma_running.then_some(dma_cycle);
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use crate::hardware::{bit_ops::BitOps, constants::apu::register0_flags};
+use crate::{
+    hardware::{apu::debug::EnvelopeState, bit_ops::BitOps, constants::apu::register0_flags},
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
 
 /// implementation of this: https://www.nesdev.org/wiki/APU_Envelope
 #[derive(Default, Debug, Clone)]
@@ -29,6 +32,15 @@ impl Envelope {
         }
     }
 
+    pub fn debug_state(&self) -> EnvelopeState {
+        EnvelopeState {
+            constant_volume: self.constant_volume_flag,
+            looping: self.loop_flag,
+            volume: self.volume,
+            decay_level: self.decay_level,
+        }
+    }
+
     pub fn tick(&mut self) {
         if self.start_flag {
             self.start_flag = false;
@@ -60,3 +72,26 @@ impl Iterator for Envelope {
         }
     }
 }
+
+impl SaveState for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.start_flag);
+        writer.write_bool(self.constant_volume_flag);
+        writer.write_bool(self.loop_flag);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.divider_period);
+        writer.write_u8(self.divider_timer);
+        writer.write_u8(self.decay_level);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.start_flag = reader.read_bool()?;
+        self.constant_volume_flag = reader.read_bool()?;
+        self.loop_flag = reader.read_bool()?;
+        self.volume = reader.read_u8()?;
+        self.divider_period = reader.read_u8()?;
+        self.divider_timer = reader.read_u8()?;
+        self.decay_level = reader.read_u8()?;
+        Ok(())
+    }
+}
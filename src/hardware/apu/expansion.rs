@@ -0,0 +1,58 @@
+//! Sound chips on the cartrige that get mixed in with the apu through the
+//! audio pin on the cartrige connector (famicom only, but every emulator
+//! plays them anyway).
+//! https://www.nesdev.org/wiki/Expansion_audio
+
+use std::fmt::Debug;
+
+use crate::{hardware::constants::apu::EXPANSION_CHIP_COUNT, save_state::SaveState};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExpansionChip {
+    Vrc6 = 0,
+    Vrc7 = 1,
+    Fds = 2,
+    Mmc5 = 3,
+    Namco163 = 4,
+    Sunsoft5B = 5,
+}
+
+impl ExpansionChip {
+    pub const ALL: [ExpansionChip; EXPANSION_CHIP_COUNT] = [
+        ExpansionChip::Vrc6,
+        ExpansionChip::Vrc7,
+        ExpansionChip::Fds,
+        ExpansionChip::Mmc5,
+        ExpansionChip::Namco163,
+        ExpansionChip::Sunsoft5B,
+    ];
+}
+
+/// An expansion sound chip. Mappers with one hand it out through
+/// [Cartrige::expansion_audio](crate::hardware::cartrige::Cartrige::expansion_audio)
+/// and the [Apu](super::Apu) clocks it and mixes it in.
+///
+/// The chip has to save its own registers, they are saved with the apu.
+pub trait ExpansionAudio: SaveState + Send + Debug {
+    fn chip(&self) -> ExpansionChip;
+
+    /// Gets every cpu write to $4020-$FFFF, the chip picks out its own
+    /// registers. The mapper sees the same writes for its bank switching.
+    fn write_register(&mut self, address: u16, value: u8);
+
+    /// Called once every cpu cycle
+    fn tick(&mut self);
+
+    /// The current output, scaled like the mixed apu output where a
+    /// pulse channel at full volume is about 0.12
+    fn output(&self) -> f32;
+
+    /// So the [Apu](super::Apu) can still be cloned
+    fn clone_box(&self) -> Box<dyn ExpansionAudio>;
+}
+
+impl Clone for Box<dyn ExpansionAudio> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
@@ -0,0 +1,125 @@
+//! The analog filters between the apu and the audio jack. The mixer
+//! output has a big dc offset and steps that are sharper than anything
+//! the console puts out, every console model smooths it a bit
+//! differently.
+//! https://www.nesdev.org/wiki/APU_Mixer#Emulation
+
+use std::f32::consts::PI;
+
+/// The filters of a console model, see [AudioFilters::from_preset]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FilterPreset {
+    /// The mixer output as is, dc offset and all
+    #[default]
+    Raw,
+    /// High-pass at 37Hz and low-pass at 14kHz
+    Famicom,
+    /// High-pass at 90Hz and 440Hz and low-pass at 14kHz, the thinner
+    /// sound of the front-loader nes
+    Nes,
+}
+
+impl FilterPreset {
+    pub const ALL: [FilterPreset; 3] =
+        [FilterPreset::Raw, FilterPreset::Famicom, FilterPreset::Nes];
+
+    /// For showing in a settings menu
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterPreset::Raw => "raw",
+            FilterPreset::Famicom => "famicom",
+            FilterPreset::Nes => "NES front-loader",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+/// A first order filter, the kind made from one resistor and capacitor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterStage {
+    pub kind: FilterKind,
+    /// In Hz
+    pub cutoff: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+impl FilterStage {
+    pub fn new(kind: FilterKind, cutoff: f32) -> Self {
+        Self {
+            kind,
+            cutoff,
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    pub fn high_pass(cutoff: f32) -> Self {
+        Self::new(FilterKind::HighPass, cutoff)
+    }
+
+    pub fn low_pass(cutoff: f32) -> Self {
+        Self::new(FilterKind::LowPass, cutoff)
+    }
+
+    fn process(&mut self, sample: f32, sample_rate: f32) -> f32 {
+        let rc = 1.0 / (2.0 * PI * self.cutoff);
+        let dt = 1.0 / sample_rate;
+        let output = match self.kind {
+            FilterKind::HighPass => rc / (rc + dt) * (self.last_output + sample - self.last_input),
+            FilterKind::LowPass => self.last_output + dt / (rc + dt) * (sample - self.last_output),
+        };
+        self.last_input = sample;
+        self.last_output = output;
+        output
+    }
+}
+
+/// The filters every mixed sample goes through, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioFilters {
+    stages: Vec<FilterStage>,
+}
+
+impl AudioFilters {
+    /// Custom filters, for presets use [AudioFilters::from_preset]
+    pub fn new(stages: Vec<FilterStage>) -> Self {
+        Self { stages }
+    }
+
+    pub fn from_preset(preset: FilterPreset) -> Self {
+        Self::new(match preset {
+            FilterPreset::Raw => vec![],
+            FilterPreset::Famicom => vec![
+                FilterStage::high_pass(37.0),
+                FilterStage::low_pass(14_000.0),
+            ],
+            FilterPreset::Nes => vec![
+                FilterStage::high_pass(90.0),
+                FilterStage::high_pass(440.0),
+                FilterStage::low_pass(14_000.0),
+            ],
+        })
+    }
+
+    pub fn get_stages(&self) -> &[FilterStage] {
+        &self.stages
+    }
+
+    pub fn process(&mut self, sample: f32, sample_rate: f32) -> f32 {
+        self.stages
+            .iter_mut()
+            .fold(sample, |sample, stage| stage.process(sample, sample_rate))
+    }
+}
+
+impl From<FilterPreset> for AudioFilters {
+    fn from(preset: FilterPreset) -> Self {
+        Self::from_preset(preset)
+    }
+}
@@ -1,4 +1,7 @@
-use crate::hardware::constants::apu::LENGTH_COUNTER_TABLE;
+use crate::{
+    hardware::{apu::debug::LengthCounterState, constants::apu::LENGTH_COUNTER_TABLE},
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
 
 /// implementation of this: https://www.nesdev.org/wiki/APU_Length_Counter
 #[derive(Default, Debug, Clone)]
@@ -32,6 +35,14 @@ impl LengthCounter {
     pub fn is_non_zero(&self) -> bool {
         self.length_counter != 0
     }
+
+    pub fn debug_state(&self) -> LengthCounterState {
+        LengthCounterState {
+            enabled: self.enabled,
+            halted: self.halt_length_counter,
+            value: self.length_counter,
+        }
+    }
 }
 
 impl Iterator for LengthCounter {
@@ -45,3 +56,18 @@ impl Iterator for LengthCounter {
         }
     }
 }
+
+impl SaveState for LengthCounter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.halt_length_counter);
+        writer.write_u8(self.length_counter);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.enabled = reader.read_bool()?;
+        self.halt_length_counter = reader.read_bool()?;
+        self.length_counter = reader.read_u8()?;
+        Ok(())
+    }
+}
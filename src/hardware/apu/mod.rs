@@ -1,26 +1,83 @@
-use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use better_default::Default;
-
-use crate::hardware::{
-    apu::{
-        pulse_channel::{PulseChannel, PulseChannelType},
-        triangle_channel::TriangleChannel,
-    },
-    bit_ops::BitOps,
-    constants::{
-        apu::{SAMPLE_QUEUE_SIZE, frame_counter_register, status_register},
-        clock_rates::{APU_SAMPLE_RATE, CPU_CLOCK},
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    devices::region::Region,
+    hardware::{
+        apu::{
+            debug::ApuDebugState,
+            dmc_channel::{DmcChannel, DmcDmaCycle},
+            expansion::{ExpansionAudio, ExpansionChip},
+            filter::AudioFilters,
+            pulse_channel::{PulseChannel, PulseChannelType},
+            triangle_channel::TriangleChannel,
+        },
+        bit_ops::BitOps,
+        constants::{
+            apu::{
+                CHANNEL_COUNT, EXPANSION_CHIP_COUNT, SAMPLE_QUEUE_SIZE, frame_counter_register,
+                status_register,
+            },
+            clock_rates::{APU_SAMPLE_RATE, CPU_CLOCK},
+        },
+        cpu::Cpu,
     },
-    cpu::Cpu,
+    save_state::{self, SaveState, StateReader, StateWriter},
+    trace::targets,
 };
 
+pub mod debug;
+pub mod dmc_channel;
 pub mod envelope;
+pub mod expansion;
+pub mod filter;
 pub mod length_counter;
 pub mod pulse_channel;
+pub mod sunsoft_5b;
 pub mod sweep;
 pub mod triangle_channel;
 
+/// The sound channels of the apu. Noise isn't emulated yet so it is
+/// always silent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1 = 0,
+    Pulse2 = 1,
+    Triangle = 2,
+    Noise = 3,
+    Dmc = 4,
+}
+
+impl Channel {
+    pub const ALL: [Channel; CHANNEL_COUNT] = [
+        Channel::Pulse1,
+        Channel::Pulse2,
+        Channel::Triangle,
+        Channel::Noise,
+        Channel::Dmc,
+    ];
+}
+
+/// How the samples get taken from the apu's output, which changes every
+/// cpu cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApuQuality {
+    /// Every sample is the average of all the cycles since the last one,
+    /// which keeps high notes from aliasing
+    #[default]
+    High,
+    /// Every sample is the output on the cycle it gets taken, the channels
+    /// only get mixed about once every 40 cycles. Noticeably harsher on
+    /// high notes.
+    Low,
+}
+
 #[derive(Default, Clone, Copy, Debug)]
 pub struct ApuTick {
     pub is_apu_cycle: bool,
@@ -34,8 +91,8 @@ pub struct Apu {
     /// If you are not using the default [MASTER_CLOCK](crate::hardware::constants::clock_rates::MASTER_CLOCK)
     /// value to tick the emulator, you should set this to your custom
     /// frequency you are ticking the nes at divided by 3 (the cpu runs
-    /// 3 times slower than the nes clock). 
-    /// 
+    /// 3 times slower than the nes clock).
+    ///
     /// Default value is: [CPU_CLOCK] (which is just MASTER_CLOCK / 3)
     #[default(CPU_CLOCK)]
     pub cpu_clock_frequency: u64,
@@ -43,12 +100,15 @@ pub struct Apu {
     /// is set to: [APU_SAMPLE_RATE]
     #[default(APU_SAMPLE_RATE)]
     pub apu_sample_rate: u64,
+    /// see [Apu::set_region]
+    region: Region,
 
     #[default(PulseChannel::new(PulseChannelType::Pulse1))]
     pulse1: PulseChannel,
     #[default(PulseChannel::new(PulseChannelType::Pulse2))]
     pulse2: PulseChannel,
     triangle: TriangleChannel,
+    dmc: DmcChannel,
 
     sequencer_mode_flag: bool,
     interrupt_inhibit_flag: bool,
@@ -63,6 +123,24 @@ pub struct Apu {
     sample_timer: f32,
     #[default(VecDeque::with_capacity(SAMPLE_QUEUE_SIZE))]
     sample_queue: VecDeque<f32>,
+
+    /// see [Apu::set_channel_enabled]
+    #[default([true; CHANNEL_COUNT])]
+    channel_enabled: [bool; CHANNEL_COUNT],
+    /// see [Apu::set_channel_taps_enabled]
+    is_tapping_channels: bool,
+    channel_totals: [f32; CHANNEL_COUNT],
+    channel_taps: [VecDeque<f32>; CHANNEL_COUNT],
+
+    /// see [Apu::attach_expansion_audio]
+    expansion: Vec<Box<dyn ExpansionAudio>>,
+    /// see [Apu::set_expansion_volume]
+    #[default([1.0; EXPANSION_CHIP_COUNT])]
+    expansion_volumes: [f32; EXPANSION_CHIP_COUNT],
+    /// see [Apu::set_audio_filters]
+    filters: AudioFilters,
+    /// see [Apu::set_quality]
+    quality: ApuQuality,
 }
 
 impl Apu {
@@ -70,7 +148,45 @@ impl Apu {
         Default::default()
     }
 
-    pub fn connect_cpu(&mut self, _cpu: Rc<RefCell<Cpu>>) {}
+    pub fn connect_cpu(&mut self, _cpu: Arc<Mutex<Cpu>>) {}
+
+    /// Puts every channel back to its power on state, the clock rates,
+    /// volumes, filters, quality and which channels are muted or tapped
+    /// stay. Expansion chips get detached, the
+    /// [Nes](crate::devices::nes::Nes) attaches fresh ones from the
+    /// cartrige.
+    pub(crate) fn power_cycle(&mut self) {
+        let old = std::mem::take(self);
+        *self = Self {
+            cpu_clock_frequency: old.cpu_clock_frequency,
+            apu_sample_rate: old.apu_sample_rate,
+            channel_enabled: old.channel_enabled,
+            is_tapping_channels: old.is_tapping_channels,
+            expansion_volumes: old.expansion_volumes,
+            filters: old.filters,
+            quality: old.quality,
+            region: old.region,
+            ..Self::new()
+        };
+    }
+
+    /// Switches the frame sequencer and the clock the samples are taken
+    /// at, only [Nes::power_cycle](crate::Nes::power_cycle) should call this
+    pub(crate) fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.cpu_clock_frequency = region.cpu_clock();
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
+
+    /// How full the sample queue is, from 0 to 1. Close to 0 means the
+    /// audio is about to crackle and close to 1 that samples are about to
+    /// get dropped.
+    pub fn get_queue_fill(&self) -> f32 {
+        self.sample_queue.len() as f32 / SAMPLE_QUEUE_SIZE as f32
+    }
 
     pub fn read_register(&mut self, address: u16, peek: bool) -> u8 {
         if address != 0x4015 {
@@ -88,7 +204,9 @@ impl Apu {
             status_register::ENABLE_PULSE2,
             self.pulse2.is_length_counter_non_zero(),
         );
+        value.set_flag_enabled(status_register::ENABLE_DMC, self.dmc.is_active());
         value.set_flag_enabled(status_register::FRAME_INTERRUPT, self.frame_interrupt_flag);
+        value.set_flag_enabled(status_register::DMC_INTERRUPT, self.dmc.is_irq_flag_set());
         self.frame_interrupt_flag = false;
         self.sync_irq_line();
         value
@@ -99,6 +217,7 @@ impl Apu {
             0x4000..0x4004 => self.pulse1.write_register(address, value),
             0x4004..0x4008 => self.pulse2.write_register(address, value),
             0x4008..0x400C => self.triangle.write_register(address, value),
+            0x4010..=0x4013 => self.dmc.write_register(address, value, self.region),
             0x4015 => {
                 self.pulse1
                     .set_enabled(value.get_flag_enabled(status_register::ENABLE_PULSE1));
@@ -106,6 +225,8 @@ impl Apu {
                     .set_enabled(value.get_flag_enabled(status_register::ENABLE_PULSE2));
                 self.triangle
                     .set_enabled(value.get_flag_enabled(status_register::ENABLE_TRIANGLE));
+                self.dmc
+                    .set_enabled(value.get_flag_enabled(status_register::ENABLE_DMC));
             }
             0x4017 => {
                 self.interrupt_inhibit_flag =
@@ -122,13 +243,160 @@ impl Apu {
         }
     }
 
+    /// What every channel is doing right now, see [debug]
+    pub fn debug_state(&self) -> ApuDebugState {
+        ApuDebugState {
+            pulse1: self.pulse1.debug_state(),
+            pulse2: self.pulse2.debug_state(),
+            triangle: self.triangle.debug_state(),
+            five_step_mode: self.sequencer_mode_flag,
+            interrupt_inhibit: self.interrupt_inhibit_flag,
+            frame_interrupt: self.frame_interrupt_flag,
+        }
+    }
+
+    /// Muted channels keep running, they just don't get mixed into the
+    /// output. All channels start enabled.
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.channel_enabled[channel as usize] = enabled;
+    }
+
+    pub fn is_channel_enabled(&self, channel: Channel) -> bool {
+        self.channel_enabled[channel as usize]
+    }
+
+    /// Mutes every channel except `channel`
+    pub fn solo_channel(&mut self, channel: Channel) {
+        for other in Channel::ALL {
+            self.set_channel_enabled(other, other == channel);
+        }
+    }
+
+    /// While enabled every channel also gets its own queue of samples,
+    /// at the same rate as the mixed ones and with the channel mixed on
+    /// its own. They are there for drawing channel visualizers or
+    /// ripping the channels separately, and include muted channels.
+    pub fn set_channel_taps_enabled(&mut self, enabled: bool) {
+        self.is_tapping_channels = enabled;
+        if !enabled {
+            self.channel_taps.iter_mut().for_each(VecDeque::clear);
+            self.channel_totals = [0.0; CHANNEL_COUNT];
+        }
+    }
+
+    /// Takes the samples of `channel` collected since the last call, see
+    /// [Apu::set_channel_taps_enabled]
+    pub fn take_channel_samples(&mut self, channel: Channel) -> Vec<f32> {
+        self.channel_taps[channel as usize].drain(..).collect()
+    }
+
+    /// Mixes a cartrige sound chip in with the apu. It gets clocked with
+    /// the apu and sees the cpu writes to the cartrige through
+    /// [Apu::write_expansion_register].
+    pub fn attach_expansion_audio(&mut self, audio: Box<dyn ExpansionAudio>) {
+        self.expansion.push(audio);
+    }
+
+    pub fn detach_expansion_audio(&mut self) {
+        self.expansion.clear();
+    }
+
+    pub fn expansion_chips(&self) -> Vec<ExpansionChip> {
+        self.expansion.iter().map(|audio| audio.chip()).collect()
+    }
+
+    pub fn has_expansion_audio(&self) -> bool {
+        !self.expansion.is_empty()
+    }
+
+    /// The volume of an expansion chip next to the apu, 1 is how loud it
+    /// is on hardware and 0 mutes it. Every chip starts at 1 and keeps its
+    /// volume when another game gets inserted.
+    pub fn set_expansion_volume(&mut self, chip: ExpansionChip, volume: f32) {
+        self.expansion_volumes[chip as usize] = volume.max(0.0);
+    }
+
+    pub fn get_expansion_volume(&self, chip: ExpansionChip) -> f32 {
+        self.expansion_volumes[chip as usize]
+    }
+
+    /// The filters the mixed samples go through before they get queued,
+    /// like `FilterPreset::Famicom.into()`. By default there are none, the
+    /// channel taps never get filtered.
+    pub fn set_audio_filters(&mut self, filters: AudioFilters) {
+        self.filters = filters;
+    }
+
+    pub fn get_audio_filters(&self) -> &AudioFilters {
+        &self.filters
+    }
+
+    /// See [ApuQuality], kept across power cycles like the filters
+    pub fn set_quality(&mut self, quality: ApuQuality) {
+        self.quality = quality;
+    }
+
+    pub fn get_quality(&self) -> ApuQuality {
+        self.quality
+    }
+
+    pub fn write_expansion_register(&mut self, address: u16, value: u8) {
+        for audio in self.expansion.iter_mut() {
+            audio.write_register(address, value);
+        }
+    }
+
+    /// What the dmc's sample fetches do to the cpu this cycle, the
+    /// [Nes](crate::Nes) asks after every [Apu::tick]. Fetches land on
+    /// the cycles the apu doesn't clock the channels on.
+    pub(crate) fn next_dmc_dma_cycle(&mut self) -> DmcDmaCycle {
+        // the cycle count is already past the cycle that just ran
+        let is_get_cycle = self.cpu_total_cycles.is_multiple_of(2);
+        self.dmc.next_dma_cycle(is_get_cycle)
+    }
+
+    /// The byte read for a [DmcDmaCycle::Fetch]
+    pub(crate) fn fill_dmc_buffer(&mut self, sample: u8) {
+        self.dmc.fill_buffer(sample);
+    }
+
     // TODO: fix this later
     fn sync_irq_line(&mut self) {}
 
-    /// https://www.nesdev.org/wiki/APU_Mixer
     fn mix(&mut self) -> f32 {
-        let pulse1 = self.pulse1.next().unwrap();
-        let pulse2 = self.pulse2.next().unwrap();
+        let mut levels = [0; CHANNEL_COUNT];
+        levels[Channel::Pulse1 as usize] = self.pulse1.next().unwrap();
+        levels[Channel::Pulse2 as usize] = self.pulse2.next().unwrap();
+        levels[Channel::Triangle as usize] = self.triangle.next().unwrap();
+        levels[Channel::Dmc as usize] = self.dmc.next().unwrap();
+
+        if self.is_tapping_channels {
+            for (i, total) in self.channel_totals.iter_mut().enumerate() {
+                let mut alone = [0; CHANNEL_COUNT];
+                alone[i] = levels[i];
+                *total += Self::mix_levels(alone);
+            }
+        }
+
+        for (level, enabled) in levels.iter_mut().zip(self.channel_enabled) {
+            if !enabled {
+                *level = 0;
+            }
+        }
+        Self::mix_levels(levels) + self.mix_expansion()
+    }
+
+    fn mix_expansion(&self) -> f32 {
+        self.expansion
+            .iter()
+            .map(|audio| audio.output() * self.expansion_volumes[audio.chip() as usize])
+            .sum()
+    }
+
+    /// https://www.nesdev.org/wiki/APU_Mixer
+    fn mix_levels(levels: [u8; CHANNEL_COUNT]) -> f32 {
+        let pulse1 = levels[Channel::Pulse1 as usize];
+        let pulse2 = levels[Channel::Pulse2 as usize];
 
         let pulse_out = if pulse1 + pulse2 == 0 {
             0.0
@@ -136,9 +404,9 @@ impl Apu {
             95.88 / ((8128.0 / (pulse1 as f32 + pulse2 as f32)) + 100.0)
         };
 
-        let triangle = self.triangle.next().unwrap();
-        let noise: u8 = 0;
-        let dmc: u8 = 0;
+        let triangle = levels[Channel::Triangle as usize];
+        let noise = levels[Channel::Noise as usize];
+        let dmc = levels[Channel::Dmc as usize];
 
         let tnd_out = if triangle + noise + dmc == 0 {
             0.0
@@ -168,34 +436,24 @@ impl Apu {
             self.apu_total_cycles += 1;
         }
 
-        if !self.sequencer_mode_flag {
-            if self.apu_total_cycles >= 14915 {
-                self.apu_total_cycles = 0;
-            }
+        let [first, second, third, fourth, fifth] = self.region.frame_sequencer_steps();
+        let last = if self.sequencer_mode_flag {
+            fifth
         } else {
-            if self.apu_total_cycles >= 18641 {
-                self.apu_total_cycles = 0;
-            }
+            fourth
+        };
+        if self.apu_total_cycles > last {
+            self.apu_total_cycles = 0;
         }
 
         #[rustfmt::skip]
         let mut apu_tick = if is_apu_cycle {
             ApuTick::default()
-        } else if !self.sequencer_mode_flag {
-            match self.apu_total_cycles {
-                3728  => ApuTick { is_quarter_frame: true, is_half_frame: false, ..ApuTick::default() },
-                7456  => ApuTick { is_quarter_frame: true, is_half_frame: true,  ..ApuTick::default() },
-                11185 => ApuTick { is_quarter_frame: true, is_half_frame: false, ..ApuTick::default() },
-                14914 => ApuTick { is_quarter_frame: true, is_half_frame: true,  ..ApuTick::default() },
-                _     => ApuTick::default(),
-            }
         } else {
             match self.apu_total_cycles {
-                3728  => ApuTick { is_quarter_frame: true, is_half_frame: false, ..ApuTick::default() },
-                7456  => ApuTick { is_quarter_frame: true, is_half_frame: true,  ..ApuTick::default() },
-                11185 => ApuTick { is_quarter_frame: true, is_half_frame: false, ..ApuTick::default() },
-                18640 => ApuTick { is_quarter_frame: true, is_half_frame: true,  ..ApuTick::default() },
-                _     => ApuTick::default(),
+                cycle if cycle == first || cycle == third => ApuTick { is_quarter_frame: true, is_half_frame: false, ..ApuTick::default() },
+                cycle if cycle == second || cycle == last => ApuTick { is_quarter_frame: true, is_half_frame: true,  ..ApuTick::default() },
+                _ => ApuTick::default(),
             }
         };
 
@@ -207,8 +465,9 @@ impl Apu {
 
         if !self.sequencer_mode_flag
             && !self.interrupt_inhibit_flag
-            && (self.apu_total_cycles == 14914 || (self.apu_total_cycles == 0 && is_apu_cycle))
+            && (self.apu_total_cycles == fourth || (self.apu_total_cycles == 0 && is_apu_cycle))
         {
+            tracing::trace!(target: targets::APU, "frame interrupt");
             self.frame_interrupt_flag = true;
         }
         self.sync_irq_line();
@@ -216,23 +475,43 @@ impl Apu {
         self.pulse1.tick(apu_tick);
         self.pulse2.tick(apu_tick);
         self.triangle.tick(apu_tick);
+        self.dmc.tick();
+        for audio in self.expansion.iter_mut() {
+            audio.tick();
+        }
 
-        self.sampled_sound_total += self.mix();
-        self.collected_samples += 1;
         self.sample_timer += 1.0;
-
         let cycles_per_sample = self.cpu_clock_frequency as f32 / self.apu_sample_rate as f32;
+        let is_sample_due = self.sample_timer >= cycles_per_sample;
+
+        if self.quality == ApuQuality::High || is_sample_due {
+            self.sampled_sound_total += self.mix();
+            self.collected_samples += 1;
+        }
 
-        if self.sample_timer >= cycles_per_sample {
+        if is_sample_due {
             self.sample_timer -= cycles_per_sample;
 
-            let out = self.sampled_sound_total / self.collected_samples as f32;
+            let out = self.filters.process(
+                self.sampled_sound_total / self.collected_samples as f32,
+                self.apu_sample_rate as f32,
+            );
 
             if self.sample_queue.len() >= SAMPLE_QUEUE_SIZE {
                 self.sample_queue.pop_front();
             }
             self.sample_queue.push_back(out);
 
+            if self.is_tapping_channels {
+                for (tap, total) in self.channel_taps.iter_mut().zip(&mut self.channel_totals) {
+                    if tap.len() >= SAMPLE_QUEUE_SIZE {
+                        tap.pop_front();
+                    }
+                    tap.push_back(*total / self.collected_samples as f32);
+                    *total = 0.0;
+                }
+            }
+
             self.sampled_sound_total = 0.0;
             self.collected_samples = 0;
         }
@@ -248,3 +527,53 @@ impl Iterator for Apu {
         self.sample_queue.pop_front()
     }
 }
+
+/// The clock and sample rate config, the channel mutes, the volumes and
+/// the filters are left alone and any samples that were still queued get
+/// dropped on load, so the audio backend doesn't play sound from before
+/// the state was loaded
+impl SaveState for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.pulse1.save_state(writer);
+        self.pulse2.save_state(writer);
+        self.triangle.save_state(writer);
+        writer.write_bool(self.sequencer_mode_flag);
+        writer.write_bool(self.interrupt_inhibit_flag);
+        writer.write_bool(self.frame_interrupt_flag);
+        writer.write_u64(self.cpu_total_cycles as u64);
+        writer.write_u64(self.apu_total_cycles as u64);
+        writer.write_bool(self.new_mode_flag);
+        writer.write_u64(self.new_mode_flag_cycle as u64);
+        writer.write_f32(self.sampled_sound_total);
+        writer.write_u32(self.collected_samples);
+        writer.write_f32(self.sample_timer);
+        for audio in self.expansion.iter() {
+            audio.save_state(writer);
+        }
+        self.dmc.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.pulse1.load_state(reader)?;
+        self.pulse2.load_state(reader)?;
+        self.triangle.load_state(reader)?;
+        self.sequencer_mode_flag = reader.read_bool()?;
+        self.interrupt_inhibit_flag = reader.read_bool()?;
+        self.frame_interrupt_flag = reader.read_bool()?;
+        self.cpu_total_cycles = reader.read_u64()? as usize;
+        self.apu_total_cycles = reader.read_u64()? as usize;
+        self.new_mode_flag = reader.read_bool()?;
+        self.new_mode_flag_cycle = reader.read_u64()? as usize;
+        self.sampled_sound_total = reader.read_f32()?;
+        self.collected_samples = reader.read_u32()?;
+        self.sample_timer = reader.read_f32()?;
+        for audio in self.expansion.iter_mut() {
+            audio.load_state(reader)?;
+        }
+        self.dmc.load_state(reader)?;
+        self.sample_queue.clear();
+        self.channel_taps.iter_mut().for_each(VecDeque::clear);
+        self.channel_totals = [0.0; CHANNEL_COUNT];
+        Ok(())
+    }
+}
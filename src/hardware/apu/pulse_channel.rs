@@ -1,7 +1,13 @@
-use crate::hardware::{
-    apu::{ApuTick, envelope::Envelope, length_counter::LengthCounter, sweep::Sweep},
-    bit_ops::BitOps,
-    constants::apu::{PULSE_WAVEFORMS, register0_flags, register2_flags, register3_flags},
+use crate::{
+    hardware::{
+        apu::{
+            ApuTick, debug::PulseState, envelope::Envelope, length_counter::LengthCounter,
+            sweep::Sweep,
+        },
+        bit_ops::BitOps,
+        constants::apu::{PULSE_WAVEFORMS, register0_flags, register2_flags, register3_flags},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
 };
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -93,6 +99,19 @@ impl PulseChannel {
         }
     }
 
+    pub fn debug_state(&self) -> PulseState {
+        PulseState {
+            duty: self.register0.get_bitfield(register0_flags::DUTY_CYCLE),
+            period: self.divider_period,
+            timer: self.divider_timer,
+            sequence_step: self.sequence_step,
+            envelope: self.envelope.debug_state(),
+            sweep: self.sweep.debug_state(),
+            length_counter: self.length_counter.debug_state(),
+            sweep_muted: self.sweep.is_muted(self.divider_period, self.channel_type),
+        }
+    }
+
     pub fn tick(&mut self, tick: ApuTick) {
         if tick.is_apu_cycle {
             if self.divider_timer == 0 {
@@ -124,3 +143,35 @@ impl Iterator for PulseChannel {
         Some(sequencer_output * not_muted * self.envelope.next()? * self.length_counter.next()?)
     }
 }
+
+/// `channel_type` is fixed when the apu gets created so it is not saved
+impl SaveState for PulseChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.waveform);
+        writer.write_u8(self.sequence_step);
+        writer.write_u16(self.divider_period);
+        writer.write_u16(self.divider_timer);
+        self.envelope.save_state(writer);
+        self.length_counter.save_state(writer);
+        self.sweep.save_state(writer);
+        writer.write_u8(self.register0);
+        writer.write_u8(self.register1);
+        writer.write_u8(self.register2);
+        writer.write_u8(self.register3);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.waveform = reader.read_u8()?;
+        self.sequence_step = reader.read_u8()? & 0b00000111;
+        self.divider_period = reader.read_u16()?;
+        self.divider_timer = reader.read_u16()?;
+        self.envelope.load_state(reader)?;
+        self.length_counter.load_state(reader)?;
+        self.sweep.load_state(reader)?;
+        self.register0 = reader.read_u8()?;
+        self.register1 = reader.read_u8()?;
+        self.register2 = reader.read_u8()?;
+        self.register3 = reader.read_u8()?;
+        Ok(())
+    }
+}
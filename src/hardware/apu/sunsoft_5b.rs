@@ -0,0 +1,238 @@
+//! The Sunsoft 5B is an FME-7 with a YM2149F (an AY-3-8910 clone) inside.
+//! Gimmick! only uses its three square channels, the noise and envelope
+//! are there for homebrew and nsf rips.
+//! https://www.nesdev.org/wiki/Sunsoft_5B_audio
+
+use better_default::Default;
+
+use crate::{
+    hardware::apu::expansion::{ExpansionAudio, ExpansionChip},
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+const REGISTER_COUNT: usize = 16;
+const ENVELOPE_STEPS: u8 = 32;
+/// A channel at full volume, about as loud as a pulse channel
+const CHANNEL_OUTPUT: f32 = 0.12;
+
+mod registers {
+    pub const NOISE_PERIOD: usize = 6;
+    pub const MIXER: usize = 7;
+    pub const VOLUME_A: usize = 8;
+    pub const ENVELOPE_PERIOD_LOW: usize = 11;
+    pub const ENVELOPE_PERIOD_HIGH: usize = 12;
+    pub const ENVELOPE_SHAPE: usize = 13;
+}
+
+mod envelope_shape {
+    pub const HOLD: u8 = 0b0001;
+    pub const ALTERNATE: u8 = 0b0010;
+    pub const ATTACK: u8 = 0b0100;
+    pub const CONTINUE: u8 = 0b1000;
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct Sunsoft5B {
+    /// Selected with $C000, writes with the upper nibble set select
+    /// nothing so $E000 writes get ignored
+    address: u8,
+    registers: [u8; REGISTER_COUNT],
+
+    /// The generators step once every 16 cpu cycles
+    prescaler: u8,
+    tone_timers: [u16; 3],
+    tone_outputs: [bool; 3],
+    /// Noise steps at half the tone rate
+    noise_half: bool,
+    noise_timer: u8,
+    #[default(1)]
+    noise_lfsr: u32,
+    envelope_timer: u16,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+impl Sunsoft5B {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let low = self.registers[channel * 2] as u16;
+        let high = (self.registers[channel * 2 + 1] & 0x0F) as u16;
+        ((high << 8) | low).max(1)
+    }
+
+    fn envelope_period(&self) -> u16 {
+        let low = self.registers[registers::ENVELOPE_PERIOD_LOW] as u16;
+        let high = self.registers[registers::ENVELOPE_PERIOD_HIGH] as u16;
+        ((high << 8) | low).max(1)
+    }
+
+    fn restart_envelope(&mut self) {
+        let shape = self.registers[registers::ENVELOPE_SHAPE];
+        self.envelope_timer = 0;
+        self.envelope_step = 0;
+        self.envelope_attack = shape & envelope_shape::ATTACK != 0;
+        self.envelope_holding = false;
+    }
+
+    /// Shapes without continue drop to 0 after one ramp, hold keeps the
+    /// last level (flipped by alternate) and alternate alone bounces
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        self.envelope_step += 1;
+        if self.envelope_step < ENVELOPE_STEPS {
+            return;
+        }
+        let shape = self.registers[registers::ENVELOPE_SHAPE];
+        self.envelope_step = 0;
+        if shape & envelope_shape::CONTINUE == 0 {
+            self.envelope_attack = false;
+            self.envelope_step = ENVELOPE_STEPS - 1;
+            self.envelope_holding = true;
+        } else if shape & envelope_shape::HOLD != 0 {
+            if shape & envelope_shape::ALTERNATE != 0 {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = ENVELOPE_STEPS - 1;
+            self.envelope_holding = true;
+        } else if shape & envelope_shape::ALTERNATE != 0 {
+            self.envelope_attack = !self.envelope_attack;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            ENVELOPE_STEPS - 1 - self.envelope_step
+        }
+    }
+
+    /// Volumes are 4 bit and the envelope is 5 bit, both go up in 1.5dB
+    /// steps on the same scale
+    fn channel_level(&self, channel: usize) -> u8 {
+        let volume = self.registers[registers::VOLUME_A + channel];
+        if volume & 0x10 != 0 {
+            self.envelope_level()
+        } else if volume & 0x0F == 0 {
+            0
+        } else {
+            (volume & 0x0F) * 2 + 1
+        }
+    }
+}
+
+impl ExpansionAudio for Sunsoft5B {
+    fn chip(&self) -> ExpansionChip {
+        ExpansionChip::Sunsoft5B
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0xC000..0xE000 => self.address = value,
+            0xE000.. if (self.address as usize) < REGISTER_COUNT => {
+                self.registers[self.address as usize] = value;
+                if self.address as usize == registers::ENVELOPE_SHAPE {
+                    self.restart_envelope();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn tick(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < 16 {
+            return;
+        }
+        self.prescaler = 0;
+
+        for channel in 0..3 {
+            self.tone_timers[channel] += 1;
+            if self.tone_timers[channel] >= self.tone_period(channel) {
+                self.tone_timers[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        self.noise_half = !self.noise_half;
+        if self.noise_half {
+            self.noise_timer += 1;
+            if self.noise_timer >= (self.registers[registers::NOISE_PERIOD] & 0x1F).max(1) {
+                self.noise_timer = 0;
+                let feedback = (self.noise_lfsr ^ (self.noise_lfsr >> 3)) & 1;
+                self.noise_lfsr = (self.noise_lfsr >> 1) | (feedback << 16);
+            }
+        }
+
+        self.envelope_timer += 1;
+        if self.envelope_timer >= self.envelope_period() {
+            self.envelope_timer = 0;
+            self.clock_envelope();
+        }
+    }
+
+    /// A channel with both its tone and noise turned off in the mixer
+    /// outputs its volume as a constant level
+    fn output(&self) -> f32 {
+        let mixer = self.registers[registers::MIXER];
+        let noise = self.noise_lfsr & 1 != 0;
+        (0..3)
+            .filter(|&channel| {
+                let tone_off = mixer & (1 << channel) != 0;
+                let noise_off = mixer & (1 << (channel + 3)) != 0;
+                (self.tone_outputs[channel] || tone_off) && (noise || noise_off)
+            })
+            .map(|channel| match self.channel_level(channel) {
+                0 => 0.0,
+                level => CHANNEL_OUTPUT * 10f32.powf((level as f32 - 31.0) * 1.5 / 20.0),
+            })
+            .sum()
+    }
+
+    fn clone_box(&self) -> Box<dyn ExpansionAudio> {
+        Box::new(self.clone())
+    }
+}
+
+impl SaveState for Sunsoft5B {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.address);
+        writer.write_bytes(&self.registers);
+        writer.write_u8(self.prescaler);
+        for (timer, output) in self.tone_timers.iter().zip(self.tone_outputs) {
+            writer.write_u16(*timer);
+            writer.write_bool(output);
+        }
+        writer.write_bool(self.noise_half);
+        writer.write_u8(self.noise_timer);
+        writer.write_u32(self.noise_lfsr);
+        writer.write_u16(self.envelope_timer);
+        writer.write_u8(self.envelope_step);
+        writer.write_bool(self.envelope_attack);
+        writer.write_bool(self.envelope_holding);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.address = reader.read_u8()?;
+        reader.read_bytes_into("5B registers", &mut self.registers)?;
+        self.prescaler = reader.read_u8()? % 16;
+        for channel in 0..3 {
+            self.tone_timers[channel] = reader.read_u16()?;
+            self.tone_outputs[channel] = reader.read_bool()?;
+        }
+        self.noise_half = reader.read_bool()?;
+        self.noise_timer = reader.read_u8()?;
+        self.noise_lfsr = (reader.read_u32()? & 0x1FFFF).max(1);
+        self.envelope_timer = reader.read_u16()?;
+        self.envelope_step = reader.read_u8()? % ENVELOPE_STEPS;
+        self.envelope_attack = reader.read_bool()?;
+        self.envelope_holding = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
-use crate::hardware::{
-    apu::pulse_channel::PulseChannelType, bit_ops::BitOps, constants::apu::register1_flags,
+use crate::{
+    hardware::{
+        apu::{debug::SweepState, pulse_channel::PulseChannelType},
+        bit_ops::BitOps,
+        constants::apu::register1_flags,
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
 };
 
 /// implementation of this: https://www.nesdev.org/wiki/APU_Sweep
@@ -37,6 +42,15 @@ impl Sweep {
         pulse_timer_period < 8 || self.target_period(pulse_timer_period, channel) > 0x7FF
     }
 
+    pub fn debug_state(&self) -> SweepState {
+        SweepState {
+            enabled: self.enabled_flag,
+            negate: self.negate_flag,
+            shift: self.shift_count,
+            period: self.divier_period,
+        }
+    }
+
     pub fn tick(&mut self, pulse_timer_period: &mut u16, channel: PulseChannelType) {
         if self.divier_timer == 0
             && self.enabled_flag
@@ -54,3 +68,24 @@ impl Sweep {
         }
     }
 }
+
+impl SaveState for Sweep {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.reload_flag);
+        writer.write_bool(self.enabled_flag);
+        writer.write_bool(self.negate_flag);
+        writer.write_u8(self.shift_count);
+        writer.write_u8(self.divier_timer);
+        writer.write_u8(self.divier_period);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.reload_flag = reader.read_bool()?;
+        self.enabled_flag = reader.read_bool()?;
+        self.negate_flag = reader.read_bool()?;
+        self.shift_count = reader.read_u8()?;
+        self.divier_timer = reader.read_u8()?;
+        self.divier_period = reader.read_u8()?;
+        Ok(())
+    }
+}
@@ -1,9 +1,14 @@
 use better_default::Default;
 
-use crate::hardware::{
-    apu::{ApuTick, length_counter::LengthCounter},
-    bit_ops::BitOps,
-    constants::apu::{TRIANGLE_WAVEFORMS, register2_flags, register3_flags, triangle_register0},
+use crate::{
+    hardware::{
+        apu::{ApuTick, debug::TriangleState, length_counter::LengthCounter},
+        bit_ops::BitOps,
+        constants::apu::{
+            TRIANGLE_WAVEFORMS, register2_flags, register3_flags, triangle_register0,
+        },
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
 };
 
 /// implementation of: https://www.nesdev.org/wiki/APU_Triangle
@@ -77,6 +82,23 @@ impl TriangleChannel {
         }
     }
 
+    pub fn debug_state(&self) -> TriangleState {
+        TriangleState {
+            // the divider period is kept one higher
+            period: u16::from_le_bytes([
+                self.register2,
+                self.register3.get_bitfield(register3_flags::TIMER_HIGH),
+            ]),
+            timer: self.divider_timer,
+            sequence_step: self.waveform_index as u8,
+            linear_counter: self.linear_timer,
+            linear_reload: self.linear_period,
+            linear_reload_flag: self.linear_reload_flag,
+            control: self.control_flag,
+            length_counter: self.length_counter.debug_state(),
+        }
+    }
+
     pub fn tick(&mut self, tick: ApuTick) {
         if tick.is_quarter_frame {
             if self.linear_reload_flag {
@@ -119,3 +141,34 @@ impl Iterator for TriangleChannel {
         )
     }
 }
+
+impl SaveState for TriangleChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.control_flag);
+        writer.write_bool(self.linear_reload_flag);
+        writer.write_u16(self.divider_period);
+        writer.write_u16(self.divider_timer);
+        writer.write_u8(self.linear_period);
+        writer.write_u8(self.linear_timer);
+        writer.write_u8(self.waveform_index as u8);
+        self.length_counter.save_state(writer);
+        writer.write_u8(self.register0);
+        writer.write_u8(self.register2);
+        writer.write_u8(self.register3);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.control_flag = reader.read_bool()?;
+        self.linear_reload_flag = reader.read_bool()?;
+        self.divider_period = reader.read_u16()?;
+        self.divider_timer = reader.read_u16()?;
+        self.linear_period = reader.read_u8()?;
+        self.linear_timer = reader.read_u8()?;
+        self.waveform_index = reader.read_u8()? as usize % TRIANGLE_WAVEFORMS.len();
+        self.length_counter.load_state(reader)?;
+        self.register0 = reader.read_u8()?;
+        self.register2 = reader.read_u8()?;
+        self.register3 = reader.read_u8()?;
+        Ok(())
+    }
+}
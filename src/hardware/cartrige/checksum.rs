@@ -0,0 +1,82 @@
+use std::fmt::Display;
+
+use serde::{Serialize, ser::SerializeStruct};
+use sha1::{Digest, Sha1};
+
+/// CRC32 and SHA-1 of a block of rom data. These are the same hashes
+/// used by No-Intro, so they can be used to verify a dump or look it up
+/// in a game database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RomChecksum {
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+}
+
+impl RomChecksum {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::from_chunks(&[bytes])
+    }
+
+    /// Hashes all the chunks as if they were one continuous block
+    pub fn from_chunks(chunks: &[&[u8]]) -> Self {
+        let mut crc32 = crc32fast::Hasher::new();
+        let mut sha1 = Sha1::new();
+        for chunk in chunks {
+            crc32.update(chunk);
+            sha1.update(chunk);
+        }
+
+        Self {
+            crc32: crc32.finalize(),
+            sha1: sha1.finalize().into(),
+        }
+    }
+
+    pub fn sha1_hex(&self) -> String {
+        self.sha1.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+}
+
+/// Serialized as hex strings, the same way they are shown in game databases
+impl Serialize for RomChecksum {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RomChecksum", 2)?;
+        state.serialize_field("crc32", &format!("{:08X}", self.crc32))?;
+        state.serialize_field("sha1", &self.sha1_hex())?;
+        state.end()
+    }
+}
+
+impl Display for RomChecksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CRC32: {:08X} SHA-1: {}", self.crc32, self.sha1_hex())
+    }
+}
+
+/// Checksums of the different parts of a cartrige. None of them include
+/// the iNES header or the trainer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Checksums {
+    pub prg: RomChecksum,
+    pub chr: RomChecksum,
+    /// PRG followed by CHR, this is what No-Intro lists for headerless roms
+    pub rom: RomChecksum,
+}
+
+impl Checksums {
+    pub fn new(prg_mem: &[u8], chr_mem: &[u8]) -> Self {
+        Self {
+            prg: RomChecksum::from_bytes(prg_mem),
+            chr: RomChecksum::from_bytes(chr_mem),
+            rom: RomChecksum::from_chunks(&[prg_mem, chr_mem]),
+        }
+    }
+}
+
+impl Display for Checksums {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "PRG {}", self.prg)?;
+        writeln!(f, "CHR {}", self.chr)?;
+        write!(f, "ROM {}", self.rom)
+    }
+}
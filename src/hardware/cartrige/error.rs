@@ -6,6 +6,52 @@ pub enum CartrigeParseError {
     MissingMagicNumbersError,
     #[error("Was trying to read {_0} bytes but the data was too short!")]
     NotEnoughBytesError(usize),
+    #[error("The new header can't add or remove the trainer of an existing rom!")]
+    TrainerMismatchError,
     #[error("Unknown mapper id: {_0}!")]
     UnknownMapperIdError(u8),
+    #[error(
+        "The file is cut off in the {_0}: it starts at byte {_1} and should be {_2} bytes long but only {_3} bytes are left!"
+    )]
+    TruncatedError(&'static str, usize, usize, usize),
+    #[error(
+        "The file has {_0} bytes after the {_1} bytes the header describes. It's probably an overdump, it loads without strict loading!"
+    )]
+    OverdumpError(usize, usize),
+    #[error("A {_0} byte binary loaded at ${_1:04X} doesn't fit in $6000-$FFFF!")]
+    FlatBinaryError(usize, u16),
+    #[error("Couldn't apply the patch: {_0}")]
+    PatchError(#[from] PatchError),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SramError {
+    #[error("Got an io error while reading or writing a save file:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("There is no cartrige inserted!")]
+    NoCartrigeError,
+    #[error("The cartrige doesn't have battery backed ram!")]
+    NoBatteryError,
+    #[error("The save file is {_0} bytes but the cartrige only has {_1} bytes of ram!")]
+    TooLargeError(usize, usize),
+    #[error("The flash save is {_0} bytes but the cartrige has {_1} bytes of flash!")]
+    FlashSizeError(usize, usize),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PatchError {
+    #[error("Got an io error while reading a patch:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("The patch isn't an IPS or BPS file!")]
+    UnknownFormatError,
+    #[error("The patch is cut off at byte {_0}!")]
+    TruncatedError(usize),
+    #[error("The number at byte {_0} of the patch is too big!")]
+    InvalidNumberError(usize),
+    #[error("The action at byte {_0} of the patch points outside the rom!")]
+    InvalidOffsetError(usize),
+    #[error("The patch expects a {_0} byte rom but the rom is {_1} bytes!")]
+    SourceSizeError(usize, usize),
+    #[error("The crc32 of the {_0} doesn't match the {_1:08X} in the patch!")]
+    ChecksumError(&'static str, u32),
 }
@@ -0,0 +1,90 @@
+//! Raw 6502 binaries without an iNES header, the way most assemblers spit
+//! them out. They get put on a made up NROM board with 32kb of prg rom at
+//! $8000, 8kb of prg ram at $6000 and chr ram, so the program can load at
+//! any address from $6000 up.
+
+use crate::hardware::{
+    cartrige::{Result, error::CartrigeParseError},
+    constants::cartrige::*,
+};
+
+/// Where the nmi, reset and irq vectors are
+const VECTORS_START: u16 = 0xFFFA;
+/// The RTI the nmi and irq vectors point to when nothing else handles them
+const RTI_ADDRESS: u16 = VECTORS_START - 1;
+const RTI: u8 = 0x40;
+/// What the rom is filled with where the binary doesn't reach
+const FILL: u8 = 0xFF;
+/// 32kb of prg rom and chr ram, 8kb of prg ram
+const HEADER: [u8; HEADER_SIZE] = [0x4E, 0x45, 0x53, 0x1A, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+
+/// Where a raw binary goes and where the cpu starts running it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatOptions {
+    /// The cpu address of the first byte of the binary, $6000-$FFFF
+    pub load_address: u16,
+    /// Vectors that are `None` keep what the binary has at $FFFA-$FFFF.
+    /// If the binary doesn't reach them the reset vector points to the
+    /// load address and the others to an RTI at $FFF9.
+    pub reset: Option<u16>,
+    pub nmi: Option<u16>,
+    pub irq: Option<u16>,
+}
+
+impl Default for FlatOptions {
+    fn default() -> Self {
+        Self {
+            load_address: 0x8000,
+            reset: None,
+            nmi: None,
+            irq: None,
+        }
+    }
+}
+
+/// The board in iNES format and what has to go into its prg ram
+pub(super) fn build(binary: &[u8], options: &FlatOptions) -> Result<(Vec<u8>, Vec<u8>)> {
+    let start = options.load_address as usize;
+    let end = start + binary.len();
+    if start < PRG_RAM_START as usize || end > 0x10000 {
+        return Err(CartrigeParseError::FlatBinaryError(
+            binary.len(),
+            options.load_address,
+        ));
+    }
+
+    // the ram starts out cleared like it is for other boards
+    let mut memory = vec![0; (PRG_RAM_END - PRG_RAM_START) as usize];
+    memory.resize(0x10000 - PRG_RAM_START as usize, FILL);
+    memory[start - PRG_RAM_START as usize..end - PRG_RAM_START as usize].copy_from_slice(binary);
+    let covers = |address: u16| (start..end).contains(&(address as usize));
+
+    let mut write = |address: u16, value: u8| memory[(address - PRG_RAM_START) as usize] = value;
+    // binaries that end right before the vectors keep their last byte,
+    // their interrupts go to the start of the program instead
+    let interrupt_fallback = if covers(RTI_ADDRESS) {
+        options.load_address
+    } else {
+        write(RTI_ADDRESS, RTI);
+        RTI_ADDRESS
+    };
+    let vectors = [
+        (VECTORS_START, options.nmi, interrupt_fallback),
+        (VECTORS_START + 2, options.reset, options.load_address),
+        (VECTORS_START + 4, options.irq, interrupt_fallback),
+    ];
+    for (vector, address, fallback) in vectors {
+        let address = match address {
+            Some(address) => address,
+            None if covers(vector) => continue,
+            None => fallback,
+        };
+        write(vector, address as u8);
+        write(vector + 1, (address >> 8) as u8);
+    }
+
+    let (ram, rom) = memory.split_at((PRG_RAM_END - PRG_RAM_START) as usize);
+    let mut ines = HEADER.to_vec();
+    ines.extend_from_slice(rom);
+    Ok((ines, ram.to_vec()))
+}
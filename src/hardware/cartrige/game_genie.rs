@@ -0,0 +1,153 @@
+//! The Game Genie plugs in between the console and the game, so two
+//! cartriges end up on the bus. After power on it is in its menu: its own
+//! rom answers for $8000-$FFFF and the pattern tables, and the codes typed
+//! in get written to its registers. Writing 0 to $8000 switches it to
+//! pass through, everything goes to the game from then on except cpu
+//! reads of the code addresses, which get the code's value instead. Resets
+//! keep it passing through, only power cycles bring the menu back.
+//! https://www.nesdev.org/wiki/Game_Genie
+
+use crate::{
+    hardware::cartrige::{Cartrige, cartrige_access::CartrigeAccess},
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+/// Codes the Game Genie holds at once
+pub const CODE_COUNT: usize = 3;
+
+/// A code as the Game Genie's menu wrote it to the registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GenieCode {
+    pub address: u16,
+    /// Only replace the byte if the game has this value there, for codes
+    /// on banks that get switched
+    pub compare: Option<u8>,
+    pub value: u8,
+}
+
+pub struct GameGenie {
+    /// the Game Genie's own rom, used while in the menu
+    rom: Box<Cartrige>,
+    /// $8000, bits 1-3 disable codes and bits 4-6 turn on their compare
+    control: u8,
+    /// (address, compare, value) of every code
+    registers: [(u16, u8, u8); CODE_COUNT],
+    is_passing_through: bool,
+}
+
+impl GameGenie {
+    /// `rom` is an iNES image of the Game Genie's rom
+    pub fn new(rom: Cartrige) -> Self {
+        Self {
+            rom: Box::new(rom),
+            control: 0,
+            registers: [(0x8000, 0, 0); CODE_COUNT],
+            is_passing_through: false,
+        }
+    }
+
+    /// False while the menu is up
+    pub fn is_passing_through(&self) -> bool {
+        self.is_passing_through
+    }
+
+    /// The codes that are on, nothing while the menu is up
+    pub fn get_codes(&self) -> Vec<GenieCode> {
+        self.codes().collect()
+    }
+
+    fn codes(&self) -> impl Iterator<Item = GenieCode> + '_ {
+        (0..CODE_COUNT)
+            .filter(|code| self.is_passing_through && self.control & (0x02 << code) == 0)
+            .map(|code| {
+                let (address, compare, value) = self.registers[code];
+                GenieCode {
+                    address,
+                    compare: (self.control & (0x10 << code) != 0).then_some(compare),
+                    value,
+                }
+            })
+    }
+
+    /// Back to the menu with no codes
+    pub(super) fn power_cycle(&mut self) {
+        self.control = 0;
+        self.registers = [(0x8000, 0, 0); CODE_COUNT];
+        self.is_passing_through = false;
+    }
+
+    /// True if the Game Genie answers `access` itself instead of the game
+    pub(super) fn is_in_menu(&self, access: &CartrigeAccess) -> bool {
+        !self.is_passing_through
+            && match *access {
+                CartrigeAccess::CpuAccess { address } => address >= 0x8000,
+                CartrigeAccess::PpuAccess { address } => address < 0x2000,
+            }
+    }
+
+    pub(super) fn get_rom_mut(&mut self) -> &mut Cartrige {
+        &mut self.rom
+    }
+
+    pub(super) fn map_nametable(&self, address: u16) -> u16 {
+        self.rom.map_nametable(address)
+    }
+
+    /// A write to the registers at $8000-$800C while in the menu
+    pub(super) fn write(&mut self, address: u16, value: u8) {
+        let Some(register) = address.checked_sub(0x8001).filter(|r| *r < 12) else {
+            if address == 0x8000 {
+                match value {
+                    0 => self.is_passing_through = true,
+                    _ => self.control = value,
+                }
+            }
+            return;
+        };
+        let code = &mut self.registers[register as usize / 4];
+        match register % 4 {
+            // the top bit of the address is always set
+            0 => code.0 = (code.0 & 0x00FF) | ((value as u16 | 0x80) << 8),
+            1 => code.0 = (code.0 & 0xFF00) | value as u16,
+            2 => code.1 = value,
+            _ => code.2 = value,
+        }
+    }
+
+    /// What the cpu reads at `address` when the game has `value` there
+    pub(super) fn apply_codes(&self, address: u16, value: Option<u8>) -> Option<u8> {
+        self.codes()
+            .find(|code| {
+                code.address == address && code.compare.is_none_or(|compare| value == Some(compare))
+            })
+            .map_or(value, |code| Some(code.value))
+    }
+}
+
+/// The Game Genie's rom gets saved too, it can have ram like any other
+/// cartrige
+impl SaveState for GameGenie {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.control);
+        for (address, compare, value) in self.registers {
+            writer.write_u16(address);
+            writer.write_u8(compare);
+            writer.write_u8(value);
+        }
+        writer.write_bool(self.is_passing_through);
+        self.rom.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.control = reader.read_u8()?;
+        for code in self.registers.iter_mut() {
+            *code = (
+                reader.read_u16()? | 0x8000,
+                reader.read_u8()?,
+                reader.read_u8()?,
+            );
+        }
+        self.is_passing_through = reader.read_bool()?;
+        self.rom.load_state(reader)
+    }
+}
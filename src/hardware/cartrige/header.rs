@@ -0,0 +1,341 @@
+use serde::Serialize;
+
+use crate::hardware::{
+    bit_ops::BitOps,
+    cartrige::{Result, error::CartrigeParseError},
+    constants::cartrige::*,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TvSystem {
+    Ntsc,
+    Pal,
+    DualCompatible,
+    Dendy,
+    Unknown(u8),
+}
+
+/// https://www.nesdev.org/wiki/Mirroring#Nametable_Mirroring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Nes2RamSizes {
+    pub prg_ram: usize,
+    pub prg_nvram: usize,
+    pub chr_ram: usize,
+    pub chr_nvram: usize,
+}
+
+/// The 16 byte header at the start of every iNES / NES 2.0 file
+///
+/// https://www.nesdev.org/wiki/INES
+/// https://www.nesdev.org/wiki/NES_2.0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    prg_size: u8,
+    chr_size: u8,
+    flags6: u8,
+    flags7: u8,
+    flags8: u8,
+    flags9: u8,
+    flags10: u8,
+    flags11: u8,
+    flags12: u8,
+    flags13: u8,
+    flags14: u8,
+    flags15: u8,
+}
+
+impl Header {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(CartrigeParseError::NotEnoughBytesError(HEADER_SIZE));
+        }
+
+        if bytes[0..4] != NES_MAGIC_NUMBERS {
+            return Err(CartrigeParseError::MissingMagicNumbersError);
+        }
+
+        Ok(Self {
+            prg_size: bytes[4],
+            chr_size: bytes[5],
+            flags6: bytes[6],
+            flags7: bytes[7],
+            flags8: bytes[8],
+            flags9: bytes[9],
+            flags10: bytes[10],
+            flags11: bytes[11],
+            flags12: bytes[12],
+            flags13: bytes[13],
+            flags14: bytes[14],
+            flags15: bytes[15],
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut out = [0; HEADER_SIZE];
+        out[0..4].copy_from_slice(&NES_MAGIC_NUMBERS);
+        out[4] = self.prg_size;
+        out[5] = self.chr_size;
+        out[6] = self.flags6;
+        out[7] = self.flags7;
+        out[8] = self.flags8;
+        out[9] = self.flags9;
+        out[10] = self.flags10;
+        out[11] = self.flags11;
+        out[12] = self.flags12;
+        out[13] = self.flags13;
+        out[14] = self.flags14;
+        out[15] = self.flags15;
+        out
+    }
+
+    /// Returns a copy of `rom` (a whole .nes file) with its header replaced
+    /// by this one. The trainer and rom data are kept as they are.
+    pub fn write_to_rom(&self, rom: &[u8]) -> Result<Vec<u8>> {
+        let old_header = Header::from_bytes(rom)?;
+        if old_header.get_has_trainer() != self.get_has_trainer() {
+            return Err(CartrigeParseError::TrainerMismatchError);
+        }
+
+        let mut out = rom.to_vec();
+        out[0..HEADER_SIZE].copy_from_slice(&self.to_bytes());
+        Ok(out)
+    }
+
+    pub fn prg_rom_size(&self) -> u8 {
+        self.prg_size
+    }
+
+    pub fn set_prg_rom_size(&mut self, prg_size: u8) {
+        self.prg_size = prg_size;
+    }
+
+    pub fn prg_chr_size(&self) -> u8 {
+        self.chr_size
+    }
+
+    pub fn set_chr_rom_size(&mut self, chr_size: u8) {
+        self.chr_size = chr_size;
+    }
+
+    pub fn prg_rom_size_bytes(&self) -> usize {
+        self.prg_size as usize * PRG_ROM_BANK_SIZE
+    }
+
+    pub fn chr_rom_size_bytes(&self) -> usize {
+        self.chr_size as usize * CHR_ROM_BANK_SIZE
+    }
+
+    /// All of the prg ram, battery backed or not. NES 2.0 headers give
+    /// the sizes exactly (0 is a board without ram), iNES ones count 8kb
+    /// banks with 0 meaning one bank.
+    pub fn prg_ram_size_bytes(&self) -> usize {
+        if let Some(sizes) = self.nes_2_0_ram_sizes() {
+            return sizes.prg_ram + sizes.prg_nvram;
+        }
+        let units = if self.flags8 == 0 {
+            1
+        } else {
+            self.flags8 as usize
+        };
+        units * PRG_RAM_BANK_SIZE
+    }
+
+    pub fn get_nametable_arrangement(&self) -> u8 {
+        self.flags6 & FLAG6_NAMETABLE
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        if self.has_four_screen_vram() {
+            Mirroring::FourScreen
+        } else if self.get_nametable_arrangement() == 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        let (arrangement, four_screen) = match mirroring {
+            Mirroring::Horizontal => (false, false),
+            Mirroring::Vertical => (true, false),
+            Mirroring::FourScreen => (self.get_nametable_arrangement() != 0, true),
+        };
+        self.flags6.set_flag_enabled(FLAG6_NAMETABLE, arrangement);
+        self.flags6.set_flag_enabled(FLAG6_FOUR_SCREEN, four_screen);
+    }
+
+    pub fn get_mapper_id(&self) -> u8 {
+        (self.flags7 & 0xF0) | (self.flags6 >> 4)
+    }
+
+    pub fn set_mapper_id(&mut self, mapper_id: u8) {
+        self.flags6.set_bitfield(0xF0, mapper_id & 0x0F);
+        self.flags7.set_bitfield(0xF0, mapper_id >> 4);
+    }
+
+    /// The whole 12 bit mapper id, for iNES headers this is the same
+    /// as [Header::get_mapper_id]
+    pub fn get_full_mapper_id(&self) -> u16 {
+        if self.is_nes_2_0() {
+            ((self.flags8.get_bitfield(FLAG8_MAPPER_MSB) as u16) << 8) | self.get_mapper_id() as u16
+        } else {
+            self.get_mapper_id() as u16
+        }
+    }
+
+    /// Only NES 2.0 headers have submappers
+    pub fn get_submapper_id(&self) -> Option<u8> {
+        self.is_nes_2_0()
+            .then(|| self.flags8.get_bitfield(FLAG8_SUBMAPPER))
+    }
+
+    /// The sizes of the different rams in NES 2.0 headers
+    /// https://www.nesdev.org/wiki/NES_2.0#PRG-(NV)RAM/EEPROM
+    pub fn nes_2_0_ram_sizes(&self) -> Option<Nes2RamSizes> {
+        let size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+        self.is_nes_2_0().then(|| Nes2RamSizes {
+            prg_ram: size(self.flags10.get_bitfield(FLAG10_PRG_RAM_SHIFT)),
+            prg_nvram: size(self.flags10.get_bitfield(FLAG10_PRG_NVRAM_SHIFT)),
+            chr_ram: size(self.flags11.get_bitfield(FLAG11_CHR_RAM_SHIFT)),
+            chr_nvram: size(self.flags11.get_bitfield(FLAG11_CHR_NVRAM_SHIFT)),
+        })
+    }
+
+    /// Sets the sizes of the volatile and battery backed prg ram, upgrading
+    /// the header to NES 2.0 since iNES can't tell them apart. Sizes that
+    /// aren't a power of 2 get rounded up.
+    pub fn set_prg_ram_sizes(&mut self, prg_ram: usize, prg_nvram: usize) {
+        self.upgrade_to_nes_2_0();
+        let shift = |size: usize| {
+            if size == 0 {
+                0
+            } else {
+                // a shift of 0 is no ram so 128 bytes is the smallest
+                (size.div_ceil(64).next_power_of_two().trailing_zeros() as u8).max(1)
+            }
+        };
+        self.flags10
+            .set_bitfield(FLAG10_PRG_RAM_SHIFT, shift(prg_ram));
+        self.flags10
+            .set_bitfield(FLAG10_PRG_NVRAM_SHIFT, shift(prg_nvram));
+    }
+
+    /// https://www.nesdev.org/wiki/NES_2.0#Extended_Console_Type
+    pub fn get_console_type(&self) -> Option<u8> {
+        self.is_nes_2_0()
+            .then(|| self.flags13.get_bitfield(FLAG13_CONSOLE_TYPE))
+    }
+
+    pub fn get_misc_rom_count(&self) -> Option<u8> {
+        self.is_nes_2_0()
+            .then(|| self.flags14.get_bitfield(FLAG14_MISC_ROMS))
+    }
+
+    /// https://www.nesdev.org/wiki/NES_2.0#Default_Expansion_Device
+    pub fn get_default_expansion_device(&self) -> Option<u8> {
+        self.is_nes_2_0()
+            .then(|| self.flags15.get_bitfield(FLAG15_EXPANSION_DEVICE))
+    }
+
+    pub fn has_battery_backed_ram(&self) -> bool {
+        self.flags6 & FLAG6_BATTERY != 0
+    }
+
+    pub fn set_battery_backed_ram(&mut self, battery: bool) {
+        self.flags6.set_flag_enabled(FLAG6_BATTERY, battery);
+    }
+
+    pub fn has_four_screen_vram(&self) -> bool {
+        self.flags6 & FLAG6_FOUR_SCREEN != 0
+    }
+
+    pub fn get_has_trainer(&self) -> bool {
+        self.flags6 & FLAG6_TRAINER != 0
+    }
+
+    pub fn is_vs_unisystem(&self) -> bool {
+        self.flags7 & FLAG7_VS_UNISYSTEM != 0
+    }
+
+    pub fn is_playchoice_10(&self) -> bool {
+        self.flags7 & FLAG7_PLAYCHOICE_10 != 0
+    }
+
+    pub fn is_nes_2_0(&self) -> bool {
+        self.flags7 & FLAG7_NES2_SIGNATURE_MASK == FLAG7_NES2_SIGNATURE_VALUE
+    }
+
+    /// Converts an iNES header to a NES 2.0 one, keeping all the
+    /// information the iNES header had. Does nothing if the header
+    /// already is NES 2.0.
+    pub fn upgrade_to_nes_2_0(&mut self) {
+        if self.is_nes_2_0() {
+            return;
+        }
+
+        let tv_system = self.tv_system();
+
+        // https://www.nesdev.org/wiki/NES_2.0#PRG-(NV)RAM/EEPROM
+        // sizes are stored as a shift count: 64 << shift
+        let prg_ram_shift = (self.prg_ram_size_bytes() / 64).trailing_zeros() as u8;
+        let chr_ram_shift = if self.chr_size == 0 {
+            (CHR_ROM_BANK_SIZE / 64).trailing_zeros() as u8
+        } else {
+            0
+        };
+
+        self.flags7
+            .set_bitmasked(FLAG7_NES2_SIGNATURE_MASK, FLAG7_NES2_SIGNATURE_VALUE);
+        self.flags8 = 0;
+        self.flags9 = 0;
+        self.flags10 = if self.has_battery_backed_ram() {
+            prg_ram_shift << 4
+        } else {
+            prg_ram_shift
+        };
+        self.flags11 = chr_ram_shift;
+        self.flags13 = 0;
+        self.flags14 = 0;
+        self.flags15 = 0;
+        self.set_tv_system(tv_system);
+    }
+
+    pub fn tv_system(&self) -> TvSystem {
+        if self.is_nes_2_0() {
+            match self.flags12 & FLAG12_TIMING_MASK {
+                0 => TvSystem::Ntsc,
+                1 => TvSystem::Pal,
+                2 => TvSystem::DualCompatible,
+                3 => TvSystem::Dendy,
+                other => TvSystem::Unknown(other),
+            }
+        } else if self.flags9 & FLAG9_TV_SYSTEM != 0 {
+            TvSystem::Pal
+        } else {
+            TvSystem::Ntsc
+        }
+    }
+
+    pub fn set_tv_system(&mut self, tv_system: TvSystem) {
+        if self.is_nes_2_0() {
+            let timing = match tv_system {
+                TvSystem::Ntsc => 0,
+                TvSystem::Pal => 1,
+                TvSystem::DualCompatible => 2,
+                TvSystem::Dendy => 3,
+                TvSystem::Unknown(other) => other,
+            };
+            self.flags12.set_bitmasked(FLAG12_TIMING_MASK, timing);
+        } else {
+            self.flags9
+                .set_flag_enabled(FLAG9_TV_SYSTEM, tv_system == TvSystem::Pal);
+        }
+    }
+}
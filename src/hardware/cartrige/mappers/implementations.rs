@@ -1,6 +1,14 @@
 use crate::{
     byte_size,
-    hardware::cartrige::{Header, Mapper, cartrige_access::CartrigeAccess},
+    hardware::{
+        apu::{expansion::ExpansionAudio, sunsoft_5b::Sunsoft5B},
+        cartrige::{
+            Header, Mapper, PrgRamAccess, cartrige_access::CartrigeAccess, mappers::A12Filter,
+        },
+        constants::cartrige::PRG_RAM_START,
+    },
+    save_state::{self, SaveState, StateReader, StateWriter, error::SaveStateError},
+    trace::targets,
 };
 
 mod mirroring {
@@ -37,28 +45,28 @@ impl Mapper for M000 {
         Self { header }
     }
 
-    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<u16> {
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize> {
         match cartrige_access {
             CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
             CartrigeAccess::CpuAccess { address } => {
-                let offset = address - 0x8000;
+                let offset = (address - 0x8000) as usize;
                 if self.header.prg_rom_size() == 1 {
                     Some(offset & 0x3FFF)
                 } else {
                     Some(offset)
                 }
             }
-            CartrigeAccess::PpuAccess { address } if address < 0x2000 => Some(address),
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => Some(address as usize),
             CartrigeAccess::PpuAccess { .. } => None,
         }
     }
 
-    fn map_write(&mut self, cartrige_access: CartrigeAccess, _: u8) -> Option<u16> {
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, _: u8) -> Option<usize> {
         match cartrige_access {
             CartrigeAccess::CpuAccess { .. } => None,
             CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
-                if self.header.chr_size == 0 {
-                    Some(address)
+                if self.header.prg_chr_size() == 0 {
+                    Some(address as usize)
                 } else {
                     None
                 }
@@ -70,6 +78,25 @@ impl Mapper for M000 {
     fn map_nametable(&self, address: u16) -> u16 {
         mirroring::from_header(&self.header, address)
     }
+
+    /// The whole rom is one bank, 16kb roms are mirrored so they also
+    /// show up at $C000 where the vectors are
+    fn prg_bank_size(&self) -> usize {
+        self.header.prg_rom_size_bytes().clamp(1, byte_size!(32 kb))
+    }
+
+    fn prg_bank_address(&self, _bank: usize, _bank_count: usize) -> u16 {
+        (0x10000 - self.prg_bank_size()) as u16
+    }
+}
+
+/// M000 has no registers so there is nothing to save
+impl SaveState for M000 {
+    fn save_state(&self, _: &mut StateWriter) {}
+
+    fn load_state(&mut self, _: &mut StateReader) -> save_state::Result<()> {
+        Ok(())
+    }
 }
 
 pub(super) struct M002 {
@@ -88,31 +115,32 @@ impl Mapper for M002 {
         }
     }
 
-    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<u16> {
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize> {
         match cartrige_access {
             CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
             CartrigeAccess::CpuAccess { address } if address < 0xC000 => {
-                Some((self.selected_bank) as u16 * byte_size!(16 kb) as u16 + (address & 0x3FFF))
+                Some(self.selected_bank as usize * byte_size!(16 kb) + (address & 0x3FFF) as usize)
             }
             CartrigeAccess::CpuAccess { address } => Some(
-                (self.header.prg_rom_size() - 1) as u16 * byte_size!(16 kb) as u16
-                    + (address & 0x3FFF),
+                (self.header.prg_rom_size() - 1) as usize * byte_size!(16 kb)
+                    + (address & 0x3FFF) as usize,
             ),
-            CartrigeAccess::PpuAccess { address } if address < 0x2000 => Some(address),
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => Some(address as usize),
             CartrigeAccess::PpuAccess { .. } => None,
         }
     }
 
-    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<u16> {
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<usize> {
         match cartrige_access {
             CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
             CartrigeAccess::CpuAccess { .. } => {
                 self.selected_bank = value & 0x0F;
+                tracing::trace!(target: targets::MAPPER, bank = self.selected_bank, "M002 bank switch");
                 None
             }
             CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
-                if self.header.chr_size == 0 {
-                    Some(address)
+                if self.header.prg_chr_size() == 0 {
+                    Some(address as usize)
                 } else {
                     None
                 }
@@ -125,3 +153,1101 @@ impl Mapper for M002 {
         mirroring::from_header(&self.header, address)
     }
 }
+
+impl SaveState for M002 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.selected_bank);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.selected_bank = reader.read_u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+/// MMC1, registers are loaded one bit at a time through a shift register
+/// https://www.nesdev.org/wiki/MMC1
+pub(super) struct M001 {
+    pub header: Header,
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+impl M001 {
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..0xA000 => self.control = value,
+            0xA000..0xC000 => self.chr_bank_0 = value,
+            0xC000..0xE000 => self.chr_bank_1 = value,
+            _ => self.prg_bank = value,
+        }
+        tracing::trace!(target: targets::MAPPER, address, value, "M001 register write");
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        (self.header.prg_rom_size() as usize).max(1)
+    }
+
+    /// SUROM and SXROM use bit 4 of the chr register to pick a 256kb half
+    /// https://www.nesdev.org/wiki/MMC1#SNROM,_SOROM,_SUROM_and_SXROM
+    fn prg_outer_bank(&self) -> usize {
+        if self.prg_bank_count() > 16 {
+            (self.chr_bank_0 & 0x10) as usize
+        } else {
+            0
+        }
+    }
+
+    fn map_prg(&self, address: u16) -> usize {
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let last = (self.prg_bank_count() - 1).min(15);
+        let bank = match (self.control >> 2) & 0x03 {
+            0 | 1 => (bank & !1) + ((address >= 0xC000) as usize),
+            2 if address < 0xC000 => 0,
+            2 => bank,
+            _ if address < 0xC000 => bank,
+            _ => last,
+        };
+        let bank = (self.prg_outer_bank() + bank) % self.prg_bank_count();
+        bank * byte_size!(16 kb) + (address & 0x3FFF) as usize
+    }
+
+    fn map_chr(&self, address: u16) -> usize {
+        let chr_size = if self.header.prg_chr_size() == 0 {
+            byte_size!(8 kb)
+        } else {
+            self.header.chr_rom_size_bytes()
+        };
+        let offset = if self.control & 0x10 == 0 {
+            (self.chr_bank_0 & !1) as usize * byte_size!(4 kb) + address as usize
+        } else {
+            let bank = if address < 0x1000 {
+                self.chr_bank_0
+            } else {
+                self.chr_bank_1
+            };
+            bank as usize * byte_size!(4 kb) + (address & 0x0FFF) as usize
+        };
+        offset % chr_size
+    }
+}
+
+impl Mapper for M001 {
+    fn new(header: Header) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            header,
+            shift: 0,
+            shift_count: 0,
+            // the last bank starts fixed at $C000
+            control: 0x0C,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { address } => Some(self.map_prg(address)),
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                Some(self.map_chr(address))
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { .. } if value & 0x80 != 0 => {
+                self.shift = 0;
+                self.shift_count = 0;
+                self.control |= 0x0C;
+                None
+            }
+            CartrigeAccess::CpuAccess { address } => {
+                self.shift |= (value & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    self.write_register(address, self.shift);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+                None
+            }
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                if self.header.prg_chr_size() == 0 {
+                    Some(self.map_chr(address))
+                } else {
+                    None
+                }
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_nametable(&self, address: u16) -> u16 {
+        match self.control & 0x03 {
+            0 => address & !0x0C00,
+            1 => (address & !0x0C00) | 0x0400,
+            2 => mirroring::vertical(address),
+            _ => mirroring::horizontal(address),
+        }
+    }
+
+    /// Bit 4 of the prg register disables the ram on MMC1B and later.
+    /// SOROM has 16kb of ram picked with bit 3 of the chr register, SXROM
+    /// 32kb picked with bits 2 and 3.
+    fn map_prg_ram(&self, address: u16, ram_size: usize) -> PrgRamAccess {
+        if self.prg_bank & 0x10 != 0 {
+            return PrgRamAccess::Disabled;
+        }
+        let bank = if ram_size <= byte_size!(16 kb) {
+            ((self.chr_bank_0 >> 3) & 0x01) as usize
+        } else {
+            ((self.chr_bank_0 >> 2) & 0x03) as usize
+        };
+        let offset = bank * byte_size!(8 kb) + (address - PRG_RAM_START) as usize;
+        PrgRamAccess::ReadWrite(offset % ram_size)
+    }
+}
+
+impl SaveState for M001 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.shift);
+        writer.write_u8(self.shift_count);
+        writer.write_u8(self.control);
+        writer.write_u8(self.chr_bank_0);
+        writer.write_u8(self.chr_bank_1);
+        writer.write_u8(self.prg_bank);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.shift = reader.read_u8()? & 0x1F;
+        self.shift_count = reader.read_u8()? % 5;
+        self.control = reader.read_u8()? & 0x1F;
+        self.chr_bank_0 = reader.read_u8()? & 0x1F;
+        self.chr_bank_1 = reader.read_u8()? & 0x1F;
+        self.prg_bank = reader.read_u8()? & 0x1F;
+        Ok(())
+    }
+}
+
+/// The MMC3's scanline counter, clocked by rises of the ppu's A12. NES 2.0
+/// submapper 4 is the older MMC3A, whose counter doesn't fire when it
+/// reloads from 0 on its own.
+/// https://www.nesdev.org/wiki/MMC3#IRQ_Specifics
+#[derive(Debug, Clone, Default)]
+struct Mmc3Irq {
+    latch: u8,
+    enabled: bool,
+    counter: u8,
+    /// set by $C001, the next clock reloads the counter
+    reload: bool,
+    asserted: bool,
+    a12: A12Filter,
+    is_alternate: bool,
+}
+
+impl Mmc3Irq {
+    fn disable(&mut self) {
+        self.enabled = false;
+        self.asserted = false;
+    }
+
+    fn clock(&mut self) {
+        let was_reloaded = self.reload;
+        let old = self.counter;
+        if self.counter == 0 || self.reload {
+            self.counter = self.latch;
+            self.reload = false;
+        } else {
+            self.counter -= 1;
+        }
+        let fires = if self.is_alternate {
+            self.counter == 0 && (old != 0 || was_reloaded)
+        } else {
+            self.counter == 0
+        };
+        if fires && self.enabled {
+            self.asserted = true;
+        }
+    }
+}
+
+impl SaveState for Mmc3Irq {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.latch);
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.counter);
+        writer.write_bool(self.reload);
+        writer.write_bool(self.asserted);
+        self.a12.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.latch = reader.read_u8()?;
+        self.enabled = reader.read_bool()?;
+        self.counter = reader.read_u8()?;
+        self.reload = reader.read_bool()?;
+        self.asserted = reader.read_bool()? && self.enabled;
+        self.a12.load_state(reader)
+    }
+}
+
+/// MMC3, 8kb prg banks, 1kb/2kb chr banks and a scanline counter irq.
+/// https://www.nesdev.org/wiki/MMC3
+pub(super) struct M004 {
+    pub header: Header,
+    bank_select: u8,
+    banks: [u8; 8],
+    mirroring: u8,
+    prg_ram_protect: u8,
+    irq: Mmc3Irq,
+}
+
+impl M004 {
+    fn prg_bank_count(&self) -> usize {
+        (self.header.prg_rom_size() as usize * 2).max(1)
+    }
+
+    fn map_prg(&self, address: u16) -> usize {
+        let second_last = self.prg_bank_count().saturating_sub(2);
+        let swapped = self.bank_select & 0x40 != 0;
+        let bank = match address {
+            0x8000..0xA000 if swapped => second_last,
+            0x8000..0xA000 => self.banks[6] as usize,
+            0xA000..0xC000 => self.banks[7] as usize,
+            0xC000..0xE000 if swapped => self.banks[6] as usize,
+            0xC000..0xE000 => second_last,
+            _ => self.prg_bank_count() - 1,
+        };
+        (bank % self.prg_bank_count()) * byte_size!(8 kb) + (address & 0x1FFF) as usize
+    }
+
+    fn map_chr(&self, address: u16) -> usize {
+        let chr_size = if self.header.prg_chr_size() == 0 {
+            byte_size!(8 kb)
+        } else {
+            self.header.chr_rom_size_bytes()
+        };
+        // chr inversion swaps the 2kb and 1kb halves
+        let address = if self.bank_select & 0x80 != 0 {
+            address ^ 0x1000
+        } else {
+            address
+        };
+        let offset = match address {
+            0x0000..0x0800 => (self.banks[0] & !1) as usize * 0x400 + (address & 0x07FF) as usize,
+            0x0800..0x1000 => (self.banks[1] & !1) as usize * 0x400 + (address & 0x07FF) as usize,
+            _ => {
+                let register = 2 + ((address - 0x1000) >> 10) as usize;
+                self.banks[register] as usize * 0x400 + (address & 0x03FF) as usize
+            }
+        };
+        offset % chr_size
+    }
+}
+
+impl Mapper for M004 {
+    fn new(header: Header) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            bank_select: 0,
+            banks: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: 0,
+            prg_ram_protect: 0x80,
+            irq: Mmc3Irq {
+                is_alternate: header.get_submapper_id() == Some(4),
+                ..Mmc3Irq::default()
+            },
+            header,
+        }
+    }
+
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { address } => Some(self.map_prg(address)),
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                Some(self.map_chr(address))
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { address } => {
+                let even = address.is_multiple_of(2);
+                match address {
+                    0x8000..0xA000 if even => self.bank_select = value,
+                    0x8000..0xA000 => self.banks[(self.bank_select & 0x07) as usize] = value,
+                    0xA000..0xC000 if even => self.mirroring = value & 1,
+                    0xA000..0xC000 => self.prg_ram_protect = value & 0xC0,
+                    0xC000..0xE000 if even => self.irq.latch = value,
+                    0xC000..0xE000 => {
+                        self.irq.counter = 0;
+                        self.irq.reload = true;
+                    }
+                    // disabling also acknowledges
+                    0xE000.. if even => self.irq.disable(),
+                    _ => self.irq.enabled = true,
+                }
+                tracing::trace!(target: targets::MAPPER, address, value, "M004 register write");
+                None
+            }
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                if self.header.prg_chr_size() == 0 {
+                    Some(self.map_chr(address))
+                } else {
+                    None
+                }
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_nametable(&self, address: u16) -> u16 {
+        if self.header.has_four_screen_vram() {
+            address
+        } else if self.mirroring == 0 {
+            mirroring::vertical(address)
+        } else {
+            mirroring::horizontal(address)
+        }
+    }
+
+    /// Bit 7 of $A001 enables the ram and bit 6 write protects it
+    fn map_prg_ram(&self, address: u16, ram_size: usize) -> PrgRamAccess {
+        let offset = (address - PRG_RAM_START) as usize % ram_size;
+        match self.prg_ram_protect {
+            protect if protect & 0x80 == 0 => PrgRamAccess::Disabled,
+            protect if protect & 0x40 != 0 => PrgRamAccess::ReadOnly(offset),
+            _ => PrgRamAccess::ReadWrite(offset),
+        }
+    }
+
+    fn ppu_address(&mut self, address: u16, dot: u64) {
+        if self.irq.a12.watch(address, dot) {
+            self.irq.clock();
+        }
+    }
+
+    fn is_irq_asserted(&self) -> bool {
+        self.irq.asserted
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        byte_size!(8 kb)
+    }
+
+    /// The last two banks are fixed at $C000 and $E000 at power on, the
+    /// other ones can go to either $8000 or $A000 so $8000 is picked
+    fn prg_bank_address(&self, bank: usize, bank_count: usize) -> u16 {
+        match bank_count - bank {
+            1 => 0xE000,
+            2 => 0xC000,
+            _ => 0x8000,
+        }
+    }
+}
+
+impl SaveState for M004 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bank_select);
+        writer.write_bytes(&self.banks);
+        writer.write_u8(self.mirroring);
+        writer.write_u8(self.prg_ram_protect);
+        self.irq.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.bank_select = reader.read_u8()?;
+        reader.read_bytes_into("M004 banks", &mut self.banks)?;
+        self.mirroring = reader.read_u8()? & 1;
+        self.prg_ram_protect = reader.read_u8()? & 0xC0;
+        self.irq.load_state(reader)
+    }
+}
+
+/// Where the flash chip is in its command sequence
+/// https://www.nesdev.org/wiki/UNROM_512#Flash_ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlashState {
+    Idle,
+    Unlocked,
+    Command,
+    Program,
+    EraseUnlocked,
+    EraseCommand,
+    Erase,
+}
+
+impl FlashState {
+    const ALL: [FlashState; 7] = [
+        FlashState::Idle,
+        FlashState::Unlocked,
+        FlashState::Command,
+        FlashState::Program,
+        FlashState::EraseUnlocked,
+        FlashState::EraseCommand,
+        FlashState::Erase,
+    ];
+}
+
+/// UNROM 512, homebrew board with 32kb of chr ram. With the battery bit
+/// set the prg is an SST39SF040 flash that the game can write its saves to.
+/// Four screen mirroring uses the console's own extra vram instead of the
+/// chr ram.
+/// https://www.nesdev.org/wiki/UNROM_512
+pub(super) struct M030 {
+    pub header: Header,
+    register: u8,
+    flash_state: FlashState,
+}
+
+impl M030 {
+    fn prg_bank_count(&self) -> usize {
+        (self.header.prg_rom_size() as usize).max(1)
+    }
+
+    fn selected_bank(&self) -> usize {
+        (self.register & 0x1F) as usize % self.prg_bank_count()
+    }
+
+    fn map_chr(&self, address: u16) -> usize {
+        let chr_size = if self.header.prg_chr_size() == 0 {
+            self.chr_ram_size()
+        } else {
+            self.header.chr_rom_size_bytes()
+        };
+        (((self.register >> 5) & 0x03) as usize * byte_size!(8 kb) + address as usize) % chr_size
+    }
+
+    fn is_flashable(&self) -> bool {
+        self.header.has_battery_backed_ram()
+    }
+
+    /// Unlock and command writes go to $5555 and $2AAA of the chip
+    fn next_flash_state(&self, flash_address: usize, value: u8) -> FlashState {
+        let command_address = flash_address & 0x7FFF;
+        match (self.flash_state, command_address, value) {
+            (FlashState::Idle, 0x5555, 0xAA) => FlashState::Unlocked,
+            (FlashState::Unlocked, 0x2AAA, 0x55) => FlashState::Command,
+            (FlashState::Command, 0x5555, 0xA0) => FlashState::Program,
+            (FlashState::Command, 0x5555, 0x80) => FlashState::EraseUnlocked,
+            (FlashState::EraseUnlocked, 0x5555, 0xAA) => FlashState::EraseCommand,
+            (FlashState::EraseCommand, 0x2AAA, 0x55) => FlashState::Erase,
+            _ => FlashState::Idle,
+        }
+    }
+}
+
+impl Mapper for M030 {
+    fn new(header: Header) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            header,
+            register: 0,
+            flash_state: FlashState::Idle,
+        }
+    }
+
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { address } if address < 0xC000 => {
+                Some(self.selected_bank() * byte_size!(16 kb) + (address & 0x3FFF) as usize)
+            }
+            CartrigeAccess::CpuAccess { address } => {
+                Some((self.prg_bank_count() - 1) * byte_size!(16 kb) + (address & 0x3FFF) as usize)
+            }
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                Some(self.map_chr(address))
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { .. } => {
+                self.register = value;
+                tracing::trace!(target: targets::MAPPER, register = self.register, "M030 bank switch");
+                None
+            }
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                if self.header.prg_chr_size() == 0 {
+                    Some(self.map_chr(address))
+                } else {
+                    None
+                }
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    /// Flags 6 bits 0 and 3 pick the mirroring, `%1000` means one screen
+    /// picked by bit 7 of the register
+    fn map_nametable(&self, address: u16) -> u16 {
+        match (
+            self.header.has_four_screen_vram(),
+            self.header.get_nametable_arrangement(),
+        ) {
+            (true, 0) if self.register & 0x80 != 0 => (address & !0x0C00) | 0x0400,
+            (true, 0) => address & !0x0C00,
+            _ => mirroring::from_header(&self.header, address),
+        }
+    }
+
+    fn chr_ram_size(&self) -> usize {
+        byte_size!(32 kb)
+    }
+
+    /// Flashable boards only take register writes at $C000-$FFFF, writes
+    /// to $8000-$BFFF talk to the flash chip through the selected bank
+    fn write_flash(&mut self, address: u16, value: u8, prg_mem: &mut [u8]) -> bool {
+        if !self.is_flashable() || !(0x8000..0xC000).contains(&address) {
+            return false;
+        }
+        let flash_address = self.selected_bank() * byte_size!(16 kb) + (address & 0x3FFF) as usize;
+        match self.flash_state {
+            // programming can only clear bits, erasing sets them again
+            FlashState::Program => {
+                if let Some(byte) = prg_mem.get_mut(flash_address) {
+                    *byte &= value;
+                }
+                self.flash_state = FlashState::Idle;
+            }
+            FlashState::Erase if value == 0x30 => {
+                let sector = flash_address & !0x0FFF;
+                let end = (sector + byte_size!(4 kb)).min(prg_mem.len());
+                if sector < end {
+                    prg_mem[sector..end].fill(0xFF);
+                }
+                self.flash_state = FlashState::Idle;
+            }
+            FlashState::Erase if value == 0x10 && flash_address & 0x7FFF == 0x5555 => {
+                prg_mem.fill(0xFF);
+                self.flash_state = FlashState::Idle;
+            }
+            _ => self.flash_state = self.next_flash_state(flash_address, value),
+        }
+        tracing::trace!(target: targets::MAPPER, flash_address, value, state = ?self.flash_state, "M030 flash write");
+        true
+    }
+
+    fn has_flash(&self) -> bool {
+        self.is_flashable()
+    }
+}
+
+impl SaveState for M030 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_u8(self.flash_state as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.register = reader.read_u8()?;
+        let state = reader.read_u8()?;
+        self.flash_state =
+            *FlashState::ALL
+                .get(state as usize)
+                .ok_or(SaveStateError::InvalidValueError(
+                    "flash state",
+                    state as u64,
+                ))?;
+        Ok(())
+    }
+}
+
+/// Sunsoft FME-7, and the 5B which is the same mapper with a sound chip.
+/// Writes to $8000 pick a command and writes to $A000 are its parameter.
+/// https://www.nesdev.org/wiki/Sunsoft_FME-7
+pub(super) struct M069 {
+    pub header: Header,
+    command: u8,
+    chr_banks: [u8; 8],
+    /// $6000, $8000, $A000 and $C000, $E000 is fixed to the last bank.
+    /// The $6000 one also picks between rom and ram.
+    prg_banks: [u8; 4],
+    mirroring: u8,
+    irq_control: u8,
+    irq_counter: u16,
+    irq_asserted: bool,
+}
+
+impl M069 {
+    /// Bit 0 of the irq control lets the counter raise the irq
+    const IRQ_ENABLE: u8 = 0x01;
+    /// Bit 7 of the irq control makes the counter count down every cpu
+    /// cycle
+    const IRQ_COUNTER_ENABLE: u8 = 0x80;
+
+    fn prg_bank_count(&self) -> usize {
+        (self.header.prg_rom_size() as usize * 2).max(1)
+    }
+
+    fn map_prg(&self, address: u16) -> usize {
+        let bank = match address {
+            0x6000..0xE000 => (self.prg_banks[(address as usize - 0x6000) >> 13] & 0x3F) as usize,
+            _ => self.prg_bank_count() - 1,
+        };
+        (bank % self.prg_bank_count()) * byte_size!(8 kb) + (address & 0x1FFF) as usize
+    }
+
+    fn is_ram_selected(&self) -> bool {
+        self.prg_banks[0] & 0x40 != 0
+    }
+
+    fn map_chr(&self, address: u16) -> usize {
+        let chr_size = if self.header.prg_chr_size() == 0 {
+            self.chr_ram_size()
+        } else {
+            self.header.chr_rom_size_bytes()
+        };
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07] as usize;
+        (bank * 0x400 + (address & 0x03FF) as usize) % chr_size
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = value,
+            0x8..=0xB => self.prg_banks[self.command as usize - 0x8] = value,
+            0xC => self.mirroring = value & 0x03,
+            // writing the control also acknowledges the irq
+            0xD => {
+                self.irq_control = value & (Self::IRQ_ENABLE | Self::IRQ_COUNTER_ENABLE);
+                self.irq_asserted = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | ((value as u16) << 8),
+        }
+    }
+}
+
+impl Mapper for M069 {
+    fn new(header: Header) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            header,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: 0,
+            irq_control: 0,
+            irq_counter: 0,
+            irq_asserted: false,
+        }
+    }
+
+    /// Ram at $6000 goes through [M069::map_prg_ram], this only maps the
+    /// rom when it's banked in there instead
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x6000 => None,
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 && self.is_ram_selected() => {
+                None
+            }
+            CartrigeAccess::CpuAccess { address } => Some(self.map_prg(address)),
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                Some(self.map_chr(address))
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    /// $C000-$FFFF are the sound chip registers, they go to the apu
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { address } => {
+                match address {
+                    0x8000..0xA000 => self.command = value & 0x0F,
+                    0xA000..0xC000 => self.write_parameter(value),
+                    _ => return None,
+                }
+                tracing::trace!(target: targets::MAPPER, address, value, "M069 register write");
+                None
+            }
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                if self.header.prg_chr_size() == 0 {
+                    Some(self.map_chr(address))
+                } else {
+                    None
+                }
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_nametable(&self, address: u16) -> u16 {
+        match self.mirroring {
+            0 => mirroring::vertical(address),
+            1 => mirroring::horizontal(address),
+            2 => address & !0x0C00,
+            _ => (address & !0x0C00) | 0x0400,
+        }
+    }
+
+    /// Bit 6 of the $6000 bank puts ram there instead of rom and bit 7
+    /// enables it, the low bits pick the ram bank on boards with more
+    fn map_prg_ram(&self, address: u16, ram_size: usize) -> PrgRamAccess {
+        let bank = (self.prg_banks[0] & 0x3F) as usize;
+        let offset = (bank * byte_size!(8 kb) + (address - PRG_RAM_START) as usize) % ram_size;
+        match self.prg_banks[0] {
+            bank if bank & 0xC0 == 0xC0 => PrgRamAccess::ReadWrite(offset),
+            _ => PrgRamAccess::Disabled,
+        }
+    }
+
+    /// The counter wrapping from $0000 to $FFFF raises the irq
+    fn tick(&mut self) {
+        if self.irq_control & Self::IRQ_COUNTER_ENABLE == 0 {
+            return;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+        if self.irq_counter == 0xFFFF && self.irq_control & Self::IRQ_ENABLE != 0 {
+            self.irq_asserted = true;
+        }
+    }
+
+    fn is_irq_asserted(&self) -> bool {
+        self.irq_asserted
+    }
+
+    fn expansion_audio(&self) -> Option<Box<dyn ExpansionAudio>> {
+        Some(Box::new(Sunsoft5B::new()))
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        byte_size!(8 kb)
+    }
+
+    /// Only the last bank is fixed, the others can go anywhere so $8000
+    /// is picked
+    fn prg_bank_address(&self, bank: usize, bank_count: usize) -> u16 {
+        if bank + 1 == bank_count {
+            0xE000
+        } else {
+            0x8000
+        }
+    }
+}
+
+impl SaveState for M069 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.command);
+        writer.write_bytes(&self.chr_banks);
+        writer.write_bytes(&self.prg_banks);
+        writer.write_u8(self.mirroring);
+        writer.write_u8(self.irq_control);
+        writer.write_u16(self.irq_counter);
+        writer.write_bool(self.irq_asserted);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.command = reader.read_u8()? & 0x0F;
+        reader.read_bytes_into("M069 chr banks", &mut self.chr_banks)?;
+        reader.read_bytes_into("M069 prg banks", &mut self.prg_banks)?;
+        self.mirroring = reader.read_u8()? & 0x03;
+        self.irq_control = reader.read_u8()? & (Self::IRQ_ENABLE | Self::IRQ_COUNTER_ENABLE);
+        self.irq_counter = reader.read_u16()?;
+        self.irq_asserted = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+/// The cpu cycle irq counter of the VRC4, VRC6 and VRC7. In scanline mode
+/// a prescaler clocks it about once every scanline instead.
+/// https://www.nesdev.org/wiki/VRC_IRQ
+#[derive(Debug, Clone, Default)]
+struct VrcIrq {
+    latch: u8,
+    control: u8,
+    counter: u8,
+    prescaler: i16,
+    asserted: bool,
+}
+
+impl VrcIrq {
+    const ENABLE_AFTER_ACK: u8 = 0b001;
+    const ENABLE: u8 = 0b010;
+    const CYCLE_MODE: u8 = 0b100;
+    /// ppu dots in a scanline, the prescaler counts down 3 per cpu cycle
+    const PRESCALER_PERIOD: i16 = 341;
+
+    fn write_control(&mut self, value: u8) {
+        self.control = value & 0x07;
+        self.asserted = false;
+        if self.control & Self::ENABLE != 0 {
+            self.counter = self.latch;
+            self.prescaler = Self::PRESCALER_PERIOD;
+        }
+    }
+
+    fn acknowledge(&mut self) {
+        self.asserted = false;
+        if self.control & Self::ENABLE_AFTER_ACK != 0 {
+            self.control |= Self::ENABLE;
+        } else {
+            self.control &= !Self::ENABLE;
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.control & Self::ENABLE == 0 {
+            return;
+        }
+        if self.control & Self::CYCLE_MODE == 0 {
+            self.prescaler -= 3;
+            if self.prescaler > 0 {
+                return;
+            }
+            self.prescaler += Self::PRESCALER_PERIOD;
+        }
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.asserted = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+impl SaveState for VrcIrq {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.latch);
+        writer.write_u8(self.control);
+        writer.write_u8(self.counter);
+        writer.write_u16(self.prescaler as u16);
+        writer.write_bool(self.asserted);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.latch = reader.read_u8()?;
+        self.control = reader.read_u8()? & 0x07;
+        self.counter = reader.read_u8()?;
+        self.prescaler = (reader.read_u16()? as i16).clamp(-2, Self::PRESCALER_PERIOD);
+        self.asserted = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+/// Konami VRC2 and VRC4, mappers 21, 22, 23 and 25. The boards wire
+/// different cpu address lines to the chip's two register select pins.
+/// NES 2.0 submappers say which ones, otherwise both wirings of the
+/// mapper are listened to since games only write through their own.
+/// https://www.nesdev.org/wiki/VRC2_and_VRC4
+pub(super) struct M021 {
+    pub header: Header,
+    /// the address lines going to the chip's A0 and A1
+    register_lines: (u16, u16),
+    is_vrc2: bool,
+    prg_banks: [u8; 2],
+    chr_banks: [u16; 8],
+    mirroring: u8,
+    /// $9002 on the VRC4, bit 1 swaps $8000 and $C000
+    prg_mode: u8,
+    irq: VrcIrq,
+}
+
+impl M021 {
+    fn prg_bank_count(&self) -> usize {
+        (self.header.prg_rom_size() as usize * 2).max(1)
+    }
+
+    /// Folds the board's wiring into $X000-$X003
+    fn register(&self, address: u16) -> u16 {
+        let (a0, a1) = self.register_lines;
+        (address & 0xF000) | ((address & a0 != 0) as u16) | (((address & a1 != 0) as u16) << 1)
+    }
+
+    fn map_prg(&self, address: u16) -> usize {
+        let second_last = self.prg_bank_count().saturating_sub(2);
+        let swapped = !self.is_vrc2 && self.prg_mode & 0x02 != 0;
+        let bank = match address {
+            0x8000..0xA000 if swapped => second_last,
+            0x8000..0xA000 => self.prg_banks[0] as usize,
+            0xA000..0xC000 => self.prg_banks[1] as usize,
+            0xC000..0xE000 if swapped => self.prg_banks[0] as usize,
+            0xC000..0xE000 => second_last,
+            _ => self.prg_bank_count() - 1,
+        };
+        (bank % self.prg_bank_count()) * byte_size!(8 kb) + (address & 0x1FFF) as usize
+    }
+
+    /// VRC2a (mapper 22) ignores the low bit of the chr banks
+    fn map_chr(&self, address: u16) -> usize {
+        let chr_size = if self.header.prg_chr_size() == 0 {
+            self.chr_ram_size()
+        } else {
+            self.header.chr_rom_size_bytes()
+        };
+        let mut bank = self.chr_banks[(address >> 10) as usize & 0x07] as usize;
+        if self.header.get_mapper_id() == 22 {
+            bank >>= 1;
+        }
+        (bank * 0x400 + (address & 0x03FF) as usize) % chr_size
+    }
+
+    /// Every chr bank is written a nibble at a time, the VRC4 has one more
+    /// bit in the high one
+    fn write_chr_bank(&mut self, register: u16, value: u8) {
+        let index = (((register >> 12) - 0xB) * 2 + ((register >> 1) & 1)) as usize;
+        let bank = &mut self.chr_banks[index];
+        if register & 1 == 0 {
+            *bank = (*bank & !0x0F) | (value & 0x0F) as u16;
+        } else {
+            let high_mask = if self.is_vrc2 { 0x0F } else { 0x1F };
+            *bank = (*bank & 0x0F) | (((value & high_mask) as u16) << 4);
+        }
+    }
+}
+
+impl Mapper for M021 {
+    fn new(header: Header) -> Self
+    where
+        Self: Sized,
+    {
+        let submapper = header.get_submapper_id().unwrap_or(0);
+        let register_lines = match (header.get_mapper_id(), submapper) {
+            (21, 1) => (0x02, 0x04),
+            (21, 2) => (0x40, 0x80),
+            (21, _) => (0x42, 0x84),
+            (22, _) => (0x02, 0x01),
+            (23, 1 | 3) => (0x01, 0x02),
+            (23, 2) => (0x04, 0x08),
+            (23, _) => (0x05, 0x0A),
+            (_, 1 | 3) => (0x02, 0x01),
+            (_, 2) => (0x08, 0x04),
+            (_, _) => (0x0A, 0x05),
+        };
+        let is_vrc2 = header.get_mapper_id() == 22 || submapper == 3;
+        Self {
+            header,
+            register_lines,
+            is_vrc2,
+            prg_banks: [0, 1],
+            chr_banks: [0; 8],
+            mirroring: 0,
+            prg_mode: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { address } => Some(self.map_prg(address)),
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                Some(self.map_chr(address))
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<usize> {
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { address } if address < 0x8000 => None,
+            CartrigeAccess::CpuAccess { address } => {
+                let register = self.register(address);
+                match register {
+                    0x8000..0x9000 => self.prg_banks[0] = value & 0x1F,
+                    0x9000..0x9004 if self.is_vrc2 => self.mirroring = value & 0x01,
+                    0x9000 | 0x9001 => self.mirroring = value & 0x03,
+                    0x9002 | 0x9003 => self.prg_mode = value & 0x03,
+                    0xA000..0xB000 => self.prg_banks[1] = value & 0x1F,
+                    0xB000..0xF000 => self.write_chr_bank(register, value),
+                    _ if self.is_vrc2 => (),
+                    0xF000 => self.irq.latch = (self.irq.latch & 0xF0) | (value & 0x0F),
+                    0xF001 => self.irq.latch = (self.irq.latch & 0x0F) | (value << 4),
+                    0xF002 => self.irq.write_control(value),
+                    _ => self.irq.acknowledge(),
+                }
+                tracing::trace!(target: targets::MAPPER, address, register, value, "M021 register write");
+                None
+            }
+            CartrigeAccess::PpuAccess { address } if address < 0x2000 => {
+                if self.header.prg_chr_size() == 0 {
+                    Some(self.map_chr(address))
+                } else {
+                    None
+                }
+            }
+            CartrigeAccess::PpuAccess { .. } => None,
+        }
+    }
+
+    fn map_nametable(&self, address: u16) -> u16 {
+        match self.mirroring {
+            0 => mirroring::vertical(address),
+            1 => mirroring::horizontal(address),
+            2 => address & !0x0C00,
+            _ => (address & !0x0C00) | 0x0400,
+        }
+    }
+
+    fn tick(&mut self) {
+        if !self.is_vrc2 {
+            self.irq.tick();
+        }
+    }
+
+    fn is_irq_asserted(&self) -> bool {
+        self.irq.asserted
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        byte_size!(8 kb)
+    }
+
+    /// Same as the MMC3, the last two banks start out at $C000 and $E000
+    fn prg_bank_address(&self, bank: usize, bank_count: usize) -> u16 {
+        match bank_count - bank {
+            1 => 0xE000,
+            2 => 0xC000,
+            _ => 0x8000,
+        }
+    }
+}
+
+impl SaveState for M021 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_banks);
+        for bank in self.chr_banks {
+            writer.write_u16(bank);
+        }
+        writer.write_u8(self.mirroring);
+        writer.write_u8(self.prg_mode);
+        self.irq.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        reader.read_bytes_into("M021 prg banks", &mut self.prg_banks)?;
+        for bank in self.chr_banks.iter_mut() {
+            *bank = reader.read_u16()? & 0x1FF;
+        }
+        self.mirroring = reader.read_u8()? & 0x03;
+        self.prg_mode = reader.read_u8()? & 0x03;
+        self.irq.load_state(reader)
+    }
+}
@@ -1,24 +1,150 @@
-use crate::hardware::cartrige::{
-    Header, cartrige_access::CartrigeAccess, error::CartrigeParseError, mappers::implementations::*,
+use crate::{
+    hardware::{
+        apu::expansion::ExpansionAudio,
+        cartrige::{
+            Header, cartrige_access::CartrigeAccess, error::CartrigeParseError,
+            mappers::implementations::*,
+        },
+        constants::cartrige::{CHR_ROM_BANK_SIZE, PRG_RAM_START, PRG_ROM_BANK_SIZE},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
 };
 
 use super::Result;
 
 mod implementations;
 
-pub(super) trait Mapper {
+/// Where a cpu access to $6000-$7FFF ends up in prg ram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PrgRamAccess {
+    /// the chip is disabled so reads are open bus and writes are dropped
+    Disabled,
+    ReadOnly(usize),
+    ReadWrite(usize),
+}
+
+/// Mappers also have to save their registers (bank selects etc.) in save states
+pub(super) trait Mapper: SaveState + Send {
     fn new(header: Header) -> Self
     where
         Self: Sized;
-    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<u16>;
-    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<u16>;
+    fn map_write(&mut self, cartrige_access: CartrigeAccess, value: u8) -> Option<usize>;
+    fn map_read(&mut self, cartrige_access: CartrigeAccess) -> Option<usize>;
     fn map_nametable(&self, address: u16) -> u16;
+
+    /// `ram_size` is all of the ram, up to 64kb on boards that bank it.
+    /// Boards without banking or write protection just mirror the ram
+    /// across the whole window.
+    fn map_prg_ram(&self, address: u16, ram_size: usize) -> PrgRamAccess {
+        PrgRamAccess::ReadWrite((address - PRG_RAM_START) as usize % ram_size)
+    }
+
+    /// Size of the chr ram for boards without chr rom
+    fn chr_ram_size(&self) -> usize {
+        CHR_ROM_BANK_SIZE
+    }
+
+    /// Boards with flash memory can reprogram their own prg rom. Returns
+    /// true if the write went to the flash instead of [Mapper::map_write].
+    fn write_flash(&mut self, _address: u16, _value: u8, _prg_mem: &mut [u8]) -> bool {
+        false
+    }
+
+    /// Flash boards save the whole prg rom instead of the prg ram
+    fn has_flash(&self) -> bool {
+        false
+    }
+
+    /// Called once every cpu cycle, for boards with irq counters that
+    /// count cpu cycles
+    fn tick(&mut self) {}
+
+    /// Called with every address the ppu puts on its bus (its own fetches
+    /// and PPUADDR/PPUDATA accesses), `dot` counts ppu dots since power
+    /// on. Boards with scanline counters watch A12 with it, see [A12Filter].
+    fn ppu_address(&mut self, _address: u16, _dot: u64) {}
+
+    /// True while the board pulls the cpu's irq line
+    fn is_irq_asserted(&self) -> bool {
+        false
+    }
+
+    /// Boards with a sound chip hand out a fresh one, see
+    /// [crate::hardware::apu::expansion]
+    fn expansion_audio(&self) -> Option<Box<dyn ExpansionAudio>> {
+        None
+    }
+
+    /// Size of the prg banks the mapper switches, used to cut the rom up
+    /// for disassembly
+    fn prg_bank_size(&self) -> usize {
+        PRG_ROM_BANK_SIZE
+    }
+
+    /// Where `bank` usually ends up in cpu memory. Most boards switch
+    /// $8000-$BFFF and keep the last bank at $C000.
+    fn prg_bank_address(&self, bank: usize, bank_count: usize) -> u16 {
+        if bank + 1 == bank_count {
+            0xC000
+        } else {
+            0x8000
+        }
+    }
 }
 
 pub(super) fn from_header(header: Header) -> Result<Box<dyn Mapper>> {
     Ok(match header.get_mapper_id() {
         0 => Box::new(M000::new(header)),
+        1 => Box::new(M001::new(header)),
         2 => Box::new(M002::new(header)),
+        4 => Box::new(M004::new(header)),
+        30 => Box::new(M030::new(header)),
+        21 | 22 | 23 | 25 => Box::new(M021::new(header)),
+        69 => Box::new(M069::new(header)),
         unkown_id => return Err(CartrigeParseError::UnknownMapperIdError(unkown_id)),
     })
 }
+
+/// Turns the ppu's A12 line into scanline clocks. While rendering A12
+/// goes up and down a few dots apart for every nametable and pattern
+/// fetch, so the MMC3 only counts a rise after it has been low for a
+/// while (3 falling edges of M2 on hardware, about 8 dots). With the
+/// background on $0000 and the sprites on $1000 that's once a line, at
+/// the first sprite fetch.
+/// https://www.nesdev.org/wiki/MMC3#IRQ_Specifics
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct A12Filter {
+    is_high: bool,
+    /// the dot A12 last went low on
+    low_since: u64,
+}
+
+impl A12Filter {
+    /// Dots A12 has to stay low for the next rise to count
+    pub(super) const LOW_DOTS: u64 = 8;
+
+    /// Returns true if `address` is a rise of A12 that counts
+    pub(super) fn watch(&mut self, address: u16, dot: u64) -> bool {
+        let is_high = address & 0x1000 != 0;
+        let clocked =
+            is_high && !self.is_high && dot.saturating_sub(self.low_since) >= Self::LOW_DOTS;
+        if !is_high && self.is_high {
+            self.low_since = dot;
+        }
+        self.is_high = is_high;
+        clocked
+    }
+}
+
+impl SaveState for A12Filter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_high);
+        writer.write_u64(self.low_since);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.is_high = reader.read_bool()?;
+        self.low_since = reader.read_u64()?;
+        Ok(())
+    }
+}
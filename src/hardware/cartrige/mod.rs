@@ -1,10 +1,34 @@
 pub mod cartrige_access;
+pub mod checksum;
 pub mod error;
+pub mod flat;
+pub mod game_genie;
+pub mod header;
 mod mappers;
-
-use crate::hardware::{
-    cartrige::{cartrige_access::CartrigeAccess, error::CartrigeParseError, mappers::Mapper},
-    constants::cartrige::*,
+pub mod patch;
+pub mod rom_info;
+pub mod sram;
+
+pub use header::{Header, Mirroring, Nes2RamSizes, TvSystem};
+pub use rom_info::RomInfo;
+
+use std::path::Path;
+
+use crate::{
+    hardware::{
+        apu::expansion::ExpansionAudio,
+        cartrige::{
+            cartrige_access::CartrigeAccess,
+            checksum::Checksums,
+            error::{CartrigeParseError, PatchError, SramError},
+            flat::FlatOptions,
+            game_genie::GameGenie,
+            mappers::{Mapper, PrgRamAccess},
+        },
+        constants::cartrige::*,
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+    trace::targets,
 };
 
 pub type Result<T> = std::result::Result<T, CartrigeParseError>;
@@ -19,23 +43,67 @@ fn try_get_next_n<'a>(data_ptr: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
     }
 }
 
-fn try_get_next(data_ptr: &mut &[u8]) -> Result<u8> {
-    if data_ptr.len() < 1 {
-        return Err(CartrigeParseError::NotEnoughBytesError(1));
-    } else {
-        let start = data_ptr.get(0);
-        *data_ptr = &data_ptr[1..];
-        start
-            .cloned()
-            .ok_or_else(|| CartrigeParseError::NotEnoughBytesError(1))
+/// Takes the next section of the rom, `section` is its name for the error
+fn take_section<'a>(
+    bytes: &mut &'a [u8],
+    offset: &mut usize,
+    section: &'static str,
+    size: usize,
+) -> Result<&'a [u8]> {
+    if bytes.len() < size {
+        return Err(CartrigeParseError::TruncatedError(
+            section,
+            *offset,
+            size,
+            bytes.len(),
+        ));
+    }
+    let (out, rest) = bytes.split_at(size);
+    *bytes = rest;
+    *offset += size;
+    Ok(out)
+}
+
+/// How forgiving [Cartrige::from_bytes_with_options] is with bad dumps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Lots of old dumps have padding or garbage after the chr rom, by
+    /// default the extra bytes get dropped with a warning. With this they
+    /// fail to load instead, for checking a rom set.
+    pub strict: bool,
+    /// By default an `.ips` or `.bps` next to the rom gets applied, see
+    /// [patch::find_patch]
+    pub ignore_patches: bool,
+}
+
+/// One switchable piece of the prg rom, see [Cartrige::prg_banks]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrgBank<'a> {
+    pub index: usize,
+    /// The cpu address the bank is usually mapped at
+    pub address: u16,
+    pub data: &'a [u8],
+}
+
+impl PrgBank<'_> {
+    /// The bank that ends at $FFFF has the nmi, reset and irq vectors
+    pub fn has_vectors(&self) -> bool {
+        self.address as usize + self.data.len() == 0x10000
     }
 }
 
 pub struct Cartrige {
     mapper: Box<dyn Mapper>,
     header: Header,
+    checksums: Checksums,
+    trainer: Option<Vec<u8>>,
     prg_mem: Vec<u8>,
     chr_mem: Vec<u8>,
+    prg_ram: Vec<u8>,
+    /// see [Cartrige::tick]
+    irq_line: bool,
+    /// see [Cartrige::attach_game_genie]
+    game_genie: Option<GameGenie>,
 }
 
 impl Cartrige {
@@ -43,161 +111,397 @@ impl Cartrige {
         &self.header
     }
 
+    /// Checksums of the rom as it was loaded
+    pub fn get_checksums(&self) -> &Checksums {
+        &self.checksums
+    }
+
+    /// Info about the rom, including its checksums
+    pub fn get_rom_info(&self) -> RomInfo {
+        RomInfo {
+            checksums: Some(self.checksums),
+            ..RomInfo::from_header(&self.header)
+        }
+    }
+
     pub fn from_file(filename: &str) -> Result<Self> {
+        Self::from_file_with_options(filename, &LoadOptions::default())
+    }
+
+    pub fn from_file_with_options(filename: &str, options: &LoadOptions) -> Result<Self> {
+        match patch::find_patch(Path::new(filename)) {
+            Some(patch) if !options.ignore_patches => {
+                Self::from_file_with_patch(filename, &patch, options)
+            }
+            _ => {
+                let bytes = std::fs::read(filename)?;
+                Cartrige::from_bytes_with_options(bytes.as_slice(), options)
+            }
+        }
+    }
+
+    /// Loads the rom with `patch` applied in memory, the rom file itself
+    /// is left alone
+    pub fn from_file_with_patch(
+        filename: &str,
+        patch: &Path,
+        options: &LoadOptions,
+    ) -> Result<Self> {
         let bytes = std::fs::read(filename)?;
-        Cartrige::from_bytes(bytes.as_slice())
+        let patch_bytes = std::fs::read(patch).map_err(PatchError::from)?;
+        let patched = patch::apply(&bytes, &patch_bytes)?;
+        tracing::info!(target: targets::MAPPER, patch = %patch.display(), "applied patch");
+        Cartrige::from_bytes_with_options(patched.as_slice(), options)
+    }
+
+    /// Drops anything after the roms the header describes, see
+    /// [LoadOptions::strict]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_options(bytes, &LoadOptions::default())
     }
 
-    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+    pub fn from_bytes_with_options(mut bytes: &[u8], options: &LoadOptions) -> Result<Self> {
         let bytes_ptr: &mut &[u8] = &mut bytes;
 
-        if try_get_next_n(bytes_ptr, 4)? != &NES_MAGIC_NUMBERS {
-            return Err(CartrigeParseError::MissingMagicNumbersError);
-        }
-
-        let prg_size = try_get_next(bytes_ptr)?;
-        let chr_size = try_get_next(bytes_ptr)?;
-        let flags6 = try_get_next(bytes_ptr)?;
-        let flags7 = try_get_next(bytes_ptr)?;
-        let flags8 = try_get_next(bytes_ptr)?;
-        let flags9 = try_get_next(bytes_ptr)?;
-        let flags10 = try_get_next(bytes_ptr)?;
-        let _ = try_get_next_n(bytes_ptr, 5)?;
-
-        let header = Header {
-            prg_size,
-            chr_size,
-            flags6,
-            flags7,
-            flags8,
-            flags9,
-            flags10,
+        let header = Header::from_bytes(try_get_next_n(bytes_ptr, HEADER_SIZE)?)?;
+        let mut offset = HEADER_SIZE;
+
+        let trainer = if header.get_has_trainer() {
+            Some(take_section(bytes_ptr, &mut offset, "trainer", TRAINER_SIZE)?.to_vec())
+        } else {
+            None
         };
 
-        if header.get_has_trainer() {
-            let _ = try_get_next_n(bytes_ptr, 512)?;
-        }
+        let prg_size = header.prg_rom_size_bytes();
+        let prg_mem = take_section(bytes_ptr, &mut offset, "prg rom", prg_size)?.to_vec();
+        let chr_size = header.chr_rom_size_bytes();
+        let mut chr_mem = take_section(bytes_ptr, &mut offset, "chr rom", chr_size)?.to_vec();
 
-        let prg_mem = try_get_next_n(bytes_ptr, 16384 * prg_size as usize)?.to_vec();
-        let chr_mem = try_get_next_n(bytes_ptr, 8192 * chr_size as usize)?.to_vec();
+        // nes 2.0 roms can have miscellaneous roms at the end
+        // https://www.nesdev.org/wiki/NES_2.0#Miscellaneous_ROM_Area
+        if !bytes_ptr.is_empty() && header.get_misc_rom_count().unwrap_or(0) == 0 {
+            if options.strict {
+                return Err(CartrigeParseError::OverdumpError(bytes_ptr.len(), offset));
+            }
+            tracing::warn!(
+                target: targets::MAPPER,
+                surplus = bytes_ptr.len(),
+                "trimmed overdumped rom"
+            );
+        }
 
         let mapper = mappers::from_header(header.clone())?;
+        tracing::info!(
+            target: targets::MAPPER,
+            mapper_id = header.get_mapper_id(),
+            prg_rom = prg_mem.len(),
+            chr_rom = chr_mem.len(),
+            prg_ram = header.prg_ram_size_bytes(),
+            "loaded cartrige"
+        );
+        let checksums = Checksums::new(&prg_mem, &chr_mem);
+
+        // boards without chr rom have chr ram instead
+        if chr_mem.is_empty() {
+            chr_mem = vec![0; mapper.chr_ram_size()];
+        }
+
+        // boards with more than 8kb bank it into $6000-$7FFF through the
+        // mapper, see Mapper::map_prg_ram
+        let mut prg_ram = vec![0; header.prg_ram_size_bytes()];
+        // the trainer gets loaded at $7000
+        // https://www.nesdev.org/wiki/INES#Trainer
+        if let Some(trainer) = trainer.as_ref() {
+            if prg_ram.len() < PRG_RAM_BANK_SIZE {
+                prg_ram.resize(PRG_RAM_BANK_SIZE, 0);
+            }
+            let start = (TRAINER_ADDRESS - PRG_RAM_START) as usize;
+            prg_ram[start..start + TRAINER_SIZE].copy_from_slice(trainer);
+        }
 
         Ok(Self {
             mapper,
             header,
+            checksums,
+            trainer,
             prg_mem,
             chr_mem,
+            prg_ram,
+            irq_line: false,
+            game_genie: None,
         })
     }
 
-    // TODO: impl writing to chr or prg mem
-    pub fn write(&mut self, cartrige_access: CartrigeAccess, value: u8) {
-        let _ = self.mapper.map_write(cartrige_access, value);
+    /// Puts a raw binary without a header on a plain NROM board, see
+    /// [flat]. Parts loaded below $8000 go into the prg ram.
+    pub fn from_flat_binary(binary: &[u8], options: &FlatOptions) -> Result<Self> {
+        let (ines, ram) = flat::build(binary, options)?;
+        let mut cartrige = Self::from_bytes(&ines)?;
+        cartrige.prg_ram.copy_from_slice(&ram);
+        Ok(cartrige)
     }
 
-    pub fn read(&mut self, cartrige_access: CartrigeAccess) -> Option<u8> {
-        let addr = self.mapper.map_read(cartrige_access.clone())?;
-        match cartrige_access {
-            CartrigeAccess::CpuAccess { .. } => Some(self.prg_mem[addr as usize]),
-            CartrigeAccess::PpuAccess { .. } => Some(self.chr_mem[addr as usize]),
+    /// Serializes the cartrige back to the iNES format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header.to_bytes().to_vec();
+        if let Some(trainer) = self.trainer.as_ref() {
+            out.extend_from_slice(trainer);
+        }
+        out.extend_from_slice(&self.prg_mem);
+        if self.header.prg_chr_size() != 0 {
+            out.extend_from_slice(&self.chr_mem);
         }
+        out
     }
 
-    pub fn map_nametable(&self, address: u16) -> u16 {
-        self.mapper.map_nametable(address)
+    pub fn has_battery(&self) -> bool {
+        self.header.has_battery_backed_ram()
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TvSystem {
-    Ntsc,
-    Pal,
-    DualCompatible,
-    Unknown(u8),
-}
-
-#[derive(Clone)]
-pub struct Header {
-    prg_size: u8,
-    chr_size: u8,
-    flags6: u8,
-    flags7: u8,
-    flags8: u8,
-    flags9: u8,
-    flags10: u8,
-}
+    /// Plugs this cartrige into a Game Genie, which shows its menu until the
+    /// codes are picked. Takes effect right away so it should be followed
+    /// by a power cycle, like on hardware.
+    pub fn attach_game_genie(&mut self, game_genie: GameGenie) {
+        self.game_genie = Some(game_genie);
+    }
 
-impl Header {
-    pub fn prg_rom_size(&self) -> u8 {
-        self.prg_size
+    pub fn detach_game_genie(&mut self) -> Option<GameGenie> {
+        self.game_genie.take()
     }
 
-    pub fn prg_chr_size(&self) -> u8 {
-        self.chr_size
+    pub fn get_game_genie(&self) -> Option<&GameGenie> {
+        self.game_genie.as_ref()
     }
 
-    pub fn prg_rom_size_bytes(&self) -> usize {
-        self.prg_size as usize * PRG_ROM_BANK_SIZE
+    /// Mappers keep their registers through power cycles for now, only the
+    /// Game Genie goes back to its menu
+    pub(crate) fn power_cycle(&mut self) {
+        if let Some(game_genie) = self.game_genie.as_mut() {
+            game_genie.power_cycle();
+        }
     }
 
-    pub fn chr_rom_size_bytes(&self) -> usize {
-        self.chr_size as usize * CHR_ROM_BANK_SIZE
+    /// Clocks the mapper once every cpu cycle. Returns the mapper's irq
+    /// line when it changed, since the last tick or because of a write.
+    pub(crate) fn tick(&mut self) -> Option<bool> {
+        self.mapper.tick();
+        let line = self.mapper.is_irq_asserted();
+        if line == self.irq_line {
+            return None;
+        }
+        self.irq_line = line;
+        Some(line)
     }
 
-    pub fn prg_ram_size_bytes(&self) -> usize {
-        let units = if self.flags8 == 0 {
-            1
-        } else {
-            self.flags8 as usize
-        };
-        units * PRG_RAM_BANK_SIZE
+    /// An address the ppu put on its bus on `dot` (dots since power on),
+    /// scanline counters are clocked through this
+    pub(crate) fn ppu_address(&mut self, address: u16, dot: u64) {
+        if self
+            .game_genie
+            .as_ref()
+            .is_some_and(|game_genie| !game_genie.is_passing_through())
+        {
+            return;
+        }
+        self.mapper.ppu_address(address, dot);
     }
 
-    pub fn get_nametable_arrangement(&self) -> u8 {
-        self.flags6 & FLAG6_NAMETABLE
+    /// The sound chip on the board in its power on state, if there is one
+    pub fn expansion_audio(&self) -> Option<Box<dyn ExpansionAudio>> {
+        self.mapper.expansion_audio()
     }
 
-    pub fn get_mapper_id(&self) -> u8 {
-        ((self.flags6 >> 4) << 4) | (self.flags7 >> 4)
+    /// The prg rom cut up into the banks the mapper switches. Banks that
+    /// can be mapped to more than one place get the usual one.
+    pub fn prg_banks(&self) -> Vec<PrgBank<'_>> {
+        let bank_size = self.mapper.prg_bank_size();
+        let bank_count = self.prg_mem.len().div_ceil(bank_size);
+        self.prg_mem
+            .chunks(bank_size)
+            .enumerate()
+            .map(|(index, data)| PrgBank {
+                index,
+                address: self.mapper.prg_bank_address(index, bank_count),
+                data,
+            })
+            .collect()
     }
 
-    pub fn has_battery_backed_ram(&self) -> bool {
-        self.flags6 & FLAG6_BATTERY != 0
+    pub fn get_prg_ram(&self) -> &[u8] {
+        &self.prg_ram
     }
 
-    pub fn has_four_screen_vram(&self) -> bool {
-        self.flags6 & FLAG6_FOUR_SCREEN != 0
+    pub(crate) fn get_prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
     }
 
-    pub fn get_has_trainer(&self) -> bool {
-        self.flags6 & FLAG6_TRAINER != 0
+    /// Returns the battery backed ram in the `.sav` format or `None` if
+    /// the cartrige has no battery. That's all of the prg ram, every bank
+    /// of it. Flash boards save their whole prg rom.
+    pub fn export_sram(&self) -> Option<Vec<u8>> {
+        if !self.has_battery() {
+            None
+        } else if self.mapper.has_flash() {
+            Some(self.prg_mem.clone())
+        } else {
+            Some(self.prg_ram.clone())
+        }
     }
 
-    pub fn is_vs_unisystem(&self) -> bool {
-        self.flags7 & FLAG7_VS_UNISYSTEM != 0
+    /// Loads a `.sav` file, see [sram::load_into] for which sizes are accepted.
+    /// Flash saves have to match the prg rom size exactly.
+    pub fn import_sram(&mut self, save: &[u8]) -> sram::Result<()> {
+        if !self.has_battery() {
+            return Err(SramError::NoBatteryError);
+        }
+        if self.mapper.has_flash() {
+            if save.len() != self.prg_mem.len() {
+                return Err(SramError::FlashSizeError(save.len(), self.prg_mem.len()));
+            }
+            self.prg_mem.copy_from_slice(save);
+            return Ok(());
+        }
+        sram::load_into(&mut self.prg_ram, save)
     }
 
-    pub fn is_playchoice_10(&self) -> bool {
-        self.flags7 & FLAG7_PLAYCHOICE_10 != 0
+    /// Mappers only hand back an address for writes to chr ram,
+    /// prg rom writes are register writes
+    pub fn write(&mut self, cartrige_access: CartrigeAccess, value: u8) {
+        if let Some(game_genie) = self.game_genie.as_mut()
+            && game_genie.is_in_menu(&cartrige_access)
+        {
+            match cartrige_access {
+                CartrigeAccess::CpuAccess { address } => game_genie.write(address, value),
+                CartrigeAccess::PpuAccess { .. } => {
+                    game_genie.get_rom_mut().write(cartrige_access, value)
+                }
+            }
+            return;
+        }
+        if let CartrigeAccess::CpuAccess { address } = cartrige_access
+            && let Some(access) = self.map_prg_ram(address)
+        {
+            match access {
+                PrgRamAccess::ReadWrite(index) => self.prg_ram[index] = value,
+                _ => tracing::trace!(
+                    target: targets::MAPPER,
+                    address,
+                    "dropped write to protected prg ram"
+                ),
+            }
+            return;
+        }
+        if let CartrigeAccess::CpuAccess { address } = cartrige_access
+            && self.mapper.write_flash(address, value, &mut self.prg_mem)
+        {
+            return;
+        }
+        let addr = self.mapper.map_write(cartrige_access.clone(), value);
+        if let (CartrigeAccess::PpuAccess { .. }, Some(addr)) = (cartrige_access, addr)
+            && let Some(byte) = self.chr_mem.get_mut(addr)
+        {
+            *byte = value;
+        }
     }
 
-    pub fn is_nes_2_0(&self) -> bool {
-        self.flags7 & FLAG7_NES2_SIGNATURE_MASK == FLAG7_NES2_SIGNATURE_VALUE
+    /// Overwrites the byte the cpu sees at `address`, rom included, for
+    /// patching code from the debugger. Only the bank that is mapped
+    /// right now gets changed. Returns false if nothing is mapped there.
+    pub fn patch(&mut self, address: u16, value: u8) -> bool {
+        if let Some(PrgRamAccess::ReadOnly(index) | PrgRamAccess::ReadWrite(index)) =
+            self.map_prg_ram(address)
+        {
+            self.prg_ram[index] = value;
+            return true;
+        }
+        let Some(index) = self.mapper.map_read(CartrigeAccess::CpuAccess { address }) else {
+            return false;
+        };
+        self.prg_mem
+            .get_mut(index)
+            .map(|byte| *byte = value)
+            .is_some()
     }
 
-    pub fn tv_system(&self) -> TvSystem {
-        if self.is_nes_2_0() {
-            match self.flags10 & FLAG10_TV_SYSTEM_MASK {
-                0 => TvSystem::Ntsc,
-                2 => TvSystem::Pal,
-                1 | 3 => TvSystem::DualCompatible,
-                other => TvSystem::Unknown(other),
+    pub fn read(&mut self, cartrige_access: CartrigeAccess) -> Option<u8> {
+        let Some(game_genie) = self.game_genie.as_mut() else {
+            return self.read_game(cartrige_access);
+        };
+        if game_genie.is_in_menu(&cartrige_access) {
+            return game_genie.get_rom_mut().read(cartrige_access);
+        }
+        let value = self.read_game(cartrige_access.clone());
+        match (self.game_genie.as_ref(), cartrige_access) {
+            (Some(game_genie), CartrigeAccess::CpuAccess { address }) => {
+                game_genie.apply_codes(address, value)
             }
-        } else if self.flags9 & FLAG9_TV_SYSTEM != 0 {
-            TvSystem::Pal
+            _ => value,
+        }
+    }
+
+    fn read_game(&mut self, cartrige_access: CartrigeAccess) -> Option<u8> {
+        // disabled ram falls through to the mapper, some can put rom there
+        if let CartrigeAccess::CpuAccess { address } = cartrige_access
+            && let Some(PrgRamAccess::ReadOnly(index) | PrgRamAccess::ReadWrite(index)) =
+                self.map_prg_ram(address)
+        {
+            return Some(self.prg_ram[index]);
+        }
+        let addr = self.mapper.map_read(cartrige_access.clone())?;
+        match cartrige_access {
+            CartrigeAccess::CpuAccess { .. } => self.prg_mem.get(addr).copied(),
+            CartrigeAccess::PpuAccess { .. } => self.chr_mem.get(addr).copied(),
+        }
+    }
+
+    pub fn map_nametable(&self, address: u16) -> u16 {
+        if let Some(game_genie) = self.game_genie.as_ref()
+            && !game_genie.is_passing_through()
+        {
+            return game_genie.map_nametable(address);
+        }
+        self.mapper.map_nametable(address)
+    }
+
+    fn map_prg_ram(&self, address: u16) -> Option<PrgRamAccess> {
+        if (PRG_RAM_START..PRG_RAM_END).contains(&address) && !self.prg_ram.is_empty() {
+            Some(self.mapper.map_prg_ram(address, self.prg_ram.len()))
         } else {
-            TvSystem::Ntsc
+            None
+        }
+    }
+}
+
+/// Only the parts of the cartrige that can change get saved, the rom
+/// itself is checked with [Checksums] by whoever loads the state. An
+/// attached Game Genie goes last, states made with one only load with one.
+impl SaveState for Cartrige {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        if self.header.prg_chr_size() == 0 {
+            writer.write_bytes(&self.chr_mem);
+        }
+        if self.mapper.has_flash() {
+            writer.write_bytes(&self.prg_mem);
+        }
+        self.mapper.save_state(writer);
+        if let Some(game_genie) = self.game_genie.as_ref() {
+            game_genie.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        reader.read_bytes_into("prg ram", &mut self.prg_ram)?;
+        if self.header.prg_chr_size() == 0 {
+            reader.read_bytes_into("chr ram", &mut self.chr_mem)?;
+        }
+        if self.mapper.has_flash() {
+            reader.read_bytes_into("flash", &mut self.prg_mem)?;
+        }
+        self.mapper.load_state(reader)?;
+        if let Some(game_genie) = self.game_genie.as_mut() {
+            game_genie.load_state(reader)?;
         }
+        Ok(())
     }
 }
@@ -0,0 +1,196 @@
+//! Soft patching: IPS and BPS patches get applied to the rom in memory so
+//! translations and rom hacks can be played without touching the original.
+//! https://www.romhacking.net/documents/746/ (BPS)
+//! https://zerosoft.zophar.net/ips.php (IPS)
+
+use std::path::{Path, PathBuf};
+
+use crate::hardware::cartrige::error::PatchError;
+
+pub type Result<T> = std::result::Result<T, PatchError>;
+
+pub const IPS_EXTENSION: &str = "ips";
+pub const BPS_EXTENSION: &str = "bps";
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// source, target and patch crc32s at the end of every bps file
+const BPS_FOOTER_SIZE: usize = 12;
+
+/// Returns the patch sitting next to `rom_path`: the rom's file name with
+/// an `.ips` or `.bps` extension. BPS wins if there are both.
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    [BPS_EXTENSION, IPS_EXTENSION]
+        .into_iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+/// Applies an IPS or BPS patch to `rom`, the format is picked from the
+/// magic at the start of the patch
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormatError)
+    }
+}
+
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .patch
+            .get(self.position..self.position + n)
+            .ok_or(PatchError::TruncatedError(self.position))?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn read_be(&mut self, n: usize) -> Result<usize> {
+        Ok(self
+            .take(n)?
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | byte as usize))
+    }
+
+    /// BPS numbers are little endian base 128 with an implied +1 on every
+    /// continuation byte so each number has only one encoding
+    fn read_varint(&mut self) -> Result<usize> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.take(1)?[0];
+            value = value
+                .checked_add((byte & 0x7F) as usize * shift)
+                .ok_or(PatchError::InvalidNumberError(self.position))?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_mul(128)
+                .ok_or(PatchError::InvalidNumberError(self.position))?;
+            value += shift;
+        }
+    }
+}
+
+/// IPS records are a 3 byte offset and 2 byte size, a size of 0 means a
+/// run of one repeated byte. An optional 3 byte length after `EOF`
+/// truncates the output.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let mut out = rom.to_vec();
+    let mut reader = PatchReader {
+        patch,
+        position: IPS_MAGIC.len(),
+    };
+    loop {
+        if reader.patch[reader.position..].starts_with(IPS_EOF) {
+            reader.position += IPS_EOF.len();
+            break;
+        }
+        let offset = reader.read_be(3)?;
+        let size = reader.read_be(2)?;
+        let data = if size == 0 {
+            let run = reader.read_be(2)?;
+            let value = reader.take(1)?[0];
+            vec![value; run]
+        } else {
+            reader.take(size)?.to_vec()
+        };
+        if out.len() < offset + data.len() {
+            out.resize(offset + data.len(), 0);
+        }
+        out[offset..offset + data.len()].copy_from_slice(&data);
+    }
+    if let Ok(length) = reader.read_be(3) {
+        out.truncate(length);
+    }
+    Ok(out)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err(PatchError::TruncatedError(patch.len()));
+    }
+    let footer = patch.len() - BPS_FOOTER_SIZE;
+    let crc = |offset: usize| u32::from_le_bytes(patch[offset..offset + 4].try_into().unwrap());
+    let (source_crc, target_crc, patch_crc) = (crc(footer), crc(footer + 4), crc(footer + 8));
+    if crc32fast::hash(&patch[..footer + 8]) != patch_crc {
+        return Err(PatchError::ChecksumError("patch", patch_crc));
+    }
+    if crc32fast::hash(rom) != source_crc {
+        return Err(PatchError::ChecksumError("source rom", source_crc));
+    }
+
+    let mut reader = PatchReader {
+        patch: &patch[..footer],
+        position: BPS_MAGIC.len(),
+    };
+    let source_size = reader.read_varint()?;
+    let target_size = reader.read_varint()?;
+    let metadata_size = reader.read_varint()?;
+    reader.take(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(PatchError::SourceSizeError(source_size, rom.len()));
+    }
+
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+    let relative = |reader: &mut PatchReader, offset: &mut isize| -> Result<usize> {
+        let data = reader.read_varint()?;
+        let delta = (data >> 1) as isize;
+        *offset += if data & 1 == 0 { delta } else { -delta };
+        usize::try_from(*offset).map_err(|_| PatchError::InvalidOffsetError(reader.position))
+    };
+    while reader.position < reader.patch.len() {
+        let action_position = reader.position;
+        let data = reader.read_varint()?;
+        let length = (data >> 2) + 1;
+        match data & 0x03 {
+            // source read
+            0 => {
+                let start = out.len();
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::InvalidOffsetError(action_position))?;
+                out.extend_from_slice(bytes);
+            }
+            // target read
+            1 => out.extend_from_slice(reader.take(length)?),
+            // source copy
+            2 => {
+                let start = relative(&mut reader, &mut source_offset)?;
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::InvalidOffsetError(action_position))?;
+                out.extend_from_slice(bytes);
+                source_offset += length as isize;
+            }
+            // target copy, this can overlap with what it writes
+            _ => {
+                let start = relative(&mut reader, &mut target_offset)?;
+                for i in start..start + length {
+                    let byte = *out
+                        .get(i)
+                        .ok_or(PatchError::InvalidOffsetError(action_position))?;
+                    out.push(byte);
+                }
+                target_offset += length as isize;
+            }
+        }
+    }
+
+    if out.len() != target_size || crc32fast::hash(&out) != target_crc {
+        return Err(PatchError::ChecksumError("patched rom", target_crc));
+    }
+    Ok(out)
+}
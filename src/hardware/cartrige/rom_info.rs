@@ -0,0 +1,81 @@
+use serde::Serialize;
+
+use crate::hardware::{
+    cartrige::{Header, Mirroring, Nes2RamSizes, TvSystem, checksum::Checksums},
+    constants::cartrige::MAPPER_NAMES,
+};
+
+/// Everything about a rom in one place, made to be shown to users or
+/// exported with serde (for example as json)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RomInfo {
+    pub mapper_id: u16,
+    /// `None` for mappers that aren't in [MAPPER_NAMES]
+    pub mapper_name: Option<&'static str>,
+    pub is_mapper_supported: bool,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    pub tv_system: TvSystem,
+    pub is_vs_unisystem: bool,
+    pub is_playchoice_10: bool,
+    pub is_nes_2_0: bool,
+    /// Only set for NES 2.0 headers
+    pub nes_2_0: Option<Nes2Info>,
+    /// Only set if the info came from a whole cartrige, see
+    /// [Cartrige::get_rom_info](super::Cartrige::get_rom_info)
+    pub checksums: Option<Checksums>,
+}
+
+/// The things only NES 2.0 headers have
+/// https://www.nesdev.org/wiki/NES_2.0
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Nes2Info {
+    pub submapper_id: u8,
+    pub ram_sizes: Nes2RamSizes,
+    pub console_type: u8,
+    pub misc_rom_count: u8,
+    pub default_expansion_device: u8,
+}
+
+impl RomInfo {
+    pub fn from_header(header: &Header) -> Self {
+        let mapper_id = header.get_full_mapper_id();
+        let nes_2_0 = header.is_nes_2_0().then(|| Nes2Info {
+            submapper_id: header.get_submapper_id().unwrap_or_default(),
+            ram_sizes: header.nes_2_0_ram_sizes().unwrap(),
+            console_type: header.get_console_type().unwrap_or_default(),
+            misc_rom_count: header.get_misc_rom_count().unwrap_or_default(),
+            default_expansion_device: header.get_default_expansion_device().unwrap_or_default(),
+        });
+
+        Self {
+            mapper_id,
+            mapper_name: get_mapper_name(mapper_id),
+            is_mapper_supported: mapper_id <= 0xFF
+                && super::mappers::from_header(header.clone()).is_ok(),
+            prg_rom_size: header.prg_rom_size_bytes(),
+            chr_rom_size: header.chr_rom_size_bytes(),
+            prg_ram_size: header.prg_ram_size_bytes(),
+            mirroring: header.mirroring(),
+            has_battery: header.has_battery_backed_ram(),
+            has_trainer: header.get_has_trainer(),
+            tv_system: header.tv_system(),
+            is_vs_unisystem: header.is_vs_unisystem(),
+            is_playchoice_10: header.is_playchoice_10(),
+            is_nes_2_0: header.is_nes_2_0(),
+            nes_2_0,
+            checksums: None,
+        }
+    }
+}
+
+pub fn get_mapper_name(mapper_id: u16) -> Option<&'static str> {
+    MAPPER_NAMES
+        .iter()
+        .find(|(id, _)| *id == mapper_id)
+        .map(|(_, name)| *name)
+}
@@ -0,0 +1,38 @@
+//! Battery backed save ram, stored in the raw `.sav` format used by
+//! FCEUX and Mesen (just the contents of the PRG-RAM, no header).
+
+use std::path::{Path, PathBuf};
+
+use crate::hardware::cartrige::error::SramError;
+
+pub type Result<T> = std::result::Result<T, SramError>;
+
+pub const SAV_EXTENSION: &str = "sav";
+
+/// Returns the path FCEUX / Mesen would use for the save of `rom_path`:
+/// the rom's file name with a `.sav` extension. The save is put in
+/// `save_dir` if there is one and next to the rom otherwise.
+pub fn sav_path(rom_path: &Path, save_dir: Option<&Path>) -> PathBuf {
+    let file_name = rom_path.with_extension(SAV_EXTENSION);
+    match (save_dir, file_name.file_name()) {
+        (Some(save_dir), Some(file_name)) => save_dir.join(file_name),
+        _ => file_name,
+    }
+}
+
+/// Copies a save file into `ram`.
+///
+/// Save files don't always match the ram size exactly: some emulators
+/// always write 8kb and some only write the part of the ram the mapper
+/// uses. Smaller saves are zero padded and bigger ones are accepted as
+/// long as the extra bytes are all zero.
+pub(super) fn load_into(ram: &mut [u8], save: &[u8]) -> Result<()> {
+    if save.len() > ram.len() && save[ram.len()..].iter().any(|&byte| byte != 0) {
+        return Err(SramError::TooLargeError(save.len(), ram.len()));
+    }
+
+    let len = save.len().min(ram.len());
+    ram[..len].copy_from_slice(&save[..len]);
+    ram[len..].fill(0);
+    Ok(())
+}
@@ -27,6 +27,8 @@ pub mod clock_rates {
     pub const MASTER_CLOCK: u64 = ORIGINAL_MASTER_CLOCK / 4;
     pub const CPU_CLOCK: u64 = MASTER_CLOCK / 3;
     pub const APU_SAMPLE_RATE: u64 = 44_100;
+    /// Frames per second of an ntsc nes, actually it's closer to 60.0988
+    pub const FRAME_RATE: u32 = 60;
 }
 
 pub mod controller {
@@ -41,6 +43,57 @@ pub mod controller {
         pub const LEFT   :u8 = 0b01000000;
         pub const RIGHT  :u8 = 0b10000000;
     }
+
+    /// bit 2 of $4016, set while the famicom microphone picks up sound
+    /// https://www.nesdev.org/wiki/Standard_controller#Famicom
+    pub const MICROPHONE: u8 = 0b00000100;
+
+    /// the usual turbo rates of controllers like the NES Advantage
+    pub const TURBO_RATE_FAST: u32 = 30;
+    pub const TURBO_RATE_SLOW: u32 = 15;
+
+    /// bits read from every port before the four score keeps returning 1
+    pub const FOUR_SCORE_REPORT_SIZE: u32 = 24;
+    /// the last 8 bits of the $4016 and $4017 reports
+    /// https://www.nesdev.org/wiki/Four_Score
+    pub const FOUR_SCORE_SIGNATURES: [u8; 2] = [0b00001000, 0b00000100];
+
+    /// https://www.nesdev.org/wiki/Family_BASIC_Keyboard
+    pub mod keyboard {
+        pub const ROWS: usize = 9;
+        pub const COLUMNS: usize = 2;
+        pub const KEYS_PER_COLUMN: usize = 4;
+
+        /// $4016 write bits
+        pub const RESET: u8 = 0b00000001;
+        pub const COLUMN_SELECT: u8 = 0b00000010;
+        pub const ENABLE: u8 = 0b00000100;
+
+        /// bits 1 to 4 of $4017, the keys are active low
+        pub const KEYS_MASK: u8 = 0b00011110;
+
+        /// the data recorder plugged into the keyboard, the output is the
+        /// same bit as [ENABLE] and the input is read from $4016
+        /// https://www.nesdev.org/wiki/Family_BASIC_Data_Recorder
+        pub const TAPE_OUTPUT: u8 = 0b00000100;
+        pub const TAPE_INPUT: u8 = 0b00000010;
+    }
+
+    /// https://www.nesdev.org/wiki/Arkanoid_controller
+    pub mod paddle {
+        /// the knob's range in Arkanoid, from all the way left to all the
+        /// way right
+        pub const POSITION_MIN: u8 = 0x62;
+        pub const POSITION_MAX: u8 = 0xF2;
+
+        /// read bits of the NES version
+        pub const NES_FIRE: u8 = 0b00001000;
+        pub const NES_DATA: u8 = 0b00010000;
+        /// the famicom version has both on bit 1, the fire button in
+        /// $4016 and the data in $4017
+        pub const FAMICOM_FIRE: u8 = 0b00000010;
+        pub const FAMICOM_DATA: u8 = 0b00000010;
+    }
 }
 
 pub mod cpu {
@@ -62,6 +115,13 @@ pub mod cpu {
 
 pub mod cartrige {
     pub const NES_MAGIC_NUMBERS: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+    pub const HEADER_SIZE: usize = 16;
+    pub const TRAINER_SIZE: usize = 512;
+    pub const TRAINER_ADDRESS: u16 = 0x7000;
+    /// The first cpu address that goes to the cartrige
+    pub const CARTRIGE_START: u16 = 0x4020;
+    pub const PRG_RAM_START: u16 = 0x6000;
+    pub const PRG_RAM_END: u16 = 0x8000;
     pub const PRG_ROM_BANK_SIZE: usize = byte_size!(16 kb);
     pub const CHR_ROM_BANK_SIZE: usize = byte_size!(8 kb);
     pub const PRG_RAM_BANK_SIZE: usize = byte_size!(8 kb);
@@ -76,11 +136,50 @@ pub mod cartrige {
     pub const FLAG7_NES2_SIGNATURE_VALUE: u8 = 1 << 3;
     pub const FLAG9_TV_SYSTEM: u8 = 1 << 0;
     pub const FLAG10_TV_SYSTEM_MASK: u8 = (1 << 1) | (1 << 0);
+    pub const FLAG12_TIMING_MASK: u8 = (1 << 1) | (1 << 0);
+    pub const FLAG8_MAPPER_MSB: u8 = 0x0F;
+    pub const FLAG8_SUBMAPPER: u8 = 0xF0;
+    pub const FLAG10_PRG_RAM_SHIFT: u8 = 0x0F;
+    pub const FLAG10_PRG_NVRAM_SHIFT: u8 = 0xF0;
+    pub const FLAG11_CHR_RAM_SHIFT: u8 = 0x0F;
+    pub const FLAG11_CHR_NVRAM_SHIFT: u8 = 0xF0;
+    pub const FLAG13_CONSOLE_TYPE: u8 = 0x0F;
+    pub const FLAG14_MISC_ROMS: u8 = 0x03;
+    pub const FLAG15_EXPANSION_DEVICE: u8 = 0x3F;
+
+    /// names of the most common mappers
+    /// https://www.nesdev.org/wiki/Mapper
+    #[rustfmt::skip]
+    pub const MAPPER_NAMES: [(u16, &str); 16] = [
+        (0, "NROM"),    (1, "MMC1"),     (2, "UxROM"),   (3, "CNROM"),
+        (4, "MMC3"),    (5, "MMC5"),     (7, "AxROM"),   (9, "MMC2"),
+        (10, "MMC4"),   (11, "Color Dreams"), (19, "Namco 163"), (24, "VRC6a"),
+        (34, "BNROM"),  (66, "GxROM"),   (69, "FME-7"),  (71, "Camerica"),
+    ];
 }
 
 pub mod ppu {
     pub const PALLET_SIZE: usize = 0x20;
     pub const NAMETABLE_SIZE: usize = byte_size!(1 kb);
+    pub const TEMP_OAM_SIZE: usize = 32;
+    pub const SCREEN_WIDTH: usize = 256;
+    pub const SCREEN_HEIGHT: usize = 240;
+    /// https://www.nesdev.org/wiki/PPU_rendering
+    pub const DOTS_PER_SCANLINE: usize = 341;
+    pub const SCANLINES_PER_FRAME: usize = 262;
+    /// how much the color channels that are not emphasized get dimmed
+    /// https://www.nesdev.org/wiki/NTSC_video#Color_Tint_Bits
+    pub const EMPHASIS_ATTENUATION: f32 = 0.816328;
+    /// ntsc tvs draw the pixels a bit wider than they are tall
+    /// https://www.nesdev.org/wiki/Overscan#For_emulator_developers
+    pub const NTSC_PIXEL_ASPECT_RATIO: f64 = 8.0 / 7.0;
+    /// both pattern tables next to each other, 16x16 tiles of 8x8 pixels each
+    /// https://www.nesdev.org/wiki/PPU_pattern_tables
+    pub const PATTERN_TABLE_VIEW_WIDTH: usize = 256;
+    pub const PATTERN_TABLE_VIEW_HEIGHT: usize = 128;
+    /// PPUSTATUS, mirrored every 8 bytes up to $3FFF
+    /// https://www.nesdev.org/wiki/PPU_registers#PPUSTATUS
+    pub const STATUS_REGISTER: u16 = 0x2002;
 
     /// read more here: https://www.nesdev.org/wiki/PPU_scrolling
     #[rustfmt::skip]
@@ -199,8 +298,28 @@ pub mod apu {
         pub const ENABLE_NOISE          : u8 = 0b00001000;
         pub const ENABLE_DMC            : u8 = 0b00010000;
         pub const FRAME_INTERRUPT       : u8 = 0b01000000;
+        pub const DMC_INTERRUPT         : u8 = 0b10000000;
+    }
+
+    // https://www.nesdev.org/wiki/APU_DMC#Registers
+    #[rustfmt::skip]
+    pub mod dmc_register0{
+        pub const RATE                  : u8 = 0b00001111;
+        pub const LOOP                  : u8 = 0b01000000;
+        pub const IRQ_ENABLED           : u8 = 0b10000000;
     }
 
+    /// cpu cycles between the output bits of the dmc, by the rate index
+    #[rustfmt::skip]
+    pub const DMC_RATES_NTSC: [u16; 16] = [
+        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+    ];
+
+    #[rustfmt::skip]
+    pub const DMC_RATES_PAL: [u16; 16] = [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ];
+
     #[rustfmt::skip]
     pub mod frame_counter_register{
         pub const SEQUENCER_MODE        : u8 = 0b10000000;
@@ -228,6 +347,10 @@ pub mod apu {
     ];
 
     pub const SAMPLE_QUEUE_SIZE: usize = 2048 * 4;
+    /// pulse 1, pulse 2, triangle, noise and dmc
+    pub const CHANNEL_COUNT: usize = 5;
+    /// vrc6, vrc7, fds, mmc5, namco 163 and sunsoft 5b
+    pub const EXPANSION_CHIP_COUNT: usize = 6;
 }
 
 // #[rustfmt::skip]
@@ -0,0 +1,83 @@
+//! Counts of things the core does, for performance work and for checking
+//! a test rom does what it should (no irqs when it never enables them, a
+//! mapper write per frame...). They are relaxed atomics so counting costs
+//! next to nothing and another thread can read them while the nes runs.
+//! They start over on power cycles and aren't part of save states.
+
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Counter {
+    Instructions,
+    /// Cpu reads from $4020-$FFFF, peeks don't count
+    CartrigeReads,
+    /// Cpu writes to $8000-$FFFF, where the mapper registers are. Most
+    /// of them switch banks but not all, and on boards without registers
+    /// they go nowhere.
+    MapperWrites,
+    Nmis,
+    Irqs,
+    /// Cpu cycles spent halted for oam and dmc dma
+    DmaStalls,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 6] = [
+        Counter::Instructions,
+        Counter::CartrigeReads,
+        Counter::MapperWrites,
+        Counter::Nmis,
+        Counter::Irqs,
+        Counter::DmaStalls,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::Instructions => "instructions",
+            Counter::CartrigeReads => "cartrige reads",
+            Counter::MapperWrites => "mapper writes",
+            Counter::Nmis => "nmis",
+            Counter::Irqs => "irqs",
+            Counter::DmaStalls => "dma stalls",
+        }
+    }
+}
+
+/// See [Nes::counters](crate::Nes::counters)
+#[derive(Debug, Default)]
+pub struct Counters {
+    counts: [AtomicU64; Counter::ALL.len()],
+}
+
+impl Counters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn increment(&self, counter: Counter) {
+        self.counts[counter as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.counts[counter as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        for count in self.counts.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// One counter a line, what `scam --stats` prints at exit
+impl Display for Counters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for counter in Counter::ALL {
+            writeln!(f, "{:<16}{}", counter.name(), self.get(counter))?;
+        }
+        Ok(())
+    }
+}
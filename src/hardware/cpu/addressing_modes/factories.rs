@@ -341,6 +341,67 @@ pub(crate) const INDIRECT: fn(cpu: &Cpu, bus: &CpuBus) -> Box<MemoryAddressingMo
         })
     };
 
+/// [INDIRECT] without the page wrapping bug, the 65C02 fixed it
+pub(crate) const INDIRECT_FIXED: fn(cpu: &Cpu, bus: &CpuBus) -> Box<MemoryAddressingMode> =
+    |cpu: &Cpu, bus: &CpuBus| {
+        let pointer_address = bus.peek_u16(cpu.program_counter);
+        let address = bus.peek_u16(pointer_address);
+
+        Box::new(MemoryAddressingMode {
+            address,
+            cpu_program_counter_offset: 2,
+            cpu_additional_cycles_required: 0,
+            display: format!("({}) = {address:04X}", format_hex_u16(pointer_address)),
+        })
+    };
+
+/// Absolute indexed indirect addressing mode
+///
+/// Only the 65C02 `JMP ($1234,X)` uses this. Reads the pointer at the
+/// next two bytes + x and jumps to it.
+pub(crate) const ABSOLUTE_X_INDIRECT: fn(cpu: &Cpu, bus: &CpuBus) -> Box<MemoryAddressingMode> =
+    |cpu: &Cpu, bus: &CpuBus| {
+        let argument = bus.peek_u16(cpu.program_counter);
+        let pointer_address = argument.wrapping_add(cpu.x as u16);
+        let address = bus.peek_u16(pointer_address);
+
+        Box::new(MemoryAddressingMode {
+            address,
+            cpu_program_counter_offset: 2,
+            cpu_additional_cycles_required: 0,
+            display: format!(
+                "({},X) @ {pointer_address:04X} = {address:04X}",
+                format_hex_u16(argument)
+            ),
+        })
+    };
+
+/// Zero page indirect addressing mode
+///
+/// A 65C02 mode, like [INDIRECT_Y_OFFSET] without adding y. Reads an
+/// 8-bit pointer to a zero page location from the next byte and uses
+/// the address stored there.
+pub(crate) const ZERO_PAGE_INDIRECT: fn(cpu: &Cpu, bus: &CpuBus) -> Box<MemoryAddressingMode> =
+    |cpu: &Cpu, bus: &CpuBus| {
+        let argument = bus.peek(cpu.program_counter);
+
+        let low = bus.peek(argument as u16) as u16;
+        let high = bus.peek(argument.wrapping_add(1) as u16) as u16;
+        let address = (high << 8) | low;
+
+        let value = bus.peek(address);
+
+        Box::new(MemoryAddressingMode {
+            address,
+            cpu_program_counter_offset: 1,
+            cpu_additional_cycles_required: 0,
+            display: format!(
+                "({}) = {address:04X} = {value:02X}",
+                format_hex_u8(argument)
+            ),
+        })
+    };
+
 /// Indirect with x offset addressing mode
 ///
 /// Reads an 8-bit pointer to a zero page location from the next byte + x
@@ -15,8 +15,49 @@ pub(super) mod implementations;
 
 use std::fmt::Debug;
 
+use serde::Serialize;
+
 use crate::hardware::{cpu::Cpu, cpu_bus::CpuBus};
 
+/// Which addressing mode an opcode uses, without the closures that do
+/// the actual addressing. Used by the assembler and disassemblers.
+/// https://www.nesdev.org/wiki/CPU_addressing_modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum AddressingModeKind {
+    Implicit,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+    /// 65C02 only, `($10)`
+    ZeroPageIndirect,
+    /// 65C02 only, `JMP ($1234,X)`
+    AbsoluteIndirectX,
+}
+
+impl AddressingModeKind {
+    /// How many bytes come after the opcode
+    pub fn operand_size(self) -> u16 {
+        match self {
+            Self::Implicit | Self::Accumulator => 0,
+            Self::Absolute
+            | Self::AbsoluteX
+            | Self::AbsoluteY
+            | Self::Indirect
+            | Self::AbsoluteIndirectX => 2,
+            _ => 1,
+        }
+    }
+}
+
 pub(super) trait AddressingMode<T: Debug> {
     fn cpu_program_counter_offset(&self) -> u16;
     fn cpu_additional_cycles_required(&self) -> u8;
@@ -0,0 +1,300 @@
+//! A small 6502 assembler for tests and for patching code from the
+//! debugger. It takes the syntax [Cpu::disassemble](super::Cpu::disassemble)
+//! puts out, including the illegal opcodes and the `= 00`/`@ 0200` value
+//! annotations after the operands, so disassembled code can be assembled
+//! again as is.
+//!
+//! On top of that it knows `label:` definitions, labels as operands,
+//! `; comments` and the `.byte`/`.word` directives:
+//! ```text
+//! reset:  LDX #$05
+//! loop:   DEX          ; count down
+//!         BNE loop
+//!         JMP ($FFFC)
+//! table:  .byte $01, 2, %11
+//! ```
+//! Numbers are `$hex`, `%binary` or decimal. An operand that is a number
+//! of at most 2 hex digits (or under 256) picks the zero page version of
+//! an instruction when there is one, labels always pick the absolute one.
+//! When an opcode exists more than once the official one is used, then
+//! the lowest one.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use crate::hardware::cpu::{AddressingModeKind, OpcodeInfo, error::AssemblerError};
+
+pub type Result<T> = std::result::Result<T, AssemblerError>;
+
+/// The opcode of every mnemonic and addressing mode pair
+static OPCODES: LazyLock<HashMap<(&'static str, AddressingModeKind), u8>> = LazyLock::new(|| {
+    let mut opcodes: HashMap<_, u8> = HashMap::new();
+    for opcode in 0..=0xFF {
+        let info = OpcodeInfo::get(opcode);
+        opcodes
+            .entry((info.mnemonic, info.mode))
+            .and_modify(|existing| {
+                if OpcodeInfo::get(*existing).is_illegal && !info.is_illegal {
+                    *existing = opcode;
+                }
+            })
+            .or_insert(opcode);
+    }
+    opcodes
+});
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    /// `is_word` is true for numbers that have to be 2 bytes
+    Number {
+        value: u32,
+        is_word: bool,
+    },
+    Label(String),
+}
+
+/// The operand as it is written, the addressing mode gets picked from it
+/// and the mnemonic
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Value),
+    Direct(Value),
+    X(Value),
+    Y(Value),
+    Indirect(Value),
+    IndirectX(Value),
+    IndirectY(Value),
+}
+
+struct Assembler<'a> {
+    origin: u16,
+    /// `None` in the first pass, when only the sizes matter
+    labels: Option<&'a HashMap<String, u16>>,
+    line_number: usize,
+}
+
+/// Assembles `source` as if it starts at `origin`, which is needed for
+/// branches and labels
+pub fn assemble(origin: u16, source: &str) -> Result<Vec<u8>> {
+    // the first pass finds the labels, everything has the same size in
+    // both passes since labels are always 2 bytes
+    let (_, labels) = Assembler {
+        origin,
+        labels: None,
+        line_number: 0,
+    }
+    .run(source)?;
+    let (out, _) = Assembler {
+        origin,
+        labels: Some(&labels),
+        line_number: 0,
+    }
+    .run(source)?;
+    Ok(out)
+}
+
+impl Assembler<'_> {
+    fn run(&mut self, source: &str) -> Result<(Vec<u8>, HashMap<String, u16>)> {
+        let mut out = Vec::new();
+        let mut labels = HashMap::new();
+        for (index, line) in source.lines().enumerate() {
+            self.line_number = index + 1;
+            let mut line = line.split(';').next().unwrap_or_default().trim();
+            while let Some((label, rest)) = line.split_once(':')
+                && is_identifier(label.trim())
+            {
+                let address = self.origin.wrapping_add(out.len() as u16);
+                if labels.insert(label.trim().to_string(), address).is_some() {
+                    return Err(AssemblerError::DuplicateLabelError(
+                        self.line_number,
+                        label.trim().to_string(),
+                    ));
+                }
+                line = rest.trim();
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let (mnemonic, operand) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let address = self.origin.wrapping_add(out.len() as u16);
+            match mnemonic.to_ascii_lowercase().as_str() {
+                ".byte" | ".db" => {
+                    for value in operand.split(',') {
+                        let value = self.parse_value(value.trim())?;
+                        out.push(self.resolve(&value, 0xFF)? as u8);
+                    }
+                }
+                ".word" | ".dw" => {
+                    for value in operand.split(',') {
+                        let value = self.parse_value(value.trim())?;
+                        out.extend_from_slice(
+                            &(self.resolve(&value, 0xFFFF)? as u16).to_le_bytes(),
+                        );
+                    }
+                }
+                _ => out.extend(self.instruction(address, mnemonic, operand)?),
+            }
+        }
+        Ok((out, labels))
+    }
+
+    fn instruction(&self, address: u16, mnemonic: &str, operand: &str) -> Result<Vec<u8>> {
+        // the disassembler marks illegal opcodes like the nestest log does
+        let mnemonic = mnemonic.trim_start_matches('*').to_ascii_uppercase();
+        if !OPCODES.keys().any(|(name, _)| *name == mnemonic) {
+            return Err(AssemblerError::UnknownMnemonicError(
+                self.line_number,
+                mnemonic,
+            ));
+        }
+        let operand_text = strip_annotations(operand);
+        let operand = self.parse_operand(operand_text)?;
+        let has = |mode| OPCODES.contains_key(&(mnemonic.as_str(), mode));
+        let short = |value: &Value, short_mode, long_mode| {
+            let is_short = matches!(value, Value::Number { is_word: false, .. });
+            if has(short_mode) && (is_short || !has(long_mode)) {
+                short_mode
+            } else {
+                long_mode
+            }
+        };
+
+        use AddressingModeKind as Mode;
+        let (mode, value) = match &operand {
+            Operand::None if has(Mode::Implicit) => (Mode::Implicit, None),
+            Operand::None | Operand::Accumulator => (Mode::Accumulator, None),
+            Operand::Immediate(value) => (Mode::Immediate, Some(value)),
+            Operand::Direct(value) if has(Mode::Relative) => (Mode::Relative, Some(value)),
+            Operand::Direct(value) => (short(value, Mode::ZeroPage, Mode::Absolute), Some(value)),
+            Operand::X(value) => (short(value, Mode::ZeroPageX, Mode::AbsoluteX), Some(value)),
+            Operand::Y(value) => (short(value, Mode::ZeroPageY, Mode::AbsoluteY), Some(value)),
+            Operand::Indirect(value) => (Mode::Indirect, Some(value)),
+            Operand::IndirectX(value) => (Mode::IndirectX, Some(value)),
+            Operand::IndirectY(value) => (Mode::IndirectY, Some(value)),
+        };
+        let Some(opcode) = OPCODES.get(&(mnemonic.as_str(), mode)) else {
+            return Err(AssemblerError::InvalidModeError(
+                self.line_number,
+                mnemonic,
+                operand_text.to_string(),
+            ));
+        };
+
+        let mut out = vec![*opcode];
+        match (mode, value) {
+            (Mode::Relative, Some(value)) => {
+                let target = self.resolve(value, 0xFFFF)? as i32;
+                let offset = target - (address as i32 + 2);
+                if self.labels.is_some() && !(-128..=127).contains(&offset) {
+                    return Err(AssemblerError::BranchRangeError(self.line_number, offset));
+                }
+                out.push(offset as u8);
+            }
+            (_, Some(value)) if mode.operand_size() == 1 => {
+                out.push(self.resolve(value, 0xFF)? as u8);
+            }
+            (_, Some(value)) => {
+                out.extend_from_slice(&(self.resolve(value, 0xFFFF)? as u16).to_le_bytes());
+            }
+            (_, None) => (),
+        }
+        Ok(out)
+    }
+
+    fn parse_operand(&self, operand: &str) -> Result<Operand> {
+        let compact: String = operand.chars().filter(|c| !c.is_whitespace()).collect();
+        let upper = compact.to_ascii_uppercase();
+        if compact.is_empty() {
+            return Ok(Operand::None);
+        }
+        if upper == "A" {
+            return Ok(Operand::Accumulator);
+        }
+        if let Some(value) = compact.strip_prefix('#') {
+            return Ok(Operand::Immediate(self.parse_value(value)?));
+        }
+        let inner = |suffix: usize| &compact[1..compact.len() - suffix];
+        if upper.starts_with('(') {
+            return if upper.ends_with(",X)") {
+                Ok(Operand::IndirectX(self.parse_value(inner(3))?))
+            } else if upper.ends_with("),Y") {
+                Ok(Operand::IndirectY(self.parse_value(inner(3))?))
+            } else if upper.ends_with(')') {
+                Ok(Operand::Indirect(self.parse_value(inner(1))?))
+            } else {
+                Err(AssemblerError::InvalidOperandError(
+                    self.line_number,
+                    operand.to_string(),
+                ))
+            };
+        }
+        let value = &compact[..compact.len().saturating_sub(2)];
+        if upper.ends_with(",X") {
+            Ok(Operand::X(self.parse_value(value)?))
+        } else if upper.ends_with(",Y") {
+            Ok(Operand::Y(self.parse_value(value)?))
+        } else {
+            Ok(Operand::Direct(self.parse_value(&compact)?))
+        }
+    }
+
+    fn parse_value(&self, text: &str) -> Result<Value> {
+        let invalid = || AssemblerError::InvalidOperandError(self.line_number, text.to_string());
+        let number =
+            |digits: &str, radix| u32::from_str_radix(digits, radix).map_err(|_| invalid());
+        if let Some(hex) = text.strip_prefix('$') {
+            let value = number(hex, 16)?;
+            return Ok(Value::Number {
+                value,
+                is_word: hex.len() > 2 || value > 0xFF,
+            });
+        }
+        let value = if let Some(binary) = text.strip_prefix('%') {
+            number(binary, 2)?
+        } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+            number(text, 10)?
+        } else if is_identifier(text) {
+            return Ok(Value::Label(text.to_string()));
+        } else {
+            return Err(invalid());
+        };
+        Ok(Value::Number {
+            value,
+            is_word: value > 0xFF,
+        })
+    }
+
+    fn resolve(&self, value: &Value, max: u32) -> Result<u32> {
+        let value = match value {
+            Value::Number { value, .. } => *value,
+            Value::Label(label) => match self.labels {
+                Some(labels) => *labels.get(label).ok_or_else(|| {
+                    AssemblerError::UnknownLabelError(self.line_number, label.clone())
+                })? as u32,
+                None => 0,
+            },
+        };
+        if value > max {
+            return Err(AssemblerError::ValueRangeError(self.line_number, value));
+        }
+        Ok(value)
+    }
+}
+
+/// Cuts off what the disassembler adds after an operand, like the
+/// ` = 00` in `LDA $0200 = 00` or ` @ 85 = 00` in `LDA $80,X @ 85 = 00`
+fn strip_annotations(operand: &str) -> &str {
+    let end = [" = ", " @ "]
+        .iter()
+        .filter_map(|separator| operand.find(separator))
+        .min()
+        .unwrap_or(operand.len());
+    operand[..end].trim()
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
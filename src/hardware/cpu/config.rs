@@ -0,0 +1,64 @@
+/// What the cpu does when it runs into an unofficial opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IllegalOpcodePolicy {
+    /// Run it like the real 2A03 does
+    #[default]
+    Emulate,
+    /// Run it but log a warning with the address, for catching homebrew
+    /// that jumps into data
+    Warn,
+    /// Stop the `Nes::run_*` methods with
+    /// [BreakReason::IllegalOpcode](crate::devices::run::BreakReason::IllegalOpcode)
+    /// before it runs. Running again executes it.
+    Break,
+}
+
+/// What the cpu does when the stack pointer wraps around the stack page,
+/// pushing with it at $00 or pulling with it at $FF. Games don't do that
+/// on purpose so it's usually a stack bug that would crash a bit later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StackWrapPolicy {
+    /// Wrap like the real cpu does
+    #[default]
+    Ignore,
+    /// Wrap but log a warning with the address of the instruction
+    Warn,
+    /// Wrap and log a warning, then stop the `Nes::run_*` methods with
+    /// [BreakReason::StackOverflow](crate::devices::run::BreakReason::StackOverflow)
+    /// or [BreakReason::StackUnderflow](crate::devices::run::BreakReason::StackUnderflow)
+    /// right after the instruction
+    Break,
+}
+
+/// Which 6502 the cpu behaves like, so the cpu can be used outside of
+/// the nes too
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CpuVariant {
+    /// The nes cpu, the decimal flag can be set but ADC and SBC ignore it
+    /// https://www.nesdev.org/wiki/CPU#Overview
+    #[default]
+    Rp2A03,
+    /// ADC and SBC do BCD math in decimal mode, the flags come from the
+    /// binary math like on the original chip
+    Nmos6502,
+    /// Like [CpuVariant::Nmos6502] but N and Z match the decimal result,
+    /// with the new 65C02 instructions and addressing modes, NOPs
+    /// instead of illegal opcodes and the JMP indirect bug fixed. Decimal
+    /// mode is cleared by interrupts.
+    Cmos65C02,
+}
+
+impl CpuVariant {
+    pub fn has_decimal_mode(self) -> bool {
+        self != CpuVariant::Rp2A03
+    }
+}
+
+/// Settings for the cpu, they stay the same across resets and power
+/// cycles and aren't part of save states
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuConfig {
+    pub illegal_opcodes: IllegalOpcodePolicy,
+    pub stack_wraps: StackWrapPolicy,
+    pub variant: CpuVariant,
+}
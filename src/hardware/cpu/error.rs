@@ -0,0 +1,17 @@
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum AssemblerError {
+    #[error("Line {_0}: {_1:?} isn't an instruction!")]
+    UnknownMnemonicError(usize, String),
+    #[error("Line {_0}: couldn't parse the operand {_1:?}!")]
+    InvalidOperandError(usize, String),
+    #[error("Line {_0}: {_1} can't be used with the operand {_2:?}!")]
+    InvalidModeError(usize, String, String),
+    #[error("Line {_0}: {_1:#06X} doesn't fit in the operand!")]
+    ValueRangeError(usize, u32),
+    #[error("Line {_0}: the branch target is {_1} bytes away, at most 128 bytes are allowed!")]
+    BranchRangeError(usize, i32),
+    #[error("Line {_0}: the label {_1:?} isn't defined anywhere!")]
+    UnknownLabelError(usize, String),
+    #[error("Line {_0}: the label {_1:?} is already defined!")]
+    DuplicateLabelError(usize, String),
+}
@@ -9,13 +9,15 @@
 //!
 //! So basically the [INSTRUCTIONS_LOOKUP] holds [InstructionFactory]s
 //! that when instantiated return [Instruction]s that can be executed.
+//! [INSTRUCTIONS_65C02_LOOKUP] is the same table with the changes of the
+//! 65C02, [instructions_lookup] picks one for a [CpuVariant].
 
 use std::{fmt::Debug, sync::LazyLock};
 
 use crate::hardware::{
     cpu::{
-        Cpu,
-        addressing_modes::{AddressingMode, factories::*},
+        Cpu, CpuVariant, OpcodeInfo,
+        addressing_modes::{AddressingMode, AddressingModeKind, factories::*},
         operations::{Operation, *},
     },
     cpu_bus::CpuBus,
@@ -30,7 +32,7 @@ pub(super) struct Instruction<T> {
     is_illegal: bool,
 }
 
-pub trait InstructionTrait {
+pub(super) trait InstructionTrait {
     /// # Returns:
     /// The ammount of cycles required for that instruction to be executed
     fn execute(&mut self, cpu: &mut Cpu, bus: &mut CpuBus) -> u8;
@@ -71,6 +73,7 @@ pub(super) struct InstructionFactory<T, AM> {
     operation: Operation<T>,
     operation_name: &'static str,
     addressing_mode_factory: AddressingModeFactory<AM>,
+    addressing_mode_kind: AddressingModeKind,
     cycles: u8,
     can_require_extra_cycles: bool,
     is_illegal: bool,
@@ -80,6 +83,7 @@ pub(super) trait InstructionFactoryTrait: Send + Sync {
     /// # Returns:
     /// An executable and dissassemblable instruction
     fn create(&self, cpu: &Cpu, bus: &CpuBus) -> Box<dyn InstructionTrait>;
+    fn info(&self) -> OpcodeInfo;
 }
 
 impl<T: 'static + Debug, AM: AddressingMode<T> + 'static> InstructionFactoryTrait
@@ -95,11 +99,21 @@ impl<T: 'static + Debug, AM: AddressingMode<T> + 'static> InstructionFactoryTrai
             is_illegal: self.is_illegal,
         })
     }
+
+    fn info(&self) -> OpcodeInfo {
+        OpcodeInfo {
+            mnemonic: self.operation_name,
+            mode: self.addressing_mode_kind,
+            cycles: self.cycles,
+            is_illegal: self.is_illegal,
+        }
+    }
 }
 
 fn instruction_factory<T, AM>(
     operation: Operation<T>,
     mode: AddressingModeFactory<AM>,
+    mode_kind: AddressingModeKind,
     cycles: u8,
     name: &'static str,
     can_require_extra_cycles: bool,
@@ -112,6 +126,7 @@ where
     Box::new(InstructionFactory::<T, AM> {
         operation,
         addressing_mode_factory: mode,
+        addressing_mode_kind: mode_kind,
         cycles,
         operation_name: name,
         can_require_extra_cycles,
@@ -119,8 +134,72 @@ where
     })
 }
 
+macro_rules! mode_kind {
+    (IMPLICIT) => {
+        AddressingModeKind::Implicit
+    };
+    (ACCUMULATOR) => {
+        AddressingModeKind::Accumulator
+    };
+    (IMMEDIATE) => {
+        AddressingModeKind::Immediate
+    };
+    (ZERO_PAGE) => {
+        AddressingModeKind::ZeroPage
+    };
+    (ZERO_PAGE_X_OFFSET) => {
+        AddressingModeKind::ZeroPageX
+    };
+    (ZERO_PAGE_Y_OFFSET) => {
+        AddressingModeKind::ZeroPageY
+    };
+    (ABSOLUTE) => {
+        AddressingModeKind::Absolute
+    };
+    (ABSOLUTE_JMP) => {
+        AddressingModeKind::Absolute
+    };
+    (ABSOLUTE_X_OFFSET) => {
+        AddressingModeKind::AbsoluteX
+    };
+    (ABSOLUTE_Y_OFFSET) => {
+        AddressingModeKind::AbsoluteY
+    };
+    (INDIRECT) => {
+        AddressingModeKind::Indirect
+    };
+    (INDIRECT_FIXED) => {
+        AddressingModeKind::Indirect
+    };
+    (ABSOLUTE_X_INDIRECT) => {
+        AddressingModeKind::AbsoluteIndirectX
+    };
+    (ZERO_PAGE_INDIRECT) => {
+        AddressingModeKind::ZeroPageIndirect
+    };
+    (INDIRECT_X_OFFSET) => {
+        AddressingModeKind::IndirectX
+    };
+    (INDIRECT_Y_OFFSET) => {
+        AddressingModeKind::IndirectY
+    };
+    (RELATIVE) => {
+        AddressingModeKind::Relative
+    };
+}
+
 macro_rules! instruction {
-    ($operation:expr, $mode:ident, $cycles:literal, $name:expr, $extra:expr, $illegal:expr) => {{ instruction_factory($operation, $mode, $cycles, $name, $extra, $illegal) }};
+    ($operation:expr, $mode:ident, $cycles:literal, $name:expr, $extra:expr, $illegal:expr) => {{
+        instruction_factory(
+            $operation,
+            $mode,
+            mode_kind!($mode),
+            $cycles,
+            $name,
+            $extra,
+            $illegal,
+        )
+    }};
 }
 
 macro_rules! instruction_entry_set_name {
@@ -177,6 +256,23 @@ pub(super) static INSTRUCTIONS_LOOKUP: LazyLock<&'static [Box<dyn InstructionFac
         Box::leak(ops_slice)
     });
 
+pub(super) static INSTRUCTIONS_65C02_LOOKUP: LazyLock<&'static [Box<dyn InstructionFactoryTrait>]> =
+    LazyLock::new(|| {
+        let ops_slice = get_65c02_instructions().into_boxed_slice();
+        Box::leak(ops_slice)
+    });
+
+/// The lookup table of `variant`, the 2A03 and the nmos 6502 have the
+/// same opcodes
+pub(super) fn instructions_lookup(
+    variant: CpuVariant,
+) -> &'static [Box<dyn InstructionFactoryTrait>] {
+    match variant {
+        CpuVariant::Cmos65C02 => *INSTRUCTIONS_65C02_LOOKUP,
+        CpuVariant::Rp2A03 | CpuVariant::Nmos6502 => *INSTRUCTIONS_LOOKUP,
+    }
+}
+
 #[rustfmt::skip]
 fn get_instructions() -> Vec<Box<dyn InstructionFactoryTrait>> {
     // illegal ops from here https://www.masswerk.at/6502/6502_instruction_set.html
@@ -200,3 +296,43 @@ fn get_instructions() -> Vec<Box<dyn InstructionFactoryTrait>> {
         { BEQ, RELATIVE*   , 2 }, { SBC, INDIRECT_Y_OFFSET*, 5 }, {*JAM, IMPLICIT , 1 }, {*ISB, INDIRECT_Y_OFFSET ,8 }, {*NOP, ZERO_PAGE_X_OFFSET, 4 }, { SBC, ZERO_PAGE_X_OFFSET, 4 },{ INC, ZERO_PAGE_X_OFFSET, 6 }, {*ISB, ZERO_PAGE_X_OFFSET, 6 }, { SED, IMPLICIT, 2 }, { SBC, ABSOLUTE_Y_OFFSET*, 4 }, {*NOP, IMPLICIT   , 2 }, {*ISB, ABSOLUTE_Y_OFFSET , 7 }, {*NOP, ABSOLUTE_X_OFFSET*, 4 }, { SBC, ABSOLUTE_X_OFFSET*, 4 }, { INC, ABSOLUTE_X_OFFSET , 7 }, {*ISB, ABSOLUTE_X_OFFSET , 7 },
     ]
 }
+
+/// The 65C02 replaces the illegal opcodes with new instructions and NOPs,
+/// fixes the JMP indirect bug and is a cycle faster at some read modify
+/// writes. ADC and SBC get marked as taking extra cycles for decimal mode.
+/// This is the original 65C02, the bit instructions of the Rockwell and
+/// WDC ones (RMB, SMB, BBR, BBS) and WAI/STP are 1 byte NOPs.
+/// http://www.6502.org/tutorials/65c02opcodes.html
+#[rustfmt::skip]
+fn get_65c02_instructions() -> Vec<Box<dyn InstructionFactoryTrait>> {
+    let mut instructions = get_instructions();
+    // columns 3, 7, B and F are all 1 byte, 1 cycle NOPs
+    for opcode in (0x03..0x100).step_by(4) {
+        instructions[opcode] = instruction_entry!({*NOP, IMPLICIT, 1});
+    }
+    let changes = vec![
+        (0x02, instruction_entry!({*NOP, IMMEDIATE, 2 })), (0x22, instruction_entry!({*NOP, IMMEDIATE, 2 })), (0x42, instruction_entry!({*NOP, IMMEDIATE, 2 })), (0x62, instruction_entry!({*NOP, IMMEDIATE, 2 })),
+        (0x82, instruction_entry!({*NOP, IMMEDIATE, 2 })), (0xC2, instruction_entry!({*NOP, IMMEDIATE, 2 })), (0xE2, instruction_entry!({*NOP, IMMEDIATE, 2 })),
+        (0x44, instruction_entry!({*NOP, ZERO_PAGE, 3 })), (0x54, instruction_entry!({*NOP, ZERO_PAGE_X_OFFSET, 4 })), (0xD4, instruction_entry!({*NOP, ZERO_PAGE_X_OFFSET, 4 })), (0xF4, instruction_entry!({*NOP, ZERO_PAGE_X_OFFSET, 4 })),
+        (0x5C, instruction_entry!({*NOP, ABSOLUTE, 8 })), (0xDC, instruction_entry!({*NOP, ABSOLUTE, 4 })), (0xFC, instruction_entry!({*NOP, ABSOLUTE, 4 })),
+
+        (0x12, instruction_entry!({ ORA, ZERO_PAGE_INDIRECT, 5 })), (0x32, instruction_entry!({ AND, ZERO_PAGE_INDIRECT, 5 })), (0x52, instruction_entry!({ EOR, ZERO_PAGE_INDIRECT, 5 })), (0x72, instruction_entry!({ ADC, ZERO_PAGE_INDIRECT*, 5 })),
+        (0x92, instruction_entry!({ STA, ZERO_PAGE_INDIRECT, 5 })), (0xB2, instruction_entry!({ LDA, ZERO_PAGE_INDIRECT, 5 })), (0xD2, instruction_entry!({ CMP, ZERO_PAGE_INDIRECT, 5 })), (0xF2, instruction_entry!({ SBC, ZERO_PAGE_INDIRECT*, 5 })),
+
+        (0x04, instruction_entry!({ TSB, ZERO_PAGE, 5 })), (0x0C, instruction_entry!({ TSB, ABSOLUTE, 6 })), (0x14, instruction_entry!({ TRB, ZERO_PAGE, 5 })), (0x1C, instruction_entry!({ TRB, ABSOLUTE, 6 })),
+        (0x64, instruction_entry!({ STZ, ZERO_PAGE, 3 })), (0x74, instruction_entry!({ STZ, ZERO_PAGE_X_OFFSET, 4 })), (0x9C, instruction_entry!({ STZ, ABSOLUTE, 4 })), (0x9E, instruction_entry!({ STZ, ABSOLUTE_X_OFFSET, 5 })),
+        (0x34, instruction_entry!({ BIT, ZERO_PAGE_X_OFFSET, 4 })), (0x3C, instruction_entry!({ BIT, ABSOLUTE_X_OFFSET*, 4 })), (0x89, instruction!(BIT_IMMEDIATE, IMMEDIATE, 2, "BIT", false, false)),
+        (0x1A, instruction_entry!({ INC, ACCUMULATOR, 2 })), (0x3A, instruction_entry!({ DEC, ACCUMULATOR, 2 })),
+        (0x5A, instruction_entry!({ PHY, IMPLICIT, 3 })), (0x7A, instruction_entry!({ PLY, IMPLICIT, 4 })), (0xDA, instruction_entry!({ PHX, IMPLICIT, 3 })), (0xFA, instruction_entry!({ PLX, IMPLICIT, 4 })),
+        (0x80, instruction_entry!({ BRA, RELATIVE*, 2 })), (0x6C, instruction_entry!({ JMP, INDIRECT_FIXED, 6 })), (0x7C, instruction_entry!({ JMP, ABSOLUTE_X_INDIRECT, 6 })),
+
+        (0x1E, instruction_entry!({ ASL, ABSOLUTE_X_OFFSET*, 6 })), (0x3E, instruction_entry!({ ROL, ABSOLUTE_X_OFFSET*, 6 })), (0x5E, instruction_entry!({ LSR, ABSOLUTE_X_OFFSET*, 6 })), (0x7E, instruction_entry!({ ROR, ABSOLUTE_X_OFFSET*, 6 })),
+
+        (0x61, instruction_entry!({ ADC, INDIRECT_X_OFFSET*, 6 })), (0x65, instruction_entry!({ ADC, ZERO_PAGE*, 3 })), (0x69, instruction_entry!({ ADC, IMMEDIATE*, 2 })), (0x6D, instruction_entry!({ ADC, ABSOLUTE*, 4 })), (0x75, instruction_entry!({ ADC, ZERO_PAGE_X_OFFSET*, 4 })),
+        (0xE1, instruction_entry!({ SBC, INDIRECT_X_OFFSET*, 6 })), (0xE5, instruction_entry!({ SBC, ZERO_PAGE*, 3 })), (0xE9, instruction_entry!({ SBC, IMMEDIATE*, 2 })), (0xED, instruction_entry!({ SBC, ABSOLUTE*, 4 })), (0xF5, instruction_entry!({ SBC, ZERO_PAGE_X_OFFSET*, 4 })),
+    ];
+    for (opcode, instruction) in changes {
+        instructions[opcode] = instruction;
+    }
+    instructions
+}
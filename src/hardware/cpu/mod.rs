@@ -1,14 +1,48 @@
-use crate::hardware::{
-    bit_ops::BitOps,
-    constants::cpu::flags::*,
-    cpu::instructions::{INSTRUCTIONS_LOOKUP, InstructionTrait},
-    cpu_bus::CpuBus,
+use crate::{
+    hardware::{
+        bit_ops::BitOps,
+        constants::cpu::flags::*,
+        counters::Counter,
+        cpu::instructions::{INSTRUCTIONS_LOOKUP, instructions_lookup},
+        cpu_bus::CpuBus,
+    },
+    save_state::{self, SaveState, StateReader, StateWriter, error::SaveStateError},
+    trace::targets,
 };
 
+pub mod assembler;
+pub mod config;
+pub mod error;
+
 mod addressing_modes;
 mod instructions;
 mod operations;
 
+pub use addressing_modes::AddressingModeKind;
+pub use config::{CpuConfig, CpuVariant, IllegalOpcodePolicy, StackWrapPolicy};
+
+/// What an opcode is, straight from the
+/// [lookup table](instructions::INSTRUCTIONS_LOOKUP)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    pub mode: AddressingModeKind,
+    /// Without the extra cycles for crossing pages and taking branches
+    pub cycles: u8,
+    pub is_illegal: bool,
+}
+
+impl OpcodeInfo {
+    /// The 2A03 version of `opcode`
+    pub fn get(opcode: u8) -> Self {
+        INSTRUCTIONS_LOOKUP[opcode as usize].info()
+    }
+
+    pub fn get_for(variant: CpuVariant, opcode: u8) -> Self {
+        instructions_lookup(variant)[opcode as usize].info()
+    }
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub enum DmaState {
     #[default]
@@ -23,6 +57,55 @@ pub enum DmaState {
     },
 }
 
+/// The registers visible to programs
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    pub accumulator: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+}
+
+/// How the stack pointer wrapped, see [StackWrapPolicy]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackWrap {
+    /// Pushed with the stack pointer at $00
+    Overflow,
+    /// Pulled with the stack pointer at $FF
+    Underflow,
+}
+
+/// An nmi or irq the cpu took, see [Cpu::get_last_interrupt]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TakenInterrupt {
+    pub is_nmi: bool,
+    /// The instruction that got interrupted, RTI goes back to it
+    pub return_address: u16,
+    /// Before the return address and status got pushed
+    pub stack_pointer: u8,
+    pub handler: u16,
+    /// [Cpu::get_total_cycles] when the handler started
+    pub cycle: u64,
+    pub scanline: u32,
+    pub dot: u32,
+    /// Taken at the start of an instruction, the first instruction of the
+    /// handler ran in the same cycle
+    pub ran_first_instruction: bool,
+}
+
+/// An instruction decoded by [Cpu::disassemble]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    pub address: u16,
+    /// The opcode followed by its operands
+    pub bytes: Vec<u8>,
+    /// In the same format as the nestest log, like `LDA $0200 = 00`
+    pub text: String,
+    pub is_illegal: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Cpu {
     accumulator: u8,
@@ -35,6 +118,12 @@ pub struct Cpu {
     total_cycles: u64,
     is_resetting: bool,
     is_jammed: bool, // Caused by the JAM instruction
+    config: CpuConfig,
+    /// The first wrap since [Cpu::take_stack_wrap], with the address of
+    /// the instruction. Only kept with [StackWrapPolicy::Break].
+    stack_wrap: Option<(StackWrap, u16)>,
+    interrupts_taken: u64,
+    last_interrupt: Option<TakenInterrupt>,
     pub is_triggered_nmi: bool,
     pub is_triggered_irq: bool,
     pub dma_status: DmaState,
@@ -54,6 +143,10 @@ impl Cpu {
             total_cycles: 7,
             is_resetting: false,
             is_jammed: false,
+            config: CpuConfig::default(),
+            stack_wrap: None,
+            interrupts_taken: 0,
+            last_interrupt: None,
             is_triggered_irq: false,
             is_triggered_nmi: false,
             dma_status: DmaState::None,
@@ -64,35 +157,137 @@ impl Cpu {
         self.is_resetting
     }
 
+    pub fn get_config(&self) -> CpuConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: CpuConfig) {
+        self.config = config;
+    }
+
+    /// Back to the power on state, the config stays
+    pub(crate) fn power_cycle(&mut self) {
+        *self = Self {
+            config: self.config,
+            ..Self::new()
+        };
+    }
+
     pub fn reset(&mut self, bus: &CpuBus) {
-        *self = Self::new();
+        self.power_cycle();
         self.program_counter = bus.read_u16(0xFFFC);
         self.is_jammed = false;
         self.is_resetting = false;
     }
 
     pub fn reset_with_program_counter(&mut self, program_counter: u16) {
-        *self = Self::new();
+        self.power_cycle();
         self.program_counter = program_counter;
         self.is_jammed = false;
         self.is_resetting = false;
     }
 
+    pub fn get_registers(&self) -> CpuRegisters {
+        CpuRegisters {
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            accumulator: self.accumulator,
+            x: self.x,
+            y: self.y,
+            status: self.status,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: CpuRegisters) {
+        self.program_counter = registers.program_counter;
+        self.stack_pointer = registers.stack_pointer;
+        self.accumulator = registers.accumulator;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.status = registers.status;
+    }
+
+    /// Decodes the instruction at `address` without running it. The
+    /// values shown for memory operands are what they are right now.
+    pub fn disassemble(&self, bus: &CpuBus, address: u16) -> DisassembledInstruction {
+        let mut cpu = self.clone();
+        cpu.program_counter = address.wrapping_add(1);
+        let opcode = bus.peek(address);
+        let instruction =
+            instructions_lookup(self.config.variant)[opcode as usize].create(&cpu, bus);
+
+        let length = 1 + instruction.next_instruction_offset();
+        let disasm = instruction.disassemble_instruction();
+        DisassembledInstruction {
+            address,
+            bytes: (0..length)
+                .map(|i| bus.peek(address.wrapping_add(i)))
+                .collect(),
+            text: disasm[1..].trim_end().to_string(),
+            is_illegal: disasm.starts_with('*'),
+        }
+    }
+
+    /// Jammed cpus don't do anything until they get reset
+    pub fn is_jammed(&self) -> bool {
+        self.is_jammed
+    }
+
     pub fn get_program_counter(&self) -> u16 {
         self.program_counter
     }
 
-    pub fn push_stack(&mut self, value: u8, bus: &mut CpuBus) {
+    /// How many nmis and irqs the cpu took since power on, not counting
+    /// BRK
+    pub fn get_interrupts_taken(&self) -> u64 {
+        self.interrupts_taken
+    }
+
+    /// The latest nmi or irq. Taken in the middle of an instruction its
+    /// handler only starts after that instruction, at the start of one
+    /// the handler's first instruction runs right away.
+    pub fn get_last_interrupt(&self) -> Option<TakenInterrupt> {
+        self.last_interrupt
+    }
+
+    /// Takes the stack wrap that happened since the last call
+    pub fn take_stack_wrap(&mut self) -> Option<(StackWrap, u16)> {
+        self.stack_wrap.take()
+    }
+
+    fn on_stack_wrap(&mut self, wrap: StackWrap, bus: &CpuBus) {
+        if self.config.stack_wraps == StackWrapPolicy::Ignore {
+            return;
+        }
+        let address = bus.get_instruction_address();
+        match wrap {
+            StackWrap::Overflow => tracing::warn!(target: targets::CPU, address, "stack overflow"),
+            StackWrap::Underflow => {
+                tracing::warn!(target: targets::CPU, address, "stack underflow")
+            }
+        }
+        if self.config.stack_wraps == StackWrapPolicy::Break {
+            self.stack_wrap.get_or_insert((wrap, address));
+        }
+    }
+
+    fn push_stack(&mut self, value: u8, bus: &mut CpuBus) {
+        if self.stack_pointer == 0x00 {
+            self.on_stack_wrap(StackWrap::Overflow, bus);
+        }
         bus.write(0x100 + self.stack_pointer as u16, value);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
-    pub fn pop_stack(&mut self, bus: &CpuBus) -> u8 {
+    fn pop_stack(&mut self, bus: &CpuBus) -> u8 {
+        if self.stack_pointer == 0xFF {
+            self.on_stack_wrap(StackWrap::Underflow, bus);
+        }
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
         bus.read(0x100 + self.stack_pointer as u16)
     }
 
-    pub fn push_stack_u16(&mut self, value: u16, bus: &mut CpuBus) {
+    fn push_stack_u16(&mut self, value: u16, bus: &mut CpuBus) {
         let high = (value >> 8) as u8;
         let low = value as u8;
 
@@ -100,7 +295,7 @@ impl Cpu {
         self.push_stack(low, bus);
     }
 
-    pub fn pop_stack_u16(&mut self, bus: &CpuBus) -> u16 {
+    fn pop_stack_u16(&mut self, bus: &CpuBus) -> u16 {
         let low = self.pop_stack(bus) as u16;
         let high = self.pop_stack(bus) as u16;
         (high << 8) | low
@@ -114,18 +309,6 @@ impl Cpu {
         self.total_cycles
     }
 
-    pub fn get_next_instruction(&mut self, bus: &CpuBus) -> Box<dyn InstructionTrait> {
-        let instruction_code = bus.peek(self.program_counter);
-
-        self.program_counter += 1;
-
-        let next_instruction = (&INSTRUCTIONS_LOOKUP[instruction_code as usize]).create(self, bus);
-
-        self.program_counter += next_instruction.next_instruction_offset();
-
-        return next_instruction;
-    }
-
     pub fn tick(&mut self, bus: &mut CpuBus) {
         if self.is_jammed {
             return;
@@ -138,15 +321,36 @@ impl Cpu {
         if self.is_triggered_nmi
             || (self.is_triggered_irq && !self.status.get_flag_enabled(INTERRUPT_DISABLE))
         {
+            bus.set_instruction_address(self.program_counter);
+            let (return_address, stack_pointer) = (self.program_counter, self.stack_pointer);
             self.push_stack_u16(self.program_counter, bus);
             self.push_stack(self.status, bus);
             self.status.set_flag_enabled(INTERRUPT_DISABLE, true);
+            if self.config.variant == CpuVariant::Cmos65C02 {
+                self.status.set_flag_enabled(DECIMAL_MODE, false);
+            }
 
             if self.is_triggered_nmi {
+                tracing::debug!(target: targets::CPU, "nmi");
+                bus.get_counters().increment(Counter::Nmis);
                 self.program_counter = bus.read_u16(0xFFFA);
             } else {
+                tracing::debug!(target: targets::CPU, "irq");
+                bus.get_counters().increment(Counter::Irqs);
                 self.program_counter = bus.read_u16(0xFFFE);
             }
+            self.interrupts_taken += 1;
+            let (scanline, dot) = bus.get_ppu_position();
+            self.last_interrupt = Some(TakenInterrupt {
+                is_nmi: self.is_triggered_nmi,
+                return_address,
+                stack_pointer,
+                handler: self.program_counter,
+                cycle: self.total_cycles,
+                scanline,
+                dot,
+                ran_first_instruction: self.cycles_left == 0,
+            });
 
             self.is_triggered_nmi = false;
             self.is_triggered_irq = false;
@@ -156,42 +360,61 @@ impl Cpu {
             self.cycles_left -= 1;
         } else {
             let instruction_location = self.program_counter;
+            bus.set_instruction_address(instruction_location);
+            bus.get_counters().increment(Counter::Instructions);
             let instruction_code = bus.peek(self.program_counter);
+            bus.notify_execute(instruction_location, instruction_code, &self.config);
 
             self.program_counter += 1;
 
-            let mut next_instruction =
-                (&INSTRUCTIONS_LOOKUP[instruction_code as usize]).create(self, bus);
+            if self.config.illegal_opcodes == IllegalOpcodePolicy::Warn
+                && OpcodeInfo::get_for(self.config.variant, instruction_code).is_illegal
+            {
+                tracing::warn!(
+                    target: targets::CPU,
+                    address = instruction_location,
+                    opcode = instruction_code,
+                    "illegal opcode"
+                );
+            }
+
+            let mut next_instruction = instructions_lookup(self.config.variant)
+                [instruction_code as usize]
+                .create(self, bus);
 
             // We are incrementing the program counter to the first location
             // after the immediate value. This is the expected behaviour
             // on the 6502 so yeah
             self.program_counter += next_instruction.next_instruction_offset();
 
-            let length = 1 + next_instruction.next_instruction_offset() as usize;
-            let mut bytes = Vec::with_capacity(length);
-            for i in 0..length {
-                bytes.push(bus.peek(instruction_location + i as u16));
+            // the trace is in the same format as the nestest log
+            if tracing::enabled!(target: targets::CPU, tracing::Level::TRACE) {
+                let length = 1 + next_instruction.next_instruction_offset() as usize;
+                let mut bytes = Vec::with_capacity(length);
+                for i in 0..length {
+                    bytes.push(bus.peek(instruction_location + i as u16));
+                }
+                let byte_str = match length {
+                    1 => format!("{:02X}      ", bytes[0]),
+                    2 => format!("{:02X} {:02X}   ", bytes[0], bytes[1]),
+                    3 => format!("{:02X} {:02X} {:02X}", bytes[0], bytes[1], bytes[2]),
+                    _ => unreachable!(),
+                };
+                let disasm = next_instruction.disassemble_instruction();
+                tracing::trace!(
+                    target: targets::CPU,
+                    "{:04X}  {} {:<33}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                    instruction_location,
+                    byte_str,
+                    disasm,
+                    self.accumulator,
+                    self.x,
+                    self.y,
+                    self.status,
+                    self.stack_pointer,
+                    self.total_cycles
+                );
             }
-            let byte_str = match length {
-                1 => format!("{:02X}      ", bytes[0]),
-                2 => format!("{:02X} {:02X}   ", bytes[0], bytes[1]),
-                3 => format!("{:02X} {:02X} {:02X}", bytes[0], bytes[1], bytes[2]),
-                _ => unreachable!(),
-            };
-            let disasm = next_instruction.disassemble_instruction();
-            log::info!(
-                "{:04X}  {} {:<33}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
-                instruction_location,
-                byte_str,
-                disasm,
-                self.accumulator,
-                self.x,
-                self.y,
-                self.status,
-                self.stack_pointer,
-                self.total_cycles
-            );
 
             let required_cycles = next_instruction.execute(self, bus);
             self.cycles_left += required_cycles;
@@ -204,3 +427,75 @@ impl Cpu {
         }
     }
 }
+
+impl SaveState for DmaState {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match *self {
+            DmaState::None => writer.write_u8(0),
+            DmaState::Initializing { page } => {
+                writer.write_u8(1);
+                writer.write_u8(page);
+            }
+            DmaState::Transfering {
+                page,
+                index,
+                fetched_value,
+            } => {
+                writer.write_u8(2);
+                writer.write_u8(page);
+                writer.write_u8(index);
+                writer.write_u8(fetched_value);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        *self = match reader.read_u8()? {
+            0 => DmaState::None,
+            1 => DmaState::Initializing {
+                page: reader.read_u8()?,
+            },
+            2 => DmaState::Transfering {
+                page: reader.read_u8()?,
+                index: reader.read_u8()?,
+                fetched_value: reader.read_u8()?,
+            },
+            other => return Err(SaveStateError::InvalidValueError("dma state", other as u64)),
+        };
+        Ok(())
+    }
+}
+
+impl SaveState for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.accumulator);
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u16(self.program_counter);
+        writer.write_u8(self.stack_pointer);
+        writer.write_u8(self.status);
+        writer.write_u8(self.cycles_left);
+        writer.write_u64(self.total_cycles);
+        writer.write_bool(self.is_resetting);
+        writer.write_bool(self.is_jammed);
+        writer.write_bool(self.is_triggered_nmi);
+        writer.write_bool(self.is_triggered_irq);
+        self.dma_status.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.accumulator = reader.read_u8()?;
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        self.program_counter = reader.read_u16()?;
+        self.stack_pointer = reader.read_u8()?;
+        self.status = reader.read_u8()?;
+        self.cycles_left = reader.read_u8()?;
+        self.total_cycles = reader.read_u64()?;
+        self.is_resetting = reader.read_bool()?;
+        self.is_jammed = reader.read_bool()?;
+        self.is_triggered_nmi = reader.read_bool()?;
+        self.is_triggered_irq = reader.read_bool()?;
+        self.dma_status.load_state(reader)
+    }
+}
@@ -2,7 +2,7 @@ use crate::hardware::{
     bit_ops::BitOps,
     constants::cpu::flags::*,
     cpu::{
-        Cpu,
+        Cpu, CpuVariant,
         addressing_modes::{AddressingMode, implementations::MemoryAddress},
     },
     cpu_bus::CpuBus,
@@ -12,8 +12,88 @@ use crate::hardware::{
 /// The ammount of extra cycles that operation required
 pub(super) type Operation<T> = fn(&mut Cpu, &mut CpuBus, &mut Box<dyn AddressingMode<T>>);
 
+/// True if ADC and SBC should do BCD math
+fn is_decimal(cpu: &Cpu) -> bool {
+    cpu.config.variant.has_decimal_mode() && cpu.status.get_flag_enabled(DECIMAL_MODE)
+}
+
+/// Decimal ADC, from appendix A of http://www.6502.org/tutorials/decimal_mode.html
+/// The nmos 6502 takes N and V from the result before the high digit
+/// gets fixed up and Z from the binary sum.
+fn decimal_adc(cpu: &mut Cpu, argument: u8) {
+    let carry = cpu.status.get_flag_enabled(CARRY) as u16;
+    let binary = cpu.accumulator as u16 + argument as u16 + carry;
+
+    let mut low = (cpu.accumulator & 0x0F) as u16 + (argument & 0x0F) as u16 + carry;
+    if low >= 0x0A {
+        low = ((low + 0x06) & 0x0F) + 0x10;
+    }
+    let mut result = (cpu.accumulator & 0xF0) as u16 + (argument & 0xF0) as u16 + low;
+    cpu.status.set_flag_enabled(
+        OVERFLOW,
+        (result as u8 ^ cpu.accumulator) & (result as u8 ^ argument) & 0x80 > 0,
+    );
+    cpu.status.set_flag_enabled(NEGATIVE, result & 0x80 > 0);
+    cpu.status.set_flag_enabled(ZERO, binary & 0xFF == 0);
+    if result >= 0xA0 {
+        result += 0x60;
+    }
+    cpu.status.set_flag_enabled(CARRY, result > 0xFF);
+
+    cpu.accumulator = result as u8;
+    if cpu.config.variant == CpuVariant::Cmos65C02 {
+        cpu.status.set_flag_enabled(ZERO, cpu.accumulator == 0);
+        cpu.status
+            .set_flag_enabled(NEGATIVE, cpu.accumulator & 0x80 > 0);
+    }
+}
+
+/// Decimal SBC, the flags are set by the binary SBC before this so
+/// `carry` is the one from before the instruction. The 65C02 fixes the
+/// digits up differently and sets N and Z again.
+fn decimal_sbc(cpu: &mut Cpu, argument: u8, carry: bool) {
+    let accumulator = cpu.accumulator;
+    let borrow = 1 - carry as i16;
+    let low = (accumulator & 0x0F) as i16 - (argument & 0x0F) as i16 - borrow;
+    let result = if cpu.config.variant == CpuVariant::Cmos65C02 {
+        let mut result = accumulator as i16 - argument as i16 - borrow;
+        if result < 0 {
+            result -= 0x60;
+        }
+        if low < 0 {
+            result -= 0x06;
+        }
+        result
+    } else {
+        let low = if low < 0 {
+            ((low - 0x06) & 0x0F) - 0x10
+        } else {
+            low
+        };
+        let mut result = (accumulator & 0xF0) as i16 - (argument & 0xF0) as i16 + low;
+        if result < 0 {
+            result -= 0x60;
+        }
+        result
+    };
+
+    cpu.accumulator = result as u8;
+    if cpu.config.variant == CpuVariant::Cmos65C02 {
+        cpu.status.set_flag_enabled(ZERO, cpu.accumulator == 0);
+        cpu.status
+            .set_flag_enabled(NEGATIVE, cpu.accumulator & 0x80 > 0);
+    }
+}
+
 pub(super) const ADC: Operation<u8> = |cpu, bus, addressing_mode| {
     let argument = addressing_mode.read(cpu, bus);
+    if is_decimal(cpu) {
+        decimal_adc(cpu, argument);
+        if cpu.config.variant == CpuVariant::Cmos65C02 {
+            addressing_mode.cpu_add_another_required_cycle();
+        }
+        return;
+    }
     let result: u16 =
         cpu.accumulator as u16 + argument as u16 + cpu.status.get_flag_enabled(CARRY) as u16;
 
@@ -139,6 +219,15 @@ pub(super) const BIT: Operation<u8> = |cpu, bus, addressing_mode| {
     cpu.status.set_flag_enabled(OVERFLOW, argument & 0x40 > 0);
 };
 
+/// 65C02 `BIT #imm`, there's no memory to take N and V from so only Z
+/// changes
+pub(super) const BIT_IMMEDIATE: Operation<u8> = |cpu, bus, addressing_mode| {
+    let argument = addressing_mode.read(cpu, bus);
+
+    cpu.status
+        .set_flag_enabled(ZERO, cpu.accumulator & argument == 0);
+};
+
 pub(super) const BMI: Operation<i8> = |cpu, bus, addressing_mode| {
     let argument = addressing_mode.read(cpu, bus);
 
@@ -163,6 +252,13 @@ pub(super) const BPL: Operation<i8> = |cpu, bus, addressing_mode| {
     }
 };
 
+/// 65C02 branch always
+pub(super) const BRA: Operation<i8> = |cpu, bus, addressing_mode| {
+    let argument = addressing_mode.read(cpu, bus);
+
+    branch(cpu, addressing_mode, argument);
+};
+
 pub(super) const BRK: Operation<()> = |cpu, bus, _| {
     cpu.is_resetting = true;
     cpu.program_counter += 1;
@@ -171,6 +267,9 @@ pub(super) const BRK: Operation<()> = |cpu, bus, _| {
     cpu.push_stack(cpu.status | BREAK, bus);
 
     cpu.status.set_flag_enabled(INTERRUPT_DISABLE, true);
+    if cpu.config.variant == CpuVariant::Cmos65C02 {
+        cpu.status.set_flag_enabled(DECIMAL_MODE, false);
+    }
     cpu.program_counter = bus.read_u16(0xFFFE);
 };
 
@@ -406,6 +505,14 @@ pub(super) const PHP: Operation<()> = |cpu, bus, _| {
     cpu.push_stack(cpu.status | BREAK | UNUSED, bus);
 };
 
+pub(super) const PHX: Operation<()> = |cpu, bus, _| {
+    cpu.push_stack(cpu.x, bus);
+};
+
+pub(super) const PHY: Operation<()> = |cpu, bus, _| {
+    cpu.push_stack(cpu.y, bus);
+};
+
 pub(super) const PLA: Operation<()> = |cpu, bus, _| {
     let result = cpu.pop_stack(bus);
 
@@ -422,6 +529,24 @@ pub(super) const PLP: Operation<()> = |cpu, bus, _| {
     cpu.status = result;
 };
 
+pub(super) const PLX: Operation<()> = |cpu, bus, _| {
+    let result = cpu.pop_stack(bus);
+
+    cpu.status.set_flag_enabled(ZERO, result == 0);
+    cpu.status.set_flag_enabled(NEGATIVE, result & 0x80 > 0);
+
+    cpu.x = result;
+};
+
+pub(super) const PLY: Operation<()> = |cpu, bus, _| {
+    let result = cpu.pop_stack(bus);
+
+    cpu.status.set_flag_enabled(ZERO, result == 0);
+    cpu.status.set_flag_enabled(NEGATIVE, result & 0x80 > 0);
+
+    cpu.y = result;
+};
+
 pub(super) const RLA: Operation<u8> = |cpu, bus, addressing_mode| {
     ROL(cpu, bus, addressing_mode);
     AND(cpu, bus, addressing_mode);
@@ -484,8 +609,8 @@ pub(super) const SBC: Operation<u8> = |cpu, bus, addressing_mode| {
     // https://www.nesdev.org/wiki/Instruction_reference#SBC
     // and the comment here (line 688):
     // https://github.com/OneLoneCoder/olcNES/blob/master/Part%232%20-%20CPU/olc6502.cpp#L688
-    let result =
-        cpu.accumulator as u16 + (!argument) as u16 + cpu.status.get_flag_enabled(CARRY) as u16;
+    let carry = cpu.status.get_flag_enabled(CARRY);
+    let result = cpu.accumulator as u16 + (!argument) as u16 + carry as u16;
 
     cpu.status.set_flag_enabled(CARRY, result > 0xFF);
     cpu.status.set_flag_enabled(ZERO, result & 0xFF == 0);
@@ -495,6 +620,13 @@ pub(super) const SBC: Operation<u8> = |cpu, bus, addressing_mode| {
         ((cpu.accumulator ^ (result as u8)) & (cpu.accumulator ^ argument) & 0x80) > 0,
     );
 
+    if is_decimal(cpu) {
+        decimal_sbc(cpu, argument, carry);
+        if cpu.config.variant == CpuVariant::Cmos65C02 {
+            addressing_mode.cpu_add_another_required_cycle();
+        }
+        return;
+    }
     cpu.accumulator = result as u8;
 };
 
@@ -564,6 +696,11 @@ pub(super) const STY: Operation<u8> = |cpu, bus, addressing_mode| {
     addressing_mode.write(cpu.y, cpu, bus);
 };
 
+/// 65C02 store zero
+pub(super) const STZ: Operation<u8> = |cpu, bus, addressing_mode| {
+    addressing_mode.write(0, cpu, bus);
+};
+
 pub(super) const TAS: Operation<MemoryAddress> = |cpu, bus, addressing_mode| {
     cpu.stack_pointer = cpu.accumulator & cpu.x;
     SHA(cpu, bus, addressing_mode);
@@ -587,6 +724,26 @@ pub(super) const TAY: Operation<()> = |cpu, _, _| {
     cpu.y = result;
 };
 
+/// 65C02 test and reset bits, clears the bits of A in memory and sets Z
+/// like BIT
+pub(super) const TRB: Operation<u8> = |cpu, bus, addressing_mode| {
+    let argument = addressing_mode.read(cpu, bus);
+
+    cpu.status
+        .set_flag_enabled(ZERO, cpu.accumulator & argument == 0);
+    addressing_mode.write(argument & !cpu.accumulator, cpu, bus);
+};
+
+/// 65C02 test and set bits, sets the bits of A in memory and sets Z like
+/// BIT
+pub(super) const TSB: Operation<u8> = |cpu, bus, addressing_mode| {
+    let argument = addressing_mode.read(cpu, bus);
+
+    cpu.status
+        .set_flag_enabled(ZERO, cpu.accumulator & argument == 0);
+    addressing_mode.write(argument | cpu.accumulator, cpu, bus);
+};
+
 pub(super) const TSX: Operation<()> = |cpu, _, _| {
     let result = cpu.stack_pointer;
 
@@ -1,27 +1,90 @@
 use std::{
-    cell::{Cell, RefCell},
-    rc::Rc,
+    cell::{Cell, Ref, RefCell},
     sync::{Arc, Mutex},
 };
 
-use crate::hardware::{
-    apu::Apu,
-    bit_ops::BitOps,
-    cartrige::{Cartrige, cartrige_access::CartrigeAccess},
-    ppu::Ppu,
+use crate::{
+    hardware::{
+        apu::Apu,
+        cartrige::{Cartrige, cartrige_access::CartrigeAccess},
+        counters::{Counter, Counters},
+        cpu::CpuConfig,
+        input::InputDevices,
+        ppu::Ppu,
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
 };
 
 use super::constants;
 
+/// A write the cpu did, with where the ppu was when it happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusWrite {
+    pub address: u16,
+    pub value: u8,
+    /// What peeking the address gave right before the write, only
+    /// meaningful for memory
+    pub previous_value: u8,
+    /// The instruction doing the write, for interrupts it's the one that
+    /// got interrupted
+    pub program_counter: u16,
+    pub frame: u64,
+    pub scanline: u32,
+    pub dot: u32,
+}
+
+/// A read the cpu did, peeks aren't reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusRead {
+    pub address: u16,
+    pub value: u8,
+    /// The instruction doing the read, for interrupts it's the one that
+    /// got interrupted
+    pub program_counter: u16,
+    pub frame: u64,
+    pub scanline: u32,
+    pub dot: u32,
+}
+
+/// Gets told about every write on the cpu bus, see
+/// [Nes::add_bus_observer](crate::Nes::add_bus_observer)
+pub trait BusObserver: Send {
+    fn on_write(&mut self, write: &BusWrite);
+
+    /// Most observers only care about writes
+    fn on_read(&mut self, _read: &BusRead) {}
+
+    /// Called when the cpu starts an instruction at `address`. Opcode and
+    /// operand fetches don't show up as reads. `config` is the cpu's, for
+    /// decoding `opcode` like it does.
+    fn on_execute(&mut self, _address: u16, _opcode: u8, _config: &CpuConfig) {}
+
+    /// Called every time the ppu finishes a frame
+    fn on_frame_end(&mut self) {}
+}
+
 pub struct CpuBus {
     cpu_ram: [u8; constants::cpu::RAM_SIZE],
-    cartrige: Option<Rc<RefCell<Cartrige>>>,
+    cartrige: Option<Arc<Mutex<Cartrige>>>,
     apu: Option<Arc<Mutex<Apu>>>,
-    ppu: Option<Rc<RefCell<Ppu>>>,
+    ppu: Option<Arc<Mutex<Ppu>>>,
     open_bus: Cell<u8>,
-    controller_state: [Cell<u8>; 2],
-    controller_shift: [Cell<u8>; 2],
-    controller_strobe: Cell<bool>,
+    input: RefCell<InputDevices>,
+    /// see [CpuBus::new_flat]
+    flat_memory: Option<Box<[u8; 0x10000]>>,
+    observers: Vec<Arc<Mutex<dyn BusObserver>>>,
+    /// counters for the idle loop detection, see [crate::devices::speed_hacks]
+    status_reads: Cell<u64>,
+    writes: u64,
+    /// see [CpuBus::set_expansion_audio]
+    expansion_audio: bool,
+    /// the page written to $4014, see [CpuBus::take_oam_dma]
+    oam_dma_page: Option<u8>,
+    /// set by the cpu for [BusWrite::program_counter]
+    instruction_address: u16,
+    /// see [CpuBus::get_last_read]
+    last_read: Cell<u16>,
+    counters: Arc<Counters>,
 }
 
 impl CpuBus {
@@ -32,17 +95,82 @@ impl CpuBus {
             apu: None,
             ppu: None,
             open_bus: Cell::new(0),
-            controller_state: std::array::from_fn(|_| Cell::new(0)),
-            controller_shift: std::array::from_fn(|_| Cell::new(0)),
-            controller_strobe: Cell::new(false),
+            input: RefCell::new(InputDevices::new()),
+            flat_memory: None,
+            observers: Vec::new(),
+            status_reads: Cell::new(0),
+            writes: 0,
+            expansion_audio: false,
+            oam_dma_page: None,
+            instruction_address: 0,
+            last_read: Cell::new(0),
+            counters: Arc::new(Counters::new()),
         }
     }
 
-    pub fn insert_cartrige(&mut self, cartrige: Rc<RefCell<Cartrige>>) {
+    /// A bus where the whole address space is just ram and nothing else
+    /// is connected. Useful for testing the cpu on its own.
+    pub fn new_flat() -> Self {
+        Self {
+            flat_memory: Some(Box::new([0; 0x10000])),
+            ..Self::new()
+        }
+    }
+
+    /// Clears the ram like turning the console off and on, attached input
+    /// devices and observers stay
+    pub(crate) fn power_cycle(&mut self) {
+        self.cpu_ram.fill(0);
+        self.open_bus.set(0);
+        self.status_reads.set(0);
+        self.writes = 0;
+        self.oam_dma_page = None;
+        self.input.get_mut().power_cycle();
+        self.counters.reset();
+    }
+
+    pub fn insert_cartrige(&mut self, cartrige: Arc<Mutex<Cartrige>>) {
         self.cartrige = Some(cartrige);
     }
 
-    pub fn connect_ppu(&mut self, ppu: Rc<RefCell<Ppu>>) {
+    pub(crate) fn eject_cartrige(&mut self) {
+        self.cartrige = None;
+    }
+
+    /// Cartrige writes also go to the apu while the cartrige has a sound
+    /// chip, so the apu doesn't get locked for every write otherwise
+    pub(crate) fn set_expansion_audio(&mut self, enabled: bool) {
+        self.expansion_audio = enabled;
+    }
+
+    /// The page of an oam dma the cpu just asked for. The cpu is busy
+    /// writing when it happens so the [Nes](crate::Nes) starts the dma
+    /// right after.
+    pub(crate) fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma_page.take()
+    }
+
+    /// How many times $2002 (or one of its mirrors) got read
+    pub(crate) fn get_status_reads(&self) -> u64 {
+        self.status_reads.get()
+    }
+
+    pub(crate) fn get_writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Shared with the cpu and the [Nes](crate::Nes), see
+    /// [Nes::counters](crate::Nes::counters)
+    pub(crate) fn get_counters(&self) -> &Arc<Counters> {
+        &self.counters
+    }
+
+    /// The last value read from the bus
+    pub(crate) fn get_open_bus(&self) -> u8 {
+        self.open_bus.get()
+    }
+
+    pub fn connect_ppu(&mut self, ppu: Arc<Mutex<Ppu>>) {
         self.ppu = Some(ppu);
     }
 
@@ -60,15 +188,18 @@ impl CpuBus {
     }
 
     pub(crate) fn read_inner(&self, address: u16, peek: bool) -> u8 {
+        if let Some(memory) = self.flat_memory.as_ref() {
+            return memory[address as usize];
+        }
+
         let result = match address {
             0x0..0x2000 => self.cpu_ram[address as usize & (constants::cpu::RAM_SIZE - 1)],
             0x2000..0x4000 => self
                 .ppu
                 .as_ref()
-                .map(|c| c.borrow_mut().read_register_inner(address, peek))
+                .map(|c| c.lock().unwrap().read_register_inner(address, peek))
                 .unwrap_or(0),
-            0x4016 => self.read_controller(0, peek),
-            0x4017 => self.read_controller(1, peek),
+            0x4016 | 0x4017 => self.input.borrow_mut().read(address, peek),
             0x4000..0x4020 => self
                 .apu
                 .as_ref()
@@ -78,7 +209,8 @@ impl CpuBus {
                 .cartrige
                 .as_ref()
                 .map(|c| {
-                    c.borrow_mut()
+                    c.lock()
+                        .unwrap()
                         .read(CartrigeAccess::CpuAccess { address })
                         .unwrap_or_else(|| self.open_bus.get())
                 })
@@ -86,43 +218,66 @@ impl CpuBus {
         };
 
         if !peek {
+            if !self.observers.is_empty() {
+                self.notify_read(address, result);
+            }
             self.open_bus.set(result);
+            self.last_read.set(address);
+            if (0x2000..0x4000).contains(&address) && address & 0x07 == 0x02 {
+                self.status_reads.set(self.status_reads.get() + 1);
+            }
+            if address >= 0x4020 {
+                self.counters.increment(Counter::CartrigeReads);
+            }
         }
         return result;
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
+        if let Some(memory) = self.flat_memory.as_mut() {
+            memory[address as usize] = value;
+            return;
+        }
+        self.writes += 1;
+        if !self.observers.is_empty() {
+            self.notify_write(address, value);
+        }
+
+        if address == 0x4014 {
+            self.oam_dma_page = Some(value);
+        }
+
         match address {
             0x0..0x2000 => self.cpu_ram[address as usize & (constants::cpu::RAM_SIZE - 1)] = value,
             0x2000..0x4000 | 0x4014 => self
                 .ppu
                 .as_ref()
-                .map(|c| c.borrow_mut().write_register(address, value))
+                .map(|c| c.lock().unwrap().write_register(address, value))
                 .unwrap_or(()),
-            0x4016 => {
-                let strobe = value & 1 != 0;
-                let prev_strobe = self.controller_strobe.replace(strobe);
-
-                if strobe || (prev_strobe && !strobe) {
-                    self.controller_state
-                        .iter()
-                        .zip(self.controller_shift.iter())
-                        .for_each(|(state, shift)| shift.set(state.get()));
-                }
-            }
+            0x4016 => self.input.get_mut().write(value),
             0x4000..0x4020 => self
                 .apu
                 .as_ref()
                 .map(|a| a.lock().unwrap().write_register(address, value))
                 .unwrap_or(()),
-            0x4020.. => self
-                .cartrige
-                .as_ref()
-                .map(|c| {
-                    c.borrow_mut()
-                        .write(CartrigeAccess::CpuAccess { address }, value)
-                })
-                .unwrap_or(()),
+            0x4020.. => {
+                if address >= 0x8000 {
+                    self.counters.increment(Counter::MapperWrites);
+                }
+                if self.expansion_audio
+                    && let Some(apu) = self.apu.as_ref()
+                {
+                    apu.lock().unwrap().write_expansion_register(address, value);
+                }
+                self.cartrige
+                    .as_ref()
+                    .map(|c| {
+                        c.lock()
+                            .unwrap()
+                            .write(CartrigeAccess::CpuAccess { address }, value)
+                    })
+                    .unwrap_or(())
+            }
         }
     }
 
@@ -152,32 +307,131 @@ impl CpuBus {
         }
     }
 
-    pub fn set_controller_button(&mut self, controller_index: usize, button: u8, pressed: bool) {
-        if controller_index >= self.controller_state.len() {
-            return;
+    pub fn add_observer(&mut self, observer: Arc<Mutex<dyn BusObserver>>) {
+        self.observers.push(observer);
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    pub(crate) fn set_instruction_address(&mut self, address: u16) {
+        self.instruction_address = address;
+    }
+
+    /// The instruction the cpu is running, or the one an interrupt
+    /// interrupted while it pushes
+    pub(crate) fn get_instruction_address(&self) -> u16 {
+        self.instruction_address
+    }
+
+    /// The address of the last read that wasn't a peek
+    pub(crate) fn get_last_read(&self) -> u16 {
+        self.last_read.get()
+    }
+
+    pub(crate) fn notify_frame_end(&self) {
+        for observer in self.observers.iter() {
+            observer.lock().unwrap().on_frame_end();
         }
+    }
 
-        let mut state = self.controller_state[controller_index].get();
-        state.set_flag_enabled(button, pressed);
+    /// The (scanline, dot) of the ppu, (0, 0) without one
+    pub(crate) fn get_ppu_position(&self) -> (u32, u32) {
+        self.ppu
+            .as_ref()
+            .map(|ppu| ppu.lock().unwrap().get_position())
+            .unwrap_or((0, 0))
+    }
+
+    /// The (frame, scanline, dot) of the ppu for [BusWrite] and [BusRead]
+    fn get_ppu_timing(&self) -> (u64, u32, u32) {
+        self.ppu
+            .as_ref()
+            .map(|ppu| {
+                let ppu = ppu.lock().unwrap();
+                let (scanline, dot) = ppu.get_position();
+                (ppu.get_frame_count(), scanline, dot)
+            })
+            .unwrap_or((0, 0, 0))
+    }
 
-        self.controller_state[controller_index].set(state);
-        if self.controller_strobe.get() {
-            self.controller_shift[controller_index].set(state);
+    pub(crate) fn notify_execute(&self, address: u16, opcode: u8, config: &CpuConfig) {
+        for observer in self.observers.iter() {
+            observer.lock().unwrap().on_execute(address, opcode, config);
         }
     }
 
-    fn read_controller(&self, controller_index: usize, peek: bool) -> u8 {
-        if self.controller_strobe.get() {
-            return self.controller_state[controller_index].get() & 1;
+    fn notify_read(&self, address: u16, value: u8) {
+        let (frame, scanline, dot) = self.get_ppu_timing();
+        let read = BusRead {
+            address,
+            value,
+            program_counter: self.instruction_address,
+            frame,
+            scanline,
+            dot,
+        };
+        for observer in self.observers.iter() {
+            observer.lock().unwrap().on_read(&read);
         }
+    }
 
-        let shift = self.controller_shift[controller_index].get();
-        let out = shift & 1;
+    fn notify_write(&self, address: u16, value: u8) {
+        let (frame, scanline, dot) = self.get_ppu_timing();
+        let write = BusWrite {
+            address,
+            value,
+            previous_value: self.peek(address),
+            program_counter: self.instruction_address,
+            frame,
+            scanline,
+            dot,
+        };
+        for observer in self.observers.iter() {
+            observer.lock().unwrap().on_write(&write);
+        }
+    }
 
-        if !peek {
-            self.controller_shift[controller_index].set((shift >> 1) | 0x80);
+    /// The 2kb of internal ram, without the mirrors
+    pub(crate) fn get_cpu_ram(&self) -> &[u8] {
+        &self.cpu_ram
+    }
+
+    /// The 2kb of internal ram for writing, without the mirrors
+    pub(crate) fn get_cpu_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cpu_ram
+    }
+
+    /// The devices plugged into the controller and expansion ports
+    pub fn get_input(&self) -> Ref<'_, InputDevices> {
+        self.input.borrow()
+    }
+
+    pub fn get_input_mut(&mut self) -> &mut InputDevices {
+        self.input.get_mut()
+    }
+
+    /// For the dmc dma, it halts the cpu on a read cycle and the halted
+    /// cycles read `address` again. Only the controller ports notice, see
+    /// [InputDevices::set_dma_conflicts].
+    pub fn dmc_dma_halt(&self, address: u16) {
+        self.counters.increment(Counter::DmaStalls);
+        if self.flat_memory.is_none() {
+            self.input.borrow_mut().dma_conflict(address);
         }
+    }
+}
+
+impl SaveState for CpuBus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.cpu_ram);
+        writer.write_u8(self.open_bus.get());
+    }
 
-        out
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        reader.read_bytes_into("cpu ram", &mut self.cpu_ram)?;
+        self.open_bus.set(reader.read_u8()?);
+        Ok(())
     }
 }
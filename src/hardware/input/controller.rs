@@ -0,0 +1,326 @@
+use std::{
+    fmt::Display,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hardware::{
+        bit_ops::BitOps,
+        constants::controller::{MICROPHONE, buttons},
+        input::{Device, InputDevice, Port, error::ButtonError},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+/// The buttons of a standard controller
+/// https://www.nesdev.org/wiki/Standard_controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    /// In the order they get read from the controller
+    pub const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    /// The bit of the button in the controller shift register
+    pub fn mask(self) -> u8 {
+        match self {
+            Button::A => buttons::A,
+            Button::B => buttons::B,
+            Button::Select => buttons::SELECT,
+            Button::Start => buttons::START,
+            Button::Up => buttons::UP,
+            Button::Down => buttons::DOWN,
+            Button::Left => buttons::LEFT,
+            Button::Right => buttons::RIGHT,
+        }
+    }
+
+    /// The name used in scripts and config files, like `"select"`
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::A => "a",
+            Button::B => "b",
+            Button::Select => "select",
+            Button::Start => "start",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::Left => "left",
+            Button::Right => "right",
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = ButtonError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Button::ALL
+            .into_iter()
+            .find(|button| button.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| ButtonError::UnknownButtonError(name.to_string()))
+    }
+}
+
+/// The buttons held on one controller, with the bits laid out like the
+/// controller shift register (see [Button::mask]). It serializes as that
+/// byte, so recordings stay a byte a frame, and displays and parses as
+/// the names of the buttons, like `"right b"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ControllerState(u8);
+
+impl ControllerState {
+    pub const A: Self = Self(buttons::A);
+    pub const B: Self = Self(buttons::B);
+    pub const SELECT: Self = Self(buttons::SELECT);
+    pub const START: Self = Self(buttons::START);
+    pub const UP: Self = Self(buttons::UP);
+    pub const DOWN: Self = Self(buttons::DOWN);
+    pub const LEFT: Self = Self(buttons::LEFT);
+    pub const RIGHT: Self = Self(buttons::RIGHT);
+
+    /// Nothing held
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_pressed(self, button: Button) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    /// Whether every button held in `other` is held in this one too
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        self.0.set_flag_enabled(button.mask(), pressed);
+    }
+
+    /// A copy with `button` held too, for building states like
+    /// `ControllerState::empty().with(Button::Start)`
+    pub fn with(mut self, button: Button) -> Self {
+        self.set(button, true);
+        self
+    }
+
+    /// The held buttons in the order they get read from the controller
+    pub fn pressed(self) -> impl Iterator<Item = Button> {
+        Button::ALL
+            .into_iter()
+            .filter(move |button| self.is_pressed(*button))
+    }
+}
+
+impl From<Button> for ControllerState {
+    fn from(button: Button) -> Self {
+        Self(button.mask())
+    }
+}
+
+impl FromIterator<Button> for ControllerState {
+    fn from_iter<T: IntoIterator<Item = Button>>(buttons: T) -> Self {
+        buttons
+            .into_iter()
+            .fold(Self::empty(), |state, button| state.with(button))
+    }
+}
+
+impl BitOr for ControllerState {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOr<Button> for ControllerState {
+    type Output = Self;
+
+    fn bitor(self, button: Button) -> Self {
+        self.with(button)
+    }
+}
+
+impl BitOrAssign for ControllerState {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for ControllerState {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl BitAndAssign for ControllerState {
+    fn bitand_assign(&mut self, other: Self) {
+        self.0 &= other.0;
+    }
+}
+
+impl Not for ControllerState {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+impl Display for ControllerState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.pressed().map(Button::name).collect();
+        f.write_str(&names.join(" "))
+    }
+}
+
+impl FromStr for ControllerState {
+    type Err = ButtonError;
+
+    /// Button names split by whitespace, an empty string is nothing held
+    fn from_str(names: &str) -> Result<Self, Self::Err> {
+        names.split_whitespace().map(str::parse).collect()
+    }
+}
+
+/// https://www.nesdev.org/wiki/Standard_controller
+pub struct StandardController {
+    state: u8,
+    shift: u8,
+    strobe: bool,
+    /// https://www.nesdev.org/wiki/Standard_controller#Famicom
+    has_microphone: bool,
+    is_microphone_active: bool,
+}
+
+impl StandardController {
+    pub fn new(has_microphone: bool) -> Self {
+        Self {
+            state: 0,
+            shift: 0,
+            strobe: false,
+            has_microphone,
+            is_microphone_active: false,
+        }
+    }
+
+    /// Reads the next button, after all 8 got read it keeps returning 1
+    pub(super) fn read_next(state: u8, shift: &mut u8, strobe: bool, peek: bool) -> u8 {
+        if strobe {
+            return state & 1;
+        }
+        let out = *shift & 1;
+        if !peek {
+            *shift = (*shift >> 1) | 0x80;
+        }
+        out
+    }
+}
+
+impl InputDevice for StandardController {
+    fn device(&self) -> Device {
+        if self.has_microphone {
+            Device::MicrophoneController
+        } else {
+            Device::StandardController
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = self.state;
+        }
+    }
+
+    fn read(&mut self, address: u16, port: Port, peek: bool) -> u8 {
+        // the microphone is on the data line of the first controller
+        let microphone = if self.is_microphone_active && address == 0x4016 {
+            MICROPHONE
+        } else {
+            0
+        };
+
+        // famicom expansion controllers use bit 1 of $4016
+        let (data_address, data_bit) = match port {
+            Port::One => (0x4016, 0),
+            Port::Two => (0x4017, 0),
+            Port::Expansion => (0x4016, 1),
+        };
+        if address != data_address {
+            return microphone;
+        }
+        let bit = Self::read_next(self.state, &mut self.shift, self.strobe, peek);
+        microphone | (bit << data_bit)
+    }
+
+    fn controller_count(&self) -> usize {
+        1
+    }
+
+    fn set_button(&mut self, _: usize, button: Button, pressed: bool) {
+        self.state.set_flag_enabled(button.mask(), pressed);
+        if self.strobe {
+            self.shift = self.state;
+        }
+    }
+
+    fn get_buttons(&self, _: usize) -> ControllerState {
+        ControllerState::from_bits(self.state)
+    }
+
+    fn set_microphone(&mut self, active: bool) {
+        self.is_microphone_active = self.has_microphone && active;
+    }
+}
+
+impl SaveState for StandardController {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.state);
+        writer.write_u8(self.shift);
+        writer.write_bool(self.strobe);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.state = reader.read_u8()?;
+        self.shift = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use std::path::Path;
+
+use crate::{
+    hardware::input::error::TapeError,
+    save_state::{self, SaveState, StateReader, StateWriter, error::SaveStateError},
+};
+
+/// Cpu cycles a tape sample lasts, about 32kHz. Family BASIC writes at
+/// 1200 or 2400 baud so that's plenty.
+pub const CYCLES_PER_SAMPLE: u32 = 56;
+
+/// Tape files start with this, followed by the sample count as a little
+/// endian u32 and the samples as bits, the first one in the lowest bit
+const TAPE_MAGIC: &[u8; 4] = b"FBT\x1A";
+const TAPE_HEADER_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TapeState {
+    #[default]
+    Stopped,
+    Playing,
+    Recording,
+}
+
+/// The cassette recorder plugged into the Family BASIC keyboard. What the
+/// game writes to bit 2 of $4016 gets recorded and the tape gets played
+/// back on bit 1 of $4016, both 1 bit audio. The tape is a plain list of
+/// samples so the files are small and don't depend on the sample rate of
+/// anything outside the emulator.
+/// https://www.nesdev.org/wiki/Family_BASIC_Data_Recorder
+#[derive(Debug, Clone, Default)]
+pub struct DataRecorder {
+    /// one bit per sample, the first sample in the lowest bit of byte 0
+    tape: Vec<u8>,
+    length: usize,
+    position: usize,
+    state: TapeState,
+    /// cycles into the current sample
+    cycles: u32,
+    /// what the game writes, recorded while [TapeState::Recording]
+    output: bool,
+}
+
+impl DataRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A tape from the bytes of a tape file, rewound and stopped
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TapeError> {
+        let Some(rest) = bytes.strip_prefix(TAPE_MAGIC.as_slice()) else {
+            return Err(TapeError::MissingMagicNumbersError);
+        };
+        let Some((length, tape)) = rest.split_first_chunk::<4>() else {
+            return Err(TapeError::TruncatedError(TAPE_HEADER_SIZE, bytes.len()));
+        };
+        let length = u32::from_le_bytes(*length) as usize;
+        let Some(tape) = tape.get(..length.div_ceil(8)) else {
+            return Err(TapeError::TruncatedError(
+                TAPE_HEADER_SIZE + length.div_ceil(8),
+                bytes.len(),
+            ));
+        };
+        Ok(Self {
+            tape: tape.to_vec(),
+            length,
+            ..Self::default()
+        })
+    }
+
+    /// The tape as a tape file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = TAPE_MAGIC.to_vec();
+        bytes.extend_from_slice(&(self.length as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.tape[..self.length.div_ceil(8)]);
+        bytes
+    }
+
+    /// Swaps in the tape in `path`, see [DataRecorder::from_bytes]
+    pub fn load_file(&mut self, path: &Path) -> Result<(), TapeError> {
+        *self = Self {
+            output: self.output,
+            ..Self::from_bytes(&std::fs::read(path)?)?
+        };
+        Ok(())
+    }
+
+    pub fn save_file(&self, path: &Path) -> Result<(), TapeError> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn play(&mut self) {
+        self.state = TapeState::Playing;
+    }
+
+    /// Records from the current position on, overwriting what was there
+    pub fn record(&mut self) {
+        self.state = TapeState::Recording;
+    }
+
+    pub fn stop(&mut self) {
+        self.state = TapeState::Stopped;
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+        self.cycles = 0;
+    }
+
+    /// Stops and empties the tape
+    pub fn eject(&mut self) {
+        *self = Self {
+            output: self.output,
+            ..Self::default()
+        };
+    }
+
+    pub fn get_state(&self) -> TapeState {
+        self.state
+    }
+
+    /// In samples, see [CYCLES_PER_SAMPLE]
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    /// In samples, see [CYCLES_PER_SAMPLE]
+    pub fn get_length(&self) -> usize {
+        self.length
+    }
+
+    /// The sample at `index`, silence past the end of the tape
+    pub fn get_sample(&self, index: usize) -> bool {
+        index < self.length && self.tape[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// What the game writes to the tape
+    pub(crate) fn set_output(&mut self, output: bool) {
+        self.output = output;
+    }
+
+    /// What the game reads from the tape, nothing while it isn't playing
+    pub(crate) fn get_input(&self) -> bool {
+        self.state == TapeState::Playing && self.get_sample(self.position)
+    }
+
+    /// Called once every cpu cycle
+    pub(crate) fn tick(&mut self) {
+        if self.state == TapeState::Stopped {
+            return;
+        }
+        self.cycles += 1;
+        if self.cycles < CYCLES_PER_SAMPLE {
+            return;
+        }
+        self.cycles = 0;
+        match self.state {
+            TapeState::Playing if self.position >= self.length => self.state = TapeState::Stopped,
+            TapeState::Playing => self.position += 1,
+            TapeState::Recording => {
+                self.set_sample(self.position, self.output);
+                self.position += 1;
+                self.length = self.length.max(self.position);
+            }
+            TapeState::Stopped => {}
+        }
+    }
+
+    fn set_sample(&mut self, index: usize, value: bool) {
+        if index / 8 >= self.tape.len() {
+            self.tape.resize(index / 8 + 1, 0);
+        }
+        let mask = 1 << (index % 8);
+        if value {
+            self.tape[index / 8] |= mask;
+        } else {
+            self.tape[index / 8] &= !mask;
+        }
+    }
+}
+
+fn state_id(state: TapeState) -> u8 {
+    match state {
+        TapeState::Stopped => 0,
+        TapeState::Playing => 1,
+        TapeState::Recording => 2,
+    }
+}
+
+/// The whole tape gets saved too, so rewinding over a recording works
+impl SaveState for DataRecorder {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.tape[..self.length.div_ceil(8)]);
+        writer.write_u32(self.length as u32);
+        writer.write_u32(self.position as u32);
+        writer.write_u8(state_id(self.state));
+        writer.write_u32(self.cycles);
+        writer.write_bool(self.output);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.tape = reader.read_bytes()?.to_vec();
+        self.length = (reader.read_u32()? as usize).min(self.tape.len() * 8);
+        self.position = reader.read_u32()? as usize;
+        self.state = match reader.read_u8()? {
+            0 => TapeState::Stopped,
+            1 => TapeState::Playing,
+            2 => TapeState::Recording,
+            other => {
+                return Err(SaveStateError::InvalidValueError(
+                    "tape state",
+                    other as u64,
+                ));
+            }
+        };
+        self.cycles = reader.read_u32()?.min(CYCLES_PER_SAMPLE - 1);
+        self.output = reader.read_bool()?;
+        Ok(())
+    }
+}
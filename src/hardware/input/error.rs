@@ -0,0 +1,17 @@
+#[derive(thiserror::Error, Debug)]
+pub enum TapeError {
+    #[error("Got an io error while reading or writing a tape:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("Magic number missing at the start of the file. Maybe recieved wrong file type.")]
+    MissingMagicNumbersError,
+    #[error("The tape file is cut off, it should be {_0} bytes long but it's only {_1} bytes!")]
+    TruncatedError(usize, usize),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ButtonError {
+    #[error(
+        "There is no button called {_0:?}, the buttons are a, b, select, start, up, down, left and right!"
+    )]
+    UnknownButtonError(String),
+}
@@ -0,0 +1,116 @@
+use crate::{
+    hardware::{
+        bit_ops::BitOps,
+        constants::controller::{FOUR_SCORE_REPORT_SIZE, FOUR_SCORE_SIGNATURES},
+        input::{
+            Device, InputDevice, Port,
+            controller::{Button, ControllerState},
+        },
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+/// Four controllers over the two ports. Every port reads 24 bits: the
+/// first controller, then the second one, then a signature so games can
+/// tell a four score is plugged in.
+/// https://www.nesdev.org/wiki/Four_Score
+pub struct FourScore {
+    /// controllers 1 and 3 are on $4016, 2 and 4 on $4017
+    states: [u8; 4],
+    shifts: [u32; 2],
+    strobe: bool,
+}
+
+impl FourScore {
+    pub fn new() -> Self {
+        Self {
+            states: [0; 4],
+            shifts: [0; 2],
+            strobe: false,
+        }
+    }
+
+    fn report(&self, line: usize) -> u32 {
+        self.states[line] as u32
+            | (self.states[line + 2] as u32) << 8
+            | (FOUR_SCORE_SIGNATURES[line] as u32) << 16
+    }
+
+    fn reload(&mut self) {
+        self.shifts = [self.report(0), self.report(1)];
+    }
+}
+
+impl Default for FourScore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for FourScore {
+    fn device(&self) -> Device {
+        Device::FourScore
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn read(&mut self, address: u16, port: Port, peek: bool) -> u8 {
+        let line = match (port, address) {
+            (Port::One, 0x4016) => 0,
+            (Port::One, 0x4017) => 1,
+            _ => return 0,
+        };
+        if self.strobe {
+            return self.states[line] & 1;
+        }
+        let shift = self.shifts[line];
+        if !peek {
+            // after the whole report got read it keeps returning 1
+            self.shifts[line] = (shift >> 1) | 1 << (FOUR_SCORE_REPORT_SIZE - 1);
+        }
+        (shift & 1) as u8
+    }
+
+    fn controller_count(&self) -> usize {
+        4
+    }
+
+    /// Controllers 0 and 2 are read through $4016, 1 and 3 through $4017
+    fn set_button(&mut self, controller_index: usize, button: Button, pressed: bool) {
+        let Some(state) = self.states.get_mut(controller_index) else {
+            return;
+        };
+        state.set_flag_enabled(button.mask(), pressed);
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn get_buttons(&self, controller_index: usize) -> ControllerState {
+        ControllerState::from_bits(self.states.get(controller_index).copied().unwrap_or(0))
+    }
+}
+
+impl SaveState for FourScore {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.states);
+        for shift in self.shifts {
+            writer.write_u32(shift);
+        }
+        writer.write_bool(self.strobe);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        reader.read_bytes_into("four score states", &mut self.states)?;
+        for shift in self.shifts.iter_mut() {
+            *shift = reader.read_u32()?;
+        }
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+}
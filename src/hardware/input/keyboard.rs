@@ -0,0 +1,126 @@
+use crate::{
+    hardware::{
+        bit_ops::BitOps,
+        constants::controller::keyboard::{
+            COLUMN_SELECT, COLUMNS, ENABLE, KEYS_MASK, KEYS_PER_COLUMN, RESET, ROWS,
+        },
+        input::{Device, InputDevice, Port},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+/// Every key of the matrix by row, then column, then from bit 4 of $4017
+/// down to bit 1. The names are the ones of
+/// [KeyCombo](crate::frontend::keymap::KeyCombo) where the pc keyboard has
+/// the key, ¥ is the backslash key, CLR HOME is home, GRPH is alt and
+/// STOP is end.
+#[rustfmt::skip]
+const MATRIX: [[[&str; KEYS_PER_COLUMN]; COLUMNS]; ROWS] = [
+    [["]", "[", "ENTER", "F8"],           ["END", "BACKSLASH", "RSHIFT", "KANA"]],
+    [[";", ":", "@", "F7"],               ["^", "-", "/", "_"]],
+    [["K", "L", "O", "F6"],               ["0", "P", ",", "."]],
+    [["J", "U", "I", "F5"],               ["8", "9", "N", "M"]],
+    [["H", "G", "Y", "F4"],               ["6", "7", "V", "B"]],
+    [["D", "R", "T", "F3"],               ["4", "5", "C", "F"]],
+    [["A", "S", "W", "F2"],               ["3", "E", "Z", "X"]],
+    [["CTRL", "Q", "ESCAPE", "F1"],       ["2", "1", "ALT", "SHIFT"]],
+    [["LEFT", "RIGHT", "UP", "HOME"],     ["INSERT", "BACKSPACE", "SPACE", "DOWN"]],
+];
+
+/// Where the key called `name` is, as the (row, column, key) that
+/// [FamilyBasicKeyboard::set_key] takes. Case doesn't matter.
+pub fn key_position(name: &str) -> Option<(usize, usize, usize)> {
+    MATRIX.iter().enumerate().find_map(|(row, columns)| {
+        columns.iter().enumerate().find_map(|(column, keys)| {
+            let bit = keys.iter().position(|key| key.eq_ignore_ascii_case(name))?;
+            Some((row, column, KEYS_PER_COLUMN - 1 - bit))
+        })
+    })
+}
+
+/// The keyboard for Family BASIC. It is a matrix of 9 rows, each with 2
+/// columns of 4 keys. Writing $4016 picks the row and column and $4017
+/// reads the 4 keys of it.
+/// https://www.nesdev.org/wiki/Family_BASIC_Keyboard
+pub struct FamilyBasicKeyboard {
+    /// one bit per key, bits 0-3 are column 0 and bits 4-7 column 1
+    keys: [u8; ROWS],
+    row: usize,
+    column: usize,
+    is_enabled: bool,
+}
+
+impl FamilyBasicKeyboard {
+    pub fn new() -> Self {
+        Self {
+            keys: [0; ROWS],
+            row: 0,
+            column: 0,
+            is_enabled: false,
+        }
+    }
+}
+
+impl Default for FamilyBasicKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for FamilyBasicKeyboard {
+    fn device(&self) -> Device {
+        Device::FamilyBasicKeyboard
+    }
+
+    fn write(&mut self, value: u8) {
+        self.is_enabled = value.get_flag_enabled(ENABLE);
+        let column = value.get_bitfield(COLUMN_SELECT) as usize;
+        // the row goes up when the column select goes from 1 to 0
+        if self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+        self.column = column;
+        if value.get_flag_enabled(RESET) {
+            self.row = 0;
+        }
+    }
+
+    fn read(&mut self, address: u16, port: Port, _: bool) -> u8 {
+        if port != Port::Expansion || address != 0x4017 || !self.is_enabled {
+            return 0;
+        }
+        // after the last row nothing is pressed
+        let Some(row) = self.keys.get(self.row) else {
+            return KEYS_MASK;
+        };
+        let pressed = (row >> (self.column * KEYS_PER_COLUMN)) & 0x0F;
+        !(pressed << KEYS_MASK.trailing_zeros()) & KEYS_MASK
+    }
+
+    /// See [Family BASIC Keyboard matrix](https://www.nesdev.org/wiki/Family_BASIC_Keyboard#Matrix)
+    /// for which key is where, `key` is the bit in the column starting
+    /// with bit 1 of $4017 as 0
+    fn set_key(&mut self, row: usize, column: usize, key: usize, pressed: bool) {
+        if row >= ROWS || column >= COLUMNS || key >= KEYS_PER_COLUMN {
+            return;
+        }
+        self.keys[row].set_flag_enabled(1 << (column * KEYS_PER_COLUMN + key), pressed);
+    }
+}
+
+impl SaveState for FamilyBasicKeyboard {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.keys);
+        writer.write_u8(self.row as u8);
+        writer.write_u8(self.column as u8);
+        writer.write_bool(self.is_enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        reader.read_bytes_into("keyboard keys", &mut self.keys)?;
+        self.row = reader.read_u8()? as usize;
+        self.column = (reader.read_u8()? as usize).min(COLUMNS - 1);
+        self.is_enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
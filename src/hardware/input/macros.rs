@@ -0,0 +1,202 @@
+use std::{collections::HashMap, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::input::controller::ControllerState;
+
+/// A short recording of one controller, the buttons of every frame. Unlike
+/// a movie it plays over live input, handy for practicing a trick by
+/// replaying the exact inputs that set it up.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputMacro {
+    pub frames: Vec<ControllerState>,
+}
+
+impl InputMacro {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Presses start for the player once the game had time to boot, to get
+/// past logos and title screens. Games that need more than one press
+/// (title screen, then a menu) can press it a few times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FastBoot {
+    /// Frames from power on to the first press
+    pub delay: u32,
+    pub presses: u32,
+    /// Frames from one press to the next
+    pub interval: u32,
+}
+
+impl Default for FastBoot {
+    fn default() -> Self {
+        Self {
+            delay: 60,
+            presses: 1,
+            interval: 30,
+        }
+    }
+}
+
+impl FastBoot {
+    /// Start is held this long, long enough for games that only read the
+    /// controllers every other frame
+    pub const HOLD_FRAMES: u32 = 2;
+
+    /// The presses as a macro for the first controller
+    pub fn to_macro(&self) -> InputMacro {
+        let interval = self.interval.max(Self::HOLD_FRAMES + 1);
+        let mut frames = vec![ControllerState::empty(); self.delay as usize];
+        for press in 0..self.presses {
+            if press > 0 {
+                let released = interval - Self::HOLD_FRAMES;
+                frames.extend((0..released).map(|_| ControllerState::empty()));
+            }
+            frames.extend((0..Self::HOLD_FRAMES).map(|_| ControllerState::START));
+        }
+        InputMacro { frames }
+    }
+}
+
+/// How a playing macro and the player's own presses get merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MacroPriority {
+    /// The game only sees the macro until it is done
+    #[default]
+    Replace,
+    /// Buttons held by either the player or the macro are pressed
+    Merge,
+}
+
+struct Playback {
+    controller_index: usize,
+    frames: Vec<ControllerState>,
+    frame: usize,
+    priority: MacroPriority,
+}
+
+/// Records and plays back [InputMacro]s, one of each at a time. The
+/// player's presses are passed in as `live` and the buttons the game
+/// should see come out.
+#[derive(Default)]
+pub struct Macros {
+    recording: Option<(usize, Vec<ControllerState>)>,
+    playback: Option<Playback>,
+}
+
+impl Macros {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_recording(&mut self, controller_index: usize) {
+        self.recording = Some((controller_index, Vec::new()));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputMacro> {
+        self.recording
+            .take()
+            .map(|(_, frames)| InputMacro { frames })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn play(
+        &mut self,
+        controller_index: usize,
+        input_macro: &InputMacro,
+        priority: MacroPriority,
+    ) {
+        self.playback = (!input_macro.is_empty()).then(|| Playback {
+            controller_index,
+            frames: input_macro.frames.clone(),
+            frame: 0,
+            priority,
+        });
+    }
+
+    /// Returns the controller the macro was playing on
+    pub fn stop_playing(&mut self) -> Option<usize> {
+        self.playback
+            .take()
+            .map(|playback| playback.controller_index)
+    }
+
+    /// The controller a macro is playing on
+    pub fn get_playing(&self) -> Option<usize> {
+        self.playback
+            .as_ref()
+            .map(|playback| playback.controller_index)
+    }
+
+    /// The buttons the game sees on a controller the player holds `live` on
+    pub fn overlay(&self, controller_index: usize, live: ControllerState) -> ControllerState {
+        match self.playback.as_ref() {
+            Some(playback) if playback.controller_index == controller_index => {
+                let buttons = playback.frames[playback.frame];
+                match playback.priority {
+                    MacroPriority::Replace => buttons,
+                    MacroPriority::Merge => buttons | live,
+                }
+            }
+            _ => live,
+        }
+    }
+
+    /// Records what the player held during the frame that just ended and
+    /// moves the playback on. Returns the controller whose macro just
+    /// finished.
+    pub fn next_frame(&mut self, live: impl Fn(usize) -> ControllerState) -> Option<usize> {
+        if let Some((controller_index, frames)) = self.recording.as_mut() {
+            frames.push(live(*controller_index));
+        }
+        let playback = self.playback.as_mut()?;
+        playback.frame += 1;
+        if playback.frame < playback.frames.len() {
+            return None;
+        }
+        self.stop_playing()
+    }
+}
+
+/// Macros bound to whatever the frontend uses for hotkeys
+#[derive(Debug, Clone)]
+pub struct MacroBindings<K> {
+    macros: HashMap<K, InputMacro>,
+}
+
+impl<K: Hash + Eq> MacroBindings<K> {
+    pub fn new() -> Self {
+        Self {
+            macros: HashMap::new(),
+        }
+    }
+
+    /// Replaces whatever was bound to `hotkey`
+    pub fn bind(&mut self, hotkey: K, input_macro: InputMacro) {
+        self.macros.insert(hotkey, input_macro);
+    }
+
+    pub fn unbind(&mut self, hotkey: &K) -> Option<InputMacro> {
+        self.macros.remove(hotkey)
+    }
+
+    pub fn get(&self, hotkey: &K) -> Option<&InputMacro> {
+        self.macros.get(hotkey)
+    }
+}
+
+impl<K: Hash + Eq> Default for MacroBindings<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
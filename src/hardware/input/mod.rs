@@ -0,0 +1,549 @@
+//! Everything that can be plugged into the controller ports and the
+//! famicom expansion port.
+//!
+//! All the devices share the same data lines, reading $4016 or $4017
+//! ORs together whatever every device puts on them. Writing $4016 goes to
+//! all the devices (it is the strobe for controllers).
+//! https://www.nesdev.org/wiki/Input_devices
+
+use crate::{
+    hardware::{
+        bit_ops::BitOps,
+        constants::controller::keyboard::{TAPE_INPUT, TAPE_OUTPUT},
+        input::{
+            controller::{Button, ControllerState, StandardController},
+            data_recorder::DataRecorder,
+            four_score::FourScore,
+            keyboard::FamilyBasicKeyboard,
+            macros::{FastBoot, InputMacro, MacroPriority, Macros},
+            paddle::ArkanoidPaddle,
+            sanitizer::{InputSanitizer, OppositeDirections},
+            turbo::Turbo,
+        },
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+    trace::targets,
+};
+
+pub mod controller;
+pub mod data_recorder;
+pub mod error;
+pub mod four_score;
+pub mod keyboard;
+pub mod macros;
+pub mod paddle;
+pub mod sanitizer;
+pub mod turbo;
+
+/// A four score has the most controllers
+const MAX_CONTROLLERS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// Read through $4016
+    One,
+    /// Read through $4017
+    Two,
+    /// The famicom expansion port, gets both $4016 and $4017
+    Expansion,
+}
+
+impl Port {
+    pub const ALL: [Port; 3] = [Port::One, Port::Two, Port::Expansion];
+
+    fn index(self) -> usize {
+        match self {
+            Port::One => 0,
+            Port::Two => 1,
+            Port::Expansion => 2,
+        }
+    }
+}
+
+/// The devices that can be attached with [Nes::attach_device](crate::Nes::attach_device)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Empty,
+    StandardController,
+    /// The second famicom controller, it has a microphone instead of
+    /// select and start
+    MicrophoneController,
+    /// Takes up both controller ports and gives 4 controllers. Can only
+    /// be attached to [Port::One].
+    FourScore,
+    /// Goes in the expansion port
+    FamilyBasicKeyboard,
+    /// The Arkanoid Vaus controller, in a controller port for the NES
+    /// version or the expansion port for the famicom one
+    ArkanoidPaddle,
+}
+
+/// Implemented by everything that can be plugged into a port
+pub(crate) trait InputDevice: SaveState + Send {
+    fn device(&self) -> Device;
+
+    /// Called on every write to $4016
+    fn write(&mut self, value: u8);
+
+    /// Returns the bits the device puts on the data lines of `address`
+    /// ($4016 or $4017) when plugged into `port`
+    fn read(&mut self, address: u16, port: Port, peek: bool) -> u8;
+
+    /// How many controllers the device has, see [InputDevices::set_button]
+    fn controller_count(&self) -> usize {
+        0
+    }
+
+    fn set_button(&mut self, _controller_index: usize, _button: Button, _pressed: bool) {}
+
+    /// The pressed buttons of a controller
+    fn get_buttons(&self, _controller_index: usize) -> ControllerState {
+        ControllerState::empty()
+    }
+
+    fn set_microphone(&mut self, _active: bool) {}
+
+    fn set_key(&mut self, _row: usize, _column: usize, _key: usize, _pressed: bool) {}
+
+    fn set_paddle(&mut self, _position: u8, _fire: bool) {}
+}
+
+struct EmptyPort;
+
+impl InputDevice for EmptyPort {
+    fn device(&self) -> Device {
+        Device::Empty
+    }
+
+    fn write(&mut self, _: u8) {}
+
+    fn read(&mut self, _: u16, _: Port, _: bool) -> u8 {
+        0
+    }
+}
+
+impl SaveState for EmptyPort {
+    fn save_state(&self, _: &mut StateWriter) {}
+
+    fn load_state(&mut self, _: &mut StateReader) -> save_state::Result<()> {
+        Ok(())
+    }
+}
+
+fn create_device(device: Device) -> Box<dyn InputDevice> {
+    match device {
+        Device::Empty => Box::new(EmptyPort),
+        Device::StandardController => Box::new(StandardController::new(false)),
+        Device::MicrophoneController => Box::new(StandardController::new(true)),
+        Device::FourScore => Box::new(FourScore::new()),
+        Device::FamilyBasicKeyboard => Box::new(FamilyBasicKeyboard::new()),
+        Device::ArkanoidPaddle => Box::new(ArkanoidPaddle::new()),
+    }
+}
+
+/// What is plugged into every port
+pub struct InputDevices {
+    ports: [Box<dyn InputDevice>; 3],
+    turbo: Turbo,
+    sanitizer: InputSanitizer,
+    macros: Macros,
+    /// the [FastBoot] presses, merged on top of everything else so it
+    /// doesn't get in the way of the player's macros
+    boot_macro: Macros,
+    /// what the player holds, before a macro is put on top
+    live_buttons: [ControllerState; MAX_CONTROLLERS],
+    /// the knob position and fire button, see [InputDevices::set_paddle]
+    paddle: (u8, bool),
+    /// plugged into the keyboard, it stays when the keyboard gets
+    /// unplugged or the console power cycles like a real one would
+    data_recorder: DataRecorder,
+    dma_conflicts: bool,
+}
+
+impl InputDevices {
+    /// Two standard controllers and nothing in the expansion port
+    pub fn new() -> Self {
+        Self {
+            ports: [
+                create_device(Device::StandardController),
+                create_device(Device::StandardController),
+                create_device(Device::Empty),
+            ],
+            turbo: Turbo::new(),
+            sanitizer: InputSanitizer::new(),
+            macros: Macros::new(),
+            boot_macro: Macros::new(),
+            live_buttons: [ControllerState::empty(); MAX_CONTROLLERS],
+            paddle: (0, false),
+            data_recorder: DataRecorder::new(),
+            dma_conflicts: true,
+        }
+    }
+
+    /// Replaces the device in `port`. Attaching a four score to
+    /// [Port::One] empties [Port::Two] since it uses both ports, it is
+    /// ignored on any other port.
+    pub fn attach_device(&mut self, port: Port, device: Device) {
+        if device == Device::FourScore {
+            if port != Port::One {
+                return;
+            }
+            self.ports[Port::Two.index()] = create_device(Device::Empty);
+        }
+        if self.ports[Port::One.index()].device() == Device::FourScore && port == Port::Two {
+            self.ports[Port::One.index()] = create_device(Device::Empty);
+        }
+        self.ports[port.index()] = create_device(device);
+        self.set_paddle(self.paddle.0, self.paddle.1);
+    }
+
+    /// Puts the shift registers and strobes of every device back to how
+    /// they are at power on, the devices and held buttons stay. Turbo
+    /// buttons start their cycle over.
+    pub(crate) fn power_cycle(&mut self) {
+        for port in self.ports.iter_mut() {
+            *port = create_device(port.device());
+        }
+        let mut presses = Vec::new();
+        self.turbo.power_cycle(|controller_index, button, pressed| {
+            presses.push((controller_index, button, pressed))
+        });
+        for (controller_index, button, pressed) in presses {
+            self.press(controller_index, button, pressed);
+        }
+        for controller_index in 0..MAX_CONTROLLERS {
+            self.update_controller(controller_index);
+        }
+        self.set_paddle(self.paddle.0, self.paddle.1);
+    }
+
+    pub fn get_device(&self, port: Port) -> Device {
+        self.ports[port.index()].device()
+    }
+
+    /// Controllers are counted across the ports in order, so with two
+    /// standard controllers 0 is port one and 1 is port two, and with a
+    /// four score 0 to 3 are its controllers
+    pub fn set_button(&mut self, controller_index: usize, button: Button, pressed: bool) {
+        let pressed = self
+            .turbo
+            .set_held(controller_index, button, pressed)
+            .unwrap_or(pressed);
+        self.press(controller_index, button, pressed);
+    }
+
+    /// The buttons the game sees as pressed on a controller, with turbo
+    /// already applied
+    pub fn get_buttons(&self, controller_index: usize) -> ControllerState {
+        self.find_controller(controller_index)
+            .map(|(device, index)| self.ports[device].get_buttons(index))
+            .unwrap_or_default()
+    }
+
+    /// Makes `button` auto fire `rate` times a second while it is held,
+    /// `None` turns it back into a normal button. See
+    /// [TURBO_RATE_FAST](crate::hardware::constants::controller::TURBO_RATE_FAST)
+    /// for the usual rates.
+    pub fn set_turbo(&mut self, controller_index: usize, button: Button, rate: Option<u32>) {
+        let was_held = self.is_held(controller_index, button);
+        self.turbo.set(controller_index, button, rate, was_held);
+        self.set_button(controller_index, button, was_held);
+    }
+
+    /// Has to be called once every frame for turbo buttons to work
+    pub fn next_frame(&mut self) {
+        let mut presses = Vec::new();
+        self.turbo.next_frame(|controller_index, button, pressed| {
+            presses.push((controller_index, button, pressed))
+        });
+        for (controller_index, button, pressed) in presses {
+            self.press(controller_index, button, pressed);
+        }
+
+        let live_buttons = self.live_buttons;
+        let finished = self.macros.next_frame(|controller_index| {
+            live_buttons
+                .get(controller_index)
+                .copied()
+                .unwrap_or_default()
+        });
+        let boot_finished = self.boot_macro.next_frame(|_| ControllerState::empty());
+        let playing = [self.macros.get_playing(), self.boot_macro.get_playing()];
+        for controller_index in [finished, boot_finished]
+            .into_iter()
+            .chain(playing)
+            .flatten()
+        {
+            self.update_controller(controller_index);
+        }
+    }
+
+    /// Records what the player presses on a controller from the next
+    /// frame on, see [InputDevices::stop_macro_recording]
+    pub fn start_macro_recording(&mut self, controller_index: usize) {
+        self.macros.start_recording(controller_index);
+    }
+
+    /// The macro recorded since [InputDevices::start_macro_recording], the
+    /// buttons of every frame that ended in between
+    pub fn stop_macro_recording(&mut self) -> Option<InputMacro> {
+        self.macros.stop_recording()
+    }
+
+    pub fn is_recording_macro(&self) -> bool {
+        self.macros.is_recording()
+    }
+
+    /// Plays `input_macro` on a controller starting right away, replacing
+    /// the macro that was playing. The player can keep pressing buttons,
+    /// `priority` decides what the game sees.
+    pub fn play_macro(
+        &mut self,
+        controller_index: usize,
+        input_macro: &InputMacro,
+        priority: MacroPriority,
+    ) {
+        let previous = self.macros.stop_playing();
+        self.macros.play(controller_index, input_macro, priority);
+        for controller_index in previous.into_iter().chain([controller_index]) {
+            self.update_controller(controller_index);
+        }
+    }
+
+    /// The player's presses go straight to the game again
+    pub fn stop_macro(&mut self) {
+        if let Some(controller_index) = self.macros.stop_playing() {
+            self.update_controller(controller_index);
+        }
+    }
+
+    pub fn is_playing_macro(&self) -> bool {
+        self.macros.get_playing().is_some()
+    }
+
+    /// Starts pressing start like `fast_boot` says, counting from the
+    /// current frame. `None` stops the presses that are left.
+    pub fn start_fast_boot(&mut self, fast_boot: Option<FastBoot>) {
+        let previous = self.boot_macro.stop_playing();
+        if let Some(fast_boot) = fast_boot {
+            self.boot_macro
+                .play(0, &fast_boot.to_macro(), MacroPriority::Merge);
+        }
+        for controller_index in previous.into_iter().chain([0]) {
+            self.update_controller(controller_index);
+        }
+    }
+
+    /// Whether the [FastBoot] presses aren't done yet
+    pub fn is_fast_booting(&self) -> bool {
+        self.boot_macro.get_playing().is_some()
+    }
+
+    fn is_held(&self, controller_index: usize, button: Button) -> bool {
+        self.turbo
+            .get_held(controller_index, button)
+            .unwrap_or_else(|| {
+                self.live_buttons
+                    .get(controller_index)
+                    .is_some_and(|buttons| buttons.is_pressed(button))
+            })
+    }
+
+    fn press(&mut self, controller_index: usize, button: Button, pressed: bool) {
+        let Some(live_buttons) = self.live_buttons.get_mut(controller_index) else {
+            return;
+        };
+        live_buttons.set(button, pressed);
+        self.sanitizer.press(controller_index, button, pressed);
+        self.update_controller(controller_index);
+    }
+
+    /// Hands the player's buttons with the macro on top to the controller
+    fn update_controller(&mut self, controller_index: usize) {
+        let live_buttons = self
+            .live_buttons
+            .get(controller_index)
+            .copied()
+            .unwrap_or_default();
+        let buttons = self.macros.overlay(controller_index, live_buttons);
+        let buttons = self.boot_macro.overlay(controller_index, buttons);
+        let buttons = self.sanitizer.sanitize(controller_index, buttons);
+        if let Some((device, index)) = self.find_controller(controller_index) {
+            for button in Button::ALL {
+                self.ports[device].set_button(index, button, buttons.is_pressed(button));
+            }
+        }
+    }
+
+    /// Returns the port index and the index of the controller in the
+    /// device plugged into it
+    fn find_controller(&self, controller_index: usize) -> Option<(usize, usize)> {
+        let mut controller_index = controller_index;
+        for (port, device) in self.ports.iter().enumerate() {
+            if controller_index < device.controller_count() {
+                return Some((port, controller_index));
+            }
+            controller_index -= device.controller_count();
+        }
+        None
+    }
+
+    /// Only does something with a [Device::MicrophoneController] attached
+    pub fn set_microphone(&mut self, active: bool) {
+        self.ports
+            .iter_mut()
+            .for_each(|device| device.set_microphone(active));
+    }
+
+    /// Only does something with a [Device::FamilyBasicKeyboard] attached,
+    /// see [FamilyBasicKeyboard::set_key]
+    pub fn set_key(&mut self, row: usize, column: usize, key: usize, pressed: bool) {
+        self.ports
+            .iter_mut()
+            .for_each(|device| device.set_key(row, column, key, pressed));
+    }
+
+    /// Only does something with a [Device::ArkanoidPaddle] attached.
+    /// `position` gets clamped to the knob's range, see
+    /// [paddle::position_from_x] for following the mouse.
+    pub fn set_paddle(&mut self, position: u8, fire: bool) {
+        self.paddle = (position, fire);
+        self.ports
+            .iter_mut()
+            .for_each(|device| device.set_paddle(position, fire));
+    }
+
+    /// What the controllers get when opposite directions are held, like
+    /// with a keyboard. Blocked by default.
+    pub fn set_opposite_directions(&mut self, mode: OppositeDirections) {
+        self.sanitizer.set_mode(mode);
+        for controller_index in 0..MAX_CONTROLLERS {
+            self.update_controller(controller_index);
+        }
+    }
+
+    pub fn get_opposite_directions(&self) -> OppositeDirections {
+        self.sanitizer.get_mode()
+    }
+
+    /// When the dmc dma halts the cpu in the middle of a $4016/$4017 read
+    /// the read gets repeated, so the controllers shift out an extra bit
+    /// and the game misses a button. Games that play dpcm samples read
+    /// the controllers until two reads match to get around it. On by
+    /// default, turning it off gives clean reads every time.
+    /// https://www.nesdev.org/wiki/DMA#Register_conflicts
+    pub fn set_dma_conflicts(&mut self, enabled: bool) {
+        self.dma_conflicts = enabled;
+    }
+
+    pub fn has_dma_conflicts(&self) -> bool {
+        self.dma_conflicts
+    }
+
+    /// Called when the dmc dma halts the cpu while it reads `address`,
+    /// see [InputDevices::set_dma_conflicts]
+    pub fn dma_conflict(&mut self, address: u16) {
+        if self.dma_conflicts && matches!(address, 0x4016 | 0x4017) {
+            tracing::trace!(target: targets::CPU, address, "dmc dma read conflict");
+            self.read(address, false);
+        }
+    }
+
+    /// The cassette recorder of the [Device::FamilyBasicKeyboard], the
+    /// game only sees it while a keyboard is attached
+    pub fn get_data_recorder(&self) -> &DataRecorder {
+        &self.data_recorder
+    }
+
+    pub fn get_data_recorder_mut(&mut self) -> &mut DataRecorder {
+        &mut self.data_recorder
+    }
+
+    fn has_keyboard(&self) -> bool {
+        self.get_device(Port::Expansion) == Device::FamilyBasicKeyboard
+    }
+
+    /// Called once every cpu cycle, runs the tape
+    pub(crate) fn tick(&mut self) {
+        if self.has_keyboard() {
+            self.data_recorder.tick();
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.ports.iter_mut().for_each(|device| device.write(value));
+        if self.has_keyboard() {
+            self.data_recorder
+                .set_output(value.get_flag_enabled(TAPE_OUTPUT));
+        }
+    }
+
+    pub fn read(&mut self, address: u16, peek: bool) -> u8 {
+        let out = Port::ALL
+            .iter()
+            .zip(self.ports.iter_mut())
+            .fold(0, |out, (port, device)| {
+                out | device.read(address, *port, peek)
+            });
+        if address == 0x4016 && self.has_keyboard() && self.data_recorder.get_input() {
+            out | TAPE_INPUT
+        } else {
+            out
+        }
+    }
+}
+
+impl Default for InputDevices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn device_id(device: Device) -> u8 {
+    match device {
+        Device::Empty => 0,
+        Device::StandardController => 1,
+        Device::MicrophoneController => 2,
+        Device::FourScore => 3,
+        Device::FamilyBasicKeyboard => 4,
+        Device::ArkanoidPaddle => 5,
+    }
+}
+
+/// Every port is saved as the id of its device followed by the state of
+/// the device, then the data recorder. When loading, ports that have a
+/// different device attached than when saving are left alone since the
+/// player chose the devices.
+impl SaveState for InputDevices {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for device in self.ports.iter() {
+            writer.write_u8(device_id(device.device()));
+            let mut device_writer = StateWriter::new();
+            device.save_state(&mut device_writer);
+            writer.write_bytes(&device_writer.into_bytes());
+        }
+        self.data_recorder.save_state(writer);
+        for buttons in self.live_buttons {
+            writer.write_u8(buttons.bits());
+        }
+        self.sanitizer.save_state(writer);
+        self.turbo.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        for device in self.ports.iter_mut() {
+            let id = reader.read_u8()?;
+            let state = reader.read_bytes()?;
+            if id == device_id(device.device()) {
+                device.load_state(&mut StateReader::new(state))?;
+            }
+        }
+        self.data_recorder.load_state(reader)?;
+        for buttons in self.live_buttons.iter_mut() {
+            *buttons = ControllerState::from_bits(reader.read_u8()?);
+        }
+        self.sanitizer.load_state(reader)?;
+        self.turbo.load_state(reader)?;
+        Ok(())
+    }
+}
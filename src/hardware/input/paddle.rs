@@ -0,0 +1,114 @@
+use crate::{
+    hardware::{
+        constants::{
+            controller::paddle::{
+                FAMICOM_DATA, FAMICOM_FIRE, NES_DATA, NES_FIRE, POSITION_MAX, POSITION_MIN,
+            },
+            ppu::SCREEN_WIDTH,
+        },
+        input::{Device, InputDevice, Port},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+/// The Vaus controller that came with Arkanoid, a knob and a fire button.
+/// Strobing latches the knob's position into an 8 bit shift register
+/// that gets read out inverted, highest bit first. The NES version goes in
+/// a controller port and puts the bits on D4 and the button on D3, the
+/// famicom one goes in the expansion port and uses D1 of $4017 for the
+/// bits and D1 of $4016 for the button.
+/// https://www.nesdev.org/wiki/Arkanoid_controller
+pub struct ArkanoidPaddle {
+    position: u8,
+    is_fire_pressed: bool,
+    shift: u8,
+    strobe: bool,
+}
+
+impl ArkanoidPaddle {
+    pub fn new() -> Self {
+        Self {
+            position: POSITION_MIN,
+            is_fire_pressed: false,
+            shift: 0,
+            strobe: false,
+        }
+    }
+
+    /// The next bit of the position, after all 8 got read it keeps
+    /// returning 1
+    fn read_next(&mut self, peek: bool) -> bool {
+        if self.strobe {
+            self.shift = self.position;
+        }
+        let bit = self.shift & 0x80 == 0;
+        if !peek {
+            self.shift <<= 1;
+        }
+        bit
+    }
+}
+
+impl Default for ArkanoidPaddle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for ArkanoidPaddle {
+    fn device(&self) -> Device {
+        Device::ArkanoidPaddle
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = self.position;
+        }
+    }
+
+    fn read(&mut self, address: u16, port: Port, peek: bool) -> u8 {
+        let is_fire_pressed = self.is_fire_pressed;
+        let fire = |mask| if is_fire_pressed { mask } else { 0 };
+        match (port, address) {
+            (Port::One, 0x4016) | (Port::Two, 0x4017) => {
+                let data = if self.read_next(peek) { NES_DATA } else { 0 };
+                data | fire(NES_FIRE)
+            }
+            (Port::Expansion, 0x4016) => fire(FAMICOM_FIRE),
+            (Port::Expansion, 0x4017) if self.read_next(peek) => FAMICOM_DATA,
+            _ => 0,
+        }
+    }
+
+    fn set_paddle(&mut self, position: u8, fire: bool) {
+        self.position = position.clamp(POSITION_MIN, POSITION_MAX);
+        self.is_fire_pressed = fire;
+    }
+}
+
+impl SaveState for ArkanoidPaddle {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.position);
+        writer.write_bool(self.is_fire_pressed);
+        writer.write_u8(self.shift);
+        writer.write_bool(self.strobe);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.position = reader.read_u8()?;
+        self.is_fire_pressed = reader.read_bool()?;
+        self.shift = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+/// The knob position for an x on the screen, so the paddle follows the
+/// mouse across the playfield. `x` is in frame pixels, see
+/// [ScalingConfig::frame_position](crate::frontend::scaling::ScalingConfig::frame_position).
+pub fn position_from_x(x: usize) -> u8 {
+    let x = x.min(SCREEN_WIDTH - 1);
+    let range = (POSITION_MAX - POSITION_MIN) as usize;
+    POSITION_MIN + (x * range / (SCREEN_WIDTH - 1)) as u8
+}
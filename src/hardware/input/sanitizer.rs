@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hardware::input::{
+        MAX_CONTROLLERS,
+        controller::{Button, ControllerState},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+const AXES: [(Button, Button); 2] = [(Button::Left, Button::Right), (Button::Up, Button::Down)];
+
+/// What happens when left and right or up and down are held together. A
+/// real d-pad can't press both, keyboards can, and some games glitch
+/// badly when they see it since they were never tested with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OppositeDirections {
+    /// Neither of the two is pressed
+    #[default]
+    Block,
+    /// The game sees both, for tases that rely on it
+    Allow,
+    /// Only the one pressed last, like most pc games do it
+    LastPressed,
+}
+
+/// Keeps opposite directions from reaching the controllers, see
+/// [OppositeDirections]. It sits in front of the controllers like
+/// [Turbo](super::turbo::Turbo), so movies record what the game saw.
+#[derive(Default)]
+pub struct InputSanitizer {
+    mode: OppositeDirections,
+    /// the direction of every axis pressed last, for
+    /// [OppositeDirections::LastPressed]
+    last_pressed: [[Option<Button>; AXES.len()]; MAX_CONTROLLERS],
+}
+
+impl InputSanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_mode(&mut self, mode: OppositeDirections) {
+        self.mode = mode;
+    }
+
+    pub fn get_mode(&self) -> OppositeDirections {
+        self.mode
+    }
+
+    /// Has to be told about every press to know which one came last
+    pub fn press(&mut self, controller_index: usize, button: Button, pressed: bool) {
+        let Some(last_pressed) = self.last_pressed.get_mut(controller_index) else {
+            return;
+        };
+        for (axis, (first, second)) in AXES.into_iter().enumerate() {
+            if pressed && (button == first || button == second) {
+                last_pressed[axis] = Some(button);
+            }
+        }
+    }
+
+    /// The buttons a controller gets when the player holds `buttons`
+    pub fn sanitize(&self, controller_index: usize, buttons: ControllerState) -> ControllerState {
+        let mut out = buttons;
+        for (axis, (first, second)) in AXES.into_iter().enumerate() {
+            if !buttons.is_pressed(first) || !buttons.is_pressed(second) {
+                continue;
+            }
+            match self.mode {
+                OppositeDirections::Allow => {}
+                OppositeDirections::Block => {
+                    out.set(first, false);
+                    out.set(second, false);
+                }
+                OppositeDirections::LastPressed => {
+                    let last = self
+                        .last_pressed
+                        .get(controller_index)
+                        .and_then(|last_pressed| last_pressed[axis]);
+                    let released = if last == Some(first) { second } else { first };
+                    out.set(released, false);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The mode is a setting, only the last pressed directions are saved. They
+/// go in a byte per controller like a [ControllerState], with at most one
+/// direction of every axis set.
+impl SaveState for InputSanitizer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for last_pressed in self.last_pressed.iter() {
+            let buttons: ControllerState = last_pressed.iter().flatten().copied().collect();
+            writer.write_u8(buttons.bits());
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        for last_pressed in self.last_pressed.iter_mut() {
+            let buttons = ControllerState::from_bits(reader.read_u8()?);
+            for (axis, (first, second)) in AXES.into_iter().enumerate() {
+                last_pressed[axis] = [first, second]
+                    .into_iter()
+                    .find(|button| buttons.is_pressed(*button));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    hardware::{
+        constants::clock_rates::FRAME_RATE,
+        input::{
+            MAX_CONTROLLERS,
+            controller::{Button, ControllerState},
+        },
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+struct TurboButton {
+    controller_index: usize,
+    button: Button,
+    /// how many frames the button stays pressed and then released
+    half_period: u64,
+    is_held: bool,
+}
+
+/// Auto fire for controller buttons. While a turbo button is held it gets
+/// pressed and released every few frames, the controllers only ever see
+/// the resulting presses so whatever reads them (games, movies) gets the
+/// effective state.
+#[derive(Default)]
+pub struct Turbo {
+    buttons: Vec<TurboButton>,
+    /// where every button is in its press and release cycle
+    frame: u64,
+}
+
+impl Turbo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `button` fire `rate` times a second while held, or turns
+    /// turbo off for it with `None`. Rates above half the frame rate are
+    /// capped since the button has to stay pressed for at least a frame.
+    pub fn set(
+        &mut self,
+        controller_index: usize,
+        button: Button,
+        rate: Option<u32>,
+        is_held: bool,
+    ) {
+        self.buttons
+            .retain(|turbo| turbo.controller_index != controller_index || turbo.button != button);
+        let Some(rate) = rate.filter(|rate| *rate > 0) else {
+            return;
+        };
+        self.buttons.push(TurboButton {
+            controller_index,
+            button,
+            half_period: (FRAME_RATE / (rate * 2)).max(1) as u64,
+            is_held,
+        });
+    }
+
+    /// Whether the player is holding a turbo button, `None` if it isn't
+    /// a turbo button
+    pub fn get_held(&self, controller_index: usize, button: Button) -> Option<bool> {
+        self.find(controller_index, button)
+            .map(|turbo| turbo.is_held)
+    }
+
+    /// Returns the effective state of a turbo button after the player
+    /// presses or releases it, or `None` if it isn't a turbo button
+    pub fn set_held(
+        &mut self,
+        controller_index: usize,
+        button: Button,
+        is_held: bool,
+    ) -> Option<bool> {
+        let frame = self.frame;
+        let turbo = self
+            .buttons
+            .iter_mut()
+            .find(|turbo| turbo.controller_index == controller_index && turbo.button == button)?;
+        turbo.is_held = is_held;
+        Some(turbo.is_pressed(frame))
+    }
+
+    /// Advances the turbo by a frame, calls `press` with the new effective
+    /// state of every turbo button
+    pub fn next_frame(&mut self, press: impl FnMut(usize, Button, bool)) {
+        self.frame += 1;
+        self.press_all(press);
+    }
+
+    /// Starts every button's cycle over, so runs from power on fire on the
+    /// same frames. Calls `press` like [Turbo::next_frame].
+    pub fn power_cycle(&mut self, press: impl FnMut(usize, Button, bool)) {
+        self.frame = 0;
+        self.press_all(press);
+    }
+
+    fn press_all(&self, mut press: impl FnMut(usize, Button, bool)) {
+        for turbo in self.buttons.iter() {
+            press(
+                turbo.controller_index,
+                turbo.button,
+                turbo.is_pressed(self.frame),
+            );
+        }
+    }
+
+    fn find(&self, controller_index: usize, button: Button) -> Option<&TurboButton> {
+        self.buttons
+            .iter()
+            .find(|turbo| turbo.controller_index == controller_index && turbo.button == button)
+    }
+}
+
+impl TurboButton {
+    fn is_pressed(&self, frame: u64) -> bool {
+        self.is_held && (frame / self.half_period).is_multiple_of(2)
+    }
+}
+
+/// Which buttons are turbo buttons is a setting of the player, only where
+/// they are in their cycle and which ones are held (a byte per controller
+/// like a [ControllerState]) is saved
+impl SaveState for Turbo {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.frame);
+        for controller_index in 0..MAX_CONTROLLERS {
+            let held: ControllerState = self
+                .buttons
+                .iter()
+                .filter(|turbo| turbo.controller_index == controller_index && turbo.is_held)
+                .map(|turbo| turbo.button)
+                .collect();
+            writer.write_u8(held.bits());
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.frame = reader.read_u64()?;
+        let mut held = [ControllerState::empty(); MAX_CONTROLLERS];
+        for buttons in held.iter_mut() {
+            *buttons = ControllerState::from_bits(reader.read_u8()?);
+        }
+        for turbo in self.buttons.iter_mut() {
+            turbo.is_held = held[turbo.controller_index].is_pressed(turbo.button);
+        }
+        Ok(())
+    }
+}
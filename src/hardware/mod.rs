@@ -2,6 +2,8 @@ pub mod apu;
 pub mod bit_ops;
 pub mod cartrige;
 pub mod constants;
+pub mod counters;
 pub mod cpu;
 pub mod cpu_bus;
+pub mod input;
 pub mod ppu;
@@ -0,0 +1,109 @@
+use crate::hardware::constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// A whole picture output by the ppu, every pixel is stored as 0x00RRGGBB
+/// which most framebuffer crates (softbuffer, minifb) can take as is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pixels: Vec<u32>,
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn fill(&mut self, color: u32) {
+        self.pixels.fill(color);
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+        if x < SCREEN_WIDTH && y < SCREEN_HEIGHT {
+            self.pixels[y * SCREEN_WIDTH + x] = color;
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    /// All the pixels row by row
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub(crate) fn pixels_mut(&mut self) -> &mut [u32] {
+        &mut self.pixels
+    }
+
+    /// The pixels as `[r, g, b, r, g, b, ...]`, row by row
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                let [_, r, g, b] = pixel.to_be_bytes();
+                [r, g, b]
+            })
+            .collect()
+    }
+
+    /// The pixels as `[r, g, b, 255, ...]`, row by row, ready to be
+    /// uploaded to an rgba8 texture
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                let [_, r, g, b] = pixel.to_be_bytes();
+                [r, g, b, 0xFF]
+            })
+            .collect()
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A frame before its colors are looked up: every pixel is the palette
+/// color id in the low 6 bits and the PPUMASK emphasis bits (red, green,
+/// blue) in bits 6 to 8. See [super::renderer] for turning it into a [Frame].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    pixels: Vec<u16>,
+}
+
+impl RawFrame {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, raw: u16) {
+        if x < SCREEN_WIDTH && y < SCREEN_HEIGHT {
+            self.pixels[y * SCREEN_WIDTH + x] = raw;
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    /// All the pixels row by row
+    pub fn pixels(&self) -> &[u16] {
+        &self.pixels
+    }
+
+    pub(crate) fn line_mut(&mut self, y: usize) -> &mut [u16] {
+        &mut self.pixels[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH]
+    }
+}
+
+impl Default for RawFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
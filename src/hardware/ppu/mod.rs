@@ -1,28 +1,78 @@
-use std::{cell::RefCell, rc::Rc};
-
-use crate::hardware::{
-    bit_ops::BitOps,
-    cartrige::{Cartrige, cartrige_access::CartrigeAccess},
-    constants::{
-        self,
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    devices::region::Region,
+    hardware::{
+        bit_ops::BitOps,
+        cartrige::{Cartrige, cartrige_access::CartrigeAccess},
+        constants::{
+            self,
+            ppu::{
+                DOTS_PER_SCANLINE, NAMETABLE_SIZE, SCREEN_HEIGHT, TEMP_OAM_SIZE,
+                control_flags::{self, SPRITE_SIZE},
+                mask_flags::{self, SHOW_LEFTMOST_BACKGROUND, SHOW_LEFTMOST_SPRITE},
+                sprite_attributes, sprite_tile_id,
+                status_flags::{self, SPRITE_0_HIT, SPRITE_OVERFLOW},
+                vram_sections::*,
+            },
+        },
+        cpu::Cpu,
         ppu::{
-            NAMETABLE_SIZE,
-            control_flags::{self, SPRITE_SIZE},
-            mask_flags::{self, SHOW_LEFTMOST_BACKGROUND, SHOW_LEFTMOST_SPRITE},
-            sprite_attributes, sprite_tile_id,
-            status_flags::{self, SPRITE_0_HIT, SPRITE_OVERFLOW},
-            vram_sections::*,
+            frame::{Frame, RawFrame},
+            pallet_memory::PalletMemory,
+            renderer::{AsyncRenderer, RenderMode},
+            scanline::PpuAccuracy,
         },
     },
-    cpu::{Cpu, DmaState},
-    ppu::pallet_memory::PalletMemory,
+    save_state::{self, SaveState, StateReader, StateWriter, error::SaveStateError},
+    trace::targets,
 };
 
+pub mod frame;
 pub mod pallet_memory;
+pub mod renderer;
+pub mod scanline;
 
 pub type BackgroundSprite = [[u8; 8]; 8];
 pub type PatternTable = [[BackgroundSprite; 16]; 32];
 
+/// What gets drawn on screen, see [Ppu::set_layer_shown]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Background,
+    Sprites,
+}
+
+/// The background tile under a pixel of the screen, see [Ppu::tile_info_at]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileInfo {
+    /// Where the tile index is in ppu memory ($2000-$2FFF)
+    pub nametable_address: u16,
+    pub tile_index: u8,
+    pub attribute_address: u16,
+    /// The background palette (0-3) picked by the attribute byte
+    pub palette: u8,
+    /// Where the tile starts in the pattern table
+    pub pattern_address: u16,
+    /// The pixel inside the tile, 0-7
+    pub pixel_x: u8,
+    pub pixel_y: u8,
+}
+
+/// The registers that decide how the ppu draws, `vram_address` and
+/// `temp_vram_address` are the v and t registers holding the scroll
+/// https://www.nesdev.org/wiki/PPU_scrolling
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PpuRegisters {
+    pub control: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_address: u8,
+    pub vram_address: u16,
+    pub temp_vram_address: u16,
+    pub fine_x: u8,
+}
+
 /// https://www.nesdev.org/wiki/PPU_OAM#OAM_(Sprite)_Data
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -33,6 +83,17 @@ pub struct Sprite {
     x: u8,
 }
 
+/// A sprite past the 8th on the next line, only drawn with
+/// [Ppu::set_sprite_limit_removed]
+#[derive(Debug, Clone)]
+struct ExtraSprite {
+    x: u8,
+    attributes: u8,
+    lsb: u8,
+    msb: u8,
+    orig_index: u8,
+}
+
 #[derive(Debug, Clone, Default)]
 pub enum SpriteRenderingState {
     #[default]
@@ -80,8 +141,8 @@ pub enum SpriteEvaluation {
 }
 
 pub struct Ppu {
-    cpu: Option<Rc<RefCell<Cpu>>>,
-    cartrige: Option<Rc<RefCell<Cartrige>>>,
+    cpu: Option<Arc<Mutex<Cpu>>>,
+    cartrige: Option<Arc<Mutex<Cartrige>>>,
     scanline: u32,
     dot: u32,
     pub pallet_memory: PalletMemory,
@@ -98,7 +159,7 @@ pub struct Ppu {
     status_register: u8,
     oam_address_register: u8,
     pub oam: [u8; 256],
-    temp_oam: [u8; 32],
+    temp_oam: [u8; TEMP_OAM_SIZE],
     // -temp_oam_address: u8,
     renderer_sprite_id: u8,
     renderer_attribute_lsb: u8,
@@ -120,6 +181,34 @@ pub struct Ppu {
     renderer_sprite_attributes: [u8; 8],
     renderer_sprite_orig_indexes: [u8; 8],
     is_odd_frame: bool,
+    /// frames finished since power on
+    frame_count: u64,
+    /// the frame that is being drawn right now
+    frame: RawFrame,
+    /// the last frame that was fully drawn
+    last_frame: Frame,
+    is_frame_ready: bool,
+    /// with video off nothing gets drawn, see [Ppu::set_video_enabled]
+    is_video_enabled: bool,
+    /// v and fine x at the start of every visible line, for
+    /// [Ppu::tile_info_at]
+    line_scroll: [(u16, u8); SCREEN_HEIGHT],
+    /// debug toggles, see [Ppu::set_layer_shown]
+    is_background_shown: bool,
+    are_sprites_shown: bool,
+    /// see [Ppu::set_accuracy]
+    accuracy: PpuAccuracy,
+    /// see [Ppu::set_sprite_limit_removed]
+    is_sprite_limit_removed: bool,
+    /// see [Ppu::set_region]
+    region: Region,
+    /// the sprites past the 8th on the next line while the limit is
+    /// removed, not part of save states since they're only drawn
+    extra_sprites: Vec<ExtraSprite>,
+    /// only there with [RenderMode::Async]
+    async_renderer: Option<AsyncRenderer>,
+    /// a raw frame the async renderer is done with, reused for drawing
+    spare_raw_frame: Option<RawFrame>,
 }
 
 impl Ppu {
@@ -142,7 +231,7 @@ impl Ppu {
             status_register: 0,
             oam_address_register: 0,
             oam: [0; 256],
-            temp_oam: [0; 32],
+            temp_oam: [0; TEMP_OAM_SIZE],
             // temp_oam_address: 0,
             renderer_sprite_id: 0,
             renderer_attribute_lsb: 0,
@@ -164,14 +253,49 @@ impl Ppu {
             renderer_sprite_attributes: [0; 8],
             renderer_sprite_orig_indexes: [0; 8],
             is_odd_frame: false,
+            frame_count: 0,
+            frame: RawFrame::new(),
+            last_frame: Frame::new(),
+            is_frame_ready: false,
+            is_video_enabled: true,
+            line_scroll: [(0, 0); SCREEN_HEIGHT],
+            is_background_shown: true,
+            are_sprites_shown: true,
+            accuracy: PpuAccuracy::default(),
+            is_sprite_limit_removed: false,
+            region: Region::default(),
+            extra_sprites: Vec::new(),
+            async_renderer: None,
+            spare_raw_frame: None,
         }
     }
 
-    pub fn insert_cartrige(&mut self, cartrige: Rc<RefCell<Cartrige>>) {
+    pub fn insert_cartrige(&mut self, cartrige: Arc<Mutex<Cartrige>>) {
         self.cartrige = Some(cartrige);
     }
 
-    pub fn connect_cpu(&mut self, cpu: Rc<RefCell<Cpu>>) {
+    pub(crate) fn eject_cartrige(&mut self) {
+        self.cartrige = None;
+    }
+
+    /// Everything goes back to its power on state except the connections,
+    /// the render mode, the accuracy, whether video is on, the shown
+    /// layers, the sprite limit and the region
+    pub(crate) fn power_cycle(&mut self) {
+        let mut ppu = Self::new();
+        ppu.cpu = self.cpu.take();
+        ppu.cartrige = self.cartrige.take();
+        ppu.set_render_mode(self.get_render_mode());
+        ppu.is_video_enabled = self.is_video_enabled;
+        ppu.is_background_shown = self.is_background_shown;
+        ppu.are_sprites_shown = self.are_sprites_shown;
+        ppu.accuracy = self.accuracy;
+        ppu.is_sprite_limit_removed = self.is_sprite_limit_removed;
+        ppu.region = self.region;
+        *self = ppu;
+    }
+
+    pub fn connect_cpu(&mut self, cpu: Arc<Mutex<Cpu>>) {
         self.cpu = Some(cpu);
     }
 
@@ -206,16 +330,23 @@ impl Ppu {
                     self.oam[self.oam_address_register as usize]
                 }
             }
+            // palette reads skip the read buffer, the buffer gets the
+            // nametable byte "under" the palette instead. The palette is
+            // only 6 bits wide so the top 2 come from open bus.
+            // https://www.nesdev.org/wiki/PPU_registers#Reading_palette_RAM
+            0x7 if self.vram_address & 0x3FFF >= 0x3F00 => {
+                let address = self.vram_address & 0x3FFF;
+                if !peek {
+                    self.ppu_data_read_buffer = self.fetch(address - 0x1000);
+                }
+                (self.pallet_memory.read_address(address) & 0x3F) | (self.open_bus & 0xC0)
+            }
             0x7 => {
-                // TODO: pallete memory is handled differently: https://www.nesdev.org/wiki/PPU_registers#Reading_palette_RAM
-                // if address < 0x3F00 {
                 let out = self.ppu_data_read_buffer;
                 if !peek {
-                    self.ppu_data_read_buffer = self.read_ppu_bus(self.vram_address);
+                    self.ppu_data_read_buffer = self.fetch(self.vram_address);
                 }
                 out
-                // } else {
-                // }
             }
             _ => self.open_bus, // TODO: impl rest of registers
         };
@@ -228,15 +359,6 @@ impl Ppu {
     pub fn write_register(&mut self, address: u16, value: u8) {
         self.open_bus = value;
 
-        if address == 0x4014 {
-            if let Some(cpu) = self.cpu.as_ref() {
-                // TODO: fix this stupid bullshit
-                unsafe {
-                    (*cpu.as_ptr()).dma_status = DmaState::Initializing { page: value };
-                }
-            }
-        }
-
         match address % 0x8 {
             // TODO: IMPL PROPERLY
             0x0 => {
@@ -281,9 +403,11 @@ impl Ppu {
                         (self.temp_vram_address.get_bitmasked(0xFF00)) + (value as u16);
                     self.is_writing_low_byte = false;
                     self.vram_address = self.temp_vram_address;
+                    self.drive_bus(self.vram_address);
                 }
             }
             0x7 => {
+                self.drive_bus(self.vram_address);
                 self.write(self.vram_address, value);
 
                 let mut inc_ammount = 1;
@@ -299,12 +423,44 @@ impl Ppu {
         };
     }
 
+    /// Dots since power on, give or take the skipped dot of odd frames.
+    /// The frame count goes up at the start of line 240 so lines are
+    /// counted from there.
+    fn get_dot_count(&self) -> u64 {
+        let lines = self.region.scanlines_per_frame() as u64;
+        let line = (self.scanline as u64 + lines - 240) % lines;
+        (self.frame_count * lines + line) * DOTS_PER_SCANLINE as u64 + self.dot as u64
+    }
+
+    /// Shows `address` to the cartrige, which is how mappers with scanline
+    /// counters watch A12. Called for the ppu's own fetches and for the
+    /// accesses through PPUADDR and PPUDATA, not for [Ppu::read_ppu_bus]
+    /// since debug views use that too.
+    fn drive_bus(&self, address: u16) {
+        if let Some(cartrige) = self.cartrige.as_ref() {
+            cartrige
+                .lock()
+                .unwrap()
+                .ppu_address(address & 0x3FFF, self.get_dot_count());
+        }
+    }
+
+    /// A read the ppu makes while rendering, see [Ppu::drive_bus]
+    fn fetch(&self, address: u16) -> u8 {
+        self.drive_bus(address);
+        self.read_ppu_bus(address)
+    }
+
     pub fn read_ppu_bus(&self, address: u16) -> u8 {
         let result = match address {
             0x0..0x2000 => self
                 .cartrige
                 .as_ref()
-                .map(|c| c.borrow_mut().read(CartrigeAccess::PpuAccess { address }))
+                .map(|c| {
+                    c.lock()
+                        .unwrap()
+                        .read(CartrigeAccess::PpuAccess { address })
+                })
                 .flatten()
                 .unwrap_or(0x0),
             0x2000..0x3F00 => {
@@ -320,7 +476,8 @@ impl Ppu {
         match address {
             0x0..0x2000 => {
                 _ = self.cartrige.as_ref().map(|c| {
-                    c.borrow_mut()
+                    c.lock()
+                        .unwrap()
                         .write(CartrigeAccess::PpuAccess { address }, value)
                 })
             }
@@ -334,7 +491,20 @@ impl Ppu {
         };
     }
 
+    /// Runs one dot. With [PpuAccuracy::Cycle] it returns the pixel drawn
+    /// on it as `(x, y, pattern, attribute)`, the scanline renderer draws
+    /// whole lines at once and always returns `None`.
     pub fn tick(&mut self) -> Option<(u32, u32, u8, u8)> {
+        match self.accuracy {
+            PpuAccuracy::Cycle => self.tick_cycle(),
+            PpuAccuracy::Scanline => {
+                self.tick_scanline();
+                None
+            }
+        }
+    }
+
+    fn tick_cycle(&mut self) -> Option<(u32, u32, u8, u8)> {
         let enabled_background_rendering = self
             .mask_register
             .get_flag_enabled(mask_flags::ENABLE_BG_RENDERING);
@@ -344,11 +514,17 @@ impl Ppu {
         };
         let enabled_rendering = enabled_background_rendering || enabled_sprite_rendering;
 
-        let scanline_background_visible = matches!(self.scanline, (0..=239) | 261);
+        let pre_render_line = self.region.pre_render_line();
+        let scanline_background_visible =
+            self.scanline < SCREEN_HEIGHT as u32 || self.scanline == pre_render_line;
         let dot_background_fetch = matches!(self.dot, (2..=256) | (321..=336));
 
         // implementation of this: https://www.nesdev.org/w/images/default/4/4f/Ppu.svg
         if enabled_rendering {
+            if self.dot == 1 && self.scanline < SCREEN_HEIGHT as u32 {
+                self.line_scroll[self.scanline as usize] = (self.vram_address, self.fine_x);
+            }
+
             // bg rendering section
             if scanline_background_visible && dot_background_fetch {
                 self.renderer_shift_attribute_lsb <<= 1;
@@ -359,32 +535,19 @@ impl Ppu {
                 match (self.dot - 1) % 8 + 1 {
                     // load shifters + last tick of NT
                     2 => {
-                        self.renderer_sprite_id = self.read_ppu_bus(
-                            0x2000 | (self.vram_address.get_bitfield(NAMETABLE_OFFSET)),
-                        )
+                        self.renderer_sprite_id =
+                            self.fetch(0x2000 | (self.vram_address.get_bitfield(NAMETABLE_OFFSET)))
                     }
                     // last tick of AT
                     4 => {
-                        let mut attributes = self.read_ppu_bus(
-                            0x23C0
-                                | self.vram_address.get_bitmasked(BASE_NAMETABLE_ADDRESS)
-                                | (self.vram_address.get_bitfield(COARSE_X) >> 2)
-                                | (self.vram_address.get_bitfield(COARSE_Y) >> 2 << 3),
-                        );
-
-                        if (self.vram_address.get_bitfield(COARSE_Y) & 2) != 0 {
-                            attributes >>= 4;
-                        }
-                        if (self.vram_address.get_bitfield(COARSE_X) & 2) != 0 {
-                            attributes >>= 2;
-                        }
+                        let attributes = self.fetch_attribute();
                         self.renderer_attribute_msb = (attributes >> 1) & 1;
                         self.renderer_attribute_lsb = attributes & 1;
                     }
                     // last tick of BG LSBIT
                     6 => {
                         // info on pattern tables: https://www.nesdev.org/wiki/PPU_pattern_tables
-                        self.renderer_pattern_lsb = self.read_ppu_bus(
+                        self.renderer_pattern_lsb = self.fetch(
                             self.get_background_pattern_address()
                                 + self.renderer_sprite_id as u16 * 16
                                 + self.vram_address.get_bitfield(FINE_Y),
@@ -392,41 +555,16 @@ impl Ppu {
                     }
                     // last tick of BG MSBIT + increment horizontaly/vertically
                     8 => {
-                        self.renderer_pattern_msb = self.read_ppu_bus(
+                        self.renderer_pattern_msb = self.fetch(
                             self.get_background_pattern_address()
                                 + self.renderer_sprite_id as u16 * 16
                                 + self.vram_address.get_bitfield(FINE_Y)
                                 + 8,
                         );
 
-                        // read more about incrementation: https://www.nesdev.org/wiki/PPU_scrolling#Wrapping_around
-                        let mut coarse_x = self.vram_address.get_bitfield(COARSE_X);
-                        if coarse_x == 31 {
-                            self.vram_address ^= BASE_NAMETABLE_ADDRESS_X;
-                            coarse_x = 0;
-                        } else {
-                            coarse_x += 1;
-                        }
-                        self.vram_address.set_bitfield(COARSE_X, coarse_x);
-
+                        self.increment_coarse_x();
                         if self.dot == 256 {
-                            let mut fine_y = self.vram_address.get_bitfield(FINE_Y);
-                            if fine_y < 7 {
-                                fine_y += 1;
-                            } else {
-                                fine_y = 0;
-                                let mut coarse_y = self.vram_address.get_bitfield(COARSE_Y);
-                                if coarse_y == 29 {
-                                    coarse_y = 0;
-                                    self.vram_address ^= BASE_NAMETABLE_ADDRESS_Y;
-                                } else if coarse_y == 31 {
-                                    coarse_y = 0;
-                                } else {
-                                    coarse_y += 1;
-                                }
-                                self.vram_address.set_bitfield(COARSE_Y, coarse_y);
-                            }
-                            self.vram_address.set_bitfield(FINE_Y, fine_y);
+                            self.increment_y();
                         }
 
                         self.renderer_shift_pattern_msb = (self.renderer_shift_pattern_msb
@@ -448,11 +586,10 @@ impl Ppu {
             }
 
             if scanline_background_visible && self.dot == 257 {
-                self.vram_address.set_bitmasked(
-                    COARSE_X | BASE_NAMETABLE_ADDRESS_X,
-                    self.temp_vram_address
-                        .get_bitmasked(COARSE_X | BASE_NAMETABLE_ADDRESS_X),
-                );
+                self.copy_horizontal_scroll();
+            }
+            if self.dot == 257 {
+                self.evaluate_extra_sprites();
             }
 
             // implementation of this: https://www.nesdev.org/wiki/PPU_sprite_evaluation
@@ -661,6 +798,8 @@ impl Ppu {
                             let tick = (self.dot - 257) % 8;
                             match tick {
                                 0 => {
+                                    // the garbage nametable fetch
+                                    self.drive_bus(0x2000);
                                     temp_sprite.y = self.temp_oam[*temp_oam_address as usize];
                                     *temp_oam_address += 1;
                                 }
@@ -680,87 +819,20 @@ impl Ppu {
                                     self.renderer_sprite_x_counter[sprite_idx] = temp_sprite.x;
                                 }
                                 4 => {
-                                    let tall_sprites = self
-                                        .control_register
-                                        .get_flag_enabled(control_flags::SPRITE_SIZE);
-                                    let height: u8 = if tall_sprites { 16 } else { 8 };
-                                    let flipped_vertically = temp_sprite
-                                        .attributes
-                                        .get_flag_enabled(sprite_attributes::FLIP_VERTICALLY);
-
-                                    let sprite_pattern_table_address = 0x1000
-                                        * if tall_sprites {
-                                            temp_sprite
-                                                .tile_id
-                                                .get_flag_enabled(sprite_tile_id::BANK)
-                                        } else {
-                                            self.control_register.get_flag_enabled(
-                                                control_flags::SPRITE_PATTERN_TABLE_ADDR,
-                                            )
-                                        } as u16;
-
-                                    let mut tile_id = if tall_sprites {
-                                        temp_sprite.tile_id.get_bitmasked(sprite_tile_id::TILE_ID)
-                                    } else {
-                                        temp_sprite.tile_id
-                                    } as u16;
-
-                                    let mut row = self.scanline as u16 - temp_sprite.y as u16;
-
-                                    if tall_sprites && row >= 8 {
-                                        tile_id += 1;
-                                        row -= 8;
-                                    }
-
-                                    let row = if flipped_vertically {
-                                        (height - 1) as u16 - row
-                                    } else {
-                                        row
-                                    };
-
-                                    *temp_fetch_addr =
-                                        sprite_pattern_table_address + tile_id * 16 + row;
+                                    *temp_fetch_addr = self.sprite_pattern_address(temp_sprite);
                                 }
                                 5 => {
-                                    let mut fetched_byte = self.read_ppu_bus(*temp_fetch_addr);
-                                    if temp_sprite
-                                        .attributes
-                                        .get_flag_enabled(sprite_attributes::FLIP_HORIZONTALLY)
-                                    {
-                                        fetched_byte = fetched_byte.reverse_bits();
-                                    }
-
-                                    let tall_sprites = self
-                                        .control_register
-                                        .get_flag_enabled(control_flags::SPRITE_SIZE);
-                                    let row = self.scanline as u16 - temp_sprite.y as u16;
-                                    if !(row < if tall_sprites { 16 } else { 8 }) {
-                                        fetched_byte = 0;
-                                    }
-
-                                    self.renderer_sprite_shift_lsb[sprite_idx] = fetched_byte;
+                                    self.drive_sprite_fetch(temp_sprite, *temp_fetch_addr);
+                                    self.renderer_sprite_shift_lsb[sprite_idx] =
+                                        self.fetch_sprite_pattern(temp_sprite, *temp_fetch_addr);
                                 }
                                 6 => {
                                     *temp_fetch_addr += 8;
                                 }
                                 7 => {
-                                    let mut fetched_byte = self.read_ppu_bus(*temp_fetch_addr);
-                                    if temp_sprite
-                                        .attributes
-                                        .get_flag_enabled(sprite_attributes::FLIP_HORIZONTALLY)
-                                    {
-                                        fetched_byte = fetched_byte.reverse_bits();
-                                    }
-
-                                    let tall_sprites = self
-                                        .control_register
-                                        .get_flag_enabled(control_flags::SPRITE_SIZE);
-                                    let row = self.scanline as u16 - temp_sprite.y as u16;
-                                    if !(row < if tall_sprites { 16 } else { 8 }) {
-                                        fetched_byte = 0;
-                                    }
-
-                                    self.renderer_sprite_shift_msb[sprite_idx] = fetched_byte;
+                                    self.drive_sprite_fetch(temp_sprite, *temp_fetch_addr);
+                                    self.renderer_sprite_shift_msb[sprite_idx] =
+                                        self.fetch_sprite_pattern(temp_sprite, *temp_fetch_addr);
 
                                     *temp_sprite = Sprite::default();
                                     *temp_oam_address += 1;
@@ -772,22 +844,233 @@ impl Ppu {
                     }
                     self.renderer_sprite_state = state;
                 }
+                // nothing gets drawn on the next line but the sprite fetches
+                // still happen, with the empty sprites secondary oam starts
+                // as. Mappers counting scanlines see them.
+                scanline if scanline == pre_render_line && matches!(self.dot, (257..=320)) => {
+                    match (self.dot - 257) % 8 {
+                        0 => self.drive_bus(0x2000),
+                        5 | 7 => self.drive_bus(self.sprite_pattern_table(0xFF)),
+                        _ => {}
+                    }
+                }
                 _ => {}
             }
         }
 
-        if self.scanline == 241 && self.dot == 1 {
+        self.update_vblank();
+        if enabled_rendering && self.scanline == pre_render_line && matches!(self.dot, (280..305)) {
+            self.copy_vertical_scroll();
+        }
+
+        let mut out = None;
+        let pixel_in_display = matches!(self.dot, (1..=256)) && matches!(self.scanline, (0..=239));
+        if pixel_in_display {
+            let background = enabled_background_rendering.then(|| {
+                let fine_x_selector = 1 << (15 - self.fine_x);
+
+                let pattern_lsb = self
+                    .renderer_shift_pattern_lsb
+                    .get_flag_enabled(fine_x_selector) as u8;
+                let pattern_msb = self
+                    .renderer_shift_pattern_msb
+                    .get_flag_enabled(fine_x_selector) as u8;
+
+                let pattern = (pattern_msb << 1) | pattern_lsb;
+
+                let attrib_lsb = self
+                    .renderer_shift_attribute_lsb
+                    .get_flag_enabled(fine_x_selector) as u8;
+                let attrib_msb = self
+                    .renderer_shift_attribute_msb
+                    .get_flag_enabled(fine_x_selector) as u8;
+
+                let attrib = (attrib_msb << 1) | attrib_lsb;
+                (pattern, attrib)
+            });
+
+            let sprite = enabled_sprite_rendering.then(|| {
+                (0..8)
+                    .find_map(|sprite_idx| {
+                        if self.renderer_sprite_x_counter[sprite_idx] != 0 {
+                            return None;
+                        }
+                        let lsb = self.renderer_sprite_shift_lsb[sprite_idx];
+                        let msb = self.renderer_sprite_shift_msb[sprite_idx];
+                        self.sprite_pixel(sprite_idx, lsb, msb)
+                    })
+                    .or_else(|| self.extra_sprite_pixel(self.dot as usize - 1))
+                    .unwrap_or_default()
+            });
+
+            out = self.output_pixel(self.dot - 1, background, sprite);
+        }
+
+        self.advance_dot(enabled_rendering);
+        out
+    }
+
+    /// The pattern table a sprite with `tile_id` comes from, 8x16 sprites
+    /// pick it with the lowest bit of the tile
+    fn sprite_pattern_table(&self, tile_id: u8) -> u16 {
+        let tall_sprites = self
+            .control_register
+            .get_flag_enabled(control_flags::SPRITE_SIZE);
+        0x1000
+            * if tall_sprites {
+                tile_id.get_flag_enabled(sprite_tile_id::BANK)
+            } else {
+                self.control_register
+                    .get_flag_enabled(control_flags::SPRITE_PATTERN_TABLE_ADDR)
+            } as u16
+    }
+
+    /// Puts a sprite pattern fetch on the bus for the cartrige to see.
+    /// Rows of empty sprites can point anywhere, the table is what the
+    /// scanline counters care about.
+    fn drive_sprite_fetch(&self, sprite: &Sprite, address: u16) {
+        self.drive_bus(self.sprite_pattern_table(sprite.tile_id) | (address & 0x0FFF));
+    }
+
+    /// Where the row of `sprite` on the current line starts in the
+    /// pattern tables, the high byte is 8 after it
+    fn sprite_pattern_address(&self, sprite: &Sprite) -> u16 {
+        let tall_sprites = self
+            .control_register
+            .get_flag_enabled(control_flags::SPRITE_SIZE);
+        let height: u8 = if tall_sprites { 16 } else { 8 };
+        let flipped_vertically = sprite
+            .attributes
+            .get_flag_enabled(sprite_attributes::FLIP_VERTICALLY);
+
+        let sprite_pattern_table_address = self.sprite_pattern_table(sprite.tile_id);
+
+        let mut tile_id = if tall_sprites {
+            sprite.tile_id.get_bitmasked(sprite_tile_id::TILE_ID)
+        } else {
+            sprite.tile_id
+        } as u16;
+
+        let mut row = self.scanline as u16 - sprite.y as u16;
+
+        if tall_sprites && row >= 8 {
+            tile_id += 1;
+            row -= 8;
+        }
+
+        let row = if flipped_vertically {
+            (height - 1) as u16 - row
+        } else {
+            row
+        };
+
+        sprite_pattern_table_address + tile_id * 16 + row
+    }
+
+    /// Reads a byte of a sprite's pattern, flipped if it has to be and 0
+    /// if the sprite isn't on the current line (empty slots)
+    fn fetch_sprite_pattern(&self, sprite: &Sprite, address: u16) -> u8 {
+        let mut fetched_byte = self.read_ppu_bus(address);
+        if sprite
+            .attributes
+            .get_flag_enabled(sprite_attributes::FLIP_HORIZONTALLY)
+        {
+            fetched_byte = fetched_byte.reverse_bits();
+        }
+
+        let tall_sprites = self
+            .control_register
+            .get_flag_enabled(control_flags::SPRITE_SIZE);
+        let row = self.scanline as u16 - sprite.y as u16;
+        if !(row < if tall_sprites { 16 } else { 8 }) {
+            fetched_byte = 0;
+        }
+        fetched_byte
+    }
+
+    /// Reads the attribute byte for the tile v points at and returns the
+    /// 2 bits of its palette
+    fn fetch_attribute(&self) -> u8 {
+        let mut attributes = self.read_ppu_bus(
+            0x23C0
+                | self.vram_address.get_bitmasked(BASE_NAMETABLE_ADDRESS)
+                | (self.vram_address.get_bitfield(COARSE_X) >> 2)
+                | (self.vram_address.get_bitfield(COARSE_Y) >> 2 << 3),
+        );
+
+        if (self.vram_address.get_bitfield(COARSE_Y) & 2) != 0 {
+            attributes >>= 4;
+        }
+        if (self.vram_address.get_bitfield(COARSE_X) & 2) != 0 {
+            attributes >>= 2;
+        }
+        attributes & 0x03
+    }
+
+    // read more about incrementation: https://www.nesdev.org/wiki/PPU_scrolling#Wrapping_around
+    fn increment_coarse_x(&mut self) {
+        let mut coarse_x = self.vram_address.get_bitfield(COARSE_X);
+        if coarse_x == 31 {
+            self.vram_address ^= BASE_NAMETABLE_ADDRESS_X;
+            coarse_x = 0;
+        } else {
+            coarse_x += 1;
+        }
+        self.vram_address.set_bitfield(COARSE_X, coarse_x);
+    }
+
+    fn increment_y(&mut self) {
+        let mut fine_y = self.vram_address.get_bitfield(FINE_Y);
+        if fine_y < 7 {
+            fine_y += 1;
+        } else {
+            fine_y = 0;
+            let mut coarse_y = self.vram_address.get_bitfield(COARSE_Y);
+            if coarse_y == 29 {
+                coarse_y = 0;
+                self.vram_address ^= BASE_NAMETABLE_ADDRESS_Y;
+            } else if coarse_y == 31 {
+                coarse_y = 0;
+            } else {
+                coarse_y += 1;
+            }
+            self.vram_address.set_bitfield(COARSE_Y, coarse_y);
+        }
+        self.vram_address.set_bitfield(FINE_Y, fine_y);
+    }
+
+    fn copy_horizontal_scroll(&mut self) {
+        self.vram_address.set_bitmasked(
+            COARSE_X | BASE_NAMETABLE_ADDRESS_X,
+            self.temp_vram_address
+                .get_bitmasked(COARSE_X | BASE_NAMETABLE_ADDRESS_X),
+        );
+    }
+
+    fn copy_vertical_scroll(&mut self) {
+        self.vram_address.set_bitmasked(
+            COARSE_Y | FINE_Y | BASE_NAMETABLE_ADDRESS_Y,
+            self.temp_vram_address
+                .get_bitmasked(COARSE_Y | FINE_Y | BASE_NAMETABLE_ADDRESS_Y),
+        );
+    }
+
+    /// Sets and clears the status flags at the start and the end of
+    /// vblank, and fires the nmi
+    fn update_vblank(&mut self) {
+        if self.scanline == self.region.vblank_line() && self.dot == 1 {
+            tracing::trace!(target: targets::PPU, "vblank started");
             if self
                 .control_register
                 .get_flag_enabled(control_flags::VBLANK_NMI)
                 && let Some(cpu) = self.cpu.as_ref()
             {
-                cpu.borrow_mut().is_triggered_nmi = true;
+                cpu.lock().unwrap().is_triggered_nmi = true;
             }
             self.status_register
                 .set_flag_enabled(status_flags::VBLANK, true);
         }
-        if self.scanline == 261 && self.dot == 1 {
+        if self.scanline == self.region.pre_render_line() && self.dot == 1 {
             self.status_register
                 .set_flag_enabled(status_flags::VBLANK, false);
             self.status_register
@@ -795,85 +1078,140 @@ impl Ppu {
             self.status_register
                 .set_flag_enabled(status_flags::SPRITE_OVERFLOW, false);
         }
-        if enabled_rendering && self.scanline == 261 && matches!(self.dot, (280..305)) {
-            self.vram_address.set_bitmasked(
-                COARSE_Y | FINE_Y | BASE_NAMETABLE_ADDRESS_Y,
-                self.temp_vram_address
-                    .get_bitmasked(COARSE_Y | FINE_Y | BASE_NAMETABLE_ADDRESS_Y),
-            );
-        }
-
-        let mut out = None;
-        let pixel_in_display = matches!(self.dot, (1..=256)) && matches!(self.scanline, (0..=239));
-        if pixel_in_display && enabled_background_rendering {
-            let fine_x_selector = 1 << (15 - self.fine_x);
-
-            let pattern_lsb = self
-                .renderer_shift_pattern_lsb
-                .get_flag_enabled(fine_x_selector) as u8;
-            let pattern_msb = self
-                .renderer_shift_pattern_msb
-                .get_flag_enabled(fine_x_selector) as u8;
+    }
 
-            let pattern = (pattern_msb << 1) | pattern_lsb;
+    /// The pixel of sprite slot `sprite_idx` whose pattern bits are the
+    /// top bits of `lsb` and `msb`, `None` if it is transparent. Returns
+    /// `(pattern, attribute, behind background, index in oam)`.
+    fn sprite_pixel(&self, sprite_idx: usize, lsb: u8, msb: u8) -> Option<(u8, u8, bool, u8)> {
+        Self::pattern_pixel(
+            self.renderer_sprite_attributes[sprite_idx],
+            self.renderer_sprite_orig_indexes[sprite_idx],
+            lsb,
+            msb,
+        )
+    }
 
-            let attrib_lsb = self
-                .renderer_shift_attribute_lsb
-                .get_flag_enabled(fine_x_selector) as u8;
-            let attrib_msb = self
-                .renderer_shift_attribute_msb
-                .get_flag_enabled(fine_x_selector) as u8;
+    fn pattern_pixel(
+        attributes: u8,
+        orig_index: u8,
+        lsb: u8,
+        msb: u8,
+    ) -> Option<(u8, u8, bool, u8)> {
+        let pattern_lsb = lsb.get_bitfield(0x80);
+        let pattern_msb = msb.get_bitfield(0x80);
+        let pattern = (pattern_msb << 1) | pattern_lsb;
+
+        let attrib = attributes.get_bitfield(sprite_attributes::PALLETE) + 4;
+        let priority = attributes.get_flag_enabled(sprite_attributes::PRIORITY);
+
+        if pattern != 0 {
+            Some((pattern, attrib, priority, orig_index))
+        } else {
+            None
+        }
+    }
 
-            let attrib = (attrib_msb << 1) | attrib_lsb;
+    /// The pixel of the first extra sprite that isn't transparent at `x`,
+    /// they go behind the 8 the hardware draws like higher oam indexes do
+    fn extra_sprite_pixel(&self, x: usize) -> Option<(u8, u8, bool, u8)> {
+        self.extra_sprites.iter().find_map(|sprite| {
+            let column = (x + 1)
+                .checked_sub(sprite.x as usize)
+                .filter(|column| *column < 8)?;
+            Self::pattern_pixel(
+                sprite.attributes,
+                sprite.orig_index,
+                sprite.lsb << column,
+                sprite.msb << column,
+            )
+        })
+    }
 
-            out = Some((self.dot - 1, self.scanline, pattern, attrib));
+    /// On dot 257 of every line: with the limit removed, finds and fetches
+    /// the sprites of the next line that didn't fit in the 8 slots. This
+    /// only reads oam and the pattern tables, the overflow flag and the
+    /// slots are left to the evaluation the hardware does.
+    fn evaluate_extra_sprites(&mut self) {
+        self.extra_sprites.clear();
+        if !self.is_sprite_limit_removed || self.scanline >= SCREEN_HEIGHT as u32 {
+            return;
+        }
+        let height = if self
+            .control_register
+            .get_flag_enabled(control_flags::SPRITE_SIZE)
+        {
+            16
+        } else {
+            8
+        };
+        let mut found = 0;
+        for index in 0..64 {
+            let bytes = &self.oam[index * 4..index * 4 + 4];
+            if (self.scanline as u8).wrapping_sub(bytes[0]) >= height {
+                continue;
+            }
+            found += 1;
+            if found <= 8 {
+                continue;
+            }
+            let sprite = Sprite {
+                y: bytes[0],
+                tile_id: bytes[1],
+                attributes: bytes[2],
+                x: bytes[3],
+            };
+            let address = self.sprite_pattern_address(&sprite);
+            self.extra_sprites.push(ExtraSprite {
+                x: sprite.x,
+                attributes: sprite.attributes,
+                lsb: self.fetch_sprite_pattern(&sprite, address),
+                msb: self.fetch_sprite_pattern(&sprite, address + 8),
+                orig_index: index as u8,
+            });
         }
+    }
 
-        if pixel_in_display && enabled_sprite_rendering {
+    /// Mixes the background and the sprite pixel at `x` of the current
+    /// line, sets the sprite 0 hit and draws it. Either is `None` while
+    /// its layer is disabled in PPUMASK.
+    fn output_pixel(
+        &mut self,
+        x: u32,
+        background: Option<(u8, u8)>,
+        sprite: Option<(u8, u8, bool, u8)>,
+    ) -> Option<(u32, u32, u8, u8)> {
+        let dot = x + 1;
+        let mut out = background.map(|(pattern, attrib)| (x, self.scanline, pattern, attrib));
+
+        if let Some((fg_pattern, fg_attrib, priority, orig_index)) = sprite {
             let (_, _, bg_pattern, bg_attrib) = out.unwrap_or_else(|| (0, 0, 0, 0));
 
-            let (fg_pattern, fg_attrib, priority, orig_index) = (0..8)
-                .find_map(|sprite_idx| {
-                    if self.renderer_sprite_x_counter[sprite_idx] != 0 {
-                        return None;
-                    }
-
-                    let lsb = self.renderer_sprite_shift_lsb[sprite_idx];
-                    let msb = self.renderer_sprite_shift_msb[sprite_idx];
-                    let orig_index = self.renderer_sprite_orig_indexes[sprite_idx];
-
-                    let pattern_lsb = lsb.get_bitfield(0x80);
-                    let pattern_msb = msb.get_bitfield(0x80);
-                    let pattern = (pattern_msb << 1) | pattern_lsb;
-
-                    let attributes = self.renderer_sprite_attributes[sprite_idx];
-                    let attrib = attributes.get_bitfield(sprite_attributes::PALLETE) + 4;
-                    let priority = attributes.get_flag_enabled(sprite_attributes::PRIORITY);
-
-                    if pattern != 0 {
-                        Some((pattern, attrib, priority, orig_index))
-                    } else {
-                        None
-                    }
-                })
-                .unwrap_or_default();
-
             let leftmost_rendering = self
                 .status_register
                 .get_flag_enabled(SHOW_LEFTMOST_BACKGROUND)
                 && self.status_register.get_flag_enabled(SHOW_LEFTMOST_SPRITE);
 
             if orig_index == 0
-                && enabled_rendering
                 && bg_pattern != 0
                 && fg_pattern != 0
-                && self.dot != 255
+                && dot != 255
                 && !self.status_register.get_flag_enabled(SPRITE_0_HIT)
-                && (leftmost_rendering || !matches!(self.dot, 0..=7))
+                && (leftmost_rendering || !matches!(dot, 0..=7))
             {
                 self.status_register.set_flag_enabled(SPRITE_0_HIT, true);
             }
 
+            let bg_pattern = if self.is_background_shown {
+                bg_pattern
+            } else {
+                0
+            };
+            let fg_pattern = if self.are_sprites_shown {
+                fg_pattern
+            } else {
+                0
+            };
             let (pattern, attrib) = if bg_pattern == 0 {
                 (fg_pattern, fg_attrib)
             } else if fg_pattern == 0 {
@@ -886,10 +1224,42 @@ impl Ppu {
                 }
             };
 
-            out = Some((self.dot - 1, self.scanline, pattern, attrib));
+            out = Some((x, self.scanline, pattern, attrib));
+        }
+
+        // the sprite block above already hid the background
+        if !self.is_background_shown && sprite.is_none() {
+            out = out.map(|(x, y, _, _)| (x, y, 0, 0));
+        }
+
+        // with rendering disabled the backdrop color is shown, unless v
+        // points into the palette, then that color is shown instead
+        // https://www.nesdev.org/wiki/PPU_palettes#The_background_palette_hack
+        if self.is_video_enabled {
+            let raw = match out {
+                Some((_, _, pattern, attrib)) => self.get_raw_pixel(pattern, attrib),
+                None if self.vram_address & 0x3F00 == 0x3F00 => {
+                    self.apply_mask(self.pallet_memory.read_address(self.vram_address))
+                }
+                None => self.get_raw_pixel(0, 0),
+            };
+            self.frame
+                .set_pixel(x as usize, self.scanline as usize, raw);
         }
+        out
+    }
 
-        if enabled_rendering && self.scanline == 261 && self.dot == 339 && self.is_odd_frame {
+    /// Moves on to the next dot, skipping the last dot of the pre-render
+    /// line on odd ntsc frames, and finishes the frame once the visible
+    /// lines are done
+    fn advance_dot(&mut self, enabled_rendering: bool) {
+        let pre_render_line = self.region.pre_render_line();
+        if enabled_rendering
+            && self.region.has_odd_frame_skip()
+            && self.scanline == pre_render_line
+            && self.dot == 339
+            && self.is_odd_frame
+        {
             self.dot = 0;
             self.scanline = 0;
             self.is_odd_frame = !self.is_odd_frame;
@@ -897,7 +1267,7 @@ impl Ppu {
             self.dot += 1;
             if self.dot > 340 {
                 self.scanline += 1;
-                if self.scanline > 261 {
+                if self.scanline > pre_render_line {
                     self.scanline = 0;
                     self.is_odd_frame = !self.is_odd_frame;
                 }
@@ -905,7 +1275,236 @@ impl Ppu {
             }
         }
 
-        out
+        // scanline 240 is the first one after the visible ones
+        // https://www.nesdev.org/wiki/PPU_rendering#Post-render_scanline_(240)
+        if self.scanline == 240 && self.dot == 0 {
+            if self.is_video_enabled {
+                self.finish_frame();
+            }
+            self.is_frame_ready = true;
+            self.frame_count += 1;
+        }
+    }
+
+    fn finish_frame(&mut self) {
+        let Some(renderer) = self.async_renderer.as_mut() else {
+            renderer::render(&self.frame, &mut self.last_frame);
+            return;
+        };
+        let next = self.spare_raw_frame.take().unwrap_or_default();
+        let raw = std::mem::replace(&mut self.frame, next);
+        if let Some((raw, frame)) = renderer.submit(raw) {
+            self.spare_raw_frame = Some(raw);
+            renderer.recycle(std::mem::replace(&mut self.last_frame, frame));
+        }
+    }
+
+    /// With [RenderMode::Async] the colors of a finished frame are looked
+    /// up on a worker thread, so [Ppu::get_last_frame] lags one frame
+    /// behind. Switching modes starts over from the next finished frame.
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        if mode == self.get_render_mode() {
+            return;
+        }
+        self.async_renderer = match mode {
+            RenderMode::Inline => None,
+            RenderMode::Async => Some(AsyncRenderer::new()),
+        };
+        self.spare_raw_frame = None;
+    }
+
+    pub fn get_render_mode(&self) -> RenderMode {
+        if self.async_renderer.is_some() {
+            RenderMode::Async
+        } else {
+            RenderMode::Inline
+        }
+    }
+
+    /// Switches between the dot by dot renderer and the scanline one, see
+    /// [PpuAccuracy]. It can be switched at any time, the change shows
+    /// from the next line on.
+    pub fn set_accuracy(&mut self, accuracy: PpuAccuracy) {
+        self.accuracy = accuracy;
+    }
+
+    pub fn get_accuracy(&self) -> PpuAccuracy {
+        self.accuracy
+    }
+
+    /// With video off the ppu still fetches tiles, evaluates sprites, sets
+    /// the sprite 0 hit and fires nmis so the game runs exactly the same,
+    /// but no pixels get drawn and [Ppu::get_last_frame] keeps the last
+    /// frame drawn while video was on. For headless runs that never look
+    /// at the screen.
+    pub fn set_video_enabled(&mut self, enabled: bool) {
+        self.is_video_enabled = enabled;
+    }
+
+    pub fn is_video_enabled(&self) -> bool {
+        self.is_video_enabled
+    }
+
+    /// Hides a layer for finding out which one a glitch is on. Unlike
+    /// turning it off in PPUMASK the layer is only dropped when the
+    /// pixels are mixed, so sprite 0 hits and the game don't change.
+    pub fn set_layer_shown(&mut self, layer: Layer, shown: bool) {
+        match layer {
+            Layer::Background => self.is_background_shown = shown,
+            Layer::Sprites => self.are_sprites_shown = shown,
+        }
+    }
+
+    pub fn is_layer_shown(&self, layer: Layer) -> bool {
+        match layer {
+            Layer::Background => self.is_background_shown,
+            Layer::Sprites => self.are_sprites_shown,
+        }
+    }
+
+    /// Draws every sprite on a line instead of the first 8, which gets rid
+    /// of the flicker games use to show more. Only what gets drawn
+    /// changes: the sprite overflow flag, the sprite 0 hit and the bus
+    /// fetches are the same as with the limit, so games run the same.
+    /// Games that hide things behind 8 other sprites on purpose (like
+    /// Zelda's doorways) show them.
+    pub fn set_sprite_limit_removed(&mut self, removed: bool) {
+        self.is_sprite_limit_removed = removed;
+    }
+
+    pub fn is_sprite_limit_removed(&self) -> bool {
+        self.is_sprite_limit_removed
+    }
+
+    /// Changes the frame layout, only [Nes::power_cycle](crate::Nes::power_cycle)
+    /// should call this
+    pub(crate) fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
+
+    /// The last frame that was fully drawn
+    pub fn get_last_frame(&self) -> &Frame {
+        &self.last_frame
+    }
+
+    /// Used to draw over the finished frame, see [osd](crate::osd)
+    pub(crate) fn get_last_frame_mut(&mut self) -> &mut Frame {
+        &mut self.last_frame
+    }
+
+    /// Returns true once after every frame that gets finished
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.is_frame_ready)
+    }
+
+    /// Turns a pixel returned by [Ppu::tick] into its color (0xRRGGBB),
+    /// applying the grayscale and color emphasis bits of PPUMASK
+    /// https://www.nesdev.org/wiki/PPU_registers#Color_control
+    pub fn get_output_color(&self, pattern: u8, attrib: u8) -> u32 {
+        renderer::raw_color(self.get_raw_pixel(pattern, attrib))
+    }
+
+    /// The palette color of a pixel with the grayscale bit applied and
+    /// the emphasis bits on top, see [RawFrame]
+    fn get_raw_pixel(&self, pattern: u8, attrib: u8) -> u16 {
+        let color_id = if pattern == 0 {
+            self.pallet_memory.read_address(0)
+        } else {
+            self.pallet_memory.read_index(attrib as u16, pattern as u16)
+        };
+        self.apply_mask(color_id)
+    }
+
+    fn apply_mask(&self, mut color_id: u8) -> u16 {
+        if self.mask_register.get_flag_enabled(mask_flags::GRAYSCALE) {
+            color_id &= 0x30;
+        }
+        let emphasis = (self.mask_register >> 5) & 0x07;
+        (color_id & 0x3F) as u16 | (emphasis as u16) << 6
+    }
+
+    /// Returns `(scanline, dot)` of the next pixel the ppu will render
+    pub fn get_position(&self) -> (u32, u32) {
+        (self.scanline, self.dot)
+    }
+
+    pub fn get_registers(&self) -> PpuRegisters {
+        PpuRegisters {
+            control: self.control_register,
+            mask: self.mask_register,
+            status: self.status_register,
+            oam_address: self.oam_address_register,
+            vram_address: self.vram_address,
+            temp_vram_address: self.temp_vram_address,
+            fine_x: self.fine_x,
+        }
+    }
+
+    /// The background tile drawn at `x`, `y` of the last frame, for
+    /// showing what is under the mouse. The scroll is the one the line
+    /// started with, so splits done mid line aren't seen. Lines drawn
+    /// with rendering off keep the scroll of the last frame they were on.
+    pub fn tile_info_at(&self, x: usize, y: usize) -> TileInfo {
+        let (v, fine_x) = self.line_scroll[y.min(SCREEN_HEIGHT - 1)];
+        // v is two tiles ahead, the first two tiles of a line are
+        // fetched at the end of the line before
+        let column = v.get_bitfield(BASE_NAMETABLE_ADDRESS_X) as usize * 32
+            + v.get_bitfield(COARSE_X) as usize
+            + 64
+            - 2
+            + (fine_x as usize + x) / 8;
+        let nametable_x = (column / 32) as u16 % 2;
+        let coarse_x = (column % 32) as u16;
+        let coarse_y = v.get_bitfield(COARSE_Y);
+        let nametable = nametable_x | v.get_bitfield(BASE_NAMETABLE_ADDRESS_Y) << 1;
+
+        let nametable_address = 0x2000 | nametable << 10 | coarse_y << 5 | coarse_x;
+        let tile_index = self.read_ppu_bus(nametable_address);
+        let attribute_address = 0x23C0 | nametable << 10 | (coarse_y >> 2) << 3 | coarse_x >> 2;
+        let shift = (coarse_y & 2) << 1 | (coarse_x & 2);
+        let palette = (self.read_ppu_bus(attribute_address) >> shift) & 0x03;
+        let pattern_table = if self
+            .control_register
+            .get_flag_enabled(control_flags::BG_PATTERN_TABLE_ADDR)
+        {
+            0x1000
+        } else {
+            0
+        };
+
+        TileInfo {
+            nametable_address,
+            tile_index,
+            attribute_address,
+            palette,
+            pattern_address: pattern_table + tile_index as u16 * 16,
+            pixel_x: ((fine_x as usize + x) % 8) as u8,
+            pixel_y: v.get_bitfield(FINE_Y) as u8,
+        }
+    }
+
+    /// All four nametables as they are stored, mappers without four
+    /// screen vram only use the first two
+    pub(crate) fn get_nametable_memory(&self) -> &[u8] {
+        &self.nametable_memory
+    }
+
+    pub(crate) fn get_nametable_memory_mut(&mut self) -> &mut [u8] {
+        &mut self.nametable_memory
+    }
+
+    /// A line of the frame being drawn right now, see [RawFrame]
+    pub(crate) fn get_drawing_line_mut(&mut self, line: usize) -> &mut [u16] {
+        self.frame.line_mut(line)
+    }
+
+    /// How many frames were finished since power on
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
     }
 
     pub fn get_pixel_color(&self, i: usize, j: usize) -> u32 {
@@ -960,7 +1559,11 @@ impl Ppu {
     fn read_cartrige(&self, address: u16) -> u8 {
         self.cartrige
             .as_ref()
-            .map(|c| c.borrow_mut().read(CartrigeAccess::PpuAccess { address }))
+            .map(|c| {
+                c.lock()
+                    .unwrap()
+                    .read(CartrigeAccess::PpuAccess { address })
+            })
             .flatten()
             .unwrap_or(0)
     }
@@ -984,7 +1587,7 @@ impl Ppu {
                 .cartrige
                 .as_ref()
                 .map(|c| {
-                    c.borrow_mut().read(CartrigeAccess::PpuAccess {
+                    c.lock().unwrap().read(CartrigeAccess::PpuAccess {
                         address: first_byte_address,
                     })
                 })
@@ -995,7 +1598,7 @@ impl Ppu {
                 .cartrige
                 .as_ref()
                 .map(|c| {
-                    c.borrow_mut().read(CartrigeAccess::PpuAccess {
+                    c.lock().unwrap().read(CartrigeAccess::PpuAccess {
                         address: second_byte_address,
                     })
                 })
@@ -1016,7 +1619,246 @@ impl Ppu {
     fn map_nametable_address(&self, address: u16) -> u16 {
         self.cartrige
             .as_ref()
-            .map(|c| c.borrow().map_nametable(address))
+            .map(|c| c.lock().unwrap().map_nametable(address))
             .unwrap_or_else(|| address)
     }
 }
+
+impl SaveState for Sprite {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.y);
+        writer.write_u8(self.tile_id);
+        writer.write_u8(self.attributes);
+        writer.write_u8(self.x);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.y = reader.read_u8()?;
+        self.tile_id = reader.read_u8()?;
+        self.attributes = reader.read_u8()?;
+        self.x = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for SpriteEvaluation {
+    fn save_state(&self, writer: &mut StateWriter) {
+        // every variant has at most 2 fields so they all get written as
+        // tag + fetched_byte + transfer_byte_count
+        let (tag, fetched_byte, transfer_byte_count) = match *self {
+            SpriteEvaluation::Read => (0, 0, 0),
+            SpriteEvaluation::Write { fetched_byte } => (1, fetched_byte, 0),
+            SpriteEvaluation::TransferRead {
+                transfer_byte_count,
+            } => (2, 0, transfer_byte_count),
+            SpriteEvaluation::TransferWrite {
+                fetched_byte,
+                transfer_byte_count,
+            } => (3, fetched_byte, transfer_byte_count),
+            SpriteEvaluation::OverflowRead => (4, 0, 0),
+            SpriteEvaluation::OverflowWrite { fetched_byte } => (5, fetched_byte, 0),
+            SpriteEvaluation::OverflowTransferRead {
+                transfer_byte_count,
+            } => (6, 0, transfer_byte_count),
+            SpriteEvaluation::OverflowTransferWrite {
+                fetched_byte,
+                transfer_byte_count,
+            } => (7, fetched_byte, transfer_byte_count),
+            SpriteEvaluation::WaitingHBlankRead => (8, 0, 0),
+            SpriteEvaluation::WaitingHBlankWrite { fetched_byte } => (9, fetched_byte, 0),
+        };
+        writer.write_u8(tag);
+        writer.write_u8(fetched_byte);
+        writer.write_u8(transfer_byte_count);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        let tag = reader.read_u8()?;
+        let fetched_byte = reader.read_u8()?;
+        let transfer_byte_count = reader.read_u8()?;
+        if matches!(tag, 2 | 3 | 6 | 7) && !(1..=3).contains(&transfer_byte_count) {
+            return Err(SaveStateError::InvalidValueError(
+                "sprite evaluation transfer count",
+                transfer_byte_count as u64,
+            ));
+        }
+
+        *self = match tag {
+            0 => SpriteEvaluation::Read,
+            1 => SpriteEvaluation::Write { fetched_byte },
+            2 => SpriteEvaluation::TransferRead {
+                transfer_byte_count,
+            },
+            3 => SpriteEvaluation::TransferWrite {
+                fetched_byte,
+                transfer_byte_count,
+            },
+            4 => SpriteEvaluation::OverflowRead,
+            5 => SpriteEvaluation::OverflowWrite { fetched_byte },
+            6 => SpriteEvaluation::OverflowTransferRead {
+                transfer_byte_count,
+            },
+            7 => SpriteEvaluation::OverflowTransferWrite {
+                fetched_byte,
+                transfer_byte_count,
+            },
+            8 => SpriteEvaluation::WaitingHBlankRead,
+            9 => SpriteEvaluation::WaitingHBlankWrite { fetched_byte },
+            other => {
+                return Err(SaveStateError::InvalidValueError(
+                    "sprite evaluation",
+                    other as u64,
+                ));
+            }
+        };
+        Ok(())
+    }
+}
+
+impl SaveState for SpriteRenderingState {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match self {
+            SpriteRenderingState::Idle => writer.write_u8(0),
+            SpriteRenderingState::Initializing => writer.write_u8(1),
+            SpriteRenderingState::Evaluating {
+                eval_state,
+                temp_oam_address,
+            } => {
+                writer.write_u8(2);
+                eval_state.save_state(writer);
+                writer.write_u8(*temp_oam_address);
+            }
+            SpriteRenderingState::Fetching {
+                temp_oam_address,
+                temp_sprite,
+                temp_fetch_addr,
+            } => {
+                writer.write_u8(3);
+                writer.write_u8(*temp_oam_address);
+                temp_sprite.save_state(writer);
+                writer.write_u16(*temp_fetch_addr);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        let read_temp_oam_address = |reader: &mut StateReader| {
+            let address = reader.read_u8()?;
+            if address as usize >= TEMP_OAM_SIZE {
+                return Err(SaveStateError::InvalidValueError(
+                    "temp oam address",
+                    address as u64,
+                ));
+            }
+            Ok(address)
+        };
+
+        *self = match reader.read_u8()? {
+            0 => SpriteRenderingState::Idle,
+            1 => SpriteRenderingState::Initializing,
+            2 => {
+                let mut eval_state = SpriteEvaluation::Read;
+                eval_state.load_state(reader)?;
+                SpriteRenderingState::Evaluating {
+                    eval_state,
+                    temp_oam_address: read_temp_oam_address(reader)?,
+                }
+            }
+            3 => {
+                let temp_oam_address = read_temp_oam_address(reader)?;
+                let mut temp_sprite = Sprite::default();
+                temp_sprite.load_state(reader)?;
+                SpriteRenderingState::Fetching {
+                    temp_oam_address,
+                    temp_sprite,
+                    temp_fetch_addr: reader.read_u16()?,
+                }
+            }
+            other => {
+                return Err(SaveStateError::InvalidValueError(
+                    "sprite rendering state",
+                    other as u64,
+                ));
+            }
+        };
+        Ok(())
+    }
+}
+
+impl SaveState for Ppu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.scanline);
+        writer.write_u32(self.dot);
+        self.pallet_memory.save_state(writer);
+        writer.write_bytes(&self.nametable_memory);
+        writer.write_u8(self.open_bus);
+        writer.write_u16(self.vram_address);
+        writer.write_u16(self.temp_vram_address);
+        writer.write_u8(self.fine_x);
+        writer.write_bool(self.is_writing_low_byte);
+        writer.write_u8(self.ppu_data_read_buffer);
+        writer.write_u8(self.control_register);
+        writer.write_u8(self.mask_register);
+        writer.write_u8(self.status_register);
+        writer.write_u8(self.oam_address_register);
+        writer.write_bytes(&self.oam);
+        writer.write_bytes(&self.temp_oam);
+        writer.write_u8(self.renderer_sprite_id);
+        writer.write_u8(self.renderer_attribute_lsb);
+        writer.write_u8(self.renderer_attribute_msb);
+        writer.write_u8(self.renderer_pattern_msb);
+        writer.write_u8(self.renderer_pattern_lsb);
+        writer.write_u16(self.renderer_shift_pattern_msb);
+        writer.write_u16(self.renderer_shift_pattern_lsb);
+        writer.write_u16(self.renderer_shift_attribute_lsb);
+        writer.write_u16(self.renderer_shift_attribute_msb);
+        self.renderer_sprite_state.save_state(writer);
+        writer.write_bytes(&self.renderer_sprite_shift_lsb);
+        writer.write_bytes(&self.renderer_sprite_shift_msb);
+        writer.write_bytes(&self.renderer_sprite_x_counter);
+        writer.write_bytes(&self.renderer_sprite_attributes);
+        writer.write_bytes(&self.renderer_sprite_orig_indexes);
+        writer.write_bool(self.is_odd_frame);
+        writer.write_u64(self.frame_count);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.scanline = reader.read_u32_in("scanline", 0..=self.region.pre_render_line())?;
+        self.dot = reader.read_u32_in("dot", 0..=340)?;
+        self.pallet_memory.load_state(reader)?;
+        reader.read_bytes_into("nametable memory", &mut self.nametable_memory)?;
+        self.open_bus = reader.read_u8()?;
+        self.vram_address = reader.read_u16()?;
+        self.temp_vram_address = reader.read_u16()?;
+        self.fine_x = reader.read_u8()? & 0b111;
+        self.is_writing_low_byte = reader.read_bool()?;
+        self.ppu_data_read_buffer = reader.read_u8()?;
+        self.control_register = reader.read_u8()?;
+        self.mask_register = reader.read_u8()?;
+        self.status_register = reader.read_u8()?;
+        self.oam_address_register = reader.read_u8()?;
+        reader.read_bytes_into("oam", &mut self.oam)?;
+        reader.read_bytes_into("temp oam", &mut self.temp_oam)?;
+        self.renderer_sprite_id = reader.read_u8()?;
+        self.renderer_attribute_lsb = reader.read_u8()?;
+        self.renderer_attribute_msb = reader.read_u8()?;
+        self.renderer_pattern_msb = reader.read_u8()?;
+        self.renderer_pattern_lsb = reader.read_u8()?;
+        self.renderer_shift_pattern_msb = reader.read_u16()?;
+        self.renderer_shift_pattern_lsb = reader.read_u16()?;
+        self.renderer_shift_attribute_lsb = reader.read_u16()?;
+        self.renderer_shift_attribute_msb = reader.read_u16()?;
+        self.renderer_sprite_state.load_state(reader)?;
+        reader.read_bytes_into("sprite shift lsb", &mut self.renderer_sprite_shift_lsb)?;
+        reader.read_bytes_into("sprite shift msb", &mut self.renderer_sprite_shift_msb)?;
+        reader.read_bytes_into("sprite x counter", &mut self.renderer_sprite_x_counter)?;
+        reader.read_bytes_into("sprite attributes", &mut self.renderer_sprite_attributes)?;
+        reader.read_bytes_into(
+            "sprite original indexes",
+            &mut self.renderer_sprite_orig_indexes,
+        )?;
+        self.is_odd_frame = reader.read_bool()?;
+        self.frame_count = reader.read_u64()?;
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use std::fmt::Debug;
 
-use crate::hardware::constants::ppu::PALLET_SIZE;
+use crate::{
+    hardware::constants::ppu::PALLET_SIZE,
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
 
 /// implementation of collor pallets from:
 /// https://www.nesdev.org/wiki/PPU_palettes
@@ -30,6 +33,14 @@ impl PalletMemory {
         self.pallet_memory[Self::map_pallet_address(address)] = value
     }
 
+    pub(crate) fn get_bytes(&self) -> &[u8] {
+        &self.pallet_memory
+    }
+
+    pub(crate) fn get_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.pallet_memory
+    }
+
     fn map_pallet_address(address: u16) -> usize {
         match (address as usize) % PALLET_SIZE {
             // pallet memory for color 0 is shared between sprites and background
@@ -39,3 +50,13 @@ impl PalletMemory {
         }
     }
 }
+
+impl SaveState for PalletMemory {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.pallet_memory);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        reader.read_bytes_into("pallet memory", &mut self.pallet_memory)
+    }
+}
@@ -0,0 +1,145 @@
+//! Turns the [RawFrame]s the ppu draws into colors. This is the only part
+//! of the ppu that can run on another thread: everything before it
+//! (fetching tiles, evaluating sprites, sprite 0 hits) can change what the
+//! cpu reads on the very next cycle, so it has to stay interleaved with
+//! the cpu. Looking up the colors can't, so with [RenderMode::Async] it
+//! happens on a worker thread while the next frame is emulated.
+
+use std::{
+    sync::{
+        LazyLock,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::hardware::{
+    constants::ppu::{COLORS, EMPHASIS_ATTENUATION},
+    ppu::frame::{Frame, RawFrame},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    /// Colors are looked up as soon as a frame is done
+    #[default]
+    Inline,
+    /// Colors are looked up on a worker thread, finished frames show up
+    /// one frame late
+    Async,
+}
+
+/// 64 colors times the 8 ways of setting the emphasis bits
+const RAW_COLORS: usize = 64 * 8;
+
+/// Every possible [RawFrame] pixel already turned into its color, so a
+/// frame is one lookup per pixel
+static COLOR_LUT: LazyLock<[u32; RAW_COLORS]> =
+    LazyLock::new(|| std::array::from_fn(|raw| compute_color(raw as u16)));
+
+/// The color of a [RawFrame] pixel (0xRRGGBB), the channels that aren't
+/// emphasized get dimmed
+/// https://www.nesdev.org/wiki/PPU_registers#Color_control
+pub fn raw_color(raw: u16) -> u32 {
+    COLOR_LUT[raw as usize % RAW_COLORS]
+}
+
+fn compute_color(raw: u16) -> u32 {
+    let color = COLORS[(raw & 0x3F) as usize];
+    let emphasis = (raw >> 6) & 0x07;
+    if emphasis == 0 {
+        return color;
+    }
+
+    let mut out = 0;
+    for i in 0..3 {
+        let shift = 16 - i * 8;
+        let mut channel = (color >> shift) & 0xFF;
+        if emphasis & (1 << i) == 0 {
+            channel = (channel as f32 * EMPHASIS_ATTENUATION) as u32;
+        }
+        out |= channel << shift;
+    }
+    out
+}
+
+pub fn render(raw: &RawFrame, frame: &mut Frame) {
+    let lut = &*COLOR_LUT;
+    for (pixel, raw) in frame.pixels_mut().iter_mut().zip(raw.pixels()) {
+        // the mask keeps the compiler from bounds checking every pixel
+        *pixel = lut[*raw as usize & (RAW_COLORS - 1)];
+    }
+}
+
+/// A worker thread rendering frames in the order they are submitted.
+/// The buffers get passed back and forth so nothing is allocated after
+/// the first couple of frames.
+pub struct AsyncRenderer {
+    to_worker: Option<Sender<(RawFrame, Frame)>>,
+    from_worker: Receiver<(RawFrame, Frame)>,
+    spare_frames: Vec<Frame>,
+    in_flight: usize,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl AsyncRenderer {
+    pub fn new() -> Self {
+        let (to_worker, worker_input) = mpsc::channel::<(RawFrame, Frame)>();
+        let (worker_output, from_worker) = mpsc::channel();
+        let worker = thread::Builder::new()
+            .name("scamu renderer".into())
+            .spawn(move || {
+                for (raw, mut frame) in worker_input {
+                    render(&raw, &mut frame);
+                    if worker_output.send((raw, frame)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn the renderer thread");
+        Self {
+            to_worker: Some(to_worker),
+            from_worker,
+            spare_frames: Vec::new(),
+            in_flight: 0,
+            worker: Some(worker),
+        }
+    }
+
+    /// Hands `raw` to the worker and returns the frame submitted before it
+    /// with the raw frame it was rendered from, for reuse. Returns `None`
+    /// for the very first frame.
+    pub fn submit(&mut self, raw: RawFrame) -> Option<(RawFrame, Frame)> {
+        let frame = self.spare_frames.pop().unwrap_or_default();
+        self.to_worker
+            .as_ref()
+            .expect("the worker only goes away on drop")
+            .send((raw, frame))
+            .expect("the renderer thread died");
+        self.in_flight += 1;
+        if self.in_flight < 2 {
+            return None;
+        }
+        self.in_flight -= 1;
+        Some(self.from_worker.recv().expect("the renderer thread died"))
+    }
+
+    /// Gives a frame back to be rendered into again
+    pub fn recycle(&mut self, frame: Frame) {
+        self.spare_frames.push(frame);
+    }
+}
+
+impl Default for AsyncRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for AsyncRenderer {
+    fn drop(&mut self) {
+        self.to_worker = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
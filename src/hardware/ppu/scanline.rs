@@ -0,0 +1,230 @@
+//! The fast renderer: instead of fetching, evaluating and drawing dot by
+//! dot, every line gets drawn in one go on dot 256 and the sprites of the
+//! next line get evaluated and fetched in one go on dot 257. Everything
+//! else is shared with the cycle renderer: the registers, vram, oam, the
+//! scroll (v, t and fine x), vblank, nmis and the odd frame skip still
+//! happen on their exact dot, so games run at the same speed and the
+//! save states are the same format.
+//!
+//! What doesn't work as on hardware with [PpuAccuracy::Scanline]:
+//! - writes to PPUSCROLL, PPUMASK or the palette in the middle of a line
+//!   show up on the next line instead of at the dot they were made
+//! - the sprite 0 hit is set on dot 256 of its line instead of on the dot
+//!   of the pixel, games that poll it in a tight loop split a few pixels
+//!   lower
+//! - sprite overflow is set for a 9th sprite on the line, without the
+//!   hardware's diagonal oam reads bug
+//! - v doesn't move during the line, so reading PPUDATA while rendering
+//!   and mappers that watch the ppu address bus on every dot see
+//!   something different. The cartrige still gets shown a background,
+//!   a sprite and a prefetch address every line on the dots the cycle
+//!   renderer fetches them, so scanline counters like the MMC3 one still
+//!   count the same lines.
+//!
+//! Most games never do any of this, the ppu test roms and games with mid
+//! line raster effects need [PpuAccuracy::Cycle].
+
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::{
+    bit_ops::BitOps,
+    constants::ppu::{
+        SCREEN_HEIGHT, SCREEN_WIDTH,
+        control_flags::SPRITE_SIZE,
+        mask_flags,
+        status_flags::SPRITE_OVERFLOW,
+        vram_sections::{FINE_Y, NAMETABLE_OFFSET},
+    },
+    ppu::{Ppu, Sprite},
+};
+
+/// Tiles fetched for a line, the 33rd one is there for fine x scrolling
+const LINE_TILES: usize = SCREEN_WIDTH / 8 + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PpuAccuracy {
+    /// Dot by dot like the hardware, passes the ppu test roms
+    #[default]
+    Cycle,
+    /// A line at a time, for weak machines and batch jobs, see the
+    /// [module docs](self) for what breaks
+    Scanline,
+}
+
+impl Ppu {
+    pub(super) fn tick_scanline(&mut self) {
+        let enabled_background_rendering = self
+            .mask_register
+            .get_flag_enabled(mask_flags::ENABLE_BG_RENDERING);
+        let enabled_sprite_rendering = self
+            .mask_register
+            .get_flag_enabled(mask_flags::ENABLE_SPRITE_RENDERING);
+        let enabled_rendering = enabled_background_rendering || enabled_sprite_rendering;
+        let is_visible = self.scanline < SCREEN_HEIGHT as u32;
+        let pre_render_line = self.region.pre_render_line();
+        let is_fetching = is_visible || self.scanline == pre_render_line;
+
+        if enabled_rendering {
+            if self.dot == 1 && is_visible {
+                // the cycle renderer has the first two tiles fetched by now,
+                // stored the same so [Ppu::tile_info_at] works with both
+                let drawn_from = self.vram_address;
+                self.increment_coarse_x();
+                self.increment_coarse_x();
+                self.line_scroll[self.scanline as usize] = (self.vram_address, self.fine_x);
+                self.vram_address = drawn_from;
+            }
+            if self.dot == 256 && is_fetching {
+                self.render_line(
+                    is_visible,
+                    enabled_background_rendering,
+                    enabled_sprite_rendering,
+                );
+            }
+            if self.dot == 257 && is_fetching {
+                self.copy_horizontal_scroll();
+            }
+            if self.dot == 257 && is_visible {
+                self.evaluate_sprites();
+            }
+            if self.dot == 257 {
+                self.evaluate_extra_sprites();
+            }
+            if is_fetching {
+                self.drive_line_fetches();
+            }
+        } else if self.dot == 256 && is_visible {
+            for x in 0..SCREEN_WIDTH as u32 {
+                self.output_pixel(x, None, None);
+            }
+        }
+
+        self.update_vblank();
+        if enabled_rendering && self.scanline == pre_render_line && self.dot == 280 {
+            self.copy_vertical_scroll();
+        }
+        self.advance_dot(enabled_rendering);
+    }
+
+    /// Fetches the background of the line, draws it if `draw` and moves v
+    /// down a line
+    fn render_line(&mut self, draw: bool, background: bool, sprites: bool) {
+        // (pattern low byte, pattern high byte, palette)
+        let mut tiles = [(0u8, 0u8, 0u8); LINE_TILES];
+        for tile in tiles.iter_mut() {
+            let tile_id =
+                self.read_ppu_bus(0x2000 | self.vram_address.get_bitfield(NAMETABLE_OFFSET));
+            let palette = self.fetch_attribute();
+            let address = self.get_background_pattern_address()
+                + tile_id as u16 * 16
+                + self.vram_address.get_bitfield(FINE_Y);
+            *tile = (
+                self.read_ppu_bus(address),
+                self.read_ppu_bus(address + 8),
+                palette,
+            );
+            self.increment_coarse_x();
+        }
+        self.increment_y();
+        if !draw {
+            return;
+        }
+
+        for x in 0..SCREEN_WIDTH {
+            let background = background.then(|| {
+                let mut position = x + self.fine_x as usize;
+                // the cycle renderer reloads its shifters a dot early, which
+                // drops the last column of the second tile. Matched here so
+                // both draw the same picture.
+                if position >= 15 {
+                    position += 1;
+                }
+                let (lsb, msb, palette) = tiles[position / 8];
+                let shift = 7 - position % 8;
+                let pattern = ((msb >> shift) & 1) << 1 | ((lsb >> shift) & 1);
+                (pattern, palette)
+            });
+            let sprite = sprites.then(|| {
+                (0..8)
+                    .find_map(|sprite_idx| {
+                        // the same as the cycle renderer's x counters and
+                        // shifters
+                        let column = (x + 1)
+                            .checked_sub(self.renderer_sprite_x_counter[sprite_idx] as usize)
+                            .filter(|column| *column < 8)?;
+                        self.sprite_pixel(
+                            sprite_idx,
+                            self.renderer_sprite_shift_lsb[sprite_idx] << column,
+                            self.renderer_sprite_shift_msb[sprite_idx] << column,
+                        )
+                    })
+                    .or_else(|| self.extra_sprite_pixel(x))
+                    .unwrap_or_default()
+            });
+            self.output_pixel(x as u32, background, sprite);
+        }
+    }
+
+    /// What the cartrige sees of a line's fetches, one address for each run
+    /// of them: the background, the garbage nametable fetches, the sprites
+    /// and the next line's first tiles
+    fn drive_line_fetches(&self) {
+        match self.dot {
+            256 | 326 => self.drive_bus(self.get_background_pattern_address()),
+            257 | 321 => self.drive_bus(0x2000),
+            261 => {
+                // pre-render lines fetch the empty sprites
+                let tile_id = if self.scanline == self.region.pre_render_line() {
+                    0xFF
+                } else {
+                    self.temp_oam[1]
+                };
+                self.drive_bus(self.sprite_pattern_table(tile_id));
+            }
+            _ => {}
+        }
+    }
+
+    /// Finds the sprites of the next line and fetches their patterns
+    fn evaluate_sprites(&mut self) {
+        let height = if self.control_register.get_flag_enabled(SPRITE_SIZE) {
+            16
+        } else {
+            8
+        };
+        self.temp_oam.fill(0xFF);
+        let mut found = 0;
+        for index in 0..64 {
+            let sprite = &self.oam[index * 4..index * 4 + 4];
+            if (self.scanline as u8).wrapping_sub(sprite[0]) >= height {
+                continue;
+            }
+            if found == 8 {
+                self.status_register.set_flag_enabled(SPRITE_OVERFLOW, true);
+                break;
+            }
+            self.temp_oam[found * 4..found * 4 + 4].copy_from_slice(sprite);
+            self.renderer_sprite_orig_indexes[found] = index as u8;
+            found += 1;
+        }
+
+        self.oam_address_register = 0;
+        for sprite_idx in 0..8 {
+            let bytes = &self.temp_oam[sprite_idx * 4..sprite_idx * 4 + 4];
+            let sprite = Sprite {
+                y: bytes[0],
+                tile_id: bytes[1],
+                attributes: bytes[2],
+                x: bytes[3],
+            };
+            self.renderer_sprite_attributes[sprite_idx] = sprite.attributes;
+            self.renderer_sprite_x_counter[sprite_idx] = sprite.x;
+            let address = self.sprite_pattern_address(&sprite);
+            self.renderer_sprite_shift_lsb[sprite_idx] =
+                self.fetch_sprite_pattern(&sprite, address);
+            self.renderer_sprite_shift_msb[sprite_idx] =
+                self.fetch_sprite_pattern(&sprite, address + 8);
+        }
+    }
+}
@@ -1,3 +1,55 @@
+//! # scamu
+//!
+//! A nes emulator library. Most programs only need [Nes] and
+//! [Cartrige], which can be imported with `use scamu::prelude::*`:
+//!
+//! ```no_run
+//! use scamu::prelude::*;
+//!
+//! let cartrige = Cartrige::from_file("game.nes").unwrap();
+//! let mut nes = Nes::new_with_cartrige(cartrige);
+//! nes.reset();
+//!
+//! let mut frame = Frame::new();
+//! nes.set_button(0, Button::Start, true);
+//! nes.run_frame(&mut frame);
+//! ```
+//!
+//! The individual components live in [hardware] for anything that needs
+//! to poke at them directly.
+
+#[cfg(feature = "achievements")]
+pub mod achievements;
+pub mod debugger;
 pub mod devices;
+pub mod frontend;
 pub mod hardware;
+pub mod osd;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod save_state;
 mod test;
+pub mod trace;
+
+pub use devices::{
+    machine::Machine,
+    nes::Nes,
+    run::{BreakReason, RunSummary},
+};
+pub use hardware::{
+    cartrige::{Cartrige, Header, Mirroring, RomInfo, TvSystem},
+    cpu::{Cpu, CpuRegisters},
+    cpu_bus::CpuBus,
+    input::{
+        Device, Port,
+        controller::{Button, ControllerState},
+    },
+    ppu::frame::Frame,
+};
+
+/// The types needed to run a game
+pub mod prelude {
+    pub use crate::{
+        BreakReason, Button, Cartrige, ControllerState, Frame, Nes, RomInfo, RunSummary,
+    };
+}
@@ -0,0 +1,89 @@
+use crate::{
+    hardware::{
+        constants::ppu::SCREEN_HEIGHT,
+        input::controller::{Button, ControllerState},
+        ppu::frame::Frame,
+    },
+    osd::{self, BACKGROUND_COLOR, DIM_COLOR, TEXT_COLOR},
+};
+
+/// Enough for a four score
+pub const MAX_CONTROLLERS: usize = 4;
+
+const CONTROLLER_WIDTH: usize = 31;
+const CONTROLLER_HEIGHT: usize = 11;
+const MARGIN: usize = 4;
+
+/// Where every button is drawn inside the controller, as (x, y, width, height)
+fn button_rect(button: Button) -> (usize, usize, usize, usize) {
+    match button {
+        Button::Up => (4, 1, 3, 3),
+        Button::Down => (4, 7, 3, 3),
+        Button::Left => (1, 4, 3, 3),
+        Button::Right => (7, 4, 3, 3),
+        Button::Select => (12, 6, 4, 2),
+        Button::Start => (17, 6, 4, 2),
+        Button::B => (23, 5, 3, 3),
+        Button::A => (27, 5, 3, 3),
+    }
+}
+
+/// Draws the buttons every controller is pressing in the bottom left
+/// corner of the frame, which is what speedrunners and TAS authors like
+/// having in their recordings. Every controller can be turned on and off
+/// on its own, they all start off.
+#[derive(Debug, Clone, Default)]
+pub struct InputDisplay {
+    enabled: [bool; MAX_CONTROLLERS],
+}
+
+impl InputDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_enabled(&mut self, controller_index: usize, enabled: bool) {
+        if let Some(is_enabled) = self.enabled.get_mut(controller_index) {
+            *is_enabled = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, controller_index: usize) -> bool {
+        self.enabled.get(controller_index).copied().unwrap_or(false)
+    }
+
+    pub fn is_any_enabled(&self) -> bool {
+        self.enabled.contains(&true)
+    }
+
+    /// `get_buttons` returns the pressed buttons of a controller. The
+    /// enabled controllers are drawn next to each other in order.
+    pub fn draw(&self, frame: &mut Frame, get_buttons: impl Fn(usize) -> ControllerState) {
+        let enabled = (0..MAX_CONTROLLERS).filter(|index| self.enabled[*index]);
+        for (slot, controller_index) in enabled.enumerate() {
+            let x = MARGIN + slot * (CONTROLLER_WIDTH + MARGIN);
+            let y = SCREEN_HEIGHT - CONTROLLER_HEIGHT - MARGIN;
+            Self::draw_controller(frame, x, y, get_buttons(controller_index));
+        }
+    }
+
+    fn draw_controller(frame: &mut Frame, x: usize, y: usize, buttons: ControllerState) {
+        osd::fill_rect(
+            frame,
+            x,
+            y,
+            CONTROLLER_WIDTH,
+            CONTROLLER_HEIGHT,
+            BACKGROUND_COLOR,
+        );
+        for button in Button::ALL {
+            let (button_x, button_y, width, height) = button_rect(button);
+            let color = if buttons.is_pressed(button) {
+                TEXT_COLOR
+            } else {
+                DIM_COLOR
+            };
+            osd::fill_rect(frame, x + button_x, y + button_y, width, height, color);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! # On screen display
+//!
+//! Things drawn over the frames after the ppu is done with them. They only
+//! change what gets shown, never what the game sees, so they don't end up
+//! in save states.
+
+pub mod input_display;
+pub mod notifications;
+pub mod overlay;
+pub mod performance;
+pub mod quick_menu;
+pub mod slot_picker;
+pub mod text;
+pub mod tile_inspector;
+
+use crate::hardware::ppu::frame::Frame;
+
+pub const TEXT_COLOR: u32 = 0xFFFFFF;
+pub const DIM_COLOR: u32 = 0x505050;
+pub const BACKGROUND_COLOR: u32 = 0x000000;
+
+/// Fills a rectangle, the parts outside of the frame are cut off
+pub fn fill_rect(frame: &mut Frame, x: usize, y: usize, width: usize, height: usize, color: u32) {
+    for y in y..y + height {
+        for x in x..x + width {
+            frame.set_pixel(x, y, color);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+
+use crate::{
+    hardware::{constants::clock_rates::FRAME_RATE, ppu::frame::Frame},
+    osd::{
+        self, BACKGROUND_COLOR, TEXT_COLOR,
+        text::{self, GLYPH_HEIGHT},
+    },
+};
+
+/// How long a notification stays on screen
+pub const NOTIFICATION_FRAMES: u32 = 3 * FRAME_RATE;
+/// Only the newest ones are shown, older ones wait their turn
+pub const MAX_VISIBLE: usize = 3;
+
+const PADDING: usize = 2;
+const MARGIN: usize = 4;
+
+/// Short messages stacked in the top left corner, like "achievement
+/// unlocked" or "state saved". Every call to [Notifications::draw] counts
+/// as one frame.
+#[derive(Debug, Clone, Default)]
+pub struct Notifications {
+    queue: VecDeque<(String, u32)>,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.queue.push_back((message.into(), NOTIFICATION_FRAMES));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// The messages on screen, oldest first
+    pub fn visible(&self) -> impl Iterator<Item = &str> {
+        self.queue
+            .iter()
+            .take(MAX_VISIBLE)
+            .map(|(message, _)| message.as_str())
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        for (index, (message, frames_left)) in self.queue.iter_mut().take(MAX_VISIBLE).enumerate() {
+            let y = MARGIN + index * (GLYPH_HEIGHT + 2 * PADDING + 1);
+            osd::fill_rect(
+                frame,
+                MARGIN,
+                y,
+                text::text_width(message) + 2 * PADDING,
+                GLYPH_HEIGHT + 2 * PADDING,
+                BACKGROUND_COLOR,
+            );
+            text::draw_text(frame, MARGIN + PADDING, y + PADDING, message, TEXT_COLOR);
+            *frames_left -= 1;
+        }
+        self.queue.retain(|(_, frames_left)| *frames_left > 0);
+    }
+}
@@ -0,0 +1,215 @@
+use serde::Deserialize;
+
+use crate::{
+    hardware::{
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        ppu::frame::Frame,
+    },
+    osd::text,
+};
+
+/// What a [Drawing] draws. Coordinates are in frame pixels (the 256x240
+/// the ppu outputs, before any scaling or overscan cropping) so they can be
+/// taken straight from a game's object positions in ram. They can be off
+/// the screen, only the visible parts get drawn.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Shape {
+    Pixel {
+        x: i32,
+        y: i32,
+    },
+    Line {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    Rect {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        /// only the outline is drawn if false
+        #[serde(default)]
+        filled: bool,
+    },
+    /// In the [osd font](text), with the top left corner at `x`, `y`
+    Text {
+        x: i32,
+        y: i32,
+        text: String,
+    },
+}
+
+/// A shape with its color as 0x00RRGGBB, blended over the frame with
+/// `alpha` (255 covers it)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Drawing {
+    #[serde(flatten)]
+    pub shape: Shape,
+    pub color: u32,
+    #[serde(default = "opaque")]
+    pub alpha: u8,
+}
+
+fn opaque() -> u8 {
+    u8::MAX
+}
+
+impl Drawing {
+    pub fn new(shape: Shape, color: u32) -> Self {
+        Self {
+            shape,
+            color,
+            alpha: opaque(),
+        }
+    }
+
+    pub fn with_alpha(self, alpha: u8) -> Self {
+        Self { alpha, ..self }
+    }
+}
+
+/// Lets scripts annotate the game, the way EmuLua's `gui` functions do.
+/// Drawings get queued up and are drawn over the next frame the ppu
+/// finishes, after that they're gone so scripts redraw every frame like
+/// they would in a frame callback.
+#[derive(Debug, Clone, Default)]
+pub struct Overlay {
+    drawings: Vec<Drawing>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, drawing: Drawing) {
+        self.drawings.push(drawing);
+    }
+
+    /// Drops the drawings that haven't been drawn yet
+    pub fn clear(&mut self) {
+        self.drawings.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drawings.is_empty()
+    }
+
+    /// Draws everything in the order it was pushed and empties the queue
+    pub fn composite(&mut self, frame: &mut Frame) {
+        for drawing in self.drawings.drain(..) {
+            draw(frame, &drawing);
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, drawing: &Drawing) {
+    let mut plot = |x: i32, y: i32| blend_pixel(frame, x, y, drawing.color, drawing.alpha);
+    match drawing.shape {
+        Shape::Pixel { x, y } => plot(x, y),
+        Shape::Line { x1, y1, x2, y2 } => {
+            let Some((x1, y1, x2, y2)) = clip_line(x1, y1, x2, y2) else {
+                return;
+            };
+            // bresenham, https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm
+            let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+            let (step_x, step_y) = ((x2 - x1).signum(), (y2 - y1).signum());
+            let (mut x, mut y, mut error) = (x1, y1, dx + dy);
+            loop {
+                plot(x, y);
+                if (x, y) == (x2, y2) {
+                    break;
+                }
+                if 2 * error >= dy {
+                    error += dy;
+                    x += step_x;
+                }
+                if 2 * error <= dx {
+                    error += dx;
+                    y += step_y;
+                }
+            }
+        }
+        Shape::Rect {
+            x,
+            y,
+            width,
+            height,
+            filled,
+        } => {
+            // clamped so huge rects don't loop over pixels nobody sees
+            let right = x.saturating_add(width as i32).min(SCREEN_WIDTH as i32);
+            let bottom = y.saturating_add(height as i32).min(SCREEN_HEIGHT as i32);
+            for pixel_y in y.max(0)..bottom {
+                for pixel_x in x.max(0)..right {
+                    let is_edge = pixel_x == x
+                        || pixel_y == y
+                        || pixel_x as i64 == x as i64 + width as i64 - 1
+                        || pixel_y as i64 == y as i64 + height as i64 - 1;
+                    if filled || is_edge {
+                        plot(pixel_x, pixel_y);
+                    }
+                }
+            }
+        }
+        Shape::Text { x, y, ref text } => {
+            for (pixel_x, pixel_y) in text::text_pixels(text) {
+                plot(x + pixel_x as i32, y + pixel_y as i32);
+            }
+        }
+    }
+}
+
+/// Cuts a line down to the part on the screen (Liang-Barsky), so lines
+/// from far off the screen don't walk over pixels nobody sees. `None` if
+/// none of it is on the screen.
+fn clip_line(x1: i32, y1: i32, x2: i32, y2: i32) -> Option<(i32, i32, i32, i32)> {
+    let (x, y) = (x1 as f64, y1 as f64);
+    let (dx, dy) = (x2 as f64 - x, y2 as f64 - y);
+    let (max_x, max_y) = ((SCREEN_WIDTH - 1) as f64, (SCREEN_HEIGHT - 1) as f64);
+    let (mut start, mut end) = (0.0f64, 1.0f64);
+    for (direction, distance) in [(-dx, x), (dx, max_x - x), (-dy, y), (dy, max_y - y)] {
+        if direction == 0.0 {
+            if distance < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = distance / direction;
+        if direction < 0.0 {
+            start = start.max(t);
+        } else {
+            end = end.min(t);
+        }
+    }
+    if start > end {
+        return None;
+    }
+    let point = |t: f64| ((x + t * dx).round() as i32, (y + t * dy).round() as i32);
+    let ((x1, y1), (x2, y2)) = (point(start), point(end));
+    Some((x1, y1, x2, y2))
+}
+
+fn blend_pixel(frame: &mut Frame, x: i32, y: i32, color: u32, alpha: u8) {
+    let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+        return;
+    };
+    if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+        return;
+    }
+    frame.set_pixel(x, y, blend(frame.get_pixel(x, y), color, alpha));
+}
+
+/// `over` on top of `under` with `alpha`, every channel on its own
+pub fn blend(under: u32, over: u32, alpha: u8) -> u32 {
+    let alpha = alpha as u32;
+    (0..3).fold(0, |out, channel| {
+        let shift = channel * 8;
+        let under = (under >> shift) & 0xFF;
+        let over = (over >> shift) & 0xFF;
+        out | ((over * alpha + under * (255 - alpha)) / 255) << shift
+    })
+}
@@ -0,0 +1,216 @@
+use std::{collections::VecDeque, io::Write, time::Duration};
+
+use crate::{
+    hardware::{constants::clock_rates::FRAME_RATE, ppu::frame::Frame},
+    osd::{self, BACKGROUND_COLOR, DIM_COLOR},
+};
+
+/// How many frames the statistics are kept for
+pub const HISTORY_SIZE: usize = 240;
+
+const GRAPH_HEIGHT: usize = 40;
+const MARGIN: usize = 4;
+const EMULATION_COLOR: u32 = 0x40C040;
+const PRESENT_COLOR: u32 = 0x4080E0;
+const AUDIO_COLOR: u32 = 0xE0C040;
+
+/// How long a single frame took, measured by the frontend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTiming {
+    /// Time spent running the nes
+    pub emulation: Duration,
+    /// Time spent getting the frame on screen
+    pub present: Duration,
+    /// See [Apu::get_queue_fill](crate::hardware::apu::Apu::get_queue_fill)
+    pub audio_fill: f32,
+}
+
+impl FrameTiming {
+    pub fn total(&self) -> Duration {
+        self.emulation + self.present
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerformanceSummary {
+    pub average_emulation: Duration,
+    pub average_present: Duration,
+    pub average_fps: f64,
+    /// The fps of the slowest 1% of frames
+    pub one_percent_low_fps: f64,
+    pub audio_fill: f32,
+}
+
+/// Keeps the timings of the last [HISTORY_SIZE] frames and can also dump
+/// every frame into a csv file for profiling
+#[derive(Default)]
+pub struct PerformanceStats {
+    timings: VecDeque<FrameTiming>,
+    frame: u64,
+    csv: Option<Box<dyn Write>>,
+}
+
+impl PerformanceStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, timing: FrameTiming) -> std::io::Result<()> {
+        if self.timings.len() >= HISTORY_SIZE {
+            self.timings.pop_front();
+        }
+        self.timings.push_back(timing);
+        self.frame += 1;
+
+        if let Some(csv) = self.csv.as_mut() {
+            writeln!(
+                csv,
+                "{},{},{},{:.3}",
+                self.frame,
+                timing.emulation.as_micros(),
+                timing.present.as_micros(),
+                timing.audio_fill
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Starts writing every recorded frame into `csv`, replacing the
+    /// previous csv output
+    pub fn start_csv(&mut self, mut csv: impl Write + 'static) -> std::io::Result<()> {
+        writeln!(csv, "frame,emulation_us,present_us,audio_fill")?;
+        self.csv = Some(Box::new(csv));
+        Ok(())
+    }
+
+    pub fn stop_csv(&mut self) -> std::io::Result<()> {
+        match self.csv.take() {
+            Some(mut csv) => csv.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_writing_csv(&self) -> bool {
+        self.csv.is_some()
+    }
+
+    pub fn timings(&self) -> impl Iterator<Item = &FrameTiming> {
+        self.timings.iter()
+    }
+
+    /// `None` until a frame gets recorded
+    pub fn summary(&self) -> Option<PerformanceSummary> {
+        let frames = self.timings.len() as u32;
+        if frames == 0 {
+            return None;
+        }
+
+        let average_emulation = self.timings.iter().map(|t| t.emulation).sum::<Duration>() / frames;
+        let average_present = self.timings.iter().map(|t| t.present).sum::<Duration>() / frames;
+
+        let mut totals: Vec<Duration> = self.timings.iter().map(FrameTiming::total).collect();
+        totals.sort_unstable_by(|a, b| b.cmp(a));
+        let slowest = &totals[..totals.len().div_ceil(100)];
+        let slowest_average = slowest.iter().sum::<Duration>() / slowest.len() as u32;
+
+        Some(PerformanceSummary {
+            average_emulation,
+            average_present,
+            average_fps: fps(average_emulation + average_present),
+            one_percent_low_fps: fps(slowest_average),
+            audio_fill: self.timings.back().map(|t| t.audio_fill).unwrap_or(0.0),
+        })
+    }
+}
+
+fn fps(frame_time: Duration) -> f64 {
+    if frame_time.is_zero() {
+        return f64::INFINITY;
+    }
+    1.0 / frame_time.as_secs_f64()
+}
+
+/// Draws a graph of the last frame times in the top left corner, every
+/// column is a frame with the emulation time stacked under the present
+/// time. The gray line is the time a frame has at 60 fps and the bar on
+/// the right is how full the audio queue is.
+#[derive(Debug, Clone, Default)]
+pub struct PerformanceHud {
+    is_enabled: bool,
+}
+
+impl PerformanceHud {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.is_enabled = enabled;
+    }
+
+    pub fn toggle(&mut self) {
+        self.is_enabled = !self.is_enabled;
+    }
+
+    pub fn draw(&self, frame: &mut Frame, stats: &PerformanceStats) {
+        if !self.is_enabled {
+            return;
+        }
+
+        osd::fill_rect(
+            frame,
+            MARGIN,
+            MARGIN,
+            HISTORY_SIZE + 3,
+            GRAPH_HEIGHT,
+            BACKGROUND_COLOR,
+        );
+
+        // the graph goes up to 2 frames worth of time
+        let frame_time = Duration::from_secs(1) / FRAME_RATE;
+        let to_height = |time: Duration| {
+            let height = time.as_secs_f64() / (frame_time * 2).as_secs_f64();
+            ((height * GRAPH_HEIGHT as f64) as usize).min(GRAPH_HEIGHT)
+        };
+        let bottom = MARGIN + GRAPH_HEIGHT;
+
+        for (column, timing) in stats.timings().enumerate() {
+            let x = MARGIN + column;
+            let emulation = to_height(timing.emulation);
+            let total = to_height(timing.total());
+            osd::fill_rect(frame, x, bottom - emulation, 1, emulation, EMULATION_COLOR);
+            osd::fill_rect(
+                frame,
+                x,
+                bottom - total,
+                1,
+                total - emulation,
+                PRESENT_COLOR,
+            );
+        }
+        osd::fill_rect(
+            frame,
+            MARGIN,
+            bottom - to_height(frame_time),
+            HISTORY_SIZE,
+            1,
+            DIM_COLOR,
+        );
+
+        if let Some(timing) = stats.timings().last() {
+            let audio = (timing.audio_fill.clamp(0.0, 1.0) * GRAPH_HEIGHT as f32) as usize;
+            osd::fill_rect(
+                frame,
+                MARGIN + HISTORY_SIZE + 1,
+                bottom - audio,
+                2,
+                audio,
+                AUDIO_COLOR,
+            );
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    hardware::{
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        ppu::frame::Frame,
+    },
+    osd::{
+        self, BACKGROUND_COLOR, DIM_COLOR, TEXT_COLOR,
+        text::{self, GLYPH_HEIGHT},
+    },
+};
+
+const PADDING: usize = 4;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 3;
+const TITLE: &str = "MENU";
+
+/// Something the quick menu can do, the frontend carries it out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    SaveState,
+    LoadState,
+    Reset,
+    PowerCycle,
+    TogglePause,
+    ToggleFilter,
+    /// Only for disk games, leave it out of the menu otherwise
+    SwapDiskSide,
+    /// Only for vs system games, leave it out of the menu otherwise
+    InsertCoin,
+}
+
+impl MenuAction {
+    /// Everything that works with any game
+    pub const DEFAULT: [MenuAction; 6] = [
+        MenuAction::SaveState,
+        MenuAction::LoadState,
+        MenuAction::Reset,
+        MenuAction::PowerCycle,
+        MenuAction::TogglePause,
+        MenuAction::ToggleFilter,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuAction::SaveState => "Save state",
+            MenuAction::LoadState => "Load state",
+            MenuAction::Reset => "Reset",
+            MenuAction::PowerCycle => "Power cycle",
+            MenuAction::TogglePause => "Pause",
+            MenuAction::ToggleFilter => "Toggle filter",
+            MenuAction::SwapDiskSide => "Swap disk side",
+            MenuAction::InsertCoin => "Insert coin",
+        }
+    }
+}
+
+/// A list of actions in the middle of the screen so nobody has to
+/// remember the hotkeys. The frontend opens it with its own key (Esc or
+/// F1 usually), moves the selection with the d-pad keys and does whatever
+/// [QuickMenu::confirm] returns.
+#[derive(Debug, Clone)]
+pub struct QuickMenu {
+    is_open: bool,
+    actions: Vec<MenuAction>,
+    selected: usize,
+}
+
+impl Default for QuickMenu {
+    fn default() -> Self {
+        Self::new(MenuAction::DEFAULT.to_vec())
+    }
+}
+
+impl QuickMenu {
+    pub fn new(actions: Vec<MenuAction>) -> Self {
+        Self {
+            is_open: false,
+            actions,
+            selected: 0,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    /// Opens with the first action selected
+    pub fn open(&mut self) {
+        self.is_open = true;
+        self.selected = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    pub fn toggle(&mut self) {
+        if self.is_open {
+            self.close();
+        } else {
+            self.open();
+        }
+    }
+
+    pub fn get_actions(&self) -> &[MenuAction] {
+        &self.actions
+    }
+
+    /// For adding [MenuAction::SwapDiskSide] or [MenuAction::InsertCoin]
+    /// when a game that has them gets loaded
+    pub fn set_actions(&mut self, actions: Vec<MenuAction>) {
+        self.actions = actions;
+        self.selected = 0;
+    }
+
+    pub fn selected(&self) -> Option<MenuAction> {
+        self.actions.get(self.selected).copied()
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.actions.is_empty() {
+            self.selected = (self.selected + 1) % self.actions.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.actions.is_empty() {
+            self.selected = (self.selected + self.actions.len() - 1) % self.actions.len();
+        }
+    }
+
+    /// Closes the menu and returns the selected action, `None` if the menu
+    /// wasn't open
+    pub fn confirm(&mut self) -> Option<MenuAction> {
+        if !self.is_open {
+            return None;
+        }
+        self.close();
+        self.selected()
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        if !self.is_open {
+            return;
+        }
+        let width = self
+            .actions
+            .iter()
+            .map(|action| text::text_width(action.label()))
+            .chain([text::text_width(TITLE)])
+            .max()
+            .unwrap_or(0)
+            + 2 * PADDING;
+        let height = (self.actions.len() + 1) * LINE_HEIGHT + 2 * PADDING;
+        let left = SCREEN_WIDTH.saturating_sub(width) / 2;
+        let top = SCREEN_HEIGHT.saturating_sub(height) / 2;
+        osd::fill_rect(frame, left, top, width, height, BACKGROUND_COLOR);
+        text::draw_text(frame, left + PADDING, top + PADDING, TITLE, DIM_COLOR);
+
+        for (index, action) in self.actions.iter().enumerate() {
+            let y = top + PADDING + (index + 1) * LINE_HEIGHT;
+            let color = if index == self.selected {
+                // the selected line is drawn inverted
+                osd::fill_rect(
+                    frame,
+                    left + 1,
+                    y - 1,
+                    width - 2,
+                    GLYPH_HEIGHT + 2,
+                    TEXT_COLOR,
+                );
+                BACKGROUND_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            text::draw_text(frame, left + PADDING, y, action.label(), color);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    frontend::slots::SLOT_COUNT,
+    hardware::{
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        ppu::frame::Frame,
+    },
+    osd::{self, BACKGROUND_COLOR, DIM_COLOR, TEXT_COLOR},
+    save_state::metadata::{StateMetadata, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH},
+};
+
+const COLUMNS: usize = 5;
+const ROWS: usize = SLOT_COUNT.div_ceil(COLUMNS);
+/// thumbnails are drawn at half their size so all the slots fit
+const CELL_WIDTH: usize = THUMBNAIL_WIDTH / 2;
+const CELL_HEIGHT: usize = THUMBNAIL_HEIGHT / 2;
+const MARGIN: usize = 8;
+const GRID_WIDTH: usize = COLUMNS * CELL_WIDTH + (COLUMNS - 1) * MARGIN;
+const GRID_HEIGHT: usize = ROWS * CELL_HEIGHT + (ROWS - 1) * MARGIN;
+
+/// A grid of thumbnails of the save slots, the selected one has a border
+/// around it and empty slots are dimmed. The frontend moves the selection
+/// with its own keys and loads or saves [SlotPicker::selected].
+#[derive(Debug, Clone, Default)]
+pub struct SlotPicker {
+    is_open: bool,
+    selected: usize,
+}
+
+impl SlotPicker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub fn open(&mut self) {
+        self.is_open = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, slot: usize) {
+        self.selected = slot % SLOT_COUNT;
+    }
+
+    pub fn select_next(&mut self) {
+        self.select(self.selected + 1);
+    }
+
+    pub fn select_previous(&mut self) {
+        self.select(self.selected + SLOT_COUNT - 1);
+    }
+
+    /// `slots` is what [crate::frontend::slots::SaveSlots::list] returns
+    pub fn draw(&self, frame: &mut Frame, slots: &[Option<StateMetadata>]) {
+        if !self.is_open {
+            return;
+        }
+        let left = (SCREEN_WIDTH - GRID_WIDTH) / 2;
+        let top = (SCREEN_HEIGHT - GRID_HEIGHT) / 2;
+        osd::fill_rect(
+            frame,
+            left - MARGIN,
+            top - MARGIN,
+            GRID_WIDTH + 2 * MARGIN,
+            GRID_HEIGHT + 2 * MARGIN,
+            BACKGROUND_COLOR,
+        );
+        for slot in 0..SLOT_COUNT {
+            let x = left + (slot % COLUMNS) * (CELL_WIDTH + MARGIN);
+            let y = top + (slot / COLUMNS) * (CELL_HEIGHT + MARGIN);
+            if slot == self.selected {
+                osd::fill_rect(
+                    frame,
+                    x - 2,
+                    y - 2,
+                    CELL_WIDTH + 4,
+                    CELL_HEIGHT + 4,
+                    TEXT_COLOR,
+                );
+            }
+            match slots.get(slot).and_then(Option::as_ref) {
+                Some(metadata) => Self::draw_thumbnail(frame, x, y, metadata),
+                None => osd::fill_rect(frame, x, y, CELL_WIDTH, CELL_HEIGHT, DIM_COLOR),
+            }
+        }
+    }
+
+    fn draw_thumbnail(frame: &mut Frame, x: usize, y: usize, metadata: &StateMetadata) {
+        for cell_y in 0..CELL_HEIGHT {
+            for cell_x in 0..CELL_WIDTH {
+                let pixel = metadata.thumbnail.get_pixel(cell_x * 2, cell_y * 2);
+                frame.set_pixel(x + cell_x, y + cell_y, pixel);
+            }
+        }
+    }
+}
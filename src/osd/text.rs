@@ -0,0 +1,69 @@
+use crate::hardware::ppu::frame::Frame;
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+/// glyph width plus one column of space
+pub const GLYPH_ADVANCE: usize = GLYPH_WIDTH + 1;
+
+/// A tiny 3x5 font, every glyph is 5 rows of 3 bits with the top left
+/// pixel in the highest bit. Lowercase letters are drawn as uppercase and
+/// anything without a glyph is drawn as `?`.
+#[rustfmt::skip]
+fn glyph(character: char) -> u16 {
+    match character.to_ascii_uppercase() {
+        '0' => 0b111_101_101_101_111, '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111, '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001, '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111, '7' => 0b111_001_001_001_001,
+        '8' => 0b111_101_111_101_111, '9' => 0b111_101_111_001_111,
+        'A' => 0b010_101_111_101_101, 'B' => 0b110_101_110_101_110,
+        'C' => 0b011_100_100_100_011, 'D' => 0b110_101_101_101_110,
+        'E' => 0b111_100_110_100_111, 'F' => 0b111_100_110_100_100,
+        'G' => 0b011_100_101_101_011, 'H' => 0b101_101_111_101_101,
+        'I' => 0b111_010_010_010_111, 'J' => 0b001_001_001_101_010,
+        'K' => 0b101_101_110_101_101, 'L' => 0b100_100_100_100_111,
+        'M' => 0b101_111_111_101_101, 'N' => 0b110_101_101_101_101,
+        'O' => 0b010_101_101_101_010, 'P' => 0b110_101_110_100_100,
+        'Q' => 0b010_101_101_110_011, 'R' => 0b110_101_110_101_101,
+        'S' => 0b011_100_010_001_110, 'T' => 0b111_010_010_010_010,
+        'U' => 0b101_101_101_101_111, 'V' => 0b101_101_101_101_010,
+        'W' => 0b101_101_111_111_101, 'X' => 0b101_101_010_101_101,
+        'Y' => 0b101_101_010_010_010, 'Z' => 0b111_001_010_100_111,
+        ' ' => 0,                     '.' => 0b000_000_000_000_010,
+        ',' => 0b000_000_000_010_100, ':' => 0b000_010_000_010_000,
+        '-' => 0b000_000_111_000_000, '+' => 0b000_010_111_010_000,
+        '=' => 0b000_111_000_111_000, '_' => 0b000_000_000_000_111,
+        '/' => 0b001_001_010_100_100, '%' => 0b101_001_010_100_101,
+        '!' => 0b010_010_010_000_010, '#' => 0b101_111_101_111_101,
+        '$' => 0b011_110_010_011_110, '(' => 0b010_100_100_100_010,
+        ')' => 0b010_001_001_001_010, '<' => 0b001_010_100_010_001,
+        '>' => 0b100_010_001_010_100, '\'' => 0b010_010_000_000_000,
+        _ => 0b110_001_010_000_010,
+    }
+}
+
+/// How many pixels wide `text` is when drawn
+pub fn text_width(text: &str) -> usize {
+    (text.chars().count() * GLYPH_ADVANCE).saturating_sub(1)
+}
+
+/// Draws `text` with its top left corner at `x`, `y`. Only the glyphs are
+/// drawn, put a [super::fill_rect] behind them if they need a background.
+pub fn draw_text(frame: &mut Frame, x: usize, y: usize, text: &str, color: u32) {
+    for (pixel_x, pixel_y) in text_pixels(text) {
+        frame.set_pixel(x + pixel_x, y + pixel_y, color);
+    }
+}
+
+/// Every pixel the glyphs of `text` cover, relative to the top left corner
+pub(super) fn text_pixels(text: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    text.chars().enumerate().flat_map(|(index, character)| {
+        let bits = glyph(character);
+        let glyph_x = index * GLYPH_ADVANCE;
+        (0..GLYPH_HEIGHT * GLYPH_WIDTH).filter_map(move |pixel| {
+            let (row, column) = (pixel / GLYPH_WIDTH, pixel % GLYPH_WIDTH);
+            let bit = (GLYPH_HEIGHT - 1 - row) * GLYPH_WIDTH + (GLYPH_WIDTH - 1 - column);
+            (bits & (1 << bit) != 0).then_some((glyph_x + column, row))
+        })
+    })
+}
@@ -0,0 +1,107 @@
+use crate::{
+    hardware::{
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        ppu::{TileInfo, frame::Frame},
+    },
+    osd::{
+        self, BACKGROUND_COLOR, TEXT_COLOR,
+        text::{self, GLYPH_HEIGHT},
+    },
+};
+
+const PADDING: usize = 2;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+/// how far the panel is from the cursor
+const OFFSET: usize = 10;
+
+/// Outlines the background tile under the mouse and shows where it comes
+/// from in a panel next to it, see [crate::hardware::ppu::Ppu::tile_info_at]
+#[derive(Debug, Clone, Default)]
+pub struct TileInspector {
+    is_enabled: bool,
+    /// in frame pixels
+    cursor: Option<(usize, usize)>,
+}
+
+impl TileInspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.is_enabled = enabled;
+    }
+
+    pub fn toggle(&mut self) {
+        self.is_enabled = !self.is_enabled;
+    }
+
+    /// `None` once the mouse leaves the window
+    pub fn set_cursor(&mut self, cursor: Option<(usize, usize)>) {
+        self.cursor = cursor.filter(|&(x, y)| x < SCREEN_WIDTH && y < SCREEN_HEIGHT);
+    }
+
+    /// Where the tile info has to be looked up, nothing gets drawn while
+    /// this is `None`
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.cursor.filter(|_| self.is_enabled)
+    }
+
+    pub fn draw(&self, frame: &mut Frame, info: &TileInfo) {
+        let Some((x, y)) = self.cursor() else {
+            return;
+        };
+
+        // the tile can start above or left of the screen when scrolled
+        let left = x as isize - info.pixel_x as isize;
+        let top = y as isize - info.pixel_y as isize;
+        for i in -1..=8 {
+            for (outline_x, outline_y) in [(i, -1), (i, 8), (-1, i), (8, i)] {
+                let (outline_x, outline_y) = (left + outline_x, top + outline_y);
+                if outline_x >= 0 && outline_y >= 0 {
+                    frame.set_pixel(outline_x as usize, outline_y as usize, TEXT_COLOR);
+                }
+            }
+        }
+
+        let lines = [
+            format!("TILE ${:02X}", info.tile_index),
+            format!("NT ${:04X}", info.nametable_address),
+            format!("AT ${:04X} PAL {}", info.attribute_address, info.palette),
+            format!("CHR ${:04X}", info.pattern_address),
+        ];
+        let width = lines
+            .iter()
+            .map(|line| text::text_width(line))
+            .max()
+            .unwrap_or(0)
+            + 2 * PADDING;
+        let height = lines.len() * LINE_HEIGHT + 2 * PADDING - 2;
+        // keep the panel on screen, flipping it to the other side of the
+        // cursor near the right and bottom edges
+        let panel_x = if x + OFFSET + width <= SCREEN_WIDTH {
+            x + OFFSET
+        } else {
+            x.saturating_sub(OFFSET + width)
+        };
+        let panel_y = if y + OFFSET + height <= SCREEN_HEIGHT {
+            y + OFFSET
+        } else {
+            y.saturating_sub(OFFSET + height)
+        };
+        osd::fill_rect(frame, panel_x, panel_y, width, height, BACKGROUND_COLOR);
+        for (index, line) in lines.iter().enumerate() {
+            text::draw_text(
+                frame,
+                panel_x + PADDING,
+                panel_y + PADDING + index * LINE_HEIGHT,
+                line,
+                TEXT_COLOR,
+            );
+        }
+    }
+}
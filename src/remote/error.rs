@@ -0,0 +1,35 @@
+use crate::{hardware::cartrige::error::CartrigeParseError, save_state::error::SaveStateError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum RemoteError {
+    #[error("Got an io error while talking to a client:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("Couldn't parse the request: {_0}")]
+    ParseError(String),
+    #[error("The request isn't a valid json-rpc 2.0 request!")]
+    InvalidRequestError,
+    #[error("There is no method called {_0:?}!")]
+    MethodNotFoundError(String),
+    #[error("Invalid params: {_0}")]
+    InvalidParamsError(String),
+    #[error("Couldn't load the rom:\n{_0}")]
+    CartrigeError(#[from] CartrigeParseError),
+    #[error("Couldn't load the save state:\n{_0}")]
+    SaveStateError(#[from] SaveStateError),
+}
+
+impl RemoteError {
+    /// The json-rpc error code
+    /// https://www.jsonrpc.org/specification#error_object
+    pub fn code(&self) -> i64 {
+        match self {
+            RemoteError::ParseError(_) => -32700,
+            RemoteError::InvalidRequestError => -32600,
+            RemoteError::MethodNotFoundError(_) => -32601,
+            RemoteError::InvalidParamsError(_) => -32602,
+            RemoteError::IoError(_) => -32000,
+            RemoteError::CartrigeError(_) => -32001,
+            RemoteError::SaveStateError(_) => -32002,
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! # Remote control
+//!
+//! A [JSON-RPC 2.0](https://www.jsonrpc.org/specification) server for
+//! controlling the emulator from other programs, like python scripts
+//! training agents or running regression tests. Needs the `remote`
+//! feature.
+//!
+//! Every request and response is a single line of json over tcp:
+//!
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 1, "method": "set_button", "params": {"controller": 0, "button": "Start", "pressed": true}}
+//! <-- {"jsonrpc": "2.0", "id": 1, "result": null}
+//! ```
+//!
+//! | method        | params                              | result                              |
+//! |---------------|-------------------------------------|-------------------------------------|
+//! | `load_rom`    | `path`                              | the [RomInfo](crate::RomInfo)       |
+//! | `run_frame`   | `frames` (default 1)                | the last [RunSummary]               |
+//! | `peek`        | `address`, `length` (default 1)     | array of bytes                      |
+//! | `poke`        | `address`, `bytes`                  | `null`                              |
+//! | `screenshot`  |                                     | `width`, `height`, `pixels` as hex encoded rgb bytes |
+//! | `set_button`  | `controller`, `button`, `pressed`   | `null`                              |
+//! | `save_state`  |                                     | the state as a hex string           |
+//! | `load_state`  | `state` as a hex string             | `null`                              |
+//! | `draw`        | a [Drawing]                         | `null`                              |
+//! | `clear_overlay` |                                   | `null`                              |
+//!
+//! `draw` queues a shape for the next frame, like EmuLua's `gui` functions:
+//!
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 2, "method": "draw", "params": {"shape": "rect", "x": 10, "y": 20, "width": 16, "height": 16, "color": 16711680, "alpha": 128}}
+//! ```
+
+pub mod error;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    devices::{nes::Nes, run::RunSummary},
+    hardware::{
+        cartrige::Cartrige,
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        input::controller::Button,
+        ppu::frame::Frame,
+    },
+    osd::overlay::Drawing,
+    remote::error::RemoteError,
+    trace::targets,
+};
+
+pub type Result<T> = std::result::Result<T, RemoteError>;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct LoadRomParams {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct RunFrameParams {
+    #[serde(default = "one")]
+    frames: u64,
+}
+
+#[derive(Deserialize)]
+struct PeekParams {
+    address: u16,
+    #[serde(default = "one")]
+    length: u64,
+}
+
+#[derive(Deserialize)]
+struct PokeParams {
+    address: u16,
+    bytes: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct SetButtonParams {
+    controller: usize,
+    button: Button,
+    pressed: bool,
+}
+
+#[derive(Deserialize)]
+struct LoadStateParams {
+    state: String,
+}
+
+fn one() -> u64 {
+    1
+}
+
+/// Owns the nes the clients control, can also be used without the server
+/// by passing requests to [RemoteSession::handle_request]
+pub struct RemoteSession {
+    nes: Nes,
+    frame: Frame,
+}
+
+impl RemoteSession {
+    pub fn new(nes: Nes) -> Self {
+        Self {
+            nes,
+            frame: Frame::new(),
+        }
+    }
+
+    pub fn nes(&self) -> &Nes {
+        &self.nes
+    }
+
+    pub fn nes_mut(&mut self) -> &mut Nes {
+        &mut self.nes
+    }
+
+    /// Accepts clients one after the other and answers their requests,
+    /// only returns if accepting a client fails
+    pub fn serve(&mut self, listener: &TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            if let Err(err) = self.handle_client(stream) {
+                tracing::warn!(target: targets::EMULATOR, "remote client disconnected: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_client(&mut self, stream: TcpStream) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = self.handle_request(&line);
+            writer.write_all(response.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Handles a single json-rpc request and returns the response
+    pub fn handle_request(&mut self, request: &str) -> String {
+        let (id, result) = match serde_json::from_str::<Request>(request) {
+            Ok(request) if request.jsonrpc == "2.0" => {
+                (request.id, self.call(&request.method, request.params))
+            }
+            Ok(request) => (request.id, Err(RemoteError::InvalidRequestError)),
+            Err(err) => (Value::Null, Err(RemoteError::ParseError(err.to_string()))),
+        };
+
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": err.code(), "message": err.to_string() },
+            }),
+        };
+        response.to_string()
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let result = match method {
+            "load_rom" => {
+                let params: LoadRomParams = parse_params(params)?;
+                let cartrige = Cartrige::from_file(&params.path)?;
+                let rom_info = cartrige.get_rom_info();
+                self.nes.insert_cartrige_now(cartrige);
+                self.nes.reset();
+                to_value(rom_info)
+            }
+            "run_frame" => {
+                let params: RunFrameParams = parse_params(params)?;
+                let mut summary = None::<RunSummary>;
+                for _ in 0..params.frames {
+                    summary = Some(self.nes.run_frame(&mut self.frame));
+                }
+                to_value(summary)
+            }
+            "peek" => {
+                let params: PeekParams = parse_params(params)?;
+                let bytes: Vec<u8> = (0..params.length.min(0x10000))
+                    .map(|i| self.nes.bus.peek(params.address.wrapping_add(i as u16)))
+                    .collect();
+                to_value(bytes)
+            }
+            "poke" => {
+                let params: PokeParams = parse_params(params)?;
+                for (i, byte) in params.bytes.iter().enumerate() {
+                    self.nes
+                        .bus
+                        .write(params.address.wrapping_add(i as u16), *byte);
+                }
+                Value::Null
+            }
+            "screenshot" => json!({
+                "width": SCREEN_WIDTH,
+                "height": SCREEN_HEIGHT,
+                "pixels": to_hex(&self.nes.get_last_frame().to_rgb_bytes()),
+            }),
+            "set_button" => {
+                let params: SetButtonParams = parse_params(params)?;
+                self.nes
+                    .set_button(params.controller, params.button, params.pressed);
+                Value::Null
+            }
+            "save_state" => Value::String(to_hex(&self.nes.save_state())),
+            "load_state" => {
+                let params: LoadStateParams = parse_params(params)?;
+                let state = from_hex(&params.state)
+                    .ok_or_else(|| RemoteError::InvalidParamsError("state isn't hex".into()))?;
+                self.nes.load_state(&state)?;
+                Value::Null
+            }
+            "draw" => {
+                let drawing: Drawing = parse_params(params)?;
+                self.nes.get_overlay_mut().push(drawing);
+                Value::Null
+            }
+            "clear_overlay" => {
+                self.nes.get_overlay_mut().clear();
+                Value::Null
+            }
+            _ => return Err(RemoteError::MethodNotFoundError(method.to_string())),
+        };
+        Ok(result)
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T> {
+    // methods without required params can be called without any
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|err| RemoteError::InvalidParamsError(err.to_string()))
+}
+
+fn to_value(value: impl serde::Serialize) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
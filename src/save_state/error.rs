@@ -0,0 +1,23 @@
+#[derive(thiserror::Error, Debug)]
+pub enum SaveStateError {
+    #[error("Got an io error while reading or writing a save state:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("Magic number missing at the start of the save state. Maybe recieved wrong file type.")]
+    MissingMagicNumbersError,
+    #[error("The save state is corrupted, its checksum doesn't match!")]
+    ChecksumMismatchError,
+    #[error("Save state version {_0} is not supported by this version of scamu!")]
+    UnsupportedVersionError(u16),
+    #[error("Was trying to read {_0} bytes but the save state was too short!")]
+    NotEnoughBytesError(usize),
+    #[error("The save state is missing the {_0:?} chunk!")]
+    MissingChunkError(String),
+    #[error("The {_0:?} chunk has {_1} bytes left over after loading it!")]
+    TrailingBytesError(String, usize),
+    #[error("Invalid value {_1} for {_0} in the save state!")]
+    InvalidValueError(&'static str, u64),
+    #[error("The save state was made with a different rom!")]
+    RomMismatchError,
+    #[error("There is no cartrige inserted!")]
+    NoCartrigeError,
+}
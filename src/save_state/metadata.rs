@@ -0,0 +1,109 @@
+use crate::{
+    hardware::{
+        constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        ppu::frame::Frame,
+    },
+    save_state::{
+        self, ChunkTag, ParsedState, SaveState, StateReader, StateWriter, error::SaveStateError,
+    },
+};
+
+pub const METADATA_TAG: ChunkTag = *b"META";
+
+/// Thumbnails are the frame shrunk down 4 times on both sides
+pub const THUMBNAIL_SCALE: usize = 4;
+pub const THUMBNAIL_WIDTH: usize = SCREEN_WIDTH / THUMBNAIL_SCALE;
+pub const THUMBNAIL_HEIGHT: usize = SCREEN_HEIGHT / THUMBNAIL_SCALE;
+
+/// A small picture of the screen when the state was saved, stored as
+/// 0x00RRGGBB like [Frame]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pixels: Vec<u32>,
+}
+
+impl Thumbnail {
+    /// Every thumbnail pixel is the average of a block of frame pixels
+    pub fn from_frame(frame: &Frame) -> Self {
+        let mut pixels = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT);
+        for y in 0..THUMBNAIL_HEIGHT {
+            for x in 0..THUMBNAIL_WIDTH {
+                let mut sums = [0u32; 3];
+                for block_y in 0..THUMBNAIL_SCALE {
+                    for block_x in 0..THUMBNAIL_SCALE {
+                        let pixel = frame.get_pixel(
+                            x * THUMBNAIL_SCALE + block_x,
+                            y * THUMBNAIL_SCALE + block_y,
+                        );
+                        let [_, r, g, b] = pixel.to_be_bytes();
+                        sums[0] += r as u32;
+                        sums[1] += g as u32;
+                        sums[2] += b as u32;
+                    }
+                }
+                let count = (THUMBNAIL_SCALE * THUMBNAIL_SCALE) as u32;
+                let [r, g, b] = sums.map(|sum| (sum / count) as u8);
+                pixels.push(u32::from_be_bytes([0, r, g, b]));
+            }
+        }
+        Self { pixels }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * THUMBNAIL_WIDTH + x]
+    }
+
+    /// All the pixels row by row
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+}
+
+/// Extra info saved next to a state so a slot picker can show what's in
+/// it without loading it. It isn't needed to load the state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateMetadata {
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    pub frame_count: u64,
+    pub thumbnail: Thumbnail,
+}
+
+impl StateMetadata {
+    /// Reads the metadata out of a save state, states saved without
+    /// metadata give `None`
+    pub fn from_state(data: &[u8]) -> save_state::Result<Option<Self>> {
+        let state = ParsedState::parse(data)?;
+        let mut metadata = Self {
+            timestamp: 0,
+            frame_count: 0,
+            thumbnail: Thumbnail {
+                pixels: vec![0; THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT],
+            },
+        };
+        match state.load_chunk(METADATA_TAG, &mut metadata) {
+            Ok(()) => Ok(Some(metadata)),
+            Err(SaveStateError::MissingChunkError(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl SaveState for StateMetadata {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.timestamp);
+        writer.write_u64(self.frame_count);
+        for pixel in self.thumbnail.pixels.iter() {
+            writer.write_u32(*pixel);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.timestamp = reader.read_u64()?;
+        self.frame_count = reader.read_u64()?;
+        for pixel in self.thumbnail.pixels.iter_mut() {
+            *pixel = reader.read_u32()? & 0xFFFFFF;
+        }
+        Ok(())
+    }
+}
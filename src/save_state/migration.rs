@@ -0,0 +1,261 @@
+//! Upgrading save states made by older versions of scamu.
+//!
+//! Every time the layout of a chunk changes, [CURRENT_VERSION] has to be
+//! bumped and a [Migration] from the previous version has to be added to
+//! [MIGRATIONS]. A migration gets the chunks exactly as the old version
+//! wrote them and rewrites them into the layout of the next version, so
+//! an old state goes through every migration after its version in order.
+//!
+//! The layout of the `CART` chunk depends on the mapper, which the state
+//! doesn't record, so its migrations go in [CARTRIGE_MIGRATIONS] instead
+//! and run with the header of the inserted cartrige when it gets loaded.
+
+use crate::{
+    hardware::cartrige::Header,
+    save_state::{Chunk, ChunkTag, Result, StateReader, StateWriter, error::SaveStateError},
+};
+
+pub const CURRENT_VERSION: u16 = 9;
+
+pub struct Migration {
+    /// The version this migration upgrades from (to `from + 1`)
+    pub from: u16,
+    pub migrate: fn(&mut Vec<Chunk>) -> Result<()>,
+}
+
+/// Must be sorted by [Migration::from]
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        migrate: split_input_chunk,
+    },
+    Migration {
+        from: 2,
+        migrate: add_frame_count,
+    },
+    Migration {
+        from: 3,
+        migrate: add_entropy_chunk,
+    },
+    Migration {
+        from: 4,
+        migrate: add_data_recorder,
+    },
+    Migration {
+        from: 5,
+        migrate: add_region,
+    },
+    Migration {
+        from: 6,
+        migrate: add_dmc,
+    },
+    Migration {
+        from: 7,
+        migrate: add_held_buttons,
+    },
+];
+
+pub struct CartrigeMigration {
+    /// The version this migration upgrades from (to `from + 1`)
+    pub from: u16,
+    pub migrate: fn(&mut Vec<u8>, &Header) -> Result<()>,
+}
+
+pub const CARTRIGE_MIGRATIONS: &[CartrigeMigration] = &[CartrigeMigration {
+    from: 8,
+    migrate: add_mapper_irq_state,
+}];
+
+pub fn migrate(version: u16, chunks: &mut Vec<Chunk>) -> Result<()> {
+    if version == 0 || version > CURRENT_VERSION {
+        return Err(SaveStateError::UnsupportedVersionError(version));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+        (migration.migrate)(chunks)?;
+    }
+    Ok(())
+}
+
+/// Upgrades the payload of a `CART` chunk saved with `header`, `version`
+/// has to be checked by [migrate] first
+pub fn migrate_cartrige(version: u16, payload: &mut Vec<u8>, header: &Header) -> Result<()> {
+    for migration in CARTRIGE_MIGRATIONS.iter().filter(|m| m.from >= version) {
+        (migration.migrate)(payload, header)?;
+    }
+    Ok(())
+}
+
+fn find_chunk<'a>(chunks: &'a mut [Chunk], tag: &ChunkTag) -> Result<&'a mut Chunk> {
+    chunks
+        .iter_mut()
+        .find(|chunk| chunk.tag == *tag)
+        .ok_or_else(|| SaveStateError::MissingChunkError(String::from_utf8_lossy(tag).into_owned()))
+}
+
+/// Version 1 kept the two standard controllers at the end of the `BUS `
+/// chunk, version 2 moved them into an `INPT` chunk with a device per port
+fn split_input_chunk(chunks: &mut Vec<Chunk>) -> Result<()> {
+    let bus = find_chunk(chunks, b"BUS ")?;
+
+    let mut reader = StateReader::new(&bus.payload);
+    reader.read_bytes()?;
+    reader.read_u8()?;
+    let controllers_start = bus.payload.len() - reader.remaining();
+    let controllers = [reader.take_array::<2>()?, reader.take_array::<2>()?];
+    let strobe = reader.read_u8()?;
+
+    let mut input = StateWriter::new();
+    for [state, shift] in controllers {
+        // standard controller: state, shift, strobe
+        input.write_u8(1);
+        input.write_bytes(&[state, shift, strobe]);
+    }
+    // nothing in the expansion port
+    input.write_u8(0);
+    input.write_bytes(&[]);
+
+    bus.payload.truncate(controllers_start);
+    chunks.push(Chunk {
+        tag: *b"INPT",
+        payload: input.into_bytes(),
+    });
+    Ok(())
+}
+
+/// Version 3 added the frame count at the end of the `PPU ` chunk, old
+/// states start counting from 0
+// has to take a vec to fit in [Migration::migrate]
+#[allow(clippy::ptr_arg)]
+fn add_frame_count(chunks: &mut Vec<Chunk>) -> Result<()> {
+    find_chunk(chunks, b"PPU ")?
+        .payload
+        .extend_from_slice(&0u64.to_le_bytes());
+    Ok(())
+}
+
+/// Version 4 added the `RNG ` chunk, old states get the rng of the
+/// default seed and a clock starting at the epoch
+fn add_entropy_chunk(chunks: &mut Vec<Chunk>) -> Result<()> {
+    let mut entropy = StateWriter::new();
+    // the rng state of the default seed and a clock at the epoch
+    entropy.write_u64(0x7A2F_2EB2_16AC_C5B9);
+    entropy.write_u64(0);
+    chunks.push(Chunk {
+        tag: *b"RNG ",
+        payload: entropy.into_bytes(),
+    });
+    Ok(())
+}
+
+/// Version 5 added the data recorder at the end of the `INPT` chunk, old
+/// states get an empty tape
+// has to take a vec to fit in [Migration::migrate]
+#[allow(clippy::ptr_arg)]
+fn add_data_recorder(chunks: &mut Vec<Chunk>) -> Result<()> {
+    let mut recorder = StateWriter::new();
+    // no tape, its length and position
+    recorder.write_bytes(&[]);
+    recorder.write_u32(0);
+    recorder.write_u32(0);
+    // stopped, no cycles into the bit and a low output
+    recorder.write_u8(0);
+    recorder.write_u32(0);
+    recorder.write_bool(false);
+    find_chunk(chunks, b"INPT")?
+        .payload
+        .extend_from_slice(&recorder.into_bytes());
+    Ok(())
+}
+
+/// Version 6 added the region at the end of the `NES ` chunk, old states
+/// are all ntsc
+// has to take a vec to fit in [Migration::migrate]
+#[allow(clippy::ptr_arg)]
+fn add_region(chunks: &mut Vec<Chunk>) -> Result<()> {
+    find_chunk(chunks, b"NES ")?.payload.push(0);
+    Ok(())
+}
+
+/// Version 7 added the dmc at the end of the `APU ` chunk, old states get
+/// a silent one
+// has to take a vec to fit in [Migration::migrate]
+#[allow(clippy::ptr_arg)]
+fn add_dmc(chunks: &mut Vec<Chunk>) -> Result<()> {
+    let mut dmc = StateWriter::new();
+    // irq and loop off, the slowest rate
+    dmc.write_bool(false);
+    dmc.write_bool(false);
+    dmc.write_u16(428);
+    dmc.write_u16(428);
+    // output level, shift register, bits remaining and silence
+    dmc.write_u8(0);
+    dmc.write_u8(0);
+    dmc.write_u8(8);
+    dmc.write_bool(true);
+    // sample address and length, current address and bytes remaining
+    dmc.write_u16(0xC000);
+    dmc.write_u16(1);
+    dmc.write_u16(0xC000);
+    dmc.write_u16(0);
+    // no sample buffered, no irq and no dma running
+    dmc.write_bool(false);
+    dmc.write_u8(0);
+    dmc.write_bool(false);
+    dmc.write_bool(false);
+    dmc.write_u8(0);
+    find_chunk(chunks, b"APU ")?
+        .payload
+        .extend_from_slice(&dmc.into_bytes());
+    Ok(())
+}
+
+/// Version 8 added the held buttons, the last pressed directions and the
+/// turbo frame at the end of the `INPT` chunk. Old states start with
+/// nothing held, it gets picked up again with the next press.
+// has to take a vec to fit in [Migration::migrate]
+#[allow(clippy::ptr_arg)]
+fn add_held_buttons(chunks: &mut Vec<Chunk>) -> Result<()> {
+    let mut input = StateWriter::new();
+    // the held buttons and the last pressed directions of the 4 controllers
+    for _ in 0..4 * 2 {
+        input.write_u8(0);
+    }
+    // the turbo frame and the held turbo buttons of the 4 controllers
+    input.write_u64(0);
+    for _ in 0..4 {
+        input.write_u8(0);
+    }
+    find_chunk(chunks, b"INPT")?
+        .payload
+        .extend_from_slice(&input.into_bytes());
+    Ok(())
+}
+
+/// Version 9 added the counter, reload flag, irq line and A12 filter to
+/// the end of the MMC3 state and the irq line to the end of the FME-7
+/// state. Old states start with a counter that was never clocked and the
+/// line low.
+fn add_mapper_irq_state(payload: &mut Vec<u8>, header: &Header) -> Result<()> {
+    let (mapper_size, added) = match header.get_mapper_id() {
+        // banks and the irq latch and enable, then the counter, reload,
+        // line, and the A12 filter's level and last fall
+        4 => (1 + 4 + 8 + 1 + 1 + 1 + 1, 1 + 1 + 1 + 1 + 8),
+        // banks and the irq control and counter, then the line
+        69 => (1 + 4 + 8 + 4 + 4 + 1 + 1 + 2, 1),
+        _ => return Ok(()),
+    };
+
+    // the prg ram and the chr ram come before the mapper
+    let mut reader = StateReader::new(payload);
+    reader.read_bytes()?;
+    if header.prg_chr_size() == 0 {
+        reader.read_bytes()?;
+    }
+    let mapper_end = payload.len() - reader.remaining() + mapper_size;
+    if mapper_end > payload.len() {
+        return Err(SaveStateError::NotEnoughBytesError(mapper_size));
+    }
+    payload.splice(mapper_end..mapper_end, std::iter::repeat_n(0, added));
+    Ok(())
+}
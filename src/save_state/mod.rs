@@ -0,0 +1,295 @@
+//! # Save states
+//!
+//! A save state is laid out like this (all numbers are little endian):
+//!
+//! | size    | contents                                        |
+//! |---------|-------------------------------------------------|
+//! | 4       | magic number: `SCST`                            |
+//! | 2       | version (see [migration::CURRENT_VERSION])      |
+//! | ...     | chunks                                          |
+//! | 4       | CRC32 of everything before it                   |
+//!
+//! And each chunk is a TLV record:
+//!
+//! | size    | contents                                        |
+//! |---------|-------------------------------------------------|
+//! | 4       | tag, for example `CPU `                         |
+//! | 4       | length of the payload                           |
+//! | length  | payload, written by a [SaveState] impl          |
+//!
+//! Every subsystem gets its own chunk so a change in one of them only
+//! needs a migration for that chunk (see [migration]). Unknown chunks are
+//! ignored when loading.
+
+pub mod error;
+pub mod metadata;
+pub mod migration;
+
+use crate::{hardware::cartrige::Cartrige, save_state::error::SaveStateError};
+
+pub type Result<T> = std::result::Result<T, SaveStateError>;
+
+pub const MAGIC_NUMBERS: [u8; 4] = *b"SCST";
+const CHECKSUM_SIZE: usize = 4;
+
+pub type ChunkTag = [u8; 4];
+
+pub struct Chunk {
+    pub tag: ChunkTag,
+    pub payload: Vec<u8>,
+}
+
+/// Implemented by everything that has to be saved in a save state
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    /// Should only fail because of bad data in the save state, never panic
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes the bytes with their length in front of them
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(SaveStateError::NotEnoughBytesError(n));
+        }
+        let (start, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(start)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(SaveStateError::InvalidValueError("bool", other as u64)),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take_array()?))
+    }
+
+    /// Reads bytes written by [StateWriter::write_bytes]
+    pub fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads bytes written by [StateWriter::write_bytes] into `out`.
+    /// Fails if the length isn't exactly the length of `out`.
+    pub fn read_bytes_into(&mut self, name: &'static str, out: &mut [u8]) -> Result<()> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != out.len() {
+            return Err(SaveStateError::InvalidValueError(name, bytes.len() as u64));
+        }
+        out.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reads a value and checks it is in `range`
+    pub fn read_u32_in(
+        &mut self,
+        name: &'static str,
+        range: std::ops::RangeInclusive<u32>,
+    ) -> Result<u32> {
+        let value = self.read_u32()?;
+        if !range.contains(&value) {
+            return Err(SaveStateError::InvalidValueError(name, value as u64));
+        }
+        Ok(value)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Builds the save state file out of chunks
+pub struct StateBuilder {
+    writer: StateWriter,
+}
+
+impl StateBuilder {
+    pub fn new() -> Self {
+        let mut writer = StateWriter::new();
+        writer.buffer.extend_from_slice(&MAGIC_NUMBERS);
+        writer.write_u16(migration::CURRENT_VERSION);
+        Self { writer }
+    }
+
+    pub fn add_chunk(&mut self, tag: ChunkTag, state: &dyn SaveState) {
+        let mut payload = StateWriter::new();
+        state.save_state(&mut payload);
+        self.add_raw_chunk(tag, &payload.into_bytes());
+    }
+
+    pub fn add_raw_chunk(&mut self, tag: ChunkTag, payload: &[u8]) {
+        self.writer.buffer.extend_from_slice(&tag);
+        self.writer.write_bytes(payload);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut out = self.writer.into_bytes();
+        let checksum = crc32fast::hash(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+}
+
+impl Default for StateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A parsed save state with all the migrations already applied
+pub struct ParsedState {
+    version: u16,
+    chunks: Vec<Chunk>,
+}
+
+impl ParsedState {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < MAGIC_NUMBERS.len() + 2 + CHECKSUM_SIZE {
+            return Err(SaveStateError::NotEnoughBytesError(
+                MAGIC_NUMBERS.len() + 2 + CHECKSUM_SIZE,
+            ));
+        }
+
+        let (data, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if crc32fast::hash(data).to_le_bytes() != checksum {
+            return Err(SaveStateError::ChecksumMismatchError);
+        }
+
+        let mut reader = StateReader::new(data);
+        if reader.take_array::<4>()? != MAGIC_NUMBERS {
+            return Err(SaveStateError::MissingMagicNumbersError);
+        }
+        let version = reader.read_u16()?;
+
+        let mut chunks = Vec::new();
+        while reader.remaining() > 0 {
+            let tag = reader.take_array::<4>()?;
+            let payload = reader.read_bytes()?.to_vec();
+            chunks.push(Chunk { tag, payload });
+        }
+
+        migration::migrate(version, &mut chunks)?;
+
+        Ok(Self { version, chunks })
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    pub fn chunk(&self, tag: ChunkTag) -> Result<&[u8]> {
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.tag == tag)
+            .map(|chunk| chunk.payload.as_slice())
+            .ok_or_else(|| SaveStateError::MissingChunkError(tag_name(tag)))
+    }
+
+    /// Loads a whole chunk into `state`, the chunk has to be used up exactly
+    pub fn load_chunk(&self, tag: ChunkTag, state: &mut dyn SaveState) -> Result<()> {
+        Self::load_payload(tag, self.chunk(tag)?, state)
+    }
+
+    /// Same as [ParsedState::load_chunk] for the chunk of `cartrige`. Its
+    /// layout depends on the mapper, so old states only get migrated here
+    /// and [ParsedState::chunk] has it the way it was saved.
+    pub fn load_cartrige_chunk(&self, tag: ChunkTag, cartrige: &mut Cartrige) -> Result<()> {
+        let mut payload = self.chunk(tag)?.to_vec();
+        migration::migrate_cartrige(self.version, &mut payload, cartrige.get_header())?;
+        Self::load_payload(tag, &payload, cartrige)
+    }
+
+    fn load_payload(tag: ChunkTag, payload: &[u8], state: &mut dyn SaveState) -> Result<()> {
+        let mut reader = StateReader::new(payload);
+        state.load_state(&mut reader)?;
+        if reader.remaining() != 0 {
+            return Err(SaveStateError::TrailingBytesError(
+                tag_name(tag),
+                reader.remaining(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn tag_name(tag: ChunkTag) -> String {
+    String::from_utf8_lossy(&tag).into_owned()
+}
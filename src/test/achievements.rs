@@ -0,0 +1,100 @@
+#![cfg(feature = "achievements")]
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::achievements::{AchievementEngine, Ruleset, error::AchievementError};
+
+const RULESET: &str = r#"
+[[achievement]]
+id = "rich"
+title = "Rich"
+
+[[achievement.conditions]]
+address = 0x10
+compare = ">="
+value = 50
+
+[[achievement.conditions]]
+address = 0x20
+compare = "=="
+value = 0x1234
+size = "u16"
+hits = 3
+
+[[achievement.resets]]
+address = 0x30
+compare = "!="
+value = 0
+
+[[achievement]]
+id = "start"
+title = "Started"
+
+[[achievement.conditions]]
+address = 0x40
+compare = "=="
+value = 1
+"#;
+
+#[test]
+fn achievement_conditions() {
+    let mut engine = AchievementEngine::new(Ruleset::from_toml(RULESET).unwrap());
+    let unlocked = Rc::new(RefCell::new(Vec::new()));
+    let callback_unlocked = unlocked.clone();
+    engine.set_unlock_callback(move |achievement| {
+        callback_unlocked
+            .borrow_mut()
+            .push(achievement.title.clone())
+    });
+
+    let mut memory = [0u8; 0x100];
+    assert!(
+        engine
+            .do_frame_with(|address| memory[address as usize])
+            .is_empty()
+    );
+
+    memory[0x10] = 60;
+    memory[0x20..0x22].copy_from_slice(&0x1234u16.to_le_bytes());
+    memory[0x40] = 1;
+    assert_eq!(
+        engine.do_frame_with(|address| memory[address as usize]),
+        ["start"]
+    );
+    engine.do_frame_with(|address| memory[address as usize]);
+
+    // the reset clears the two hits so far
+    memory[0x30] = 1;
+    engine.do_frame_with(|address| memory[address as usize]);
+    memory[0x30] = 0;
+    engine.do_frame_with(|address| memory[address as usize]);
+    engine.do_frame_with(|address| memory[address as usize]);
+    assert!(!engine.is_unlocked("rich"));
+    assert_eq!(
+        engine.do_frame_with(|address| memory[address as usize]),
+        ["rich"]
+    );
+    assert!(
+        engine
+            .do_frame_with(|address| memory[address as usize])
+            .is_empty()
+    );
+    assert_eq!(*unlocked.borrow(), ["Started", "Rich"]);
+
+    engine.reset();
+    assert!(!engine.is_unlocked("start"));
+
+    let duplicate = format!("{RULESET}\n[[achievement]]\nid = \"start\"\ntitle = \"Again\"");
+    assert!(matches!(
+        Ruleset::from_toml(&duplicate),
+        Err(AchievementError::DuplicateIdError(id)) if id == "start"
+    ));
+    assert!(matches!(
+        Ruleset::from_toml("[[achievement]]\nid = \"empty\"\ntitle = \"Empty\""),
+        Err(AchievementError::NoConditionsError(_))
+    ));
+    assert!(matches!(
+        Ruleset::from_toml("[[achievement]]\nid = 1"),
+        Err(AchievementError::ParseError(_))
+    ));
+}
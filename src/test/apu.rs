@@ -0,0 +1,239 @@
+use crate::{
+    devices::nes::Nes,
+    hardware::apu::{
+        Apu, ApuQuality, Channel,
+        expansion::{ExpansionAudio, ExpansionChip},
+        filter::{AudioFilters, FilterPreset, FilterStage},
+    },
+    save_state::{self, SaveState, StateReader, StateWriter},
+};
+
+/// An apu playing a square wave on pulse 1 at full volume
+fn pulse_apu() -> Apu {
+    let mut apu = Apu::new();
+    apu.write_register(0x4015, 0b0000_0001);
+    apu.write_register(0x4000, 0b1011_1111);
+    apu.write_register(0x4002, 0xFD);
+    apu.write_register(0x4003, 0x00);
+    apu
+}
+
+fn run(apu: &mut Apu, cycles: usize) -> Vec<f32> {
+    for _ in 0..cycles {
+        apu.tick();
+    }
+    apu.by_ref().collect()
+}
+
+#[test]
+fn channel_mute_and_solo() {
+    let mut apu = pulse_apu();
+    let samples = run(&mut apu, 10_000);
+    assert!(samples[1..].iter().any(|sample| *sample != samples[1]));
+
+    apu.set_channel_enabled(Channel::Pulse1, false);
+    assert!(!apu.is_channel_enabled(Channel::Pulse1));
+    // the first sample still has some of the pulse in it, after that
+    // only the silent triangle is left which doesn't move
+    let samples = run(&mut apu, 10_000);
+    assert!(samples[1..].iter().all(|sample| *sample == samples[1]));
+
+    apu.solo_channel(Channel::Pulse1);
+    assert!(apu.is_channel_enabled(Channel::Pulse1));
+    assert!(!apu.is_channel_enabled(Channel::Triangle));
+    let samples = run(&mut apu, 10_000);
+    assert!(samples[1..].iter().any(|sample| *sample != samples[1]));
+}
+
+#[test]
+fn channel_taps() {
+    let mut apu = pulse_apu();
+    apu.set_channel_taps_enabled(true);
+    apu.set_channel_enabled(Channel::Pulse1, false);
+    let samples = run(&mut apu, 10_000);
+
+    // muted channels still get tapped
+    let pulse = apu.take_channel_samples(Channel::Pulse1);
+    assert_eq!(pulse.len(), samples.len());
+    assert!(pulse.iter().any(|sample| *sample > 0.0));
+    assert!(apu.take_channel_samples(Channel::Pulse1).is_empty());
+
+    let noise = apu.take_channel_samples(Channel::Noise);
+    assert!(noise.iter().all(|sample| *sample == 0.0));
+
+    apu.set_channel_taps_enabled(false);
+    run(&mut apu, 10_000);
+    assert!(apu.take_channel_samples(Channel::Pulse1).is_empty());
+}
+
+#[test]
+fn apu_debug_state() {
+    let mut apu = pulse_apu();
+    apu.write_register(0x4008, 0b1000_0001);
+    apu.write_register(0x4015, 0b0000_0101);
+    apu.write_register(0x400A, 0xFD);
+    apu.write_register(0x400B, 0b0000_1001);
+    run(&mut apu, 10_000);
+
+    let state = apu.debug_state();
+    let pulse = state.pulse1;
+    assert_eq!(pulse.duty, 2);
+    assert_eq!(pulse.period, 0xFD);
+    assert!(pulse.envelope.constant_volume);
+    assert_eq!(pulse.envelope.output(), 15);
+    assert!(pulse.length_counter.halted);
+    assert!(pulse.is_audible());
+    // A4
+    assert!((pulse.frequency() - 440.0).abs() < 1.0);
+    assert!(!state.pulse2.is_audible());
+
+    let triangle = state.triangle;
+    assert_eq!(triangle.period, 0x1FD);
+    assert!(triangle.control);
+    assert_eq!(triangle.linear_counter, 1);
+    assert!(triangle.is_audible());
+    assert!((triangle.frequency() - 110.0).abs() < 1.0);
+    assert!(!state.five_step_mode);
+}
+
+#[test]
+fn audio_filter_presets() {
+    let mean = |samples: &[f32]| samples.iter().sum::<f32>() / samples.len() as f32;
+
+    // the raw mixer output of a pulse wave sits well above 0
+    let mut apu = pulse_apu();
+    let raw = run(&mut apu, 200_000);
+    assert!(mean(&raw[raw.len() / 2..]) > 0.02);
+
+    // the high-passes take out the dc offset
+    for preset in [FilterPreset::Famicom, FilterPreset::Nes] {
+        let mut apu = pulse_apu();
+        apu.set_audio_filters(preset.into());
+        let filtered = run(&mut apu, 200_000);
+        assert_eq!(filtered.len(), raw.len());
+        assert!(mean(&filtered[filtered.len() / 2..]).abs() < 0.005);
+    }
+
+    // a low-pass alone keeps it but rounds off the edges
+    let mut apu = pulse_apu();
+    apu.set_audio_filters(AudioFilters::new(vec![FilterStage::low_pass(1000.0)]));
+    let smoothed = run(&mut apu, 200_000);
+    let steepest = |samples: &[f32]| {
+        samples
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max)
+    };
+    assert!(mean(&smoothed[smoothed.len() / 2..]) > 0.02);
+    assert!(steepest(&smoothed[1..]) < steepest(&raw[1..]) / 2.0);
+
+    apu.power_cycle();
+    assert_eq!(apu.get_audio_filters().get_stages().len(), 1);
+}
+
+#[test]
+fn apu_quality() {
+    let distinct = |samples: &[f32]| {
+        let mut values: Vec<u32> = samples.iter().map(|sample| sample.to_bits()).collect();
+        values.sort();
+        values.dedup();
+        values.len()
+    };
+    let mut high = pulse_apu();
+    let mut low = pulse_apu();
+    low.set_quality(ApuQuality::Low);
+    let high_samples = run(&mut high, 10_000);
+    let low_samples = run(&mut low, 10_000);
+    assert_eq!(high_samples.len(), low_samples.len());
+    // averaging catches the square wave between its two levels, taking
+    // single cycles never does
+    assert!(distinct(&high_samples[1..]) > 2);
+    assert_eq!(distinct(&low_samples[1..]), 2);
+
+    low.power_cycle();
+    assert_eq!(low.get_quality(), ApuQuality::Low);
+}
+
+/// A chip that outputs whatever was last written to $5000
+#[derive(Debug, Clone, Default)]
+struct DcChip {
+    level: u8,
+}
+
+impl ExpansionAudio for DcChip {
+    fn chip(&self) -> ExpansionChip {
+        ExpansionChip::Namco163
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        if address == 0x5000 {
+            self.level = value;
+        }
+    }
+
+    fn tick(&mut self) {}
+
+    fn output(&self) -> f32 {
+        self.level as f32 / 255.0
+    }
+
+    fn clone_box(&self) -> Box<dyn ExpansionAudio> {
+        Box::new(self.clone())
+    }
+}
+
+impl SaveState for DcChip {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.level);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> save_state::Result<()> {
+        self.level = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn expansion_audio() {
+    let mut apu = Apu::new();
+    apu.attach_expansion_audio(Box::new(DcChip::default()));
+    assert_eq!(apu.expansion_chips(), vec![ExpansionChip::Namco163]);
+
+    apu.write_expansion_register(0x5000, 0xFF);
+    let samples = run(&mut apu, 10_000);
+    assert!(
+        samples[1..]
+            .iter()
+            .all(|sample| (*sample - 1.0).abs() < 1e-6)
+    );
+
+    apu.set_expansion_volume(ExpansionChip::Namco163, 0.5);
+    let samples = run(&mut apu, 10_000);
+    assert!(
+        samples[1..]
+            .iter()
+            .all(|sample| (*sample - 0.5).abs() < 1e-6)
+    );
+
+    // cartrige writes only reach the chip once the bus knows about it
+    let mut nes = Nes::new();
+    nes.apu
+        .lock()
+        .unwrap()
+        .attach_expansion_audio(Box::new(DcChip::default()));
+    nes.bus.write(0x5000, 0x80);
+    let mut state = StateWriter::new();
+    nes.apu.lock().unwrap().save_state(&mut state);
+    nes.bus.set_expansion_audio(true);
+    nes.bus.write(0x5000, 0x40);
+    let mut apu = nes.apu.lock().unwrap();
+    let samples = run(&mut apu, 10_000);
+    assert!(samples[1..].iter().all(|sample| *sample == samples[1]));
+    assert!((samples[1] - 0x40 as f32 / 255.0).abs() < 1e-6);
+
+    // the chip gets saved with the apu, it hadn't seen any write yet
+    let bytes = state.into_bytes();
+    apu.load_state(&mut StateReader::new(&bytes)).unwrap();
+    let samples = run(&mut apu, 10_000);
+    assert!(samples[1..].iter().all(|sample| *sample == 0.0));
+}
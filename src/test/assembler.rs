@@ -0,0 +1,133 @@
+use crate::{
+    debugger::Debugger,
+    devices::nes::Nes,
+    hardware::{
+        cartrige::Cartrige,
+        cpu::{AddressingModeKind, OpcodeInfo, assembler::assemble, error::AssemblerError},
+    },
+};
+
+/// Every line of the nestest log assembles back to the bytes it shows,
+/// except for illegal opcodes that exist more than once
+#[test]
+fn nestest_log_round_trip() {
+    let log = include_str!("./nestest/correct_nestest.log").replace("\r\n", "\n");
+    for line in log.lines() {
+        let address = u16::from_str_radix(&line[..4], 16).unwrap();
+        let bytes: Vec<u8> = line[6..15]
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+            .collect();
+        let text = line[15..48].trim();
+        let assembled = assemble(address, text).unwrap_or_else(|err| panic!("{line}\n{err}"));
+
+        assert_eq!(assembled[1..], bytes[1..], "{line}");
+        let (expected, got) = (OpcodeInfo::get(bytes[0]), OpcodeInfo::get(assembled[0]));
+        assert_eq!(
+            (expected.mnemonic, expected.mode),
+            (got.mnemonic, got.mode),
+            "{line}"
+        );
+    }
+}
+
+#[test]
+fn every_opcode_round_trip() {
+    let mut nes = Nes::new();
+    for opcode in 0..=0xFF {
+        nes.write_memory(0x0300, &[opcode, 0x34, 0x02]);
+        let instruction = &Debugger::disassemble(&nes, 0x0300, 1)[0];
+        let assembled = assemble(0x0300, &instruction.text).unwrap();
+        assert_eq!(
+            assembled.len(),
+            instruction.bytes.len(),
+            "{}",
+            instruction.text
+        );
+        assert_eq!(
+            assembled[1..],
+            instruction.bytes[1..],
+            "{}",
+            instruction.text
+        );
+        let info = OpcodeInfo::get(assembled[0]);
+        assert_eq!(info.mnemonic, OpcodeInfo::get(opcode).mnemonic);
+        assert!(!info.is_illegal || OpcodeInfo::get(opcode).is_illegal);
+    }
+    assert_eq!(OpcodeInfo::get(0xEA).mode, AddressingModeKind::Implicit);
+    assert_eq!(assemble(0, "NOP").unwrap(), [0xEA]);
+    assert_eq!(assemble(0, "SBC #$01").unwrap(), [0xE9, 0x01]);
+}
+
+#[test]
+fn labels_and_directives() {
+    let source = "
+        reset:  LDX #5          ; count down
+        loop:   DEX
+                BNE loop
+                STA $0010,X     ; 4 digits stay absolute
+                STA $10,x
+                JMP (vector)
+                ASL
+                LSR A
+        vector: .word reset
+        table:  .byte $01, 2, %11
+    ";
+    assert_eq!(
+        assemble(0x8000, source).unwrap(),
+        [
+            0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0x9D, 0x10, 0x00, 0x95, 0x10, 0x6C, 0x0F, 0x80, 0x0A,
+            0x4A, 0x00, 0x80, 0x01, 0x02, 0x03
+        ]
+    );
+}
+
+#[test]
+fn assembler_errors() {
+    assert_eq!(
+        assemble(0, "STA #$01"),
+        Err(AssemblerError::InvalidModeError(
+            1,
+            "STA".into(),
+            "#$01".into()
+        ))
+    );
+    assert_eq!(
+        assemble(0, "LDA #$100"),
+        Err(AssemblerError::ValueRangeError(1, 0x100))
+    );
+    assert_eq!(
+        assemble(0, "BNE far\n.byte 0\nfar:"),
+        Ok(vec![0xD0, 0x01, 0x00])
+    );
+    assert_eq!(
+        assemble(0x8000, "BNE $9000"),
+        Err(AssemblerError::BranchRangeError(1, 0x0FFE))
+    );
+    assert_eq!(
+        assemble(0, "JMP nowhere"),
+        Err(AssemblerError::UnknownLabelError(1, "nowhere".into()))
+    );
+    assert_eq!(
+        assemble(0, "a:\na:"),
+        Err(AssemblerError::DuplicateLabelError(2, "a".into()))
+    );
+    assert!(matches!(
+        assemble(0, "LDA ($10"),
+        Err(AssemblerError::InvalidOperandError(1, _))
+    ));
+}
+
+#[test]
+fn assemble_in_place() {
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap());
+    let written = Debugger::assemble_in_place(&mut nes, 0xC000, "LDA #$42\nNOP").unwrap();
+    assert_eq!(written, 3);
+    let instructions = Debugger::disassemble(&nes, 0xC000, 2);
+    assert_eq!(instructions[0].text, "LDA #$42");
+    assert_eq!(instructions[1].text, "NOP");
+
+    Debugger::assemble_in_place(&mut nes, 0x0200, "INX").unwrap();
+    assert_eq!(nes.bus.peek(0x0200), 0xE8);
+}
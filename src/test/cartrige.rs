@@ -0,0 +1,641 @@
+use std::{
+    env,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    byte_size,
+    devices::nes::Nes,
+    hardware::{
+        apu::expansion::ExpansionChip,
+        cartrige::{
+            Cartrige, Header, LoadOptions, Mirroring, RomInfo, TvSystem,
+            cartrige_access::CartrigeAccess,
+            error::{CartrigeParseError, PatchError, SramError},
+            flat::FlatOptions,
+            patch,
+        },
+        cpu::assembler::assemble,
+        ppu::{Ppu, scanline::PpuAccuracy},
+    },
+};
+
+const NESTEST: &[u8] = include_bytes!("./nestest/nestest.nes");
+
+#[test]
+fn cartrige_round_trip() {
+    let cartrige = Cartrige::from_bytes(NESTEST).unwrap();
+    assert_eq!(cartrige.to_bytes(), NESTEST);
+}
+
+#[test]
+fn header_editing() {
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(0x42);
+    header.set_mirroring(Mirroring::Vertical);
+    header.set_battery_backed_ram(true);
+    header.upgrade_to_nes_2_0();
+
+    let fixed_rom = header.write_to_rom(NESTEST).unwrap();
+    let fixed_header = Header::from_bytes(&fixed_rom).unwrap();
+
+    assert_eq!(fixed_header, header);
+    assert_eq!(fixed_header.get_mapper_id(), 0x42);
+    assert_eq!(fixed_header.mirroring(), Mirroring::Vertical);
+    assert!(fixed_header.has_battery_backed_ram());
+    assert!(fixed_header.is_nes_2_0());
+    assert_eq!(fixed_header.tv_system(), TvSystem::Ntsc);
+    assert_eq!(fixed_rom[16..], NESTEST[16..]);
+}
+
+#[test]
+fn sram_import_export() {
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_battery_backed_ram(true);
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(&header.write_to_rom(NESTEST).unwrap()).unwrap());
+
+    // smaller saves get zero padded
+    nes.import_sram(&[1, 2, 3]).unwrap();
+    assert_eq!(nes.bus.read(0x6001), 2);
+    nes.bus.write(0x6003, 4);
+
+    let save = nes.export_sram().unwrap();
+    assert_eq!(save.len(), 0x2000);
+    assert_eq!(save[0..5], [1, 2, 3, 4, 0]);
+
+    let mut too_large = vec![0; 0x2001];
+    too_large[0x2000] = 1;
+    assert!(matches!(
+        nes.import_sram(&too_large),
+        Err(SramError::TooLargeError(0x2001, 0x2000))
+    ));
+}
+
+#[test]
+fn rom_info_json() {
+    let info = Cartrige::from_bytes(NESTEST).unwrap().get_rom_info();
+    let json = serde_json::to_value(&info).unwrap();
+
+    assert_eq!(json["mapper_id"], 0);
+    assert_eq!(json["mapper_name"], "NROM");
+    assert_eq!(json["prg_rom_size"], 0x4000);
+    assert_eq!(json["mirroring"], "Horizontal");
+    assert_eq!(json["tv_system"], "Ntsc");
+    assert_eq!(json["nes_2_0"], serde_json::Value::Null);
+    assert_eq!(
+        json["checksums"]["rom"]["crc32"],
+        format!("{:08X}", info.checksums.unwrap().rom.crc32)
+    );
+
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.upgrade_to_nes_2_0();
+    let json = serde_json::to_value(RomInfo::from_header(&header)).unwrap();
+    assert_eq!(json["nes_2_0"]["ram_sizes"]["prg_ram"], 0x2000);
+}
+
+#[test]
+fn bad_dumps() {
+    let mut overdump = NESTEST.to_vec();
+    overdump.extend_from_slice(&[0xFF; 100]);
+    let cartrige = Cartrige::from_bytes(&overdump).unwrap();
+    assert_eq!(cartrige.to_bytes(), NESTEST);
+    let strict = LoadOptions {
+        strict: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        Cartrige::from_bytes_with_options(&overdump, &strict),
+        Err(CartrigeParseError::OverdumpError(100, 24592))
+    ));
+
+    // cut off in the middle of the chr rom
+    let underdump = &NESTEST[..NESTEST.len() - 0x100];
+    assert!(matches!(
+        Cartrige::from_bytes(underdump),
+        Err(CartrigeParseError::TruncatedError(
+            "chr rom", 16400, 8192, 7936
+        ))
+    ));
+}
+
+fn with_mapper(mapper_id: u8) -> Nes {
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(mapper_id);
+    header.set_battery_backed_ram(true);
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(&header.write_to_rom(NESTEST).unwrap()).unwrap());
+    nes
+}
+
+#[test]
+fn prg_ram_protection() {
+    // MMC1 loads registers one bit per write
+    let mut nes = with_mapper(1);
+    let write_mmc1 = |nes: &mut Nes, address: u16, value: u8| {
+        for bit in 0..5 {
+            nes.bus.write(address, (value >> bit) & 1);
+        }
+    };
+    nes.bus.write(0x6000, 0x42);
+    write_mmc1(&mut nes, 0xE000, 0x10);
+    nes.bus.write(0x6000, 0x24);
+    write_mmc1(&mut nes, 0xE000, 0x00);
+    assert_eq!(nes.bus.read(0x6000), 0x42);
+    assert_eq!(nes.export_sram().unwrap()[0], 0x42);
+
+    // MMC3 write protects with bit 6 of $A001 and disables with bit 7
+    let mut nes = with_mapper(4);
+    nes.bus.write(0x6010, 0x11);
+    nes.bus.write(0xA001, 0xC0);
+    nes.bus.write(0x6010, 0x22);
+    assert_eq!(nes.bus.read(0x6010), 0x11);
+    nes.bus.write(0xA001, 0x00);
+    nes.bus.write(0x6010, 0x33);
+    nes.bus.write(0xA001, 0x80);
+    nes.bus.write(0x6010, 0x44);
+    assert_eq!(nes.bus.read(0x6010), 0x44);
+    assert_eq!(nes.export_sram().unwrap()[0x10], 0x44);
+}
+
+#[test]
+fn prg_ram_banks() {
+    let with_ram = |prg_ram: usize, prg_nvram: usize| {
+        let mut header = Header::from_bytes(NESTEST).unwrap();
+        header.set_mapper_id(1);
+        header.set_battery_backed_ram(true);
+        header.set_prg_ram_sizes(prg_ram, prg_nvram);
+        let mut nes = Nes::new();
+        nes.insert_cartrige(Cartrige::from_bytes(&header.write_to_rom(NESTEST).unwrap()).unwrap());
+        nes
+    };
+    let select_chr_bank = |nes: &mut Nes, value: u8| {
+        for bit in 0..5 {
+            nes.bus.write(0xA000, (value >> bit) & 1);
+        }
+    };
+
+    // SXROM picks one of 4 banks with bits 2 and 3 of the chr register
+    let mut nes = with_ram(0, byte_size!(32 kb));
+    for bank in 0..4 {
+        select_chr_bank(&mut nes, bank << 2);
+        nes.bus.write(0x6010, 0x40 + bank);
+    }
+    select_chr_bank(&mut nes, 1 << 2);
+    assert_eq!(nes.bus.read(0x6010), 0x41);
+    let save = nes.export_sram().unwrap();
+    assert_eq!(save.len(), byte_size!(32 kb));
+    for bank in 0..4 {
+        assert_eq!(save[bank * byte_size!(8 kb) + 0x10], 0x40 + bank as u8);
+    }
+
+    // SOROM has the battery backed bank after the volatile one and picks
+    // it with bit 3
+    let mut nes = with_ram(byte_size!(8 kb), byte_size!(8 kb));
+    select_chr_bank(&mut nes, 1 << 3);
+    nes.bus.write(0x6000, 0x42);
+    assert_eq!(nes.export_sram().unwrap()[byte_size!(8 kb)], 0x42);
+
+    // NES 2.0 boards can say they have no ram at all
+    let nes = with_ram(0, 0);
+    assert!(nes.export_sram().unwrap().is_empty());
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_prg_ram_sizes(byte_size!(2 kb), 100);
+    let sizes = header.nes_2_0_ram_sizes().unwrap();
+    assert_eq!((sizes.prg_ram, sizes.prg_nvram), (byte_size!(2 kb), 128));
+    assert_eq!(header.prg_ram_size_bytes(), byte_size!(2 kb) + 128);
+}
+
+#[test]
+fn unrom_512_flash() {
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(30);
+    header.set_battery_backed_ram(true);
+    header.set_prg_rom_size(2);
+    header.set_chr_rom_size(0);
+    let mut rom = header.to_bytes().to_vec();
+    rom.extend_from_slice(&[0xFF; 0x8000]);
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(&rom).unwrap());
+
+    // $5555 is in bank 1 and $2AAA in bank 0 of the flash
+    let unlock = |nes: &mut Nes| {
+        for (bank, address, value) in [(1, 0x9555, 0xAA), (0, 0xAAAA, 0x55)] {
+            nes.bus.write(0xC000, bank);
+            nes.bus.write(address, value);
+        }
+    };
+    let command = |nes: &mut Nes, command: u8| {
+        unlock(nes);
+        nes.bus.write(0xC000, 1);
+        nes.bus.write(0x9555, command);
+        nes.bus.write(0xC000, 0);
+    };
+    command(&mut nes, 0xA0);
+    nes.bus.write(0x8010, 0x12);
+    assert_eq!(nes.bus.read(0x8010), 0x12);
+    // without the unlock sequence nothing gets programmed
+    nes.bus.write(0x8010, 0x00);
+    assert_eq!(nes.bus.read(0x8010), 0x12);
+
+    let save = nes.export_sram().unwrap();
+    assert_eq!(save.len(), 0x8000);
+    assert_eq!(save[0x10], 0x12);
+
+    command(&mut nes, 0x80);
+    unlock(&mut nes);
+    nes.bus.write(0x8000, 0x30);
+    assert_eq!(nes.bus.read(0x8010), 0xFF);
+
+    nes.import_sram(&save).unwrap();
+    assert_eq!(nes.bus.read(0x8010), 0x12);
+    assert!(matches!(
+        nes.import_sram(&save[..0x2000]),
+        Err(SramError::FlashSizeError(0x2000, 0x8000))
+    ));
+}
+
+#[test]
+fn fme7_banking() {
+    let prg = &NESTEST[0x10..0x4010];
+    let mut nes = with_mapper(69);
+    let command = |nes: &mut Nes, command: u8, parameter: u8| {
+        nes.bus.write(0x8000, command);
+        nes.bus.write(0xA000, parameter);
+    };
+    // the last 8kb bank is fixed at $E000
+    assert_eq!(nes.bus.read(0xE000), prg[0x2000]);
+    command(&mut nes, 0x9, 1);
+    assert_eq!(nes.bus.read(0x8010), prg[0x2010]);
+    command(&mut nes, 0xB, 0);
+    assert_eq!(nes.bus.read(0xC010), prg[0x0010]);
+
+    // rom at $6000 until ram is picked and enabled
+    command(&mut nes, 0x8, 0x01);
+    assert_eq!(nes.bus.read(0x6020), prg[0x2020]);
+    command(&mut nes, 0x8, 0xC0);
+    nes.bus.write(0x6020, 0x42);
+    assert_eq!(nes.bus.read(0x6020), 0x42);
+    assert_eq!(nes.export_sram().unwrap()[0x20], 0x42);
+
+    // the 5B sound chip listens on $C000 and $E000
+    assert_eq!(
+        nes.apu.lock().unwrap().expansion_chips(),
+        vec![ExpansionChip::Sunsoft5B]
+    );
+    for (register, value) in [(0, 0x80), (7, 0b0011_1110), (8, 0x0F)] {
+        nes.bus.write(0xC000, register);
+        nes.bus.write(0xE000, value);
+    }
+    let mut apu = nes.apu.lock().unwrap();
+    for _ in 0..10_000 {
+        apu.tick();
+    }
+    assert!(apu.by_ref().any(|sample| sample > 0.1));
+    drop(apu);
+
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(69);
+    let mut cartrige = Cartrige::from_bytes(&header.write_to_rom(NESTEST).unwrap()).unwrap();
+    let write = |cartrige: &mut Cartrige, address: u16, value: u8| {
+        cartrige.write(CartrigeAccess::CpuAccess { address }, value)
+    };
+    write(&mut cartrige, 0x8000, 0x0C);
+    write(&mut cartrige, 0xA000, 0x01);
+    assert_eq!(cartrige.map_nametable(0x2C10), 0x2810);
+    write(&mut cartrige, 0xA000, 0x03);
+    assert_eq!(cartrige.map_nametable(0x2810), 0x2410);
+    // chr banks are 1kb
+    write(&mut cartrige, 0x8000, 0x00);
+    write(&mut cartrige, 0xA000, 0x05);
+    assert_eq!(
+        cartrige.read(CartrigeAccess::PpuAccess { address: 0x0010 }),
+        Some(NESTEST[0x4010 + 0x1410])
+    );
+}
+
+#[test]
+fn fme7_irq() {
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(69);
+    let mut cartrige = Cartrige::from_bytes(&header.write_to_rom(NESTEST).unwrap()).unwrap();
+    let command = |cartrige: &mut Cartrige, command: u8, parameter: u8| {
+        cartrige.write(CartrigeAccess::CpuAccess { address: 0x8000 }, command);
+        cartrige.write(CartrigeAccess::CpuAccess { address: 0xA000 }, parameter);
+    };
+    command(&mut cartrige, 0xE, 100);
+    command(&mut cartrige, 0xF, 0);
+    // counting without the irq enabled
+    command(&mut cartrige, 0xD, 0x80);
+    for _ in 0..200 {
+        assert_eq!(cartrige.tick(), None);
+    }
+
+    command(&mut cartrige, 0xE, 100);
+    command(&mut cartrige, 0xF, 0);
+    command(&mut cartrige, 0xD, 0x81);
+    // it fires on the wrap after 0
+    for _ in 0..100 {
+        assert_eq!(cartrige.tick(), None);
+    }
+    assert_eq!(cartrige.tick(), Some(true));
+    assert_eq!(cartrige.tick(), None);
+    command(&mut cartrige, 0xD, 0x00);
+    assert_eq!(cartrige.tick(), Some(false));
+}
+
+#[test]
+fn vrc4_registers() {
+    let prg = &NESTEST[0x10..0x4010];
+    // without a submapper both VRC4a (A1, A2) and VRC4c (A6, A7) work
+    let mut nes = with_mapper(21);
+    nes.bus.write(0x8000, 1);
+    assert_eq!(nes.bus.read(0x8010), prg[0x2010]);
+    nes.bus.write(0x9080, 0x02);
+    assert_eq!(nes.bus.read(0xC010), prg[0x2010]);
+    assert_eq!(nes.bus.read(0x8010), prg[0x0010]);
+
+    // the irq counts up from the latch every cpu cycle and fires on the
+    // wrap from $FF
+    for (address, value) in [(0xF000, 0x0C), (0xF002, 0x0F), (0xF004, 0b110)] {
+        nes.bus.write(address, value);
+    }
+    let mut cycles = 0;
+    while !nes.cpu.lock().unwrap().is_triggered_irq {
+        nes.tick();
+        cycles += 1;
+        assert!(cycles < 100);
+    }
+    assert!((10..=15).contains(&cycles));
+    nes.bus.write(0xF006, 0);
+    for _ in 0..3 {
+        nes.tick();
+    }
+    assert!(!nes.cpu.lock().unwrap().is_triggered_irq);
+
+    // chr banks are written a nibble at a time
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(25);
+    let mut cartrige = Cartrige::from_bytes(&header.write_to_rom(NESTEST).unwrap()).unwrap();
+    let write = |cartrige: &mut Cartrige, address: u16, value: u8| {
+        cartrige.write(CartrigeAccess::CpuAccess { address }, value)
+    };
+    // VRC4b has A0 and A1 swapped, so $B001 is bank 1 low
+    write(&mut cartrige, 0xB001, 0x03);
+    write(&mut cartrige, 0xB003, 0x00);
+    assert_eq!(
+        cartrige.read(CartrigeAccess::PpuAccess { address: 0x0410 }),
+        Some(NESTEST[0x4010 + 0x0C10])
+    );
+    write(&mut cartrige, 0x9000, 0x01);
+    assert_eq!(cartrige.map_nametable(0x2C10), 0x2810);
+}
+
+#[test]
+fn mmc3_scanline_irq() {
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(4);
+    let rom = header.write_to_rom(NESTEST).unwrap();
+    let cartrige = Arc::new(Mutex::new(Cartrige::from_bytes(&rom).unwrap()));
+    let write = |address: u16, value: u8| {
+        cartrige
+            .lock()
+            .unwrap()
+            .write(CartrigeAccess::CpuAccess { address }, value)
+    };
+    let irq = || cartrige.lock().unwrap().tick();
+    let mut ppu = Ppu::new();
+    ppu.insert_cartrige(cartrige.clone());
+    let set_address = |ppu: &mut Ppu, address: u16, low_dots: usize| {
+        ppu.write_register(0x2006, 0x00);
+        ppu.write_register(0x2006, 0x00);
+        for _ in 0..low_dots {
+            ppu.tick();
+        }
+        ppu.write_register(0x2006, (address >> 8) as u8);
+        ppu.write_register(0x2006, address as u8);
+    };
+
+    // the first clock loads the latch, the next ones count down to 0
+    write(0xC000, 1);
+    write(0xC001, 0);
+    write(0xE001, 0);
+    set_address(&mut ppu, 0x1000, 10);
+    assert_eq!(irq(), None);
+    set_address(&mut ppu, 0x1000, 10);
+    assert_eq!(irq(), Some(true));
+    write(0xE000, 0);
+    assert_eq!(irq(), Some(false));
+
+    // A12 has to be low for 8 dots for a rise to count
+    write(0xE001, 0);
+    for _ in 0..4 {
+        set_address(&mut ppu, 0x1000, 2);
+    }
+    set_address(&mut ppu, 0x1000, 10);
+    assert_eq!(irq(), None);
+    set_address(&mut ppu, 0x1000, 10);
+    assert_eq!(irq(), Some(true));
+
+    // background on $0000 and sprites on $1000 clock once a line, on the
+    // same line with both renderers
+    for accuracy in [PpuAccuracy::Cycle, PpuAccuracy::Scanline] {
+        let cartrige = Arc::new(Mutex::new(Cartrige::from_bytes(&rom).unwrap()));
+        let mut ppu = Ppu::new();
+        ppu.insert_cartrige(cartrige.clone());
+        ppu.set_accuracy(accuracy);
+        ppu.write_register(0x2000, 0x08);
+        ppu.write_register(0x2001, 0x18);
+        for (address, value) in [(0xC000, 10), (0xC001, 0), (0xE001, 0)] {
+            cartrige
+                .lock()
+                .unwrap()
+                .write(CartrigeAccess::CpuAccess { address }, value);
+        }
+        while cartrige.lock().unwrap().tick().is_none() {
+            ppu.tick();
+        }
+        let (scanline, dot) = ppu.get_position();
+        assert_eq!(scanline, 10, "{accuracy:?}");
+        assert!(
+            (257..=270).contains(&dot),
+            "{accuracy:?} fired on dot {dot}"
+        );
+    }
+}
+
+#[test]
+fn game_genie_pass_through() {
+    let prg = &NESTEST[0x10..0x4010];
+    let mut genie_rom = Header::from_bytes(NESTEST).unwrap().to_bytes().to_vec();
+    genie_rom.extend_from_slice(&[0xEA; 0x4000]);
+    genie_rom.extend_from_slice(&[0x55; 0x2000]);
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(NESTEST).unwrap());
+    nes.attach_game_genie(Cartrige::from_bytes(&genie_rom).unwrap());
+
+    // the menu runs from the Game Genie's rom and pattern tables
+    assert_eq!(nes.bus.read(0x8010), 0xEA);
+    assert_eq!(
+        nes.ppu.lock().unwrap().read_ppu_bus(0x0010),
+        0x55,
+        "chr comes from the Game Genie"
+    );
+
+    // code 0 always replaces $8010, code 1 only if $8020 has the wrong
+    // value and code 2 is off
+    let codes = [
+        (0x8001, 0x00),
+        (0x8002, 0x10),
+        (0x8004, 0x42),
+        (0x8005, 0x00),
+        (0x8006, 0x20),
+        (0x8007, prg[0x20] ^ 0xFF),
+        (0x8008, 0x43),
+        (0x8009, 0x00),
+        (0x800A, 0x30),
+        (0x800C, 0x44),
+        (0x8000, 0b0010_1000),
+        (0x8000, 0x00),
+    ];
+    for (address, value) in codes {
+        nes.bus.write(address, value);
+    }
+    assert_eq!(nes.bus.read(0x8010), 0x42);
+    assert_eq!(nes.bus.read(0xC010), prg[0x10]);
+    assert_eq!(nes.bus.read(0x8020), prg[0x20]);
+    assert_eq!(nes.bus.read(0x8030), prg[0x30]);
+    assert_eq!(
+        nes.ppu.lock().unwrap().read_ppu_bus(0x0010),
+        NESTEST[0x4010 + 0x10]
+    );
+
+    // resets keep the codes, power cycles go back to the menu
+    let state = nes.save_state();
+    nes.reset();
+    assert_eq!(nes.bus.read(0x8010), 0x42);
+    nes.power_cycle();
+    assert_eq!(nes.bus.read(0x8010), 0xEA);
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.bus.read(0x8010), 0x42);
+
+    nes.detach_game_genie();
+    assert_eq!(nes.bus.read(0x8010), prg[0x10]);
+}
+
+#[test]
+fn flat_binary() {
+    let program = assemble(
+        0x9000,
+        "
+        LDA #$42
+        STA $10
+    loop:
+        JMP loop
+        ",
+    )
+    .unwrap();
+    let options = FlatOptions {
+        load_address: 0x9000,
+        ..FlatOptions::default()
+    };
+    let mut nes = Nes::new_with_cartrige(Cartrige::from_flat_binary(&program, &options).unwrap());
+    assert_eq!(nes.bus.read(0xFFFC), 0x00);
+    assert_eq!(nes.bus.read(0xFFFD), 0x90);
+    assert_eq!(nes.bus.read(0xFFFA), 0xF9);
+    assert_eq!(nes.bus.read(0xFFF9), 0x40, "unhandled interrupts return");
+    nes.reset();
+    for _ in 0..300 {
+        nes.tick();
+    }
+    assert_eq!(nes.bus.read(0x10), 0x42);
+
+    // binaries can start in prg ram and keep their own vectors
+    let mut binary = vec![0xEA; 0x10000 - 0x7FFE];
+    binary[0xFFFC - 0x7FFE..0xFFFE - 0x7FFE].copy_from_slice(&[0x34, 0x12]);
+    let options = FlatOptions {
+        load_address: 0x7FFE,
+        irq: Some(0x8000),
+        ..FlatOptions::default()
+    };
+    let cartrige = Cartrige::from_flat_binary(&binary, &options).unwrap();
+    assert_eq!(cartrige.get_prg_ram()[0x1FFE..], [0xEA, 0xEA]);
+    assert_eq!(cartrige.get_prg_ram()[0], 0);
+    let rom = cartrige.to_bytes();
+    assert_eq!(rom[16 + 0x7FFC..], [0x34, 0x12, 0x00, 0x80]);
+
+    for load_address in [0x5FFF, 0xFFFF] {
+        let options = FlatOptions {
+            load_address,
+            ..FlatOptions::default()
+        };
+        assert!(matches!(
+            Cartrige::from_flat_binary(&[0, 0], &options),
+            Err(CartrigeParseError::FlatBinaryError(2, _))
+        ));
+    }
+}
+
+fn bps_number(mut value: usize, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte | 0x80);
+            return;
+        }
+        out.push(byte);
+        value -= 1;
+    }
+}
+
+#[test]
+fn soft_patching() {
+    let mut expected = NESTEST.to_vec();
+    expected[0x10] = 0x42;
+    expected[0x20..0x24].fill(0xEA);
+
+    // one normal record and one run
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x00, 0x10, 0x00, 0x01, 0x42]);
+    ips.extend_from_slice(&[0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x04, 0xEA]);
+    ips.extend_from_slice(b"EOF");
+    assert_eq!(patch::apply(NESTEST, &ips).unwrap(), expected);
+
+    // source read, target read, then a source copy of the rest
+    let mut bps = b"BPS1".to_vec();
+    bps_number(NESTEST.len(), &mut bps);
+    bps_number(expected.len(), &mut bps);
+    bps_number(0, &mut bps);
+    bps_number((0x10 - 1) << 2, &mut bps);
+    bps_number(((0x14 - 1) << 2) | 1, &mut bps);
+    bps.extend_from_slice(&expected[0x10..0x24]);
+    bps_number(((NESTEST.len() - 0x24 - 1) << 2) | 2, &mut bps);
+    bps_number(0x24 << 1, &mut bps);
+    bps.extend_from_slice(&crc32fast::hash(NESTEST).to_le_bytes());
+    bps.extend_from_slice(&crc32fast::hash(&expected).to_le_bytes());
+    bps.extend_from_slice(&crc32fast::hash(&bps).to_le_bytes());
+    assert_eq!(patch::apply(NESTEST, &bps).unwrap(), expected);
+    assert!(matches!(
+        patch::apply(&expected, &bps),
+        Err(PatchError::ChecksumError("source rom", _))
+    ));
+
+    // a patch next to the rom gets picked up unless it's ignored
+    let directory = env::temp_dir().join("scamu_soft_patching");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let rom_path = directory.join("game.nes");
+    std::fs::write(&rom_path, NESTEST).unwrap();
+    std::fs::write(rom_path.with_extension("ips"), &ips).unwrap();
+    let rom_path = rom_path.to_str().unwrap();
+    let patched = Cartrige::from_file(rom_path).unwrap();
+    assert_eq!(patched.to_bytes(), expected);
+    let options = LoadOptions {
+        ignore_patches: true,
+        ..Default::default()
+    };
+    let original = Cartrige::from_file_with_options(rom_path, &options).unwrap();
+    assert_eq!(original.to_bytes(), NESTEST);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
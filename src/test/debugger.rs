@@ -0,0 +1,725 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    debugger::{
+        Debugger,
+        call_stack::{CallStackWarning, FrameKind},
+        debug_info::{DebugInfo, SourceLine},
+        disassembler::{Disassembler, DisassemblyFormat, DisassemblyOptions},
+        event_viewer::{EventKind, EventViewer},
+        heatmap::{AccessCounts, AccessHeatmap},
+        interrupt_log::InterruptKind,
+        register_log::{RegisterFilter, RegisterLog},
+    },
+    devices::{dump::Region, error::DumpError, nes::Nes, run::BreakReason},
+    hardware::{
+        cartrige::Cartrige,
+        constants::ppu::{DOTS_PER_SCANLINE, PATTERN_TABLE_VIEW_HEIGHT, PATTERN_TABLE_VIEW_WIDTH},
+        cpu::{CpuConfig, CpuVariant, assembler::assemble},
+        ppu::frame::Frame,
+    },
+};
+
+fn nestest_nes() -> Nes {
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap());
+    nes.reset_with_program_counter(0xC000);
+    nes
+}
+
+#[test]
+fn access_heatmap() {
+    let mut nes = nestest_nes();
+    let program = assemble(
+        0xC000,
+        "
+    loop:
+        LDA $0300
+        STA $0301
+        JMP loop
+        ",
+    )
+    .unwrap();
+    nes.patch_memory(0xC000, &program);
+    let heatmap = Arc::new(Mutex::new(AccessHeatmap::new(1)));
+    nes.add_bus_observer(heatmap.clone());
+    nes.run_instructions(30);
+
+    let mut heatmap = heatmap.lock().unwrap();
+    assert_eq!(heatmap.get_counts(0xC000).executes, 10);
+    let operand = heatmap.get_counts(0xC002);
+    assert_eq!(
+        (operand.reads, operand.executes),
+        (0, 10),
+        "operands are code"
+    );
+    let data = heatmap.get_counts(0x0300);
+    assert_eq!((data.reads, data.writes, data.executes), (10, 0, 0));
+    assert_eq!(heatmap.get_counts(0x0301).writes, 10);
+    assert!(heatmap.get_counts(0xC009).is_empty());
+
+    let mut csv = Vec::new();
+    heatmap.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert!(csv.starts_with("address,reads,writes,executes\n"));
+    assert!(csv.contains("$0300,10,0,0\n"));
+
+    assert_eq!(heatmap.image_size(), (256, 256));
+    let rgb = heatmap.to_rgb();
+    assert_eq!(rgb[0x0300 * 3..0x0300 * 3 + 3], [0, 255, 0]);
+    let mut png = Vec::new();
+    heatmap.write_png(&mut png).unwrap();
+    assert_eq!(png[1..4], *b"PNG");
+
+    // coarse buckets add up, turned off nothing gets counted
+    heatmap.clear();
+    heatmap.set_enabled(false);
+    drop(heatmap);
+    nes.run_instructions(30);
+    let heatmap = Arc::new(Mutex::new(AccessHeatmap::new(12)));
+    nes.add_bus_observer(heatmap.clone());
+    nes.run_instructions(30);
+    let heatmap = heatmap.lock().unwrap();
+    assert_eq!(heatmap.get_bucket_size(), 16);
+    assert_eq!(heatmap.image_size(), (64, 64));
+    assert_eq!(heatmap.get_counts(0xC00F).executes, 90);
+    assert_eq!(
+        heatmap.touched().next(),
+        Some((
+            0x0300,
+            AccessCounts {
+                reads: 10,
+                writes: 10,
+                executes: 0
+            }
+        ))
+    );
+    drop(heatmap);
+
+    // instructions are decoded like the configured cpu does, $B2 is
+    // `LDA ($10)` on a 65C02 and jams a 2A03
+    let mut nes = nestest_nes();
+    nes.patch_memory(0xC000, &[0xB2, 0x10, 0x4C, 0x00, 0xC0]);
+    nes.set_cpu_config(CpuConfig {
+        variant: CpuVariant::Cmos65C02,
+        ..Default::default()
+    });
+    let heatmap = Arc::new(Mutex::new(AccessHeatmap::new(1)));
+    nes.add_bus_observer(heatmap.clone());
+    nes.run_instructions(4);
+    let heatmap = heatmap.lock().unwrap();
+    assert_eq!(heatmap.get_counts(0xC001).executes, 2);
+}
+
+#[test]
+fn disassembly() {
+    let nes = nestest_nes();
+    let instructions = Debugger::disassemble(&nes, 0xC000, 2);
+    assert_eq!(instructions[0].address, 0xC000);
+    assert_eq!(instructions[0].bytes, [0x4C, 0xF5, 0xC5]);
+    assert_eq!(instructions[0].text, "JMP $C5F5");
+    assert!(!instructions[0].is_illegal);
+    assert_eq!(instructions[1].address, 0xC003);
+
+    let rows = Debugger::memory_rows(&nes, 0xC005, 2);
+    assert_eq!(rows[0].0, 0xC000);
+    assert_eq!(rows[0].1[..3], [0x4C, 0xF5, 0xC5]);
+    assert_eq!(rows[1].0, 0xC010);
+
+    let pixels = Debugger::pattern_tables(&nes, 0);
+    assert_eq!(
+        pixels.len(),
+        PATTERN_TABLE_VIEW_WIDTH * PATTERN_TABLE_VIEW_HEIGHT
+    );
+}
+
+const DISASSEMBLY_PROGRAM: [u8; 20] = [
+    0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0x8D, 0x10, 0x00, 0x04, 0xA9, 0x4C, 0x02, 0x80, 0x01, 0x02, 0x03,
+    0x6C, 0x00, 0x02, 0x20,
+];
+
+#[test]
+fn disassembly_formats() {
+    let mut options = DisassemblyOptions {
+        origin: 0x8000,
+        data_ranges: vec![0x800D..0x8010, 0x8013..0x8014],
+        ..Default::default()
+    };
+    let listing = Disassembler::new(options.clone()).disassemble(&DISASSEMBLY_PROGRAM);
+    assert_eq!(
+        listing,
+        "\
+8000  A2 05     LDX #$05
+8002  CA        DEX
+8003  D0 FD     BNE $8002
+8005  8D 10 00  STA $0010
+8008  04 A9    *NOP $A9
+800A  4C 02 80  JMP $8002
+800D  01        .byte $01
+800E  02        .byte $02
+800F  03        .byte $03
+8010  6C 00 02  JMP ($0200)
+8013  20        .byte $20
+"
+    );
+
+    options.format = DisassemblyFormat::Ca65;
+    let source = Disassembler::new(options.clone()).disassemble(&DISASSEMBLY_PROGRAM);
+    assert_eq!(
+        source,
+        "\
+.org $8000
+        ldx #$05
+L8002:
+        dex
+        bne L8002
+        sta a:$0010
+        .byte $04, $A9 ; NOP $A9
+        jmp L8002
+        .byte $01, $02, $03
+        jmp ($0200)
+        .byte $20
+"
+    );
+
+    let records = Disassembler::new(options).records(&DISASSEMBLY_PROGRAM);
+    let json = serde_json::to_value(&records).unwrap();
+    assert_eq!(json[2]["mnemonic"], "BNE");
+    assert_eq!(json[2]["mode"], "Relative");
+    assert_eq!(json[2]["operand"], 0xFD);
+    assert_eq!(json[2]["target"], 0x8002);
+    assert_eq!(json[4]["is_illegal"], true);
+    assert_eq!(json[6]["mnemonic"], serde_json::Value::Null);
+}
+
+#[test]
+fn cartrige_disassembly() {
+    let nestest = Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let banks = Disassembler::default().cartrige_records(&nestest);
+    assert_eq!(banks.len(), 1);
+    assert_eq!((banks[0].address, banks[0].end_address()), (0xC000, 0xFFFF));
+
+    // mmc3 with 4 prg banks, the last one jumps to itself from every vector
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 0, 0x40, 0];
+    rom.resize(16, 0);
+    let mut prg = vec![0xEA; 0x8000];
+    prg[0x6000..0x6003].copy_from_slice(&[0x4C, 0x00, 0xE0]);
+    for vector in prg[0x7FFA..].chunks_mut(2) {
+        vector.copy_from_slice(&0xE000u16.to_le_bytes());
+    }
+    rom.extend_from_slice(&prg);
+    let cartrige = Cartrige::from_bytes(&rom).unwrap();
+
+    let mut disassembler = Disassembler::default();
+    let banks = disassembler.cartrige_records(&cartrige);
+    let addresses: Vec<u16> = banks.iter().map(|bank| bank.address).collect();
+    assert_eq!(addresses, [0x8000, 0x8000, 0xC000, 0xE000]);
+    let vectors = &banks[3].records[banks[3].records.len() - 3..];
+    assert!(
+        vectors
+            .iter()
+            .all(|vector| vector.is_data() && vector.target == Some(0xE000))
+    );
+    assert_eq!(vectors[1].address, 0xFFFC);
+
+    let listing = disassembler.disassemble_cartrige(&cartrige);
+    assert!(listing.starts_with("; bank 0 $8000-$9FFF\n8000  EA        NOP\n"));
+    assert!(listing.contains("; bank 3 $E000-$FFFF\nE000  4C 00 E0  JMP $E000\n"));
+    assert!(listing.ends_with("FFFC  00 E0     .word $E000\nFFFE  00 E0     .word $E000\n"));
+
+    disassembler = Disassembler::new(DisassemblyOptions {
+        format: DisassemblyFormat::Ca65,
+        ..Default::default()
+    });
+    let source = disassembler.disassemble_cartrige(&cartrige);
+    assert_eq!(source.matches(".scope").count(), 4);
+    assert!(source.contains(".scope Bank3\n.org $E000\nLE000:\n        jmp LE000\n"));
+    assert!(source.ends_with("        .word LE000\n.endscope\n"));
+}
+
+#[test]
+fn breakpoints() {
+    let mut nes = nestest_nes();
+    let mut debugger = Debugger::new();
+    let mut frame = Frame::new();
+    debugger.add_breakpoint(0xC5F5);
+    debugger.add_breakpoint(0xC5F5);
+    assert_eq!(debugger.get_breakpoints().len(), 1);
+
+    let summary = debugger.run_frame(&mut nes, &mut frame);
+    assert_eq!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0xC5F5)
+    );
+    assert_eq!(summary.instructions, 1);
+
+    // continuing doesn't stop on the same instruction again
+    debugger.add_breakpoint(0xC5F7);
+    debugger.set_breakpoint_enabled(0xC5F7, false);
+    let summary = debugger.run_frame(&mut nes, &mut frame);
+    assert_ne!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0xC5F5)
+    );
+    assert_ne!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0xC5F7)
+    );
+}
+
+/// ```text
+/// 0200: JSR sub
+/// 0203: LDA #1
+/// 0205: JMP $0205
+/// 0210: sub: LDX #5
+/// 0212: loop: DEX
+/// 0213: BNE loop
+/// 0215: RTS
+/// ```
+const SOURCE_PROGRAM: [(u16, &[u8]); 2] = [
+    (0x0200, &[0x20, 0x10, 0x02, 0xA9, 0x01, 0x4C, 0x05, 0x02]),
+    (0x0210, &[0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0x60]),
+];
+
+const SOURCE_DEBUG_INFO: &str = "\
+version	major=2,minor=0
+file	id=0,name=\"src/main.s\",size=300,mtime=0x65F0A1B2,mod=0
+file	id=1,name=\"macros, and more.inc\",size=80,mtime=0x65F0A1B2,mod=0
+seg	id=0,name=\"CODE\",start=0x000200,size=0x0016,addrsize=absolute,type=ro
+span	id=0,seg=0,start=0,size=3
+span	id=1,seg=0,start=3,size=2
+span	id=2,seg=0,start=16,size=6
+span	id=3,seg=0,start=16,size=2
+span	id=4,seg=0,start=18,size=1
+line	id=0,file=0,line=1
+line	id=1,file=0,line=3,span=0
+line	id=2,file=0,line=4,span=1
+line	id=3,file=0,line=9,span=2
+line	id=4,file=0,line=10,span=3
+line	id=5,file=1,line=2,type=2,span=4
+line	id=6,file=0,line=11,span=4
+";
+
+#[test]
+fn source_level_debugging() {
+    let debug_info = DebugInfo::parse(SOURCE_DEBUG_INFO).unwrap();
+    let line = |file: &str, line| {
+        Some(SourceLine {
+            file: file.to_string(),
+            line,
+        })
+    };
+    assert_eq!(
+        debug_info.source_line(0x0201).cloned(),
+        line("src/main.s", 3)
+    );
+    // the smallest span wins over the whole .proc
+    assert_eq!(
+        debug_info.source_line(0x0211).cloned(),
+        line("src/main.s", 10)
+    );
+    // the line using the macro wins over the macro
+    assert_eq!(
+        debug_info.source_line(0x0212).cloned(),
+        line("src/main.s", 11)
+    );
+    assert_eq!(
+        debug_info.source_line(0x0213).cloned(),
+        line("src/main.s", 9)
+    );
+    assert_eq!(debug_info.source_line(0x0300), None);
+    assert!(
+        DebugInfo::parse("span\tid=0,seg=3,start=0,size=1\nline\tid=0,file=0,line=1,span=0")
+            .is_err()
+    );
+
+    let mut nes = Nes::new();
+    for (address, code) in SOURCE_PROGRAM {
+        nes.write_memory(address, code);
+    }
+    nes.reset_with_program_counter(0x0200);
+    let mut debugger = Debugger::new();
+    assert!(debugger.add_source_breakpoint("main.s", 4).is_empty());
+    debugger.set_debug_info(Some(debug_info));
+    assert_eq!(
+        debugger.current_source_line(&nes).cloned(),
+        line("src/main.s", 3)
+    );
+
+    // stepping over the JSR runs the whole subroutine
+    let summary = debugger.step_over(&mut nes);
+    assert_eq!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0x0203)
+    );
+    assert_eq!(nes.cpu.lock().unwrap().get_registers().x, 0);
+    assert_eq!(
+        debugger.current_source_line(&nes).cloned(),
+        line("src/main.s", 4)
+    );
+    debugger.step_over(&mut nes);
+    assert_eq!(nes.cpu.lock().unwrap().get_program_counter(), 0x0205);
+
+    // unless there is a breakpoint in it
+    nes.reset_with_program_counter(0x0200);
+    assert_eq!(debugger.add_source_breakpoint("main.s", 11), [0x0212]);
+    let summary = debugger.step_over(&mut nes);
+    assert_eq!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0x0212)
+    );
+}
+
+#[test]
+fn event_viewer() {
+    let mut nes = Nes::new();
+    let viewer = Arc::new(Mutex::new(EventViewer::new()));
+    nes.add_bus_observer(viewer.clone());
+    viewer.lock().unwrap().set_enabled(EventKind::Input, false);
+    nes.next_frame();
+
+    let position = nes.ppu.lock().unwrap().get_position();
+    for address in [0x0000, 0x2001, 0x4000, 0x4016, 0x8000] {
+        nes.bus.write(address, 0x1E);
+    }
+    assert_eq!(viewer.lock().unwrap().get_current_events().len(), 3);
+    nes.next_frame();
+
+    let viewer = viewer.lock().unwrap();
+    let kinds: Vec<EventKind> = viewer.get_events().iter().map(|e| e.kind).collect();
+    assert_eq!(kinds, [EventKind::Ppu, EventKind::Apu, EventKind::Mapper]);
+    let write = viewer.get_events()[0].write;
+    assert_eq!((write.address, write.value), (0x2001, 0x1E));
+    assert_eq!((write.scanline, write.dot), position);
+
+    let heatmap = viewer.heatmap();
+    let (scanline, dot) = position;
+    assert_eq!(
+        heatmap[scanline as usize * DOTS_PER_SCANLINE + dot as usize],
+        3
+    );
+    assert_eq!(
+        heatmap.iter().map(|count| *count as usize).sum::<usize>(),
+        3
+    );
+}
+
+#[test]
+fn memory_dumps() {
+    let mut nes = nestest_nes();
+    nes.run_cycles(20_000);
+    let mut dumps = Vec::new();
+    for region in Region::ALL {
+        assert_eq!(region.to_string().parse::<Region>().unwrap(), region);
+        let mut dump = Vec::new();
+        nes.dump_region(region, &mut dump).unwrap();
+        dumps.push(dump);
+    }
+    assert_eq!(
+        dumps[..4].iter().map(Vec::len).collect::<Vec<_>>(),
+        [0x800, 0x1000, 256, 32]
+    );
+    assert_eq!(
+        dumps[0][0x10..0x20],
+        (0x10..0x20)
+            .map(|address| nes.bus.peek(address))
+            .collect::<Vec<_>>()
+    );
+
+    // running on changes the ram, restoring puts it back
+    nes.run_cycles(20_000);
+    for (region, dump) in Region::ALL.into_iter().zip(dumps.iter()) {
+        nes.restore_region(region, &mut dump.as_slice()).unwrap();
+        let mut restored = Vec::new();
+        nes.dump_region(region, &mut restored).unwrap();
+        assert_eq!(&restored, dump);
+    }
+
+    assert!(matches!(
+        nes.restore_region(Region::Oam, &mut [0; 10].as_slice()),
+        Err(DumpError::SizeMismatchError(Region::Oam, 10, 256))
+    ));
+    assert!(matches!(
+        "vram".parse::<Region>(),
+        Err(DumpError::UnknownRegionError(_))
+    ));
+    assert!(matches!(
+        Nes::new().dump_region(Region::PrgRam, &mut Vec::new()),
+        Err(DumpError::NoCartrigeError)
+    ));
+}
+
+#[test]
+fn watchpoints() {
+    let mut nes = nestest_nes();
+    let program = assemble(
+        0xC000,
+        "
+        LDA #$03
+        STA $30
+        STA $30
+    loop:
+        DEC $30
+        JMP loop
+        ",
+    )
+    .unwrap();
+    nes.patch_memory(0xC000, &program);
+    let mut debugger = Debugger::new();
+    debugger.attach(&mut nes);
+    let mut frame = Frame::new();
+
+    // the mirror is watched too
+    debugger.add_watchpoint(0x0830, Some(1));
+    let summary = debugger.run_frame(&mut nes, &mut frame);
+    assert_eq!(summary.break_reason, BreakReason::Watchpoint(0x0030));
+    let hit = debugger.take_watch_hit().unwrap();
+    assert_eq!((hit.write.previous_value, hit.write.value), (2, 1));
+    assert_eq!(hit.write.program_counter, 0xC006);
+    assert_eq!(nes.bus.peek(0x30), 1);
+    assert!(
+        hit.to_string()
+            .starts_with("$0030 changed from 02 to 01 by the instruction at $C006")
+    );
+
+    // any change, the second store doesn't change anything
+    let mut nes = nestest_nes();
+    nes.patch_memory(0xC000, &program);
+    debugger.attach(&mut nes);
+    debugger.add_watchpoint(0x0830, None);
+    assert_eq!(debugger.get_watchpoints().len(), 1);
+    debugger.run_frame(&mut nes, &mut frame);
+    assert_eq!(
+        debugger.take_watch_hit().unwrap().write.program_counter,
+        0xC002
+    );
+    let summary = debugger.run_frame(&mut nes, &mut frame);
+    assert_eq!(summary.break_reason, BreakReason::Watchpoint(0x0030));
+    assert_eq!(
+        debugger.take_watch_hit().unwrap().write.program_counter,
+        0xC006
+    );
+
+    debugger.set_watchpoint_enabled(0x0830, false);
+    let summary = debugger.run_frame(&mut nes, &mut frame);
+    assert_eq!(summary.break_reason, BreakReason::FrameDone);
+}
+
+#[test]
+fn call_stack() {
+    let mut nes = nestest_nes();
+    let program = assemble(
+        0xC000,
+        "
+        JSR outer
+        JSR tricky
+    loop:
+        JMP loop
+    done:
+        JMP done
+    outer:
+        JSR inner
+        RTS
+    inner:
+        NOP
+        RTS
+    tricky:
+        LDA #$C0
+        PHA
+        LDA #$08
+        PHA
+        RTS
+        ",
+    )
+    .unwrap();
+    nes.patch_memory(0xC000, &program);
+    let mut debugger = Debugger::new();
+    let mut frame = Frame::new();
+
+    debugger.add_breakpoint(0xC010);
+    let summary = debugger.run_frame(&mut nes, &mut frame);
+    assert_eq!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0xC010)
+    );
+    let frames = debugger.call_stack();
+    assert_eq!(
+        frames
+            .iter()
+            .map(|frame| (frame.kind, frame.call_site, frame.target))
+            .collect::<Vec<_>>(),
+        vec![
+            (FrameKind::Subroutine, 0xC000, 0xC00C),
+            (FrameKind::Subroutine, 0xC00C, 0xC010),
+        ]
+    );
+    assert_eq!(
+        debugger.call_stack_report(),
+        "#0 $C010 from JSR at $C00C\n#1 $C00C from JSR at $C000\n"
+    );
+
+    // the rts trick jumps to done instead of returning
+    debugger.clear_breakpoints();
+    debugger.add_breakpoint(0xC009);
+    let summary = debugger.run_frame(&mut nes, &mut frame);
+    assert_eq!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0xC009)
+    );
+    assert_eq!(
+        debugger.take_call_stack_warnings(),
+        vec![CallStackWarning::UnmatchedReturn {
+            address: 0xC018,
+            return_address: 0xC009
+        }]
+    );
+    assert!(debugger.take_call_stack_warnings().is_empty());
+    assert_eq!(debugger.call_stack().len(), 1);
+    assert_eq!(debugger.call_stack()[0].target, 0xC012);
+
+    debugger.clear_call_stack();
+    assert!(debugger.call_stack().is_empty());
+}
+
+#[test]
+fn stack_view() {
+    let mut nes = nestest_nes();
+    let program = assemble(
+        0xC000,
+        "
+        LDA #$12
+        PHA
+        LDA #$34
+        PHA
+        ",
+    )
+    .unwrap();
+    nes.patch_memory(0xC000, &program);
+    nes.run_instructions(4);
+
+    let stack = Debugger::stack(&nes);
+    assert_eq!(stack.stack_pointer, 0xFB);
+    assert_eq!(stack.top_address(), 0x01FB);
+    assert_eq!(&stack.used()[..2], &[0x34, 0x12]);
+    assert_eq!(stack.bytes[0xFC], 0x34);
+}
+
+#[test]
+fn interrupt_log() {
+    let mut nes = nestest_nes();
+    let program = assemble(
+        0xC000,
+        "
+        BRK
+        NOP
+        LDA #$80
+        STA $2000
+    loop:
+        JMP loop
+    nmi:
+        INC $10
+        RTI
+    irq:
+        RTI
+        ",
+    )
+    .unwrap();
+    nes.patch_memory(0xC000, &program);
+    // nmi and irq vectors
+    nes.patch_memory(0xFFFA, &[0x0A, 0xC0]);
+    nes.patch_memory(0xFFFE, &[0x0D, 0xC0]);
+    let debugger = Debugger::new();
+    let mut frame = Frame::new();
+    for _ in 0..3 {
+        debugger.run_frame(&mut nes, &mut frame);
+    }
+
+    let interrupts = debugger.interrupt_log();
+    let brk = interrupts[0];
+    assert_eq!(brk.kind, InterruptKind::Brk);
+    assert_eq!((brk.address, brk.handler), (0xC000, 0xC00D));
+    // 7 for BRK and 6 for RTI
+    assert_eq!(brk.cycles, Some(13));
+
+    let nmis = &interrupts[1..];
+    assert!(!nmis.is_empty());
+    for nmi in nmis {
+        assert_eq!(nmi.kind, InterruptKind::Nmi);
+        assert_eq!(nmi.handler, 0xC00A);
+        assert_eq!(nmi.scanline, 241);
+        // 5 for INC and 6 for RTI
+        assert_eq!(nmi.cycles, Some(11));
+    }
+    assert_eq!(nes.bus.peek(0x10) as usize, nmis.len());
+    // every handler returned to where it came from
+    assert!(debugger.call_stack().is_empty());
+    assert!(debugger.take_call_stack_warnings().is_empty());
+
+    debugger.dump_interrupt_log();
+    debugger.clear_interrupt_log();
+    assert!(debugger.interrupt_log().is_empty());
+}
+
+#[test]
+fn register_log() {
+    let mut nes = nestest_nes();
+    let program = assemble(
+        0xC000,
+        "
+        LDA #$10
+        STA $2005
+        STA $2000
+        STA $2805
+        LDA $2002
+        LDA $2005
+        ",
+    )
+    .unwrap();
+    nes.patch_memory(0xC000, &program);
+    let filter: RegisterFilter = "$2005, 2002".parse().unwrap();
+    assert_eq!(filter.registers, vec![0x2005, 0x2002]);
+    assert!("$2005,nope".parse::<RegisterFilter>().is_err());
+    let log = Arc::new(Mutex::new(RegisterLog::new(filter)));
+    nes.add_bus_observer(log.clone());
+    nes.run_instructions(6);
+
+    let accesses: Vec<_> = log.lock().unwrap().get_accesses().copied().collect();
+    assert_eq!(
+        accesses
+            .iter()
+            .map(|access| (access.is_write, access.address, access.program_counter))
+            .collect::<Vec<_>>(),
+        vec![
+            (true, 0x2005, 0xC002),
+            // mirrors count too
+            (true, 0x2805, 0xC008),
+            (false, 0x2002, 0xC00B),
+            (false, 0x2005, 0xC00E),
+        ]
+    );
+    assert!(
+        accesses[0]
+            .to_string()
+            .starts_with("write $2005 = 10 at $C002")
+    );
+
+    // only writes
+    {
+        let mut log = log.lock().unwrap();
+        log.clear();
+        let filter = RegisterFilter {
+            reads: false,
+            ..log.get_filter().clone()
+        };
+        log.set_filter(filter);
+    }
+    nes.reset_with_program_counter(0xC000);
+    nes.run_instructions(6);
+    assert!(
+        log.lock()
+            .unwrap()
+            .get_accesses()
+            .all(|access| access.is_write)
+    );
+    assert_eq!(log.lock().unwrap().get_accesses().count(), 2);
+}
@@ -0,0 +1,1105 @@
+use std::{env, io::Cursor, path::Path};
+
+use crate::{
+    debugger::Debugger,
+    devices::{entropy::RamInit, nes::Nes, run::BreakReason},
+    frontend::{
+        compat::{CompatDatabase, CompatStatus},
+        desync::{self, VerificationLog},
+        error::{GoldenRunError, InputScriptError, KeymapError},
+        filters::{FilterUniforms, ShaderFilter},
+        golden::{GoldenMismatch, GoldenRun},
+        host::{Frontend, FrontendExit, InputEvent, NullFrontend, run_frontend},
+        input_script::InputScript,
+        keymap::{Hotkey, KeyCombo, KeyConflict, Keymap},
+        lockstep::Lockstep,
+        pipe::PipeFrontend,
+        practice::{PracticePoints, RamWatch},
+        runner::Runner,
+        scaling::{Overscan, ScalingConfig, ScalingMode, Viewport},
+        scan::ScanReport,
+        screenshots::render_frames,
+        slots::{SLOT_COUNT, SaveSlots},
+        speed::{AudioStretch, MAX_RATE_DEVIATION, MAX_SPEED, RateControl, Resampler, Wsola},
+        terminal::{ColorMode, KEY_HOLD_FRAMES, TerminalFrontend},
+        testsuite::{PassCondition, TestRom, TestStatus, TestSuite},
+        watch::RomWatcher,
+        wav::{AudioCapture, render_audio},
+        window::{TitleStatus, WindowMode, WindowState, window_title},
+    },
+    hardware::{
+        cartrige::{Cartrige, Header, LoadOptions},
+        cpu::{CpuConfig, CpuVariant, assembler::assemble},
+        input::controller::{Button, ControllerState},
+        ppu::frame::Frame,
+    },
+    osd::{DIM_COLOR, TEXT_COLOR, notifications::Notifications, slot_picker::SlotPicker},
+    save_state::metadata::{StateMetadata, Thumbnail},
+};
+
+#[test]
+fn scaling_viewports() {
+    let mut config = ScalingConfig::default();
+    assert_eq!(
+        config.viewport(1920, 1080),
+        Viewport {
+            x: 448,
+            y: 60,
+            width: 1024,
+            height: 960
+        }
+    );
+
+    config.correct_aspect_ratio = true;
+    assert_eq!(config.viewport(1920, 1080).width, 1170);
+
+    config.mode = ScalingMode::Fit;
+    config.correct_aspect_ratio = false;
+    config.overscan = Overscan::NTSC;
+    assert_eq!(
+        config.viewport(1920, 1080),
+        Viewport {
+            x: 343,
+            y: 0,
+            width: 1234,
+            height: 1080
+        }
+    );
+
+    config.mode = ScalingMode::Stretch;
+    assert_eq!(config.viewport(300, 200).width, 300);
+
+    // smaller than the frame, integer scaling can't go below 1
+    config.mode = ScalingMode::Integer;
+    assert_eq!(config.viewport(128, 112).height, 112);
+}
+
+#[test]
+fn scaling_blit() {
+    let mut frame = Frame::new();
+    frame.set_pixel(0, 8, 0xFF0000);
+    frame.set_pixel(255, 231, 0x00FF00);
+
+    let config = ScalingConfig {
+        overscan: Overscan::NTSC,
+        background: 0x0000FF,
+        ..Default::default()
+    };
+    let (width, height) = (600, 500);
+    let mut surface = vec![0; width * height];
+    config.blit(&frame, &mut surface, width, height);
+
+    // 2x scale, 512x448 in the middle
+    let viewport = config.viewport(width, height);
+    assert_eq!((viewport.x, viewport.y), (44, 26));
+    assert_eq!(surface[0], 0x0000FF);
+    assert_eq!(surface[26 * width + 44], 0xFF0000);
+    assert_eq!(surface[27 * width + 45], 0xFF0000);
+    assert_eq!(surface[(26 + 447) * width + 44 + 511], 0x00FF00);
+    assert_eq!(surface[(26 + 448) * width + 44 + 511], 0x0000FF);
+
+    // the mouse maps back onto the same pixels
+    assert_eq!(config.frame_position(45, 27, width, height), Some((0, 8)));
+    assert_eq!(
+        config.frame_position(44 + 511, 26 + 447, width, height),
+        Some((255, 231))
+    );
+    assert_eq!(config.frame_position(43, 27, width, height), None);
+    assert_eq!(config.frame_position(45, 26 + 448, width, height), None);
+}
+
+#[test]
+fn filter_uniforms() {
+    let config = ScalingConfig {
+        overscan: Overscan::NTSC,
+        background: 0xFF8000,
+        ..Default::default()
+    };
+    let params = ShaderFilter::Crt.params();
+    let uniforms = FilterUniforms::new(&config, params, 1920, 1080);
+    assert_eq!(uniforms.source_origin, [0.0, 8.0]);
+    assert_eq!(uniforms.source_size, [256.0, 224.0]);
+    assert_eq!(uniforms.viewport_size, [1024.0, 896.0]);
+    assert_eq!(uniforms.background, [1.0, 128.0 / 255.0, 0.0, 1.0]);
+
+    let bytes = uniforms.to_bytes();
+    let float = |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    assert_eq!(float(4), 8.0);
+    assert_eq!(float(12), 224.0);
+    assert_eq!(float(48), params.scanline_strength);
+    assert_eq!(float(60), params.persistence);
+
+    let mut frame = Frame::new();
+    frame.set_pixel(1, 0, 0x123456);
+    assert_eq!(
+        &frame.to_rgba_bytes()[..8],
+        &[0, 0, 0, 0xFF, 0x12, 0x34, 0x56, 0xFF]
+    );
+}
+
+#[test]
+fn fullscreen_toggle() {
+    let mut window = WindowState::new(768, 720);
+    assert_eq!(window.toggle_fullscreen(), WindowMode::Borderless);
+    window.resize(3840, 2160);
+    window.resize(0, 0);
+    assert_eq!(window.get_size(), (3840, 2160));
+    assert_eq!(window.get_windowed_size(), (768, 720));
+
+    // the integer scaler has to fill a whole monitor sized buffer
+    let (width, height) = window.get_size();
+    let (width, height) = (width as usize, height as usize);
+    let mut frame = Frame::new();
+    frame.fill(0xFFFFFF);
+    let mut surface = vec![0x123456; width * height];
+    let config = ScalingConfig::default();
+    config.blit(&frame, &mut surface, width, height);
+    let viewport = config.viewport(width, height);
+    assert_eq!((viewport.width, viewport.height), (2304, 2160));
+    assert_eq!(
+        surface.iter().filter(|pixel| **pixel == 0xFFFFFF).count(),
+        2304 * 2160
+    );
+    assert!(!surface.contains(&0x123456));
+
+    assert_eq!(window.toggle_fullscreen(), WindowMode::Windowed);
+    assert_eq!(window.get_size(), (768, 720));
+
+    window.set_fullscreen_mode(WindowMode::Exclusive);
+    assert_eq!(window.toggle_fullscreen(), WindowMode::Exclusive);
+}
+
+#[test]
+fn runner_step() {
+    let mut runner = Runner::new(Nes::new());
+    let (width, height) = (512, 480);
+    let mut surface = vec![0x123456; width * height];
+
+    runner.presented().unwrap();
+    assert!(runner.stats().summary().is_none());
+
+    for _ in 0..3 {
+        let summary = runner.step(&mut surface, width, height);
+        assert_eq!(summary.break_reason, BreakReason::FrameDone);
+        runner.presented().unwrap();
+    }
+    assert_eq!(runner.stats().timings().count(), 3);
+    assert!(!surface.contains(&0x123456));
+
+    runner.set_frame_skip(2);
+    let frames = runner.machine().frame_count();
+    runner.step(&mut surface, width, height);
+    assert_eq!(runner.machine().frame_count(), frames + 3);
+}
+
+#[test]
+fn dynamic_rate_control() {
+    let mut rate_control = RateControl::new();
+    assert_eq!(rate_control.get_adjustment(), 0.0);
+    for _ in 0..200 {
+        rate_control.update(0.0);
+    }
+    assert!((rate_control.get_adjustment() - MAX_RATE_DEVIATION).abs() < 1e-5);
+    rate_control.update(1.0);
+    assert!(
+        rate_control.get_adjustment() < MAX_RATE_DEVIATION,
+        "one frame moves it a bit"
+    );
+    rate_control.set_max_deviation(0.0);
+    assert_eq!(rate_control.get_adjustment(), 0.0);
+
+    // a running dry buffer makes the frames shorter and the audio longer
+    let nestest = || Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let mut steady = Runner::new(Nes::new_with_cartrige(nestest()));
+    let mut dry = Runner::new(Nes::new_with_cartrige(nestest()));
+    for _ in 0..200 {
+        dry.set_audio_fill(0.0);
+    }
+    assert!(dry.frame_duration() < steady.frame_duration());
+    let mut surface = vec![0; 256 * 240];
+    let (mut steady_samples, mut dry_samples) = (0, 0);
+    for _ in 0..10 {
+        steady.step(&mut surface, 256, 240);
+        dry.step(&mut surface, 256, 240);
+        steady_samples += steady.take_audio_samples().len();
+        dry_samples += dry.take_audio_samples().len();
+    }
+    let stretch = dry_samples as f32 / steady_samples as f32 - 1.0;
+    assert!((0.003..0.008).contains(&stretch), "stretched by {stretch}");
+}
+
+#[test]
+fn wav_render() {
+    let mut nes = Nes::new();
+    let wav = render_audio(&mut nes, 44100, 0.1, Cursor::new(Vec::new()))
+        .unwrap()
+        .into_inner();
+
+    let samples = 4410;
+    assert_eq!(wav.len(), 44 + samples * 2);
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(wav[4..8], ((36 + samples * 2) as u32).to_le_bytes());
+    assert_eq!(wav[24..28], 44100u32.to_le_bytes());
+    assert_eq!(wav[40..44], ((samples * 2) as u32).to_le_bytes());
+
+    // captures without a limit take everything
+    let mut capture = AudioCapture::new(Cursor::new(Vec::new()), 44100, None).unwrap();
+    assert!(capture.push(&[0.5, -2.0]).unwrap());
+    let wav = capture.finish().unwrap().into_inner();
+    assert_eq!(wav[44..], [0xFF, 0x3F, 0x01, 0x80]);
+}
+
+#[test]
+fn save_slots() {
+    let mut nes = Nes::new();
+    nes.run_frame(&mut Frame::new());
+    let directory = env::temp_dir().join("scamu_save_slots");
+    let _ = std::fs::remove_dir_all(&directory);
+    let slots = SaveSlots::new(&directory, Path::new("roms/game.nes"));
+    assert_eq!(slots.slot_path(3), directory.join("game.ss3"));
+
+    slots.save(3, &nes).unwrap();
+    let list = slots.list();
+    assert_eq!(list.len(), SLOT_COUNT);
+    assert!(
+        list.iter()
+            .enumerate()
+            .all(|(slot, metadata)| metadata.is_some() == (slot == 3))
+    );
+    let metadata = list[3].as_ref().unwrap();
+    assert!(metadata.timestamp > 0);
+    assert_eq!(metadata.frame_count, nes.frame_count());
+    assert_eq!(
+        metadata.thumbnail,
+        Thumbnail::from_frame(&nes.get_last_frame())
+    );
+
+    nes.run_frame(&mut Frame::new());
+    slots.load(3, &mut nes).unwrap();
+    assert_eq!(nes.frame_count(), metadata.frame_count);
+    assert!(slots.save(SLOT_COUNT, &nes).is_err());
+    // plain states load fine but have no metadata
+    assert_eq!(StateMetadata::from_state(&nes.save_state()).unwrap(), None);
+
+    let mut picker = SlotPicker::new();
+    let mut frame = Frame::new();
+    frame.fill(0x123456);
+    picker.draw(&mut frame, &list);
+    assert!(frame.pixels().iter().all(|&pixel| pixel == 0x123456));
+    picker.open();
+    picker.select_previous();
+    assert_eq!(picker.selected(), SLOT_COUNT - 1);
+    picker.select(3);
+    picker.draw(&mut frame, &list);
+    assert!(frame.pixels().contains(&TEXT_COLOR));
+    assert!(frame.pixels().contains(&DIM_COLOR));
+}
+
+/// An NROM rom that starts at `reset`, the extra bank makes the size
+/// change so the watcher sees it even with a coarse modification time
+fn watched_rom(reset: u16, prg_banks: u8) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, prg_banks, 1, 0, 0];
+    rom.resize(16, 0);
+    let prg_size = prg_banks as usize * 0x4000;
+    let mut prg = vec![0xEA; prg_size];
+    prg[prg_size - 4..prg_size - 2].copy_from_slice(&reset.to_le_bytes());
+    rom.extend_from_slice(&prg);
+    rom.extend_from_slice(&[0; 0x2000]);
+    rom
+}
+
+#[test]
+fn rom_hot_reload() {
+    let directory = env::temp_dir().join("scamu_rom_watcher");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("game.nes");
+    std::fs::write(&path, watched_rom(0x8000, 1)).unwrap();
+
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_file(&path.to_string_lossy()).unwrap());
+    nes.reset();
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(0x8010);
+    nes.run_frame(&mut Frame::new());
+    nes.write_memory(0x0000, &[0x42]);
+
+    let mut watcher = RomWatcher::new(&path);
+    assert!(!watcher.poll());
+
+    std::fs::write(&path, watched_rom(0x8100, 2)).unwrap();
+    // the first poll only notices the change, the rom might not be done
+    assert!(!watcher.poll());
+    assert!(watcher.poll());
+    watcher.reload(&mut nes, &LoadOptions::default()).unwrap();
+    assert!(!watcher.poll());
+
+    assert_eq!(nes.cpu.lock().unwrap().get_program_counter(), 0x8100);
+    assert_eq!(nes.frame_count(), 0);
+    assert_eq!(nes.bus.peek(0x0000), 0);
+    assert_eq!(debugger.get_breakpoints().len(), 1);
+
+    // a broken rom leaves the running game alone
+    std::fs::write(&path, b"not a rom").unwrap();
+    watcher.poll();
+    assert!(watcher.poll());
+    assert!(watcher.reload(&mut nes, &LoadOptions::default()).is_err());
+    assert_eq!(nes.cpu.lock().unwrap().get_program_counter(), 0x8100);
+    assert!(!watcher.poll());
+}
+
+#[test]
+fn rom_collection_scan() {
+    let directory = env::temp_dir().join("scamu_rom_scan");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(directory.join("mmc5")).unwrap();
+    let rom = watched_rom(0x8000, 1);
+    std::fs::write(directory.join("game.nes"), &rom).unwrap();
+    std::fs::write(directory.join("notes.txt"), b"not a rom").unwrap();
+    std::fs::write(directory.join("broken.NES"), b"NES").unwrap();
+    let mut header = Header::from_bytes(&rom).unwrap();
+    header.set_mapper_id(5);
+    std::fs::write(
+        directory.join("mmc5").join("game.nes"),
+        header.write_to_rom(&rom).unwrap(),
+    )
+    .unwrap();
+
+    let report = ScanReport::scan(&directory).unwrap();
+    assert_eq!(report.rom_count(), 2);
+    assert_eq!(report.supported_count(), 1);
+    assert_eq!(report.failures.len(), 1);
+    assert!(report.failures[0].path.ends_with("broken.NES"));
+    let mappers = report
+        .groups
+        .iter()
+        .map(|group| (group.mapper_id, group.is_supported))
+        .collect::<Vec<_>>();
+    assert_eq!(mappers, vec![(0, true), (5, false)]);
+
+    let text = report.to_string();
+    assert!(text.starts_with("2 roms, 1 supported (50%)"));
+    assert!(text.contains("mapper   5 MMC5"));
+}
+
+/// A rom reporting `code` the way blargg's test roms do
+fn blargg_rom(code: u8) -> Vec<u8> {
+    let program = assemble(
+        0x8000,
+        &format!(
+            "LDA #$80
+            STA $6000
+            LDA #$DE
+            STA $6001
+            LDA #$B0
+            STA $6002
+            LDA #$61
+            STA $6003
+            LDA #$6F
+            STA $6004
+            LDA #$6B
+            STA $6005
+            LDA #$00
+            STA $6006
+            LDA #${code:02X}
+            STA $6000
+            done:
+            JMP done"
+        ),
+    )
+    .unwrap();
+    let mut rom = watched_rom(0x8000, 1);
+    rom[16..16 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+#[test]
+fn rom_test_suite() {
+    let directory = env::temp_dir().join("scamu_test_suite");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("pass.nes"), blargg_rom(0)).unwrap();
+    std::fs::write(directory.join("fail.nes"), blargg_rom(3)).unwrap();
+    std::fs::write(directory.join("hang.nes"), watched_rom(0x8000, 1)).unwrap();
+
+    let mut nes = Nes::new_with_cartrige(Cartrige::from_bytes(&watched_rom(0x8000, 1)).unwrap());
+    nes.reset();
+    let mut frame = Frame::new();
+    nes.run_frame(&mut frame);
+    let crc32 = crc32fast::hash(&frame.to_rgb_bytes());
+
+    let suite = TestSuite {
+        roms: vec![
+            TestRom::new(directory.join("pass.nes"), PassCondition::Blargg),
+            TestRom::new(directory.join("fail.nes"), PassCondition::Blargg),
+            TestRom {
+                max_frames: 10,
+                ..TestRom::new(directory.join("hang.nes"), PassCondition::Blargg)
+            },
+            TestRom::new(
+                directory.join("hang.nes"),
+                PassCondition::ScreenHash { crc32 },
+            ),
+            TestRom::new(directory.join("missing.nes"), PassCondition::Blargg),
+        ],
+        threads: 2,
+    };
+    let report = suite.run();
+    let statuses: Vec<TestStatus> = report.results.iter().map(|result| result.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            TestStatus::Passed,
+            TestStatus::Failed,
+            TestStatus::TimedOut,
+            TestStatus::Passed,
+            TestStatus::Error,
+        ]
+    );
+    assert_eq!(report.results[0].message, "ok");
+    assert_eq!(report.results[2].frames, 10);
+    assert_eq!(report.passed(), 2);
+    assert!(!report.is_all_passed());
+
+    let junit = report.to_junit();
+    assert!(junit.contains(r#"tests="5" failures="2" errors="1""#));
+    assert_eq!(junit.matches("<testcase").count(), 5);
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["results"][1]["status"], "failed");
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn compatibility_database() {
+    let nestest = Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let mut database = CompatDatabase::builtin();
+    let entry = database.lookup(&nestest).unwrap();
+    assert_eq!(entry.status, CompatStatus::Perfect);
+    let mut notifications = Notifications::new();
+    database.notify_on_load(&nestest, &mut notifications);
+    assert!(notifications.is_empty());
+
+    let user: CompatDatabase = serde_json::from_str(
+        r#"[{
+            "crc32": "158B0388",
+            "title": "nestest",
+            "status": "playable",
+            "issues": ["the last page is blank"]
+        }]"#,
+    )
+    .unwrap();
+    database.extend(user.entries().cloned());
+    assert_eq!(database.len(), 1);
+    database.notify_on_load(&nestest, &mut notifications);
+    assert_eq!(
+        notifications.visible().collect::<Vec<_>>(),
+        ["playable: the last page is blank"]
+    );
+    assert_eq!(
+        database.lookup(&nestest).unwrap().to_string(),
+        "nestest (158B0388): playable\n  - the last page is blank\n"
+    );
+
+    let exported = serde_json::to_string(&database).unwrap();
+    assert!(exported.contains(r#""crc32":"158B0388""#));
+    let imported: CompatDatabase = serde_json::from_str(&exported).unwrap();
+    assert_eq!(imported, database);
+}
+
+#[test]
+fn practice_points() {
+    let mut nes = Nes::new();
+    let directory = env::temp_dir().join("scamu_practice_points");
+    let _ = std::fs::remove_dir_all(&directory);
+    let rom_path = Path::new("roms/game.nes");
+    let mut practice = PracticePoints::open(&directory, rom_path).unwrap();
+    assert!(practice.points().is_empty());
+    assert_eq!(practice.next(&mut nes).unwrap(), None);
+
+    let mut frames = Vec::new();
+    for name in ["level 1", "boss", "skip"] {
+        nes.run_frame(&mut Frame::new());
+        nes.bus.write(0x0010, frames.len() as u8);
+        frames.push(nes.frame_count());
+        practice.add(name, vec![RamWatch::new("index", 0x0010)], &nes);
+    }
+    assert_eq!(practice.current_index(), Some(2));
+    practice.save().unwrap();
+    assert_eq!(practice.path(), directory.join("game.practice"));
+
+    let mut practice = PracticePoints::open(&directory, rom_path).unwrap();
+    assert_eq!(practice.points().len(), 3);
+    let point = practice.next(&mut nes).unwrap().unwrap();
+    assert_eq!(point.name, "level 1");
+    assert_eq!(point.watches[0].read(&nes), 0);
+    assert_eq!(nes.frame_count(), frames[0]);
+    let point = practice.previous(&mut nes).unwrap().unwrap();
+    assert_eq!(point.name, "skip");
+    assert_eq!(point.watches[0].read(&nes), 2);
+
+    nes.run_frame(&mut Frame::new());
+    practice.reload(&mut nes).unwrap();
+    assert_eq!(nes.frame_count(), frames[2]);
+
+    assert_eq!(practice.remove(2).unwrap().name, "skip");
+    assert_eq!(practice.current().unwrap().name, "boss");
+    assert!(practice.select(5, &mut nes).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn lockstep_divergence() {
+    let program = assemble(
+        0xC000,
+        "
+        LDA #$01
+        STA $10
+        SED
+        CLC
+        LDA #$09
+        ADC #$01
+        STA $10
+    loop:
+        JMP loop
+        ",
+    )
+    .unwrap();
+    let nes = || {
+        let mut nes = Nes::new();
+        nes.insert_cartrige(Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap());
+        nes.reset_with_program_counter(0xC000);
+        nes.patch_memory(0xC000, &program);
+        nes
+    };
+
+    // the same configuration never diverges
+    let mut lockstep = Lockstep::new(nes(), nes());
+    assert_eq!(
+        lockstep
+            .run(3, |_| vec![ControllerState::empty(); 2])
+            .unwrap(),
+        None
+    );
+    assert_eq!(lockstep.frame(), 3);
+
+    // only the nmos 6502 does decimal math
+    let mut right = nes();
+    right.set_cpu_config(CpuConfig {
+        variant: CpuVariant::Nmos6502,
+        ..Default::default()
+    });
+    let mut lockstep = Lockstep::new(nes(), right);
+    lockstep.set_trace_window(3);
+    let divergence = lockstep
+        .run(3, |_| vec![ControllerState::empty(); 2])
+        .unwrap()
+        .unwrap();
+    assert_eq!(divergence.frame, 0);
+    assert!(divergence.chunks.contains(&"BUS".to_string()));
+    assert!(divergence.is_in_trace());
+    assert_eq!(divergence.left_trace.len(), 3);
+    assert_eq!(divergence.left_trace[..2], divergence.right_trace[..2]);
+    let (left, right) = (&divergence.left_trace[2], &divergence.right_trace[2]);
+    assert_eq!(left.registers.program_counter, 0xC00A);
+    assert_eq!(left.text, "STA $10 = 01");
+    assert_eq!(
+        (left.registers.accumulator, right.registers.accumulator),
+        (0x0A, 0x10)
+    );
+    assert!(divergence.to_string().starts_with("diverged in frame 0"));
+    // left at the end of the frame
+    assert_eq!(lockstep.right().bus.peek(0x10), 0x10);
+}
+
+#[test]
+fn desync_bisect() {
+    let program = assemble(
+        0xC000,
+        "
+    loop:
+        INC $10
+        JMP loop
+        ",
+    )
+    .unwrap();
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap());
+    nes.reset_with_program_counter(0xC000);
+    nes.patch_memory(0xC000, &program);
+    let start = nes.save_state();
+
+    let mut log = VerificationLog::new(&nes, 4);
+    let mut frame = Frame::new();
+    for _ in 0..21 {
+        nes.run_frame(&mut frame);
+        log.record_frame(&nes);
+    }
+    assert_eq!(log.get_frame_count(), 21);
+
+    nes.load_state(&start).unwrap();
+    assert_eq!(
+        desync::find_desync(&mut nes, &log, |_| vec![ControllerState::empty()], 4).unwrap(),
+        None
+    );
+
+    // pressing start from frame 10 on changes the state after it
+    let input = |frame| {
+        vec![if frame >= 10 {
+            ControllerState::START
+        } else {
+            ControllerState::empty()
+        }]
+    };
+    let found = desync::find_desync(&mut nes, &log, input, 4)
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.frame, 10);
+    assert_eq!(found.expected_hash, log.get_hash(10).unwrap());
+    assert_ne!(found.actual_hash, found.expected_hash);
+    assert!(found.chunks.contains(&"INPT".to_string()));
+    assert_eq!(found.trace.len(), 4);
+    assert!(
+        found
+            .trace
+            .iter()
+            .all(|line| line.registers.program_counter >= 0xC000)
+    );
+    assert!(found.to_string().starts_with("desynced in frame 10"));
+
+    // after the last whole state there's nothing to compare chunks with
+    let input = |frame| {
+        vec![if frame == 20 {
+            ControllerState::START
+        } else {
+            ControllerState::empty()
+        }]
+    };
+    let found = desync::find_desync(&mut nes, &log, input, 4)
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.frame, 20);
+    assert!(found.chunks.is_empty());
+}
+
+#[test]
+fn render_frame_sequence() {
+    let nestest = include_bytes!("./nestest/nestest.nes");
+    let directory = env::temp_dir().join("scamu_render_frames");
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let mut nes = Nes::new_with_cartrige(Cartrige::from_bytes(nestest).unwrap());
+    let saved = render_frames(&mut nes, 10, 4, &directory).unwrap();
+    assert_eq!(
+        saved,
+        [
+            directory.join("frame_00004.png"),
+            directory.join("frame_00008.png")
+        ]
+    );
+
+    let mut expected = Nes::new_with_cartrige(Cartrige::from_bytes(nestest).unwrap());
+    let mut frame = Frame::new();
+    for _ in 0..8 {
+        expected.run_frame(&mut frame);
+    }
+    let decoder = png::Decoder::new(std::fs::File::open(&saved[1]).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    assert_eq!((info.width, info.height), (256, 240));
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    buffer.truncate(info.buffer_size());
+    assert_eq!(buffer, frame.to_rgb_bytes());
+}
+
+#[test]
+fn null_frontend() {
+    let nestest = Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let mut runner = Runner::new(Nes::new_with_cartrige(nestest));
+    let mut frontend = NullFrontend::new(5);
+    assert_eq!(
+        run_frontend(&mut runner, &mut frontend).unwrap(),
+        FrontendExit::Quit
+    );
+    assert_eq!(frontend.get_frames(), 5);
+    assert_eq!(frontend.get_surface().len(), 256 * 240);
+    assert!(frontend.get_sample_count() > 0);
+    assert!(frontend.get_messages().is_empty());
+
+    // a jam stops the loop in the middle of a frame
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap());
+    nes.reset_with_program_counter(0xC000);
+    nes.patch_memory(0xC000, &[0x02]);
+    let mut runner = Runner::new(nes);
+    let mut frontend = NullFrontend::new(5);
+    assert_eq!(
+        run_frontend(&mut runner, &mut frontend).unwrap(),
+        FrontendExit::Stopped(BreakReason::CpuJammed)
+    );
+    assert_eq!(frontend.get_frames(), 1);
+    assert_eq!(frontend.get_messages().len(), 1);
+}
+
+#[test]
+fn terminal_frontend() {
+    let mut terminal = TerminalFrontend::new(Vec::new(), 4, 3);
+    assert_eq!(terminal.surface_size(), (4, 4));
+    let mut surface = vec![0x000000; 16];
+    surface[0] = 0xFF0000;
+    terminal.present_frame(&surface, 4, 4).unwrap();
+    terminal.color_mode = ColorMode::Ansi256;
+    terminal.present_frame(&surface, 4, 4).unwrap();
+    terminal.show_message("stopped");
+    let text = String::from_utf8(terminal.finish().unwrap()).unwrap();
+    assert_eq!(text.matches('▀').count(), 16);
+    assert!(text.contains("\x1b[38;2;255;0;0m\x1b[48;2;0;0;0m▀"));
+    // red and black from the color cube
+    assert!(text.contains("\x1b[38;5;196m\x1b[48;5;16m▀"));
+    // cut to the width of the terminal
+    assert!(text.contains("\x1b[3;1Hstop\x1b[K"));
+
+    let mut terminal = TerminalFrontend::new(Vec::new(), 4, 3);
+    let a = |pressed| InputEvent::Button {
+        controller_index: 0,
+        button: Button::A,
+        pressed,
+    };
+    terminal.press_key('k');
+    assert_eq!(terminal.poll_input(), [a(true)]);
+    // key repeat keeps it held
+    terminal.press_key('k');
+    for _ in 1..KEY_HOLD_FRAMES {
+        assert!(terminal.poll_input().is_empty());
+    }
+    assert_eq!(terminal.poll_input(), [a(false)]);
+    terminal.press_key('x');
+    terminal.press_key('p');
+    terminal.press_key('q');
+    assert_eq!(
+        terminal.poll_input(),
+        [InputEvent::Hotkey(Hotkey::Pause), InputEvent::Quit]
+    );
+
+    // everything but ctrl+c goes to the keyboard
+    terminal.raw_keyboard = true;
+    let q = |pressed| InputEvent::Key {
+        row: 7,
+        column: 0,
+        key: 2,
+        pressed,
+    };
+    terminal.press_key('q');
+    terminal.press_key('\t');
+    assert_eq!(terminal.poll_input(), [q(true)]);
+    terminal.press_key('q');
+    for _ in 1..KEY_HOLD_FRAMES {
+        assert!(terminal.poll_input().is_empty());
+    }
+    assert_eq!(terminal.poll_input(), [q(false)]);
+    terminal.press_key('\x03');
+    assert_eq!(terminal.poll_input(), [InputEvent::Quit]);
+}
+
+#[test]
+fn keymap() {
+    assert_eq!(
+        "ctrl+shift+f5".parse::<KeyCombo>().unwrap(),
+        KeyCombo::key("F5").with_ctrl().with_shift()
+    );
+    assert_eq!(
+        KeyCombo::key("Enter").with_alt().with_ctrl().to_string(),
+        "Ctrl+Alt+ENTER"
+    );
+    assert_eq!(
+        "Ctrl+Shift".parse::<KeyCombo>(),
+        Err(KeymapError::MissingKeyError("Ctrl+Shift".to_string()))
+    );
+    assert_eq!(
+        "A+B".parse::<KeyCombo>(),
+        Err(KeymapError::TooManyKeysError("A+B".to_string()))
+    );
+
+    let keymap = Keymap::default();
+    assert!(keymap.conflicts().is_empty());
+    assert_eq!(keymap.hotkey(&KeyCombo::key("f5")), Some(Hotkey::SaveState));
+    assert_eq!(
+        keymap.hotkey(&KeyCombo::key("F6").with_shift()),
+        Some(Hotkey::PreviousSlot)
+    );
+    assert_eq!(keymap.hotkey(&KeyCombo::key("F5").with_ctrl()), None);
+
+    // the config only has what changed
+    let mut keymap: Keymap =
+        serde_json::from_str(r#"{"save_state": ["Ctrl+S"], "screenshot": ["F5"], "rewind": []}"#)
+            .unwrap();
+    assert_eq!(keymap.get_keys(Hotkey::LoadState), [KeyCombo::key("F7")]);
+    assert!(keymap.get_keys(Hotkey::Rewind).is_empty());
+    assert_eq!(
+        keymap.hotkey(&KeyCombo::key("S").with_ctrl()),
+        Some(Hotkey::SaveState)
+    );
+    assert!(serde_json::from_str::<Keymap>(r#"{"pause": ["Shift"]}"#).is_err());
+
+    keymap.bind(Hotkey::Pause, vec![KeyCombo::key("F5"), KeyCombo::key("P")]);
+    assert_eq!(
+        keymap.conflicts(),
+        [KeyConflict {
+            combo: KeyCombo::key("F5"),
+            hotkeys: vec![Hotkey::Pause, Hotkey::Screenshot],
+        }]
+    );
+    // the first one in Hotkey::ALL wins
+    assert_eq!(keymap.hotkey(&KeyCombo::key("F5")), Some(Hotkey::Pause));
+
+    let table = keymap.to_string();
+    assert!(table.contains("save_state        Ctrl+S\n"));
+    assert!(table.contains("rewind            -\n"));
+    assert!(table.contains("quick_menu        ESCAPE, F1\n"));
+    assert!(table.ends_with("conflict: F5 is bound to pause, screenshot\n"));
+
+    // pause and quit are handled by the loop
+    struct Keys(Vec<InputEvent>);
+    impl Frontend for Keys {
+        fn surface_size(&self) -> (usize, usize) {
+            (1, 1)
+        }
+        fn present_frame(&mut self, _: &[u32], _: usize, _: usize) -> std::io::Result<()> {
+            Ok(())
+        }
+        fn poll_input(&mut self) -> Vec<InputEvent> {
+            self.0.pop().into_iter().collect()
+        }
+        fn push_audio(&mut self, _samples: &[f32]) {}
+        fn show_message(&mut self, _message: &str) {}
+    }
+    let nestest = Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let mut runner = Runner::new(Nes::new_with_cartrige(nestest));
+    let mut keys = Keys(vec![
+        InputEvent::Hotkey(Hotkey::Quit),
+        InputEvent::Hotkey(Hotkey::Pause),
+    ]);
+    assert_eq!(
+        run_frontend(&mut runner, &mut keys).unwrap(),
+        FrontendExit::Quit
+    );
+    assert!(runner.is_paused());
+}
+
+#[test]
+fn pipe_frontend() {
+    let nestest = Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let mut runner = Runner::new(Nes::new_with_cartrige(nestest));
+    let mut pipes = PipeFrontend::new(Some(Vec::new()), Some(Vec::new()), Some(3));
+    assert_eq!(
+        run_frontend(&mut runner, &mut pipes).unwrap(),
+        FrontendExit::Quit
+    );
+    let (video, audio) = pipes.finish().unwrap();
+    let video = video.unwrap();
+    assert_eq!(video.len(), 3 * 256 * 240 * 3);
+    // about 735 samples a frame
+    let audio = audio.unwrap();
+    assert_eq!(audio.len() % 2, 0);
+    assert!((3 * 700 * 2..3 * 770 * 2).contains(&audio.len()));
+
+    // a closed pipe ends the stream instead of failing
+    struct Closed;
+    impl std::io::Write for Closed {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut pipes = PipeFrontend::new(Some(Closed), None::<Vec<u8>>, None);
+    assert_eq!(
+        run_frontend(&mut runner, &mut pipes).unwrap(),
+        FrontendExit::Quit
+    );
+    assert!(pipes.is_closed());
+}
+
+/// The frequency of `samples` at 44100Hz, from how often it crosses 0
+fn zero_crossing_frequency(samples: &[f32]) -> f32 {
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+        .count();
+    crossings as f32 / 2.0 / (samples.len() as f32 / 44100.0)
+}
+
+#[test]
+fn runner_speed() {
+    let nestest = Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let mut runner = Runner::new(Nes::new_with_cartrige(nestest));
+    let mut surface = vec![0; 256 * 240];
+
+    runner.set_speed(10.0);
+    assert_eq!(runner.get_speed(), MAX_SPEED);
+    runner.set_speed(2.0);
+    for _ in 0..3 {
+        runner.step(&mut surface, 256, 240);
+    }
+    assert_eq!(runner.machine().frame_count(), 6);
+    runner.take_audio_samples();
+
+    runner.set_speed(0.5);
+    for _ in 0..4 {
+        runner.step(&mut surface, 256, 240);
+    }
+    assert_eq!(runner.machine().frame_count(), 8);
+    // 2 frames of samples stretched over 4
+    let samples = runner.take_audio_samples();
+    assert!((4 * 700..4 * 770).contains(&samples.len()));
+    runner.set_audio_stretch(AudioStretch::PreservePitch);
+    assert_eq!(runner.get_audio_stretch(), AudioStretch::PreservePitch);
+
+    let sine: Vec<f32> = (0..44100)
+        .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / 44100.0).sin())
+        .collect();
+    for speed in [0.5, 2.0] {
+        let mut resampler = Resampler::new();
+        let mut wsola = Wsola::new();
+        let (mut resampled, mut stretched) = (Vec::new(), Vec::new());
+        for chunk in sine.chunks(735) {
+            resampled.extend(resampler.process(chunk, speed));
+            stretched.extend(wsola.process(chunk, speed));
+        }
+        let expected = sine.len() as f32 / speed;
+        assert!((resampled.len() as f32 - expected).abs() < 2.0);
+        // wsola holds on to a few pieces
+        assert!((stretched.len() as f32 - expected).abs() < 2000.0);
+
+        assert!((zero_crossing_frequency(&resampled) - 440.0 * speed).abs() < 10.0);
+        assert!((zero_crossing_frequency(&stretched) - 440.0).abs() < 10.0);
+    }
+}
+
+#[test]
+fn window_titles() {
+    let nestest = Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let header = nestest.get_header().clone();
+    assert_eq!(window_title(None, None, TitleStatus::default()), "SCAM");
+
+    let mut runner = Runner::new(Nes::new_with_cartrige(nestest));
+    let path = Path::new("roms/nestest.nes");
+    let title = |runner: &Runner, recording| {
+        window_title(
+            Some(path),
+            Some(&header),
+            TitleStatus::from_runner(runner, recording),
+        )
+    };
+    assert_eq!(title(&runner, false), "SCAM — nestest.nes [Mapper 0, NTSC]");
+
+    runner.set_paused(true);
+    runner.set_speed(2.0);
+    assert_eq!(
+        title(&runner, true),
+        "SCAM — nestest.nes [Mapper 0, NTSC] (paused, 200%, recording)"
+    );
+
+    // paused steps don't run anything
+    let mut surface = vec![0; 256 * 240];
+    runner.step(&mut surface, 256, 240);
+    assert_eq!(runner.machine().frame_count(), 0);
+    runner.set_paused(false);
+    runner.step(&mut surface, 256, 240);
+    assert_eq!(runner.machine().frame_count(), 2);
+}
+
+#[test]
+fn golden_run() {
+    let nestest = || Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let mut nes = Nes::new_with_cartrige(nestest());
+    nes.set_seed(7);
+    nes.set_ram_init(RamInit::Random);
+    // start pressed for a few frames gets nestest going
+    let run = GoldenRun::record(&mut nes, 40, |frame| {
+        vec![if (10..14).contains(&frame) {
+            ControllerState::START
+        } else {
+            ControllerState::empty()
+        }]
+    })
+    .unwrap();
+    assert_eq!(run.frames.len(), 40);
+    assert_eq!(run.seed, 7);
+
+    let json = serde_json::to_string(&run).unwrap();
+    let run: GoldenRun = serde_json::from_str(&json).unwrap();
+    // another machine, from a different state
+    let mut other = Nes::new_with_cartrige(nestest());
+    other.run_frame(&mut Frame::new());
+    assert_eq!(run.replay(&mut other).unwrap(), None);
+
+    let mut changed = run.clone();
+    changed.frames[20].input = vec![Button::Select.into()];
+    let mismatch = changed.replay(&mut other).unwrap().unwrap();
+    assert_eq!(mismatch.frame, 20);
+    assert_eq!(
+        mismatch,
+        GoldenMismatch {
+            frame: 20,
+            expected: run.frames[20].hash,
+            actual: mismatch.actual,
+        }
+    );
+    assert_ne!(mismatch.actual, mismatch.expected);
+
+    assert_eq!(
+        run.replay(&mut Nes::new()),
+        Err(GoldenRunError::NoCartrigeError)
+    );
+}
+
+#[test]
+fn input_script() {
+    let text = "
+        # get nestest going
+        10-13 start
+        20 p2 a b   # the second controller
+        frames 40
+    ";
+    let script = InputScript::read(text.as_bytes()).unwrap();
+    assert_eq!(script.get_frame_count(), 40);
+    let none = ControllerState::empty();
+    assert_eq!(script.input(9), [none, none]);
+    assert_eq!(script.input(13), [ControllerState::START, none]);
+    assert_eq!(
+        script.input(20),
+        [none, ControllerState::A | ControllerState::B]
+    );
+
+    let json = serde_json::to_string(&script).unwrap();
+    assert!(json.contains(r#""buttons":"a b""#));
+    assert_eq!(serde_json::from_str::<InputScript>(&json).unwrap(), script);
+
+    assert!(matches!(
+        InputScript::parse("5 start\n6 jump"),
+        Err(InputScriptError::UnknownButtonError(2, name)) if name == "jump"
+    ));
+    assert!(matches!(
+        InputScript::parse("9-5 start"),
+        Err(InputScriptError::InvalidLineError(1, _))
+    ));
+
+    // the hashes come out the same from any state
+    let nestest = || Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap();
+    let result = script.run(&mut Nes::new_with_cartrige(nestest()));
+    assert_eq!(result.frames, 40);
+    assert_eq!(result.passed, None);
+    let mut other = Nes::new_with_cartrige(nestest());
+    other.run_frame(&mut Frame::new());
+    let expecting =
+        InputScript::parse(&format!("{text}\nexpect {:08x}", result.screen_hash)).unwrap();
+    let other_result = expecting.run(&mut other);
+    assert_eq!(other_result.state_hash, result.state_hash);
+    assert_eq!(other_result.passed, Some(true));
+
+    // without start nestest stays on its menu
+    let idle = InputScript::parse("frames 40").unwrap();
+    assert_ne!(idle.run(&mut other).state_hash, result.state_hash);
+}
@@ -0,0 +1,558 @@
+use crate::{
+    hardware::{
+        constants::{
+            controller::{
+                MICROPHONE, TURBO_RATE_FAST, TURBO_RATE_SLOW,
+                keyboard::{TAPE_INPUT, TAPE_OUTPUT},
+                paddle::{NES_DATA, NES_FIRE, POSITION_MAX, POSITION_MIN},
+            },
+            ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+        },
+        input::{
+            Device, InputDevices, Port,
+            controller::{Button, ControllerState},
+            data_recorder::{CYCLES_PER_SAMPLE, DataRecorder, TapeState},
+            error::{ButtonError, TapeError},
+            keyboard,
+            macros::{FastBoot, InputMacro, MacroBindings, MacroPriority},
+            paddle,
+            sanitizer::OppositeDirections,
+        },
+        ppu::frame::Frame,
+    },
+    osd::{self, input_display::InputDisplay},
+    save_state::{Chunk, SaveState, StateReader, StateWriter, migration},
+};
+
+fn strobe(input: &mut InputDevices) {
+    input.write(1);
+    input.write(0);
+}
+
+fn read_bits(input: &mut InputDevices, address: u16, count: usize) -> Vec<u8> {
+    (0..count).map(|_| input.read(address, false) & 1).collect()
+}
+
+#[test]
+fn standard_controllers() {
+    let mut input = InputDevices::new();
+    input.set_button(0, Button::A, true);
+    input.set_button(1, Button::Right, true);
+    strobe(&mut input);
+
+    assert_eq!(
+        read_bits(&mut input, 0x4016, 10),
+        [1, 0, 0, 0, 0, 0, 0, 0, 1, 1]
+    );
+    assert_eq!(
+        read_bits(&mut input, 0x4017, 10),
+        [0, 0, 0, 0, 0, 0, 0, 1, 1, 1]
+    );
+}
+
+#[test]
+fn dma_read_conflicts() {
+    let mut input = InputDevices::new();
+    input.set_button(0, Button::A, true);
+    input.set_button(0, Button::B, true);
+    input.set_button(0, Button::Start, true);
+
+    // the dma lands on the read of B so B gets skipped
+    let read_with_conflict = |input: &mut InputDevices| {
+        strobe(input);
+        let mut bits = read_bits(input, 0x4016, 1);
+        input.dma_conflict(0x4016);
+        bits.extend(read_bits(input, 0x4016, 7));
+        bits
+    };
+    let glitched = read_with_conflict(&mut input);
+    assert_eq!(glitched, [1, 0, 1, 0, 0, 0, 0, 1]);
+    // reading until two reads match gets the right buttons
+    strobe(&mut input);
+    assert_ne!(glitched, read_bits(&mut input, 0x4016, 8));
+
+    input.set_dma_conflicts(false);
+    assert_eq!(read_with_conflict(&mut input), [1, 1, 0, 1, 0, 0, 0, 0]);
+    // other addresses don't care
+    input.set_dma_conflicts(true);
+    strobe(&mut input);
+    input.dma_conflict(0x4015);
+    assert_eq!(read_bits(&mut input, 0x4016, 2), [1, 1]);
+}
+
+#[test]
+fn four_score() {
+    let mut input = InputDevices::new();
+    input.attach_device(Port::One, Device::FourScore);
+    assert_eq!(input.get_device(Port::Two), Device::Empty);
+
+    input.set_button(0, Button::A, true);
+    input.set_button(1, Button::B, true);
+    input.set_button(2, Button::Start, true);
+    input.set_button(3, Button::Right, true);
+    strobe(&mut input);
+
+    #[rustfmt::skip]
+    let expected_4016 = [
+        1, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 1, 0, 0, 0, 0,
+        0, 0, 0, 1, 0, 0, 0, 0,
+        1, 1,
+    ];
+    #[rustfmt::skip]
+    let expected_4017 = [
+        0, 1, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 1,
+        0, 0, 1, 0, 0, 0, 0, 0,
+        1, 1,
+    ];
+    assert_eq!(read_bits(&mut input, 0x4016, 26), expected_4016);
+    assert_eq!(read_bits(&mut input, 0x4017, 26), expected_4017);
+}
+
+#[test]
+fn microphone_controller() {
+    let mut input = InputDevices::new();
+    input.set_microphone(true);
+    assert_eq!(input.read(0x4016, true) & MICROPHONE, 0);
+
+    input.attach_device(Port::Two, Device::MicrophoneController);
+    input.set_microphone(true);
+    assert_eq!(input.read(0x4016, true) & MICROPHONE, MICROPHONE);
+    assert_eq!(input.read(0x4017, true) & MICROPHONE, 0);
+}
+
+#[test]
+fn family_basic_keyboard() {
+    let mut input = InputDevices::new();
+    input.attach_device(Port::Expansion, Device::FamilyBasicKeyboard);
+    // row 1, column 1, second key
+    input.set_key(1, 1, 1, true);
+
+    // reset to row 0 column 0
+    input.write(0b101);
+    assert_eq!(input.read(0x4017, false), 0b11110);
+    // column 1
+    input.write(0b110);
+    assert_eq!(input.read(0x4017, false), 0b11110);
+    // row 1 column 0
+    input.write(0b100);
+    assert_eq!(input.read(0x4017, false), 0b11110);
+    // row 1 column 1
+    input.write(0b110);
+    assert_eq!(input.read(0x4017, false), 0b11010);
+
+    // disabled keyboards don't drive the lines
+    input.write(0b000);
+    assert_eq!(input.read(0x4017, false), 0);
+
+    assert_eq!(keyboard::key_position("/"), Some((1, 1, 1)));
+    assert_eq!(keyboard::key_position("enter"), Some((0, 0, 1)));
+    assert_eq!(keyboard::key_position("F8"), Some((0, 0, 0)));
+    assert_eq!(keyboard::key_position("Tab"), None);
+}
+
+#[test]
+fn data_recorder() {
+    let tick_sample = |input: &mut InputDevices| {
+        for _ in 0..CYCLES_PER_SAMPLE {
+            input.tick();
+        }
+    };
+    let square = |sample: usize| (sample / 4).is_multiple_of(2);
+
+    let mut input = InputDevices::new();
+    input.attach_device(Port::Expansion, Device::FamilyBasicKeyboard);
+    input.get_data_recorder_mut().record();
+    for sample in 0..16 {
+        input.write(if square(sample) { TAPE_OUTPUT } else { 0 });
+        tick_sample(&mut input);
+    }
+    let recorder = input.get_data_recorder_mut();
+    recorder.stop();
+    recorder.rewind();
+    assert_eq!(recorder.get_length(), 16);
+    recorder.play();
+
+    let mut writer = StateWriter::new();
+    input.save_state(&mut writer);
+    let state = writer.into_bytes();
+    for sample in 0..16 {
+        let bit = input.read(0x4016, false) & TAPE_INPUT != 0;
+        assert_eq!(bit, square(sample), "{sample}");
+        tick_sample(&mut input);
+    }
+    // stops at the end of the tape
+    tick_sample(&mut input);
+    assert_eq!(input.get_data_recorder().get_state(), TapeState::Stopped);
+
+    // the tape and its position are in save states
+    input.load_state(&mut StateReader::new(&state)).unwrap();
+    assert_eq!(input.get_data_recorder().get_state(), TapeState::Playing);
+    assert_eq!(input.read(0x4016, false) & TAPE_INPUT, TAPE_INPUT);
+
+    // without the keyboard nothing can hear the tape
+    input.attach_device(Port::Expansion, Device::Empty);
+    assert_eq!(input.read(0x4016, false) & TAPE_INPUT, 0);
+
+    let bytes = input.get_data_recorder().to_bytes();
+    let tape = DataRecorder::from_bytes(&bytes).unwrap();
+    assert_eq!(tape.get_length(), 16);
+    assert!((0..16).all(|sample| tape.get_sample(sample) == square(sample)));
+    assert!(matches!(
+        DataRecorder::from_bytes(&bytes[..9]),
+        Err(TapeError::TruncatedError(10, 9))
+    ));
+    assert!(matches!(
+        DataRecorder::from_bytes(b"RIFF"),
+        Err(TapeError::MissingMagicNumbersError)
+    ));
+}
+
+#[test]
+fn arkanoid_paddle() {
+    let mut input = InputDevices::new();
+    input.set_paddle(0xA5, true);
+    input.attach_device(Port::Two, Device::ArkanoidPaddle);
+    strobe(&mut input);
+
+    // the position comes out inverted and highest bit first on D4, the
+    // fire button is on D3 for every read
+    let bits: Vec<u8> = (0..10)
+        .map(|_| {
+            let value = input.read(0x4017, false);
+            assert_eq!(value & NES_FIRE, NES_FIRE);
+            (value & NES_DATA) >> 4
+        })
+        .collect();
+    assert_eq!(bits, [0, 1, 0, 1, 1, 0, 1, 0, 1, 1]);
+
+    // the famicom one is on bit 1 of both registers
+    input.attach_device(Port::Two, Device::StandardController);
+    input.attach_device(Port::Expansion, Device::ArkanoidPaddle);
+    input.set_paddle(0, false);
+    strobe(&mut input);
+    assert_eq!(input.read(0x4016, false) & 0b10, 0);
+    let bits: Vec<u8> = (0..8)
+        .map(|_| (input.read(0x4017, false) & 0b10) >> 1)
+        .collect();
+    // clamped to the lowest position
+    assert_eq!(bits, [1, 0, 0, 1, 1, 1, 0, 1]);
+
+    assert_eq!(paddle::position_from_x(0), POSITION_MIN);
+    assert_eq!(paddle::position_from_x(SCREEN_WIDTH), POSITION_MAX);
+    assert!(paddle::position_from_x(128) > paddle::position_from_x(127));
+}
+
+#[test]
+fn input_migration_from_v1() {
+    // version 1 kept the controllers at the end of the bus chunk
+    let mut bus = StateWriter::new();
+    bus.write_bytes(&[0; 2048]);
+    bus.write_u8(0x40);
+    bus.write_bytes(&[]);
+    let mut payload = bus.into_bytes();
+    payload.truncate(payload.len() - 4);
+    // controller 1 state and shift, controller 2 state and shift, strobe
+    payload.extend_from_slice(&[0b1, 0b1, 0b1000_0000, 0b1000_0000, 0]);
+
+    let mut chunks = vec![
+        Chunk {
+            tag: *b"BUS ",
+            payload,
+        },
+        // needed by the later migrations
+        Chunk {
+            tag: *b"PPU ",
+            payload: Vec::new(),
+        },
+        Chunk {
+            tag: *b"NES ",
+            payload: Vec::new(),
+        },
+        Chunk {
+            tag: *b"APU ",
+            payload: Vec::new(),
+        },
+    ];
+    migration::migrate(1, &mut chunks).unwrap();
+
+    let bus = chunks.iter().find(|chunk| chunk.tag == *b"BUS ").unwrap();
+    assert_eq!(bus.payload.len(), 4 + 2048 + 1);
+
+    let input_chunk = chunks.iter().find(|chunk| chunk.tag == *b"INPT").unwrap();
+    let mut input = InputDevices::new();
+    let mut reader = StateReader::new(&input_chunk.payload);
+    input.load_state(&mut reader).unwrap();
+    assert_eq!(reader.remaining(), 0);
+
+    assert_eq!(read_bits(&mut input, 0x4016, 2), [1, 0]);
+    assert_eq!(read_bits(&mut input, 0x4017, 8), [0, 0, 0, 0, 0, 0, 0, 1]);
+}
+
+#[test]
+fn turbo_buttons() {
+    let mut input = InputDevices::new();
+    input.set_turbo(0, Button::A, Some(TURBO_RATE_SLOW));
+    input.set_turbo(1, Button::B, Some(TURBO_RATE_FAST));
+    input.set_button(0, Button::A, true);
+    input.set_button(1, Button::B, true);
+
+    let mut presses = Vec::new();
+    for _ in 0..8 {
+        presses.push((input.get_buttons(0).bits(), input.get_buttons(1).bits()));
+        input.next_frame();
+    }
+    let (a, b) = (Button::A.mask(), Button::B.mask());
+    assert_eq!(
+        presses,
+        [
+            (a, b),
+            (a, 0),
+            (0, b),
+            (0, 0),
+            (a, b),
+            (a, 0),
+            (0, b),
+            (0, 0)
+        ]
+    );
+
+    // turning turbo off keeps the button held
+    input.set_turbo(0, Button::A, None);
+    assert_eq!(input.get_buttons(0), ControllerState::A);
+    input.set_button(1, Button::B, false);
+    input.next_frame();
+    assert!(input.get_buttons(1).is_empty());
+}
+
+#[test]
+fn controller_state() {
+    let state: ControllerState = [Button::Right, Button::B].into_iter().collect();
+    assert_eq!(state, ControllerState::RIGHT | ControllerState::B);
+    assert_eq!(state.bits(), 0x82);
+    assert!(state.is_pressed(Button::B));
+    assert!(state.contains(ControllerState::RIGHT));
+    assert!(!state.contains(ControllerState::RIGHT | ControllerState::A));
+    assert_eq!(
+        state.pressed().collect::<Vec<_>>(),
+        [Button::B, Button::Right]
+    );
+
+    // named like in input scripts, a byte in json
+    assert_eq!(state.to_string(), "b right");
+    assert_eq!("RIGHT b".parse(), Ok(state));
+    assert_eq!("".parse(), Ok(ControllerState::empty()));
+    assert_eq!(
+        "b jump".parse::<ControllerState>(),
+        Err(ButtonError::UnknownButtonError("jump".to_string()))
+    );
+    assert_eq!(serde_json::to_string(&state).unwrap(), "130");
+    assert_eq!(
+        serde_json::from_str::<ControllerState>("130").unwrap(),
+        state
+    );
+
+    let mut state = state;
+    state.set(Button::B, false);
+    state &= !ControllerState::LEFT;
+    assert_eq!(state, Button::Right.into());
+}
+
+#[test]
+fn opposite_directions() {
+    let mut input = InputDevices::new();
+    assert_eq!(input.get_opposite_directions(), OppositeDirections::Block);
+    input.set_button(0, Button::Left, true);
+    input.set_button(0, Button::Right, true);
+    input.set_button(0, Button::Up, true);
+    assert_eq!(input.get_buttons(0), ControllerState::UP);
+    // letting go of one gives the other back
+    input.set_button(0, Button::Left, false);
+    assert_eq!(
+        input.get_buttons(0),
+        ControllerState::UP | ControllerState::RIGHT
+    );
+
+    input.set_button(0, Button::Left, true);
+    input.set_opposite_directions(OppositeDirections::LastPressed);
+    assert_eq!(
+        input.get_buttons(0),
+        ControllerState::UP | ControllerState::LEFT
+    );
+    input.set_button(0, Button::Down, true);
+    assert_eq!(
+        input.get_buttons(0),
+        ControllerState::DOWN | ControllerState::LEFT
+    );
+    input.set_button(0, Button::Right, false);
+    input.set_button(0, Button::Right, true);
+    assert_eq!(
+        input.get_buttons(0),
+        ControllerState::DOWN | ControllerState::RIGHT
+    );
+
+    input.set_opposite_directions(OppositeDirections::Allow);
+    assert_eq!(input.get_buttons(0), "up down left right".parse().unwrap());
+}
+
+#[test]
+fn held_buttons_save_state() {
+    let plugged_in = || {
+        let mut input = InputDevices::new();
+        input.set_turbo(0, Button::A, Some(TURBO_RATE_SLOW));
+        input.set_opposite_directions(OppositeDirections::LastPressed);
+        input
+    };
+    let mut input = plugged_in();
+    input.set_button(0, Button::A, true);
+    input.set_button(0, Button::B, true);
+    input.set_button(0, Button::Right, true);
+    input.set_button(0, Button::Left, true);
+    input.next_frame();
+    let mut writer = StateWriter::new();
+    input.save_state(&mut writer);
+    let state = writer.into_bytes();
+
+    let mut loaded = plugged_in();
+    let mut reader = StateReader::new(&state);
+    loaded.load_state(&mut reader).unwrap();
+    assert_eq!(reader.remaining(), 0);
+    // the turbo keeps firing in step
+    for _ in 0..4 {
+        assert_eq!(loaded.get_buttons(0), input.get_buttons(0));
+        input.next_frame();
+        loaded.next_frame();
+    }
+    // B and left are still held, and left was pressed last
+    loaded.set_button(0, Button::A, false);
+    assert_eq!(
+        loaded.get_buttons(0),
+        ControllerState::B | ControllerState::LEFT
+    );
+}
+
+#[test]
+fn input_display() {
+    let mut frame = Frame::new();
+    frame.fill(0x123456);
+    let mut display = InputDisplay::new();
+    display.draw(&mut frame, |_| !ControllerState::empty());
+    assert!(frame.pixels().iter().all(|pixel| *pixel == 0x123456));
+
+    // only the second controller, drawn in the first slot
+    display.set_enabled(1, true);
+    display.draw(&mut frame, |controller_index| match controller_index {
+        1 => ControllerState::A,
+        _ => !ControllerState::empty(),
+    });
+    let y = SCREEN_HEIGHT - 4 - 11;
+    // A, B and the background of the controller
+    assert_eq!(frame.get_pixel(4 + 28, y + 6), osd::TEXT_COLOR);
+    assert_eq!(frame.get_pixel(4 + 24, y + 6), osd::DIM_COLOR);
+    assert_eq!(frame.get_pixel(4, y), osd::BACKGROUND_COLOR);
+    // nothing where the second slot would be
+    assert_eq!(frame.get_pixel(4 + 35 + 28, y + 6), 0x123456);
+}
+
+#[test]
+fn input_macros() {
+    let mut input = InputDevices::new();
+    input.start_macro_recording(0);
+    for buttons in [0x01, 0x81, 0x00].map(ControllerState::from_bits) {
+        for button in Button::ALL {
+            input.set_button(0, button, buttons.is_pressed(button));
+        }
+        input.next_frame();
+    }
+    let recorded = input.stop_macro_recording().unwrap();
+    assert_eq!(
+        recorded.frames,
+        [0x01, 0x81, 0x00].map(ControllerState::from_bits)
+    );
+    assert!(!input.is_recording_macro());
+
+    let mut bindings = MacroBindings::new();
+    bindings.bind("F1", recorded);
+    let input_macro = bindings.get(&"F1").unwrap().clone();
+
+    // the player holds B the whole time
+    input.set_button(0, Button::B, true);
+    input.play_macro(0, &input_macro, MacroPriority::Replace);
+    let mut seen = Vec::new();
+    for _ in 0..4 {
+        seen.push(input.get_buttons(0).bits());
+        input.next_frame();
+    }
+    assert_eq!(seen, [0x01, 0x81, 0x00, 0x02]);
+    assert!(!input.is_playing_macro());
+
+    input.play_macro(0, &input_macro, MacroPriority::Merge);
+    assert_eq!(input.get_buttons(0).bits(), 0x03);
+    // merged, the player's own presses still go through
+    input.set_button(0, Button::B, false);
+    input.set_button(0, Button::Up, true);
+    assert_eq!(
+        input.get_buttons(0),
+        ControllerState::A | ControllerState::UP
+    );
+    input.stop_macro();
+    assert_eq!(input.get_buttons(0), ControllerState::UP);
+    // the other controller is never touched
+    assert!(input.get_buttons(1).is_empty());
+
+    input.play_macro(1, &InputMacro::default(), MacroPriority::Replace);
+    assert!(!input.is_playing_macro());
+}
+
+#[test]
+fn fast_boot() {
+    let fast_boot = FastBoot {
+        delay: 2,
+        presses: 2,
+        interval: 4,
+    };
+    let (none, start) = (ControllerState::empty(), ControllerState::START);
+    assert_eq!(
+        fast_boot.to_macro().frames,
+        [none, none, start, start, none, none, start, start]
+    );
+    // missing fields come from the default
+    let json = r#"{ "delay": 90 }"#;
+    assert_eq!(
+        serde_json::from_str::<FastBoot>(json).unwrap(),
+        FastBoot {
+            delay: 90,
+            ..FastBoot::default()
+        }
+    );
+
+    let mut input = InputDevices::new();
+    input.start_fast_boot(Some(fast_boot));
+    // the player's presses still go through
+    input.set_button(0, Button::A, true);
+    let mut seen = Vec::new();
+    for _ in 0..10 {
+        seen.push(input.get_buttons(0));
+        input.next_frame();
+    }
+    let (a, a_start) = (
+        ControllerState::A,
+        ControllerState::A | ControllerState::START,
+    );
+    assert_eq!(seen, [a, a, a_start, a_start, a, a, a_start, a_start, a, a]);
+    assert!(!input.is_fast_booting());
+
+    // on top of a macro of the player's
+    let input_macro = InputMacro {
+        frames: vec![ControllerState::B; 4],
+    };
+    input.play_macro(0, &input_macro, MacroPriority::Replace);
+    input.start_fast_boot(Some(FastBoot {
+        delay: 0,
+        ..fast_boot
+    }));
+    assert_eq!(input.get_buttons(0), ControllerState::B | start);
+    input.start_fast_boot(None);
+    assert_eq!(input.get_buttons(0), ControllerState::B);
+}
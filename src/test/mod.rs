@@ -1,17 +1,37 @@
 #![cfg(test)]
 
+mod achievements;
+mod apu;
+mod assembler;
+mod cartrige;
+mod debugger;
+mod frontend;
+mod input;
+mod osd;
+mod ppu_snapshots;
+mod remote;
+mod run;
+mod save_state;
+mod single_step;
 mod test_logger;
 
 use std::env;
 
-use crate::{devices::nes::Nes, hardware::cartrige::Cartrige, test::test_logger::TestLogger};
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt};
 
-static NESTEST_TEST_LOGGER: TestLogger = TestLogger::new();
+use crate::{
+    devices::nes::Nes, hardware::cartrige::Cartrige, test::test_logger::TestLogger, trace::targets,
+};
 
 #[test]
 fn nestest() {
-    log::set_logger(&NESTEST_TEST_LOGGER).unwrap();
-    log::set_max_level(log::LevelFilter::Trace);
+    let logger = TestLogger::new(targets::CPU);
+    let subscriber = tracing_subscriber::registry()
+        .with(logger.clone())
+        .with(LevelFilter::TRACE);
+    // only captures the traces of this thread, so tests running in
+    // parallel don't end up in the log
+    let _guard = tracing::subscriber::set_default(subscriber);
 
     let mut nes = Nes::new();
     // Thank you nes dev wiki https://www.nesdev.org/wiki/Emulator_tests
@@ -31,7 +51,7 @@ fn nestest() {
     let correct_log = include_str!("./nestest/correct_nestest.log");
     // windows line endings lmfao
     let correct_log = correct_log.replace("\r\n", "\n");
-    let actual_log_res = NESTEST_TEST_LOGGER.logs.read().unwrap();
+    let actual_log_res = logger.logs.read().unwrap();
     let actual_log = actual_log_res.as_str();
 
     let equal = correct_log == actual_log;
@@ -0,0 +1,205 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    hardware::ppu::frame::Frame,
+    osd::{
+        TEXT_COLOR,
+        notifications::{NOTIFICATION_FRAMES, Notifications},
+        overlay::{self, Drawing, Overlay, Shape},
+        performance::{FrameTiming, PerformanceHud, PerformanceStats},
+        quick_menu::{MenuAction, QuickMenu},
+        text,
+    },
+};
+
+/// Lets the test look at the csv after giving it away
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn timing(emulation_ms: u64, present_ms: u64) -> FrameTiming {
+    FrameTiming {
+        emulation: Duration::from_millis(emulation_ms),
+        present: Duration::from_millis(present_ms),
+        audio_fill: 0.5,
+    }
+}
+
+#[test]
+fn performance_stats() {
+    let mut stats = PerformanceStats::new();
+    assert!(stats.summary().is_none());
+
+    let csv = SharedBuffer::default();
+    stats.start_csv(csv.clone()).unwrap();
+    // 99 frames at 100 fps and one at 20 fps
+    for _ in 0..99 {
+        stats.record(timing(8, 2)).unwrap();
+    }
+    stats.record(timing(40, 10)).unwrap();
+    let summary = stats.summary().unwrap();
+    assert_eq!(summary.one_percent_low_fps.round(), 20.0);
+    assert_eq!(summary.audio_fill, 0.5);
+
+    // frames after stopping don't end up in the csv
+    stats.stop_csv().unwrap();
+    stats.record(timing(8, 2)).unwrap();
+
+    let csv = String::from_utf8(csv.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 101);
+    assert_eq!(lines[0], "frame,emulation_us,present_us,audio_fill");
+    assert_eq!(lines[100], "100,40000,10000,0.500");
+}
+
+#[test]
+fn performance_hud_toggle() {
+    let mut stats = PerformanceStats::new();
+    stats.record(timing(8, 2)).unwrap();
+    let mut hud = PerformanceHud::new();
+
+    let mut frame = Frame::new();
+    hud.draw(&mut frame, &stats);
+    assert_eq!(frame, Frame::new());
+
+    hud.toggle();
+    hud.draw(&mut frame, &stats);
+    assert_ne!(frame, Frame::new());
+}
+
+#[test]
+fn notifications() {
+    let mut notifications = Notifications::new();
+    for message in ["one", "two", "three", "four"] {
+        notifications.push(message);
+    }
+    assert_eq!(
+        notifications.visible().collect::<Vec<_>>(),
+        ["one", "two", "three"]
+    );
+
+    let mut frame = Frame::new();
+    frame.fill(0x123456);
+    notifications.draw(&mut frame);
+    assert!(frame.pixels().contains(&TEXT_COLOR));
+    assert_eq!(text::text_width("one"), 11);
+
+    for _ in 1..NOTIFICATION_FRAMES {
+        notifications.draw(&mut Frame::new());
+    }
+    assert_eq!(notifications.visible().collect::<Vec<_>>(), ["four"]);
+    for _ in 0..NOTIFICATION_FRAMES {
+        notifications.draw(&mut Frame::new());
+    }
+    assert!(notifications.is_empty());
+}
+
+#[test]
+fn quick_menu() {
+    let mut menu = QuickMenu::default();
+    let mut frame = Frame::new();
+    menu.draw(&mut frame);
+    assert!(frame.pixels().iter().all(|pixel| *pixel == 0));
+    assert_eq!(menu.confirm(), None);
+
+    menu.toggle();
+    assert!(menu.is_open());
+    menu.select_previous();
+    assert_eq!(menu.selected(), Some(MenuAction::ToggleFilter));
+    menu.select_next();
+    menu.select_next();
+    assert_eq!(menu.selected(), Some(MenuAction::LoadState));
+
+    frame.fill(0x123456);
+    menu.draw(&mut frame);
+    assert!(frame.pixels().contains(&TEXT_COLOR));
+    assert_eq!(frame.get_pixel(0, 0), 0x123456);
+
+    assert_eq!(menu.confirm(), Some(MenuAction::LoadState));
+    assert!(!menu.is_open());
+
+    let mut actions = MenuAction::DEFAULT.to_vec();
+    actions.push(MenuAction::SwapDiskSide);
+    menu.set_actions(actions);
+    menu.open();
+    menu.select_previous();
+    assert_eq!(menu.confirm(), Some(MenuAction::SwapDiskSide));
+}
+
+#[test]
+fn overlay_shapes() {
+    assert_eq!(overlay::blend(0x000000, 0xFF8040, 255), 0xFF8040);
+    assert_eq!(overlay::blend(0xFFFFFF, 0x000000, 0), 0xFFFFFF);
+    assert_eq!(overlay::blend(0x0000FF, 0xFF0000, 128), 0x80007F);
+
+    let mut frame = Frame::new();
+    let mut overlay = Overlay::new();
+    overlay.push(Drawing::new(
+        Shape::Line {
+            x1: 10,
+            y1: 10,
+            x2: 13,
+            y2: 13,
+        },
+        0xFFFFFF,
+    ));
+    overlay.push(Drawing::new(
+        Shape::Rect {
+            x: 20,
+            y: 20,
+            width: 4,
+            height: 3,
+            filled: false,
+        },
+        0x00FF00,
+    ));
+    overlay.push(Drawing::new(Shape::Pixel { x: -1, y: 300 }, 0xFFFFFF));
+    overlay.push(Drawing::new(Shape::Pixel { x: 0, y: 0 }, 0xFFFFFF).with_alpha(0x80));
+    overlay.push(Drawing::new(
+        Shape::Text {
+            x: 40,
+            y: 40,
+            text: "1".to_string(),
+        },
+        TEXT_COLOR,
+    ));
+    overlay.composite(&mut frame);
+    assert!(overlay.is_empty());
+
+    assert!((10..=13).all(|i| frame.get_pixel(i, i) == 0xFFFFFF));
+    assert_eq!(frame.get_pixel(11, 10), 0);
+    assert_eq!(frame.get_pixel(23, 22), 0x00FF00);
+    assert_eq!(frame.get_pixel(20, 21), 0x00FF00);
+    assert_eq!(frame.get_pixel(21, 21), 0, "only the outline");
+    assert_eq!(frame.get_pixel(0, 0), 0x808080);
+    assert_eq!(frame.get_pixel(41, 40), TEXT_COLOR);
+    assert_eq!(frame.get_pixel(40, 40), 0);
+
+    // lines get clipped to the screen, however far off their ends are
+    let mut frame = Frame::new();
+    for (x1, y1, x2, y2) in [
+        (i32::MIN, 100, i32::MAX, 100),
+        (-10, -10, 5, 5),
+        (-50, -10, -5, -20),
+    ] {
+        overlay.push(Drawing::new(Shape::Line { x1, y1, x2, y2 }, 0xFFFFFF));
+    }
+    overlay.composite(&mut frame);
+    assert!((0..256).all(|x| frame.get_pixel(x, 100) == 0xFFFFFF));
+    assert!((0..=5).all(|i| frame.get_pixel(i, i) == 0xFFFFFF));
+    assert_eq!(frame.get_pixel(6, 6), 0);
+    assert_eq!(frame.get_pixel(1, 0), 0);
+}
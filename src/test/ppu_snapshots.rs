@@ -0,0 +1,657 @@
+//! Golden image tests for the ppu. Every test builds a small rom, sets up
+//! the ppu through its registers and compares the rendered frame with a
+//! png in `src/test/snapshots`.
+//!
+//! After an intended change to the rendering run the tests with
+//! `UPDATE_SNAPSHOTS=1 cargo test` to overwrite the snapshots, then check
+//! the new pngs by hand before commiting them.
+
+use std::{env, fs::File, io::BufWriter, path::PathBuf};
+
+use crate::{
+    devices::{nes::Nes, run::BreakReason},
+    hardware::{
+        cartrige::Cartrige,
+        constants::ppu::{COLORS, SCREEN_HEIGHT, SCREEN_WIDTH, mask_flags, status_flags},
+        ppu::{
+            Layer,
+            frame::Frame,
+            renderer::{self, RenderMode},
+            scanline::PpuAccuracy,
+        },
+    },
+    osd::{TEXT_COLOR, tile_inspector::TileInspector},
+};
+
+const FRAMES_TO_RUN: usize = 3;
+/// Enough cpu cycles to get anywhere in the next frame
+const FRAME_CYCLES: u64 = 40_000;
+
+#[rustfmt::skip]
+const PALLETS: [u8; 32] = [
+    0x0F, 0x16, 0x2A, 0x12,   0x0F, 0x27, 0x1A, 0x30,   0x0F, 0x11, 0x21, 0x31,   0x0F, 0x05, 0x15, 0x25,
+    0x0F, 0x28, 0x38, 0x30,   0x0F, 0x14, 0x24, 0x34,   0x0F, 0x19, 0x29, 0x39,   0x0F, 0x00, 0x10, 0x20,
+];
+
+/// Both pattern tables get these tiles:
+/// 0 is empty, 1-3 are filled with that color, 4 is a checkerboard and
+/// 5 is a triangle, so flipping it is visible
+fn tiles() -> Vec<[u8; 16]> {
+    let checkerboard = [0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55];
+    let triangle = [0x80, 0xC0, 0xE0, 0xF0, 0xF8, 0xFC, 0xFE, 0xFF];
+    let mut out = vec![[0; 16]; 6];
+    out[1][..8].fill(0xFF);
+    out[2][8..].fill(0xFF);
+    out[3].fill(0xFF);
+    out[4][..8].copy_from_slice(&checkerboard);
+    out[4][8..].fill(0xF0);
+    out[5][..8].copy_from_slice(&triangle);
+    out[5][12..].fill(0xFF);
+    out
+}
+
+/// NROM with vertical mirroring, the program is just `JMP $8000`
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x01];
+    rom.resize(16, 0);
+
+    let mut prg = vec![0xEA; 0x4000];
+    prg[0..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+    // nmi, reset and irq vectors
+    prg[0x3FFA..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    rom.extend_from_slice(&prg);
+
+    let mut chr = vec![0; 0x2000];
+    for (i, tile) in tiles().iter().enumerate() {
+        chr[i * 16..(i + 1) * 16].copy_from_slice(tile);
+        chr[0x1000 + i * 16..0x1000 + (i + 1) * 16].copy_from_slice(tile);
+    }
+    rom.extend_from_slice(&chr);
+    rom
+}
+
+fn write_ppu_memory(nes: &mut Nes, address: u16, data: &[u8]) {
+    nes.bus.read(0x2002);
+    nes.bus.write(0x2006, (address >> 8) as u8);
+    nes.bus.write(0x2006, address as u8);
+    for value in data {
+        nes.bus.write(0x2007, *value);
+    }
+}
+
+/// A nes with the pallets and both nametables filled in, rendering is
+/// still disabled
+fn setup_nes() -> Nes {
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(&test_rom()).unwrap());
+    nes.reset_with_program_counter(0x8000);
+
+    write_ppu_memory(&mut nes, 0x3F00, &PALLETS);
+
+    let mut nametables = vec![0; 0x800];
+    for y in 0..30 {
+        for x in 0..32 {
+            nametables[y * 32 + x] = ((x + y) % 6) as u8;
+            nametables[0x400 + y * 32 + x] = ((x * y) % 6) as u8;
+        }
+    }
+    for i in 0..64 {
+        nametables[0x3C0 + i] = (i as u8).wrapping_mul(0x1B);
+        nametables[0x7C0 + i] = (i as u8).wrapping_mul(0x2D);
+    }
+    write_ppu_memory(&mut nes, 0x2000, &nametables);
+    nes
+}
+
+fn set_scroll(nes: &mut Nes, control: u8, x: u8, y: u8) {
+    nes.bus.read(0x2002);
+    nes.bus.write(0x2000, control);
+    nes.bus.write(0x2005, x);
+    nes.bus.write(0x2005, y);
+}
+
+fn render(nes: &mut Nes, mask: u8) -> Frame {
+    nes.bus.write(0x2001, mask);
+    let mut frame = Frame::new();
+    for _ in 0..FRAMES_TO_RUN {
+        nes.run_frame(&mut frame);
+    }
+    frame
+}
+
+/// A register write the cpu makes right before the ppu draws `dot` of
+/// `scanline`
+struct RasterWrite {
+    scanline: u32,
+    dot: u32,
+    address: u16,
+    value: u8,
+}
+
+/// Does nothing if the ppu is already there, so writes can share a dot
+fn run_to_dot(nes: &mut Nes, scanline: u32, dot: u32) {
+    if nes.ppu_dot_position() == (scanline, dot) {
+        return;
+    }
+    let summary = nes.run_until(FRAME_CYCLES, |nes| {
+        nes.ppu_dot_position() == (scanline, dot)
+    });
+    assert_eq!(summary.break_reason, BreakReason::ConditionMet);
+}
+
+/// Like [render] but every frame starts with no scroll (set in vblank like
+/// games do) and gets `writes` in the middle of it
+fn render_with_writes(nes: &mut Nes, mask: u8, writes: &[RasterWrite]) -> Frame {
+    for _ in 0..FRAMES_TO_RUN {
+        run_to_dot(nes, 241, 1);
+        set_scroll(nes, 0, 0, 0);
+        nes.bus.write(0x2001, mask);
+        for write in writes {
+            run_to_dot(nes, write.scanline, write.dot);
+            nes.bus.write(write.address, write.value);
+        }
+        run_to_dot(nes, 240, 0);
+    }
+    let mut frame = Frame::new();
+    frame.clone_from(&nes.get_last_frame());
+    frame
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "src", "test", "snapshots"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{name}.png"))
+}
+
+fn write_png(path: &PathBuf, frame: &Frame) {
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&frame.to_rgb_bytes()).unwrap();
+}
+
+fn read_png(path: &PathBuf) -> Option<Vec<u8>> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    buffer.truncate(info.buffer_size());
+    Some(buffer)
+}
+
+fn assert_snapshot(name: &str, frame: &Frame) {
+    let path = snapshot_path(name);
+    if env::var("UPDATE_SNAPSHOTS").is_ok_and(|value| value == "1") {
+        write_png(&path, frame);
+        return;
+    }
+
+    let Some(expected) = read_png(&path) else {
+        panic!(
+            "Snapshot {} is missing! Run the tests with UPDATE_SNAPSHOTS=1 to create it.",
+            path.display()
+        );
+    };
+    let actual = frame.to_rgb_bytes();
+    if expected != actual {
+        let mut actual_path = env::temp_dir();
+        actual_path.push(format!("scam_snapshot_{name}.png"));
+        write_png(&actual_path, frame);
+
+        let different_pixels = expected
+            .chunks(3)
+            .zip(actual.chunks(3))
+            .filter(|(expected, actual)| expected != actual)
+            .count();
+        panic!(
+            "Snapshot {name} has {different_pixels} different pixels!\nExpected: {}\nGot: {}",
+            path.display(),
+            actual_path.display()
+        );
+    }
+}
+
+#[test]
+fn snapshot_background() {
+    let mut nes = setup_nes();
+    set_scroll(&mut nes, 0, 0, 0);
+    let frame = render(
+        &mut nes,
+        mask_flags::ENABLE_BG_RENDERING | mask_flags::SHOW_LEFTMOST_BACKGROUND,
+    );
+    assert_snapshot("background", &frame);
+}
+
+#[test]
+fn snapshot_sprites() {
+    let mut nes = setup_nes();
+    // empty the top half so the sprites can be seen on top of nothing
+    write_ppu_memory(&mut nes, 0x2000, &[0; 15 * 32]);
+    for i in 0..64 {
+        let sprite = [
+            (i / 8 * 28 + 8) as u8,
+            [1, 2, 3, 4, 5][i % 5],
+            (i % 8) as u8 * 0x20 + (i / 8) as u8,
+            (i % 8 * 30 + 8) as u8,
+        ];
+        nes.ppu.lock().unwrap().oam[i * 4..i * 4 + 4].copy_from_slice(&sprite);
+    }
+    set_scroll(&mut nes, 0, 0, 0);
+    let frame = render(
+        &mut nes,
+        mask_flags::ENABLE_BG_RENDERING
+            | mask_flags::ENABLE_SPRITE_RENDERING
+            | mask_flags::SHOW_LEFTMOST_BACKGROUND
+            | mask_flags::SHOW_LEFTMOST_SPRITE,
+    );
+    assert_snapshot("sprites", &frame);
+}
+
+#[test]
+fn snapshot_scrolling() {
+    let mut nes = setup_nes();
+    set_scroll(&mut nes, 0, 100, 37);
+    let frame = render(
+        &mut nes,
+        mask_flags::ENABLE_BG_RENDERING | mask_flags::SHOW_LEFTMOST_BACKGROUND,
+    );
+    assert_snapshot("scrolling", &frame);
+}
+
+#[test]
+fn snapshot_emphasis() {
+    let mut nes = setup_nes();
+    set_scroll(&mut nes, 0, 0, 0);
+    let frame = render(
+        &mut nes,
+        mask_flags::ENABLE_BG_RENDERING
+            | mask_flags::SHOW_LEFTMOST_BACKGROUND
+            | mask_flags::EMPHASIZE_RED
+            | mask_flags::EMPHASIZE_BLUE,
+    );
+    assert_snapshot("emphasis", &frame);
+
+    let frame = render(
+        &mut nes,
+        mask_flags::ENABLE_BG_RENDERING
+            | mask_flags::SHOW_LEFTMOST_BACKGROUND
+            | mask_flags::GRAYSCALE,
+    );
+    assert_snapshot("grayscale", &frame);
+}
+
+/// The status bar split of SMB: the scroll changes in hblank and the new
+/// x scroll gets copied into v at dot 257 of the next line
+#[test]
+fn snapshot_split_scroll() {
+    let mut nes = setup_nes();
+    let split = |value: u8| RasterWrite {
+        scanline: 31,
+        dot: 300,
+        address: 0x2005,
+        value,
+    };
+    let mask = mask_flags::ENABLE_BG_RENDERING | mask_flags::SHOW_LEFTMOST_BACKGROUND;
+    let frame = render_with_writes(&mut nes, mask, &[split(83), split(0)]);
+    assert_snapshot("split_scroll", &frame);
+
+    // above the split nothing moved, the line right after it already
+    // has the new fine x but still the old coarse x
+    let unsplit = render_with_writes(&mut nes, mask, &[]);
+    let rows = |frame: &Frame, rows: std::ops::Range<usize>| {
+        frame.pixels()[rows.start * SCREEN_WIDTH..rows.end * SCREEN_WIDTH].to_vec()
+    };
+    assert_eq!(rows(&frame, 0..32), rows(&unsplit, 0..32));
+    assert_ne!(
+        rows(&frame, 33..SCREEN_HEIGHT),
+        rows(&unsplit, 33..SCREEN_HEIGHT)
+    );
+}
+
+/// Fine x is used straight from the register so it changes in the middle
+/// of the line, PPUMASK changes take effect on the next dot
+#[test]
+fn snapshot_mid_scanline_writes() {
+    let mut nes = setup_nes();
+    let mask = mask_flags::ENABLE_BG_RENDERING | mask_flags::SHOW_LEFTMOST_BACKGROUND;
+    let write = |scanline, dot, address, value| RasterWrite {
+        scanline,
+        dot,
+        address,
+        value,
+    };
+    let frame = render_with_writes(
+        &mut nes,
+        mask,
+        &[
+            write(60, 100, 0x2001, mask | mask_flags::GRAYSCALE),
+            write(120, 180, 0x2001, mask),
+            write(160, 128, 0x2005, 5),
+            write(160, 128, 0x2005, 0),
+        ],
+    );
+    assert_snapshot("mid_scanline_writes", &frame);
+
+    let plain = render_with_writes(&mut nes, mask, &[]);
+    let row =
+        |frame: &Frame, y: usize| frame.pixels()[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH].to_vec();
+    assert_eq!(row(&frame, 59), row(&plain, 59));
+    assert_eq!(row(&frame, 60)[..96], row(&plain, 60)[..96]);
+    assert_ne!(row(&frame, 60)[104..], row(&plain, 60)[104..]);
+    assert_eq!(row(&frame, 120)[184..], row(&plain, 120)[184..]);
+    assert_eq!(row(&frame, 160)[..124], row(&plain, 160)[..124]);
+    assert_ne!(row(&frame, 160)[132..], row(&plain, 160)[132..]);
+}
+
+/// Everything without mid line writes looks the same with the scanline
+/// renderer, so it has to match the same snapshots
+#[test]
+fn scanline_renderer_snapshots() {
+    let scanline_nes = || {
+        let mut nes = setup_nes();
+        nes.set_ppu_accuracy(PpuAccuracy::Scanline);
+        nes
+    };
+    let background = mask_flags::ENABLE_BG_RENDERING | mask_flags::SHOW_LEFTMOST_BACKGROUND;
+
+    let mut nes = scanline_nes();
+    set_scroll(&mut nes, 0, 0, 0);
+    assert_snapshot("background", &render(&mut nes, background));
+    let frame = render(&mut nes, background | mask_flags::GRAYSCALE);
+    assert_snapshot("grayscale", &frame);
+
+    let mut nes = scanline_nes();
+    set_scroll(&mut nes, 0, 100, 37);
+    assert_snapshot("scrolling", &render(&mut nes, background));
+
+    let mut nes = scanline_nes();
+    write_ppu_memory(&mut nes, 0x2000, &[0; 15 * 32]);
+    for i in 0..64 {
+        let sprite = [
+            (i / 8 * 28 + 8) as u8,
+            [1, 2, 3, 4, 5][i % 5],
+            (i % 8) as u8 * 0x20 + (i / 8) as u8,
+            (i % 8 * 30 + 8) as u8,
+        ];
+        nes.ppu.lock().unwrap().oam[i * 4..i * 4 + 4].copy_from_slice(&sprite);
+    }
+    set_scroll(&mut nes, 0, 0, 0);
+    let frame = render(
+        &mut nes,
+        background | mask_flags::ENABLE_SPRITE_RENDERING | mask_flags::SHOW_LEFTMOST_SPRITE,
+    );
+    assert_snapshot("sprites", &frame);
+
+    // the split is written in hblank, so it lands on the same line
+    let mut nes = scanline_nes();
+    let split = |value: u8| RasterWrite {
+        scanline: 31,
+        dot: 300,
+        address: 0x2005,
+        value,
+    };
+    let frame = render_with_writes(&mut nes, background, &[split(83), split(0)]);
+    assert_snapshot("split_scroll", &frame);
+}
+
+/// The scanline renderer sets the sprite 0 hit at the end of the line
+#[test]
+fn scanline_renderer_sprite_0_hit() {
+    let mask = mask_flags::ENABLE_BG_RENDERING
+        | mask_flags::ENABLE_SPRITE_RENDERING
+        | mask_flags::SHOW_LEFTMOST_BACKGROUND
+        | mask_flags::SHOW_LEFTMOST_SPRITE;
+    let hit_at = |accuracy| {
+        let mut nes = setup_nes();
+        nes.set_ppu_accuracy(accuracy);
+        nes.ppu.lock().unwrap().oam[..4].copy_from_slice(&[40, 1, 0, 40]);
+        render_with_writes(&mut nes, mask, &[]);
+        run_to_dot(&mut nes, 261, 2);
+        let summary = nes.run_until(FRAME_CYCLES, |nes| {
+            nes.bus.peek(0x2002) & status_flags::SPRITE_0_HIT != 0
+        });
+        assert_eq!(summary.break_reason, BreakReason::ConditionMet);
+        nes.ppu_dot_position()
+    };
+    let (cycle_line, cycle_dot) = hit_at(PpuAccuracy::Cycle);
+    let (scanline_line, scanline_dot) = hit_at(PpuAccuracy::Scanline);
+    assert_eq!(cycle_line, 41);
+    assert_eq!(scanline_line, 41);
+    assert!(cycle_dot < 100);
+    assert!(scanline_dot > 256);
+}
+
+/// 10 sprites on the same lines, the last 2 only get drawn without the
+/// limit but the overflow flag gets set either way
+#[test]
+fn sprite_limit_removed() {
+    let mask = mask_flags::ENABLE_BG_RENDERING
+        | mask_flags::ENABLE_SPRITE_RENDERING
+        | mask_flags::SHOW_LEFTMOST_BACKGROUND
+        | mask_flags::SHOW_LEFTMOST_SPRITE;
+    for accuracy in [PpuAccuracy::Cycle, PpuAccuracy::Scanline] {
+        for removed in [false, true] {
+            let mut nes = setup_nes();
+            nes.set_ppu_accuracy(accuracy);
+            nes.set_sprite_limit_removed(removed);
+            write_ppu_memory(&mut nes, 0x2000, &[0; 15 * 32]);
+            {
+                let mut ppu = nes.ppu.lock().unwrap();
+                ppu.oam.fill(0xFF);
+                for i in 0..10 {
+                    ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[40, 3, 0, i as u8 * 20 + 8]);
+                }
+            }
+            set_scroll(&mut nes, 0, 0, 0);
+            let frame = render(&mut nes, mask);
+
+            let backdrop = frame.get_pixel(2, 44);
+            let first = frame.get_pixel(10, 44);
+            assert_ne!(first, backdrop);
+            for i in 8..10 {
+                let pixel = frame.get_pixel(i * 20 + 10, 44);
+                assert_eq!(pixel, if removed { first } else { backdrop });
+            }
+
+            run_to_dot(&mut nes, 100, 0);
+            assert_ne!(
+                nes.bus.peek(0x2002) & status_flags::SPRITE_OVERFLOW,
+                0,
+                "{accuracy:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn async_renderer_matches_inline() {
+    let mut inline = setup_nes();
+    let mut threaded = setup_nes();
+    threaded.set_render_mode(RenderMode::Async);
+    assert_eq!(threaded.get_render_mode(), RenderMode::Async);
+
+    let mask = mask_flags::ENABLE_BG_RENDERING
+        | mask_flags::SHOW_LEFTMOST_BACKGROUND
+        | mask_flags::EMPHASIZE_GREEN;
+    let mut inline_frames = Vec::new();
+    let mut threaded_frames = Vec::new();
+    for i in 0..5 {
+        for nes in [&mut inline, &mut threaded] {
+            set_scroll(nes, 0, i * 13, i * 7);
+            nes.bus.write(0x2001, mask);
+        }
+        let mut frame = Frame::new();
+        inline.run_frame(&mut frame);
+        inline_frames.push(frame.clone());
+        threaded.run_frame(&mut frame);
+        threaded_frames.push(frame);
+    }
+
+    // the async frames come out one frame late
+    assert_ne!(inline_frames[1], inline_frames[2]);
+    assert_eq!(&threaded_frames[1..], &inline_frames[..4]);
+}
+
+#[test]
+fn video_disabled() {
+    let mut drawn = setup_nes();
+    let mut headless = setup_nes();
+    headless.set_video_enabled(false);
+    let mask = mask_flags::ENABLE_BG_RENDERING
+        | mask_flags::ENABLE_SPRITE_RENDERING
+        | mask_flags::SHOW_LEFTMOST_BACKGROUND
+        | mask_flags::SHOW_LEFTMOST_SPRITE;
+    for nes in [&mut drawn, &mut headless] {
+        // sprite 0 on top of the background
+        nes.ppu.lock().unwrap().oam[..4].copy_from_slice(&[40, 1, 0, 40]);
+        set_scroll(nes, 0, 0, 0);
+        nes.bus.write(0x2001, mask);
+    }
+
+    let mut drawn_frame = Frame::new();
+    let mut headless_frame = Frame::new();
+    for _ in 0..FRAMES_TO_RUN {
+        drawn.run_frame(&mut drawn_frame);
+        headless.run_frame(&mut headless_frame);
+    }
+    assert_ne!(drawn_frame, Frame::new());
+    assert_eq!(headless_frame, Frame::new());
+    assert_eq!(*headless.get_last_frame(), Frame::new());
+    assert_eq!(headless.frame_count(), drawn.frame_count());
+    assert_eq!(headless.total_cpu_cycles(), drawn.total_cpu_cycles());
+
+    // the sprite 0 hit still happens at the same dot
+    for nes in [&mut drawn, &mut headless] {
+        run_to_dot(nes, 41, 0);
+        assert_eq!(nes.bus.peek(0x2002) & 0x40, 0);
+        run_to_dot(nes, 42, 0);
+        assert_eq!(nes.bus.peek(0x2002) & 0x40, 0x40);
+    }
+
+    // the top of this frame was skipped, the next one is whole again
+    headless.set_video_enabled(true);
+    for _ in 0..2 {
+        headless.run_frame(&mut headless_frame);
+        drawn.run_frame(&mut drawn_frame);
+    }
+    assert_eq!(headless_frame, drawn_frame);
+}
+
+#[test]
+fn layer_toggles() {
+    let all = mask_flags::ENABLE_BG_RENDERING
+        | mask_flags::ENABLE_SPRITE_RENDERING
+        | mask_flags::SHOW_LEFTMOST_BACKGROUND
+        | mask_flags::SHOW_LEFTMOST_SPRITE;
+    // hiding a layer looks like turning it off in PPUMASK
+    for (layer, mask) in [
+        (Layer::Background, all & !mask_flags::ENABLE_BG_RENDERING),
+        (Layer::Sprites, all & !mask_flags::ENABLE_SPRITE_RENDERING),
+    ] {
+        let mut hidden = setup_nes();
+        let mut masked = setup_nes();
+        assert!(!hidden.toggle_layer(layer));
+        assert!(!hidden.is_layer_shown(layer));
+        for (nes, mask) in [(&mut hidden, all), (&mut masked, mask)] {
+            // sprite 0 on top of the background
+            nes.ppu.lock().unwrap().oam[..4].copy_from_slice(&[40, 1, 0, 40]);
+            set_scroll(nes, 0, 0, 0);
+            nes.bus.write(0x2001, mask);
+        }
+
+        let mut hidden_frame = Frame::new();
+        let mut masked_frame = Frame::new();
+        for _ in 0..FRAMES_TO_RUN {
+            hidden.run_frame(&mut hidden_frame);
+            masked.run_frame(&mut masked_frame);
+        }
+        assert_eq!(hidden_frame, masked_frame);
+
+        // but the sprite 0 hit still happens
+        run_to_dot(&mut hidden, 42, 0);
+        assert_eq!(hidden.bus.peek(0x2002) & 0x40, 0x40);
+        assert!(hidden.toggle_layer(layer));
+    }
+}
+
+#[test]
+fn tile_inspector() {
+    for accuracy in [PpuAccuracy::Scanline, PpuAccuracy::Cycle] {
+        let mut nes = setup_nes();
+        nes.set_ppu_accuracy(accuracy);
+        set_scroll(&mut nes, 0, 250, 13);
+        let frame = render(&mut nes, mask_flags::ENABLE_BG_RENDERING);
+
+        // 280, 43 in the nametables, tile 3 of the right one
+        let info = nes.ppu.lock().unwrap().tile_info_at(30, 30);
+        assert_eq!(info.nametable_address, 0x24A3, "{accuracy:?}");
+        assert_eq!(info.tile_index, 3);
+        assert_eq!(info.attribute_address, 0x27C8);
+        assert_eq!(info.palette, 2);
+        assert_eq!(info.pattern_address, 0x0030);
+        assert_eq!((info.pixel_x, info.pixel_y), (0, 3));
+        // tile 3 is filled with color 3
+        let color = PALLETS[info.palette as usize * 4 + 3];
+        assert_eq!(frame.get_pixel(30, 30), COLORS[color as usize]);
+        // the left edge of the screen is still in the left nametable
+        let info = nes.ppu.lock().unwrap().tile_info_at(0, 30);
+        assert_eq!(info.nametable_address, 0x20BF, "{accuracy:?}");
+        assert_eq!(info.pixel_x, 2);
+    }
+
+    let mut nes = setup_nes();
+    set_scroll(&mut nes, 0, 250, 13);
+    let mut frame = render(&mut nes, mask_flags::ENABLE_BG_RENDERING);
+
+    let mut inspector = TileInspector::new();
+    inspector.set_cursor(Some((30, 30)));
+    assert_eq!(inspector.cursor(), None);
+    inspector.toggle();
+    let info = nes.ppu.lock().unwrap().tile_info_at(30, 30);
+    inspector.draw(&mut frame, &info);
+    assert_eq!(frame.get_pixel(29, 26), TEXT_COLOR);
+    assert_eq!(frame.get_pixel(38, 35), TEXT_COLOR);
+    assert_ne!(frame.get_pixel(30, 30), TEXT_COLOR);
+}
+
+#[test]
+fn palette_lut() {
+    for (id, color) in COLORS.iter().enumerate() {
+        // no emphasis and all three emphasized both leave the color as is
+        assert_eq!(renderer::raw_color(id as u16), *color);
+        assert_eq!(renderer::raw_color(id as u16 | 0b111 << 6), *color);
+        // only red emphasized dims green and blue
+        let dimmed = renderer::raw_color(id as u16 | 0b001 << 6);
+        assert_eq!(dimmed >> 16, color >> 16);
+        assert!(dimmed & 0xFFFF <= color & 0xFFFF);
+    }
+}
+
+#[test]
+fn background_palette_hack() {
+    let mut nes = setup_nes();
+    // palette reads skip the read buffer, $3F10 mirrors $3F00
+    nes.bus.write(0x2006, 0x3F);
+    nes.bus.write(0x2006, 0x15);
+    assert_eq!(nes.bus.read(0x2007), PALLETS[0x15]);
+    nes.bus.write(0x2006, 0x3F);
+    nes.bus.write(0x2006, 0x10);
+    assert_eq!(nes.bus.read(0x2007), PALLETS[0x00]);
+
+    // with rendering off and v in the palette that color fills the screen
+    nes.bus.write(0x2006, 0x3F);
+    nes.bus.write(0x2006, 0x05);
+    let frame = render(&mut nes, 0);
+    let color = COLORS[PALLETS[0x05] as usize];
+    assert!(frame.pixels().iter().all(|pixel| *pixel == color));
+
+    nes.bus.write(0x2006, 0x20);
+    nes.bus.write(0x2006, 0x00);
+    let frame = render(&mut nes, 0);
+    let backdrop = COLORS[PALLETS[0x00] as usize];
+    assert!(frame.pixels().iter().all(|pixel| *pixel == backdrop));
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "remote")]
+
+use serde_json::{Value, json};
+
+use crate::{
+    devices::nes::Nes,
+    hardware::constants::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    remote::RemoteSession,
+};
+
+fn call(session: &mut RemoteSession, method: &str, params: Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": params });
+    let response: Value =
+        serde_json::from_str(&session.handle_request(&request.to_string())).unwrap();
+    assert_eq!(response["id"], 7);
+    response
+}
+
+#[test]
+fn remote_requests() {
+    let mut session = RemoteSession::new(Nes::new());
+
+    let response = call(
+        &mut session,
+        "poke",
+        json!({ "address": 0x10, "bytes": [1, 2, 3] }),
+    );
+    assert_eq!(response["result"], Value::Null);
+    let response = call(
+        &mut session,
+        "peek",
+        json!({ "address": 0x810, "length": 3 }),
+    );
+    assert_eq!(response["result"], json!([1, 2, 3]));
+
+    let response = call(&mut session, "save_state", Value::Null);
+    let state = response["result"].as_str().unwrap().to_string();
+    call(
+        &mut session,
+        "poke",
+        json!({ "address": 0x10, "bytes": [9] }),
+    );
+    let response = call(&mut session, "load_state", json!({ "state": state }));
+    assert_eq!(response["result"], Value::Null);
+    let response = call(&mut session, "peek", json!({ "address": 0x10 }));
+    assert_eq!(response["result"], json!([1]));
+
+    let response = call(&mut session, "run_frame", json!({ "frames": 2 }));
+    assert_eq!(response["result"]["break_reason"], "FrameDone");
+
+    let response = call(
+        &mut session,
+        "set_button",
+        json!({ "controller": 0, "button": "Start", "pressed": true }),
+    );
+    assert_eq!(response["result"], Value::Null);
+    assert_eq!(session.nes().get_buttons(0).bits(), 0b1000);
+
+    let response = call(&mut session, "screenshot", Value::Null);
+    assert_eq!(
+        response["result"]["pixels"].as_str().unwrap().len(),
+        SCREEN_WIDTH * SCREEN_HEIGHT * 3 * 2
+    );
+}
+
+#[test]
+fn remote_overlay() {
+    let mut session = RemoteSession::new(Nes::new());
+    let response = call(
+        &mut session,
+        "draw",
+        json!({ "shape": "rect", "x": -1, "y": 0, "width": 3, "height": 2, "filled": true, "color": 0xFF0000 }),
+    );
+    assert_eq!(response["result"], Value::Null);
+    let response = call(
+        &mut session,
+        "draw",
+        json!({ "shape": "circle", "color": 0 }),
+    );
+    assert_eq!(response["error"]["code"], -32602);
+
+    let pixels = |session: &mut RemoteSession| {
+        call(session, "run_frame", Value::Null);
+        let response = call(session, "screenshot", Value::Null);
+        response["result"]["pixels"].as_str().unwrap()[..12].to_string()
+    };
+    assert_eq!(pixels(&mut session), "ff0000ff0000");
+    assert_ne!(
+        pixels(&mut session),
+        "ff0000ff0000",
+        "drawings last a frame"
+    );
+
+    call(
+        &mut session,
+        "draw",
+        json!({ "shape": "pixel", "x": 0, "y": 0, "color": 0xFF0000 }),
+    );
+    call(&mut session, "clear_overlay", Value::Null);
+    assert_ne!(&pixels(&mut session)[..6], "ff0000");
+}
+
+#[test]
+fn remote_errors() {
+    let mut session = RemoteSession::new(Nes::new());
+
+    let response = call(&mut session, "fly", Value::Null);
+    assert_eq!(response["error"]["code"], -32601);
+    let response = call(&mut session, "peek", json!({ "address": "nope" }));
+    assert_eq!(response["error"]["code"], -32602);
+    let response = call(&mut session, "load_state", json!({ "state": "abc" }));
+    assert_eq!(response["error"]["code"], -32602);
+
+    let response: Value = serde_json::from_str(&session.handle_request("{not json")).unwrap();
+    assert_eq!(response["error"]["code"], -32700);
+}
@@ -0,0 +1,702 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    devices::{
+        error::{ProfileError, RegionError},
+        hooks::PpuEvent,
+        machine::Machine,
+        nes::{CartrigeSwap, Nes},
+        profile::{Profile, ProfileSettings},
+        region::{Region, RegionSetting},
+        run::{BreakReason, RunSummary},
+        speed_hacks::SpeedHacks,
+    },
+    frontend::compat::{CompatDatabase, CompatEntry, CompatStatus},
+    hardware::{
+        apu::ApuQuality,
+        cartrige::{Cartrige, flat::FlatOptions},
+        constants::ppu::status_flags,
+        counters::Counter,
+        cpu::{CpuConfig, IllegalOpcodePolicy, StackWrapPolicy, assembler::assemble},
+        input::controller::{Button, ControllerState},
+        ppu::{frame::Frame, renderer::raw_color, scanline::PpuAccuracy},
+    },
+};
+
+fn nestest_nes() -> Nes {
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(include_bytes!("./nestest/nestest.nes")).unwrap());
+    nes.reset_with_program_counter(0xC000);
+    nes
+}
+
+#[test]
+fn run_instructions() {
+    let mut nes = nestest_nes();
+    // the 7th line of the nestest log is `C72D NOP` at cycle 27
+    assert_eq!(
+        nes.run_instructions(6),
+        RunSummary {
+            cycles: 20,
+            instructions: 6,
+            frames: 0,
+            break_reason: BreakReason::InstructionsDone,
+        }
+    );
+    assert_eq!(nes.cpu.lock().unwrap().get_program_counter(), 0xC72D);
+}
+
+#[test]
+fn parallel_instances() {
+    let run = |mut nes: Nes| {
+        nes.run_cycles(50_000);
+        let ram: Vec<u8> = (0..0x800).map(|address| nes.bus.peek(address)).collect();
+        (nes.total_cpu_cycles(), ram)
+    };
+    let serial = run(nestest_nes());
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let nes = nestest_nes();
+            std::thread::spawn(move || run(nes))
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), serial);
+    }
+}
+
+#[test]
+fn run_until_pc() {
+    let mut nes = nestest_nes();
+    let summary = nes.run_until_pc(0xC72D, 1000);
+    assert_eq!(
+        summary.break_reason,
+        BreakReason::ReachedProgramCounter(0xC72D)
+    );
+    assert_eq!(summary.instructions, 6);
+
+    let summary = nes.run_until_pc(0x1234, 1000);
+    assert_eq!(summary.break_reason, BreakReason::CycleLimit);
+    assert_eq!(summary.cycles, 1000);
+}
+
+#[test]
+fn run_cycles_and_frames() {
+    let mut nes = nestest_nes();
+    let summary = nes.run_cycles(100);
+    assert_eq!(summary.cycles, 100);
+    assert_eq!(summary.break_reason, BreakReason::CyclesDone);
+
+    // nestest jams before the first frame is done
+    let summary = nes.run_frame(&mut Frame::new());
+    assert_eq!(summary.break_reason, BreakReason::CpuJammed);
+
+    let summary = Nes::new().run_frame(&mut Frame::new());
+    assert_eq!(summary.frames, 1);
+    assert_eq!(summary.break_reason, BreakReason::FrameDone);
+}
+
+#[test]
+fn illegal_opcode_policy() {
+    let mut nes = nestest_nes();
+    nes.set_cpu_config(CpuConfig {
+        illegal_opcodes: IllegalOpcodePolicy::Break,
+        ..Default::default()
+    });
+    // the first illegal opcode in the nestest log is line 5004
+    let summary = nes.run_cycles(100_000);
+    assert_eq!(summary.break_reason, BreakReason::IllegalOpcode(0xC6BD));
+    assert_eq!(summary.instructions, 5003);
+    assert_eq!(nes.cpu.lock().unwrap().get_program_counter(), 0xC6BD);
+
+    // running again runs it and stops at the next one
+    let summary = nes.run_cycles(100_000);
+    assert_eq!(summary.break_reason, BreakReason::IllegalOpcode(0xC6BF));
+    assert_eq!(summary.instructions, 1);
+
+    // the config survives resets
+    nes.reset_with_program_counter(0xC000);
+    assert_eq!(
+        nes.get_cpu_config().illegal_opcodes,
+        IllegalOpcodePolicy::Break
+    );
+    nes.set_cpu_config(CpuConfig::default());
+    let summary = nes.run_cycles(100_000);
+    assert_eq!(summary.break_reason, BreakReason::CpuJammed);
+}
+
+#[test]
+fn stack_wrap_policy() {
+    let mut nes = nestest_nes();
+    let program = assemble(
+        0xC000,
+        "
+        LDX #$00
+        TXS
+        PHA
+        PLA
+    loop:
+        JMP loop
+        ",
+    )
+    .unwrap();
+    nes.patch_memory(0xC000, &program);
+    nes.set_cpu_config(CpuConfig {
+        stack_wraps: StackWrapPolicy::Break,
+        ..Default::default()
+    });
+
+    let summary = nes.run_cycles(1000);
+    assert_eq!(summary.break_reason, BreakReason::StackOverflow(0xC003));
+    assert_eq!(summary.instructions, 3);
+    assert_eq!(nes.cpu.lock().unwrap().get_registers().stack_pointer, 0xFF);
+    let summary = nes.run_cycles(1000);
+    assert_eq!(summary.break_reason, BreakReason::StackUnderflow(0xC004));
+    assert_eq!(summary.instructions, 1);
+
+    // only warns
+    nes.reset_with_program_counter(0xC000);
+    nes.set_cpu_config(CpuConfig {
+        stack_wraps: StackWrapPolicy::Warn,
+        ..Default::default()
+    });
+    let summary = nes.run_cycles(1000);
+    assert_eq!(summary.break_reason, BreakReason::CyclesDone);
+}
+
+#[test]
+fn frame_callback() {
+    let frames = Arc::new(AtomicU32::new(0));
+    let mut nes = Nes::new();
+    let callback_frames = frames.clone();
+    nes.set_frame_callback(move |_| {
+        callback_frames.fetch_add(1, Ordering::Relaxed);
+    });
+
+    let mut frame = Frame::new();
+    for _ in 0..3 {
+        nes.run_frame(&mut frame);
+    }
+    assert_eq!(frames.load(Ordering::Relaxed), 3);
+    assert_eq!(*nes.get_last_frame(), frame);
+    assert_eq!(*nes.next_frame(), frame);
+}
+
+#[test]
+fn scanline_callback() {
+    let lines = Arc::new(AtomicU32::new(0));
+    let mut nes = Nes::new();
+    nes.bus.write(0x0010, 0x16);
+    let callback_lines = lines.clone();
+    nes.set_scanline_callback(move |scanline| {
+        callback_lines.fetch_add(1, Ordering::Relaxed);
+        // a box drawn in the color the game keeps in ram
+        if (100..110).contains(&scanline.line) {
+            scanline.pixels[50..60].fill(scanline.bus.peek(0x0010) as u16);
+        }
+        // peeking the ppu from the callback must not deadlock
+        scanline.bus.peek(0x2002);
+    });
+
+    let mut frame = Frame::new();
+    nes.run_frame(&mut frame);
+    assert_eq!(lines.load(Ordering::Relaxed), 240);
+    assert_eq!(frame.get_pixel(50, 100), raw_color(0x16));
+    assert_eq!(frame.get_pixel(59, 109), raw_color(0x16));
+    assert_ne!(frame.get_pixel(60, 109), raw_color(0x16));
+
+    nes.clear_scanline_callback();
+    nes.run_frame(&mut frame);
+    assert_eq!(lines.load(Ordering::Relaxed), 240);
+    assert_ne!(frame.get_pixel(50, 100), raw_color(0x16));
+}
+
+#[test]
+fn ppu_hooks() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut nes = Nes::new();
+    let hooks = nes.hooks_mut();
+    let log = events.clone();
+    let line_hook = hooks.on_scanline(100, move |context| {
+        assert_eq!(context.event, PpuEvent::Scanline(100));
+        log.lock().unwrap().push((context.event, context.frame));
+        // hooks can write like the cpu
+        context.bus.write(0x0010, context.frame as u8 + 1);
+    });
+    for vblank in [PpuEvent::VblankStart, PpuEvent::VblankEnd] {
+        let log = events.clone();
+        hooks.add(vblank, move |context| {
+            let in_vblank = context.ppu.status & status_flags::VBLANK != 0;
+            assert_eq!(in_vblank, context.event == PpuEvent::VblankStart);
+            log.lock().unwrap().push((context.event, context.frame));
+        });
+    }
+    assert_eq!(nes.hooks().len(), 3);
+
+    let mut frame = Frame::new();
+    nes.run_frame(&mut frame);
+    nes.run_frame(&mut frame);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            (PpuEvent::Scanline(100), 0),
+            (PpuEvent::VblankStart, 1),
+            (PpuEvent::VblankEnd, 1),
+            (PpuEvent::Scanline(100), 1),
+        ]
+    );
+    assert_eq!(nes.bus.peek(0x0010), 2);
+
+    assert!(nes.hooks_mut().remove(line_hook));
+    assert!(!nes.hooks_mut().remove(line_hook));
+    events.lock().unwrap().clear();
+    nes.run_frame(&mut frame);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [(PpuEvent::VblankStart, 2), (PpuEvent::VblankEnd, 2)]
+    );
+}
+
+#[test]
+fn timestamps() {
+    let mut nes = Nes::new();
+    assert_eq!(nes.frame_count(), 0);
+    let summary = nes.run_cycles(1000);
+    assert_eq!(nes.total_cpu_cycles(), summary.cycles);
+
+    nes.next_frame();
+    assert_eq!(nes.frame_count(), 1);
+    assert_eq!(nes.ppu_dot_position(), (240, 0));
+
+    // the frame count is part of save states
+    let state = nes.save_state();
+    nes.next_frame();
+    assert_eq!(nes.frame_count(), 2);
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.frame_count(), 1);
+}
+
+/// Only uses the [Machine] trait like a frontend would
+fn run_machine(machine: &mut impl Machine, media: &[u8]) -> Vec<u8> {
+    machine.load_media(media).unwrap();
+    machine.set_button(0, Button::Start, true);
+    machine.run_frame(&mut Frame::new());
+    machine.take_audio_samples();
+    machine.save_state()
+}
+
+#[test]
+fn machine_trait() {
+    let mut nes = Nes::new();
+    assert!(nes.load_media(&[0; 16]).is_err());
+
+    let state = run_machine(&mut nes, include_bytes!("./nestest/nestest.nes"));
+    assert_eq!(nes.get_buttons(0), ControllerState::START);
+    Machine::load_state(&mut nes, &state).unwrap();
+
+    // loading doesn't wait for a power cycle that never comes
+    let mut nes = Nes::new();
+    nes.set_cartrige_swap(CartrigeSwap::PowerCycle);
+    run_machine(&mut nes, include_bytes!("./nestest/nestest.nes"));
+    assert!(!nes.has_pending_cartrige());
+    assert!(nes.get_rom_crc32().is_some());
+}
+
+/// `wait: BIT $2002; BPL wait; INC $10; JMP wait`, counts vblanks forever
+fn idle_loop_nes() -> Nes {
+    let mut nes = Nes::new();
+    nes.write_memory(
+        0x0200,
+        &[0x2C, 0x02, 0x20, 0x10, 0xFB, 0xE6, 0x10, 0x4C, 0x00, 0x02],
+    );
+    nes.reset_with_program_counter(0x0200);
+    nes
+}
+
+#[test]
+fn idle_loop_skipping() {
+    let mut frame = Frame::new();
+    let mut accurate = idle_loop_nes();
+    let mut fast = idle_loop_nes();
+    fast.set_speed_hacks(SpeedHacks {
+        skip_idle_loops: true,
+        ..Default::default()
+    });
+    for _ in 0..3 {
+        accurate.run_frame(&mut frame);
+        fast.run_frame(&mut frame);
+    }
+    assert_eq!(accurate.skipped_idle_cycles(), 0);
+    // nearly the whole frame is spent waiting
+    assert!(fast.skipped_idle_cycles() > 2 * 29780 * 9 / 10);
+    assert_eq!(fast.frame_count(), accurate.frame_count());
+    // it still leaves the loop every vblank
+    assert_eq!(fast.bus.peek(0x10), accurate.bus.peek(0x10));
+    assert!(fast.bus.peek(0x10) >= 2);
+
+    let mut deterministic = idle_loop_nes();
+    deterministic.set_speed_hacks(fast.get_speed_hacks());
+    deterministic.set_deterministic(true);
+    deterministic.run_frame(&mut frame);
+    assert_eq!(deterministic.skipped_idle_cycles(), 0);
+}
+
+#[test]
+fn overclocking() {
+    let mut frame = Frame::new();
+    let mut accurate = idle_loop_nes();
+    let mut overclocked = idle_loop_nes();
+    let mut deterministic = idle_loop_nes();
+    let speed_hacks = SpeedHacks {
+        overclock_scanlines: 20,
+        ..Default::default()
+    };
+    overclocked.set_speed_hacks(speed_hacks);
+    deterministic.set_speed_hacks(speed_hacks);
+    deterministic.set_deterministic(true);
+    for _ in 0..3 {
+        accurate.run_frame(&mut frame);
+        overclocked.run_frame(&mut frame);
+        deterministic.run_frame(&mut frame);
+    }
+    // the first frame ends before reaching the pre-render line, the
+    // other two get 20 scanlines of 341 dots, a cpu cycle every 3 dots
+    let extra = overclocked.total_cpu_cycles() - accurate.total_cpu_cycles();
+    assert!(extra.abs_diff(2 * 20 * 341 / 3) <= 1, "{extra}");
+    assert_eq!(overclocked.frame_count(), accurate.frame_count());
+    assert_eq!(overclocked.bus.peek(0x10), accurate.bus.peek(0x10));
+    assert_eq!(
+        deterministic.total_cpu_cycles(),
+        accurate.total_cpu_cycles()
+    );
+}
+
+#[test]
+fn profiles() {
+    assert_eq!("Fast".parse::<Profile>(), Ok(Profile::Fast));
+    assert_eq!(
+        "turbo".parse::<Profile>(),
+        Err(ProfileError::UnknownProfileError("turbo".to_string()))
+    );
+    for profile in Profile::ALL {
+        assert_eq!(profile.to_string().parse::<Profile>(), Ok(profile));
+        assert_eq!(Profile::from_settings(&profile.settings()), Some(profile));
+    }
+
+    // a new nes has every speed hack off
+    let mut nes = idle_loop_nes();
+    assert_eq!(
+        Profile::from_settings(&nes.get_profile_settings()),
+        Some(Profile::Accurate)
+    );
+    nes.apply_profile(Profile::Fast.into());
+    assert_eq!(nes.get_ppu_accuracy(), PpuAccuracy::Scanline);
+    assert_eq!(nes.apu.lock().unwrap().get_quality(), ApuQuality::Low);
+    assert!(nes.get_speed_hacks().skip_idle_loops);
+    assert_eq!(
+        Profile::from_settings(&nes.get_profile_settings()),
+        Some(Profile::Fast)
+    );
+    let mut frame = Frame::new();
+    nes.run_frame(&mut frame);
+    assert_eq!(nes.frame_count(), 1);
+
+    nes.apply_profile(Profile::Accurate.into());
+    assert!(nes.has_dma_conflicts());
+    assert_eq!(nes.get_speed_hacks(), SpeedHacks::default());
+    // changing a single knob makes it a custom profile
+    nes.set_ppu_accuracy(PpuAccuracy::Scanline);
+    assert_eq!(Profile::from_settings(&nes.get_profile_settings()), None);
+
+    let settings = ProfileSettings {
+        apu_quality: ApuQuality::Low,
+        ..Profile::Balanced.settings()
+    };
+    let json = serde_json::to_string(&settings).unwrap();
+    assert!(json.contains(r#""apu_quality":"low""#), "{json}");
+    assert_eq!(
+        serde_json::from_str::<ProfileSettings>(&json).unwrap(),
+        settings
+    );
+}
+
+#[test]
+fn counters() {
+    // `LDA $8000; STA $8000; LDA #$03; STA $4014; loop: JMP loop`
+    let mut nes = Nes::new();
+    nes.write_memory(
+        0x0200,
+        &[
+            0xAD, 0x00, 0x80, 0x8D, 0x00, 0x80, 0xA9, 0x03, 0x8D, 0x14, 0x40, 0x4C, 0x0B, 0x02,
+        ],
+    );
+    nes.reset_with_program_counter(0x0200);
+    let counters = nes.counters();
+    nes.run_instructions(10);
+    assert_eq!(counters.get(Counter::Instructions), 10);
+    assert_eq!(counters.get(Counter::CartrigeReads), 1);
+    assert_eq!(counters.get(Counter::MapperWrites), 1);
+    // 256 reads and writes and a cycle or two to line up with them
+    let stalls = counters.get(Counter::DmaStalls);
+    assert!((513..=514).contains(&stalls), "{stalls}");
+    assert_eq!(counters.get(Counter::Nmis), 0);
+
+    nes.cpu.lock().unwrap().is_triggered_nmi = true;
+    nes.run_instructions(1);
+    assert_eq!(counters.get(Counter::Nmis), 1);
+    assert_eq!(counters.get(Counter::Irqs), 0);
+    assert!(counters.to_string().contains("nmis            1\n"));
+
+    // everything but the reset vector starts over
+    nes.power_cycle();
+    assert_eq!(counters.get(Counter::CartrigeReads), 2);
+    assert!(
+        [Counter::Instructions, Counter::Nmis, Counter::DmaStalls]
+            .into_iter()
+            .all(|counter| counters.get(counter) == 0)
+    );
+}
+
+#[test]
+fn pal_cpu_cycles() {
+    // `LDA #$03; STA $4014; loop: JMP loop`
+    let program = [0xA9, 0x03, 0x8D, 0x14, 0x40, 0x4C, 0x05, 0x80];
+    let mut nes = Nes::new_with_cartrige(
+        Cartrige::from_flat_binary(&program, &FlatOptions::default()).unwrap(),
+    );
+    nes.set_region(Region::Pal);
+    nes.power_cycle();
+    let counters = nes.counters();
+
+    // the dma still takes 513 or 514 cpu cycles with 5 of them every 16
+    // dots
+    nes.run_instructions(2);
+    let stalls = counters.get(Counter::DmaStalls);
+    assert!((513..=514).contains(&stalls), "{stalls}");
+    assert_eq!(nes.total_cpu_cycles(), 2 + 4 + stalls);
+
+    let mut frame = Frame::new();
+    nes.run_frame(&mut frame);
+    let start = nes.total_cpu_cycles();
+    let cycles = nes.run_frame(&mut frame).cycles + nes.run_frame(&mut frame).cycles;
+    assert_eq!(cycles, 312 * 341 * 2 * 5 / 16);
+    assert_eq!(nes.total_cpu_cycles() - start, cycles);
+
+    // states only keep the dots, the cpu cycles get counted again
+    let state = nes.save_state();
+    let cycles = nes.total_cpu_cycles();
+    nes.power_cycle();
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.total_cpu_cycles(), cycles);
+}
+
+#[test]
+fn dmc_dma_read_conflicts() {
+    // plays a looping sample at the fastest rate while reading the first
+    // controller, every read that doesn't come out as just A counts in $01
+    let program = assemble(
+        0xC000,
+        "
+        LDA #$4F
+        STA $4010
+        LDA #$FF
+        STA $4013
+        LDA #$10
+        STA $4015
+    strobe:
+        LDA #$01
+        STA $4016
+        LDA #$00
+        STA $4016
+        LDX #$08
+    read:
+        LDA $4016
+        LSR A
+        ROL $00
+        DEX
+        BNE read
+        LDA $00
+        CMP #$80
+        BEQ strobe
+        INC $01
+        JMP strobe
+        ",
+    )
+    .unwrap();
+    let glitched_reads = |dma_conflicts: bool| {
+        let mut nes = nestest_nes();
+        nes.patch_memory(0xC000, &program);
+        nes.write_memory(0x0001, &[0]);
+        nes.set_dma_conflicts(dma_conflicts);
+        nes.set_button(0, Button::A, true);
+        nes.run_cycles(100_000);
+        nes.bus.peek(0x0001)
+    };
+    assert!(glitched_reads(true) > 0);
+    assert_eq!(glitched_reads(false), 0);
+}
+
+#[test]
+fn dmc_dma_halt_on_last_cycle() {
+    // reads A then B, the dmc gets enabled while the first read is running
+    let program = assemble(
+        0xC000,
+        "
+        LDA #$01
+        STA $4016
+        LDA #$00
+        STA $4016
+    read:
+        LDA $4016
+        STA $00
+        LDA $4016
+        STA $01
+    done:
+        JMP done
+        ",
+    )
+    .unwrap();
+    let first_read = 0xC00A;
+    let second_read = |halt_cycles_left: u8| {
+        let mut nes = nestest_nes();
+        nes.patch_memory(0xC000, &program);
+        nes.set_button(0, Button::A, true);
+        nes.set_button(0, Button::B, true);
+        loop {
+            nes.tick();
+            let cpu = nes.cpu.lock().unwrap();
+            if nes.bus.get_instruction_address() == first_read
+                && cpu.get_cycles_left() == halt_cycles_left
+            {
+                break;
+            }
+        }
+        // the halt comes on the next cpu cycle
+        nes.apu.lock().unwrap().write_register(0x4015, 0x10);
+        nes.run_cycles(1000);
+        nes.bus.peek(0x0001) & 1
+    };
+    // halted on the $4016 read, it gets repeated and B is lost
+    assert_eq!(second_read(1), 0);
+    // halted on the operand fetch, nothing happens to the controller
+    assert_eq!(second_read(2), 1);
+}
+
+#[test]
+fn regions() {
+    assert_eq!("PAL".parse::<Region>(), Ok(Region::Pal));
+    assert_eq!("auto".parse::<RegionSetting>(), Ok(RegionSetting::Auto));
+    assert_eq!(
+        "secam".parse::<RegionSetting>(),
+        Err(RegionError::UnknownRegionError("secam".to_string()))
+    );
+
+    // auto goes by the game database first, then the header
+    let mut rom = include_bytes!("./nestest/nestest.nes").to_vec();
+    rom[9] |= 1;
+    let cartrige = Cartrige::from_bytes(&rom).unwrap();
+    let mut database = CompatDatabase::new();
+    assert_eq!(
+        RegionSetting::Auto.resolve(&cartrige, &database),
+        Region::Pal
+    );
+    database.insert(CompatEntry {
+        crc32: cartrige.get_checksums().rom.crc32,
+        title: "nestest".to_string(),
+        status: CompatStatus::Perfect,
+        issues: Vec::new(),
+        region: Some(Region::Dendy),
+        fast_boot: None,
+    });
+    assert_eq!(
+        RegionSetting::Auto.resolve(&cartrige, &database),
+        Region::Dendy
+    );
+    assert_eq!(
+        RegionSetting::Ntsc.resolve(&cartrige, &database),
+        Region::Ntsc
+    );
+
+    // cpu cycles in 2 frames, to even out the odd frame skip and the
+    // fractional pal cycles
+    let frame_cycles = |nes: &mut Nes| {
+        let mut frame = Frame::new();
+        nes.run_frame(&mut frame);
+        nes.run_frame(&mut frame).cycles + nes.run_frame(&mut frame).cycles
+    };
+    let mut nes = Nes::new_with_cartrige(
+        Cartrige::from_flat_binary(&[0x4C, 0x00, 0x80], &FlatOptions::default()).unwrap(),
+    );
+    nes.reset();
+    nes.bus.write(0x2001, 0x18);
+    assert_eq!(frame_cycles(&mut nes), 59561);
+
+    // only switches on a power cycle
+    nes.set_region(Region::Pal);
+    assert_eq!(nes.get_region(), Region::Ntsc);
+    assert_eq!(nes.get_next_region(), Region::Pal);
+    nes.power_cycle();
+    assert_eq!(nes.get_region(), Region::Pal);
+    assert_eq!(Machine::get_frame_rate(&nes), 50);
+    assert_eq!(frame_cycles(&mut nes), 106392 * 2 * 5 / 16);
+
+    // vblank starts 50 lines later on a dendy
+    nes.set_region(Region::Dendy);
+    nes.power_cycle();
+    nes.run_until(100_000, |nes| nes.ppu_dot_position() == (250, 0));
+    assert_eq!(nes.bus.peek(0x2002) & 0x80, 0);
+    nes.run_until(100_000, |nes| nes.ppu_dot_position() == (292, 0));
+    assert_eq!(nes.bus.peek(0x2002) & 0x80, 0x80);
+
+    // states keep the region they were made in
+    let state = nes.save_state();
+    nes.set_region(Region::Ntsc);
+    nes.power_cycle();
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.get_region(), Region::Dendy);
+}
+
+#[test]
+fn cartrige_hot_swap() {
+    let first = || Cartrige::from_flat_binary(&[0x4C, 0x00, 0x80], &FlatOptions::default());
+    let second = Cartrige::from_flat_binary(&[0xEA, 0x4C, 0x00, 0x80], &FlatOptions::default());
+    let first_crc32 = first().unwrap().get_checksums().rom.crc32;
+    let second = second.unwrap();
+    let second_crc32 = second.get_checksums().rom.crc32;
+    let mut nes = Nes::new_with_cartrige(first().unwrap());
+    nes.reset();
+    assert_eq!(nes.get_cartrige_swap(), CartrigeSwap::Immediate);
+
+    // the old cartrige finishes the frame, the new one boots in vblank
+    nes.set_cartrige_swap(CartrigeSwap::NextVblank);
+    nes.run_until(100_000, |nes| nes.ppu_dot_position() == (10, 0));
+    nes.insert_cartrige(second);
+    assert!(nes.has_pending_cartrige());
+    assert_eq!(nes.get_rom_crc32(), Some(first_crc32));
+    let mut swapped_at = nes.ppu_dot_position();
+    while nes.has_pending_cartrige() {
+        swapped_at = nes.ppu_dot_position();
+        nes.tick();
+    }
+    assert_eq!(swapped_at.0, 241);
+    assert_eq!(nes.get_rom_crc32(), Some(second_crc32));
+    assert_eq!(nes.cpu.lock().unwrap().get_program_counter(), 0x8000);
+
+    // held until the power is off
+    nes.set_cartrige_swap(CartrigeSwap::PowerCycle);
+    nes.eject_cartrige();
+    let mut frame = Frame::new();
+    nes.run_frame(&mut frame);
+    nes.run_frame(&mut frame);
+    assert_eq!(nes.get_rom_crc32(), Some(second_crc32));
+    nes.power_cycle();
+    assert!(!nes.has_pending_cartrige());
+    assert_eq!(nes.get_rom_crc32(), None);
+
+    nes.set_cartrige_swap(CartrigeSwap::Immediate);
+    nes.insert_cartrige(first().unwrap());
+    assert_eq!(nes.get_rom_crc32(), Some(first_crc32));
+}
@@ -0,0 +1,203 @@
+use std::ops::Range;
+
+use crate::{
+    devices::{entropy::RamInit, nes::Nes},
+    hardware::cartrige::{Cartrige, Header},
+    save_state::{ParsedState, StateBuilder, StateReader, StateWriter, error::SaveStateError},
+};
+
+const NESTEST: &[u8] = include_bytes!("./nestest/nestest.nes");
+
+fn nestest_nes(ticks: usize) -> Nes {
+    let mut nes = Nes::new();
+    nes.insert_cartrige(Cartrige::from_bytes(NESTEST).unwrap());
+    nes.reset_with_program_counter(0xC000);
+    for _ in 0..ticks {
+        nes.tick();
+    }
+    nes
+}
+
+/// xorshift so the fuzz tests are the same every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn fix_checksum(state: &mut [u8]) {
+    let len = state.len() - 4;
+    let checksum = crc32fast::hash(&state[..len]);
+    state[len..].copy_from_slice(&checksum.to_le_bytes());
+}
+
+#[test]
+fn save_state_round_trip() {
+    let mut nes = nestest_nes(50_000);
+    let state = nes.save_state();
+
+    // both runs have to end up in exactly the same state
+    for _ in 0..10_000 {
+        nes.tick();
+    }
+    let expected = nes.save_state();
+
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.save_state(), state);
+    for _ in 0..10_000 {
+        nes.tick();
+    }
+    assert_eq!(nes.save_state(), expected);
+}
+
+#[test]
+fn save_state_rejects_other_versions_and_roms() {
+    let mut nes = nestest_nes(1000);
+    let mut state = nes.save_state();
+
+    state[4..6].copy_from_slice(&u16::MAX.to_le_bytes());
+    fix_checksum(&mut state);
+    assert!(matches!(
+        nes.load_state(&state),
+        Err(SaveStateError::UnsupportedVersionError(u16::MAX))
+    ));
+
+    let mut empty_nes = Nes::new();
+    assert!(matches!(
+        empty_nes.load_state(&nes.save_state()),
+        Err(SaveStateError::NoCartrigeError)
+    ));
+    assert!(matches!(
+        nes.load_state(&empty_nes.save_state()),
+        Err(SaveStateError::RomMismatchError)
+    ));
+    assert!(matches!(
+        empty_nes.load_state(&StateBuilder::new().finish()),
+        Err(SaveStateError::MissingChunkError(_))
+    ));
+}
+
+#[test]
+fn save_state_fuzz() {
+    let mut nes = nestest_nes(20_000);
+    let state = nes.save_state();
+    let mut rng = Rng(0x5CA3_0001);
+
+    for i in 0..2000 {
+        let mut corrupted = state.clone();
+        match i % 3 {
+            0 => {
+                for _ in 0..=rng.below(8) {
+                    let index = rng.below(corrupted.len());
+                    corrupted[index] ^= 1 << rng.below(8);
+                }
+            }
+            1 => corrupted.truncate(rng.below(corrupted.len())),
+            _ => {
+                let index = rng.below(corrupted.len());
+                let extra: Vec<u8> = (0..=rng.below(16)).map(|_| rng.next() as u8).collect();
+                corrupted.splice(index..index, extra);
+            }
+        }
+        assert!(nes.load_state(&corrupted).is_err());
+
+        // with a valid checksum the corruption gets all the way to the
+        // chunk parsing, which may or may not accept it but must not panic
+        if corrupted.len() >= 4 {
+            fix_checksum(&mut corrupted);
+            let _ = nes.load_state(&corrupted);
+        }
+        nes.load_state(&state).unwrap();
+    }
+
+    assert_eq!(nes.save_state(), state);
+}
+
+fn power_on_ram(seed: u64) -> Vec<u8> {
+    let mut nes = nestest_nes(0);
+    nes.set_seed(seed);
+    nes.set_ram_init(RamInit::Random);
+    nes.power_cycle();
+    (0..0x800).map(|address| nes.bus.peek(address)).collect()
+}
+
+#[test]
+fn seeded_power_on_ram_and_clock() {
+    assert_eq!(power_on_ram(7), power_on_ram(7));
+    assert_ne!(power_on_ram(7), power_on_ram(8));
+    assert!(nestest_nes(0).bus.peek(0x0123) == 0);
+
+    let mut nes = nestest_nes(0);
+    nes.set_clock(1_000_000);
+    let state = nes.save_state();
+    // a bit over a second of cpu cycles
+    for _ in 0..6_000_000 {
+        nes.tick();
+    }
+    assert_eq!(nes.get_clock(), 1_000_001);
+
+    nes.set_clock(0);
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.get_clock(), 1_000_000);
+}
+
+/// Writes `state` again as version 8 with `cut` taken out of its `CART`
+/// chunk
+fn as_version_8(state: &ParsedState, cut: Range<usize>) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    for byte in *b"SCST" {
+        writer.write_u8(byte);
+    }
+    writer.write_u16(8);
+    for chunk in state.chunks() {
+        let mut payload = chunk.payload.clone();
+        if chunk.tag == *b"CART" {
+            payload.drain(cut.clone());
+        }
+        for byte in chunk.tag {
+            writer.write_u8(byte);
+        }
+        writer.write_bytes(&payload);
+    }
+    let mut out = writer.into_bytes();
+    out.extend_from_slice(&crc32fast::hash(&out).to_le_bytes());
+    out
+}
+
+/// Loads a version 8 state of a fresh nes with `mapper_id`, its mapper
+/// state was `added` bytes shorter and they went after `mapper_size`
+fn check_mapper_migration(mapper_id: u8, mapper_size: usize, added: usize) {
+    let mut header = Header::from_bytes(NESTEST).unwrap();
+    header.set_mapper_id(mapper_id);
+    let rom = header.write_to_rom(NESTEST).unwrap();
+    let mut nes = Nes::new_with_cartrige(Cartrige::from_bytes(&rom).unwrap());
+    let state = nes.save_state();
+
+    // nestest has chr rom, only the prg ram comes before the mapper
+    let parsed = ParsedState::parse(&state).unwrap();
+    let cartrige = parsed.chunk(*b"CART").unwrap();
+    let mut reader = StateReader::new(cartrige);
+    reader.read_bytes().unwrap();
+    let mapper_end = cartrige.len() - reader.remaining() + mapper_size;
+    let old_state = as_version_8(&parsed, mapper_end..mapper_end + added);
+
+    nes.load_state(&old_state).unwrap();
+    assert_eq!(nes.save_state(), state);
+}
+
+#[test]
+fn mapper_irq_migration() {
+    // version 8 ended the MMC3 state after the irq latch and enable and
+    // the FME-7 state after the irq counter
+    check_mapper_migration(4, 17, 12);
+    check_mapper_migration(69, 25, 1);
+}
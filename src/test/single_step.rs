@@ -0,0 +1,304 @@
+//! Runs the json tests from https://github.com/SingleStepTests/65x02
+//! (the `nes6502` ones). Every test sets up the registers and memory,
+//! runs a single instruction and checks the registers, memory and the
+//! amount of cycles it took.
+//!
+//! The full test suite is too big to be in this repo, so only a few
+//! tests in `single_step/sample.json` are always run. To run all of
+//! them point `SINGLE_STEP_TESTS_DIR` to the folder with the json files:
+//!
+//! `SINGLE_STEP_TESTS_DIR=path/to/65x02/nes6502/v1 cargo test single_step`
+//!
+//! The `6502` tests are the same but with decimal mode, run them with
+//! `SINGLE_STEP_TESTS_VARIANT=6502` so the cpu does BCD math. The
+//! `synertek65c02` ones need `SINGLE_STEP_TESTS_VARIANT=65c02`.
+//!
+//! The bus activity of every cycle is in the tests too, but the cpu runs
+//! a whole instruction in one go so only the amount of cycles is checked.
+
+use std::{env, fmt::Write, path::Path};
+
+use serde::Deserialize;
+
+use crate::hardware::{
+    constants::cpu::flags::{CARRY, DECIMAL_MODE, NEGATIVE, ZERO},
+    cpu::{AddressingModeKind, Cpu, CpuConfig, CpuRegisters, CpuVariant, OpcodeInfo},
+    cpu_bus::CpuBus,
+};
+
+const SAMPLE_TESTS: &str = include_str!("./single_step/sample.json");
+
+#[derive(Deserialize)]
+struct SingleStepTest {
+    name: String,
+    initial: CpuState,
+    #[serde(rename = "final")]
+    expected: CpuState,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct CpuState {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+impl CpuState {
+    fn registers(&self) -> CpuRegisters {
+        CpuRegisters {
+            program_counter: self.pc,
+            stack_pointer: self.s,
+            accumulator: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.p,
+        }
+    }
+}
+
+/// Returns a description of everything that didn't match
+fn run_test(test: &SingleStepTest, config: CpuConfig) -> Option<String> {
+    let mut bus = CpuBus::new_flat();
+    for (address, value) in test.initial.ram.iter() {
+        bus.write(*address, *value);
+    }
+    let mut cpu = Cpu::new();
+    cpu.set_config(config);
+    cpu.set_registers(test.initial.registers());
+
+    let start_cycles = cpu.get_total_cycles();
+    cpu.tick(&mut bus);
+    let cycles = cpu.get_total_cycles() - start_cycles;
+
+    let mut errors = String::new();
+    let registers = cpu.get_registers();
+    let expected_registers = test.expected.registers();
+    if registers != expected_registers {
+        writeln!(
+            errors,
+            "  registers: got {registers:X?}\n  expected {expected_registers:X?}"
+        )
+        .unwrap();
+    }
+    for (address, value) in test.expected.ram.iter() {
+        let actual = bus.peek(*address);
+        if actual != *value {
+            writeln!(
+                errors,
+                "  ram[{address:04X}]: got {actual:02X} expected {value:02X}"
+            )
+            .unwrap();
+        }
+    }
+    if cycles != test.cycles.len() as u64 {
+        writeln!(
+            errors,
+            "  cycles: got {cycles} expected {}",
+            test.cycles.len()
+        )
+        .unwrap();
+    }
+
+    (!errors.is_empty()).then(|| format!("{}:\n{errors}", test.name))
+}
+
+/// Runs all the tests and panics with the first few failures
+fn run_tests(source: &str, tests: &[SingleStepTest]) {
+    let failures: Vec<String> = tests
+        .iter()
+        .filter_map(|test| run_test(test, CpuConfig::default()))
+        .collect();
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} single step tests failed in {source}, first failures:\n{}",
+            failures.len(),
+            tests.len(),
+            failures[..failures.len().min(5)].join("\n")
+        );
+    }
+}
+
+#[test]
+fn single_step_sample() {
+    let tests: Vec<SingleStepTest> = serde_json::from_str(SAMPLE_TESTS).unwrap();
+    run_tests("sample.json", &tests);
+}
+
+/// Runs `ADC`/`SBC #argument` with decimal mode on and returns the
+/// accumulator and status
+fn run_decimal(
+    variant: CpuVariant,
+    opcode: u8,
+    accumulator: u8,
+    argument: u8,
+    carry: bool,
+) -> (u8, u8) {
+    let mut bus = CpuBus::new_flat();
+    bus.write(0x0200, opcode);
+    bus.write(0x0201, argument);
+    let mut cpu = Cpu::new();
+    cpu.set_config(CpuConfig {
+        variant,
+        ..Default::default()
+    });
+    cpu.set_registers(CpuRegisters {
+        program_counter: 0x0200,
+        stack_pointer: 0xFD,
+        accumulator,
+        x: 0,
+        y: 0,
+        status: DECIMAL_MODE | if carry { CARRY } else { 0 },
+    });
+    cpu.tick(&mut bus);
+    let registers = cpu.get_registers();
+    (registers.accumulator, registers.status)
+}
+
+#[test]
+fn decimal_mode() {
+    const ADC: u8 = 0x69;
+    const SBC: u8 = 0xE9;
+    for variant in [CpuVariant::Nmos6502, CpuVariant::Cmos65C02] {
+        assert_eq!(run_decimal(variant, ADC, 0x12, 0x34, false).0, 0x46);
+        let (result, status) = run_decimal(variant, ADC, 0x58, 0x46, false);
+        assert_eq!((result, status & CARRY), (0x04, CARRY));
+        assert_eq!(run_decimal(variant, SBC, 0x46, 0x12, true).0, 0x34);
+        let (result, status) = run_decimal(variant, SBC, 0x40, 0x13, true);
+        assert_eq!((result, status & CARRY), (0x27, CARRY));
+        let (result, status) = run_decimal(variant, SBC, 0x12, 0x21, true);
+        assert_eq!((result, status & CARRY), (0x91, 0));
+    }
+
+    // 99 + 1 is 00, the nmos chip takes Z from the binary sum and N from
+    // the half fixed up one
+    let (result, status) = run_decimal(CpuVariant::Nmos6502, ADC, 0x99, 0x01, false);
+    assert_eq!((result, status & (ZERO | NEGATIVE)), (0x00, NEGATIVE));
+    let (result, status) = run_decimal(CpuVariant::Cmos65C02, ADC, 0x99, 0x01, false);
+    assert_eq!((result, status & (ZERO | NEGATIVE)), (0x00, ZERO));
+
+    // the nes ignores the flag
+    assert_eq!(
+        run_decimal(CpuVariant::Rp2A03, ADC, 0x99, 0x01, false).0,
+        0x9A
+    );
+}
+
+const CMOS_PROGRAM: &[u8] = &[
+    0xA9, 0x0F, // LDA #$0F
+    0x64, 0x10, // STZ $10
+    0xA2, 0x03, // LDX #$03
+    0xDA, // PHX
+    0x7A, // PLY
+    0x92, 0x20, // STA ($20)
+    0x04, 0x11, // TSB $11
+    0x14, 0x11, // TRB $11
+    0x80, 0x02, // BRA +2
+    0xA9, 0xFF, // LDA #$FF, skipped
+    0x6C, 0xFF, 0x03, // JMP ($03FF)
+];
+
+#[test]
+fn cmos_65c02_instructions() {
+    let mut bus = CpuBus::new_flat();
+    for (i, byte) in CMOS_PROGRAM.iter().enumerate() {
+        bus.write(0x0200 + i as u16, *byte);
+    }
+    for (address, value) in [(0x10, 0xFF), (0x11, 0xF0), (0x20, 0x00), (0x21, 0x03)] {
+        bus.write(address, value);
+    }
+    // the nmos cpu would read the high byte from $0300
+    bus.write(0x03FF, 0x00);
+    bus.write(0x0400, 0x05);
+
+    let mut cpu = Cpu::new();
+    cpu.set_config(CpuConfig {
+        variant: CpuVariant::Cmos65C02,
+        ..Default::default()
+    });
+    cpu.reset_with_program_counter(0x0200);
+    let step = |cpu: &mut Cpu, bus: &mut CpuBus| {
+        let start = cpu.get_total_cycles();
+        cpu.tick(bus);
+        while cpu.get_cycles_left() > 0 {
+            cpu.tick(bus);
+        }
+        cpu.get_total_cycles() - start
+    };
+    step(&mut cpu, &mut bus);
+    assert_eq!(step(&mut cpu, &mut bus), 3);
+    assert_eq!(bus.peek(0x10), 0x00);
+    for _ in 0..4 {
+        step(&mut cpu, &mut bus);
+    }
+    assert_eq!(cpu.get_registers().y, 0x03);
+    assert_eq!(bus.peek(0x0300), 0x0F);
+    step(&mut cpu, &mut bus);
+    assert_eq!(bus.peek(0x11), 0xFF);
+    assert_ne!(cpu.get_registers().status & ZERO, 0);
+    step(&mut cpu, &mut bus);
+    assert_eq!(bus.peek(0x11), 0xF0);
+    assert_eq!(step(&mut cpu, &mut bus), 3);
+    assert_eq!(step(&mut cpu, &mut bus), 6);
+    assert_eq!(cpu.get_registers().accumulator, 0x0F);
+    assert_eq!(cpu.get_program_counter(), 0x0500);
+
+    let nop = OpcodeInfo::get_for(CpuVariant::Cmos65C02, 0x03);
+    assert_eq!(
+        (nop.mnemonic, nop.mode),
+        ("NOP", AddressingModeKind::Implicit)
+    );
+    assert_eq!(OpcodeInfo::get(0x03).mnemonic, "SLO");
+    let instruction = cpu.disassemble(&bus, 0x0208);
+    assert_eq!(instruction.text, "STA ($20) = 0300 = 0F");
+}
+
+#[test]
+fn single_step_full() {
+    let Ok(tests_dir) = env::var("SINGLE_STEP_TESTS_DIR") else {
+        println!("SINGLE_STEP_TESTS_DIR not set, skipping the full single step tests");
+        return;
+    };
+
+    let mut paths: Vec<_> = std::fs::read_dir(Path::new(&tests_dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    let config = CpuConfig {
+        variant: match env::var("SINGLE_STEP_TESTS_VARIANT").as_deref() {
+            Ok("6502") => CpuVariant::Nmos6502,
+            Ok("65c02") => CpuVariant::Cmos65C02,
+            _ => CpuVariant::Rp2A03,
+        },
+        ..Default::default()
+    };
+
+    let mut failed_files = Vec::new();
+    for path in paths {
+        let tests: Vec<SingleStepTest> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let failed = tests
+            .iter()
+            .filter_map(|test| run_test(test, config))
+            .count();
+        if failed != 0 {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            println!("{name}: {failed} of {} failed", tests.len());
+            failed_files.push(name);
+        }
+    }
+    assert!(
+        failed_files.is_empty(),
+        "single step tests failed for: {}",
+        failed_files.join(", ")
+    );
+}
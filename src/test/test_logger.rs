@@ -1,32 +1,44 @@
-use std::sync::RwLock;
+use std::{
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
 
-use log::{Level, Metadata, Record};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
 
+/// Keeps the message of every event of one target, one per line
+#[derive(Clone)]
 pub(super) struct TestLogger {
-    pub logs: RwLock<String>,
+    pub logs: Arc<RwLock<String>>,
+    target: &'static str,
 }
 
 impl TestLogger {
-    pub const fn new() -> Self {
+    pub fn new(target: &'static str) -> Self {
         TestLogger {
-            logs: RwLock::new(String::new()),
+            logs: Arc::new(RwLock::new(String::new())),
+            target,
         }
     }
 }
 
-impl log::Log for TestLogger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info
-    }
+struct MessageVisitor<'a>(&'a mut String);
 
-    fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            self.logs
-                .write()
-                .unwrap()
-                .push_str(&format!("{}\n", record.args()));
+impl Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0.push_str(&format!("{value:?}\n"));
         }
     }
+}
 
-    fn flush(&self) {}
+impl<S: Subscriber> Layer<S> for TestLogger {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if event.metadata().target() == self.target {
+            event.record(&mut MessageVisitor(&mut self.logs.write().unwrap()));
+        }
+    }
 }
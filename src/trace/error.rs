@@ -0,0 +1,9 @@
+#[derive(thiserror::Error, Debug)]
+pub enum TraceError {
+    #[error("Got an io error while opening the trace output:\nio error was: {_0}!")]
+    IoError(#[from] std::io::Error),
+    #[error("Invalid trace filter {_0:?}:\n{_1}")]
+    InvalidFilterError(String, String),
+    #[error("Tracing was already set up!")]
+    AlreadyInitializedError,
+}
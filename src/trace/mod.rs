@@ -0,0 +1,83 @@
+//! # Tracing
+//!
+//! Everything in scamu reports what it is doing through [tracing], with
+//! one target per subsystem (see [targets]) so they can be filtered on
+//! their own. For example `SCAMU_LOG=scamu::cpu=trace` gives a trace of
+//! every instruction in the same format as the nestest log.
+//!
+//! Library users can use any tracing subscriber they want, [init] is just
+//! a quick way to set one up.
+
+use std::{fs::File, path::PathBuf, sync::Mutex};
+
+use tracing_subscriber::EnvFilter;
+
+use crate::trace::error::TraceError;
+
+pub mod error;
+
+pub type Result<T> = std::result::Result<T, TraceError>;
+
+pub mod targets {
+    pub const CPU: &str = "scamu::cpu";
+    pub const PPU: &str = "scamu::ppu";
+    pub const APU: &str = "scamu::apu";
+    pub const MAPPER: &str = "scamu::mapper";
+    /// Register accesses picked by
+    /// [RegisterLog](crate::debugger::register_log::RegisterLog)
+    pub const REGISTERS: &str = "scamu::registers";
+    /// Things that aren't part of the hardware like save states
+    pub const EMULATOR: &str = "scamu::emulator";
+    /// For frontends built on top of scamu
+    pub const FRONTEND: &str = "scamu::frontend";
+}
+
+/// The environment variable the filter is read from if
+/// [TraceConfig::filter] isn't set
+pub const FILTER_ENV_VAR: &str = "SCAMU_LOG";
+pub const DEFAULT_FILTER: &str = "warn";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One json object per line, for postprocessing the traces
+    Json,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TraceConfig {
+    /// Same syntax as `RUST_LOG`: https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/struct.EnvFilter.html
+    pub filter: Option<String>,
+    pub format: TraceFormat,
+    /// Writes to stderr if not set
+    pub output: Option<PathBuf>,
+}
+
+/// Sets up the global tracing subscriber, this can only be done once
+pub fn init(config: &TraceConfig) -> Result<()> {
+    let filter = config
+        .filter
+        .clone()
+        .or_else(|| std::env::var(FILTER_ENV_VAR).ok())
+        .unwrap_or_else(|| DEFAULT_FILTER.to_string());
+    let env_filter = EnvFilter::try_new(&filter)
+        .map_err(|err| TraceError::InvalidFilterError(filter, err.to_string()))?;
+
+    let output = config.output.as_ref().map(File::create).transpose()?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(env_filter)
+        .with_writer(std::io::stderr);
+
+    let result = match (config.format, output) {
+        (TraceFormat::Text, None) => builder.try_init(),
+        (TraceFormat::Text, Some(file)) => builder
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .try_init(),
+        (TraceFormat::Json, None) => builder.json().try_init(),
+        (TraceFormat::Json, Some(file)) => builder.json().with_writer(Mutex::new(file)).try_init(),
+    };
+    result.map_err(|_| TraceError::AlreadyInitializedError)
+}